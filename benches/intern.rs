@@ -0,0 +1,83 @@
+// cargo bench --bench intern
+//
+// 统计解析一个较大的源码时的内存分配次数与耗时
+// lex 与 parse 两项只依赖公开的 Lexer 与 nls::parse, 可以在驻留之前的提交上运行同一个 bench 作为对比的基准
+use nls::analyzer::intern::{intern, Atom};
+use nls::analyzer::lexer::{Lexer, TokenType};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+const FIXTURES: [&str; 4] = [
+    include_str!("../tests/fixtures/format/basic.n"),
+    include_str!("../tests/fixtures/format/comments.n"),
+    include_str!("../tests/fixtures/format/generics.n"),
+    include_str!("../tests/fixtures/format/wrap.n"),
+];
+const REPEAT: usize = 500;
+const ROUNDS: usize = 5;
+
+// 只统计当前线程的分配, 不受其他线程的影响
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// 分配次数每一轮都相同, 耗时取多轮中的最小值
+fn measure<T>(f: impl Fn() -> T) -> (usize, Duration) {
+    let mut allocations = 0;
+    let mut elapsed = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let before = ALLOCATIONS.with(|count| count.get());
+        let now = Instant::now();
+        let result = f();
+        elapsed = elapsed.min(now.elapsed());
+        allocations = ALLOCATIONS.with(|count| count.get()) - before;
+        drop(result);
+    }
+    (allocations, elapsed)
+}
+
+fn report(name: &str, allocations: usize, elapsed: Duration) {
+    println!("{:<32} {:>10} allocations {:>10.2?}", name, allocations, elapsed);
+}
+
+fn main() {
+    // 重复的声明不影响 parser, 只用于放大源码的规模
+    let source = FIXTURES.join("\n").repeat(REPEAT);
+    let (tokens, _, _) = Lexer::new(source.clone()).scan();
+    let idents: Vec<&str> = tokens.iter().filter(|t| t.token_type == TokenType::Ident).map(|t| t.literal.as_str()).collect();
+    println!("source: {} bytes, {} tokens, {} identifiers", source.len(), tokens.len(), idents.len());
+
+    // 每个标识符持有一个 String 与驻留为 Atom 的差异
+    let (allocations, elapsed) = measure(|| idents.iter().map(|ident| ident.to_string()).collect::<Vec<String>>());
+    report("String per identifier", allocations, elapsed);
+
+    let (allocations, elapsed) = measure(|| idents.iter().map(|ident| intern(ident)).collect::<Vec<Atom>>());
+    report("Atom per identifier", allocations, elapsed);
+
+    let unique: HashSet<Atom> = idents.iter().map(|ident| intern(ident)).collect();
+    println!("{:<32} {:>10} unique identifiers", "", unique.len());
+
+    let (allocations, elapsed) = measure(|| Lexer::new(source.clone()).scan());
+    report("lex", allocations, elapsed);
+
+    let (allocations, elapsed) = measure(|| nls::parse(&source));
+    report("parse", allocations, elapsed);
+}
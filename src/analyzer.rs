@@ -1,18 +1,48 @@
+pub mod ast_json;
+pub mod code_action;
+pub mod code_lens;
 pub mod common;
+pub mod completion;
+pub mod diagnostic;
+pub mod diff;
+pub mod document_link;
+pub mod errors;
+pub mod file_rename;
+pub mod folding_range;
+pub mod formatter;
+pub mod hover;
+pub mod inlay_hint;
+pub mod intern;
 pub mod lexer; // 声明子模块
+pub mod lint;
+pub mod metrics;
+pub mod parse;
+pub mod printer;
+pub mod query;
+pub mod reference;
+pub mod reparse;
+pub mod selection_range;
 pub mod semantic;
+pub mod semantic_tokens;
+pub mod signature_help;
+pub mod snippet;
 pub mod symbol;
 pub mod syntax;
+pub mod token_cursor;
+pub mod type_hierarchy;
+pub mod type_utils;
 pub mod typesys;
+pub mod walk;
+pub mod workspace_symbol;
 
 use std::path::Path;
 
 use crate::package::parse_package;
 use crate::project::{Module, DEFAULT_NATURE_ROOT};
 use crate::utils::format_global_ident;
-use common::{AnalyzerError, AstNode, ImportStmt, PackageConfig, Stmt};
+use common::{AnalyzerError, AstNode, ImportStmt, PackageConfig, Span, Stmt};
 use lazy_static::lazy_static;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::path::PathBuf;
@@ -31,12 +61,35 @@ const TARGET_ARCH: &str = "amd64";
 #[cfg(target_arch = "aarch64")]
 const TARGET_ARCH: &str = "arm64";
 
+// nature 只支持上述平台, 其余平台(例如 wasm32 中运行的 playground)使用 linux_amd64, 可以通过 BUILD_OS/BUILD_ARCH 指定
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const TARGET_OS: &str = "linux";
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const TARGET_ARCH: &str = "amd64";
+
 lazy_static! {
-    static ref STD_PACKAGES: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+    static ref STD_PACKAGES: Mutex<HashMap<String, HashSet<String>>> = Mutex::new(HashMap::new()); // key = nature root
 }
 const PACKAGE_SOURCE_INFIX: &str = ".nature/packages";
 const PACKAGE_TOML: &str = "package.toml";
 
+/**
+ * 编译目标的 arch, 与 nature 一致可以通过 BUILD_ARCH 覆盖
+ */
+pub fn target_arch() -> String {
+    env::var("BUILD_ARCH").unwrap_or(TARGET_ARCH.to_string())
+}
+
+/**
+ * 编译目标的指针宽度(bit), 决定 int/uint 与数组长度的取值范围
+ */
+pub fn target_pointer_width() -> u32 {
+    match target_arch().as_str() {
+        "386" | "arm" | "riscv32" | "wasm32" => 32,
+        _ => 64,
+    }
+}
+
 fn dep_package_dir() -> PathBuf {
     let home = env::var("HOME").expect("cannot find home dir");
     PathBuf::from(home).join(PACKAGE_SOURCE_INFIX)
@@ -57,6 +110,12 @@ fn package_dep_local_dir(package_config: &PackageConfig, package: &str) -> Strin
     let package_dir = dep_package_dir();
     let dep_data = &package_config.package_data.dependencies[package];
 
+    // 指定了 path 的 local 依赖, 相对路径基于 package.toml 所在目录
+    if let Some(path) = &dep_data.path {
+        let base = Path::new(&package_config.path).parent().unwrap_or(Path::new(""));
+        return base.join(path).to_string_lossy().into_owned();
+    }
+
     let mut path = package.replace("/", ".");
     let version = dep_data.version.replace("/", ".");
     path = format!("{}@{}", path, version);
@@ -64,11 +123,11 @@ fn package_dep_local_dir(package_config: &PackageConfig, package: &str) -> Strin
     package_dir.join(path).to_str().unwrap().to_string()
 }
 
-fn is_std_package(package: &str) -> bool {
+fn is_std_package(nature_root: &str, package: &str) -> bool {
     let mut std_packages = STD_PACKAGES.lock().unwrap();
 
     // 如果已经初始化过，直接检查包是否存在
-    if let Some(packages) = std_packages.as_ref() {
+    if let Some(packages) = std_packages.get(nature_root) {
         return packages.contains(package);
     }
 
@@ -76,7 +135,7 @@ fn is_std_package(package: &str) -> bool {
     let mut packages = HashSet::new();
 
     // 扫描 std 目录
-    let std_dir = Path::new(&std::env::var("NATURE_ROOT").unwrap_or(DEFAULT_NATURE_ROOT.to_string())).join("std");
+    let std_dir = Path::new(nature_root).join("std");
 
     if let Ok(entries) = fs::read_dir(&std_dir) {
         for entry in entries {
@@ -96,8 +155,9 @@ fn is_std_package(package: &str) -> bool {
     }
 
     // 保存结果并返回
-    *std_packages = Some(packages);
-    std_packages.as_ref().unwrap().contains(package)
+    let found = packages.contains(package);
+    std_packages.insert(nature_root.to_string(), packages);
+    found
 }
 
 fn is_dep_package(package_config: &PackageConfig, package: &str) -> bool {
@@ -119,22 +179,17 @@ fn analyze_import_dep(package_config: &PackageConfig, _m: &mut Module, import: &
         "git" => package_dep_git_dir(package_config, &package_ident),
         "local" => package_dep_local_dir(package_config, &package_ident),
         _ => {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("{} not found", package_ident),
-            });
+            return Err(AnalyzerError::new(Span::new(import.start, import.end), format!("{} not found", package_ident)));
         }
     };
 
     // join package.toml and must exists
     let package_conf_path = Path::new(&package_dir).join("package.toml");
     if !package_conf_path.exists() {
-        return Err(AnalyzerError {
-            start: import.start,
-            end: import.end,
-            message: format!("{} not found", package_conf_path.display()),
-        });
+        return Err(AnalyzerError::new(
+            Span::new(import.start, import.end),
+            format!("{} not found", package_conf_path.display()),
+        ));
     }
 
     match parse_package(package_conf_path.to_str().unwrap()) {
@@ -146,31 +201,27 @@ fn analyze_import_dep(package_config: &PackageConfig, _m: &mut Module, import: &
             return Ok(());
         }
         Err(e) => {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("import failed: {} {}", package_conf_path.display(), e.message),
-            })
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("import failed: {} {}", package_conf_path.display(), e.message),
+            ))
         }
     }
 }
 
-fn analyze_import_std(_m: &mut Module, import: &mut ImportStmt) -> Result<(), AnalyzerError> {
+fn analyze_import_std(nature_root: &str, _m: &mut Module, import: &mut ImportStmt) -> Result<(), AnalyzerError> {
     let package_ident = import.ast_package.as_ref().unwrap()[0].clone();
 
     // 获取标准库目录
-    let std_dir = Path::new(&std::env::var("NATURE_ROOT").unwrap_or(DEFAULT_NATURE_ROOT.to_string()))
-        .join("std")
-        .join(&package_ident);
+    let std_dir = Path::new(nature_root).join("std").join(&package_ident);
 
     // 检查 package.toml 是否存在
     let package_conf_path = std_dir.join(PACKAGE_TOML);
     if !package_conf_path.exists() {
-        return Err(AnalyzerError {
-            start: import.start,
-            end: import.end,
-            message: format!("{} not found", package_conf_path.display()),
-        });
+        return Err(AnalyzerError::new(
+            Span::new(import.start, import.end),
+            format!("{} not found", package_conf_path.display()),
+        ));
     }
 
     match parse_package(package_conf_path.to_str().unwrap()) {
@@ -182,16 +233,44 @@ fn analyze_import_std(_m: &mut Module, import: &mut ImportStmt) -> Result<(), An
             return Ok(());
         }
         Err(e) => {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("import package failed: {} parse err {}", package_conf_path.display(), e.message),
-            });
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("import package failed: {} parse err {}", package_conf_path.display(), e.message),
+            ));
         }
     }
 }
 
-fn package_import_fullpath(package_conf: &PackageConfig, package_dir: &str, ast_import_package: &Vec<String>) -> Option<String> {
+/**
+ * settings 中配置的额外 package 查找目录, 目录下存在 <package>/package.toml 时视为可导入的 package, 优先级低于 std
+ */
+fn search_path_package_dir(search_paths: &[String], package_ident: &str) -> Option<PathBuf> {
+    search_paths
+        .iter()
+        .map(|dir| Path::new(dir).join(package_ident))
+        .find(|dir| dir.join(PACKAGE_TOML).exists())
+}
+
+fn analyze_import_search_path(package_dir: PathBuf, import: &mut ImportStmt) -> Result<(), AnalyzerError> {
+    let package_conf_path = package_dir.join(PACKAGE_TOML);
+
+    let package_conf = parse_package(package_conf_path.to_str().unwrap()).map_err(|e| {
+        AnalyzerError::new(
+            Span::new(import.start, import.end),
+            format!("import package failed: {} parse err {}", package_conf_path.display(), e.message),
+        )
+    })?;
+
+    import.use_links = true;
+    import.package_dir = package_dir.to_string_lossy().into_owned();
+    import.package_conf = Some(package_conf);
+    Ok(())
+}
+
+/**
+ * 按照查找顺序返回 import package 可能对应的文件, os_arch > os > 普通文件, 路径是目录时继续查找目录下的 entry 文件
+ */
+fn package_import_candidates(package_conf: &PackageConfig, package_dir: &str, ast_import_package: &Vec<String>) -> Vec<PathBuf> {
     assert!(!ast_import_package.is_empty());
 
     // 获取入口文件名，默认为 main
@@ -205,43 +284,51 @@ fn package_import_fullpath(package_conf: &PackageConfig, package_dir: &str, ast_
         prefix.push(package_part);
     }
 
-    let mut entry_count = 0;
-    loop {
-        if entry_count == 1 {
-            if prefix.is_dir() {
-                prefix.push(entry);
-            }
-        }
-        entry_count += 1;
+    let os = env::var("BUILD_OS").unwrap_or(TARGET_OS.to_string());
+    let arch = target_arch();
+    let os_arch = format!("{}_{}", os, arch);
 
-        // 检查 os_arch 特定文件
-        let os = env::var("BUILD_OS").unwrap_or(TARGET_OS.to_string());
-        let arch = env::var("BUILD_ARCH").unwrap_or(TARGET_ARCH.to_string());
-        let os_arch = format!("{}_{}", os, arch);
+    let mut candidates = Vec::new();
+    let mut prefixes = vec![prefix.clone()];
+    if prefix.is_dir() {
+        prefixes.push(prefix.join(entry));
+    }
 
-        let os_arch_path = prefix.with_extension(format!("{}.n", os_arch));
-        if os_arch_path.exists() {
-            return Some(os_arch_path.to_str().unwrap().to_string());
-        }
+    for prefix in prefixes {
+        candidates.push(prefix.with_extension(format!("{}.n", os_arch)));
+        candidates.push(prefix.with_extension(format!("{}.n", os)));
+        candidates.push(prefix.with_extension("n"));
+    }
 
-        // 检查 os 特定文件
-        let os_path = prefix.with_extension(format!("{}.n", os));
-        if os_path.exists() {
-            return Some(os_path.to_str().unwrap().to_string());
-        }
+    candidates
+}
 
-        // 检查标准文件
-        let normal_path = prefix.with_extension("n");
-        if normal_path.exists() {
-            return Some(normal_path.to_str().unwrap().to_string());
-        }
+fn package_import_fullpath(package_conf: &PackageConfig, package_dir: &str, ast_import_package: &Vec<String>) -> Option<String> {
+    package_import_candidates(package_conf, package_dir, ast_import_package)
+        .into_iter()
+        .find(|path| path.exists())
+        .map(|path| path.to_str().unwrap().to_string())
+}
 
-        if entry_count >= 2 {
-            break;
-        }
+/**
+ * import 解析时尝试过的路径, 用于解析失败时的提示
+ */
+pub fn import_search_paths(m: &Module, import: &ImportStmt) -> Vec<String> {
+    if let Some(file) = &import.file {
+        return vec![Path::new(&m.dir).join(file).to_string_lossy().into_owned()];
     }
 
-    None
+    let ast_package = import.ast_package.as_ref().unwrap();
+    if let Some(package_conf) = &import.package_conf {
+        return package_import_candidates(package_conf, &import.package_dir, ast_package)
+            .into_iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+    }
+
+    // package 本身没有找到, 最后尝试的是 std package
+    let std_dir = Path::new(&std::env::var("NATURE_ROOT").unwrap_or(DEFAULT_NATURE_ROOT.to_string())).join("std");
+    vec![std_dir.join(&ast_package[0]).join(PACKAGE_TOML).to_string_lossy().into_owned()]
 }
 
 // 在文件中添加这个新函数
@@ -268,34 +355,34 @@ pub fn module_unique_ident(root: &str, full_path: &str) -> String {
  * import 'xxx/xxx.n' 只支持相对于当前 源文件路径导入
  * import project.test.mod
  */
-pub fn analyze_import(package_config_mutex: &Option<Arc<Mutex<PackageConfig>>>, m: &mut Module, import: &mut ImportStmt) -> Result<(), AnalyzerError> {
+pub fn analyze_import(
+    package_config_mutex: &Option<Arc<Mutex<PackageConfig>>>,
+    nature_root: &str,
+    search_paths: &[String],
+    m: &mut Module,
+    import: &mut ImportStmt,
+) -> Result<(), AnalyzerError> {
     if let Some(file) = &import.file {
         // file 不能以 . 或者 / 开头
         if file.starts_with(".") || file.starts_with("/") {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("import file cannot start with . or /"),
-            });
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("import file cannot start with . or /"),
+            ));
         }
 
         import.full_path = Path::new(&m.dir).join(file).to_string_lossy().into_owned();
 
         if !import.full_path.ends_with(".n") {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("import file suffix must .n"),
-            });
+            return Err(AnalyzerError::new(Span::new(import.start, import.end), format!("import file suffix must .n")));
         }
 
         // check file exist
         if !Path::new(&import.full_path).exists() {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("import file {} not found", file.clone()),
-            });
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("import file {} not found", file.clone()),
+            ));
         }
 
         // 如果 import as empty, 则使用 import 的 file  的文件名称去除后缀作为 import as
@@ -307,6 +394,13 @@ pub fn analyze_import(package_config_mutex: &Option<Arc<Mutex<PackageConfig>>>,
                 .to_string();
         }
 
+        // 存在 package.toml 时 module ident 基于 package 目录计算, 与 main module 保持一致
+        if let Some(p_mutex) = package_config_mutex {
+            let p = p_mutex.lock().unwrap();
+            let package_dir = Path::new(&p.path).parent().and_then(|dir| dir.to_str()).unwrap_or("");
+            import.module_ident = module_unique_ident(package_dir, &import.full_path);
+        }
+
         return Ok(());
     }
 
@@ -323,25 +417,27 @@ pub fn analyze_import(package_config_mutex: &Option<Arc<Mutex<PackageConfig>>>,
             import.package_dir = Path::new(&p.path).parent().unwrap_or(Path::new("")).to_str().unwrap_or("").to_string();
         } else if is_dep_package(&p, &package_ident) {
             analyze_import_dep(&p, m, import)?;
-        } else if is_std_package(&package_ident) {
-            analyze_import_std(m, import)?;
+        } else if is_std_package(nature_root, &package_ident) {
+            analyze_import_std(nature_root, m, import)?;
+        } else if let Some(package_dir) = search_path_package_dir(search_paths, &package_ident) {
+            analyze_import_search_path(package_dir, import)?;
         } else {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("package '{}' not found", package_ident),
-            });
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("package '{}' not found", package_ident),
+            ));
         }
     } else {
-        if is_std_package(&package_ident) {
+        if is_std_package(nature_root, &package_ident) {
             // only import std package
-            analyze_import_std(m, import)?;
+            analyze_import_std(nature_root, m, import)?;
+        } else if let Some(package_dir) = search_path_package_dir(search_paths, &package_ident) {
+            analyze_import_search_path(package_dir, import)?;
         } else {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("package '{}' not found", package_ident),
-            });
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("package '{}' not found", package_ident),
+            ));
         }
     }
 
@@ -351,27 +447,21 @@ pub fn analyze_import(package_config_mutex: &Option<Arc<Mutex<PackageConfig>>>,
 
         // check full_path exists
         if !Path::new(&import.full_path).exists() {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("cannot import '{}': file not found", import.full_path.clone()),
-            });
+            return Err(AnalyzerError::new(
+                Span::new(import.start, import.end),
+                format!("cannot import '{}': file not found", import.full_path.clone()),
+            ));
         }
 
         // check file is n file
         if !import.full_path.ends_with(".n") {
-            return Err(AnalyzerError {
-                start: import.start,
-                end: import.end,
-                message: format!("import file suffix must .n"),
-            });
+            return Err(AnalyzerError::new(Span::new(import.start, import.end), format!("import file suffix must .n")));
         }
     } else {
-        return Err(AnalyzerError {
-            start: import.start,
-            end: import.end,
-            message: format!("cannot find import file in package {}", package_ident),
-        });
+        return Err(AnalyzerError::new(
+            Span::new(import.start, import.end),
+            format!("cannot find import file in package {}", package_ident),
+        ));
     }
 
     // calc import as, 如果不存在 import as, 则使用 ast_package 的最后一个元素作为 import as
@@ -384,13 +474,19 @@ pub fn analyze_import(package_config_mutex: &Option<Arc<Mutex<PackageConfig>>>,
     return Ok(());
 }
 
-pub fn analyze_imports(package_config: &Option<Arc<Mutex<PackageConfig>>>, m: &mut Module, stmts: &mut Vec<Box<Stmt>>) -> Vec<ImportStmt> {
+pub fn analyze_imports(
+    package_config: &Option<Arc<Mutex<PackageConfig>>>,
+    nature_root: &str,
+    search_paths: &[String],
+    m: &mut Module,
+    stmts: &mut Vec<Box<Stmt>>,
+) -> Vec<ImportStmt> {
     let mut imports: Vec<ImportStmt> = Vec::new();
 
     for stmt in stmts {
         if let AstNode::Import(import) = &mut stmt.node {
             // 解析出目标文件
-            match analyze_import(package_config, m, import) {
+            match analyze_import(package_config, nature_root, search_paths, m, import) {
                 Ok(_) => {}
                 Err(e) => {
                     m.analyzer_errors.push(e);
@@ -411,25 +507,25 @@ pub fn register_global_symbol(m: &Module, symbol_table: &mut SymbolTable, stmts:
     for stmt in stmts {
         match &stmt.node {
             AstNode::VarDecl(var_decl_mutex) => {
-                let var_decl = var_decl_mutex.lock().unwrap();
+                let var_decl = var_decl_mutex.read().unwrap();
                 // 构造全局唯一标识符
-                let global_ident = format_global_ident(m.ident.clone(), var_decl.ident.clone());
+                let global_ident = format_global_ident(m.ident.clone(), var_decl.ident.to_string());
                 let _ = symbol_table.define_symbol(global_ident, SymbolKind::Var(var_decl_mutex.clone()), var_decl.symbol_start);
             }
             AstNode::VarDef(var_decl_mutex, _) => {
-                let var_decl = var_decl_mutex.lock().unwrap();
+                let var_decl = var_decl_mutex.read().unwrap();
                 // 构造全局唯一标识符
-                let global_ident = format_global_ident(m.ident.clone(), var_decl.ident.clone());
+                let global_ident = format_global_ident(m.ident.clone(), var_decl.ident.to_string());
                 let _ = symbol_table.define_symbol(global_ident, SymbolKind::Var(var_decl_mutex.clone()), var_decl.symbol_start);
             }
             AstNode::TypeAlias(type_alias_mutex) => {
-                let type_alias = type_alias_mutex.lock().unwrap();
+                let type_alias = type_alias_mutex.read().unwrap();
                 // 构造全局唯一标识符
                 let global_ident = format_global_ident(m.ident.clone(), type_alias.ident.clone());
                 let _ = symbol_table.define_symbol(global_ident, SymbolKind::TypeAlias(type_alias_mutex.clone()), type_alias.symbol_start);
             }
             AstNode::FnDef(fndef_mutex) => {
-                let fndef = fndef_mutex.lock().unwrap();
+                let fndef = fndef_mutex.read().unwrap();
                 // 构造全局唯一标识符
                 let global_ident = format_global_ident(m.ident.clone(), fndef.symbol_name.clone());
                 let _ = symbol_table.define_symbol(global_ident, SymbolKind::Fn(fndef_mutex.clone()), fndef.symbol_start);
@@ -0,0 +1,515 @@
+use super::common::{
+    AstCall, AstFnDef, AstNode, Decorator, Expr, GenericsParam, ImportStmt, MacroArg, MatchCase, SelectCase, Stmt, StructNewEntry, StructNewProperty, Type,
+    TypeAliasStmt, TypeKind, TypeStructProperty, VarDeclExpr,
+};
+use serde::{Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/**
+ * 将 module 的 stmt 列表序列化为 json, 供编辑器插件、语法测试以及文档生成等外部工具使用
+ *
+ * 每个节点通过 kind 字段区分类型并包含 start/end 位置, object 的 key 按照字典序输出, 因此多次运行的结果一致
+ */
+pub fn ast_to_json(stmts: &[Box<Stmt>]) -> String {
+    let mut builder = JsonBuilder::default();
+    let value = builder.stmts(stmts);
+    serde_json::to_string_pretty(&value).unwrap_or_default()
+}
+
+/**
+ * 比较两个 stmt 的结构, 忽略位置, AstNodeId 以及 typesys 为 expr 推导得到的类型, Arc<RwLock<..>> 节点比较其内容
+ *
+ * 比较的内容与 ast_to_json 的输出一致, 因此两个 stmt 结构相同时重新解析 to_source 的输出会得到相同的结果
+ */
+pub fn structurally_eq(a: &Stmt, b: &Stmt) -> bool {
+    structure(|builder| builder.stmt(a)) == structure(|builder| builder.stmt(b))
+}
+
+pub fn body_structurally_eq(a: &[Box<Stmt>], b: &[Box<Stmt>]) -> bool {
+    structure(|builder| builder.stmts(a)) == structure(|builder| builder.stmts(b))
+}
+
+fn structure(build: impl FnOnce(&mut JsonBuilder) -> Value) -> Value {
+    let mut builder = JsonBuilder {
+        structural: true,
+        ..Default::default()
+    };
+    let mut value = build(&mut builder);
+    strip_metadata(&mut value);
+    value
+}
+
+// 与 Type 的 PartialEq 一致, origin_ident 不参与比较, symbol_name 在 semantic 阶段会被改写为全局唯一的名称
+fn strip_metadata(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for key in ["start", "end", "symbol_start", "symbol_end", "origin_ident", "symbol_name"] {
+                map.remove(key);
+            }
+            map.values_mut().for_each(strip_metadata);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_metadata),
+        _ => {}
+    }
+}
+
+impl Serialize for Stmt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonBuilder::default().stmt(self).serialize(serializer)
+    }
+}
+
+impl Serialize for Expr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonBuilder::default().expr(self).serialize(serializer)
+    }
+}
+
+impl Serialize for AstNode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::Object(JsonBuilder::default().node(self)).serialize(serializer)
+    }
+}
+
+impl Serialize for Type {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonBuilder::default().type_(self).serialize(serializer)
+    }
+}
+
+impl Serialize for TypeKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::Object(JsonBuilder::default().type_kind(self)).serialize(serializer)
+    }
+}
+
+fn object(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/**
+ * Arc<RwLock<..>> 节点(fn/var decl/type alias)可能在多处被引用, 第一次出现时内联输出并分配 id,
+ * 之后出现时只输出 {"ref": id}, 分配 id 先于 lock, 因此节点引用自身时不会死锁
+ */
+#[derive(Default)]
+struct JsonBuilder {
+    ids: HashMap<usize, usize>,
+    structural: bool, // structurally_eq 使用, 不输出 expr 推导得到的类型
+}
+
+impl JsonBuilder {
+    fn shared<T>(&mut self, node: &Arc<RwLock<T>>, build: fn(&mut Self, &T) -> Map<String, Value>) -> Value {
+        let ptr = Arc::as_ptr(node) as *const () as usize;
+        if let Some(id) = self.ids.get(&ptr) {
+            return json!({ "ref": id });
+        }
+
+        let id = self.ids.len() + 1;
+        self.ids.insert(ptr, id);
+
+        let mut map = build(self, &node.read().unwrap());
+        map.insert("id".to_string(), json!(id));
+        Value::Object(map)
+    }
+
+    fn stmts(&mut self, stmts: &[Box<Stmt>]) -> Value {
+        Value::Array(stmts.iter().map(|stmt| self.stmt(stmt)).collect())
+    }
+
+    fn exprs(&mut self, exprs: &[Box<Expr>]) -> Value {
+        Value::Array(exprs.iter().map(|expr| self.expr(expr)).collect())
+    }
+
+    fn types(&mut self, types: &[Type]) -> Value {
+        Value::Array(types.iter().map(|t| self.type_(t)).collect())
+    }
+
+    fn option_expr(&mut self, expr: &Option<Box<Expr>>) -> Value {
+        match expr {
+            Some(expr) => self.expr(expr),
+            None => Value::Null,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Value {
+        let mut map = self.node(&stmt.node);
+        map.insert("start".to_string(), json!(stmt.span.start));
+        map.insert("end".to_string(), json!(stmt.span.end));
+        Value::Object(map)
+    }
+
+    // 类型推导之前 expr 的类型为 unknown, 此时不输出 type 字段
+    fn expr(&mut self, expr: &Expr) -> Value {
+        let mut map = self.node(&expr.node);
+        map.insert("start".to_string(), json!(expr.span.start));
+        map.insert("end".to_string(), json!(expr.span.end));
+        if !self.structural && expr.type_.kind.is_exist() {
+            map.insert("type".to_string(), self.type_(&expr.type_));
+        }
+        Value::Object(map)
+    }
+
+    fn type_(&mut self, t: &Type) -> Value {
+        let mut map = self.type_kind(&t.kind);
+        map.insert("start".to_string(), json!(t.span.start));
+        map.insert("end".to_string(), json!(t.span.end));
+        if let Some(origin_ident) = &t.origin_ident {
+            map.insert("origin_ident".to_string(), json!(origin_ident));
+        }
+        Value::Object(map)
+    }
+
+    fn type_kind(&mut self, kind: &TypeKind) -> Map<String, Value> {
+        let fields = match kind {
+            TypeKind::Vec(element) | TypeKind::Set(element) | TypeKind::Chan(element) => json!({ "element": self.type_(element) }),
+            TypeKind::Promise(value) | TypeKind::Ptr(value) | TypeKind::RawPtr(value) => json!({ "value": self.type_(value) }),
+            TypeKind::Arr(length, element) => json!({ "length": length, "element": self.type_(element) }),
+            TypeKind::Map(key, value) => json!({ "key": self.type_(key), "value": self.type_(value) }),
+            TypeKind::Tuple(elements, _) => json!({ "elements": self.types(elements) }),
+            TypeKind::Struct(ident, _, properties) => {
+                let properties: Vec<Value> = properties.iter().map(|property| self.type_struct_property(property)).collect();
+                json!({ "ident": ident, "properties": properties })
+            }
+            TypeKind::Fn(type_fn) => json!({
+                "name": type_fn.name,
+                "params": self.types(&type_fn.param_types),
+                "return_type": self.type_(&type_fn.return_type),
+                "errable": type_fn.errable,
+                "rest": type_fn.rest,
+            }),
+            TypeKind::Alias(alias) => {
+                // 具名实参输出为 { "name": "T", "type": ... }
+                let args = match &alias.args {
+                    Some(args) => Value::Array(
+                        args.iter()
+                            .map(|arg| match &arg.name {
+                                Some(name) => json!({ "name": name, "type": self.type_(&arg.type_) }),
+                                None => self.type_(&arg.type_),
+                            })
+                            .collect(),
+                    ),
+                    None => Value::Null,
+                };
+                json!({ "ident": alias.ident, "import_as": alias.import_as, "args": args })
+            }
+            TypeKind::Param(ident) => json!({ "ident": ident }),
+            TypeKind::Union(any, elements) => json!({ "any": any, "elements": self.types(elements) }),
+            _ => json!({}),
+        };
+
+        let mut map = object(fields);
+        map.insert("kind".to_string(), json!(kind.to_string()));
+        map
+    }
+
+    fn type_struct_property(&mut self, property: &TypeStructProperty) -> Value {
+        json!({
+            "key": property.key,
+            "type": self.type_(&property.type_),
+            "value": self.option_expr(&property.value),
+            "start": property.start,
+            "end": property.end,
+        })
+    }
+
+    fn var_decl(&mut self, var_decl: &VarDeclExpr) -> Map<String, Value> {
+        object(json!({
+            "kind": "VarDecl",
+            "ident": var_decl.ident,
+            "type": self.type_(&var_decl.type_),
+            "symbol_start": var_decl.symbol_start,
+            "symbol_end": var_decl.symbol_end,
+            "doc": var_decl.doc,
+        }))
+    }
+
+    fn generics_params(&mut self, params: &[GenericsParam]) -> Value {
+        let params: Vec<Value> = params
+            .iter()
+            .map(|param| json!({ "ident": param.ident, "any": param.constraints.0, "constraints": self.types(&param.constraints.1) }))
+            .collect();
+        Value::Array(params)
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) -> Value {
+        let decorators: Vec<Value> = decorators
+            .iter()
+            .map(|decorator| {
+                json!({
+                    "name": decorator.name,
+                    "args": self.exprs(&decorator.args),
+                    "start": decorator.start,
+                    "end": decorator.end,
+                })
+            })
+            .collect();
+        Value::Array(decorators)
+    }
+
+    /**
+     * global_parent/local_children 以及泛型特化表是语义分析阶段建立的引用关系, 不属于语法树, 不输出
+     */
+    fn fndef(&mut self, fndef: &AstFnDef) -> Map<String, Value> {
+        let params: Vec<Value> = fndef.params.iter().map(|param| self.shared(param, Self::var_decl)).collect();
+        let generics_params = match &fndef.generics_params {
+            Some(params) => self.generics_params(params),
+            None => Value::Null,
+        };
+        let impl_type = if fndef.impl_type.kind.is_exist() {
+            self.type_(&fndef.impl_type)
+        } else {
+            Value::Null
+        };
+
+        object(json!({
+            "kind": "FnDef",
+            "symbol_name": fndef.symbol_name,
+            "fn_name": fndef.fn_name,
+            "params": params,
+            "rest_param": fndef.rest_param,
+            "return_type": self.type_(&fndef.return_type),
+            "generics_params": generics_params,
+            "impl_type": impl_type,
+            "linkid": fndef.linkid,
+            "decorators": self.decorators(&fndef.decorators),
+            "is_async": fndef.is_async,
+            "is_errable": fndef.is_errable,
+            "is_tpl": fndef.is_tpl,
+            "is_intrinsic": fndef.is_intrinsic,
+            "is_test": fndef.is_test,
+            "is_deprecated": fndef.is_deprecated,
+            "doc": fndef.doc,
+            "body": self.stmts(&fndef.body),
+            "symbol_start": fndef.symbol_start,
+            "symbol_end": fndef.symbol_end,
+        }))
+    }
+
+    fn type_alias(&mut self, type_alias: &TypeAliasStmt) -> Map<String, Value> {
+        object(json!({
+            "kind": "TypeAlias",
+            "ident": type_alias.ident,
+            "params": self.generics_params(&type_alias.params),
+            "type": self.type_(&type_alias.type_expr),
+            "decorators": self.decorators(&type_alias.decorators),
+            "doc": type_alias.doc,
+            "symbol_start": type_alias.symbol_start,
+            "symbol_end": type_alias.symbol_end,
+        }))
+    }
+
+    fn call(&mut self, call: &AstCall) -> Value {
+        json!({
+            "left": self.expr(&call.left),
+            "generics_args": self.types(&call.generics_args),
+            "args": self.exprs(&call.args),
+            "spread": call.spread,
+        })
+    }
+
+    fn import(&mut self, import: &ImportStmt) -> Value {
+        json!({
+            "file": import.file,
+            "package": import.ast_package,
+            "as_name": import.as_name,
+            "is_star": import.is_star,
+            "version": import.version,
+        })
+    }
+
+    fn struct_new_property(&mut self, property: &StructNewProperty) -> Value {
+        json!({
+            "key": property.key,
+            "value": self.expr(&property.value),
+            "start": property.start,
+            "end": property.end,
+        })
+    }
+
+    fn match_case(&mut self, case: &MatchCase) -> Value {
+        json!({
+            "cond_list": self.exprs(&case.cond_list),
+            "is_default": case.is_default,
+            "body": self.stmts(&case.handle_body),
+            "start": case.start,
+            "end": case.end,
+        })
+    }
+
+    fn select_case(&mut self, case: &SelectCase) -> Value {
+        let on_call = match &case.on_call {
+            Some(call) => self.call(call),
+            None => Value::Null,
+        };
+        let recv_var = match &case.recv_var {
+            Some(var_decl) => self.shared(var_decl, Self::var_decl),
+            None => Value::Null,
+        };
+
+        json!({
+            "on_call": on_call,
+            "recv_var": recv_var,
+            "is_recv": case.is_recv,
+            "is_default": case.is_default,
+            "body": self.stmts(&case.handle_body),
+            "start": case.start,
+            "end": case.end,
+        })
+    }
+
+    fn node(&mut self, node: &AstNode) -> Map<String, Value> {
+        let (kind, fields) = match node {
+            AstNode::None => ("None", json!({})),
+            AstNode::Literal(kind, value, _) => ("Literal", json!({ "literal_kind": kind.to_string(), "value": value })),
+            AstNode::Binary(op, left, right) => ("Binary", json!({ "op": op.to_string(), "left": self.expr(left), "right": self.expr(right) })),
+            AstNode::Range(start, end) => ("Range", json!({ "from": self.expr(start), "to": self.expr(end) })),
+            AstNode::Unary(op, operand) => ("Unary", json!({ "op": op.to_string(), "operand": self.expr(operand) })),
+            AstNode::Ident(ident, _) => ("Ident", json!({ "ident": ident })),
+            AstNode::As(target_type, src) => ("As", json!({ "target_type": self.type_(target_type), "src": self.expr(src) })),
+            AstNode::TypeCast(target_type, src) => ("TypeCast", json!({ "target_type": self.type_(target_type), "src": self.expr(src) })),
+            AstNode::Is(target_type, src) => ("Is", json!({ "target_type": self.type_(target_type), "src": self.expr(src) })),
+            AstNode::MatchIs(target_type) => ("MatchIs", json!({ "target_type": self.type_(target_type) })),
+            AstNode::MacroSizeof(target_type) => ("MacroSizeof", json!({ "target_type": self.type_(target_type) })),
+            AstNode::Sizeof(src) => ("Sizeof", json!({ "src": self.expr(src) })),
+            AstNode::MacroUla(src) => ("MacroUla", json!({ "src": self.expr(src) })),
+            AstNode::LambdaShort(body) => ("LambdaShort", json!({ "body": self.expr(body) })),
+            AstNode::MacroReflectHash(target_type) => ("MacroReflectHash", json!({ "target_type": self.type_(target_type) })),
+            AstNode::MacroTypeEq(left, right) => ("MacroTypeEq", json!({ "left": self.type_(left), "right": self.type_(right) })),
+            // closure_fn 由 parser 基于 origin_call 生成, 只输出用户编写的部分
+            AstNode::MacroAsync(r#async) => (
+                "MacroAsync",
+                json!({ "origin_call": self.call(&r#async.origin_call), "flag_expr": self.option_expr(&r#async.flag_expr) }),
+            ),
+            AstNode::MacroCall(ident, args) => {
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        MacroArg::Stmt(stmt) => self.stmt(stmt),
+                        MacroArg::Expr(expr) => self.expr(expr),
+                        MacroArg::Type(t) => self.type_(t),
+                    })
+                    .collect();
+                ("MacroCall", json!({ "ident": ident, "args": args }))
+            }
+            AstNode::MacroDefault => ("MacroDefault", json!({})),
+            AstNode::MacroMinMax(kind, args) => ("MacroMinMax", json!({ "min_max": kind.to_string(), "args": self.exprs(args) })),
+            AstNode::MacroAssert(cond, implicit_msg) => ("MacroAssert", json!({ "cond": self.expr(cond), "msg": self.expr(implicit_msg) })),
+            AstNode::MacroBitCast(target_type, src) => ("MacroBitCast", json!({ "target_type": self.type_(target_type), "src": self.expr(src) })),
+            AstNode::New(type_, properties, args) => {
+                let properties: Vec<Value> = properties.iter().map(|property| self.struct_new_property(property)).collect();
+                ("New", json!({ "type": self.type_(type_), "properties": properties, "args": self.exprs(args) }))
+            }
+            AstNode::MapAccess(_, _, left, key) => ("MapAccess", json!({ "left": self.expr(left), "key": self.expr(key) })),
+            AstNode::VecAccess(_, left, index) => ("VecAccess", json!({ "left": self.expr(left), "index": self.expr(index) })),
+            AstNode::ArrayAccess(_, left, index) => ("ArrayAccess", json!({ "left": self.expr(left), "index": self.expr(index) })),
+            AstNode::TupleAccess(_, left, index) => ("TupleAccess", json!({ "left": self.expr(left), "index": index })),
+            AstNode::StructSelect(instance, key, _) => ("StructSelect", json!({ "instance": self.expr(instance), "key": key })),
+            AstNode::EnvAccess(index, ident, _) => ("EnvAccess", json!({ "index": index, "ident": ident })),
+            AstNode::VecNew(elements, len, cap) => (
+                "VecNew",
+                json!({ "elements": self.exprs(elements), "len": self.option_expr(len), "cap": self.option_expr(cap) }),
+            ),
+            AstNode::ArrayNew(elements) => ("ArrayNew", json!({ "elements": self.exprs(elements) })),
+            AstNode::MapNew(elements) => {
+                let elements: Vec<Value> = elements
+                    .iter()
+                    .map(|element| json!({ "key": self.expr(&element.key), "value": self.expr(&element.value) }))
+                    .collect();
+                ("MapNew", json!({ "elements": elements }))
+            }
+            AstNode::SetNew(elements) => ("SetNew", json!({ "elements": self.exprs(elements) })),
+            AstNode::TupleNew(elements) => ("TupleNew", json!({ "elements": self.exprs(elements) })),
+            AstNode::TupleDestr(elements) => ("TupleDestr", json!({ "elements": self.exprs(elements) })),
+            AstNode::StructNew(ident, type_, entries) => {
+                let entries: Vec<Value> = entries
+                    .iter()
+                    .map(|entry| match entry {
+                        StructNewEntry::Property(property) => self.struct_new_property(property),
+                        StructNewEntry::Spread(expr) => json!({ "spread": self.expr(expr) }),
+                    })
+                    .collect();
+                ("StructNew", json!({ "ident": ident, "type": self.type_(type_), "entries": entries }))
+            }
+            AstNode::Try(try_expr, catch_err, body) => (
+                "Try",
+                json!({ "try_expr": self.expr(try_expr), "catch_err": self.var_decl(catch_err), "body": self.stmts(body) }),
+            ),
+            AstNode::EmptyCurlyNew => ("EmptyCurlyNew", json!({})),
+            AstNode::AccessExpr(left, key) => ("AccessExpr", json!({ "left": self.expr(left), "key": self.expr(key) })),
+            AstNode::SelectExpr(left, key) => ("SelectExpr", json!({ "left": self.expr(left), "key": key })),
+            AstNode::VarDecl(var_decl) => return object(self.shared(var_decl, Self::var_decl)),
+            AstNode::Fake(expr) => ("Fake", json!({ "expr": self.expr(expr) })),
+            AstNode::Break(expr) => ("Break", json!({ "expr": self.option_expr(expr) })),
+            AstNode::Continue => ("Continue", json!({})),
+            AstNode::Import(import) => ("Import", self.import(import)),
+            AstNode::VarTupleDestr(elements, right) => ("VarTupleDestr", json!({ "elements": self.exprs(elements), "right": self.expr(right) })),
+            AstNode::Assign(left, right) => ("Assign", json!({ "left": self.expr(left), "right": self.expr(right) })),
+            AstNode::Return(expr) => ("Return", json!({ "expr": self.option_expr(expr) })),
+            AstNode::If(condition, consequent, alternate) => (
+                "If",
+                json!({ "condition": self.expr(condition), "consequent": self.stmts(consequent), "alternate": self.stmts(alternate) }),
+            ),
+            AstNode::Throw(expr) => ("Throw", json!({ "expr": self.expr(expr) })),
+            AstNode::TryCatch(try_expr, catch_err, body) => (
+                "TryCatch",
+                json!({ "try_expr": self.expr(try_expr), "catch_err": self.shared(catch_err, Self::var_decl), "body": self.stmts(body) }),
+            ),
+            AstNode::Let(expr) => ("Let", json!({ "expr": self.expr(expr) })),
+            AstNode::Do(expr) => ("Do", json!({ "expr": self.expr(expr) })),
+            AstNode::ForIterator(iterate, first, second, step, body) => {
+                let second = match second {
+                    Some(second) => self.shared(second, Self::var_decl),
+                    None => Value::Null,
+                };
+                (
+                    "ForIterator",
+                    json!({
+                        "iterate": self.expr(iterate),
+                        "first": self.shared(first, Self::var_decl),
+                        "second": second,
+                        "step": self.option_expr(step),
+                        "body": self.stmts(body),
+                    }),
+                )
+            }
+            AstNode::ForCond(condition, body) => ("ForCond", json!({ "condition": self.expr(condition), "body": self.stmts(body) })),
+            AstNode::ForTradition(init, cond, update, body) => (
+                "ForTradition",
+                json!({
+                    "init": self.stmt(init),
+                    "cond": self.expr(cond),
+                    "update": self.stmt(update),
+                    "body": self.stmts(body),
+                }),
+            ),
+            AstNode::Call(call) => ("Call", self.call(call)),
+            AstNode::Catch(try_expr, catch_err, body) => (
+                "Catch",
+                json!({ "try_expr": self.expr(try_expr), "catch_err": self.shared(catch_err, Self::var_decl), "body": self.stmts(body) }),
+            ),
+            AstNode::Match(subject, cases, _) => {
+                let cases: Vec<Value> = cases.iter().map(|case| self.match_case(case)).collect();
+                ("Match", json!({ "subject": self.option_expr(subject), "cases": cases }))
+            }
+            AstNode::Select(cases, ..) => {
+                let cases: Vec<Value> = cases.iter().map(|case| self.select_case(case)).collect();
+                ("Select", json!({ "cases": cases }))
+            }
+            AstNode::VarDef(var_decl, right) => (
+                "VarDef",
+                json!({ "var_decl": self.shared(var_decl, Self::var_decl), "right": self.expr(right) }),
+            ),
+            AstNode::TypeAlias(type_alias) => return object(self.shared(type_alias, Self::type_alias)),
+            AstNode::FnDef(fndef) => return object(self.shared(fndef, Self::fndef)),
+        };
+
+        let mut map = object(fields);
+        map.insert("kind".to_string(), json!(kind));
+        map
+    }
+}
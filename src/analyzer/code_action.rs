@@ -0,0 +1,729 @@
+use super::common::{AstCall, AstNode, Expr, Span, Stmt, StructNewEntry, Type, TypeKind, VarDeclExpr, MISSING_FIELDS_CODE};
+use super::completion::{find_dependency, import_insert_position, module_import_path, AutoImportContext};
+use super::lexer::{Token, TokenType};
+use super::walk::{walk_body, walk_node, walk_stmts};
+use crate::project::Module;
+use crate::utils::{offset_to_position, range_of};
+use lsp_types::{CodeAction, CodeActionKind, Diagnostic, NumberOrString, Position, Range, TextEdit, Url, WorkspaceEdit};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/**
+ * 查找 range 与 span 完全一致的 expr
+ */
+fn find_expr(m: &Module, span: Span) -> Option<Expr> {
+    let mut found: Option<Expr> = None;
+    let mut visit = |expr: &Expr| {
+        if found.is_none() && expr.span == span {
+            found = Some(expr.clone());
+        }
+    };
+
+    walk_body(&m.stmts, &mut visit);
+    for node in &m.global_vardefs {
+        walk_node(node, &mut visit);
+    }
+
+    found
+}
+
+fn position_to_char(m: &Module, line: u32, character: u32) -> Option<usize> {
+    Some(m.line_index.offset(Position::new(line, character)))
+}
+
+/**
+ * 属性未赋值时使用的零值
+ */
+fn zero_value(t: &Type) -> String {
+    if Type::is_number(&t.kind) {
+        return "0".to_string();
+    }
+
+    match &t.kind {
+        TypeKind::Bool => "false".to_string(),
+        TypeKind::String => "''".to_string(),
+        TypeKind::Vec(..) | TypeKind::Arr(..) => "[]".to_string(),
+        TypeKind::Map(..) | TypeKind::Set(..) => "{}".to_string(),
+        _ => "@default()".to_string(),
+    }
+}
+
+/**
+ * lexer 已经将字符串中的转义字符转换为实际字符, 写回源码时需要重新转义
+ */
+fn escape_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\'' => result.push_str("\\'"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '\0' => result.push_str("\\0"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/**
+ * 优先使用 struct 声明中的字面量默认值
+ */
+fn property_value(value: &Option<Box<Expr>>, t: &Type) -> String {
+    match value.as_ref().map(|v| &v.node) {
+        Some(AstNode::Literal(TypeKind::String, value, _)) => format!("'{}'", escape_string(value)),
+        Some(AstNode::Literal(_, value, _)) => value.clone(),
+        _ => zero_value(t),
+    }
+}
+
+fn line_indent(m: &Module, offset: usize) -> String {
+    let Ok(line) = m.rope.try_char_to_line(offset) else {
+        return String::new();
+    };
+    m.rope.line(line).chars().take_while(|c| *c == ' ' || *c == '\t').collect()
+}
+
+/**
+ * struct new 缺少属性时, 生成补全所有缺失属性的 quickfix
+ * 多行的 struct new 中每个属性独占一行并保持已有的缩进
+ * 只处理 typesys 产生的 missing-fields 诊断, 同一范围上的其他诊断不提供该 action
+ */
+pub fn missing_fields_action(m: &Module, uri: &Url, diagnostic: &Diagnostic) -> Option<CodeAction> {
+    if diagnostic.code != Some(NumberOrString::String(MISSING_FIELDS_CODE.to_string())) {
+        return None;
+    }
+
+    let start = position_to_char(m, diagnostic.range.start.line, diagnostic.range.start.character)?;
+    let end = position_to_char(m, diagnostic.range.end.line, diagnostic.range.end.character)?;
+
+    let expr = find_expr(m, Span::new(start, end))?;
+    let (type_, properties) = match &expr.node {
+        // spread 表达式已经提供了所有属性
+        AstNode::StructNew(_, _, entries) if entries.iter().any(|e| matches!(e, StructNewEntry::Spread(_))) => return None,
+        AstNode::StructNew(_, type_, entries) => (
+            type_,
+            entries
+                .iter()
+                .filter_map(|e| match e {
+                    StructNewEntry::Property(property) => Some(property.as_ref()),
+                    StructNewEntry::Spread(_) => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        AstNode::New(type_, properties, args) if args.is_empty() => (type_, properties.iter().collect()),
+        _ => return None,
+    };
+
+    // 泛型 struct 在 typesys 阶段已经完成了类型参数的替换
+    let TypeKind::Struct(_, _, type_properties) = &type_.kind else {
+        return None;
+    };
+
+    // 只保留用户编写的属性, typesys 会将默认值属性追加到 properties 中
+    let exists: HashMap<&str, usize> = properties
+        .iter()
+        .filter(|p| p.start >= expr.span.start as usize && p.end <= expr.span.end as usize)
+        .map(|p| (p.key.as_str(), p.end))
+        .collect();
+
+    let missing: Vec<String> = type_properties
+        .iter()
+        .filter(|p| !exists.contains_key(p.key.as_str()))
+        .map(|p| format!("{} = {}", p.key, property_value(&p.value, &p.type_)))
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+
+    // struct new 的 { 与 } 不在同一行时按照多行格式插入
+    let left_curly = m
+        .token_indexes
+        .iter()
+        .map(|i| &m.token_db[*i])
+        .find(|t| t.span.start >= expr.span.start && t.token_type == TokenType::LeftCurly)?;
+    let multiline =
+        m.rope.try_char_to_line(left_curly.span.start as usize).ok()? != m.rope.try_char_to_line((expr.span.end as usize).saturating_sub(1)).ok()?;
+
+    let (insert_offset, new_text) = match exists.values().max() {
+        Some(last_end) if multiline => {
+            let indent = line_indent(m, *last_end);
+            let text: String = missing.iter().map(|p| format!(",\n{}{}", indent, p)).collect();
+            (*last_end, text)
+        }
+        Some(last_end) => (*last_end, missing.iter().map(|p| format!(", {}", p)).collect()),
+        None if multiline => {
+            let indent = format!("{}    ", line_indent(m, left_curly.span.start as usize));
+            let lines: Vec<String> = missing.iter().map(|p| format!("{}{}", indent, p)).collect();
+            (left_curly.span.end as usize, format!("\n{}", lines.join(",\n")))
+        }
+        None => (left_curly.span.end as usize, missing.join(", ")),
+    };
+
+    let position = offset_to_position(insert_offset, &m.line_index)?;
+    let mut changes = HashMap::new();
+    changes.insert(
+        uri.clone(),
+        vec![TextEdit {
+            range: Range::new(position, position),
+            new_text,
+        }],
+    );
+
+    Some(CodeAction {
+        title: "Add missing fields".to_string(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/**
+ * 根据 parser 记录的期望 token 生成插入 token 的 quickfix, 最多提供两个最可能的 token
+ *
+ * 相邻的语法错误会被合并为一个诊断, 因此使用诊断范围内的所有期望 token, 按照错误出现的顺序排列
+ */
+pub fn insert_expected_token_actions(m: &Module, uri: &Url, diagnostic: &Diagnostic) -> Vec<CodeAction> {
+    let mut actions = Vec::new();
+    let (Some(start), Some(end)) = (
+        position_to_char(m, diagnostic.range.start.line, diagnostic.range.start.character),
+        position_to_char(m, diagnostic.range.end.line, diagnostic.range.end.character),
+    ) else {
+        return actions;
+    };
+
+    // 只有符号类 token 可以直接插入, ident/literal 等 token 无法确定具体内容
+    let mut candidates: Vec<(usize, String)> = Vec::new();
+    for expected in m
+        .expected_tokens
+        .iter()
+        .filter(|e| e.span.start as usize >= start && e.span.end as usize <= end)
+    {
+        for text in expected.tokens.iter().map(|t| t.to_string()) {
+            if text.is_empty() || !text.chars().all(|c| c.is_ascii_punctuation()) || candidates.iter().any(|(_, t)| *t == text) {
+                continue;
+            }
+            candidates.push((expected.insert_at, text));
+        }
+    }
+
+    for (insert_at, text) in candidates.into_iter().take(2) {
+        let Some(position) = offset_to_position(insert_at, &m.line_index) else {
+            continue;
+        };
+
+        let mut changes = HashMap::new();
+        changes.insert(
+            uri.clone(),
+            vec![TextEdit {
+                range: Range::new(position, position),
+                new_text: text.clone(),
+            }],
+        );
+
+        actions.push(CodeAction {
+            title: format!("Insert '{}'", text),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(vec![diagnostic.clone()]),
+            edit: Some(WorkspaceEdit {
+                changes: Some(changes),
+                ..Default::default()
+            }),
+            is_preferred: Some(actions.is_empty()),
+            ..Default::default()
+        });
+    }
+
+    actions
+}
+
+/**
+ * 将 typesys 推导出的类型渲染为可以在当前 module 中书写的类型
+ * 其他 module 中定义的类型会带上 import as 前缀, 尚未 import 的 module 记录在 imports 中
+ */
+struct TypeRenderer<'a> {
+    m: &'a Module,
+    module_db: &'a [Module],
+    ctx: &'a AutoImportContext<'a>,
+    imports: Vec<String>,
+}
+
+impl TypeRenderer<'_> {
+    /**
+     * type alias 的 impl_ident 为 module_ident.ident 格式的全局标识符
+     */
+    fn alias_ident(&mut self, t: &Type) -> Option<String> {
+        let global_ident = t.impl_ident.as_ref()?;
+        let Some((module_ident, ident)) = global_ident.rsplit_once('.') else {
+            return Some(global_ident.clone());
+        };
+
+        if module_ident == self.m.ident {
+            return Some(ident.to_string());
+        }
+
+        // 未被索引的 module 无法确定 import 路径, 按照 builtin 处理
+        let Some(target) = self.module_db.iter().find(|target| target.ident == module_ident) else {
+            return Some(ident.to_string());
+        };
+
+        let builtin_dir = Path::new(self.ctx.nature_root).join("std").join("builtin");
+        if Path::new(&target.path).starts_with(builtin_dir) {
+            return Some(ident.to_string());
+        }
+
+        if let Some(import) = find_dependency(self.m, target) {
+            if import.is_star {
+                return Some(ident.to_string());
+            }
+            return Some(format!("{}.{}", import.as_name, ident));
+        }
+
+        let (import_path, as_name) = module_import_path(self.m, target, self.ctx)?;
+        if !self.imports.contains(&import_path) {
+            self.imports.push(import_path);
+        }
+        Some(format!("{}.{}", as_name, ident))
+    }
+
+    fn join(&mut self, types: &[Type], separator: &str) -> Option<String> {
+        let rendered: Option<Vec<String>> = types.iter().map(|t| self.render(t)).collect();
+        Some(rendered?.join(separator))
+    }
+
+    fn render(&mut self, t: &Type) -> Option<String> {
+        if t.err || t.kind.is_unknown() {
+            return None;
+        }
+
+        if let TypeKind::Param(ident) = &t.kind {
+            return Some(ident.clone());
+        }
+
+        if t.origin_ident.is_some() {
+            let ident = self.alias_ident(t)?;
+            if t.impl_args.is_empty() {
+                return Some(ident);
+            }
+            return Some(format!("{}<{}>", ident, self.join(&t.impl_args, ",")?));
+        }
+
+        if Type::is_origin_type(&t.kind) {
+            return Some(t.kind.to_string());
+        }
+
+        match &t.kind {
+            TypeKind::Vec(element_type) => Some(format!("vec<{}>", self.render(element_type)?)),
+            TypeKind::Set(element_type) => Some(format!("set<{}>", self.render(element_type)?)),
+            TypeKind::Chan(element_type) => Some(format!("chan<{}>", self.render(element_type)?)),
+            TypeKind::Promise(value_type) => Some(format!("promise<{}>", self.render(value_type)?)),
+            TypeKind::Ptr(value_type) => Some(format!("ptr<{}>", self.render(value_type)?)),
+            TypeKind::RawPtr(value_type) => Some(format!("raw_ptr<{}>", self.render(value_type)?)),
+            TypeKind::Arr(length, element_type) => Some(format!("arr<{},{}>", self.render(element_type)?, length)),
+            TypeKind::Map(key_type, value_type) => Some(format!("map<{},{}>", self.render(key_type)?, self.render(value_type)?)),
+            TypeKind::Tuple(elements, _) => Some(format!("({})", self.join(elements, ", ")?)),
+            TypeKind::Union(true, _) => Some("any".to_string()),
+            TypeKind::Union(false, elements) => self.join(elements, "|"),
+            TypeKind::Fn(type_fn) if !type_fn.rest => Some(format!(
+                "fn({}):{}{}",
+                self.join(&type_fn.param_types, ", ")?,
+                self.render(&type_fn.return_type)?,
+                if type_fn.errable { "!" } else { "" }
+            )),
+            // 匿名 struct 等类型无法在源码中书写
+            _ => None,
+        }
+    }
+}
+
+/**
+ * 光标所在的局部 var def stmt 与全局 var def, 全局 var def 在 semantic 阶段已经脱离了 stmt
+ */
+fn find_var_def(m: &Module, offset: usize) -> Option<(Option<usize>, Arc<RwLock<VarDeclExpr>>, Expr)> {
+    let contains = |node: &AstNode, start: usize| -> bool {
+        let AstNode::VarDef(var_decl_mutex, _) = node else {
+            return false;
+        };
+        let symbol_end = var_decl_mutex.read().unwrap().symbol_end;
+        start <= offset && offset <= symbol_end
+    };
+
+    let mut found = None;
+    let mut visit = |stmt: &Stmt| {
+        if found.is_none() && contains(&stmt.node, stmt.span.start as usize) {
+            if let AstNode::VarDef(var_decl_mutex, right) = &stmt.node {
+                found = Some((Some(stmt.span.start as usize), var_decl_mutex.clone(), *right.clone()));
+            }
+        }
+    };
+    for fndef_mutex in &m.all_fndefs {
+        let fndef = fndef_mutex.read().unwrap();
+        walk_stmts(&fndef.body, &mut visit);
+    }
+    if found.is_some() {
+        return found;
+    }
+
+    m.global_vardefs.iter().find_map(|node| {
+        let AstNode::VarDef(var_decl_mutex, right) = node else {
+            return None;
+        };
+        let symbol_start = var_decl_mutex.read().unwrap().symbol_start;
+        let start = token_before(m, symbol_start)?.span.start as usize;
+        contains(node, start).then(|| (None, var_decl_mutex.clone(), *right.clone()))
+    })
+}
+
+fn token_before(m: &Module, offset: usize) -> Option<&Token> {
+    m.token_indexes
+        .iter()
+        .map(|i| &m.token_db[*i])
+        .take_while(|t| (t.span.start as usize) < offset)
+        .filter(|t| t.token_type != TokenType::StmtEof)
+        .last()
+}
+
+fn rewrite_action(m: &Module, uri: &Url, title: String, span: Span, new_text: String, imports: &[String]) -> Option<CodeAction> {
+    let mut edits = vec![TextEdit {
+        range: range_of(span.start as usize, span.end as usize, &m.line_index)?,
+        new_text,
+    }];
+
+    let insert_position = import_insert_position(m);
+    for import_path in imports {
+        edits.push(TextEdit {
+            range: Range::new(insert_position, insert_position),
+            new_text: format!("import {}\n", import_path),
+        });
+    }
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    Some(CodeAction {
+        title,
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/**
+ * var a = expr -> T a = expr, 推导类型无法书写时(unknown/匿名 struct 等)不提供该 action
+ */
+pub fn specify_type_action(m: &Module, module_db: &[Module], uri: &Url, range: Range, ctx: &AutoImportContext) -> Option<CodeAction> {
+    let offset = position_to_char(m, range.start.line, range.start.character)?;
+    let (_, var_decl_mutex, _) = find_var_def(m, offset)?;
+    let var_decl = var_decl_mutex.read().unwrap();
+
+    let var_token = token_before(m, var_decl.symbol_start).filter(|t| t.token_type == TokenType::Var)?;
+    if offset < var_token.span.start as usize {
+        return None;
+    }
+
+    let mut renderer = TypeRenderer {
+        m,
+        module_db,
+        ctx,
+        imports: Vec::new(),
+    };
+    let type_string = renderer.render(&var_decl.type_)?;
+
+    rewrite_action(m, uri, format!("Specify type: {}", type_string), var_token.span, type_string, &renderer.imports)
+}
+
+/**
+ * T a = expr -> var a = expr, 仅当右值自身的类型与声明类型一致时才可以省略类型
+ * literal 等右值的类型依赖声明类型进行推导，所以不提供该 action
+ */
+pub fn use_var_action(m: &Module, module_db: &[Module], uri: &Url, range: Range, ctx: &AutoImportContext) -> Option<CodeAction> {
+    let offset = position_to_char(m, range.start.line, range.start.character)?;
+    let (Some(stmt_start), var_decl_mutex, right) = find_var_def(m, offset)? else {
+        return None;
+    };
+    let var_decl = var_decl_mutex.read().unwrap();
+
+    if token_before(m, var_decl.symbol_start).is_none_or(|t| t.token_type == TokenType::Var) {
+        return None;
+    }
+
+    if !matches!(
+        right.node,
+        AstNode::Call(..) | AstNode::Ident(..) | AstNode::StructNew(..) | AstNode::New(..) | AstNode::As(..)
+    ) {
+        return None;
+    }
+
+    let mut renderer = TypeRenderer {
+        m,
+        module_db,
+        ctx,
+        imports: Vec::new(),
+    };
+    let declared = renderer.render(&var_decl.type_)?;
+    if renderer.render(&right.type_)? != declared {
+        return None;
+    }
+
+    rewrite_action(
+        m,
+        uri,
+        "Use var".to_string(),
+        Span::new(stmt_start, var_decl.symbol_start),
+        "var ".to_string(),
+        &[],
+    )
+}
+
+/**
+ * 诊断范围与 call.left 一致的 call, 返回 call 所在的 expr 范围
+ * call 也可以直接作为 stmt 使用, 此时返回 stmt 的范围
+ */
+fn find_call(m: &Module, span: Span) -> Option<(Span, AstCall)> {
+    let is_target = |call: &AstCall| call.left.span == span;
+
+    let mut found = None;
+    let mut visit = |expr: &Expr| {
+        if let AstNode::Call(call) = &expr.node {
+            if found.is_none() && is_target(call) {
+                found = Some((expr.span, call.clone()));
+            }
+        }
+    };
+    walk_body(&m.stmts, &mut visit);
+    for node in &m.global_vardefs {
+        walk_node(node, &mut visit);
+    }
+    if found.is_some() {
+        return found;
+    }
+
+    let mut visit = |stmt: &Stmt| {
+        if let AstNode::Call(call) = &stmt.node {
+            if found.is_none() && is_target(call) {
+                found = Some((stmt.span, call.clone()));
+            }
+        }
+    };
+    walk_stmts(&m.stmts, &mut visit);
+
+    found
+}
+
+/**
+ * 根据 call 所在的上下文推断返回值类型, 无法推断时为 void
+ * 支持显式声明类型的 var def, assign 以及 return
+ */
+fn call_return_type(m: &Module, call_span: Span) -> Option<Type> {
+    let is_call = |expr: &Expr| expr.span == call_span;
+    let mut found = None;
+    let mut visit = |stmt: &Stmt| {
+        if found.is_some() {
+            return;
+        }
+        match &stmt.node {
+            AstNode::VarDef(var_decl_mutex, right) if is_call(right) => {
+                let var_decl = var_decl_mutex.read().unwrap();
+                if token_before(m, var_decl.symbol_start).is_some_and(|t| t.token_type != TokenType::Var) {
+                    found = Some(var_decl.type_.clone());
+                }
+            }
+            AstNode::Assign(left, right) if is_call(right) => found = Some(left.type_.clone()),
+            _ => {}
+        }
+    };
+    for fndef_mutex in &m.all_fndefs {
+        let fndef = fndef_mutex.read().unwrap();
+        walk_stmts(&fndef.body, &mut visit);
+    }
+    if found.is_some() {
+        return found;
+    }
+
+    // return call(), 使用包含 call 的最内层 fn 的返回值类型
+    let mut return_type: Option<(usize, Type)> = None;
+    for fndef_mutex in &m.all_fndefs {
+        let fndef = fndef_mutex.read().unwrap();
+        if fndef.start > call_span.start as usize || fndef.end < call_span.end as usize {
+            continue;
+        }
+        if return_type.as_ref().is_some_and(|(size, _)| *size <= fndef.end - fndef.start) {
+            continue;
+        }
+
+        let mut is_returned = false;
+        let mut visit = |stmt: &Stmt| {
+            if let AstNode::Return(Some(expr)) = &stmt.node {
+                is_returned |= is_call(expr);
+            }
+        };
+        walk_stmts(&fndef.body, &mut visit);
+        if is_returned {
+            return_type = Some((fndef.end - fndef.start, fndef.return_type.clone()));
+        }
+    }
+
+    return_type.map(|(_, t)| t)
+}
+
+/**
+ * 基于 call 的实参生成形参列表, ident 实参沿用源码中的名称, 其余实参使用 argN
+ */
+fn stub_params(m: &Module, renderer: &mut TypeRenderer, args: &[Box<Expr>]) -> Option<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut params = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let source_ident = match &arg.node {
+            AstNode::Ident(..) => Some(m.rope.get_slice(arg.span.range())?.to_string()),
+            _ => None,
+        };
+        let name = match source_ident {
+            Some(ident) if !names.contains(&ident) => ident,
+            _ => format!("arg{}", i),
+        };
+
+        params.push(format!("{} {}", renderer.render(&arg.type_)?, name));
+        names.push(name);
+    }
+
+    Some(params.join(", "))
+}
+
+fn stub_action(m: &Module, uri: &Url, title: String, insert_at: usize, signature: String, imports: &[String], diagnostic: &Diagnostic) -> Option<CodeAction> {
+    let position = offset_to_position(insert_at, &m.line_index)?;
+    let new_text = if insert_at >= m.rope.len_chars() {
+        let separator = if m.source.ends_with('\n') { "\n" } else { "\n\n" };
+        format!("{}{} {{\n}}\n", separator, signature)
+    } else {
+        format!("\n\n{} {{\n}}", signature)
+    };
+
+    let mut edits = vec![TextEdit {
+        range: Range::new(position, position),
+        new_text,
+    }];
+
+    let insert_position = import_insert_position(m);
+    for import_path in imports {
+        edits.push(TextEdit {
+            range: Range::new(insert_position, insert_position),
+            new_text: format!("import {}\n", import_path),
+        });
+    }
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    Some(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn stub_return_type(m: &Module, renderer: &mut TypeRenderer, call_span: Span) -> String {
+    call_return_type(m, call_span)
+        .and_then(|t| renderer.render(&t))
+        .unwrap_or_else(|| "void".to_string())
+}
+
+/**
+ * person.greet() 中 impl fn 不存在时生成 fn Person.greet() 声明, 插入到 type 声明之后
+ * 只能为当前 module 中声明的 type 添加 impl fn
+ */
+pub fn create_method_action(m: &Module, module_db: &[Module], uri: &Url, diagnostic: &Diagnostic, ctx: &AutoImportContext) -> Option<CodeAction> {
+    let start = position_to_char(m, diagnostic.range.start.line, diagnostic.range.start.character)?;
+    let end = position_to_char(m, diagnostic.range.end.line, diagnostic.range.end.character)?;
+    let (call_span, call) = find_call(m, Span::new(start, end))?;
+
+    let AstNode::SelectExpr(receiver, key) = &call.left.node else {
+        return None;
+    };
+    let receiver_type = match &receiver.type_.kind {
+        TypeKind::Ptr(value_type) | TypeKind::RawPtr(value_type) => value_type.as_ref(),
+        _ => &receiver.type_,
+    };
+    if !matches!(receiver_type.kind, TypeKind::Struct(..)) {
+        return None;
+    }
+
+    let impl_ident = receiver_type.impl_ident.as_ref()?;
+    let (alias_stmt_end, receiver_string) = m.stmts.iter().find_map(|stmt| {
+        let AstNode::TypeAlias(type_alias_mutex) = &stmt.node else {
+            return None;
+        };
+        let type_alias = type_alias_mutex.read().unwrap();
+        if type_alias.ident != *impl_ident {
+            return None;
+        }
+
+        let ident = m.rope.get_slice(type_alias.symbol_start..type_alias.symbol_end)?.to_string();
+        if type_alias.params.is_empty() {
+            return Some((stmt.span.end as usize, ident));
+        }
+        let params: Vec<&str> = type_alias.params.iter().map(|p| p.ident.as_str()).collect();
+        Some((stmt.span.end as usize, format!("{}<{}>", ident, params.join(", "))))
+    })?;
+
+    let mut renderer = TypeRenderer {
+        m,
+        module_db,
+        ctx,
+        imports: Vec::new(),
+    };
+    let params = stub_params(m, &mut renderer, &call.args)?;
+    let return_type = stub_return_type(m, &mut renderer, call_span);
+
+    let signature = format!("fn {}.{}({}):{}", receiver_string, key, params, return_type);
+    let title = format!("Create method {}.{}", receiver_string.split('<').next().unwrap_or_default(), key);
+    stub_action(m, uri, title, alias_stmt_end, signature, &renderer.imports, diagnostic)
+}
+
+/**
+ * greet() 中的 fn 未声明时, 在文件末尾生成 fn greet() 声明
+ */
+pub fn create_function_action(m: &Module, module_db: &[Module], uri: &Url, diagnostic: &Diagnostic, ctx: &AutoImportContext) -> Option<CodeAction> {
+    let start = position_to_char(m, diagnostic.range.start.line, diagnostic.range.start.character)?;
+    let end = position_to_char(m, diagnostic.range.end.line, diagnostic.range.end.character)?;
+    let (call_span, call) = find_call(m, Span::new(start, end))?;
+
+    if !call.left.err || !matches!(call.left.node, AstNode::Ident(..)) {
+        return None;
+    }
+    let ident = m.rope.get_slice(call.left.span.range())?.to_string();
+
+    let mut renderer = TypeRenderer {
+        m,
+        module_db,
+        ctx,
+        imports: Vec::new(),
+    };
+    let params = stub_params(m, &mut renderer, &call.args)?;
+    let return_type = stub_return_type(m, &mut renderer, call_span);
+
+    let signature = format!("fn {}({}):{}", ident, params, return_type);
+    stub_action(
+        m,
+        uri,
+        format!("Create function {}", ident),
+        m.rope.len_chars(),
+        signature,
+        &renderer.imports,
+        diagnostic,
+    )
+}
@@ -0,0 +1,125 @@
+use super::common::AstNode;
+use super::reference::{OccurrenceKind, ReferenceIndex, ReferenceTarget};
+use crate::project::Module;
+use crate::utils::range_of;
+use lsp_types::{CodeLens, Command};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+pub const RUN_TEST_COMMAND: &str = "nature.runTest";
+
+pub struct CodeLensConfig {
+    pub references: bool,
+    pub references_max_lines: usize, // 超过该行数的文件不显示 references lens
+}
+
+impl Default for CodeLensConfig {
+    fn default() -> Self {
+        Self {
+            references: true,
+            references_max_lines: 5000,
+        }
+    }
+}
+
+/**
+ * 顶层 fn 与 type alias 上方的 references lens, 以及 #test fn 上方的 run test lens
+ *
+ * references lens 在 codeLens/resolve 时才计算引用数量, data 中记录了 (path, offset)
+ */
+pub fn code_lenses(m: &Module, config: &CodeLensConfig) -> Vec<CodeLens> {
+    let references = config.references && m.rope.len_lines() <= config.references_max_lines;
+    let mut lenses = Vec::new();
+
+    for stmt in &m.stmts {
+        let (symbol_start, symbol_end) = match &stmt.node {
+            AstNode::FnDef(fndef_mutex) => {
+                let fndef = fndef_mutex.read().unwrap();
+                if fndef.is_test {
+                    if let Some(range) = range_of(fndef.symbol_start, fndef.symbol_end, &m.line_index) {
+                        lenses.push(CodeLens {
+                            range,
+                            command: Some(Command {
+                                title: "▶ run test".to_string(),
+                                command: RUN_TEST_COMMAND.to_string(),
+                                arguments: Some(vec![json!({ "fn": fndef.symbol_name, "file": m.path })]),
+                            }),
+                            data: None,
+                        });
+                    }
+                }
+                (fndef.symbol_start, fndef.symbol_end)
+            }
+            AstNode::TypeAlias(type_alias_mutex) => {
+                let type_alias = type_alias_mutex.read().unwrap();
+                (type_alias.symbol_start, type_alias.symbol_end)
+            }
+            _ => continue,
+        };
+
+        if !references || symbol_end <= symbol_start {
+            continue;
+        }
+        if let Some(range) = range_of(symbol_start, symbol_end, &m.line_index) {
+            lenses.push(CodeLens {
+                range,
+                command: None,
+                data: Some(json!({ "path": m.path, "offset": symbol_start })),
+            });
+        }
+    }
+
+    lenses
+}
+
+/**
+ * references lens 中记录的 (path, offset)
+ */
+pub fn lens_target(lens: &CodeLens) -> Option<(String, usize)> {
+    let data: &Value = lens.data.as_ref()?;
+    Some((data.get("path")?.as_str()?.to_string(), data.get("offset")?.as_u64()? as usize))
+}
+
+fn declaration_target(m: &Module, offset: usize) -> Option<ReferenceTarget> {
+    m.stmts.iter().find_map(|stmt| match &stmt.node {
+        AstNode::FnDef(fndef_mutex) => {
+            let fndef = fndef_mutex.read().unwrap();
+            (fndef.symbol_start == offset).then_some(ReferenceTarget::Symbol(fndef.symbol_id?))
+        }
+        AstNode::TypeAlias(type_alias_mutex) => {
+            let type_alias = type_alias_mutex.read().unwrap();
+            (type_alias.symbol_start == offset).then(|| ReferenceTarget::TypeAlias(type_alias.ident.clone()))
+        }
+        _ => None,
+    })
+}
+
+/**
+ * 统计 path 中 offset 处声明的 fn 或 type alias 在所有 module 中被引用的次数, index_of 用于获取(缓存的) module reference index
+ */
+pub fn count_references(modules: &[Module], index_of: &dyn Fn(&Module) -> Arc<ReferenceIndex>, path: &str, offset: usize) -> Option<usize> {
+    let m = modules.iter().find(|m| m.path == path)?;
+    let target = declaration_target(m, offset)?;
+
+    Some(
+        modules
+            .iter()
+            .map(|m| index_of(m).occurrences_of(&target).filter(|o| o.kind == OccurrenceKind::Read).count())
+            .sum(),
+    )
+}
+
+pub fn resolve_reference_lens(mut lens: CodeLens, count: usize) -> CodeLens {
+    let title = if count == 1 {
+        "1 reference".to_string()
+    } else {
+        format!("{} references", count)
+    };
+
+    lens.command = Some(Command {
+        title,
+        command: String::new(),
+        arguments: None,
+    });
+    lens
+}
@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result};
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::sync::{Arc, Mutex};
-use strum_macros::Display;
+use std::sync::{Arc, RwLock};
+use strum_macros::{Display, IntoStaticStr};
 
 use crate::utils::align_up;
 
+use super::intern::Atom;
+use super::lexer::{caret_indent, source_line};
 use super::symbol::{NodeId, SymbolTable, GLOBAL_SCOPE_ID};
+use super::type_utils::type_eq;
 
 use serde::Deserialize;
 
@@ -47,13 +50,190 @@ pub struct PackageConfig {
     pub package_data: PackageData,
 }
 
-#[derive(Debug, Clone)]
-pub struct AnalyzerError {
-    pub start: usize,
-    pub end: usize,
+/**
+ * 源码中的一段范围 [start, end), 与 token 一致使用字符偏移
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start: start as u32,
+            end: end as u32,
+        }
+    }
+
+    /**
+     * 同时覆盖两个范围的最小范围
+     */
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start as usize <= offset && offset < self.end as usize
+    }
+
+    // 光标位于末尾时同样认为处于范围内, 与 hover/reference 等查找方式一致
+    pub fn touches(&self, offset: usize) -> bool {
+        self.start as usize <= offset && offset <= self.end as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 用于 rope 与 char 数组的切片
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start as usize..self.end as usize
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "information",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+/**
+ * 产生诊断的阶段, semantic 与 typesys 等 parser 之后的阶段统一为 Analyzer
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagnosticPhase {
+    Lexer,
+    Syntax,
+    Validate, // Syntax::validate_ast
+    #[default]
+    Analyzer,
+}
+
+/**
+ * 诊断的关联位置, 例如重复声明时之前的声明, 与诊断位于同一个文件中
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelatedInfo {
+    pub span: Span,
     pub message: String,
 }
 
+/**
+ * lexer, parser 以及之后各个分析阶段共用的诊断, start 与 end 为 char offset
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub related: Vec<RelatedInfo>,
+    pub phase: DiagnosticPhase,
+}
+
+// 迁移期间保留的名称, semantic 与 typesys 中依旧使用 AnalyzerError 构造诊断
+pub type AnalyzerError = Diagnostic;
+
+// struct new 缺少必须赋值的属性, code action 根据该 code 提供补全属性的 quickfix
+pub const MISSING_FIELDS_CODE: &str = "missing-fields";
+
+impl Diagnostic {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+            severity: Severity::Error,
+            code: None,
+            related: Vec::new(),
+            phase: DiagnosticPhase::default(),
+        }
+    }
+
+    pub fn with_phase(mut self, phase: DiagnosticPhase) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_related(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.related.push(RelatedInfo { span, message: message.into() });
+        self
+    }
+
+    /**
+     * 类似 rustc 的错误输出, 展示错误所在行的源码并使用 ^ 标记错误的位置, 错误跨越多行时只标记到第一行的末尾
+     *
+     * error: expected ';' or '}' at end of statement
+     *  --> 2:13
+     *   |
+     * 2 |     var a = 1 2
+     *   |             ^
+     */
+    pub fn display_with_source(&self, source: &str) -> String {
+        let (line, col, line_text) = source_line(source, self.span.start as usize);
+        let gutter = " ".repeat(line.to_string().len());
+        let remaining = line_text.chars().count().saturating_sub(col as usize - 1);
+        let width = self.span.len().min(remaining).max(1);
+        let indent = caret_indent(line_text, col as usize - 1);
+
+        format!(
+            "{}: {}\n{gutter}--> {line}:{col}\n{gutter} |\n{line} | {line_text}\n{gutter} | {indent}{}",
+            self.severity.as_str(),
+            self.message,
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.code {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity.as_str(), code, self.message),
+            None => write!(f, "{}: {}", self.severity.as_str(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
 #[derive(Debug, Clone)]
 pub struct Type {
     pub kind: TypeKind,
@@ -63,8 +243,7 @@ pub struct Type {
 
     pub impl_ident: Option<String>,
     pub impl_args: Vec<Type>,
-    pub start: usize, // 类型定义开始位置
-    pub end: usize,   // 类型定义结束位置
+    pub span: Span, // 类型定义的位置
     pub in_heap: bool,
     pub err: bool,
 }
@@ -78,16 +257,23 @@ impl Default for Type {
             origin_type_kind: TypeKind::Unknown,
             impl_ident: None,
             impl_args: Vec::new(),
-            start: 0,
-            end: 0,
+            span: Span::default(),
             in_heap: false,
             err: false,
         }
     }
 }
 
+/**
+ * 输出的类型字符串是合法的类型声明语法, 可以通过 parse_type_str 重新解析
+ */
 impl Display for Type {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        // alias 的 origin_ident 中没有记录泛型参数
+        if matches!(self.kind, TypeKind::Alias(..)) {
+            return write!(f, "{}", self._type_format());
+        }
+
         if let Some(ident) = &self.origin_ident {
             if let TypeKind::Param(param_ident) = &self.kind {
                 write!(f, "{}", param_ident)
@@ -100,6 +286,15 @@ impl Display for Type {
     }
 }
 
+/**
+ * 结构化比较, TypeKind 自身的 == 只比较 variant, 因此逐层比较 normalize 之后保留的 kind
+ */
+impl PartialEq for Type {
+    fn eq(&self, other: &Self) -> bool {
+        type_eq(self, other)
+    }
+}
+
 impl Type {
     pub fn new(kind: TypeKind) -> Self {
         Self {
@@ -109,8 +304,7 @@ impl Type {
             origin_type_kind: TypeKind::Unknown,
             impl_ident: Some(kind.to_string()),
             impl_args: Vec::new(),
-            start: 0,
-            end: 0,
+            span: Span::default(),
             in_heap: Self::kind_in_heap(&kind),
             err: false,
         }
@@ -124,8 +318,7 @@ impl Type {
             origin_type_kind: TypeKind::Unknown,
             impl_ident: Some(kind.to_string()),
             impl_args: Vec::new(),
-            start: 0,
-            end: 0,
+            span: Span::default(),
             in_heap: Self::kind_in_heap(&kind),
             err: false,
         }
@@ -146,6 +339,9 @@ impl Type {
             TypeKind::Chan(element_type) => {
                 format!("chan<{}>", element_type)
             }
+            TypeKind::Promise(value_type) => {
+                format!("promise<{}>", value_type)
+            }
             TypeKind::Arr(length, element_type) => {
                 format!("arr<{},{}>", element_type, length)
             }
@@ -155,13 +351,41 @@ impl Type {
             TypeKind::Set(element_type) => {
                 format!("set<{}>", element_type)
             }
-            TypeKind::Tuple(_elements, _) => {
-                // 简化版本，可以根据需要实现完整版本
-                "tup<...>".to_string()
+            TypeKind::Tuple(elements, _) => {
+                format!("({})", Self::join(elements, ","))
             }
             TypeKind::Fn(type_fn) => {
-                format!("fn(...):{}{}", type_fn.return_type, if type_fn.errable { "!" } else { "" })
+                // 返回值中的 T|null 使用 T? 表示
+                let return_type = match &type_fn.return_type.kind {
+                    TypeKind::Union(false, elements) if elements.len() == 2 && elements[1].kind == TypeKind::Null => {
+                        format!("{}?", elements[0])
+                    }
+                    _ => type_fn.return_type.to_string(),
+                };
+                format!(
+                    "fn({}):{}{}",
+                    Self::join(&type_fn.param_types, ","),
+                    return_type,
+                    if type_fn.errable { "!" } else { "" }
+                )
             }
+            TypeKind::Struct(ident, _, properties) if ident.is_empty() => {
+                let fields: Vec<String> = properties.iter().map(|p| format!("{} {}", p.type_, p.key)).collect();
+                format!("struct{{{}}}", fields.join(";"))
+            }
+            TypeKind::Struct(ident, ..) => ident.clone(),
+            TypeKind::Alias(alias) => {
+                let mut result = match &alias.import_as {
+                    Some(import_as) => format!("{}.{}", import_as, alias.ident),
+                    None => alias.ident.clone(),
+                };
+                if let Some(args) = &alias.args {
+                    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                    result.push_str(&format!("<{}>", args.join(",")));
+                }
+                result
+            }
+            TypeKind::Param(ident) => ident.clone(),
             TypeKind::Ptr(value_type) => {
                 format!("ptr<{}>", value_type)
             }
@@ -169,10 +393,15 @@ impl Type {
                 format!("raw_ptr<{}>", value_type)
             }
             TypeKind::Union(any, _) if *any => "any".to_string(),
+            TypeKind::Union(_, elements) => Self::join(elements, "|"),
             _ => self.kind.to_string(),
         }
     }
 
+    fn join(types: &[Type], separator: &str) -> String {
+        types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(separator)
+    }
+
     pub fn kind_in_heap(kind: &TypeKind) -> bool {
         matches!(
             kind,
@@ -186,6 +415,7 @@ impl Type {
                 | TypeKind::Fn(..)
                 | TypeKind::CoroutineT
                 | TypeKind::Chan(..)
+                | TypeKind::Promise(..)
         )
     }
 
@@ -259,6 +489,7 @@ impl Type {
                 | TypeKind::Map(..)
                 | TypeKind::Vec(..)
                 | TypeKind::Chan(..)
+                | TypeKind::Promise(..)
                 | TypeKind::Arr(..)
                 | TypeKind::Tuple(..)
                 | TypeKind::Set(..)
@@ -296,7 +527,7 @@ impl Type {
     pub fn sizeof(kind: &TypeKind) -> u64 {
         match &kind {
             TypeKind::Struct(..) => Self::type_struct_sizeof(kind),
-            TypeKind::Arr(len, element_type) => len * Self::sizeof(&element_type.kind),
+            TypeKind::Arr(len, element_type) => len.saturating_mul(Self::sizeof(&element_type.kind)),
             _ => kind.sizeof(),
         }
     }
@@ -374,6 +605,12 @@ impl Type {
                 format!("{}_{}", self.kind.to_string(), element_hash).hash(&mut hasher);
                 hasher.finish()
             }
+            TypeKind::Promise(value_type) => {
+                let value_hash = value_type.hash();
+                let mut hasher = DefaultHasher::new();
+                format!("{}_{}", self.kind, value_hash).hash(&mut hasher);
+                hasher.finish()
+            }
             TypeKind::Arr(length, element_type) => {
                 let element_hash = element_type.hash();
                 let mut hasher = DefaultHasher::new();
@@ -458,7 +695,7 @@ impl Type {
 #[derive(Debug, Clone)]
 pub struct TypeStructProperty {
     pub type_: Type,
-    pub key: String,
+    pub key: Atom,
     pub value: Option<Box<Expr>>,
     pub start: usize,
     pub end: usize,
@@ -479,7 +716,7 @@ pub struct TypeAlias {
     pub import_as: Option<String>,
     pub ident: String,
     pub symbol_id: Option<NodeId>,
-    pub args: Option<Vec<Type>>,
+    pub args: Option<Vec<TypeArg>>,
 }
 
 impl TypeAlias {
@@ -491,6 +728,64 @@ impl TypeAlias {
             args: None,
         }
     }
+
+    /**
+     * 将具名实参 Pair<U=string, T=int> 按照 params 声明的顺序重新排列, 没有具名实参时不做处理
+     */
+    pub fn order_args(&mut self, params: &[GenericsParam]) -> std::result::Result<(), String> {
+        let Some(args) = &mut self.args else {
+            return Ok(());
+        };
+        if args.iter().all(|arg| arg.name.is_none()) {
+            return Ok(());
+        }
+
+        let mut ordered: Vec<Option<TypeArg>> = vec![None; params.len()];
+        for arg in args.drain(..) {
+            let Some(name) = &arg.name else {
+                return Err("named and positional type args cannot be mixed".to_string());
+            };
+            let Some(index) = params.iter().position(|param| param.ident == *name) else {
+                return Err(format!("type alias '{}' has no param '{}'", self.ident, name));
+            };
+            if ordered[index].is_some() {
+                return Err(format!("type alias '{}' param '{}' specified more than once", self.ident, name));
+            }
+            ordered[index] = Some(arg);
+        }
+
+        for (param, arg) in params.iter().zip(ordered.iter()) {
+            if arg.is_none() {
+                return Err(format!("type alias '{}' missing param '{}'", self.ident, param.ident));
+            }
+        }
+        *args = ordered.into_iter().flatten().collect();
+        Ok(())
+    }
+}
+
+/**
+ * 类型别名的实参, Pair<T=int, U=string> 中的实参为具名实参, name 为对应的 param
+ */
+#[derive(Debug, Clone)]
+pub struct TypeArg {
+    pub name: Option<String>,
+    pub type_: Type,
+}
+
+impl TypeArg {
+    pub fn new(type_: Type) -> Self {
+        Self { name: None, type_ }
+    }
+}
+
+impl Display for TypeArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.name {
+            Some(name) => write!(f, "{}={}", name, self.type_),
+            None => write!(f, "{}", self.type_),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -501,7 +796,7 @@ pub enum ReductionStatus {
     Done = 3,
 }
 
-#[derive(Debug, Clone, Display)]
+#[derive(Debug, Clone, Display, IntoStaticStr)]
 #[repr(u8)]
 pub enum TypeKind {
     #[strum(serialize = "unknown")]
@@ -562,6 +857,9 @@ pub enum TypeKind {
     #[strum(serialize = "chan")]
     Chan(Box<Type>), // element type
 
+    #[strum(serialize = "promise")]
+    Promise(Box<Type>), // async fn 的返回值类型, value type
+
     #[strum(serialize = "coroutine_t")]
     CoroutineT,
 
@@ -630,6 +928,10 @@ impl TypeKind {
         !self.is_unknown()
     }
 
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, TypeKind::Uint | TypeKind::Uint8 | TypeKind::Uint16 | TypeKind::Uint32 | TypeKind::Uint64)
+    }
+
     pub fn sizeof(&self) -> u64 {
         match self {
             TypeKind::Void => 0,
@@ -664,6 +966,8 @@ pub enum ExprOp {
     Div,
     #[strum(to_string = "%")]
     Rem,
+    #[strum(to_string = "**")]
+    Pow,
 
     #[strum(to_string = "!")]
     Not,
@@ -733,6 +1037,7 @@ impl ExprOp {
                 | ExprOp::Mul
                 | ExprOp::Div
                 | ExprOp::Rem
+                | ExprOp::Pow
                 | ExprOp::Lshift
                 | ExprOp::Rshift
                 | ExprOp::And
@@ -743,34 +1048,48 @@ impl ExprOp {
     }
 }
 
-#[derive(Debug, Clone)]
+/**
+ * VarDecl/VarDef/FnDef/TypeAlias 等节点通过 Arc<RwLock<..>> 在 ast 与 symbol table 之间共享,
+ * 只读的遍历(walk/hover/reference 等)使用 read, 因此多个线程可以同时分析同一个 ast, 只有 semantic/typesys 改写节点时使用 write
+ *
+ * 加锁顺序为 fn → var decl → type alias, 嵌套的 fn 由外向内, 持有内层节点的锁时不能再获取外层节点的锁。
+ * 同一线程再次获取已经持有的锁会死锁, 遍历统一通过 walk::read_fndef 读取 fn def, 已经持有 fn def 的调用方只遍历其 body。
+ * 只有通过 symbol table 回到自身的情况(例如递归调用)无法确定是否已经持有锁, 此时才使用 try_read
+ */
+#[derive(Debug, Clone, IntoStaticStr)]
 pub enum AstNode {
     None,
-    Literal(TypeKind, String),            // (kind, value)
-    Binary(ExprOp, Box<Expr>, Box<Expr>), // (op, left, right)
-    Unary(ExprOp, Box<Expr>),             // (op, operand)
-    Ident(String, Option<NodeId>),        // (ident, symbol_id)
-    As(Type, Box<Expr>),                  // (target_type, src)
-    Is(Type, Box<Expr>),                  // (target_type, src)
-    MatchIs(Type),                        // (target_type)
+    Literal(TypeKind, String, LiteralSource), // (kind, value, source)
+    Binary(ExprOp, Box<Expr>, Box<Expr>),     // (op, left, right)
+    Range(Box<Expr>, Box<Expr>),              // (start, end), 仅用于 for in 0..10
+    Unary(ExprOp, Box<Expr>),                 // (op, operand)
+    Ident(Atom, Option<NodeId>),              // (ident, symbol_id)
+    As(Type, Box<Expr>),                      // (target_type, src)
+    TypeCast(Type, Box<Expr>),                // (target_type, src), 仅由类型推导插入的隐式转换, parser 不会产生
+    Is(Type, Box<Expr>),                      // (target_type, src)
+    MatchIs(Type),                            // (target_type)
 
     // marco
     MacroSizeof(Type),       // (target_type)
+    Sizeof(Box<Expr>),       // (src), 大小取决于 src 推导出的类型, 泛型实例化之后才能确定
     MacroUla(Box<Expr>),     // (src)
     MacroReflectHash(Type),  // (target_type)
     MacroTypeEq(Type, Type), // (left_type, right_type)
     MacroAsync(MacroAsyncExpr),
     MacroCall(String, Vec<MacroArg>), // (ident, args)
     MacroDefault,
+    MacroMinMax(MinMaxKind, Vec<Box<Expr>>), // (kind, args)
+    MacroAssert(Box<Expr>, Box<Expr>),       // (cond, implicit_msg), implicit_msg 为 cond 的源码文本
+    MacroBitCast(Type, Box<Expr>),           // (target_type, src)
 
-    New(Type, Vec<StructNewProperty>), // (type_, properties)
+    New(Type, Vec<StructNewProperty>, Vec<Box<Expr>>), // (type_, properties, args)
 
-    MapAccess(Type, Type, Box<Expr>, Box<Expr>),         // (key_type, value_type, left, key)
-    VecAccess(Type, Box<Expr>, Box<Expr>),               // (element_type, left, index)
-    ArrayAccess(Type, Box<Expr>, Box<Expr>),             // (element_type, left, index)
-    TupleAccess(Type, Box<Expr>, u64),                   // (element_type, left, index)
-    StructSelect(Box<Expr>, String, TypeStructProperty), // (instance, key, property)
-    EnvAccess(u8, String, Option<NodeId>),               // (index, unique_ident)
+    MapAccess(Type, Type, Box<Expr>, Box<Expr>),       // (key_type, value_type, left, key)
+    VecAccess(Type, Box<Expr>, Box<Expr>),             // (element_type, left, index)
+    ArrayAccess(Type, Box<Expr>, Box<Expr>),           // (element_type, left, index)
+    TupleAccess(Type, Box<Expr>, u64),                 // (element_type, left, index)
+    StructSelect(Box<Expr>, Atom, TypeStructProperty), // (instance, key, property)
+    EnvAccess(u8, Atom, Option<NodeId>),               // (index, unique_ident)
 
     VecNew(Vec<Box<Expr>>, Option<Box<Expr>>, Option<Box<Expr>>), // (elements, len, cap)
     ArrayNew(Vec<Box<Expr>>),                                     // elements
@@ -778,14 +1097,14 @@ pub enum AstNode {
     SetNew(Vec<Box<Expr>>),                                       //  elements
     TupleNew(Vec<Box<Expr>>),                                     // elements
     TupleDestr(Vec<Box<Expr>>),                                   // elements
-    StructNew(String, Type, Vec<StructNewProperty>),              // (ident, type_, properties)
+    StructNew(String, Type, Vec<StructNewEntry>),                 // (ident, type_, entries)
     Try(Box<Expr>, VarDeclExpr, Vec<Box<Stmt>>),                  // (try_expr, catch_err, catch_body)
 
     // 未推断出具体表达式类型
     EmptyCurlyNew,
     AccessExpr(Box<Expr>, Box<Expr>), // (left, key)
-    SelectExpr(Box<Expr>, String),    // (left, key)
-    VarDecl(Arc<Mutex<VarDeclExpr>>),
+    SelectExpr(Box<Expr>, Atom),      // (left, key)
+    VarDecl(Arc<RwLock<VarDeclExpr>>),
 
     // Statements
     Fake(Box<Expr>), // (expr)
@@ -798,23 +1117,31 @@ pub enum AstNode {
     Return(Option<Box<Expr>>),                     // (expr)
     If(Box<Expr>, Vec<Box<Stmt>>, Vec<Box<Stmt>>), // (condition, consequent, alternate)
     Throw(Box<Expr>),
-    TryCatch(Box<Expr>, Arc<Mutex<VarDeclExpr>>, Vec<Box<Stmt>>), // (try_expr, catch_err, catch_body)
-    Let(Box<Expr>),                                               // (expr)
-    ForIterator(Box<Expr>, Arc<Mutex<VarDeclExpr>>, Option<Arc<Mutex<VarDeclExpr>>>, Vec<Box<Stmt>>), // (iterate, first, second, body)
+    TryCatch(Box<Expr>, Arc<RwLock<VarDeclExpr>>, Vec<Box<Stmt>>), // (try_expr, catch_err, catch_body)
+    Let(Box<Expr>),                                                // (expr)
+    Do(Box<Expr>),                                                 // (expr), 只为副作用求值, 丢弃表达式的值
+    ForIterator(
+        Box<Expr>,
+        Arc<RwLock<VarDeclExpr>>,
+        Option<Arc<RwLock<VarDeclExpr>>>,
+        Option<Box<Expr>>,
+        Vec<Box<Stmt>>,
+    ), // (iterate, first, second, step, body)
 
     ForCond(Box<Expr>, Vec<Box<Stmt>>),                            // (condition, body)
     ForTradition(Box<Stmt>, Box<Expr>, Box<Stmt>, Vec<Box<Stmt>>), // (init, cond, update, body)
 
     // 既可以作为表达式，也可以作为语句
     Call(AstCall),
-    Catch(Box<Expr>, Arc<Mutex<VarDeclExpr>>, Vec<Box<Stmt>>), // (try_expr, catch_err, catch_body)
-    Match(Option<Box<Expr>>, Vec<MatchCase>),                  // (subject, cases)
+    Catch(Box<Expr>, Arc<RwLock<VarDeclExpr>>, Vec<Box<Stmt>>), // (try_expr, catch_err, catch_body)
+    Match(Option<Box<Expr>>, Vec<MatchCase>, bool),             // (subject, cases, has_default)
 
     Select(Vec<SelectCase>, bool, i16, i16), // (cases, has_default, send_count, recv_count)
 
-    VarDef(Arc<Mutex<VarDeclExpr>>, Box<Expr>), // (var_decl, right)
-    TypeAlias(Arc<Mutex<TypeAliasStmt>>),
-    FnDef(Arc<Mutex<AstFnDef>>),
+    VarDef(Arc<RwLock<VarDeclExpr>>, Box<Expr>), // (var_decl, right)
+    TypeAlias(Arc<RwLock<TypeAliasStmt>>),
+    FnDef(Arc<RwLock<AstFnDef>>),
+    LambdaShort(Box<Expr>), // => expr, semantic 阶段改写为 FnDef
 }
 
 impl AstNode {
@@ -828,21 +1155,188 @@ impl AstNode {
                 | AstNode::VecAccess(..)
                 | AstNode::EnvAccess(..)
                 | AstNode::StructSelect(..)
+                | AstNode::TupleDestr(..)
+                | AstNode::Unary(ExprOp::Ia, _)
         )
     }
+
+    /**
+     * 错误信息中对节点的描述, 例如 cannot assign to a function call
+     */
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AstNode::Literal(..) => "a literal",
+            AstNode::Call(..) => "a function call",
+            AstNode::MacroAsync(..) => "an async call",
+            AstNode::Binary(..) => "a binary expression",
+            AstNode::Unary(..) => "a unary expression",
+            AstNode::Range(..) => "a range expression",
+            AstNode::As(..) | AstNode::TypeCast(..) => "a type cast",
+            AstNode::Is(..) | AstNode::MatchIs(..) => "a type test",
+            AstNode::New(..) => "a new expression",
+            AstNode::VecNew(..) => "a vec literal",
+            AstNode::ArrayNew(..) => "an array literal",
+            AstNode::MapNew(..) => "a map literal",
+            AstNode::SetNew(..) => "a set literal",
+            AstNode::TupleNew(..) => "a tuple literal",
+            AstNode::StructNew(..) => "a struct literal",
+            AstNode::EmptyCurlyNew => "an empty literal",
+            AstNode::FnDef(..) | AstNode::LambdaShort(..) => "a function literal",
+            AstNode::Try(..) | AstNode::TryCatch(..) | AstNode::Catch(..) => "a catch expression",
+            AstNode::Match(..) => "a match expression",
+            AstNode::MacroSizeof(..)
+            | AstNode::Sizeof(..)
+            | AstNode::MacroUla(..)
+            | AstNode::MacroReflectHash(..)
+            | AstNode::MacroTypeEq(..)
+            | AstNode::MacroCall(..)
+            | AstNode::MacroDefault
+            | AstNode::MacroMinMax(..)
+            | AstNode::MacroAssert(..)
+            | AstNode::MacroBitCast(..) => "a macro call",
+            _ => "this expression",
+        }
+    }
+}
+
+// 区分用户编写的字面量与编译器生成的字面量, 例如 formatter 不需要输出生成的字面量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LiteralSource {
+    UserWritten,
+    Synthesized,
+}
+
+/**
+ * parser 为每个 Expr 与 Stmt 分配的 id, 在同一次 parse 中唯一, 从 1 开始分配
+ *
+ * 0 表示节点不是由 parser 创建的(例如 semantic/typesys 改写时生成的节点)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct AstNodeId(pub u32);
+
+impl AstNodeId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/**
+ * 以 AstNodeId 为 key 的稠密 map, 分析阶段通过 IdMap 记录节点的类型, 解析结果等, 而不需要修改共享的 ast 节点
+ */
+#[derive(Debug, Clone)]
+pub struct IdMap<T> {
+    items: Vec<Option<T>>,
+    len: usize,
+}
+
+impl<T> Default for IdMap<T> {
+    fn default() -> Self {
+        Self { items: Vec::new(), len: 0 }
+    }
+}
+
+impl<T> IdMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: AstNodeId, value: T) -> Option<T> {
+        let index = id.index();
+        if index >= self.items.len() {
+            self.items.resize_with(index + 1, || None);
+        }
+
+        let old = self.items[index].replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn get(&self, id: AstNodeId) -> Option<&T> {
+        self.items.get(id.index())?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: AstNodeId) -> Option<&mut T> {
+        self.items.get_mut(id.index())?.as_mut()
+    }
+
+    pub fn remove(&mut self, id: AstNodeId) -> Option<T> {
+        let old = self.items.get_mut(id.index())?.take();
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub fn contains_key(&self, id: AstNodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /**
+     * 按照 id 从小到大的顺序遍历
+     */
+    pub fn iter(&self) -> impl Iterator<Item = (AstNodeId, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| item.as_ref().map(|value| (AstNodeId(index as u32), value)))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Stmt {
-    pub start: usize,
-    pub end: usize,
+    pub id: AstNodeId,
+    pub span: Span,
     pub node: AstNode,
 }
 
+/**
+ * 以下构造函数用于去糖或测试中生成节点, 生成的节点 id 为 AstNodeId::default(), parser 中使用时需要重新分配 id
+ */
+impl Stmt {
+    pub fn new(span: Span, node: AstNode) -> Self {
+        Self {
+            id: AstNodeId::default(),
+            span,
+            node,
+        }
+    }
+
+    pub fn var_def(span: Span, decl: VarDeclExpr, init: Box<Expr>) -> Self {
+        Self::new(span, AstNode::VarDef(Arc::new(RwLock::new(decl)), init))
+    }
+
+    pub fn assign(span: Span, left: Box<Expr>, right: Box<Expr>) -> Self {
+        Self::new(span, AstNode::Assign(left, right))
+    }
+
+    pub fn call(span: Span, left: Box<Expr>, args: Vec<Box<Expr>>) -> Self {
+        Self::new(span, AstNode::Call(AstCall::new(left, args)))
+    }
+
+    pub fn ret(span: Span, value: Option<Box<Expr>>) -> Self {
+        Self::new(span, AstNode::Return(value))
+    }
+
+    // stmt 的位置与 fndef 相同
+    pub fn fn_def(fndef: AstFnDef) -> Self {
+        Self::new(Span::new(fndef.start, fndef.end), AstNode::FnDef(Arc::new(RwLock::new(fndef))))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Expr {
-    pub start: usize,
-    pub end: usize,
+    pub id: AstNodeId,
+    pub span: Span,
     pub type_: Type,
     pub target_type: Type,
     pub node: AstNode,
@@ -853,8 +1347,8 @@ pub struct Expr {
 impl Default for Expr {
     fn default() -> Self {
         Self {
-            start: 0,
-            end: 0,
+            id: AstNodeId::default(),
+            span: Span::default(),
             type_: Type::default(),
             target_type: Type::default(),
             node: AstNode::None,
@@ -864,27 +1358,68 @@ impl Default for Expr {
 }
 
 impl Expr {
-    pub fn ident(start: usize, end: usize, literal: String, symbol_id_option: Option<NodeId>) -> Self {
+    pub fn ident(span: Span, literal: impl Into<Atom>, symbol_id_option: Option<NodeId>) -> Self {
         Self {
-            start,
-            end,
+            id: AstNodeId::default(),
+            span,
             type_: Type::default(),
             target_type: Type::default(),
-            node: AstNode::Ident(literal, symbol_id_option),
+            node: AstNode::Ident(literal.into(), symbol_id_option),
             err: false,
         }
     }
+
+    pub fn new(span: Span, node: AstNode) -> Self {
+        Self {
+            span,
+            node,
+            ..Default::default()
+        }
+    }
+
+    pub fn literal(span: Span, kind: TypeKind, value: impl Into<String>) -> Self {
+        Self::new(span, AstNode::Literal(kind, value.into(), LiteralSource::Synthesized))
+    }
+
+    pub fn binary(span: Span, op: ExprOp, left: Box<Expr>, right: Box<Expr>) -> Self {
+        Self::new(span, AstNode::Binary(op, left, right))
+    }
+
+    pub fn unary(span: Span, op: ExprOp, operand: Box<Expr>) -> Self {
+        Self::new(span, AstNode::Unary(op, operand))
+    }
+
+    pub fn call(span: Span, left: Box<Expr>, args: Vec<Box<Expr>>) -> Self {
+        Self::new(span, AstNode::Call(AstCall::new(left, args)))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct VarDeclExpr {
-    pub ident: String,
+    pub ident: Atom,
     pub symbol_id: Option<NodeId>, // unique symbol table id
     pub symbol_start: usize,       // 符号定义位置
     pub symbol_end: usize,         // 符号定义位置
     pub type_: Type,
     pub be_capture: bool,
     pub heap_ident: Option<String>,
+    pub doc: Option<String>, // 声明上方的文档注释
+}
+
+impl VarDeclExpr {
+    // symbol 的位置为 span, 类型未声明时使用 Type::default(), 由类型推导填充
+    pub fn new(ident: impl Into<Atom>, type_: Type, span: Span) -> Self {
+        Self {
+            ident: ident.into(),
+            symbol_id: None,
+            symbol_start: span.start as usize,
+            symbol_end: span.end as usize,
+            type_,
+            be_capture: false,
+            heap_ident: None,
+            doc: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -896,24 +1431,69 @@ pub struct AstCall {
     pub spread: bool,
 }
 
+impl AstCall {
+    pub fn new(left: Box<Expr>, args: Vec<Box<Expr>>) -> Self {
+        Self {
+            return_type: Type::default(),
+            left,
+            generics_args: Vec::new(),
+            args,
+            spread: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StructNewProperty {
     pub type_: Type,
-    pub key: String,
+    pub key: Atom,
     pub value: Box<Expr>,
     pub start: usize,
     pub end: usize,
 }
 
+/**
+ * struct new 中的一项, 按照书写顺序赋值, 后出现的 entry 会覆盖之前相同的属性
+ */
+#[derive(Debug, Clone)]
+pub enum StructNewEntry {
+    Property(Box<StructNewProperty>),
+    Spread(Box<Expr>), // ...other
+}
+
+impl StructNewEntry {
+    pub fn value(&self) -> &Expr {
+        match self {
+            StructNewEntry::Property(property) => &property.value,
+            StructNewEntry::Spread(expr) => expr,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> &mut Box<Expr> {
+        match self {
+            StructNewEntry::Property(property) => &mut property.value,
+            StructNewEntry::Spread(expr) => expr,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MacroAsyncExpr {
-    pub closure_fn: Arc<Mutex<AstFnDef>>,
-    pub closure_fn_void: Arc<Mutex<AstFnDef>>,
+    pub closure_fn: Arc<RwLock<AstFnDef>>,
+    pub closure_fn_void: Arc<RwLock<AstFnDef>>,
     pub origin_call: Box<AstCall>,
     pub flag_expr: Option<Box<Expr>>,
     pub return_type: Type,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+pub enum MinMaxKind {
+    #[strum(to_string = "min")]
+    Min,
+    #[strum(to_string = "max")]
+    Max,
+}
+
 #[derive(Debug, Clone)]
 pub enum MacroArg {
     Stmt(Box<Stmt>),
@@ -938,6 +1518,8 @@ pub struct ImportStmt {
     pub file: Option<String>,
     pub ast_package: Option<Vec<String>>,
     pub as_name: String,
+    pub is_star: bool,           // import xxx as *
+    pub version: Option<String>, // import math@2.0 中的 2.0
     pub module_type: u8,
     pub module_ident: String, //  基于 full path 计算的 unique ident, 如果是 main.n 则 包含 main
     pub full_path: String,
@@ -954,6 +1536,8 @@ impl Default for ImportStmt {
             file: None,
             ast_package: None,
             as_name: String::new(),
+            is_star: false,
+            version: None,
             module_type: 0,
             full_path: String::new(),
             package_conf: None,
@@ -989,6 +1573,17 @@ pub struct TypeAliasStmt {
     pub symbol_start: usize,
     pub symbol_end: usize,
     pub symbol_id: Option<NodeId>,
+    pub decorators: Vec<Decorator>,
+    pub doc: Option<String>, // 声明上方的文档注释
+}
+
+// @name 或者 @name(args), 位于 fn 或者 type 声明之前
+#[derive(Debug, Clone)]
+pub struct Decorator {
+    pub name: String,
+    pub args: Vec<Box<Expr>>,
+    pub start: usize,
+    pub end: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -1003,7 +1598,7 @@ pub struct MatchCase {
 #[derive(Debug, Clone)]
 pub struct SelectCase {
     pub on_call: Option<AstCall>,
-    pub recv_var: Option<Arc<Mutex<VarDeclExpr>>>,
+    pub recv_var: Option<Arc<RwLock<VarDeclExpr>>>,
     pub is_recv: bool,
     pub is_default: bool,
     pub handle_body: Vec<Box<Stmt>>,
@@ -1016,11 +1611,11 @@ pub struct AstFnDef {
     pub symbol_name: String,
     pub symbol_id: Option<NodeId>,
     pub return_type: Type,
-    pub params: Vec<Arc<Mutex<VarDeclExpr>>>,
+    pub params: Vec<Arc<RwLock<VarDeclExpr>>>,
     pub rest_param: bool,
     pub body: Vec<Box<Stmt>>,
     pub closure: Option<isize>,
-    pub generics_hash_table: Option<HashMap<u64, Arc<Mutex<AstFnDef>>>>,
+    pub generics_hash_table: Option<HashMap<u64, Arc<RwLock<AstFnDef>>>>,
     pub generics_args_table: Option<HashMap<String, Type>>,
     pub generics_args_hash: Option<u64>,
     pub generics_params: Option<Vec<GenericsParam>>,
@@ -1029,19 +1624,25 @@ pub struct AstFnDef {
     pub be_capture_locals: Vec<String>,
     pub type_: Type,
     pub generic_assign: Option<HashMap<String, Type>>,
-    pub global_parent: Option<Arc<Mutex<AstFnDef>>>,
-    pub local_children: Vec<Arc<Mutex<AstFnDef>>>,
+    pub global_parent: Option<Arc<RwLock<AstFnDef>>>,
+    pub local_children: Vec<Arc<RwLock<AstFnDef>>>,
     pub is_closure: bool, // fn 如果引用了外部的 var, 就需要编译成闭包
     pub is_local: bool,
     pub is_tpl: bool,
     pub is_generics: bool,
     pub is_async: bool,
     pub is_private: bool,
-    pub is_errable: bool, // 当前函数是否返回错误
+    pub is_errable: bool,           // 当前函数是否返回错误
+    pub is_deprecated: bool,        // #deprecated
+    pub is_test: bool,              // #test
+    pub is_intrinsic: bool,         // #intrinsic, 由编译器实现的内置函数, 与 tpl fn 一样没有 body
+    pub is_lambda_short: bool,      // => expr, 参数与返回类型由上下文中的 fn 类型确定
+    pub decorators: Vec<Decorator>, // 按照 bottom-up 的应用顺序排列, 即最靠近 fn 的 decorator 在最前面
     pub break_target_types: Vec<Type>,
     pub linkid: Option<String>,
     pub fn_name: String, // default empty
     pub rel_path: Option<String>,
+    pub doc: Option<String>, // 声明上方的文档注释
 
     // symbol 符号定义位置
     pub symbol_start: usize,
@@ -1053,6 +1654,21 @@ pub struct AstFnDef {
     pub module_index: usize, // belong module index
 }
 
+impl AstFnDef {
+    /**
+     * 编译器生成的匿名 fn(例如协程闭包), 没有参数, 位置为 span
+     */
+    pub fn synthetic(span: Span, return_type: Type, body: Vec<Box<Stmt>>) -> Self {
+        Self {
+            return_type,
+            body,
+            start: span.start as usize,
+            end: span.end as usize,
+            ..Default::default()
+        }
+    }
+}
+
 // ast fn def default
 impl Default for AstFnDef {
     fn default() -> Self {
@@ -1083,9 +1699,15 @@ impl Default for AstFnDef {
             is_async: false,
             is_private: false,
             is_errable: false,
+            is_deprecated: false,
+            is_test: false,
+            is_intrinsic: false,
+            is_lambda_short: false,
+            decorators: Vec::new(),
             break_target_types: Vec::new(),
             fn_name: "".to_string(),
             rel_path: None,
+            doc: None,
             symbol_start: 0,
             symbol_end: 0,
             start: 0,
@@ -0,0 +1,264 @@
+use super::common::{AstNode, ImportStmt, PackageConfig};
+use crate::project::Module;
+use lsp_types::{CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind, Position, Range, TextEdit};
+use ropey::Rope;
+use std::path::Path;
+
+// 已经 import 的符号优先于 auto import 的符号
+const SORT_IMPORTED: &str = "0";
+const SORT_AUTO_IMPORT: &str = "1";
+
+#[derive(Debug, Clone, Default)]
+pub struct CompletionConfig {
+    // true: 同一目录下的 module 优先使用 import "xxx.n" 导入, false: 跳过只能通过 string path 导入的 module
+    pub prefer_string_path: bool,
+}
+
+/**
+ * auto import 需要的 project 信息
+ */
+pub struct AutoImportContext<'a> {
+    pub nature_root: &'a str,
+    pub package_config: Option<&'a PackageConfig>,
+    pub config: &'a CompletionConfig,
+}
+
+struct ModuleExport {
+    ident: String,
+    kind: CompletionItemKind,
+    doc: Option<String>,
+}
+
+impl ModuleExport {
+    // 文档注释的第一段作为简短描述
+    fn detail(&self) -> Option<String> {
+        let doc = self.doc.as_ref()?;
+        let paragraph: Vec<&str> = doc.lines().take_while(|line| !line.trim().is_empty()).collect();
+        Some(paragraph.join(" "))
+    }
+
+    fn documentation(&self) -> Option<Documentation> {
+        Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: self.doc.clone()?,
+        }))
+    }
+}
+
+/**
+ * 光标前正在输入的 ident
+ */
+pub fn completion_prefix(rope: &Rope, offset: usize) -> String {
+    let mut start = offset.min(rope.len_chars());
+    while start > 0 {
+        let c = rope.char(start - 1);
+        if !c.is_alphanumeric() && c != '_' {
+            break;
+        }
+        start -= 1;
+    }
+
+    rope.slice(start..offset.min(rope.len_chars())).to_string()
+}
+
+/**
+ * import 语句插入的位置, 存在 import 时插入到最后一个 import 的下一行，否则插入到文件开头
+ */
+pub fn import_insert_position(m: &Module) -> Position {
+    let mut last_import_end: Option<usize> = None;
+    for stmt in &m.stmts {
+        if let AstNode::Import(import) = &stmt.node {
+            last_import_end = Some(import.end);
+        }
+    }
+
+    let Some(end) = last_import_end else {
+        return Position::new(0, 0);
+    };
+
+    let line = m.rope.try_char_to_line(end).unwrap_or(0);
+    Position::new(line as u32 + 1, 0)
+}
+
+/**
+ * 计算 target module 在当前 module 中的 import 路径, 返回 (import 语句中的路径, import as name)
+ * 无法导入时返回 None
+ */
+pub fn module_import_path(m: &Module, target: &Module, ctx: &AutoImportContext) -> Option<(String, String)> {
+    // 同一目录下的 module 可以通过 import "xxx.n" 导入
+    let string_path = Path::new(&target.path)
+        .strip_prefix(&m.dir)
+        .ok()
+        .and_then(|p| p.to_str())
+        .map(|p| p.to_string());
+
+    if ctx.config.prefer_string_path {
+        if let Some(file) = &string_path {
+            let as_name = Path::new(file).file_stem()?.to_str()?.to_string();
+            return Some((format!("\"{}\"", file), as_name));
+        }
+    }
+
+    // std package, builtin 中的符号不需要 import
+    let std_dir = Path::new(ctx.nature_root).join("std");
+    if let Ok(rel) = Path::new(&target.path).strip_prefix(&std_dir) {
+        if rel.starts_with("builtin") {
+            return None;
+        }
+
+        return package_path(None, rel, "main");
+    }
+
+    // 当前 package 中的 module
+    if let Some(package_config) = ctx.package_config {
+        let package_dir = Path::new(&package_config.path).parent()?;
+        if let Ok(rel) = Path::new(&target.path).strip_prefix(package_dir) {
+            let entry = package_config.package_data.entry.as_deref().unwrap_or("main");
+            return package_path(Some(&package_config.package_data.name), rel, entry);
+        }
+    }
+
+    None
+}
+
+/**
+ * net/http/main.n -> net.http, entry 文件可以省略
+ */
+fn package_path(package_name: Option<&str>, rel: &Path, entry: &str) -> Option<(String, String)> {
+    let rel = rel.to_str()?.trim_end_matches(".n");
+
+    let mut parts: Vec<&str> = Vec::new();
+    if let Some(name) = package_name {
+        parts.push(name);
+    }
+    parts.extend(rel.split('/').filter(|p| !p.is_empty()));
+
+    if parts.len() > 1 && *parts.last().unwrap() == entry {
+        parts.pop();
+    }
+
+    if parts.is_empty() {
+        return None;
+    }
+
+    let as_name = parts.last().unwrap().to_string();
+    Some((parts.join("."), as_name))
+}
+
+/**
+ * module 中可以被外部引用的全局符号
+ */
+fn module_exports(m: &Module) -> Vec<ModuleExport> {
+    let mut exports = Vec::new();
+
+    for stmt in &m.stmts {
+        match &stmt.node {
+            AstNode::FnDef(fndef_mutex) => {
+                let fndef = fndef_mutex.read().unwrap();
+                // impl fn 通过 type 访问, private fn 不能被外部引用
+                if fndef.impl_type.kind.is_exist() || fndef.is_private || fndef.fn_name.is_empty() {
+                    continue;
+                }
+
+                exports.push(ModuleExport {
+                    ident: fndef.fn_name.clone(),
+                    kind: CompletionItemKind::FUNCTION,
+                    doc: fndef.doc.clone(),
+                });
+            }
+            AstNode::VarDef(var_decl_mutex, _) => {
+                let var_decl = var_decl_mutex.read().unwrap();
+                exports.push(ModuleExport {
+                    ident: var_decl.ident.to_string(),
+                    kind: CompletionItemKind::VARIABLE,
+                    doc: var_decl.doc.clone(),
+                });
+            }
+            AstNode::TypeAlias(type_alias_mutex) => {
+                let type_alias = type_alias_mutex.read().unwrap();
+                exports.push(ModuleExport {
+                    ident: type_alias.ident.clone(),
+                    kind: CompletionItemKind::STRUCT,
+                    doc: type_alias.doc.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    exports
+}
+
+pub fn find_dependency<'a>(m: &'a Module, target: &Module) -> Option<&'a ImportStmt> {
+    m.dependencies.iter().find(|import| import.full_path == target.path)
+}
+
+/**
+ * 在所有已经索引的 module 中查找以 prefix 开头的全局符号
+ * 已经 import 的 module 直接补全 as_name.ident, 未 import 的 module 会附带插入 import 语句的 text edit
+ */
+pub fn auto_import_completions(m: &Module, module_db: &[Module], prefix: &str, ctx: &AutoImportContext) -> Vec<CompletionItem> {
+    let mut items = Vec::new();
+    if prefix.is_empty() {
+        return items;
+    }
+
+    let insert_position = import_insert_position(m);
+
+    for target in module_db {
+        if target.path == m.path {
+            continue;
+        }
+
+        let exports: Vec<ModuleExport> = module_exports(target).into_iter().filter(|e| e.ident.starts_with(prefix)).collect();
+        if exports.is_empty() {
+            continue;
+        }
+
+        // 已经 import 的 module 不需要重复插入 import 语句
+        if let Some(import) = find_dependency(m, target) {
+            for export in exports {
+                let insert_text = if import.is_star {
+                    export.ident.clone()
+                } else {
+                    format!("{}.{}", import.as_name, export.ident)
+                };
+
+                items.push(CompletionItem {
+                    label: export.ident.clone(),
+                    kind: Some(export.kind),
+                    detail: export.detail(),
+                    documentation: export.documentation(),
+                    filter_text: Some(export.ident.clone()),
+                    insert_text: Some(insert_text),
+                    sort_text: Some(format!("{}{}", SORT_IMPORTED, export.ident)),
+                    ..Default::default()
+                });
+            }
+            continue;
+        }
+
+        let Some((import_path, as_name)) = module_import_path(m, target, ctx) else {
+            continue;
+        };
+
+        for export in exports {
+            items.push(CompletionItem {
+                label: format!("{} (import {})", export.ident, import_path),
+                kind: Some(export.kind),
+                detail: export.detail(),
+                documentation: export.documentation(),
+                filter_text: Some(export.ident.clone()),
+                insert_text: Some(format!("{}.{}", as_name, export.ident)),
+                sort_text: Some(format!("{}{}", SORT_AUTO_IMPORT, export.ident)),
+                additional_text_edits: Some(vec![TextEdit {
+                    range: Range::new(insert_position, insert_position),
+                    new_text: format!("import {}\n", import_path),
+                }]),
+                ..Default::default()
+            });
+        }
+    }
+
+    items
+}
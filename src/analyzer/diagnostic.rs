@@ -0,0 +1,149 @@
+use super::common::{AnalyzerError, Severity};
+use super::lint::{lint, LintConfig};
+use crate::project::Module;
+use crate::utils::{calculate_hash, offset_to_position};
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DocumentDiagnosticReport, FullDocumentDiagnosticReport, Location, NumberOrString, Range,
+    RelatedFullDocumentDiagnosticReport, RelatedUnchangedDocumentDiagnosticReport, UnchangedDocumentDiagnosticReport, Url, WorkspaceDocumentDiagnosticReport,
+    WorkspaceFullDocumentDiagnosticReport, WorkspaceUnchangedDocumentDiagnosticReport,
+};
+
+pub const DIAGNOSTIC_SOURCE: &str = "nature";
+
+pub fn to_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Information => DiagnosticSeverity::INFORMATION,
+        Severity::Hint => DiagnosticSeverity::HINT,
+    }
+}
+
+/**
+ * lexer, parser 与 analyzer 的诊断转换为 lsp 诊断, 关联信息位于同一个文件中, path 无法转换为 uri 时被忽略
+ */
+pub fn to_diagnostic(m: &Module, error: &AnalyzerError) -> Option<Diagnostic> {
+    let range = Range {
+        start: offset_to_position(error.span.start as usize, &m.line_index)?,
+        end: offset_to_position(error.span.end as usize, &m.line_index)?,
+    };
+
+    let uri = Url::from_file_path(&m.path).ok();
+    let related: Vec<DiagnosticRelatedInformation> = error
+        .related
+        .iter()
+        .filter_map(|info| {
+            let range = Range::new(
+                offset_to_position(info.span.start as usize, &m.line_index)?,
+                offset_to_position(info.span.end as usize, &m.line_index)?,
+            );
+            Some(DiagnosticRelatedInformation {
+                location: Location::new(uri.clone()?, range),
+                message: info.message.clone(),
+            })
+        })
+        .collect();
+
+    Some(Diagnostic {
+        range,
+        severity: Some(to_severity(error.severity)),
+        code: error.code.clone().map(NumberOrString::String),
+        source: Some(DIAGNOSTIC_SOURCE.to_string()),
+        message: error.message.clone(),
+        related_information: if related.is_empty() { None } else { Some(related) },
+        ..Default::default()
+    })
+}
+
+pub fn module_diagnostics(m: &Module) -> Vec<Diagnostic> {
+    m.analyzer_errors
+        .iter()
+        .filter(|error| error.span.end > 0) // 过滤掉 start = end = 0 的错误
+        .filter_map(|error| to_diagnostic(m, error))
+        .collect()
+}
+
+/**
+ * 诊断的上报配置, 由 settings 中的 nls.diagnostics 生成
+ */
+#[derive(Debug, Clone)]
+pub struct DiagnosticConfig {
+    pub max_per_file: usize,
+    pub lint: LintConfig,
+}
+
+impl Default for DiagnosticConfig {
+    fn default() -> Self {
+        Self {
+            max_per_file: 100,
+            lint: LintConfig::default(),
+        }
+    }
+}
+
+/**
+ * 单个文件最终上报的诊断, error 在前 lint warning 在后, 超出 max_per_file 的部分被丢弃, 只读的 std module 不进行 lint
+ */
+pub fn file_diagnostics(m: &Module, config: &DiagnosticConfig) -> Vec<Diagnostic> {
+    let mut items = module_diagnostics(m);
+    if !m.read_only {
+        items.extend(lint(m, &config.lint));
+    }
+    items.truncate(config.max_per_file);
+    items
+}
+
+/**
+ * 基于源码与诊断内容计算 result id, module 没有被重新分析时 result id 保持不变
+ */
+fn diagnostics_result_id(m: &Module, items: &[Diagnostic]) -> String {
+    let items = serde_json::to_string(items).unwrap_or_default();
+    calculate_hash(&(&m.source, items)).to_string()
+}
+
+/**
+ * textDocument/diagnostic, previous_result_id 与当前 result id 一致时返回 unchanged report
+ */
+pub fn document_diagnostic_report(m: &Module, config: &DiagnosticConfig, previous_result_id: Option<&str>) -> DocumentDiagnosticReport {
+    let items = file_diagnostics(m, config);
+    let result_id = diagnostics_result_id(m, &items);
+
+    if previous_result_id == Some(result_id.as_str()) {
+        return DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+        });
+    }
+
+    DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+        related_documents: None,
+        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+            result_id: Some(result_id),
+            items,
+        },
+    })
+}
+
+/**
+ * workspace/diagnostic 中单个文件的 report
+ */
+pub fn workspace_document_report(
+    m: &Module,
+    config: &DiagnosticConfig,
+    uri: Url,
+    version: Option<i64>,
+    previous_result_id: Option<&str>,
+) -> WorkspaceDocumentDiagnosticReport {
+    match document_diagnostic_report(m, config, previous_result_id) {
+        DocumentDiagnosticReport::Full(report) => WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+            uri,
+            version,
+            full_document_diagnostic_report: report.full_document_diagnostic_report,
+        }),
+        DocumentDiagnosticReport::Unchanged(report) => WorkspaceDocumentDiagnosticReport::Unchanged(WorkspaceUnchangedDocumentDiagnosticReport {
+            uri,
+            version,
+            unchanged_document_diagnostic_report: report.unchanged_document_diagnostic_report,
+        }),
+    }
+}
@@ -0,0 +1,219 @@
+use super::lexer::Token;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/**
+ * Added 与 Changed 的 range 指向 new tokens, Removed 的 range 指向 old tokens
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenDiff {
+    pub kind: DiffKind,
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditOp {
+    Equal,
+    Delete(usize), // old index
+    Insert(usize), // new index
+}
+
+fn token_eq(a: &Token, b: &Token) -> bool {
+    a.token_type == b.token_type && a.literal == b.literal
+}
+
+// 对角线 k 上能够到达的最远 x, k 的取值范围为 [-max, max]
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max: usize) -> Self {
+        Self {
+            offset: max as isize,
+            v: vec![0; 2 * max + 2],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+fn common_prefix(old: &[Token], new: &[Token]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| token_eq(a, b)).count()
+}
+
+fn common_suffix(old: &[Token], new: &[Token]) -> usize {
+    old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| token_eq(a, b)).count()
+}
+
+/**
+ * 同时从头部与尾部搜索, 返回最短编辑路径中间的 snake 的起点, 只需要保存当前这一轮的 v, 而不需要保存每一轮的 trace
+ */
+fn middle_snake(old: &[Token], new: &[Token], vf: &mut V, vb: &mut V) -> Option<(usize, usize)> {
+    let n = old.len();
+    let m = new.len();
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    vf[1] = 0;
+    vb[1] = 0;
+
+    for d in 0..=(n + m).div_ceil(2) as isize {
+        let mut k = d;
+        while k >= -d {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
+                vf[k + 1]
+            } else {
+                vf[k - 1] + 1
+            };
+            let y = (x as isize - k) as usize;
+            let snake_start = (x, y);
+            if x < n && y < m {
+                x += common_prefix(&old[x..], &new[y..]);
+            }
+            vf[k] = x;
+            if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                return Some(snake_start);
+            }
+            k -= 2;
+        }
+
+        // vb 中的 x 从尾部开始计算
+        let mut k = d;
+        while k >= -d {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) {
+                vb[k + 1]
+            } else {
+                vb[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let advance = common_suffix(&old[..n - x], &new[..m - y]);
+                x += advance;
+                y += advance;
+            }
+            vb[k] = x;
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                return Some((n - x, m - y));
+            }
+            k -= 2;
+        }
+    }
+
+    None
+}
+
+fn conquer(old: &[Token], old_start: usize, new: &[Token], new_start: usize, vf: &mut V, vb: &mut V, ops: &mut Vec<EditOp>) {
+    let prefix = common_prefix(old, new);
+    ops.extend(std::iter::repeat_n(EditOp::Equal, prefix));
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let (old_start, new_start) = (old_start + prefix, new_start + prefix);
+
+    let suffix = common_suffix(old, new);
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    if old.is_empty() {
+        ops.extend((new_start..new_start + new.len()).map(EditOp::Insert));
+    } else if new.is_empty() {
+        ops.extend((old_start..old_start + old.len()).map(EditOp::Delete));
+    } else if let Some((x, y)) = middle_snake(old, new, vf, vb) {
+        conquer(&old[..x], old_start, &new[..y], new_start, vf, vb, ops);
+        conquer(&old[x..], old_start + x, &new[y..], new_start + y, vf, vb, ops);
+    } else {
+        ops.extend((old_start..old_start + old.len()).map(EditOp::Delete));
+        ops.extend((new_start..new_start + new.len()).map(EditOp::Insert));
+    }
+
+    ops.extend(std::iter::repeat_n(EditOp::Equal, suffix));
+}
+
+/**
+ * myers diff 的线性空间版本, 返回从 old 到 new 的最短编辑序列
+ *
+ * 通过 middle snake 将问题分治, 内存占用为 O(N+M), 而不是保存每一轮的 v 所需要的 O(D·(N+M))
+ */
+fn myers(old: &[Token], new: &[Token]) -> Vec<EditOp> {
+    let max = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut vf = V::new(max);
+    let mut vb = V::new(max);
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    conquer(old, 0, new, 0, &mut vf, &mut vb, &mut ops);
+    ops
+}
+
+/**
+ * 对比两次编辑之间的 token 序列, 相邻的 delete 与 insert 合并为 Changed
+ */
+pub fn diff_tokens(old: &[Token], new: &[Token]) -> Vec<TokenDiff> {
+    let ops = myers(old, new);
+    let mut diffs = Vec::new();
+
+    let mut old_index = 0;
+    let mut new_index = 0;
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i] == EditOp::Equal {
+            old_index += 1;
+            new_index += 1;
+            i += 1;
+            continue;
+        }
+
+        // 收集连续的编辑操作
+        let old_start = old_index;
+        let new_start = new_index;
+        while i < ops.len() && ops[i] != EditOp::Equal {
+            match ops[i] {
+                EditOp::Delete(_) => old_index += 1,
+                EditOp::Insert(_) => new_index += 1,
+                EditOp::Equal => unreachable!(),
+            }
+            i += 1;
+        }
+
+        let deleted = old_index - old_start;
+        let inserted = new_index - new_start;
+        let changed = deleted.min(inserted);
+
+        if changed > 0 {
+            diffs.push(TokenDiff {
+                kind: DiffKind::Changed,
+                range: new_start..new_start + changed,
+            });
+        }
+
+        if inserted > changed {
+            diffs.push(TokenDiff {
+                kind: DiffKind::Added,
+                range: new_start + changed..new_index,
+            });
+        }
+
+        if deleted > changed {
+            diffs.push(TokenDiff {
+                kind: DiffKind::Removed,
+                range: old_start + changed..old_index,
+            });
+        }
+    }
+
+    diffs
+}
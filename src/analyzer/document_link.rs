@@ -0,0 +1,63 @@
+use super::common::{ImportStmt, Span};
+use super::lexer::TokenType;
+use crate::project::Module;
+use crate::utils::offset_to_position;
+use lsp_types::{DocumentLink, Range, Url};
+use std::path::Path;
+
+/**
+ * import 语句中路径部分的 char offset 范围, 字符串形式的路径不包含引号
+ */
+pub fn import_path_range(m: &Module, import: &ImportStmt) -> Option<(usize, usize)> {
+    // import.start 位于 import 关键字处, 下一个 token 即为路径的起点
+    let index = m.token_db.partition_point(|t| (t.span.start as usize) <= import.start);
+    let path_token = m.token_db[index..]
+        .iter()
+        .find(|t| !matches!(t.token_type, TokenType::LineComment | TokenType::BlockComment))?;
+
+    if path_token.token_type == TokenType::StringLiteral {
+        Some((path_token.span.start as usize + 1, import.end.saturating_sub(1)))
+    } else {
+        Some((path_token.span.start as usize, import.end))
+    }
+}
+
+/**
+ * textDocument/documentLink, import "lib/util.n" 与 import my.pkg 中的路径部分链接到 import 解析出的文件
+ *
+ * 解析失败的 import 同样返回 link, 但是没有 target, tooltip 中记录了失败原因
+ */
+pub fn document_links(m: &Module) -> Vec<DocumentLink> {
+    m.dependencies
+        .iter()
+        .filter_map(|import| {
+            let (start, end) = import_path_range(m, import)?;
+
+            let range = Range {
+                start: offset_to_position(start, &m.line_index)?,
+                end: offset_to_position(end, &m.line_index)?,
+            };
+
+            let target = (!import.full_path.is_empty() && Path::new(&import.full_path).is_file())
+                .then(|| Url::from_file_path(&import.full_path).ok())
+                .flatten();
+            let tooltip = match target {
+                Some(_) => None,
+                None => Some(
+                    m.analyzer_errors
+                        .iter()
+                        .find(|e| e.span == Span::new(import.start, import.end))
+                        .map(|e| e.message.clone())
+                        .unwrap_or_else(|| "import cannot be resolved".to_string()),
+                ),
+            };
+
+            Some(DocumentLink {
+                range,
+                target,
+                tooltip,
+                data: None,
+            })
+        })
+        .collect()
+}
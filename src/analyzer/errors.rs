@@ -0,0 +1,26 @@
+use super::common::Diagnostic;
+
+/**
+ * 错误恢复时同一段有问题的代码可能产生多个范围重叠的错误, 将相邻且重叠的错误合并为一个覆盖所有范围的错误
+ *
+ * 合并时不区分 severity 与 phase, 合并后的诊断保留第一个错误的 severity, code 与 phase, 合并后的 message 以 "; " 连接, 相同的 message 只保留一个
+ */
+pub fn merge_adjacent_errors(mut errors: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    // 稳定排序, start 相同的错误保持原有的顺序
+    errors.sort_by_key(|e| e.span.start);
+
+    let mut merged: Vec<Diagnostic> = Vec::with_capacity(errors.len());
+    for error in errors {
+        match merged.last_mut() {
+            Some(last) if last.span.end >= error.span.start => {
+                last.span.end = last.span.end.max(error.span.end);
+                if !last.message.split("; ").any(|message| message == error.message) {
+                    last.message = format!("{}; {}", last.message, error.message);
+                }
+            }
+            _ => merged.push(error),
+        }
+    }
+
+    merged
+}
@@ -0,0 +1,112 @@
+use super::common::ImportStmt;
+use super::document_link::import_path_range;
+use crate::project::Module;
+use crate::utils::offset_to_position;
+use lsp_types::{Range, TextEdit, Url};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/**
+ * 按照 rename 列表计算 path 移动之后的位置, 目录的 rename 同样作用于目录下的所有文件
+ */
+fn renamed_path(renames: &[(String, String)], path: &Path) -> PathBuf {
+    let mut result = path.to_path_buf();
+    for (old, new) in renames {
+        // join 空路径会在末尾添加分隔符, 因此 path 本身被 rename 时单独处理
+        match result.strip_prefix(old) {
+            Ok(rest) if rest.as_os_str().is_empty() => result = PathBuf::from(new),
+            Ok(rest) => result = Path::new(new).join(rest),
+            Err(_) => {}
+        }
+    }
+    result
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/**
+ * import "lib/util.n" 基于 importer 所在的目录解析, 且不能以 . 开头, 因此 target 移动到 importer 目录之外时无法改写
+ */
+fn rename_file_import(renames: &[(String, String)], m: &Module, import: &ImportStmt) -> Option<String> {
+    let file = import.file.as_ref()?;
+    let importer = renamed_path(renames, Path::new(&m.path));
+    let target = renamed_path(renames, Path::new(&import.full_path));
+
+    let relative = target.strip_prefix(importer.parent()?).ok()?;
+    let parts: Vec<&str> = relative.iter().map(|part| part.to_str()).collect::<Option<_>>()?;
+    let new_file = parts.join("/");
+
+    (!new_file.is_empty() && &new_file != file).then_some(new_file)
+}
+
+/**
+ * import pkg.a.b 由 package_dir/a/b 加上后缀(.n, .linux.n, /main.n 等)解析得到, rename 之后后缀保持不变,
+ * 只重新计算 package 中的路径部分, package name 保持不变
+ */
+fn rename_package_import(renames: &[(String, String)], import: &ImportStmt) -> Option<String> {
+    let ast_package = import.ast_package.as_ref()?;
+
+    let mut prefix = PathBuf::from(&import.package_dir);
+    prefix.extend(ast_package.iter().skip(1));
+    let suffix = import.full_path.strip_prefix(prefix.to_str()?)?;
+
+    let package_dir = renamed_path(renames, Path::new(&import.package_dir));
+    let target = renamed_path(renames, Path::new(&import.full_path));
+    let new_prefix = target.to_str()?.strip_suffix(suffix)?;
+
+    let mut parts = vec![ast_package[0].as_str()];
+    for part in Path::new(new_prefix).strip_prefix(&package_dir).ok()? {
+        let part = part.to_str()?;
+        if !is_ident(part) {
+            return None;
+        }
+        parts.push(part);
+    }
+
+    let new_package = parts.join(".");
+    (new_package != ast_package.join(".")).then_some(new_package)
+}
+
+/**
+ * workspace/willRenameFiles, renames 中的 (old, new) 可以是 .n 文件也可以是目录
+ *
+ * 改写所有解析到被移动文件的 import, 被移动文件自身的 import "..." 同样基于新的目录重新计算,
+ * edit 只替换 import 中的路径部分, 基于 module db 中的内容(即编辑器中尚未保存的内容)计算
+ */
+pub fn import_rename_edits(module_db: &[Module], renames: &[(String, String)]) -> HashMap<Url, Vec<TextEdit>> {
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+    for m in module_db.iter().filter(|m| !m.read_only) {
+        let mut edits = Vec::new();
+        for import in m.dependencies.iter().filter(|import| !import.full_path.is_empty()) {
+            let new_text = if import.file.is_some() {
+                rename_file_import(renames, m, import)
+            } else {
+                rename_package_import(renames, import)
+            };
+            let Some(new_text) = new_text else {
+                continue;
+            };
+            let Some((start, end)) = import_path_range(m, import) else {
+                continue;
+            };
+            let (Some(start), Some(end)) = (offset_to_position(start, &m.line_index), offset_to_position(end, &m.line_index)) else {
+                continue;
+            };
+
+            edits.push(TextEdit::new(Range { start, end }, new_text));
+        }
+
+        if edits.is_empty() {
+            continue;
+        }
+        if let Ok(uri) = Url::from_file_path(&m.path) {
+            changes.entry(uri).or_default().extend(edits);
+        }
+    }
+
+    changes
+}
@@ -0,0 +1,340 @@
+use super::common::{AstNode, Expr, MatchCase, Span, Stmt, TypeKind};
+use super::lexer::{Token, TokenType};
+use crate::project::Module;
+use lsp_types::{FoldingRange, FoldingRangeKind};
+use ropey::Rope;
+
+/**
+ * 基于 ast 计算 folding range, 以 } 结尾的结构折叠到 } 的上一行，从而保留 } 所在行可见
+ * 语法错误时只处理已经解析成功的结构
+ */
+struct FoldingCollector<'a> {
+    rope: &'a Rope,
+    tokens: Vec<&'a Token>, // 不包含 StmtEof 与 Eof 的 syntax token
+    ranges: Vec<FoldingRange>,
+}
+
+impl<'a> FoldingCollector<'a> {
+    fn line(&self, offset: usize) -> u32 {
+        self.rope.try_char_to_line(offset).unwrap_or(0) as u32
+    }
+
+    fn push(&mut self, start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>) {
+        if end_line <= start_line {
+            return;
+        }
+
+        self.ranges.push(FoldingRange {
+            start_line,
+            start_character: None,
+            end_line,
+            end_character: None,
+            kind,
+            collapsed_text: None,
+        });
+    }
+
+    /**
+     * 从 start 所在行折叠到 } 的上一行
+     */
+    fn push_block(&mut self, start: usize, right_curly: &Token) {
+        let start_line = self.line(start);
+        let end_line = self.line(right_curly.span.start as usize);
+        if end_line > 0 {
+            self.push(start_line, end_line - 1, None);
+        }
+    }
+
+    /**
+     * end 之前的最后一个 token 如果是 }, 返回与之匹配的 ({, })
+     */
+    fn block_before(&self, end: usize) -> Option<(&'a Token, &'a Token)> {
+        let index = self.tokens.partition_point(|t| (t.span.end as usize) <= end);
+        if index == 0 || self.tokens[index - 1].token_type != TokenType::RightCurly {
+            return None;
+        }
+
+        let mut depth = 0;
+        for i in (0..index).rev() {
+            match self.tokens[i].token_type {
+                TokenType::RightCurly => depth += 1,
+                TokenType::LeftCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((self.tokens[i], self.tokens[index - 1]));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /**
+     * start 之后的第一个 {, 返回与之匹配的 ({, })
+     */
+    fn block_after(&self, start: usize) -> Option<(&'a Token, &'a Token)> {
+        let index = self.tokens.partition_point(|t| (t.span.start as usize) < start);
+        if index >= self.tokens.len() || self.tokens[index].token_type != TokenType::LeftCurly {
+            return None;
+        }
+
+        let mut depth = 0;
+        for i in index..self.tokens.len() {
+            match self.tokens[i].token_type {
+                TokenType::LeftCurly => depth += 1,
+                TokenType::RightCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((self.tokens[index], self.tokens[i]));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn body(&mut self, body: &[Box<Stmt>]) {
+        for stmt in body {
+            self.node(&stmt.node, stmt.span);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.node(&expr.node, expr.span);
+    }
+
+    /**
+     * struct/vec/map 等字面量从开始行折叠到结束符号的上一行
+     */
+    fn literal(&mut self, start: usize, end: usize) {
+        if end == 0 {
+            return;
+        }
+        let end_line = self.line(end - 1);
+        if end_line > 0 {
+            self.push(self.line(start), end_line - 1, None);
+        }
+    }
+
+    fn match_cases(&mut self, cases: &[MatchCase]) {
+        for case in cases {
+            // 只有 block body 的 case 才需要折叠, block body 的第一个 stmt 之前是 {
+            if let Some(first) = case.handle_body.first() {
+                let index = self.tokens.partition_point(|t| t.span.start < first.span.start);
+                if index > 0 && self.tokens[index - 1].token_type == TokenType::LeftCurly {
+                    if let Some((_, right)) = self.block_after(self.tokens[index - 1].span.start as usize) {
+                        self.push_block(case.start, right);
+                    }
+                }
+            }
+
+            for cond in &case.cond_list {
+                self.expr(cond);
+            }
+            self.body(&case.handle_body);
+        }
+    }
+
+    fn node(&mut self, node: &AstNode, span: Span) {
+        let (start, end) = (span.start as usize, span.end as usize);
+        match node {
+            AstNode::FnDef(fndef_mutex) => {
+                let fndef = fndef_mutex.read().unwrap();
+                if let Some((_, right)) = self.block_before(end) {
+                    self.push_block(start, right);
+                }
+                self.body(&fndef.body);
+            }
+            AstNode::TypeAlias(type_alias_mutex) => {
+                let type_alias = type_alias_mutex.read().unwrap();
+                if matches!(type_alias.type_expr.kind, TypeKind::Struct(..)) {
+                    if let Some((_, right)) = self.block_before(end) {
+                        self.push_block(start, right);
+                    }
+                }
+            }
+            AstNode::If(condition, consequent, alternate) => {
+                self.expr(condition);
+
+                let consequent_block = self.block_after(condition.span.end as usize);
+                if let Some((_, right)) = consequent_block {
+                    self.push_block(start, right);
+                }
+                self.body(consequent);
+
+                // else if 作为单独的 if stmt 处理
+                let is_else_if = alternate.len() == 1 && matches!(alternate[0].node, AstNode::If(..));
+                if !alternate.is_empty() && !is_else_if {
+                    if let Some((left, right)) = self.block_before(end) {
+                        if consequent_block.is_none_or(|(_, consequent_right)| left.span.start > consequent_right.span.start) {
+                            self.push_block(left.span.start as usize, right);
+                        }
+                    }
+                }
+                self.body(alternate);
+            }
+            AstNode::ForIterator(iterate, _, _, _, body) => {
+                self.expr(iterate);
+                if let Some((_, right)) = self.block_before(end) {
+                    self.push_block(start, right);
+                }
+                self.body(body);
+            }
+            AstNode::ForCond(condition, body) => {
+                self.expr(condition);
+                if let Some((_, right)) = self.block_before(end) {
+                    self.push_block(start, right);
+                }
+                self.body(body);
+            }
+            AstNode::ForTradition(init, condition, update, body) => {
+                self.node(&init.node, init.span);
+                self.expr(condition);
+                self.node(&update.node, update.span);
+                if let Some((_, right)) = self.block_before(end) {
+                    self.push_block(start, right);
+                }
+                self.body(body);
+            }
+            AstNode::Match(subject, cases, _) => {
+                if let Some(subject) = subject {
+                    self.expr(subject);
+                }
+                if let Some((_, right)) = self.block_before(end) {
+                    self.push_block(start, right);
+                }
+                self.match_cases(cases);
+            }
+            AstNode::Catch(try_expr, _, catch_body) | AstNode::TryCatch(try_expr, _, catch_body) => {
+                self.expr(try_expr);
+                if let Some((_, right)) = self.block_before(end) {
+                    self.push_block(start, right);
+                }
+                self.body(catch_body);
+            }
+            AstNode::StructNew(_, _, entries) => {
+                self.literal(start, end);
+                for entry in entries {
+                    self.expr(entry.value());
+                }
+            }
+            AstNode::New(_, properties, args) => {
+                self.literal(start, end);
+                for property in properties {
+                    self.expr(&property.value);
+                }
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            AstNode::VecNew(elements, _, _) | AstNode::ArrayNew(elements) | AstNode::SetNew(elements) | AstNode::TupleNew(elements) => {
+                self.literal(start, end);
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            AstNode::MapNew(elements) => {
+                self.literal(start, end);
+                for element in elements {
+                    self.expr(&element.key);
+                    self.expr(&element.value);
+                }
+            }
+            AstNode::Call(call) => {
+                self.expr(&call.left);
+                for arg in &call.args {
+                    self.expr(arg);
+                }
+            }
+            AstNode::VarDef(_, right) | AstNode::VarTupleDestr(_, right) => self.expr(right),
+            AstNode::Assign(left, right) | AstNode::Binary(_, left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            AstNode::Fake(expr)
+            | AstNode::Do(expr)
+            | AstNode::Unary(_, expr)
+            | AstNode::As(_, expr)
+            | AstNode::TypeCast(_, expr)
+            | AstNode::Is(_, expr)
+            | AstNode::Throw(expr)
+            | AstNode::Let(expr)
+            | AstNode::Return(Some(expr))
+            | AstNode::Break(Some(expr)) => self.expr(expr),
+            _ => {}
+        }
+    }
+
+    /**
+     * 连续的 import 语句折叠为一个 imports range
+     */
+    fn imports(&mut self, stmts: &[Box<Stmt>]) {
+        let mut group: Option<(usize, usize)> = None;
+        for stmt in stmts {
+            if matches!(stmt.node, AstNode::Import(..)) {
+                group = Some(match group {
+                    Some((start, _)) => (start, stmt.span.end as usize),
+                    None => (stmt.span.start as usize, stmt.span.end as usize),
+                });
+                continue;
+            }
+
+            if let Some((start, end)) = group.take() {
+                self.push(self.line(start), self.line(end), Some(FoldingRangeKind::Imports));
+            }
+        }
+
+        if let Some((start, end)) = group {
+            self.push(self.line(start), self.line(end), Some(FoldingRangeKind::Imports));
+        }
+    }
+
+    /**
+     * 跨越多行的 block comment, lexer 会将 block comment 按行拆分为多个 token
+     */
+    fn comments(&mut self, token_db: &[Token]) {
+        let mut comment: Option<(usize, usize)> = None;
+        for token in token_db.iter().filter(|t| t.token_type == TokenType::BlockComment) {
+            if token.literal.starts_with("/*") {
+                if let Some((start, end)) = comment.take() {
+                    self.push(self.line(start), self.line(end), Some(FoldingRangeKind::Comment));
+                }
+                comment = Some((token.span.start as usize, token.span.start as usize));
+            } else if let Some((_, end)) = comment.as_mut() {
+                *end = token.span.start as usize;
+            }
+        }
+
+        if let Some((start, end)) = comment {
+            self.push(self.line(start), self.line(end), Some(FoldingRangeKind::Comment));
+        }
+    }
+}
+
+pub fn folding_ranges(m: &Module) -> Vec<FoldingRange> {
+    let tokens = m
+        .token_indexes
+        .iter()
+        .map(|i| &m.token_db[*i])
+        .filter(|t| !matches!(t.token_type, TokenType::StmtEof | TokenType::Eof))
+        .collect();
+
+    let mut collector = FoldingCollector {
+        rope: &m.rope,
+        tokens,
+        ranges: Vec::new(),
+    };
+
+    collector.imports(&m.stmts);
+    collector.body(&m.stmts);
+    collector.comments(&m.token_db);
+
+    let mut ranges = collector.ranges;
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges.dedup_by_key(|r| (r.start_line, r.end_line));
+    ranges
+}
@@ -0,0 +1,575 @@
+use super::common::Severity;
+use super::lexer::{Lexer, TokenType};
+use super::syntax::Syntax;
+
+/**
+ * 格式化配置, indent_width 为每一层缩进的空格数量, 超过 max_width 的行会尝试在调用参数/结构体字面量处折行
+ */
+#[derive(Debug, Clone)]
+pub struct FormatConfig {
+    pub indent_width: usize,
+    pub max_width: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_width: 120,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Normal,
+    Unary,        // -a, !a, *p, &a
+    Suffix,       // int?, int!
+    Binary,       // a + b, a = b
+    GenericOpen,  // vec<
+    GenericClose, // vec<int>
+    Block,        // if a {, fn main():int {, struct {
+}
+
+#[derive(Debug, Clone)]
+struct FmtToken {
+    token_type: TokenType,
+    text: String, // 源码中的原始文本, 包含字符串引号与注释符号
+    line: usize,
+    start: usize,
+    end: usize,
+    role: Role,
+    pair: Option<usize>, // 括号对应的另一半
+}
+
+/**
+ * 格式化源码, 词法或语法存在错误时返回 None, 避免破坏用户代码
+ *
+ * 格式化基于语法分析之后的 token 流(包含注释), 语法分析会修正 < 与 >> 的歧义, 注释原样保留。
+ * 格式化结果会重新进行词法与语法分析, 只有 token 序列完全一致时才会返回
+ */
+pub fn format_source(source: &str, config: &FormatConfig) -> Option<String> {
+    let tokens = collect_tokens(source)?;
+
+    let mut formatter = Formatter::new(tokens, config);
+    let result = formatter.format();
+
+    let verify = collect_tokens(&result)?;
+    if verify.len() != formatter.tokens.len() {
+        return None;
+    }
+
+    let same = verify
+        .iter()
+        .zip(formatter.tokens.iter())
+        .all(|(a, b)| a.token_type == b.token_type && a.text.trim_end() == b.text.trim_end());
+    if !same {
+        return None;
+    }
+
+    Some(result)
+}
+
+/**
+ * 收集除 Eof 与自动插入的 StmtEof 之外的所有 token, 并根据上下文判断运算符的角色
+ */
+fn collect_tokens(source: &str) -> Option<Vec<FmtToken>> {
+    let (token_db, token_indexes, lexer_errors) = Lexer::new(source.to_string()).scan();
+    if !lexer_errors.is_empty() {
+        return None;
+    }
+
+    let (_, mut token_db, syntax_errors) = Syntax::new(token_db, token_indexes).parser();
+    // warning(例如重复的 star import)不影响格式化
+    if syntax_errors.iter().any(|e| e.severity == Severity::Error) {
+        return None;
+    }
+
+    token_db.sort_by_key(|t| t.span.start);
+
+    let chars: Vec<char> = source.chars().collect();
+    let mut result: Vec<FmtToken> = Vec::new();
+    let mut prev: Option<(TokenType, Role)> = None; // 上一个非注释 token, 包含自动插入的 StmtEof
+    let mut angle_depth = 0;
+    let mut depth = 0;
+    let mut header: Option<usize> = None; // if/for/fn 等语句头部所在的括号深度, 头部之后的 { 是代码块
+
+    for token in token_db {
+        if token.token_type == TokenType::Eof {
+            continue;
+        }
+
+        let is_comment = matches!(token.token_type, TokenType::LineComment | TokenType::BlockComment);
+        if token.token_type == TokenType::StmtEof && chars.get(token.span.start as usize) != Some(&';') {
+            prev = Some((TokenType::StmtEof, Role::Normal));
+            continue;
+        }
+
+        let after_operand = prev.as_ref().is_some_and(|(t, r)| is_operand_end(t, *r));
+        let role = match token.token_type {
+            TokenType::If | TokenType::For | TokenType::Match | TokenType::Catch | TokenType::Select | TokenType::Try | TokenType::Fn => {
+                if header.is_none() {
+                    header = Some(depth);
+                }
+                Role::Normal
+            }
+            TokenType::LeftCurly => {
+                let block = header == Some(depth)
+                    || prev
+                        .as_ref()
+                        .is_some_and(|(t, _)| matches!(t, TokenType::Struct | TokenType::Else | TokenType::RightParen));
+                if header == Some(depth) {
+                    header = None;
+                }
+                if block {
+                    Role::Block
+                } else {
+                    Role::Normal
+                }
+            }
+            TokenType::LeftAngle => {
+                angle_depth += 1;
+                Role::GenericOpen
+            }
+            TokenType::RightAngle if angle_depth > 0 => {
+                angle_depth -= 1;
+                Role::GenericClose
+            }
+            TokenType::Minus | TokenType::Plus | TokenType::Star | TokenType::And => {
+                if after_operand {
+                    Role::Binary
+                } else {
+                    Role::Unary
+                }
+            }
+            TokenType::Not => {
+                if after_operand {
+                    Role::Suffix
+                } else {
+                    Role::Unary
+                }
+            }
+            TokenType::Tilde => Role::Unary,
+            TokenType::Question => Role::Suffix,
+            TokenType::RightAngle
+            | TokenType::RightShift
+            | TokenType::StarStar
+            | TokenType::LessThan
+            | TokenType::LessEqual
+            | TokenType::GreaterEqual
+            | TokenType::EqualEqual
+            | TokenType::NotEqual
+            | TokenType::LeftShift
+            | TokenType::Slash
+            | TokenType::Percent
+            | TokenType::Xor
+            | TokenType::AndAnd
+            | TokenType::OrOr
+            | TokenType::Equal
+            | TokenType::PlusEqual
+            | TokenType::MinusEqual
+            | TokenType::StarEqual
+            | TokenType::SlashEqual
+            | TokenType::PercentEqual
+            | TokenType::AndEqual
+            | TokenType::OrEqual
+            | TokenType::XorEqual
+            | TokenType::LeftShiftEqual
+            | TokenType::RightShiftEqual
+            | TokenType::RightArrow
+            | TokenType::FatArrow => Role::Binary,
+            _ => Role::Normal,
+        };
+
+        if is_opener(&token.token_type) {
+            depth += 1;
+        } else if is_closer(&token.token_type) {
+            depth -= 1;
+        }
+
+        if !is_comment {
+            prev = Some((token.token_type.clone(), role));
+        }
+
+        result.push(FmtToken {
+            token_type: token.token_type,
+            text: chars[token.span.start as usize..(token.span.end as usize).min(chars.len())].iter().collect(),
+            line: token.line,
+            start: token.span.start as usize,
+            end: token.span.end as usize,
+            role,
+            pair: None,
+        });
+    }
+
+    // 括号配对
+    let mut stack = Vec::new();
+    for i in 0..result.len() {
+        if is_opener(&result[i].token_type) {
+            stack.push(i);
+        } else if is_closer(&result[i].token_type) {
+            if let Some(open) = stack.pop() {
+                result[open].pair = Some(i);
+                result[i].pair = Some(open);
+            }
+        }
+    }
+
+    Some(result)
+}
+
+fn is_opener(t: &TokenType) -> bool {
+    matches!(t, TokenType::LeftParen | TokenType::LeftSquare | TokenType::LeftCurly)
+}
+
+fn is_closer(t: &TokenType) -> bool {
+    matches!(t, TokenType::RightParen | TokenType::RightSquare | TokenType::RightCurly)
+}
+
+fn is_comment(t: &TokenType) -> bool {
+    matches!(t, TokenType::LineComment | TokenType::BlockComment)
+}
+
+/**
+ * token 之后出现的 - * & ! 是否是二元运算符(或后缀)
+ */
+fn is_operand_end(t: &TokenType, role: Role) -> bool {
+    if matches!(role, Role::GenericClose | Role::Suffix) {
+        return true;
+    }
+
+    t.is_type_keyword()
+        || matches!(
+            t,
+            TokenType::Ident
+                | TokenType::IntLiteral
+                | TokenType::FloatLiteral
+                | TokenType::StringLiteral
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Null
+                | TokenType::RightParen
+                | TokenType::RightSquare
+                | TokenType::RightCurly
+        )
+}
+
+/**
+ * 标识符、字面量与关键字之间至少需要一个空格
+ */
+fn is_word(t: &TokenType) -> bool {
+    t.is_keyword()
+        || t.is_literal()
+        || matches!(
+            t,
+            TokenType::Ident | TokenType::MacroIdent | TokenType::Label | TokenType::Sizeof | TokenType::ReflectHash
+        )
+}
+
+struct Line {
+    first: usize,
+    last: usize,
+    text: String,
+}
+
+struct Formatter<'a> {
+    config: &'a FormatConfig,
+    tokens: Vec<FmtToken>,
+    newline: Vec<bool>, // token 之前是否换行
+    blank: Vec<bool>,   // token 之前是否保留一个空行
+}
+
+impl<'a> Formatter<'a> {
+    fn new(tokens: Vec<FmtToken>, config: &'a FormatConfig) -> Self {
+        let mut newline = vec![false; tokens.len()];
+        let mut blank = vec![false; tokens.len()];
+        for i in 1..tokens.len() {
+            newline[i] = tokens[i].line > tokens[i - 1].line;
+            blank[i] = tokens[i].line > tokens[i - 1].line + 1;
+        }
+
+        Self {
+            config,
+            tokens,
+            newline,
+            blank,
+        }
+    }
+
+    fn format(&mut self) -> String {
+        self.split_stmts();
+
+        // 每次折行都会减少超长的行, 最多折行 token 数量次
+        let mut lines = self.layout();
+        for _ in 0..self.tokens.len() {
+            if !self.wrap(&lines) {
+                break;
+            }
+            lines = self.layout();
+        }
+
+        let mut result = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 && self.blank[line.first] {
+                result.push('\n');
+            }
+            result.push_str(&line.text);
+            result.push('\n');
+        }
+        result
+    }
+
+    /**
+     * 多行块注释除第一行之外的内容原样输出
+     */
+    fn is_comment_continuation(&self, i: usize) -> bool {
+        i > 0
+            && self.tokens[i].token_type == TokenType::BlockComment
+            && self.tokens[i - 1].token_type == TokenType::BlockComment
+            && !self.tokens[i - 1].text.ends_with("*/")
+    }
+
+    /**
+     * 一行多个语句时按照 ; 拆分为多行, for 语句头部中的 ; 除外。拆分发生在 {} 中时, { 与 } 也各自换行
+     */
+    fn split_stmts(&mut self) {
+        let mut enclosing: Vec<usize> = Vec::new();
+        let mut for_depth: Option<usize> = None;
+
+        for i in 0..self.tokens.len() {
+            match self.tokens[i].token_type {
+                TokenType::For if for_depth.is_none() => for_depth = Some(enclosing.len()),
+                TokenType::LeftCurly => {
+                    if for_depth == Some(enclosing.len()) {
+                        for_depth = None;
+                    }
+                    enclosing.push(i);
+                }
+                TokenType::LeftParen | TokenType::LeftSquare => enclosing.push(i),
+                TokenType::RightParen | TokenType::RightSquare | TokenType::RightCurly => {
+                    enclosing.pop();
+                }
+                TokenType::StmtEof => {
+                    if for_depth.is_some() || i + 1 >= self.tokens.len() {
+                        continue;
+                    }
+
+                    let next = &self.tokens[i + 1];
+                    if self.newline[i + 1] || is_comment(&next.token_type) {
+                        continue;
+                    }
+                    self.newline[i + 1] = true;
+
+                    let Some(&open) = enclosing.last() else {
+                        continue;
+                    };
+                    if self.tokens[open].token_type != TokenType::LeftCurly {
+                        continue;
+                    }
+
+                    if let Some(first) = (open + 1..self.tokens.len()).find(|&k| !is_comment(&self.tokens[k].token_type)) {
+                        self.newline[first] = true;
+                    }
+                    if let Some(close) = self.tokens[open].pair {
+                        self.newline[close] = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /**
+     * 计算每一行的缩进与内容。行尾的括号产生一层缩进, 以对应右括号开头的行回退一层;
+     * 行尾仍未闭合的其他括号使后续行作为续行缩进一层
+     */
+    fn layout(&self) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut stack: Vec<bool> = Vec::new(); // 未闭合的括号是否产生了缩进
+        let len = self.tokens.len();
+
+        let mut first = 0;
+        while first < len {
+            let mut last = first;
+            while last + 1 < len && !self.newline[last + 1] {
+                last += 1;
+            }
+
+            let mut text = String::new();
+            if self.is_comment_continuation(first) {
+                text.push_str(&self.tokens[first].text);
+            } else {
+                let mut depth = stack.iter().filter(|indent| **indent).count();
+                if is_closer(&self.tokens[first].token_type) && stack.last() == Some(&true) {
+                    depth -= 1;
+                }
+                text.push_str(&" ".repeat(depth * self.config.indent_width));
+                text.push_str(&self.tokens[first].text);
+            }
+
+            let mut low = stack.len();
+            let mut indented = false;
+            for i in first..=last {
+                if i > first {
+                    if self.space(i) {
+                        text.push(' ');
+                    }
+                    text.push_str(&self.tokens[i].text);
+                }
+
+                let token_type = &self.tokens[i].token_type;
+                if is_opener(token_type) {
+                    let indent = (i + 1..=last).all(|k| is_comment(&self.tokens[k].token_type));
+                    indented |= indent;
+                    stack.push(indent);
+                } else if is_closer(token_type) {
+                    stack.pop();
+                    low = low.min(stack.len());
+                }
+            }
+
+            if !indented && stack.len() > low {
+                stack[low] = true;
+            }
+
+            lines.push(Line {
+                first,
+                last,
+                text: text.trim_end().to_string(),
+            });
+            first = last + 1;
+        }
+
+        lines
+    }
+
+    /**
+     * 超过 max_width 的行, 在最外层包含逗号的括号处折行, 每个元素独占一行, 右括号跟随最后一个元素
+     */
+    fn wrap(&mut self, lines: &[Line]) -> bool {
+        for line in lines {
+            if line.text.chars().count() <= self.config.max_width {
+                continue;
+            }
+
+            for open in line.first..=line.last {
+                if !is_opener(&self.tokens[open].token_type) {
+                    continue;
+                }
+                let Some(close) = self.tokens[open].pair else {
+                    continue;
+                };
+                if close > line.last {
+                    continue;
+                }
+
+                let mut commas = Vec::new();
+                let mut angle_depth = 0;
+                let mut i = open + 1;
+                while i < close {
+                    let token = &self.tokens[i];
+                    if is_opener(&token.token_type) {
+                        i = token.pair.unwrap_or(i) + 1;
+                        continue;
+                    }
+
+                    match token.role {
+                        Role::GenericOpen => angle_depth += 1,
+                        Role::GenericClose => angle_depth -= 1,
+                        _ => {}
+                    }
+                    if token.token_type == TokenType::Comma && angle_depth == 0 && i + 1 < close {
+                        commas.push(i);
+                    }
+                    i += 1;
+                }
+
+                if commas.is_empty() {
+                    continue;
+                }
+
+                self.newline[open + 1] = true;
+                for comma in commas {
+                    self.newline[comma + 1] = true;
+                }
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /**
+     * 同一行中 token i 与上一个 token 之间是否需要空格
+     */
+    fn space(&self, i: usize) -> bool {
+        let prev = &self.tokens[i - 1];
+        let cur = &self.tokens[i];
+        let gap = cur.start > prev.end; // 无法确定时保留原有的空格(最多一个)
+
+        if cur.token_type == TokenType::LineComment {
+            return true;
+        }
+        if is_comment(&prev.token_type) || cur.token_type == TokenType::BlockComment {
+            return gap;
+        }
+
+        // >> 由两个 > 组成, 不能被拆开
+        if prev.token_type == TokenType::RightAngle && matches!(cur.token_type, TokenType::RightAngle | TokenType::RightShift) && !gap {
+            return false;
+        }
+
+        // ** 由两个相邻的 * 组成, 拆开后会变为乘法与解引用
+        if prev.token_type == TokenType::Star && cur.token_type == TokenType::StarStar {
+            return false;
+        }
+
+        if matches!(
+            cur.token_type,
+            TokenType::Comma | TokenType::StmtEof | TokenType::RightParen | TokenType::RightSquare | TokenType::Dot | TokenType::DotDot
+        ) || matches!(cur.role, Role::Suffix | Role::GenericOpen | Role::GenericClose)
+        {
+            return false;
+        }
+        if matches!(
+            prev.token_type,
+            TokenType::LeftParen | TokenType::LeftSquare | TokenType::Dot | TokenType::DotDot | TokenType::Ellipsis | TokenType::At
+        ) || matches!(prev.role, Role::Unary | Role::GenericOpen)
+        {
+            return false;
+        }
+
+        if matches!(prev.token_type, TokenType::Comma | TokenType::StmtEof) || prev.role == Role::Binary || cur.role == Role::Binary {
+            return true;
+        }
+
+        // fn main():int
+        if cur.token_type == TokenType::Colon {
+            return false;
+        }
+
+        // call(a), list[0]
+        let call = matches!(
+            prev.token_type,
+            TokenType::Ident | TokenType::MacroIdent | TokenType::Fn | TokenType::RightParen | TokenType::RightSquare
+        ) || prev.role == Role::GenericClose
+            || prev.token_type.is_type_keyword();
+        if (cur.token_type == TokenType::LeftParen && call) || (cur.token_type == TokenType::LeftSquare && is_operand_end(&prev.token_type, prev.role)) {
+            return false;
+        }
+        if cur.role == Role::Block {
+            return true;
+        }
+
+        if prev.token_type == TokenType::Colon {
+            let before = i.checked_sub(2).map(|k| &self.tokens[k].token_type);
+            return before != Some(&TokenType::RightParen) && gap;
+        }
+
+        if is_word(&cur.token_type) && (is_word(&prev.token_type) || matches!(prev.token_type, TokenType::RightCurly) || prev.role == Role::GenericClose) {
+            return true;
+        }
+
+        gap
+    }
+}
@@ -0,0 +1,141 @@
+use super::common::{ImportStmt, Span};
+use super::import_search_paths;
+use super::lexer::TokenType;
+use super::reference::{ReferenceIndex, ReferenceTarget};
+use super::signature_help::{find_macro, fn_signature, macro_signature};
+use super::symbol::{SymbolKind, SymbolTable};
+use crate::project::Module;
+use crate::utils::range_of;
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
+use std::path::Path;
+
+fn markdown(value: String) -> HoverContents {
+    HoverContents::Markup(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+/**
+ * textDocument/hover
+ */
+pub fn hover(m: &Module, symbol_table: &SymbolTable, index: &ReferenceIndex, offset: usize) -> Option<Hover> {
+    import_hover(m, offset)
+        .or_else(|| macro_hover(m, offset))
+        .or_else(|| symbol_hover(m, symbol_table, index, offset))
+}
+
+/**
+ * 声明的签名与文档注释, 其他 module 中的符号通过共享的 symbol table 获取声明, 不需要重新打开对应的文件
+ */
+fn symbol_hover(m: &Module, symbol_table: &SymbolTable, index: &ReferenceIndex, offset: usize) -> Option<Hover> {
+    let occurrence = index.occurrences.iter().find(|o| o.start <= offset && offset <= o.end)?;
+    let symbol = match &occurrence.target {
+        ReferenceTarget::Symbol(symbol_id) => symbol_table.find_symbol(*symbol_id)?,
+        ReferenceTarget::TypeAlias(global_ident) => symbol_table.find_global_symbol(global_ident)?,
+        ReferenceTarget::Field(..) => return None,
+    };
+
+    let (signature, doc) = match &symbol.kind {
+        SymbolKind::Fn(fndef_mutex) => {
+            let fndef = fndef_mutex.read().unwrap();
+            (fn_signature(&fndef, 0).label, fndef.doc.clone())
+        }
+        SymbolKind::Var(var_decl_mutex) => {
+            let var_decl = var_decl_mutex.read().unwrap();
+            (format!("{} {}", var_decl.type_, var_decl.ident), var_decl.doc.clone())
+        }
+        SymbolKind::TypeAlias(type_alias_mutex) => {
+            let type_alias = type_alias_mutex.read().unwrap();
+            let mut signature = format!("type {}", type_alias.ident);
+            if !type_alias.params.is_empty() {
+                let params: Vec<&str> = type_alias.params.iter().map(|p| p.ident.as_str()).collect();
+                signature.push_str(&format!("<{}>", params.join(", ")));
+            }
+            (signature, type_alias.doc.clone())
+        }
+    };
+
+    let mut value = format!("```n\n{}\n```", signature);
+    if let Some(doc) = doc {
+        value.push_str(&format!("\n\n---\n\n{}", doc));
+    }
+
+    Some(Hover {
+        contents: markdown(value),
+        range: range_of(occurrence.start, occurrence.end, &m.line_index),
+    })
+}
+
+/**
+ * builtin 宏展示签名与说明, 未定义的宏展示对应的诊断信息
+ */
+fn macro_hover(m: &Module, offset: usize) -> Option<Hover> {
+    let token = m.token_db.iter().find(|t| t.token_type == TokenType::MacroIdent && t.span.touches(offset))?;
+
+    let value = match find_macro(&token.literal) {
+        Some(signature) => format!("```n\n{}\n```\n\n{}", macro_signature(signature, 0).label, signature.description),
+        None => {
+            let mut value = format!("unknown macro `@{}`", token.literal);
+            if let Some(e) = m
+                .analyzer_errors
+                .iter()
+                .find(|e| e.span.start == token.span.start && e.span.end == token.span.end)
+            {
+                value.push_str(&format!("\n\n{}", e.message));
+            }
+            value
+        }
+    };
+
+    Some(Hover {
+        contents: markdown(value),
+        range: range_of(token.span.start as usize, token.span.end as usize, &m.line_index),
+    })
+}
+
+/**
+ * 光标位于 import 所在行的任意位置时, 展示 import 的解析结果, 解析失败时展示尝试过的路径
+ */
+fn import_hover(m: &Module, offset: usize) -> Option<Hover> {
+    let line = m.rope.try_char_to_line(offset).ok()?;
+    let import = m.dependencies.iter().find(|import| m.rope.try_char_to_line(import.start).ok() == Some(line))?;
+
+    let source = m.rope.line(line).to_string();
+    let mut value = format!("```n\n{}\n```\n\n---\n\n", source.trim_end());
+    value.push_str(&import_detail(m, import));
+
+    Some(Hover {
+        contents: markdown(value),
+        range: range_of(import.start, import.end, &m.line_index),
+    })
+}
+
+fn import_detail(m: &Module, import: &ImportStmt) -> String {
+    let resolved = !import.full_path.is_empty() && Path::new(&import.full_path).is_file();
+    if !resolved {
+        let reason = m
+            .analyzer_errors
+            .iter()
+            .find(|e| e.span == Span::new(import.start, import.end))
+            .map(|e| e.message.clone())
+            .unwrap_or_else(|| "import cannot be resolved".to_string());
+
+        let mut detail = format!("**unresolved**: {}\n\nsearched:\n", reason);
+        for path in import_search_paths(m, import) {
+            detail.push_str(&format!("- `{}`\n", path));
+        }
+        return detail;
+    }
+
+    let mut lines = vec![format!("path: `{}`", import.full_path)];
+    if !import.module_ident.is_empty() {
+        lines.push(format!("module: `{}`", import.module_ident));
+    }
+    lines.push(format!("as: `{}`", import.as_name));
+    if let Some(package_conf) = &import.package_conf {
+        lines.push(format!("package: `{}` {}", package_conf.package_data.name, package_conf.package_data.version));
+    }
+
+    lines.join("  \n")
+}
@@ -0,0 +1,265 @@
+use super::common::{AstCall, AstNode, Expr, Stmt, VarDeclExpr};
+use super::lexer::{Token, TokenType};
+use super::symbol::{SymbolKind, SymbolTable};
+use super::type_utils::render_truncated;
+use super::walk::{walk_body, walk_node, walk_stmts};
+use crate::project::Module;
+use crate::utils::offset_to_position;
+use lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range, TextEdit};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone)]
+pub struct InlayHintConfig {
+    pub type_hints: bool,
+    pub parameter_hints: bool, // 在 call 的实参之前显示对应的 param 名称
+    pub max_length: usize,     // 超过该长度的类型会被截断
+}
+
+impl Default for InlayHintConfig {
+    fn default() -> Self {
+        Self {
+            type_hints: true,
+            parameter_hints: true,
+            max_length: 30,
+        }
+    }
+}
+
+struct InlayHintCollector<'a> {
+    m: &'a Module,
+    symbol_table: &'a SymbolTable,
+    tokens: Vec<&'a Token>,
+    config: &'a InlayHintConfig,
+    hints: Vec<InlayHint>,
+}
+
+impl<'a> InlayHintCollector<'a> {
+    fn token_before(&self, offset: usize) -> Option<&'a Token> {
+        let index = self.tokens.partition_point(|t| (t.span.start as usize) < offset);
+        if index == 0 || index >= self.tokens.len() || self.tokens[index].span.start as usize != offset {
+            return None;
+        }
+        Some(self.tokens[index - 1])
+    }
+
+    /**
+     * var a = 1 -> var a: int = 1, 接受 hint 时将 var 替换为推导出的类型
+     * for 迭代变量与 tuple destr 无法声明类型，所以不附带 text edit
+     */
+    fn var_decl(&mut self, var_decl_mutex: &Arc<RwLock<VarDeclExpr>>, var_token: Option<&Token>) {
+        let var_decl = var_decl_mutex.read().unwrap();
+        if var_decl.type_.kind.is_unknown() || var_decl.type_.err {
+            return;
+        }
+
+        let Some(position) = offset_to_position(var_decl.symbol_end, &self.m.line_index) else {
+            return;
+        };
+
+        let type_string = var_decl.type_.to_string();
+        let text_edits = var_token.and_then(|token| {
+            let start = offset_to_position(token.span.start as usize, &self.m.line_index)?;
+            let end = offset_to_position(token.span.end as usize, &self.m.line_index)?;
+            Some(vec![TextEdit {
+                range: Range::new(start, end),
+                new_text: type_string.clone(),
+            }])
+        });
+
+        self.hints.push(InlayHint {
+            position,
+            label: InlayHintLabel::String(format!(": {}", render_truncated(&var_decl.type_, self.config.max_length))),
+            kind: Some(InlayHintKind::TYPE),
+            text_edits,
+            tooltip: None,
+            padding_left: None,
+            padding_right: None,
+            data: None,
+        });
+    }
+
+    /**
+     * resize(10, 20, true) -> resize(width: 10, height: 20, animate: true)
+     * impl fn 的 self 由 typesys 改写为第一个实参, 其位置在 callee 之前, 只占用 param 而不需要 hint
+     */
+    fn call(&mut self, call: &AstCall) {
+        let AstNode::Ident(_, Some(symbol_id)) = &call.left.node else {
+            return;
+        };
+        let Some(SymbolKind::Fn(fndef_mutex)) = self.symbol_table.find_symbol(*symbol_id).map(|s| &s.kind) else {
+            return;
+        };
+        // 递归调用时 fndef 可能已经被锁定
+        let Ok(fndef) = fndef_mutex.try_read() else {
+            return;
+        };
+
+        let args: Vec<(usize, &Box<Expr>)> = call.args.iter().enumerate().filter(|(_, arg)| arg.span.start >= call.left.span.end).collect();
+        if args.len() < 2 {
+            return;
+        }
+
+        for (index, arg) in args {
+            // rest param 与 spread 实参都不是与单个 param 一一对应
+            if (fndef.rest_param && index + 1 >= fndef.params.len()) || (call.spread && index + 1 == call.args.len()) {
+                break;
+            }
+            let Some(param_mutex) = fndef.params.get(index) else {
+                break;
+            };
+            let ident = param_mutex.read().unwrap().ident;
+
+            // 同名的 ident 实参已经足够清晰, global ident 在 semantic 中被改写为 module.ident
+            let same_name = match &arg.node {
+                AstNode::Ident(arg_ident, _) | AstNode::EnvAccess(_, arg_ident, _) => arg_ident.rsplit('.').next() == Some(ident.as_str()),
+                _ => false,
+            };
+            if same_name {
+                continue;
+            }
+
+            let Some(position) = offset_to_position(arg.span.start as usize, &self.m.line_index) else {
+                continue;
+            };
+            self.hints.push(InlayHint {
+                position,
+                label: InlayHintLabel::String(format!("{}:", ident)),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+    }
+
+    fn tuple_destr(&mut self, elements: &[Box<Expr>]) {
+        for element in elements {
+            match &element.node {
+                AstNode::VarDecl(var_decl_mutex) => self.var_decl(var_decl_mutex, None),
+                AstNode::TupleDestr(elements) => self.tuple_destr(elements),
+                _ => {}
+            }
+        }
+    }
+
+    /**
+     * fn body 通过 module.all_fndefs 单独处理，这里不需要进入 closure
+     */
+    fn body(&mut self, body: &[Box<Stmt>]) {
+        for stmt in body {
+            self.node(&stmt.node);
+        }
+    }
+
+    fn node(&mut self, node: &AstNode) {
+        match node {
+            AstNode::VarDef(var_decl_mutex, right) => {
+                // 显式声明类型的变量不需要 hint
+                let symbol_start = var_decl_mutex.read().unwrap().symbol_start;
+                if let Some(token) = self.token_before(symbol_start).filter(|t| t.token_type == TokenType::Var) {
+                    self.var_decl(var_decl_mutex, Some(token));
+                }
+                self.expr(right);
+            }
+            AstNode::VarTupleDestr(elements, right) => {
+                self.tuple_destr(elements);
+                self.expr(right);
+            }
+            AstNode::ForIterator(_, first, second, _, body) => {
+                self.var_decl(first, None);
+                if let Some(second) = second {
+                    self.var_decl(second, None);
+                }
+                self.body(body);
+            }
+            AstNode::If(_, consequent, alternate) => {
+                self.body(consequent);
+                self.body(alternate);
+            }
+            AstNode::ForCond(_, body) | AstNode::ForTradition(_, _, _, body) => self.body(body),
+            AstNode::TryCatch(_, _, body) | AstNode::Catch(_, _, body) => self.body(body),
+            AstNode::Match(_, cases, _) => {
+                for case in cases {
+                    self.body(&case.handle_body);
+                }
+            }
+            AstNode::Fake(expr) | AstNode::Do(expr) | AstNode::Assign(_, expr) => self.expr(expr),
+            _ => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.node {
+            AstNode::Match(_, cases, _) => {
+                for case in cases {
+                    self.body(&case.handle_body);
+                }
+            }
+            AstNode::Catch(_, _, body) => self.body(body),
+            _ => {}
+        }
+    }
+}
+
+/**
+ * 基于 typesys 推导出的类型为未声明类型的变量生成 inlay hint, 并在 call 的实参之前显示 param 名称
+ *
+ * param 名称只在 callee 可以通过 symbol table 解析为 fn def 时显示
+ */
+pub fn inlay_hints(m: &Module, symbol_table: &SymbolTable, config: &InlayHintConfig) -> Vec<InlayHint> {
+    if !config.type_hints && !config.parameter_hints {
+        return Vec::new();
+    }
+
+    let tokens = m
+        .token_indexes
+        .iter()
+        .map(|i| &m.token_db[*i])
+        .filter(|t| !matches!(t.token_type, TokenType::StmtEof | TokenType::Eof))
+        .collect();
+
+    let mut collector = InlayHintCollector {
+        m,
+        symbol_table,
+        tokens,
+        config,
+        hints: Vec::new(),
+    };
+
+    if config.type_hints {
+        // semantic 之后 global var def 从 stmts 中移动到了 global_vardefs
+        for node in &m.global_vardefs {
+            collector.node(node);
+        }
+
+        for fndef_mutex in &m.all_fndefs {
+            let fndef = fndef_mutex.read().unwrap();
+            collector.body(&fndef.body);
+        }
+    }
+
+    if config.parameter_hints {
+        // walk 会进入 fn 与 closure 的 body, 单独作为 stmt 的 call 不会作为 expr 被访问
+        let mut on_expr = |expr: &Expr| {
+            if let AstNode::Call(call) = &expr.node {
+                collector.call(call);
+            }
+        };
+        for node in &m.global_vardefs {
+            walk_node(node, &mut on_expr);
+        }
+        walk_body(&m.stmts, &mut on_expr);
+
+        walk_stmts(&m.stmts, &mut |stmt| {
+            if let AstNode::Call(call) = &stmt.node {
+                collector.call(call);
+            }
+        });
+    }
+
+    let mut hints = collector.hints;
+    hints.sort_by_key(|h| (h.position.line, h.position.character));
+    hints
+}
@@ -0,0 +1,181 @@
+use lazy_static::lazy_static;
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::RwLock;
+
+/**
+ * 驻留之后的文本, 只是全局 Interner 中的下标, 可以直接 copy, 比较与 hash 只需要比较一个 u32
+ *
+ * 通过 resolve 取回文本, 与 symbol table 中的 Symbol 不同, Atom 只代表一段文本, 不包含定义的位置与类型
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Atom(u32);
+
+/**
+ * 只有标识符, 关键字, 运算符与 label 这类大量重复的文本才会驻留, 字符串/数字字面量与注释不进入 interner
+ *
+ * 文本只增不减, 规模取决于出现过的不同标识符的数量而不是源码的大小
+ * 已经驻留的文本只需要读锁, 第一次出现的文本才需要写锁, Lexer 另外持有本地缓存, 同一个文件中重复的标识符不会再访问全局锁
+ */
+struct Interner {
+    atoms: HashMap<&'static str, Atom>,
+    texts: Vec<&'static str>,
+}
+
+lazy_static! {
+    static ref INTERNER: RwLock<Interner> = RwLock::new(Interner {
+        atoms: HashMap::from([("", Atom::EMPTY)]),
+        texts: vec![""],
+    });
+}
+
+pub fn intern(text: &str) -> Atom {
+    if let Some(atom) = INTERNER.read().unwrap().atoms.get(text) {
+        return *atom;
+    }
+
+    let mut interner = INTERNER.write().unwrap();
+    // 获取写锁期间其他线程可能已经驻留了相同的文本
+    if let Some(atom) = interner.atoms.get(text) {
+        return *atom;
+    }
+
+    let atom = Atom(interner.texts.len() as u32);
+    let text: &'static str = Box::leak(Box::from(text));
+    interner.texts.push(text);
+    interner.atoms.insert(text, atom);
+    atom
+}
+
+pub fn resolve(atom: Atom) -> &'static str {
+    INTERNER.read().unwrap().texts[atom.0 as usize]
+}
+
+// 已经驻留的文本数量
+pub fn interned_count() -> usize {
+    INTERNER.read().unwrap().texts.len()
+}
+
+impl Atom {
+    pub const EMPTY: Atom = Atom(0);
+
+    pub fn as_str(&self) -> &'static str {
+        resolve(*self)
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Atom {
+    fn default() -> Self {
+        Atom::EMPTY
+    }
+}
+
+impl Deref for Atom {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Atom {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(text: &str) -> Self {
+        intern(text)
+    }
+}
+
+impl From<&String> for Atom {
+    fn from(text: &String) -> Self {
+        intern(text)
+    }
+}
+
+impl From<String> for Atom {
+    fn from(text: String) -> Self {
+        intern(&text)
+    }
+}
+
+impl From<Atom> for String {
+    fn from(atom: Atom) -> Self {
+        atom.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Atom {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Atom {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Atom {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Atom> for String {
+    fn eq(&self, other: &Atom) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Atom> for &str {
+    fn eq(&self, other: &Atom) -> bool {
+        *self == other.as_str()
+    }
+}
+
+// 按照文本排序, 与使用 String 时的顺序一致, 下标只代表驻留的先后
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Atom {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.0 == other.0 {
+            return Ordering::Equal;
+        }
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// 与 String 的 Debug 输出一致
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Atom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
@@ -1,39 +1,115 @@
-use super::common::AnalyzerError;
+use super::common::{Diagnostic, DiagnosticPhase, Span};
+use super::intern::{intern, Atom};
+use lsp_types::{SemanticTokenModifier, SemanticTokenType};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
 use strum_macros::Display;
-use tower_lsp::lsp_types::SemanticTokenType;
 
 pub const LEGEND_TYPE: &[SemanticTokenType] = &[
-    SemanticTokenType::FUNCTION, // fn ident
-    SemanticTokenType::VARIABLE, // variable ident
-    SemanticTokenType::STRING, // string literal
-    SemanticTokenType::COMMENT, // comment
-    SemanticTokenType::NUMBER, // number literal
-    SemanticTokenType::KEYWORD, //  所有的语法关键字，比如 var, if, then, else, fn ...
-    SemanticTokenType::OPERATOR, // 运算符
+    SemanticTokenType::FUNCTION,  // fn ident
+    SemanticTokenType::VARIABLE,  // variable ident
+    SemanticTokenType::STRING,    // string literal
+    SemanticTokenType::COMMENT,   // comment
+    SemanticTokenType::NUMBER,    // number literal
+    SemanticTokenType::KEYWORD,   //  所有的语法关键字，比如 var, if, then, else, fn ...
+    SemanticTokenType::OPERATOR,  // 运算符
     SemanticTokenType::PARAMETER, // function parameter ident
-    SemanticTokenType::TYPE,          // 用于类型名称（如 int, float, string 等）
-    SemanticTokenType::MACRO,         // 用于宏标识符
-    SemanticTokenType::PROPERTY,      // struct property ident
-    SemanticTokenType::NAMESPACE,     // package ident
+    SemanticTokenType::TYPE,      // 用于类型名称（如 int, float, string 等）
+    SemanticTokenType::MACRO,     // 用于宏标识符
+    SemanticTokenType::PROPERTY,  // struct property ident
+    SemanticTokenType::NAMESPACE, // package ident
 ];
 
 pub fn semantic_token_type_index(token_type: SemanticTokenType) -> usize {
-    if token_type == SemanticTokenType::FUNCTION { return 0; }
-    if token_type == SemanticTokenType::VARIABLE { return 1; }
-    if token_type == SemanticTokenType::STRING { return 2; }
-    if token_type == SemanticTokenType::COMMENT { return 3; }
-    if token_type == SemanticTokenType::NUMBER { return 4; }
-    if token_type == SemanticTokenType::KEYWORD { return 5; }
-    if token_type == SemanticTokenType::OPERATOR { return 6; }
-    if token_type == SemanticTokenType::PARAMETER { return 7; }
-    if token_type == SemanticTokenType::TYPE { return 8; }
-    if token_type == SemanticTokenType::MACRO { return 9; }
-    if token_type == SemanticTokenType::PROPERTY { return 10; }
-    if token_type == SemanticTokenType::NAMESPACE { return 11; }
+    if token_type == SemanticTokenType::FUNCTION {
+        return 0;
+    }
+    if token_type == SemanticTokenType::VARIABLE {
+        return 1;
+    }
+    if token_type == SemanticTokenType::STRING {
+        return 2;
+    }
+    if token_type == SemanticTokenType::COMMENT {
+        return 3;
+    }
+    if token_type == SemanticTokenType::NUMBER {
+        return 4;
+    }
+    if token_type == SemanticTokenType::KEYWORD {
+        return 5;
+    }
+    if token_type == SemanticTokenType::OPERATOR {
+        return 6;
+    }
+    if token_type == SemanticTokenType::PARAMETER {
+        return 7;
+    }
+    if token_type == SemanticTokenType::TYPE {
+        return 8;
+    }
+    if token_type == SemanticTokenType::MACRO {
+        return 9;
+    }
+    if token_type == SemanticTokenType::PROPERTY {
+        return 10;
+    }
+    if token_type == SemanticTokenType::NAMESPACE {
+        return 11;
+    }
 
     panic!("unknown semantic token type: {:?}", token_type)
 }
 
+pub const LEGEND_MODIFIER: &[SemanticTokenModifier] = &[
+    SemanticTokenModifier::DECLARATION,     // 变量, 函数, 类型的定义点
+    SemanticTokenModifier::READONLY,        // for 迭代变量
+    SemanticTokenModifier::DEFAULT_LIBRARY, // builtin 符号，比如 println
+    SemanticTokenModifier::DEPRECATED,      // #deprecated label
+];
+
+// token_modifiers_bitset 中对应的 bit
+pub fn semantic_token_modifier_bit(modifier: SemanticTokenModifier) -> u32 {
+    match LEGEND_MODIFIER.iter().position(|m| *m == modifier) {
+        Some(index) => 1 << index,
+        None => panic!("unknown semantic token modifier: {:?}", modifier),
+    }
+}
+
+/**
+ * offset(char offset) 所在的行号与列号(均从 1 开始)以及该行的源码(不包含换行符), 用于在错误信息中展示上下文
+ *
+ * offset 超出源码长度时返回最后一行的末尾
+ */
+pub fn source_line(source: &str, offset: usize) -> (u32, u32, &str) {
+    let mut line = 1;
+    let mut line_start = 0; // byte offset
+    let mut col = 1;
+    for (index, (byte_index, c)) in source.char_indices().enumerate() {
+        if index == offset {
+            break;
+        }
+        if c == '\n' {
+            line += 1;
+            line_start = byte_index + 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+
+    let line_end = source[line_start..].find('\n').map_or(source.len(), |index| line_start + index);
+    (line, col, source[line_start..line_end].trim_end_matches('\r'))
+}
+
+/**
+ * 标注 line 中第 column 个字符(从 0 开始)时 ^ 之前的缩进, tab 保留为 tab, 使得标注在任意 tab 宽度下都与源码对齐
+ */
+pub fn caret_indent(line: &str, column: usize) -> String {
+    line.chars().take(column).map(|c| if c == '\t' { '\t' } else { ' ' }).collect()
+}
+
 #[derive(Debug, Clone, PartialEq, Display)]
 pub enum TokenType {
     #[strum(serialize = "unknown")]
@@ -68,6 +144,8 @@ pub enum TokenType {
     Plus,
     #[strum(serialize = "...")]
     Ellipsis,
+    #[strum(serialize = "..")]
+    DotDot,
     #[strum(serialize = ":")]
     Colon,
     #[strum(serialize = ";")]
@@ -84,6 +162,8 @@ pub enum TokenType {
     Question,
     #[strum(serialize = "->")]
     RightArrow,
+    #[strum(serialize = "=>")]
+    FatArrow,
 
     // 一到两个字符的标记
     #[strum(serialize = "!")]
@@ -140,6 +220,8 @@ pub enum TokenType {
     LeftShift,
     #[strum(serialize = ">>")]
     RightShift,
+    #[strum(serialize = "**")]
+    StarStar, // 由 parser 合并两个相邻的 * 生成, 与 >> 类似
 
     // 字面量
     #[strum(serialize = "ident_literal")]
@@ -148,6 +230,8 @@ pub enum TokenType {
     Pound,
     #[strum(serialize = "macro_ident")]
     MacroIdent,
+    #[strum(serialize = "@")]
+    At,
     #[strum(serialize = "label")]
     Label,
     #[strum(serialize = "string_literal")]
@@ -204,6 +288,8 @@ pub enum TokenType {
     Set,
     #[strum(serialize = "chan")]
     Chan,
+    #[strum(serialize = "promise")]
+    Promise,
 
     // 关键字
     #[strum(serialize = "ptr")]
@@ -250,6 +336,8 @@ pub enum TokenType {
     Var,
     #[strum(serialize = "let")]
     Let,
+    #[strum(serialize = "do")]
+    Do,
     #[strum(serialize = "is")]
     Is,
     #[strum(serialize = "sizeof")]
@@ -266,6 +354,8 @@ pub enum TokenType {
     Return,
     #[strum(serialize = "go")]
     Go,
+    #[strum(serialize = "async")]
+    Async,
     #[strum(serialize = ";")]
     StmtEof,
     #[strum(serialize = "\0")]
@@ -279,6 +369,106 @@ pub enum TokenType {
     Eof,
 }
 
+impl TokenType {
+    /**
+     * 保留关键字(包括内置类型名称), true/false/null 属于字面量
+     */
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::String
+                | TokenType::Bool
+                | TokenType::Float
+                | TokenType::Int
+                | TokenType::Uint
+                | TokenType::U8
+                | TokenType::U16
+                | TokenType::U32
+                | TokenType::U64
+                | TokenType::I8
+                | TokenType::I16
+                | TokenType::I32
+                | TokenType::I64
+                | TokenType::F32
+                | TokenType::F64
+                | TokenType::New
+                | TokenType::Arr
+                | TokenType::Vec
+                | TokenType::Map
+                | TokenType::Tup
+                | TokenType::Set
+                | TokenType::Chan
+                | TokenType::Promise
+                | TokenType::Ptr
+                | TokenType::Type
+                | TokenType::Void
+                | TokenType::Any
+                | TokenType::Struct
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::Catch
+                | TokenType::Match
+                | TokenType::Select
+                | TokenType::Continue
+                | TokenType::Break
+                | TokenType::For
+                | TokenType::In
+                | TokenType::If
+                | TokenType::Else
+                | TokenType::ElseIf
+                | TokenType::Var
+                | TokenType::Let
+                | TokenType::Do
+                | TokenType::Is
+                | TokenType::As
+                | TokenType::Fn
+                | TokenType::Import
+                | TokenType::Return
+                | TokenType::Go
+                | TokenType::Async
+        )
+    }
+
+    // 内置类型名称
+    pub fn is_type_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::String
+                | TokenType::Bool
+                | TokenType::Float
+                | TokenType::Int
+                | TokenType::Uint
+                | TokenType::U8
+                | TokenType::U16
+                | TokenType::U32
+                | TokenType::U64
+                | TokenType::I8
+                | TokenType::I16
+                | TokenType::I32
+                | TokenType::I64
+                | TokenType::F32
+                | TokenType::F64
+                | TokenType::Void
+                | TokenType::Any
+                | TokenType::Arr
+                | TokenType::Vec
+                | TokenType::Map
+                | TokenType::Tup
+                | TokenType::Set
+                | TokenType::Chan
+                | TokenType::Promise
+                | TokenType::Ptr
+        )
+    }
+
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            TokenType::IntLiteral | TokenType::FloatLiteral | TokenType::StringLiteral | TokenType::True | TokenType::False | TokenType::Null
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum LeftAngleType {
     FnArgs,
@@ -290,15 +480,117 @@ pub enum LeftAngleType {
 pub struct Token {
     pub token_type: TokenType,
     pub semantic_token_type: usize,
-    pub literal: String,
+    pub semantic_token_modifiers: u32, // LEGEND_MODIFIER bitset
+    pub literal: TokenText,
     pub line: usize,
-    pub start: usize, // start index
-    pub end: usize,   // end index
+    pub span: Span,
     pub length: usize,
 }
 
+/**
+ * token 的文本, 标识符, 关键字, 运算符与 label 驻留为 Atom
+ * 字符串/数字字面量与注释几乎不会重复, 直接从源码中复制一份, 不进入 interner
+ */
+#[derive(Clone)]
+pub enum TokenText {
+    Atom(Atom),
+    Text(String),
+}
+
+impl TokenText {
+    pub fn as_str(&self) -> &str {
+        match self {
+            TokenText::Atom(atom) => atom.as_str(),
+            TokenText::Text(text) => text,
+        }
+    }
+
+    // ast 中的标识符使用 Atom, 字面量 token 在这里才会驻留
+    pub fn atom(&self) -> Atom {
+        match self {
+            TokenText::Atom(atom) => *atom,
+            TokenText::Text(text) => intern(text),
+        }
+    }
+}
+
+impl Deref for TokenText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<Atom> for TokenText {
+    fn from(atom: Atom) -> Self {
+        TokenText::Atom(atom)
+    }
+}
+
+impl From<String> for TokenText {
+    fn from(text: String) -> Self {
+        TokenText::Text(text)
+    }
+}
+
+impl PartialEq for TokenText {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TokenText::Atom(a), TokenText::Atom(b)) => a == b,
+            _ => self.as_str() == other.as_str(),
+        }
+    }
+}
+
+impl PartialEq<str> for TokenText {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for TokenText {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for TokenText {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<TokenText> for String {
+    fn eq(&self, other: &TokenText) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Atom> for TokenText {
+    fn eq(&self, other: &Atom) -> bool {
+        match self {
+            TokenText::Atom(atom) => atom == other,
+            TokenText::Text(text) => text == other,
+        }
+    }
+}
+
+impl fmt::Display for TokenText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for TokenText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
 impl Token {
-    pub fn new(token_type: TokenType, literal: String, start: usize, end: usize, line: usize) -> Self {
+    pub fn new(token_type: TokenType, literal: impl Into<TokenText>, start: usize, end: usize, line: usize) -> Self {
+        let literal = literal.into();
         let mut length = literal.chars().count(); // vscode lsp 使用 utf16 编码 长度
 
         // string 的 length + 2  携带上 字符串的开始和结束符号
@@ -312,10 +604,10 @@ impl Token {
         Self {
             token_type,
             semantic_token_type,
+            semantic_token_modifiers: 0,
             literal,
             line,
-            start,
-            end,
+            span: Span::new(start, end),
             length,
         }
     }
@@ -351,6 +643,7 @@ impl Token {
             | TokenType::Tup
             | TokenType::Set
             | TokenType::Chan
+            | TokenType::Promise
             | TokenType::Ptr => SemanticTokenType::TYPE,
             // 所有关键字
             TokenType::If
@@ -362,6 +655,7 @@ impl Token {
             | TokenType::Continue
             | TokenType::Return
             | TokenType::Let
+            | TokenType::Do
             | TokenType::Var
             | TokenType::Fn
             | TokenType::Import
@@ -378,7 +672,8 @@ impl Token {
             | TokenType::Is
             | TokenType::As
             | TokenType::New
-            | TokenType::Go => SemanticTokenType::KEYWORD,
+            | TokenType::Go
+            | TokenType::Async => SemanticTokenType::KEYWORD,
             // 默认情况下标识符被视为变量
             TokenType::Ident => SemanticTokenType::VARIABLE,
             // 其他所有操作符
@@ -405,21 +700,35 @@ impl Token {
     pub fn debug(&self) -> String {
         format!(
             "Token {{ type: {:?}, literal: '{}', start: {}, end: {}, length: {} }}",
-            self.token_type, self.literal, self.start, self.end, self.length
+            self.token_type, self.literal, self.span.start, self.span.end, self.length
         )
     }
 }
 
+// 先查 lexer 的本地缓存, 未命中时才访问全局 interner
+fn cached_intern(atoms: &mut HashMap<&'static str, Atom>, text: &str) -> Atom {
+    if let Some(atom) = atoms.get(text) {
+        return *atom;
+    }
+    let atom = intern(text);
+    atoms.insert(atom.as_str(), atom);
+    atom
+}
+
 #[derive(Debug)]
 pub struct Lexer {
     source: Vec<char>,
+    text: String, // 与 source 相同的源码, 通过 byte_offset/byte_guard 切片后直接驻留
     offset: usize,
     guard: usize, // char
+    byte_offset: usize,
+    byte_guard: usize,
     length: usize,
     line: usize,
-    errors: Vec<AnalyzerError>,
-    token_db: Vec<Token>,       // 所有的 token 都注册在这里,
-    syntax_indexes: Vec<usize>, //  存储 tokens 索引
+    errors: Vec<Diagnostic>,
+    token_db: Vec<Token>,               // 所有的 token 都注册在这里,
+    syntax_indexes: Vec<usize>,         //  存储 tokens 索引
+    atoms: HashMap<&'static str, Atom>, // 本地缓存, 重复的标识符不需要访问全局 interner 的锁
 }
 
 impl Lexer {
@@ -429,11 +738,15 @@ impl Lexer {
             offset: 0,
             guard: 0,
             source: source_chars,
+            text: source_string,
+            byte_offset: 0,
+            byte_guard: 0,
             length: 0,
             line: 1,
             errors: Vec::new(),
             token_db: Vec::new(),
             syntax_indexes: Vec::new(),
+            atoms: HashMap::new(),
         }
     }
 
@@ -448,7 +761,11 @@ impl Lexer {
         self.syntax_indexes.push(index);
     }
 
-    pub fn scan(&mut self) -> (Vec<Token>, Vec<usize>, Vec<AnalyzerError>) {
+    fn error(&mut self, span: Span, message: impl Into<String>) {
+        self.errors.push(Diagnostic::new(span, message).with_phase(DiagnosticPhase::Lexer));
+    }
+
+    pub fn scan(&mut self) -> (Vec<Token>, Vec<usize>, Vec<Diagnostic>) {
         while !self.at_eof() {
             let line = self.line;
             self.skip_space();
@@ -463,10 +780,9 @@ impl Lexer {
                 // has newline 后如果上一个字符可以接受语句结束符， 则在上一个字符的后面插入语句结束符(不用考虑会 影响 white_token 和 comment token, 这些 token 不会进入到 sytax_indexes 中)
                 // white_token 和 comment_token 在 token_db 中延顺后移即可
                 if self.need_stmt_end(prev_token) {
-                    self.insert(
-                        last_index + 1,
-                        Token::new(TokenType::StmtEof, ";".to_string(), prev_token.end, prev_token.end + 1, prev_token.line),
-                    );
+                    let (end, line) = (prev_token.span.end as usize, prev_token.line);
+                    let literal = cached_intern(&mut self.atoms, ";");
+                    self.insert(last_index + 1, Token::new(TokenType::StmtEof, literal, end, end + 1, line));
                 }
             }
 
@@ -475,21 +791,23 @@ impl Lexer {
         }
 
         self.offset = self.guard;
-        self.push(Token::new(TokenType::Eof, "EOF".to_string(), self.offset, self.offset + 1, self.line));
+        self.byte_offset = self.byte_guard;
+        let literal = cached_intern(&mut self.atoms, "EOF");
+        self.push(Token::new(TokenType::Eof, literal, self.offset, self.offset + 1, self.line));
 
         (self.token_db.clone(), self.syntax_indexes.clone(), self.errors.clone())
     }
 
-    fn ident_advance(&mut self) -> String {
+    fn ident_advance(&mut self) {
         while !self.at_eof() && (self.is_alpha(self.peek_guard()) || self.is_number(self.peek_guard())) {
             self.guard_advance();
         }
-        self.gen_word()
     }
 
     fn ident(&self, word: &str, _: usize) -> TokenType {
         match word {
             "as" => TokenType::As,
+            "async" => TokenType::Async,
             "any" => TokenType::Any,
             "arr" => TokenType::Arr,
             "bool" => TokenType::Bool,
@@ -497,6 +815,7 @@ impl Lexer {
             "catch" => TokenType::Catch,
             "chan" => TokenType::Chan,
             "continue" => TokenType::Continue,
+            "do" => TokenType::Do,
             "else" => TokenType::Else,
             "false" => TokenType::False,
             "float" => TokenType::Float,
@@ -514,6 +833,7 @@ impl Lexer {
             "select" => TokenType::Select,
             "new" => TokenType::New,
             "null" => TokenType::Null,
+            "promise" => TokenType::Promise,
             "ptr" => TokenType::Ptr,
             "return" => TokenType::Return,
             "set" => TokenType::Set,
@@ -556,6 +876,7 @@ impl Lexer {
                     if let Some(next_char) = self.peek_next() {
                         if next_char == '/' {
                             let comment_start = self.guard;
+                            let byte_start = self.byte_guard;
                             let comment_line = self.line; // advance 如果遇到 \n, self.line 会自动加 1
 
                             // 跳过 //
@@ -568,33 +889,31 @@ impl Lexer {
                             }
 
                             // 生成注释 token (utf8 编码)
-                            let comment: String = self.source[comment_start..self.guard].iter().collect();
+                            let comment = self.gen_text(byte_start);
 
                             let token = Token::new(TokenType::LineComment, comment, comment_start, self.guard, comment_line);
                             self.token_db.push(token);
                         } else if next_char == '*' {
                             let mut line_start = self.guard;
+                            let mut byte_start = self.byte_guard;
                             let mut current_line = self.line;
 
                             while !self.block_comment_end() {
                                 if self.at_eof() {
                                     // 直到完美结尾都没有找到注释闭合符号, 不需要做任何错误恢复，已经到达了文件的末尾
-                                    self.errors.push(AnalyzerError {
-                                        start: self.offset,
-                                        end: self.guard,
-                                        message: String::from("Unterminated comment"),
-                                    });
+                                    self.error(Span::new(self.offset, self.guard), String::from("Unterminated comment"));
                                     return; // 直接返回，避免 advance 溢出
                                 }
 
                                 // 当遇到换行符时，生成当前行的注释token
                                 if self.peek_guard() == '\n' {
-                                    let line_content: String = self.source[line_start..self.guard].iter().collect();
+                                    let line_content = self.gen_text(byte_start);
                                     self.token_db
                                         .push(Token::new(TokenType::BlockComment, line_content, line_start, self.guard, current_line));
 
                                     self.guard_advance(); // 跳过换行符
                                     line_start = self.guard; // 更新下一行的起始位置
+                                    byte_start = self.byte_guard;
                                     current_line = self.line; // 更新当前行号
                                 } else {
                                     self.guard_advance();
@@ -606,7 +925,7 @@ impl Lexer {
                                 self.guard_advance(); // *
                                 self.guard_advance(); // /
 
-                                let line_content: String = self.source[line_start..self.guard].iter().collect();
+                                let line_content = self.gen_text(byte_start);
                                 self.token_db
                                     .push(Token::new(TokenType::BlockComment, line_content, line_start, self.guard, current_line));
                             }
@@ -623,8 +942,18 @@ impl Lexer {
         }
     }
 
-    fn gen_word(&self) -> String {
-        self.source[self.offset..self.guard].iter().collect()
+    fn gen_word(&mut self) -> Atom {
+        self.intern_from(self.byte_offset)
+    }
+
+    // start 是字节下标, 直接从源码切片驻留, 不经过中间的 String
+    fn intern_from(&mut self, start: usize) -> Atom {
+        cached_intern(&mut self.atoms, &self.text[start..self.byte_guard])
+    }
+
+    // 字面量与注释从源码中复制一份, 只分配一次
+    fn gen_text(&self, start: usize) -> TokenText {
+        TokenText::Text(self.text[start..self.byte_guard].to_string())
     }
 
     fn is_string(&self, s: char) -> bool {
@@ -678,6 +1007,7 @@ impl Lexer {
 
         // 返回 utf8 char 实际占用的字符数量
         self.guard += 1;
+        self.byte_guard += c.len_utf8();
         self.length += 1;
 
         c // c 实现了 copy， 所以这里不会发生所有权转移，顶多就是 clone
@@ -699,32 +1029,44 @@ impl Lexer {
     fn item(&mut self) -> Token {
         // reset by guard
         self.offset = self.guard;
+        self.byte_offset = self.byte_guard;
         self.length = 0;
 
         // 检查标识符
         if self.is_alpha(self.peek_guard()) {
-            let word = self.ident_advance();
+            self.ident_advance();
+            let word = self.gen_word();
             let token = Token::new(self.ident(&word, self.length), word, self.offset, self.guard, self.line);
             return token;
         }
 
         // 检查宏标识符
         if self.match_char('@') {
-            let word = self.ident_advance();
-            let word = word[1..].to_string(); // 跳过 @ 字符
+            // import math@2.0 中的版本号由 parser 处理
+            if self.peek_guard_optional().is_some_and(|c| self.is_number(c)) {
+                return Token::new(TokenType::At, self.gen_word(), self.offset, self.guard, self.line);
+            }
+
+            self.ident_advance();
+
+            // 单独的 @ 符号
+            if self.guard == self.offset + 1 {
+                return Token::new(TokenType::At, self.gen_word(), self.offset, self.guard, self.line);
+            }
+            let word = self.intern_from(self.byte_offset + 1); // 跳过 @ 字符
             return Token::new(TokenType::MacroIdent, word, self.offset, self.guard, self.line);
         }
 
         // 检查函数标签
         if self.match_char('#') {
-            let word = self.ident_advance();
-            let word = word[1..].to_string(); // 跳过 # 字符
+            self.ident_advance();
+            let word = self.intern_from(self.byte_offset + 1); // 跳过 # 字符
             return Token::new(TokenType::Label, word, self.offset, self.guard, self.line);
         }
 
         // 检查数字
         if self.is_number(self.peek_guard()) {
-            let word: String;
+            let word: TokenText;
 
             // 处理 0 开头的特殊数字格式
             if self.peek_guard() == '0' {
@@ -850,13 +1192,8 @@ impl Lexer {
                     if self.match_char('.') {
                         return TokenType::Ellipsis;
                     } else {
-                        self.errors.push(AnalyzerError {
-                            start: self.offset,
-                            end: self.guard,
-                            message: String::from("Expected '...'"),
-                        });
-
-                        return TokenType::Ellipsis;
+                        // for i in 0..10
+                        return TokenType::DotDot;
                     }
                 } else {
                     TokenType::Dot
@@ -872,6 +1209,8 @@ impl Lexer {
             '=' => {
                 if self.match_char('=') {
                     TokenType::EqualEqual
+                } else if self.match_char('>') {
+                    TokenType::FatArrow
                 } else {
                     TokenType::Equal
                 }
@@ -892,7 +1231,7 @@ impl Lexer {
             '>' => {
                 if self.match_char('=') {
                     return TokenType::GreaterEqual;
-                } 
+                }
 
                 if self.peek_guard_optional() == Some('>') && self.peek_next() == Some('=') {
                     self.guard_advance();
@@ -925,11 +1264,7 @@ impl Lexer {
                 }
             }
             _ => {
-                self.errors.push(AnalyzerError {
-                    start: self.offset,
-                    end: self.guard,
-                    message: String::from("Unexpected character"),
-                });
+                self.error(Span::new(self.offset, self.guard), String::from("Unexpected character"));
                 TokenType::Unknown
             }
         }
@@ -943,11 +1278,7 @@ impl Lexer {
 
         // 结束判断
         if self.at_eof() {
-            self.errors.push(AnalyzerError {
-                start: self.offset,
-                end: self.guard,
-                message: String::from("string not terminated"),
-            });
+            self.error(Span::new(self.offset, self.guard), String::from("string not terminated"));
             return result;
         }
 
@@ -957,11 +1288,7 @@ impl Lexer {
             let mut guard_char = self.peek_guard(); // utf8 char
 
             if guard_char == '\n' {
-                self.errors.push(AnalyzerError {
-                    start: self.offset,
-                    end: self.guard,
-                    message: String::from("string not terminated"),
-                });
+                self.error(Span::new(self.offset, self.guard), String::from("string not terminated"));
                 return result; // 返回已经解析的字符串
             }
 
@@ -983,26 +1310,18 @@ impl Lexer {
                     '0' => '\0',
                     '\\' | '\'' | '"' => guard_char,
                     _ => {
-                        self.errors.push(AnalyzerError {
-                            start: self.offset,
-                            end: self.guard + 1,
-                            message: format!("unknown escape char '{}'", guard_char),
-                        });
+                        self.error(Span::new(self.offset, self.guard + 1), format!("unknown escape char '{}'", guard_char));
                         guard_char
                     }
                 };
             }
 
             result.push(guard_char);
-            self.guard_advance(); 
+            self.guard_advance();
 
             // 结束判断
             if self.at_eof() {
-                self.errors.push(AnalyzerError {
-                    start: self.offset,
-                    end: self.guard,
-                    message: String::from("string not terminated"),
-                });
+                self.error(Span::new(self.offset, self.guard), String::from("string not terminated"));
                 return result;
             }
         }
@@ -1017,6 +1336,7 @@ impl Lexer {
         matches!(
             prev_token.token_type,
             TokenType::ImportStar
+                | TokenType::Label
                 | TokenType::IntLiteral
                 | TokenType::StringLiteral
                 | TokenType::FloatLiteral
@@ -1045,26 +1365,27 @@ impl Lexer {
                 | TokenType::U32
                 | TokenType::U64
                 | TokenType::String
+                | TokenType::Void
+                | TokenType::Any
                 | TokenType::Null
                 | TokenType::Not
         )
     }
 
     // 添加缺失的数字处理函数
-    fn hex_number_advance(&mut self) -> String {
+    fn hex_number_advance(&mut self) -> TokenText {
         // 跳过开始的 0x
         self.guard_advance();
         self.guard_advance();
 
-
         while !self.at_eof() && self.is_hex_number(self.peek_guard()) {
             self.guard_advance();
         }
 
-        self.gen_word()
+        self.gen_text(self.byte_offset)
     }
 
-    fn oct_number_advance(&mut self) -> String {
+    fn oct_number_advance(&mut self) -> TokenText {
         // 跳过开始的 0o
         self.guard_advance();
         self.guard_advance();
@@ -1072,10 +1393,10 @@ impl Lexer {
         while !self.at_eof() && self.is_oct_number(self.peek_guard()) {
             self.guard_advance();
         }
-        self.gen_word()
+        self.gen_text(self.byte_offset)
     }
 
-    fn bin_number_advance(&mut self) -> String {
+    fn bin_number_advance(&mut self) -> TokenText {
         // 跳过开始的 0b
         self.guard_advance();
         self.guard_advance();
@@ -1083,14 +1404,22 @@ impl Lexer {
         while !self.at_eof() && self.is_bin_number(self.peek_guard()) {
             self.guard_advance();
         }
-        self.gen_word()
+        self.gen_text(self.byte_offset)
     }
 
-    fn number_advance(&mut self) -> String {
-        while !self.at_eof() && (self.is_number(self.peek_guard()) || self.peek_guard() == '.') {
+    fn number_advance(&mut self) -> TokenText {
+        while !self.at_eof() && (self.is_number(self.peek_guard()) || self.peek_guard() == '.' || self.peek_guard() == '_') {
+            // 0..10 中的 .. 不属于 number
+            if self.peek_guard() == '.' && self.peek_next() == Some('.') {
+                break;
+            }
+            // 数字分隔符, 例如 1_000_000, _ 之后必须是数字
+            if self.peek_guard() == '_' && !self.peek_next().is_some_and(|c| self.is_number(c)) {
+                break;
+            }
             self.guard_advance();
         }
-        self.gen_word()
+        self.gen_text(self.byte_offset)
     }
 
     fn peek_guard(&self) -> char {
@@ -0,0 +1,195 @@
+use super::common::{AstFnDef, AstNode, Stmt, VarDeclExpr};
+use super::diagnostic::DIAGNOSTIC_SOURCE;
+use super::intern::Atom;
+use super::reference::{OccurrenceKind, ReferenceIndex, ReferenceTarget};
+use super::walk::{read_fndef, walk_stmts};
+use crate::project::Module;
+use crate::utils::offset_to_position;
+use lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, Range};
+use std::sync::{Arc, RwLock};
+
+/**
+ * lint 只产生 warning, 不影响后续的分析, 以 _ 开头的变量不参与检查
+ */
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    pub unused_variable: bool,
+    pub shadowing: bool,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            unused_variable: true,
+            shadowing: true,
+        }
+    }
+}
+
+fn warning(m: &Module, var_decl: &VarDeclExpr, message: String) -> Option<Diagnostic> {
+    let range = Range {
+        start: offset_to_position(var_decl.symbol_start, &m.line_index)?,
+        end: offset_to_position(var_decl.symbol_end, &m.line_index)?,
+    };
+
+    Some(Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        source: Some(DIAGNOSTIC_SOURCE.to_string()),
+        message,
+        ..Default::default()
+    })
+}
+
+fn global_fndefs(m: &Module) -> impl Iterator<Item = &Arc<RwLock<AstFnDef>>> {
+    m.stmts.iter().filter_map(|stmt| match &stmt.node {
+        AstNode::FnDef(fndef) => Some(fndef),
+        _ => None,
+    })
+}
+
+pub fn lint(m: &Module, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if config.unused_variable {
+        diagnostics.extend(unused_variables(m));
+    }
+
+    if config.shadowing {
+        let mut checker = ShadowChecker {
+            m,
+            scopes: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        for fndef in global_fndefs(m) {
+            checker.fndef(&fndef.read().unwrap());
+        }
+        diagnostics.extend(checker.diagnostics);
+    }
+
+    diagnostics.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+    diagnostics
+}
+
+/**
+ * 声明之后没有被读取过的局部变量, 只有赋值的变量同样视为未使用, global var 可能被其他 module 使用因此不检查
+ */
+fn unused_variables(m: &Module) -> Vec<Diagnostic> {
+    let index = ReferenceIndex::build(m);
+    let mut diagnostics = Vec::new();
+
+    for fndef in global_fndefs(m) {
+        walk_stmts(&fndef.read().unwrap().body, &mut |stmt: &Stmt| {
+            let AstNode::VarDef(var_decl_mutex, _) = &stmt.node else {
+                return;
+            };
+            let var_decl = var_decl_mutex.read().unwrap();
+            let Some(symbol_id) = var_decl.symbol_id else {
+                return;
+            };
+            if var_decl.ident.starts_with('_') {
+                return;
+            }
+
+            let target = ReferenceTarget::Symbol(symbol_id);
+            if index.occurrences_of(&target).any(|o| o.kind == OccurrenceKind::Read) {
+                return;
+            }
+
+            if let Some(mut diagnostic) = warning(m, &var_decl, format!("unused variable '{}'", var_decl.ident)) {
+                diagnostic.tags = Some(vec![DiagnosticTag::UNNECESSARY]);
+                diagnostics.push(diagnostic);
+            }
+        });
+    }
+
+    diagnostics
+}
+
+/**
+ * 局部变量与外层作用域(包括 fn 参数与外层 fn 中的局部变量)中的变量同名, 同一作用域中的重复声明由 semantic 报告
+ *
+ * 只检查语句级别的作用域, 表达式中的 closure 不参与检查
+ */
+struct ShadowChecker<'a> {
+    m: &'a Module,
+    scopes: Vec<Vec<Atom>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ShadowChecker<'_> {
+    fn declare(&mut self, var_decl: &VarDeclExpr) {
+        let Some((current, outer)) = self.scopes.split_last_mut() else {
+            return;
+        };
+
+        if !var_decl.ident.starts_with('_') && outer.iter().any(|scope| scope.contains(&var_decl.ident)) {
+            let message = format!("variable '{}' shadows a declaration in an outer scope", var_decl.ident);
+            self.diagnostics.extend(warning(self.m, var_decl, message));
+        }
+        current.push(var_decl.ident);
+    }
+
+    fn scoped(&mut self, vars: &[&Arc<RwLock<VarDeclExpr>>], body: &[Box<Stmt>]) {
+        self.scopes.push(Vec::new());
+        for var_decl in vars {
+            self.declare(&var_decl.read().unwrap());
+        }
+        self.body(body);
+        self.scopes.pop();
+    }
+
+    fn fndef(&mut self, fndef: &AstFnDef) {
+        let params: Vec<&Arc<RwLock<VarDeclExpr>>> = fndef.params.iter().collect();
+        self.scoped(&params, &fndef.body);
+    }
+
+    fn body(&mut self, body: &[Box<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.node {
+            AstNode::VarDef(var_decl, _) => self.declare(&var_decl.read().unwrap()),
+            AstNode::VarTupleDestr(elements, _) => {
+                for element in elements {
+                    if let AstNode::VarDecl(var_decl) = &element.node {
+                        self.declare(&var_decl.read().unwrap());
+                    }
+                }
+            }
+            AstNode::If(_, consequent, alternate) => {
+                self.scoped(&[], consequent);
+                self.scoped(&[], alternate);
+            }
+            AstNode::ForCond(_, body) => self.scoped(&[], body),
+            AstNode::ForIterator(_, first, second, _, body) => {
+                let mut vars = vec![first];
+                vars.extend(second);
+                self.scoped(&vars, body);
+            }
+            AstNode::ForTradition(init, _, _, body) => {
+                self.scopes.push(Vec::new());
+                self.stmt(init);
+                self.scoped(&[], body);
+                self.scopes.pop();
+            }
+            AstNode::Catch(_, catch_err, body) | AstNode::TryCatch(_, catch_err, body) => self.scoped(&[catch_err], body),
+            AstNode::Match(_, cases, _) => {
+                for case in cases {
+                    self.scoped(&[], &case.handle_body);
+                }
+            }
+            AstNode::Select(cases, ..) => {
+                for case in cases {
+                    let vars: Vec<&Arc<RwLock<VarDeclExpr>>> = case.recv_var.iter().collect();
+                    self.scoped(&vars, &case.handle_body);
+                }
+            }
+            AstNode::FnDef(fndef) => self.fndef(&read_fndef(fndef)),
+            _ => {}
+        }
+    }
+}
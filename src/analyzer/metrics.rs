@@ -0,0 +1,127 @@
+use super::common::{AstNode, Expr, Span, Stmt};
+use super::walk::{walk_with, Visitor};
+use std::collections::BTreeMap;
+
+/**
+ * 单个 fn(包括 local fn 与 closure) 的统计, 嵌套 fn 中的 stmt 与分支只计入嵌套 fn 自身
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FnMetrics {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    pub stmts: usize,      // body 中的 stmt 数量, 包括 if/for/match 等嵌套 body 中的 stmt
+    pub complexity: usize, // 1 + if/catch + 循环 + match/select 的分支数量
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metrics {
+    pub node_counts: BTreeMap<&'static str, usize>, // key 为 AstNode 的 variant 名称, 同时统计 stmt 与 expr
+    pub stmts: usize,
+    pub exprs: usize,
+    pub max_expr_depth: usize, // 单个 stmt 中 expr 的最大嵌套深度, closure body 中的 stmt 重新计算
+    pub fns: Vec<FnMetrics>,   // 按照在源码中出现的顺序排列
+    pub covered: usize,        // 顶层 stmt 覆盖的 char 数量, 与源码长度对比可以得知 parser 错误恢复之后保留了多少代码
+}
+
+impl Metrics {
+    pub fn count(&self, kind: &str) -> usize {
+        self.node_counts.get(kind).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Default)]
+struct Collector {
+    metrics: Metrics,
+    fn_stack: Vec<usize>, // 当前所在的 fn 在 metrics.fns 中的 index, 最内层的 fn 在最后
+    depth: usize,
+    depth_stack: Vec<usize>, // 进入 stmt 时保存外层 expr 的深度
+}
+
+// 对所在 fn 的 complexity 的贡献, else if 作为 alternate 中的 if 单独计算
+fn branches(node: &AstNode) -> usize {
+    match node {
+        AstNode::If(..) | AstNode::Catch(..) | AstNode::TryCatch(..) | AstNode::Try(..) => 1,
+        AstNode::ForIterator(..) | AstNode::ForCond(..) | AstNode::ForTradition(..) => 1,
+        AstNode::Match(_, cases, _) => cases.len(),
+        AstNode::Select(cases, ..) => cases.len(),
+        _ => 0,
+    }
+}
+
+impl Collector {
+    fn enter(&mut self, node: &AstNode, span: Span) {
+        *self.metrics.node_counts.entry(node.into()).or_default() += 1;
+        if let Some(&index) = self.fn_stack.last() {
+            self.metrics.fns[index].complexity += branches(node);
+        }
+
+        if let AstNode::FnDef(fndef_mutex) = node {
+            let fndef = fndef_mutex.read().unwrap();
+            self.fn_stack.push(self.metrics.fns.len());
+            self.metrics.fns.push(FnMetrics {
+                name: fndef.symbol_name.clone(),
+                start: span.start as usize,
+                end: span.end as usize,
+                stmts: 0,
+                complexity: 1,
+            });
+        }
+    }
+
+    fn leave(&mut self, node: &AstNode) {
+        if matches!(node, AstNode::FnDef(..)) {
+            self.fn_stack.pop();
+        }
+    }
+}
+
+impl Visitor for Collector {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        // local fn 的声明本身是外层 fn 中的 stmt
+        self.metrics.stmts += 1;
+        if let Some(&index) = self.fn_stack.last() {
+            self.metrics.fns[index].stmts += 1;
+        }
+        self.depth_stack.push(self.depth);
+        self.depth = 0;
+        self.enter(&stmt.node, stmt.span);
+    }
+
+    fn leave_stmt(&mut self, stmt: &Stmt) {
+        self.leave(&stmt.node);
+        self.depth = self.depth_stack.pop().unwrap_or(0);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.metrics.exprs += 1;
+        self.depth += 1;
+        self.metrics.max_expr_depth = self.metrics.max_expr_depth.max(self.depth);
+        self.enter(&expr.node, expr.span);
+    }
+
+    fn leave_expr(&mut self, expr: &Expr) {
+        self.leave(&expr.node);
+        self.depth -= 1;
+    }
+}
+
+/**
+ * 统计 ast 中各类节点的数量, expr 的嵌套深度以及每个 fn 的 stmt 数量与圈复杂度, 用于判断是否需要降级为 lazy 分析
+ *
+ * 基于 walk 进行遍历, 与其他的只读遍历一样不会访问 parser 生成的节点(例如 assert 的 implicit_msg 与 async 的 closure_fn)。
+ * parser 出错时统计的是错误恢复之后保留的 stmt
+ */
+pub fn metrics(stmts: &[Box<Stmt>]) -> Metrics {
+    let mut collector = Collector::default();
+    walk_with(stmts, &mut collector);
+
+    let mut metrics = collector.metrics;
+    let mut last_end = 0;
+    for stmt in stmts {
+        let start = stmt.span.start.max(last_end);
+        metrics.covered += stmt.span.end.saturating_sub(start) as usize;
+        last_end = last_end.max(stmt.span.end);
+    }
+    metrics
+}
@@ -0,0 +1,106 @@
+use super::common::{Diagnostic, Span, Stmt};
+use super::lexer::{Lexer, Token, TokenType};
+use super::syntax::{ParserConfig, Syntax};
+use crate::document::{LineIndex, PositionEncoding};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub config: ParserConfig,
+    pub keep_tokens: bool, // 保留 lexer 输出的 token, 默认不保留
+    pub encoding: PositionEncoding,
+}
+
+/**
+ * parse 的结果, errors 按照 lexer, syntax, validate 的顺序排列, 通过 Diagnostic::phase 区分来源
+ */
+#[derive(Debug, Clone)]
+pub struct ParseResult {
+    pub stmts: Vec<Box<Stmt>>,
+    pub errors: Vec<Diagnostic>,
+    pub tokens: Option<Vec<Token>>,
+    pub line_index: LineIndex,
+}
+
+impl ParseResult {
+    pub fn is_error_free(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /**
+     * 与 span 相交的错误, 位于 span 边界上的错误同样返回
+     */
+    pub fn errors_in(&self, span: Span) -> impl Iterator<Item = &Diagnostic> {
+        self.errors.iter().filter(move |e| e.span.start <= span.end && e.span.end >= span.start)
+    }
+}
+
+/**
+ * 对源码进行词法与语法分析, 不需要处理 Lexer 与 Syntax 之间的衔接
+ */
+pub fn parse(source: &str) -> ParseResult {
+    parse_with_options(source, ParseOptions::default())
+}
+
+pub fn parse_with_options(source: &str, options: ParseOptions) -> ParseResult {
+    let (token_db, token_indexes, lexer_errors) = Lexer::new(source.to_string()).scan();
+    let tokens = if options.keep_tokens { Some(token_db.clone()) } else { None };
+
+    let (stmts, _, syntax_errors) = Syntax::with_source(token_db, token_indexes, options.config, Arc::new(source.to_string())).parser();
+    let validate_errors = Syntax::validate_ast(&stmts);
+
+    let errors: Vec<Diagnostic> = lexer_errors.into_iter().chain(syntax_errors).chain(validate_errors).collect();
+
+    ParseResult {
+        stmts,
+        errors,
+        tokens,
+        line_index: LineIndex::new(source, options.encoding),
+    }
+}
+
+/**
+ * source 只包含注释与空白, 并且至少包含一个注释, 用于区分空文件与只有注释(例如文档)的文件
+ *
+ * 注释没有闭合时 lexer 会产生错误, 此时返回 false
+ */
+pub fn is_comment_only(source: &str) -> bool {
+    let (token_db, _, errors) = Lexer::new(source.to_string()).scan();
+    if !errors.is_empty() {
+        return false;
+    }
+
+    let mut has_comment = false;
+    for token in &token_db {
+        match token.token_type {
+            TokenType::LineComment | TokenType::BlockComment => has_comment = true,
+            TokenType::StmtEof | TokenType::Eof => {}
+            _ => return false,
+        }
+    }
+    has_comment
+}
+
+/**
+ * 没有 stmt 并且注释中没有文字, 只包含注释符号的注释(例如 // 或者 /* */)不算作内容
+ *
+ * comments 可以直接传入 lexer 输出的全部 token, 非注释 token 会被忽略
+ */
+pub fn is_effectively_empty(stmts: &[Box<Stmt>], comments: &[Token]) -> bool {
+    if !stmts.is_empty() {
+        return false;
+    }
+
+    comments
+        .iter()
+        .filter(|t| matches!(t.token_type, TokenType::LineComment | TokenType::BlockComment))
+        .all(|t| comment_text(&t.literal).is_empty())
+}
+
+// block comment 按行拆分为多个 token, 中间行不包含 /* 与 */
+fn comment_text(literal: &str) -> &str {
+    let text = literal.trim();
+    let text = text.strip_prefix("//").or_else(|| text.strip_prefix("/*")).unwrap_or(text);
+    let text = text.strip_suffix("*/").unwrap_or(text);
+    text.trim_matches(|c: char| c == '/' || c == '*' || c.is_whitespace())
+}
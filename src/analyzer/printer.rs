@@ -0,0 +1,838 @@
+use super::common::{
+    AstCall, AstFnDef, AstNode, Decorator, Expr, ExprOp, GenericsParam, ImportStmt, MacroArg, MatchCase, SelectCase, Stmt, StructNewEntry, Type, TypeAliasStmt,
+    TypeKind,
+};
+use super::syntax::{SyntaxPrecedence, LOCAL_FN_NAME};
+use std::fmt::{Display, Formatter, Result};
+
+const INDENT: &str = "    ";
+
+/**
+ * 将 stmt 还原为 nature 源码, 输出的源码重新解析之后得到的 ast 与原 ast 结构一致(忽略位置信息)
+ *
+ * 块内的 stmt 通过换行分隔并缩进 4 个空格, 运算符两侧保留一个空格, 只在改变结合方式时添加括号
+ */
+pub fn to_source(stmt: &Stmt) -> String {
+    let mut printer = Printer::default();
+    printer.node(&stmt.node);
+    printer.out
+}
+
+pub fn expr_to_source(expr: &Expr) -> String {
+    let mut printer = Printer::default();
+    printer.node(&expr.node);
+    printer.out
+}
+
+/**
+ * module 或者 body 中的 stmt 列表, 每个 stmt 单独一行
+ */
+pub fn body_to_source(body: &[Box<Stmt>]) -> String {
+    let mut printer = Printer::default();
+    for (i, stmt) in body.iter().enumerate() {
+        if i > 0 {
+            printer.newline();
+        }
+        printer.node(&stmt.node);
+    }
+    printer.out
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", to_source(self))
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", expr_to_source(self))
+    }
+}
+
+fn binary_precedence(op: &ExprOp) -> SyntaxPrecedence {
+    match op {
+        ExprOp::OrOr => SyntaxPrecedence::OrOr,
+        ExprOp::AndAnd => SyntaxPrecedence::AndAnd,
+        ExprOp::Or => SyntaxPrecedence::Or,
+        ExprOp::Xor => SyntaxPrecedence::Xor,
+        ExprOp::And => SyntaxPrecedence::And,
+        ExprOp::Ee | ExprOp::Ne => SyntaxPrecedence::CmpEqual,
+        ExprOp::Lt | ExprOp::Le | ExprOp::Gt | ExprOp::Ge => SyntaxPrecedence::Compare,
+        ExprOp::Lshift | ExprOp::Rshift => SyntaxPrecedence::Shift,
+        ExprOp::Add | ExprOp::Sub => SyntaxPrecedence::Term,
+        ExprOp::Mul | ExprOp::Div | ExprOp::Rem => SyntaxPrecedence::Factor,
+        ExprOp::Pow => SyntaxPrecedence::Power,
+        _ => SyntaxPrecedence::Unary,
+    }
+}
+
+/**
+ * 表达式作为运算符的操作数时的优先级, 与 parser 中的 SyntaxPrecedence 一致
+ *
+ * fn/new/match/go 以及 struct new 只能通过 parser_expr 解析, 不能直接作为操作数, 因此优先级为 Null
+ */
+fn precedence(node: &AstNode) -> SyntaxPrecedence {
+    match node {
+        AstNode::Binary(op, ..) => binary_precedence(op),
+        AstNode::Catch(..) | AstNode::TryCatch(..) | AstNode::Try(..) => SyntaxPrecedence::Catch,
+        AstNode::As(..) | AstNode::TypeCast(..) | AstNode::Is(..) => SyntaxPrecedence::TypeCast,
+        AstNode::Unary(..) => SyntaxPrecedence::Unary,
+        AstNode::Literal(kind, value, _) if Type::is_number(kind) && value.starts_with('-') => SyntaxPrecedence::Unary,
+        AstNode::Call(..)
+        | AstNode::AccessExpr(..)
+        | AstNode::SelectExpr(..)
+        | AstNode::MapAccess(..)
+        | AstNode::VecAccess(..)
+        | AstNode::ArrayAccess(..)
+        | AstNode::TupleAccess(..)
+        | AstNode::StructSelect(..) => SyntaxPrecedence::Call,
+        AstNode::FnDef(..) | AstNode::LambdaShort(..) | AstNode::New(..) | AstNode::Match(..) | AstNode::StructNew(..) | AstNode::Range(..) => {
+            SyntaxPrecedence::Null
+        }
+        AstNode::MacroAsync(r#async) if r#async.flag_expr.is_none() => SyntaxPrecedence::Null,
+        _ => SyntaxPrecedence::Primary,
+    }
+}
+
+fn is_number_literal(expr: &Expr) -> bool {
+    matches!(&expr.node, AstNode::Literal(kind, ..) if Type::is_number(kind))
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/**
+ * lexer 会将字符串中的转义字符转换为实际字符, 输出时需要重新转义
+ */
+pub fn quote(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '\x08' => result.push_str("\\b"),
+            '\x0C' => result.push_str("\\f"),
+            '\x07' => result.push_str("\\a"),
+            '\x0B' => result.push_str("\\v"),
+            '\0' => result.push_str("\\0"),
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn generics_param(param: &GenericsParam) -> String {
+    if param.constraints.0 {
+        return param.ident.clone();
+    }
+
+    let constraints: Vec<String> = param.constraints.1.iter().map(|t| t.to_string()).collect();
+    format!("{}:{}", param.ident, constraints.join("|"))
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn body(&mut self, body: &[Box<Stmt>]) {
+        if body.is_empty() {
+            self.write("{}");
+            return;
+        }
+
+        self.write("{");
+        self.indent += 1;
+        for stmt in body {
+            self.newline();
+            self.node(&stmt.node);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    /**
+     * parser 通过 for 所在行中的 ; 数量识别 for init; cond; update, 同一行中的 {} 之后会插入 ;, 因此空的循环体需要换行
+     */
+    fn loop_body(&mut self, body: &[Box<Stmt>]) {
+        if body.is_empty() {
+            self.write("{");
+            self.newline();
+            self.write("}");
+            return;
+        }
+        self.body(body);
+    }
+
+    /**
+     * expr 作为操作数解析时的最低优先级为 min, 优先级更低的 expr 需要添加括号
+     */
+    fn expr(&mut self, expr: &Expr, min: SyntaxPrecedence) {
+        if precedence(&expr.node) < min {
+            self.paren(expr);
+        } else {
+            self.node(&expr.node);
+        }
+    }
+
+    fn paren(&mut self, expr: &Expr) {
+        self.write("(");
+        self.node(&expr.node);
+        self.write(")");
+    }
+
+    fn exprs(&mut self, exprs: &[Box<Expr>]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expr(expr, SyntaxPrecedence::Null);
+        }
+    }
+
+    /**
+     * 匿名 struct 中的默认值不属于类型字符串, 因此单独输出, 其余类型使用 Type 的 Display
+     * 声明位置不能直接书写 union, T|null 需要输出为 T?
+     */
+    fn type_(&mut self, t: &Type) {
+        let properties = match &t.kind {
+            TypeKind::Union(false, elements) if elements.len() == 2 && elements[1].kind == TypeKind::Null => {
+                self.type_(&elements[0]);
+                self.write("?");
+                return;
+            }
+            TypeKind::Struct(ident, _, properties) if ident.is_empty() && t.origin_ident.is_none() => properties,
+            _ => {
+                self.write(&t.to_string());
+                return;
+            }
+        };
+
+        if properties.is_empty() {
+            self.write("struct {}");
+            return;
+        }
+
+        self.write("struct {");
+        self.indent += 1;
+        for property in properties {
+            self.newline();
+            self.type_(&property.type_);
+            self.write(" ");
+            self.write(&property.key);
+            if let Some(value) = &property.value {
+                self.write(" = ");
+                self.expr(value, SyntaxPrecedence::Null);
+            }
+        }
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    fn call(&mut self, call: &AstCall) {
+        self.expr(&call.left, SyntaxPrecedence::Call);
+        if !call.generics_args.is_empty() {
+            let args: Vec<String> = call.generics_args.iter().map(|t| t.to_string()).collect();
+            self.write(&format!("<{}>", args.join(", ")));
+        }
+
+        self.write("(");
+        for (i, arg) in call.args.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            if call.spread && i == call.args.len() - 1 {
+                self.write("...");
+            }
+            self.expr(arg, SyntaxPrecedence::Null);
+        }
+        self.write(")");
+    }
+
+    // 1.foo 会被识别为浮点数, 因此数字字面量作为 select 的左值时需要添加括号
+    fn select(&mut self, left: &Expr, key: &str) {
+        if is_number_literal(left) {
+            self.paren(left);
+        } else {
+            self.expr(left, SyntaxPrecedence::Call);
+        }
+        self.write(".");
+        self.write(key);
+    }
+
+    fn access(&mut self, left: &Expr, key: &Expr) {
+        self.expr(left, SyntaxPrecedence::Call);
+        self.write("[");
+        self.expr(key, SyntaxPrecedence::Null);
+        self.write("]");
+    }
+
+    fn catch(&mut self, try_expr: &Expr, err_ident: &str, body: &[Box<Stmt>]) {
+        self.expr(try_expr, SyntaxPrecedence::Catch);
+        self.write(" catch ");
+        self.write(err_ident);
+        self.write(" ");
+        self.body(body);
+    }
+
+    fn decorator(&mut self, decorator: &Decorator) {
+        self.write("@");
+        self.write(&decorator.name);
+        if !decorator.args.is_empty() {
+            self.write("(");
+            self.exprs(&decorator.args);
+            self.write(")");
+        }
+    }
+
+    fn fn_def(&mut self, fndef: &AstFnDef) {
+        let anonymous = fndef.fn_name.is_empty() || fndef.fn_name.starts_with(LOCAL_FN_NAME);
+
+        if !anonymous {
+            let mut labels = Vec::new();
+            if let Some(linkid) = &fndef.linkid {
+                labels.push(if is_ident(linkid) {
+                    format!("#linkid {}", linkid)
+                } else {
+                    format!("#linkid {}", quote(linkid))
+                });
+            }
+            if fndef.is_private {
+                labels.push("#local".to_string());
+            }
+            if fndef.is_deprecated {
+                labels.push("#deprecated".to_string());
+            }
+            if fndef.is_test {
+                labels.push("#test".to_string());
+            }
+            if fndef.is_intrinsic {
+                labels.push("#intrinsic".to_string());
+            }
+            if !labels.is_empty() {
+                self.write(&labels.join(" "));
+                self.newline();
+            }
+
+            // decorators 按照 bottom-up 的顺序保存
+            for decorator in fndef.decorators.iter().rev() {
+                self.decorator(decorator);
+                self.newline();
+            }
+
+            if fndef.is_async {
+                self.write("async ");
+            }
+        }
+
+        self.write("fn");
+        if !anonymous {
+            self.write(" ");
+
+            let generics = fndef
+                .generics_params
+                .as_ref()
+                .map(|params| params.iter().map(generics_param).collect::<Vec<_>>().join(", "));
+            match &fndef.impl_type.kind {
+                // fn list<T:int|float>.push(), 泛型参数声明在 impl type 上
+                TypeKind::Alias(alias) => {
+                    self.write(&alias.ident);
+                    if let Some(generics) = &generics {
+                        self.write(&format!("<{}>", generics));
+                    }
+                    self.write(".");
+                    self.write(&fndef.fn_name);
+                }
+                TypeKind::Unknown => {
+                    self.write(&fndef.fn_name);
+                    if let Some(generics) = &generics {
+                        self.write(&format!("<{}>", generics));
+                    }
+                }
+                _ => {
+                    self.write(&fndef.impl_type.to_string());
+                    self.write(".");
+                    self.write(&fndef.fn_name);
+                }
+            }
+        }
+
+        self.write("(");
+        for (i, param) in fndef.params.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            if fndef.rest_param && i == fndef.params.len() - 1 {
+                self.write("...");
+            }
+            let param = param.read().unwrap();
+            self.type_(&param.type_);
+            self.write(" ");
+            self.write(&param.ident);
+        }
+        self.write(")");
+
+        if !matches!(fndef.return_type.kind, TypeKind::Void) {
+            self.write(":");
+            self.type_(&fndef.return_type);
+        }
+        if fndef.is_errable {
+            self.write("!");
+        }
+
+        // tpl fn 与 intrinsic fn 没有 body
+        if fndef.is_tpl || fndef.is_intrinsic {
+            return;
+        }
+        self.write(" ");
+        self.body(&fndef.body);
+    }
+
+    fn type_alias(&mut self, type_alias: &TypeAliasStmt) {
+        for decorator in type_alias.decorators.iter().rev() {
+            self.decorator(decorator);
+            self.newline();
+        }
+
+        self.write("type ");
+        self.write(&type_alias.ident);
+        if !type_alias.params.is_empty() {
+            let params: Vec<String> = type_alias.params.iter().map(generics_param).collect();
+            self.write(&format!("<{}>", params.join(", ")));
+        }
+        self.write(" = ");
+        self.type_(&type_alias.type_expr);
+    }
+
+    fn import(&mut self, import: &ImportStmt) {
+        self.write("import ");
+        match (&import.file, &import.ast_package) {
+            (Some(file), _) => self.write(&quote(file)),
+            (None, Some(package)) => self.write(&package.join(".")),
+            (None, None) => {}
+        }
+        if let Some(version) = &import.version {
+            self.write("@");
+            self.write(version);
+        }
+        if !import.as_name.is_empty() {
+            self.write(" as ");
+            self.write(&import.as_name);
+        }
+    }
+
+    fn if_stmt(&mut self, condition: &Expr, consequent: &[Box<Stmt>], alternate: &[Box<Stmt>]) {
+        self.write("if ");
+        self.expr(condition, SyntaxPrecedence::Assign);
+        self.write(" ");
+        self.body(consequent);
+
+        if alternate.is_empty() {
+            return;
+        }
+
+        // else 必须与 } 位于同一行, 否则 } 之后会插入语句结束符
+        self.write(" else ");
+        if let [stmt] = alternate {
+            if let AstNode::If(condition, consequent, alternate) = &stmt.node {
+                self.if_stmt(condition, consequent, alternate);
+                return;
+            }
+        }
+        self.body(alternate);
+    }
+
+    fn match_case(&mut self, case: &MatchCase, has_subject: bool) {
+        for (i, cond) in case.cond_list.iter().enumerate() {
+            if i > 0 {
+                self.write(" | ");
+            }
+
+            // 存在 subject 时 | 用于分隔多个 cond
+            if has_subject && matches!(cond.node, AstNode::Binary(ExprOp::Or, ..)) {
+                self.paren(cond);
+            } else {
+                self.expr(cond, SyntaxPrecedence::Null);
+            }
+        }
+        self.write(" -> ");
+
+        // 表达式形式的 case 会被解析为 break expr, {} 开头的 expr 会被识别为 body
+        if let [stmt] = case.handle_body.as_slice() {
+            if let AstNode::Break(Some(expr)) = &stmt.node {
+                if !matches!(expr.node, AstNode::MapNew(..) | AstNode::SetNew(..) | AstNode::EmptyCurlyNew) {
+                    self.expr(expr, SyntaxPrecedence::Null);
+                    return;
+                }
+            }
+        }
+        self.body(&case.handle_body);
+    }
+
+    fn select_case(&mut self, case: &SelectCase) {
+        match &case.on_call {
+            Some(call) if !case.is_default => self.call(call),
+            _ => self.write("_"),
+        }
+        self.write(" -> ");
+        if let Some(recv_var) = &case.recv_var {
+            self.write(&recv_var.read().unwrap().ident);
+            self.write(" ");
+        }
+        self.body(&case.handle_body);
+    }
+
+    fn macro_arg(&mut self, arg: &MacroArg) {
+        match arg {
+            MacroArg::Stmt(stmt) => self.node(&stmt.node),
+            MacroArg::Expr(expr) => self.expr(expr, SyntaxPrecedence::Null),
+            MacroArg::Type(t) => self.type_(t),
+        }
+    }
+
+    fn node(&mut self, node: &AstNode) {
+        match node {
+            AstNode::None => {}
+            AstNode::Literal(kind, value, _) => match kind {
+                TypeKind::String => self.write(&quote(value)),
+                _ => self.write(value),
+            },
+            AstNode::Binary(op, left, right) => {
+                let precedence = binary_precedence(op);
+                let next = precedence.next().unwrap_or(precedence);
+
+                // ** 是右结合, 其余运算符都是左结合
+                let (left_min, right_min) = if matches!(op, ExprOp::Pow) { (next, precedence) } else { (precedence, next) };
+                self.expr(left, left_min);
+                self.write(&format!(" {} ", op));
+                self.expr(right, right_min);
+            }
+            AstNode::Range(start, end) => {
+                self.expr(start, SyntaxPrecedence::TypeCast);
+                self.write("..");
+                self.expr(end, SyntaxPrecedence::TypeCast);
+            }
+            AstNode::Unary(op, operand) => {
+                self.write(&op.to_string());
+
+                // --a 与 -1 等会和前面的运算符合并, 因此嵌套的 unary 与数字字面量需要添加括号
+                if matches!(operand.node, AstNode::Unary(..)) || is_number_literal(operand) {
+                    self.paren(operand);
+                } else {
+                    self.expr(operand, SyntaxPrecedence::Unary);
+                }
+            }
+            AstNode::Ident(ident, _) | AstNode::EnvAccess(_, ident, _) => self.write(ident),
+            AstNode::As(target_type, src) | AstNode::TypeCast(target_type, src) => {
+                self.expr(src, SyntaxPrecedence::TypeCast);
+                self.write(" as ");
+                self.type_(target_type);
+            }
+            AstNode::Is(target_type, src) => {
+                self.expr(src, SyntaxPrecedence::TypeCast);
+                self.write(" is ");
+                self.type_(target_type);
+            }
+            AstNode::MatchIs(target_type) => {
+                self.write("is ");
+                self.type_(target_type);
+            }
+            AstNode::MacroSizeof(target_type) => {
+                self.write("@sizeof(");
+                self.type_(target_type);
+                self.write(")");
+            }
+            AstNode::MacroReflectHash(target_type) => {
+                self.write("@reflect_hash(");
+                self.type_(target_type);
+                self.write(")");
+            }
+            AstNode::MacroTypeEq(left, right) => {
+                self.write("@type_eq(");
+                self.type_(left);
+                self.write(", ");
+                self.type_(right);
+                self.write(")");
+            }
+            AstNode::Sizeof(src) => {
+                self.write("@sizeof(");
+                self.expr(src, SyntaxPrecedence::Null);
+                self.write(")");
+            }
+            AstNode::LambdaShort(body) => {
+                self.write("=> ");
+                self.expr(body, SyntaxPrecedence::Null);
+            }
+            AstNode::MacroUla(src) => {
+                self.write("@ula(");
+                self.expr(src, SyntaxPrecedence::Null);
+                self.write(")");
+            }
+            AstNode::MacroAsync(r#async) => match &r#async.flag_expr {
+                Some(flag_expr) => {
+                    self.write("@async(");
+                    self.call(&r#async.origin_call);
+                    self.write(", ");
+                    self.expr(flag_expr, SyntaxPrecedence::Null);
+                    self.write(")");
+                }
+                None => {
+                    self.write("go ");
+                    self.call(&r#async.origin_call);
+                }
+            },
+            AstNode::MacroCall(ident, args) => {
+                self.write("@");
+                self.write(ident);
+                self.write("(");
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.macro_arg(arg);
+                }
+                self.write(")");
+            }
+            AstNode::MacroDefault => self.write("@default()"),
+            AstNode::MacroMinMax(kind, args) => {
+                self.write(&format!("@{}(", kind));
+                self.exprs(args);
+                self.write(")");
+            }
+            // implicit_msg 由 parser 根据 cond 生成
+            AstNode::MacroAssert(cond, _) => {
+                self.write("@assert(");
+                self.expr(cond, SyntaxPrecedence::Null);
+                self.write(")");
+            }
+            AstNode::MacroBitCast(target_type, src) => {
+                self.write("@bitcast(");
+                self.type_(target_type);
+                self.write(", ");
+                self.expr(src, SyntaxPrecedence::Null);
+                self.write(")");
+            }
+            AstNode::New(t, properties, args) => {
+                self.write("new ");
+                self.type_(t);
+                if !properties.is_empty() {
+                    self.write("(");
+                    for (i, property) in properties.iter().enumerate() {
+                        if i > 0 {
+                            self.write(", ");
+                        }
+                        self.write(&property.key);
+                        self.write(" = ");
+                        self.expr(&property.value, SyntaxPrecedence::Null);
+                    }
+                    self.write(")");
+                } else if !args.is_empty() {
+                    self.write("(");
+                    self.exprs(args);
+                    self.write(")");
+                }
+            }
+            AstNode::MapAccess(_, _, left, key) | AstNode::VecAccess(_, left, key) | AstNode::ArrayAccess(_, left, key) | AstNode::AccessExpr(left, key) => {
+                self.access(left, key)
+            }
+            AstNode::TupleAccess(_, left, index) => {
+                self.expr(left, SyntaxPrecedence::Call);
+                self.write(&format!("[{}]", index));
+            }
+            AstNode::StructSelect(left, key, _) | AstNode::SelectExpr(left, key) => self.select(left, key),
+            AstNode::VecNew(elements, ..) | AstNode::ArrayNew(elements) => {
+                self.write("[");
+                self.exprs(elements);
+                self.write("]");
+            }
+            AstNode::MapNew(elements) => {
+                self.write("{");
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expr(&element.key, SyntaxPrecedence::Null);
+                    self.write(": ");
+                    self.expr(&element.value, SyntaxPrecedence::Null);
+                }
+                self.write("}");
+            }
+            AstNode::SetNew(elements) => {
+                self.write("{");
+                self.exprs(elements);
+                self.write("}");
+            }
+            AstNode::TupleNew(elements) | AstNode::TupleDestr(elements) => {
+                self.write("(");
+                self.exprs(elements);
+                self.write(")");
+            }
+            AstNode::StructNew(_, t, entries) => {
+                self.type_(t);
+                self.write("{");
+                for (i, entry) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    match entry {
+                        StructNewEntry::Property(property) => {
+                            self.write(&property.key);
+                            self.write(" = ");
+                            self.expr(&property.value, SyntaxPrecedence::Null);
+                        }
+                        StructNewEntry::Spread(expr) => {
+                            self.write("...");
+                            self.expr(expr, SyntaxPrecedence::Null);
+                        }
+                    }
+                }
+                self.write("}");
+            }
+            AstNode::Try(try_expr, catch_err, body) => self.catch(try_expr, &catch_err.ident, body),
+            AstNode::TryCatch(try_expr, catch_err, body) | AstNode::Catch(try_expr, catch_err, body) => {
+                self.catch(try_expr, &catch_err.read().unwrap().ident, body)
+            }
+            AstNode::EmptyCurlyNew => self.write("{}"),
+            AstNode::VarDecl(var_decl) => self.write(&var_decl.read().unwrap().ident),
+            AstNode::Fake(expr) => self.expr(expr, SyntaxPrecedence::Null),
+            AstNode::Break(expr) | AstNode::Return(expr) => {
+                self.write(if matches!(node, AstNode::Break(..)) { "break" } else { "return" });
+                if let Some(expr) = expr {
+                    self.write(" ");
+                    self.expr(expr, SyntaxPrecedence::Null);
+                }
+            }
+            AstNode::Continue => self.write("continue"),
+            AstNode::Import(import) => self.import(import),
+            AstNode::VarTupleDestr(elements, right) => {
+                self.write("var (");
+                self.exprs(elements);
+                self.write(") = ");
+                self.expr(right, SyntaxPrecedence::Null);
+            }
+            AstNode::Assign(left, right) => {
+                self.expr(left, SyntaxPrecedence::Null);
+                self.write(" = ");
+                self.expr(right, SyntaxPrecedence::Null);
+            }
+            AstNode::If(condition, consequent, alternate) => self.if_stmt(condition, consequent, alternate),
+            AstNode::Throw(expr) => {
+                self.write("throw ");
+                self.expr(expr, SyntaxPrecedence::Null);
+            }
+            AstNode::Let(expr) => {
+                self.write("let ");
+                self.expr(expr, SyntaxPrecedence::Null);
+            }
+            AstNode::Do(expr) => {
+                self.write("do ");
+                self.expr(expr, SyntaxPrecedence::Null);
+            }
+            AstNode::ForIterator(iterate, first, second, step, body) => {
+                self.write("for ");
+                self.write(&first.read().unwrap().ident);
+                if let Some(second) = second {
+                    self.write(", ");
+                    self.write(&second.read().unwrap().ident);
+                }
+                self.write(" in ");
+                if matches!(iterate.node, AstNode::Range(..)) {
+                    self.node(&iterate.node);
+                } else {
+                    self.expr(iterate, SyntaxPrecedence::TypeCast);
+                }
+                if let Some(step) = step {
+                    self.write(" step ");
+                    self.expr(step, SyntaxPrecedence::TypeCast);
+                }
+                self.write(" ");
+                self.loop_body(body);
+            }
+            AstNode::ForCond(condition, body) => {
+                self.write("for ");
+                self.expr(condition, SyntaxPrecedence::Assign);
+                self.write(" ");
+                self.loop_body(body);
+            }
+            AstNode::ForTradition(init, condition, update, body) => {
+                self.write("for ");
+                self.node(&init.node);
+                self.write("; ");
+                self.expr(condition, SyntaxPrecedence::Assign);
+                self.write("; ");
+                self.node(&update.node);
+                self.write(" ");
+                self.loop_body(body);
+            }
+            AstNode::Call(call) => self.call(call),
+            AstNode::Match(subject, cases, _) => {
+                self.write("match ");
+                if let Some(subject) = subject {
+                    self.expr(subject, SyntaxPrecedence::Assign);
+                    self.write(" ");
+                }
+                if cases.is_empty() {
+                    self.write("{}");
+                    return;
+                }
+
+                self.write("{");
+                self.indent += 1;
+                for case in cases {
+                    self.newline();
+                    self.match_case(case, subject.is_some());
+                }
+                self.indent -= 1;
+                self.newline();
+                self.write("}");
+            }
+            AstNode::Select(cases, ..) => {
+                self.write("select {");
+                self.indent += 1;
+                for case in cases {
+                    self.newline();
+                    self.select_case(case);
+                }
+                self.indent -= 1;
+                self.newline();
+                self.write("}");
+            }
+            AstNode::VarDef(var_decl, right) => {
+                let var_decl = var_decl.read().unwrap();
+                if var_decl.type_.kind.is_unknown() {
+                    self.write("var");
+                } else {
+                    self.type_(&var_decl.type_);
+                }
+                self.write(" ");
+                self.write(&var_decl.ident);
+                self.write(" = ");
+                self.expr(right, SyntaxPrecedence::Null);
+            }
+            AstNode::TypeAlias(type_alias) => self.type_alias(&type_alias.read().unwrap()),
+            AstNode::FnDef(fndef) => self.fn_def(&fndef.read().unwrap()),
+        }
+    }
+}
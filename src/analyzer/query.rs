@@ -0,0 +1,374 @@
+use super::common::{AstCall, AstFnDef, AstNode, AstNodeId, Expr, GenericsParam, MacroArg, Span, Stmt, Type, TypeAliasStmt, TypeKind, VarDeclExpr};
+use super::walk::read_fndef;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Stmt,
+    Expr,
+    Type,
+}
+
+/**
+ * node_at 返回的节点信息, name 为 AstNode 或 TypeKind 的变体名称, 例如 Call, VarDef, vec
+ *
+ * type 节点没有 id, 此时 id 为 AstNodeId::default()
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeRef {
+    pub kind: NodeKind,
+    pub name: &'static str,
+    pub span: Span,
+    pub id: AstNodeId,
+}
+
+impl NodeRef {
+    fn stmt(stmt: &Stmt) -> Self {
+        Self {
+            kind: NodeKind::Stmt,
+            name: (&stmt.node).into(),
+            span: stmt.span,
+            id: stmt.id,
+        }
+    }
+
+    fn expr(expr: &Expr) -> Self {
+        Self {
+            kind: NodeKind::Expr,
+            name: (&expr.node).into(),
+            span: expr.span,
+            id: expr.id,
+        }
+    }
+
+    fn type_(t: &Type) -> Self {
+        Self {
+            kind: NodeKind::Type,
+            name: (&t.kind).into(),
+            span: t.span,
+            id: AstNodeId::default(),
+        }
+    }
+}
+
+/**
+ * 查询 offset 所在的节点, 返回的列表由内到外排列, 第一个元素为包含 offset 的最内层 stmt/expr/type, 之后依次为其祖先节点
+ *
+ * 查询依赖父节点包含子节点的位置约定, 只进入包含 offset 的子节点, 相邻的兄弟节点同时包含 offset 时(例如 offset 位于两者的边界)取前一个。
+ * offset 位于同一个 body 的两个 stmt 之间(空白或注释)时返回距离最近的 stmt, 位于第一个 stmt 之前或最后一个 stmt 之后时返回 None
+ */
+pub fn node_at(stmts: &[Box<Stmt>], offset: usize) -> Option<Vec<NodeRef>> {
+    let mut query = Query {
+        offset,
+        path: Vec::new(),
+        depth: 0,
+    };
+    query.body(stmts);
+
+    if query.path.is_empty() {
+        return None;
+    }
+    query.path.reverse();
+    Some(query.path)
+}
+
+struct Query {
+    offset: usize,
+    path: Vec<NodeRef>, // 由外到内
+    depth: usize,
+}
+
+impl Query {
+    /**
+     * 当前层级已经有兄弟节点包含 offset 时不再进入, 返回 true 表示 node 加入了 path, 需要继续遍历其子节点
+     */
+    fn enter(&mut self, node: NodeRef) -> bool {
+        if self.path.len() > self.depth || !node.span.touches(self.offset) {
+            return false;
+        }
+        self.path.push(node);
+        true
+    }
+
+    fn children(&mut self, f: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    fn body(&mut self, body: &[Box<Stmt>]) {
+        if body.iter().any(|stmt| stmt.span.touches(self.offset)) {
+            for stmt in body {
+                self.stmt(stmt);
+            }
+            return;
+        }
+
+        // offset 位于两个 stmt 之间
+        let (Some(first), Some(last)) = (body.first(), body.last()) else {
+            return;
+        };
+        if self.path.len() > self.depth || self.offset < first.span.start as usize || self.offset > last.span.end as usize {
+            return;
+        }
+        let distance = |stmt: &Stmt| {
+            (stmt.span.start as usize)
+                .saturating_sub(self.offset)
+                .max(self.offset.saturating_sub(stmt.span.end as usize))
+        };
+        if let Some(nearest) = body.iter().min_by_key(|stmt| distance(stmt)) {
+            self.path.push(NodeRef::stmt(nearest));
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        if self.enter(NodeRef::stmt(stmt)) {
+            self.children(|query| query.node(&stmt.node));
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        if self.enter(NodeRef::expr(expr)) {
+            self.children(|query| query.node(&expr.node));
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Box<Expr>]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    // 只访问源码中声明的类型, 推导得到的类型没有对应的源码位置
+    fn type_(&mut self, t: &Type) {
+        if t.span.start >= t.span.end || !self.enter(NodeRef::type_(t)) {
+            return;
+        }
+
+        self.children(|query| match &t.kind {
+            TypeKind::Vec(element)
+            | TypeKind::Arr(_, element)
+            | TypeKind::Set(element)
+            | TypeKind::Chan(element)
+            | TypeKind::Promise(element)
+            | TypeKind::Ptr(element)
+            | TypeKind::RawPtr(element) => query.type_(element),
+            TypeKind::Map(key, value) => {
+                query.type_(key);
+                query.type_(value);
+            }
+            TypeKind::Tuple(elements, _) | TypeKind::Union(_, elements) => {
+                for element in elements {
+                    query.type_(element);
+                }
+            }
+            TypeKind::Struct(_, _, properties) => {
+                for property in properties {
+                    query.type_(&property.type_);
+                    if let Some(value) = &property.value {
+                        query.expr(value);
+                    }
+                }
+            }
+            TypeKind::Fn(type_fn) => {
+                for param_type in &type_fn.param_types {
+                    query.type_(param_type);
+                }
+                query.type_(&type_fn.return_type);
+            }
+            TypeKind::Alias(alias) => {
+                for arg in alias.args.iter().flatten() {
+                    query.type_(&arg.type_);
+                }
+            }
+            _ => {}
+        });
+    }
+
+    fn generics_params(&mut self, params: &[GenericsParam]) {
+        for param in params {
+            for constraint in &param.constraints.1 {
+                self.type_(constraint);
+            }
+        }
+    }
+
+    fn var_decl(&mut self, var_decl_mutex: &Arc<RwLock<VarDeclExpr>>) {
+        self.type_(&var_decl_mutex.read().unwrap().type_);
+    }
+
+    fn fndef(&mut self, fndef_mutex: &Arc<RwLock<AstFnDef>>) {
+        let fndef = read_fndef(fndef_mutex);
+        for decorator in &fndef.decorators {
+            self.exprs(&decorator.args);
+        }
+        if let Some(generics_params) = &fndef.generics_params {
+            self.generics_params(generics_params);
+        }
+        self.type_(&fndef.impl_type);
+        for param in &fndef.params {
+            self.var_decl(param);
+        }
+        self.type_(&fndef.return_type);
+        self.body(&fndef.body);
+    }
+
+    fn type_alias(&mut self, type_alias_mutex: &Arc<RwLock<TypeAliasStmt>>) {
+        let type_alias = type_alias_mutex.read().unwrap();
+        for decorator in &type_alias.decorators {
+            self.exprs(&decorator.args);
+        }
+        self.generics_params(&type_alias.params);
+        self.type_(&type_alias.type_expr);
+    }
+
+    fn call(&mut self, call: &AstCall) {
+        self.expr(&call.left);
+        for generics_arg in &call.generics_args {
+            self.type_(generics_arg);
+        }
+        self.exprs(&call.args);
+    }
+
+    fn node(&mut self, node: &AstNode) {
+        match node {
+            AstNode::Binary(_, left, right) | AstNode::Range(left, right) | AstNode::Assign(left, right) | AstNode::AccessExpr(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            AstNode::Unary(_, expr)
+            | AstNode::TypeCast(_, expr)
+            | AstNode::MacroUla(expr)
+            | AstNode::LambdaShort(expr)
+            | AstNode::Sizeof(expr)
+            | AstNode::MacroAssert(expr, _) // implicit_msg 由 parser 生成, 不对应用户编写的代码
+            | AstNode::Fake(expr)
+            | AstNode::Do(expr)
+            | AstNode::Throw(expr)
+            | AstNode::Let(expr)
+            | AstNode::TupleAccess(_, expr, _)
+            | AstNode::StructSelect(expr, _, _)
+            | AstNode::SelectExpr(expr, _)
+            | AstNode::Return(Some(expr))
+            | AstNode::Break(Some(expr)) => self.expr(expr),
+            AstNode::As(target_type, expr) | AstNode::Is(target_type, expr) | AstNode::MacroBitCast(target_type, expr) => {
+                self.type_(target_type);
+                self.expr(expr);
+            }
+            AstNode::MatchIs(target_type) | AstNode::MacroSizeof(target_type) | AstNode::MacroReflectHash(target_type) => self.type_(target_type),
+            AstNode::MacroTypeEq(left_type, right_type) => {
+                self.type_(left_type);
+                self.type_(right_type);
+            }
+            AstNode::MapAccess(_, _, left, key) | AstNode::VecAccess(_, left, key) | AstNode::ArrayAccess(_, left, key) => {
+                self.expr(left);
+                self.expr(key);
+            }
+            // 协程闭包由 parser 生成, 只访问用户编写的 call
+            AstNode::MacroAsync(async_expr) => {
+                self.call(&async_expr.origin_call);
+                if let Some(flag_expr) = &async_expr.flag_expr {
+                    self.expr(flag_expr);
+                }
+            }
+            AstNode::MacroCall(_, args) => {
+                for arg in args {
+                    match arg {
+                        MacroArg::Stmt(stmt) => self.stmt(stmt),
+                        MacroArg::Expr(expr) => self.expr(expr),
+                        MacroArg::Type(t) => self.type_(t),
+                    }
+                }
+            }
+            AstNode::New(t, properties, args) => {
+                self.type_(t);
+                for property in properties {
+                    self.expr(&property.value);
+                }
+                self.exprs(args);
+            }
+            AstNode::StructNew(_, t, entries) => {
+                self.type_(t);
+                for entry in entries {
+                    self.expr(entry.value());
+                }
+            }
+            AstNode::VecNew(elements, len, cap) => {
+                self.exprs(elements);
+                if let Some(len) = len {
+                    self.expr(len);
+                }
+                if let Some(cap) = cap {
+                    self.expr(cap);
+                }
+            }
+            AstNode::ArrayNew(elements)
+            | AstNode::SetNew(elements)
+            | AstNode::TupleNew(elements)
+            | AstNode::TupleDestr(elements)
+            | AstNode::MacroMinMax(_, elements) => self.exprs(elements),
+            AstNode::MapNew(elements) => {
+                for element in elements {
+                    self.expr(&element.key);
+                    self.expr(&element.value);
+                }
+            }
+            AstNode::Try(try_expr, _, body) | AstNode::TryCatch(try_expr, _, body) | AstNode::Catch(try_expr, _, body) => {
+                self.expr(try_expr);
+                self.body(body);
+            }
+            AstNode::VarDecl(var_decl) => self.var_decl(var_decl),
+            AstNode::VarDef(var_decl, right) => {
+                self.var_decl(var_decl);
+                self.expr(right);
+            }
+            AstNode::VarTupleDestr(elements, right) => {
+                self.exprs(elements);
+                self.expr(right);
+            }
+            AstNode::If(condition, consequent, alternate) => {
+                self.expr(condition);
+                self.body(consequent);
+                self.body(alternate);
+            }
+            AstNode::ForIterator(iterate, _, _, step, body) => {
+                self.expr(iterate);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.body(body);
+            }
+            AstNode::ForCond(condition, body) => {
+                self.expr(condition);
+                self.body(body);
+            }
+            AstNode::ForTradition(init, condition, update, body) => {
+                self.stmt(init);
+                self.expr(condition);
+                self.stmt(update);
+                self.body(body);
+            }
+            AstNode::Call(call) => self.call(call),
+            AstNode::Match(subject, cases, _) => {
+                if let Some(subject) = subject {
+                    self.expr(subject);
+                }
+                for case in cases {
+                    self.exprs(&case.cond_list);
+                    self.body(&case.handle_body);
+                }
+            }
+            AstNode::Select(cases, ..) => {
+                for case in cases {
+                    if let Some(on_call) = &case.on_call {
+                        self.call(on_call);
+                    }
+                    self.body(&case.handle_body);
+                }
+            }
+            AstNode::TypeAlias(type_alias) => self.type_alias(type_alias),
+            AstNode::FnDef(fndef) => self.fndef(fndef),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use super::common::{AstFnDef, AstNode, Expr, Span, Stmt, StructNewEntry, TypeKind, VarDeclExpr};
+use super::lexer::{semantic_token_modifier_bit, semantic_token_type_index, Token, TokenType};
+use super::symbol::NodeId;
+use super::walk::{read_fndef, walk_body, walk_stmts};
+use crate::project::Module;
+use crate::utils::{format_global_ident, range_of};
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, LinkedEditingRanges, Range, SemanticTokenModifier, SemanticTokenType};
+use std::collections::HashSet;
+
+// 与 lexer 中 ident 的规则一致
+pub const IDENT_WORD_PATTERN: &str = "[a-zA-Z_][a-zA-Z0-9_]*";
+
+/**
+ * 被引用的目标, 局部变量/参数/函数通过 symbol_id 区分, struct field 通过 (key, 字段定义位置) 区分
+ *
+ * 类型推导后 type alias 的引用会被 reduction 并丢失位置信息, 因此 type alias 通过 global ident 区分
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReferenceTarget {
+    Symbol(NodeId),
+    Field(String, usize), // (key, property start)
+    TypeAlias(String),    // global ident
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OccurrenceKind {
+    Read,
+    Write, // 声明, 赋值左值, 复合赋值左值, tuple destr 中的元素
+}
+
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub target: ReferenceTarget,
+    pub start: usize,
+    pub end: usize,
+    pub kind: OccurrenceKind,
+}
+
+/**
+ * 单个 module 中所有符号出现位置的索引, 按照 start 排序
+ */
+#[derive(Debug, Default)]
+pub struct ReferenceIndex {
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl ReferenceIndex {
+    pub fn build(m: &Module) -> Self {
+        let mut collector = Collector {
+            tokens: &m.token_db,
+            occurrences: Vec::new(),
+        };
+
+        walk_stmts(&m.stmts, &mut |stmt: &Stmt| collector.node(&stmt.node, stmt.span));
+        walk_body(&m.stmts, &mut |expr: &Expr| collector.node(&expr.node, expr.span));
+        collector.type_aliases(m);
+
+        // a += 1 会被解析为 a = a + 1, 生成的 a 与左值位置相同, 同一位置只保留一个 occurrence 且 Write 优先
+        let mut occurrences = collector.occurrences;
+        occurrences.sort_by_key(|o| (o.start, o.end, o.kind == OccurrenceKind::Read));
+        occurrences.dedup_by_key(|o| (o.start, o.end));
+
+        Self { occurrences }
+    }
+
+    pub fn target_at(&self, offset: usize) -> Option<&ReferenceTarget> {
+        self.occurrences.iter().find(|o| o.start <= offset && offset <= o.end).map(|o| &o.target)
+    }
+
+    pub fn occurrences_of<'a>(&'a self, target: &'a ReferenceTarget) -> impl Iterator<Item = &'a Occurrence> {
+        self.occurrences.iter().filter(move |o| o.target == *target)
+    }
+}
+
+struct Collector<'a> {
+    tokens: &'a [Token],
+    occurrences: Vec<Occurrence>,
+}
+
+impl Collector<'_> {
+    fn push(&mut self, target: ReferenceTarget, start: usize, end: usize, kind: OccurrenceKind) {
+        // 编译器生成的节点没有位置信息
+        if end > start {
+            self.occurrences.push(Occurrence { target, start, end, kind });
+        }
+    }
+
+    fn var_decl(&mut self, var_decl: &VarDeclExpr) {
+        if let Some(symbol_id) = var_decl.symbol_id {
+            self.push(
+                ReferenceTarget::Symbol(symbol_id),
+                var_decl.symbol_start,
+                var_decl.symbol_end,
+                OccurrenceKind::Write,
+            );
+        }
+    }
+
+    fn fndef(&mut self, fndef: &AstFnDef) {
+        if let Some(symbol_id) = fndef.symbol_id {
+            self.push(ReferenceTarget::Symbol(symbol_id), fndef.symbol_start, fndef.symbol_end, OccurrenceKind::Write);
+        }
+        for param in &fndef.params {
+            self.var_decl(&param.read().unwrap());
+        }
+    }
+
+    /**
+     * 赋值左值, 子表达式会在 walk 时以 Read 的形式再次出现, 并在去重时被 Write 覆盖
+     */
+    fn assign_target(&mut self, expr: &Expr) {
+        match &expr.node {
+            AstNode::Ident(_, Some(symbol_id)) | AstNode::EnvAccess(_, _, Some(symbol_id)) => {
+                self.push(
+                    ReferenceTarget::Symbol(*symbol_id),
+                    expr.span.start as usize,
+                    expr.span.end as usize,
+                    OccurrenceKind::Write,
+                );
+            }
+            AstNode::StructSelect(_, key, property) => self.select_key(key, property.start, expr.span.end as usize, OccurrenceKind::Write),
+            AstNode::TupleDestr(elements) => {
+                for element in elements {
+                    self.assign_target(element);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // struct select 的 key 位于表达式的末尾
+    fn select_key(&mut self, key: &str, property_start: usize, end: usize, kind: OccurrenceKind) {
+        let start = end.saturating_sub(key.chars().count());
+        self.push(ReferenceTarget::Field(key.to_string(), property_start), start, end, kind);
+    }
+
+    fn token_at(&self, start: usize) -> Option<&Token> {
+        let index = self.tokens.partition_point(|t| (t.span.start as usize) < start);
+        self.tokens.get(index)
+    }
+
+    // struct new 中 key = value, 从 value 向前查找 key token
+    fn struct_new_key(&self, value_start: usize) -> Option<&Token> {
+        let index = self.tokens.partition_point(|t| (t.span.start as usize) < value_start);
+        let (key, equal) = (self.tokens.get(index.checked_sub(2)?)?, self.tokens.get(index - 1)?);
+        (key.token_type == TokenType::Ident && equal.token_type == TokenType::Equal).then_some(key)
+    }
+
+    /**
+     * parser 会将类型位置的 ident 标记为 TYPE semantic token, 包括 type alias 的声明处与 pkg.foo 中的 pkg 与 foo
+     */
+    fn type_aliases(&mut self, m: &Module) {
+        let type_index = semantic_token_type_index(SemanticTokenType::TYPE);
+        let declaration = semantic_token_modifier_bit(SemanticTokenModifier::DECLARATION);
+        let tokens: Vec<&Token> = m
+            .sem_token_db
+            .iter()
+            .filter(|t| !matches!(t.token_type, TokenType::LineComment | TokenType::BlockComment))
+            .collect();
+
+        for (i, token) in tokens.iter().enumerate() {
+            if token.token_type != TokenType::Ident || token.semantic_token_type != type_index {
+                continue;
+            }
+
+            // pkg.foo 中的 pkg
+            if tokens.get(i + 1).is_some_and(|next| next.token_type == TokenType::Dot) {
+                continue;
+            }
+
+            let global_ident = if i >= 2 && tokens[i - 1].token_type == TokenType::Dot {
+                let Some(import) = m.dependencies.iter().find(|import| import.as_name == tokens[i - 2].literal) else {
+                    continue;
+                };
+                format_global_ident(import.module_ident.clone(), token.literal.to_string())
+            } else {
+                format_global_ident(m.ident.clone(), token.literal.to_string())
+            };
+
+            let kind = if token.semantic_token_modifiers & declaration != 0 {
+                OccurrenceKind::Write
+            } else {
+                OccurrenceKind::Read
+            };
+            self.push(
+                ReferenceTarget::TypeAlias(global_ident),
+                token.span.start as usize,
+                token.span.end as usize,
+                kind,
+            );
+        }
+    }
+
+    fn node(&mut self, node: &AstNode, span: Span) {
+        let (start, end) = (span.start as usize, span.end as usize);
+        match node {
+            AstNode::VarDef(var_decl, _) | AstNode::VarDecl(var_decl) => self.var_decl(&var_decl.read().unwrap()),
+            AstNode::ForIterator(_, first, second, _, _) => {
+                self.var_decl(&first.read().unwrap());
+                if let Some(second) = second {
+                    self.var_decl(&second.read().unwrap());
+                }
+            }
+            AstNode::Try(_, catch_err, _) => self.var_decl(catch_err),
+            AstNode::TryCatch(_, catch_err, _) | AstNode::Catch(_, catch_err, _) => self.var_decl(&catch_err.read().unwrap()),
+            AstNode::Select(cases, ..) => {
+                for recv_var in cases.iter().filter_map(|case| case.recv_var.as_ref()) {
+                    self.var_decl(&recv_var.read().unwrap());
+                }
+            }
+            AstNode::FnDef(fndef_mutex) => self.fndef(&read_fndef(fndef_mutex)),
+            AstNode::Assign(left, _) => self.assign_target(left),
+            AstNode::Ident(_, Some(symbol_id)) | AstNode::EnvAccess(_, _, Some(symbol_id)) => {
+                self.push(ReferenceTarget::Symbol(*symbol_id), start, end, OccurrenceKind::Read);
+            }
+            AstNode::StructSelect(_, key, property) => self.select_key(key, property.start, end, OccurrenceKind::Read),
+            AstNode::StructNew(_, type_, entries) => {
+                let TypeKind::Struct(_, _, type_properties) = &type_.kind else {
+                    return;
+                };
+                for entry in entries {
+                    let StructNewEntry::Property(property) = entry else {
+                        continue;
+                    };
+                    let Some(type_property) = type_properties.iter().find(|p| p.key == property.key) else {
+                        continue;
+                    };
+                    if let Some(key) = self.struct_new_key(property.value.span.start as usize) {
+                        let (start, end) = (key.span.start as usize, key.span.end as usize);
+                        self.push(
+                            ReferenceTarget::Field(property.key.to_string(), type_property.start),
+                            start,
+                            end,
+                            OccurrenceKind::Write,
+                        );
+                    }
+                }
+            }
+            AstNode::TypeAlias(type_alias_mutex) => {
+                let type_alias = type_alias_mutex.read().unwrap();
+                let TypeKind::Struct(_, _, properties) = &type_alias.type_expr.kind else {
+                    return;
+                };
+                // struct { field_type field_name = default_value }, field_name 紧跟在 field_type 之后
+                for property in properties {
+                    if let Some(key) = self.token_at(property.end).filter(|t| t.literal == property.key) {
+                        let (start, end) = (key.span.start as usize, key.span.end as usize);
+                        self.push(
+                            ReferenceTarget::Field(property.key.to_string(), property.start),
+                            start,
+                            end,
+                            OccurrenceKind::Write,
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+ * textDocument/documentHighlight, 返回 offset 处符号在当前文件中的所有出现位置
+ */
+pub fn document_highlights(index: &ReferenceIndex, m: &Module, offset: usize) -> Vec<DocumentHighlight> {
+    let Some(target) = index.target_at(offset) else {
+        return Vec::new();
+    };
+
+    index
+        .occurrences_of(target)
+        .filter_map(|o| {
+            Some(DocumentHighlight {
+                range: range_of(o.start, o.end, &m.line_index)?,
+                kind: Some(match o.kind {
+                    OccurrenceKind::Read => DocumentHighlightKind::READ,
+                    OccurrenceKind::Write => DocumentHighlightKind::WRITE,
+                }),
+            })
+        })
+        .collect()
+}
+
+/**
+ * 局部变量与参数的 symbol id, 这些符号的所有引用都位于当前文件中
+ *
+ * global var 与 fn 可能被其他文件引用, 不包含在内
+ */
+fn local_symbols(m: &Module) -> HashSet<NodeId> {
+    let mut symbols = HashSet::new();
+    let mut collect = |node: &AstNode| match node {
+        AstNode::VarDef(var_decl, _) | AstNode::VarDecl(var_decl) => symbols.extend(var_decl.read().unwrap().symbol_id),
+        AstNode::ForIterator(_, first, second, _, _) => {
+            symbols.extend(first.read().unwrap().symbol_id);
+            if let Some(second) = second {
+                symbols.extend(second.read().unwrap().symbol_id);
+            }
+        }
+        AstNode::Try(_, catch_err, _) => symbols.extend(catch_err.symbol_id),
+        AstNode::TryCatch(_, catch_err, _) | AstNode::Catch(_, catch_err, _) => symbols.extend(catch_err.read().unwrap().symbol_id),
+        AstNode::Select(cases, ..) => {
+            for recv_var in cases.iter().filter_map(|case| case.recv_var.as_ref()) {
+                symbols.extend(recv_var.read().unwrap().symbol_id);
+            }
+        }
+        AstNode::FnDef(fndef_mutex) => {
+            let fndef = read_fndef(fndef_mutex);
+            symbols.extend(fndef.params.iter().filter_map(|param| param.read().unwrap().symbol_id));
+        }
+        _ => {}
+    };
+    walk_stmts(&m.stmts, &mut |stmt: &Stmt| collect(&stmt.node));
+    walk_body(&m.stmts, &mut |expr: &Expr| collect(&expr.node));
+
+    for stmt in &m.stmts {
+        if let AstNode::VarDef(var_decl, _) = &stmt.node {
+            if let Some(symbol_id) = var_decl.read().unwrap().symbol_id {
+                symbols.remove(&symbol_id);
+            }
+        }
+    }
+    symbols
+}
+
+/**
+ * textDocument/linkedEditingRange, 编辑时实时同步修改所有出现位置, 因此只处理局部变量与参数, 并且所有出现位置的文本必须一致
+ */
+pub fn linked_editing_ranges(index: &ReferenceIndex, m: &Module, offset: usize) -> Option<LinkedEditingRanges> {
+    let target = index.target_at(offset)?;
+    let ReferenceTarget::Symbol(symbol_id) = target else {
+        return None;
+    };
+    if !local_symbols(m).contains(symbol_id) {
+        return None;
+    }
+
+    let occurrences: Vec<&Occurrence> = index.occurrences_of(target).collect();
+    let text = |o: &Occurrence| m.rope.get_slice(o.start..o.end).map(|slice| slice.to_string());
+    let ident = text(occurrences.first()?)?;
+    if occurrences.iter().any(|o| text(o).as_ref() != Some(&ident)) {
+        return None;
+    }
+
+    let ranges = occurrences
+        .iter()
+        .map(|o| range_of(o.start, o.end, &m.line_index))
+        .collect::<Option<Vec<Range>>>()?;
+    Some(LinkedEditingRanges {
+        ranges,
+        word_pattern: Some(IDENT_WORD_PATTERN.to_string()),
+    })
+}
@@ -0,0 +1,478 @@
+use super::common::*;
+use super::lexer::{Lexer, Token};
+use super::syntax::{ParserConfig, Syntax};
+use super::walk::{read_fndef, walk_body, walk_stmts};
+use std::sync::{Arc, RwLock};
+
+/**
+ * 源码编辑, 旧源码中 [start, end) 区间(char offset)被替换为 new_text
+ */
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub new_text: String,
+}
+
+impl Syntax {
+    /**
+     * 增量解析, 只重新解析与 edit 相交的顶层 stmt, 其余 stmt 直接 clone, 位于 edit 之后的 stmt 需要平移位置信息
+     *
+     * old_stmts 与 old_tokens 需要是 parser 的原始输出(未经过 semantic 改写)
+     * 返回的 errors 只包含重新解析部分的语法错误
+     */
+    pub fn reparse_range(old_stmts: &[Box<Stmt>], old_tokens: &[Token], edit: &TextEdit, new_source: &str) -> (Vec<Box<Stmt>>, Vec<Diagnostic>) {
+        let reparse_full = || {
+            let (token_db, token_indexes, mut errors) = Lexer::new(new_source.to_string()).scan();
+            let (stmts, _, syntax_errors) = Syntax::new(token_db, token_indexes).parser();
+            errors.extend(syntax_errors);
+            (stmts, errors)
+        };
+
+        let chars: Vec<char> = new_source.chars().collect();
+        let delta = edit.new_text.chars().count() as isize - (edit.end - edit.start) as isize;
+        let old_len = (chars.len() as isize - delta) as usize;
+
+        // 与 edit 相交(包括相邻)的 stmt 需要重新解析, edit 位于 stmt 之间时, label 与注释属于其后的 stmt, 同样需要重新解析
+        let lower = old_stmts.partition_point(|stmt| (stmt.span.end as usize) < edit.start);
+        let mut upper = old_stmts.partition_point(|stmt| (stmt.span.start as usize) <= edit.end);
+        if upper == lower {
+            upper = (lower + 1).min(old_stmts.len());
+        }
+
+        // 重新解析的区间从上一个 stmt 的结尾开始, 到下一个 stmt 的开头结束(old offset)
+        let region_start = if lower > 0 { old_stmts[lower - 1].span.end as usize } else { 0 };
+        let region_end = old_stmts.get(upper).map_or(old_len, |stmt| stmt.span.start as usize);
+
+        // 跨越区间边界的 token(例如多行字符串)无法局部解析
+        let straddle = |offset: usize| old_tokens.iter().any(|t| (t.span.start as usize) < offset && offset < t.span.end as usize);
+        if region_start > edit.start || region_end < edit.end || straddle(region_start) || straddle(region_end) {
+            return reparse_full();
+        }
+
+        let new_region_end = (region_end as isize + delta) as usize;
+        let region_source: String = chars[region_start..new_region_end].iter().collect();
+        let (mut token_db, token_indexes, lexer_errors) = Lexer::new(region_source).scan();
+
+        // 未闭合的注释或字符串会影响区间之后的源码
+        if !lexer_errors.is_empty() {
+            return reparse_full();
+        }
+
+        let line_offset = chars[..region_start].iter().filter(|&&c| c == '\n').count();
+        for token in token_db.iter_mut() {
+            token.span.start += region_start as u32;
+            token.span.end += region_start as u32;
+            token.line += line_offset;
+        }
+
+        let config = ParserConfig {
+            lambda_index: next_lambda_index(old_stmts),
+            node_id: max_node_id(old_stmts),
+            ..Default::default()
+        };
+        let (region_stmts, _, errors) = Syntax::with_config(token_db, token_indexes, config).parser();
+
+        let mut stmts = old_stmts[..lower].to_vec();
+        stmts.extend(region_stmts);
+
+        let shift = Shift { from: region_end, delta };
+        stmts.extend(old_stmts[upper..].iter().map(|stmt| {
+            let mut stmt = stmt.clone();
+            if delta != 0 {
+                shift.stmt(&mut stmt);
+            }
+            stmt
+        }));
+
+        (stmts, errors)
+    }
+}
+
+/**
+ * local fn 的名称为 lambda{index}, 重新解析的部分需要从未使用的序号开始
+ */
+fn next_lambda_index(stmts: &[Box<Stmt>]) -> usize {
+    let mut next = 0;
+    walk_body(stmts, &mut |expr| {
+        let AstNode::FnDef(fndef_mutex) = &expr.node else {
+            return;
+        };
+        let fndef = read_fndef(fndef_mutex);
+        if let Some(index) = fndef.symbol_name.strip_prefix("lambda").and_then(|index| index.parse::<usize>().ok()) {
+            next = next.max(index + 1);
+        }
+    });
+    next
+}
+
+/**
+ * 复用的 stmt 保留原有的 id, 重新解析的部分需要从未使用的 id 开始分配
+ *
+ * @async 展开得到的 closure fn 不在 walk 的范围内, 需要单独处理
+ */
+fn max_node_id(stmts: &[Box<Stmt>]) -> u32 {
+    let mut max = 0;
+    walk_stmts(stmts, &mut |stmt| max = max.max(stmt.id.0));
+    walk_body(stmts, &mut |expr| {
+        max = max.max(expr.id.0);
+        let AstNode::MacroAsync(async_expr) = &expr.node else {
+            return;
+        };
+        for closure_mutex in [&async_expr.closure_fn, &async_expr.closure_fn_void] {
+            max = max.max(max_node_id(&read_fndef(closure_mutex).body));
+        }
+    });
+    max
+}
+
+/**
+ * 平移 from 之后的位置信息, Arc 共享的节点需要深拷贝, 避免修改 old stmts
+ */
+struct Shift {
+    from: usize,
+    delta: isize,
+}
+
+impl Shift {
+    fn pos(&self, pos: &mut usize) {
+        // 编译器生成的节点位置为 0, 不需要平移
+        if *pos >= self.from {
+            *pos = (*pos as isize + self.delta) as usize;
+        }
+    }
+
+    fn span(&self, span: &mut Span) {
+        let (mut start, mut end) = (span.start as usize, span.end as usize);
+        self.pos(&mut start);
+        self.pos(&mut end);
+        *span = Span::new(start, end);
+    }
+
+    fn body(&self, body: &mut [Box<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&self, stmt: &mut Stmt) {
+        self.span(&mut stmt.span);
+        self.node(&mut stmt.node);
+    }
+
+    fn expr(&self, expr: &mut Expr) {
+        self.span(&mut expr.span);
+        self.type_(&mut expr.type_);
+        self.type_(&mut expr.target_type);
+        self.node(&mut expr.node);
+    }
+
+    fn exprs(&self, exprs: &mut [Box<Expr>]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn type_(&self, t: &mut Type) {
+        self.span(&mut t.span);
+
+        match &mut t.kind {
+            TypeKind::Vec(element)
+            | TypeKind::Arr(_, element)
+            | TypeKind::Set(element)
+            | TypeKind::Chan(element)
+            | TypeKind::Promise(element)
+            | TypeKind::Ptr(element)
+            | TypeKind::RawPtr(element) => self.type_(element),
+            TypeKind::Map(key, value) => {
+                self.type_(key);
+                self.type_(value);
+            }
+            TypeKind::Tuple(elements, _) | TypeKind::Union(_, elements) => {
+                for element in elements {
+                    self.type_(element);
+                }
+            }
+            TypeKind::Struct(_, _, properties) => {
+                for property in properties {
+                    self.pos(&mut property.start);
+                    self.pos(&mut property.end);
+                    self.type_(&mut property.type_);
+                    if let Some(value) = &mut property.value {
+                        self.expr(value);
+                    }
+                }
+            }
+            TypeKind::Fn(type_fn) => {
+                self.type_(&mut type_fn.return_type);
+                for param_type in &mut type_fn.param_types {
+                    self.type_(param_type);
+                }
+            }
+            TypeKind::Alias(alias) => {
+                for arg in alias.args.iter_mut().flatten() {
+                    self.type_(&mut arg.type_);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn generics_params(&self, params: &mut [GenericsParam]) {
+        for param in params {
+            for constraint in &mut param.constraints.1 {
+                self.type_(constraint);
+            }
+        }
+    }
+
+    fn decorators(&self, decorators: &mut [Decorator]) {
+        for decorator in decorators {
+            self.pos(&mut decorator.start);
+            self.pos(&mut decorator.end);
+            self.exprs(&mut decorator.args);
+        }
+    }
+
+    fn var_decl(&self, var_decl: &mut VarDeclExpr) {
+        self.pos(&mut var_decl.symbol_start);
+        self.pos(&mut var_decl.symbol_end);
+        self.type_(&mut var_decl.type_);
+    }
+
+    fn var_decl_arc(&self, var_decl_mutex: &mut Arc<RwLock<VarDeclExpr>>) {
+        let mut var_decl = var_decl_mutex.read().unwrap().clone();
+        self.var_decl(&mut var_decl);
+        *var_decl_mutex = Arc::new(RwLock::new(var_decl));
+    }
+
+    fn fndef_arc(&self, fndef_mutex: &mut Arc<RwLock<AstFnDef>>) {
+        let mut fndef = fndef_mutex.read().unwrap().clone();
+        self.pos(&mut fndef.symbol_start);
+        self.pos(&mut fndef.symbol_end);
+        self.pos(&mut fndef.start);
+        self.pos(&mut fndef.end);
+        self.type_(&mut fndef.return_type);
+        self.type_(&mut fndef.impl_type);
+        self.type_(&mut fndef.type_);
+        for param in &mut fndef.params {
+            self.var_decl_arc(param);
+        }
+        if let Some(generics_params) = &mut fndef.generics_params {
+            self.generics_params(generics_params);
+        }
+        self.decorators(&mut fndef.decorators);
+        self.body(&mut fndef.body);
+        *fndef_mutex = Arc::new(RwLock::new(fndef));
+    }
+
+    fn call(&self, call: &mut AstCall) {
+        self.type_(&mut call.return_type);
+        self.expr(&mut call.left);
+        for generics_arg in &mut call.generics_args {
+            self.type_(generics_arg);
+        }
+        self.exprs(&mut call.args);
+    }
+
+    fn node(&self, node: &mut AstNode) {
+        match node {
+            AstNode::Binary(_, left, right)
+            | AstNode::Range(left, right)
+            | AstNode::Assign(left, right)
+            | AstNode::AccessExpr(left, right)
+            | AstNode::MacroAssert(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            AstNode::Unary(_, expr)
+            | AstNode::MacroUla(expr)
+            | AstNode::LambdaShort(expr)
+            | AstNode::Sizeof(expr)
+            | AstNode::Fake(expr)
+            | AstNode::Do(expr)
+            | AstNode::Throw(expr)
+            | AstNode::Let(expr)
+            | AstNode::SelectExpr(expr, _)
+            | AstNode::Return(Some(expr))
+            | AstNode::Break(Some(expr)) => self.expr(expr),
+            AstNode::As(target_type, expr)
+            | AstNode::TypeCast(target_type, expr)
+            | AstNode::Is(target_type, expr)
+            | AstNode::TupleAccess(target_type, expr, _) => {
+                self.type_(target_type);
+                self.expr(expr);
+            }
+            AstNode::MatchIs(target_type) | AstNode::MacroSizeof(target_type) | AstNode::MacroReflectHash(target_type) => self.type_(target_type),
+            AstNode::MacroBitCast(target_type, src) => {
+                self.type_(target_type);
+                self.expr(src);
+            }
+            AstNode::MacroTypeEq(left_type, right_type) => {
+                self.type_(left_type);
+                self.type_(right_type);
+            }
+            AstNode::MacroAsync(async_expr) => {
+                self.fndef_arc(&mut async_expr.closure_fn);
+                self.fndef_arc(&mut async_expr.closure_fn_void);
+                self.call(&mut async_expr.origin_call);
+                if let Some(flag_expr) = &mut async_expr.flag_expr {
+                    self.expr(flag_expr);
+                }
+                self.type_(&mut async_expr.return_type);
+            }
+            AstNode::MacroCall(_, args) => {
+                for arg in args {
+                    match arg {
+                        MacroArg::Stmt(stmt) => self.stmt(stmt),
+                        MacroArg::Expr(expr) => self.expr(expr),
+                        MacroArg::Type(t) => self.type_(t),
+                    }
+                }
+            }
+            AstNode::New(t, properties, args) => {
+                self.type_(t);
+                for property in properties {
+                    self.pos(&mut property.start);
+                    self.pos(&mut property.end);
+                    self.type_(&mut property.type_);
+                    self.expr(&mut property.value);
+                }
+                self.exprs(args);
+            }
+            AstNode::MapAccess(key_type, value_type, left, key) => {
+                self.type_(key_type);
+                self.type_(value_type);
+                self.expr(left);
+                self.expr(key);
+            }
+            AstNode::VecAccess(element_type, left, index) | AstNode::ArrayAccess(element_type, left, index) => {
+                self.type_(element_type);
+                self.expr(left);
+                self.expr(index);
+            }
+            AstNode::StructSelect(instance, _, property) => {
+                self.expr(instance);
+                self.pos(&mut property.start);
+                self.pos(&mut property.end);
+                self.type_(&mut property.type_);
+            }
+            AstNode::VecNew(elements, len, cap) => {
+                self.exprs(elements);
+                if let Some(len) = len {
+                    self.expr(len);
+                }
+                if let Some(cap) = cap {
+                    self.expr(cap);
+                }
+            }
+            AstNode::ArrayNew(elements)
+            | AstNode::SetNew(elements)
+            | AstNode::TupleNew(elements)
+            | AstNode::TupleDestr(elements)
+            | AstNode::MacroMinMax(_, elements) => self.exprs(elements),
+            AstNode::MapNew(elements) => {
+                for element in elements {
+                    self.expr(&mut element.key);
+                    self.expr(&mut element.value);
+                }
+            }
+            AstNode::StructNew(_, t, entries) => {
+                self.type_(t);
+                for entry in entries {
+                    if let StructNewEntry::Property(property) = entry {
+                        self.pos(&mut property.start);
+                        self.pos(&mut property.end);
+                        self.type_(&mut property.type_);
+                    }
+                    self.expr(entry.value_mut());
+                }
+            }
+            AstNode::Try(try_expr, catch_err, body) => {
+                self.expr(try_expr);
+                self.var_decl(catch_err);
+                self.body(body);
+            }
+            AstNode::TryCatch(try_expr, catch_err, body) | AstNode::Catch(try_expr, catch_err, body) => {
+                self.expr(try_expr);
+                self.var_decl_arc(catch_err);
+                self.body(body);
+            }
+            AstNode::VarDecl(var_decl) => self.var_decl_arc(var_decl),
+            AstNode::VarDef(var_decl, right) => {
+                self.var_decl_arc(var_decl);
+                self.expr(right);
+            }
+            AstNode::VarTupleDestr(elements, right) => {
+                self.exprs(elements);
+                self.expr(right);
+            }
+            AstNode::Import(import) => {
+                self.pos(&mut import.start);
+                self.pos(&mut import.end);
+            }
+            AstNode::If(condition, consequent, alternate) => {
+                self.expr(condition);
+                self.body(consequent);
+                self.body(alternate);
+            }
+            AstNode::ForIterator(iterate, first, second, step, body) => {
+                self.expr(iterate);
+                self.var_decl_arc(first);
+                if let Some(second) = second {
+                    self.var_decl_arc(second);
+                }
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.body(body);
+            }
+            AstNode::ForCond(condition, body) => {
+                self.expr(condition);
+                self.body(body);
+            }
+            AstNode::ForTradition(init, condition, update, body) => {
+                self.stmt(init);
+                self.expr(condition);
+                self.stmt(update);
+                self.body(body);
+            }
+            AstNode::Call(call) => self.call(call),
+            AstNode::Match(subject, cases, _) => {
+                if let Some(subject) = subject {
+                    self.expr(subject);
+                }
+                for case in cases {
+                    self.pos(&mut case.start);
+                    self.pos(&mut case.end);
+                    self.exprs(&mut case.cond_list);
+                    self.body(&mut case.handle_body);
+                }
+            }
+            AstNode::Select(cases, _, _, _) => {
+                for case in cases {
+                    self.pos(&mut case.start);
+                    self.pos(&mut case.end);
+                    if let Some(on_call) = &mut case.on_call {
+                        self.call(on_call);
+                    }
+                    if let Some(recv_var) = &mut case.recv_var {
+                        self.var_decl_arc(recv_var);
+                    }
+                    self.body(&mut case.handle_body);
+                }
+            }
+            AstNode::TypeAlias(type_alias_mutex) => {
+                let mut type_alias = type_alias_mutex.read().unwrap().clone();
+                self.pos(&mut type_alias.symbol_start);
+                self.pos(&mut type_alias.symbol_end);
+                self.type_(&mut type_alias.type_expr);
+                self.generics_params(&mut type_alias.params);
+                self.decorators(&mut type_alias.decorators);
+                *type_alias_mutex = Arc::new(RwLock::new(type_alias));
+            }
+            AstNode::FnDef(fndef_mutex) => self.fndef_arc(fndef_mutex),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use super::common::{AstNode, Span};
+use super::lexer::TokenType;
+use super::query::{node_at, NodeKind};
+use super::walk::walk_body;
+use crate::project::Module;
+use crate::utils::range_of;
+use lsp_types::SelectionRange;
+
+/**
+ * 计算 offset 所在的语法结构链, 由内到外依次为 token → expr → stmt → block → fn body → fn → file
+ *
+ * stmt/expr/type 的祖先链来自 query::node_at, block 与 match case 在此基础上补充。
+ * 去糖产生的节点(复合赋值中的 Binary)与所在的 stmt 位置相同, 会在去重时被合并
+ */
+pub fn node_path_at(m: &Module, offset: usize) -> Vec<Span> {
+    let mut ranges: Vec<Span> = vec![Span::new(0, m.rope.len_chars())];
+
+    // offset 位于 stmt 之间时 node_at 返回最近的 stmt, 其并不包含 offset
+    let nodes = node_at(&m.stmts, offset).unwrap_or_default();
+    ranges.extend(nodes.iter().map(|node| node.span).filter(|span| span.touches(offset)));
+
+    // match case 没有对应的 node, 从路径中的 match 表达式取得
+    let match_ids: Vec<_> = nodes
+        .iter()
+        .filter(|node| node.kind == NodeKind::Expr && node.name == "Match")
+        .map(|node| node.id)
+        .collect();
+    let mut cases = Vec::new();
+    if !match_ids.is_empty() {
+        walk_body(&m.stmts, &mut |expr| {
+            if let AstNode::Match(_, match_cases, _) = &expr.node {
+                if match_ids.contains(&expr.id) {
+                    cases.extend(match_cases.iter().map(|case| Span::new(case.start, case.end)));
+                }
+            }
+        });
+    }
+
+    // block 与 block 内部的 stmts, 例如 fn body 以及 if/for/match 的 body
+    let mut stack = Vec::new();
+    let tokens: Vec<_> = m
+        .token_db
+        .iter()
+        .filter(|t| {
+            !matches!(
+                t.token_type,
+                TokenType::StmtEof | TokenType::Eof | TokenType::LineComment | TokenType::BlockComment
+            )
+        })
+        .collect();
+    for (i, token) in tokens.iter().enumerate() {
+        match token.token_type {
+            TokenType::LeftCurly => stack.push(i),
+            TokenType::RightCurly => {
+                let Some(open) = stack.pop() else {
+                    continue;
+                };
+                let block = tokens[open].span.merge(token.span);
+                if !block.touches(offset) {
+                    continue;
+                }
+
+                ranges.push(block);
+                if open + 1 < i {
+                    let stmts = tokens[open + 1].span.merge(tokens[i - 1].span);
+                    if stmts.touches(offset) {
+                        ranges.push(stmts);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // match case 的 end 包含了 case 之后的 StmtEof, 需要收缩到最后一个 token
+    for case in cases {
+        let index = tokens.partition_point(|t| t.span.end <= case.end);
+        if index > 0 {
+            let case = Span {
+                end: tokens[index - 1].span.end,
+                ..case
+            };
+            if case.touches(offset) {
+                ranges.push(case);
+            }
+        }
+    }
+
+    if let Some(token) = tokens.iter().find(|t| t.span.touches(offset)) {
+        ranges.push(token.span);
+    }
+
+    // 由内到外排列, 只保留严格嵌套的 range
+    ranges.sort_by_key(|span| (span.len(), u32::MAX - span.start));
+    let mut path: Vec<Span> = Vec::new();
+    for span in ranges {
+        if let Some(inner) = path.last() {
+            if !(span.start <= inner.start && inner.end <= span.end) || span == *inner {
+                continue;
+            }
+        }
+        path.push(span);
+    }
+
+    path
+}
+
+/**
+ * 返回值与 offsets 一一对应, 无法计算语法结构链时使用 offset 处的空 range
+ */
+pub fn selection_ranges(m: &Module, offsets: &[usize]) -> Vec<SelectionRange> {
+    let selection_range = |offset: usize| -> Option<SelectionRange> {
+        let mut selection: Option<SelectionRange> = None;
+        for span in node_path_at(m, offset).iter().rev() {
+            selection = Some(SelectionRange {
+                range: range_of(span.start as usize, span.end as usize, &m.line_index)?,
+                parent: selection.map(Box::new),
+            });
+        }
+        selection
+    };
+
+    offsets
+        .iter()
+        .map(|&offset| {
+            selection_range(offset).unwrap_or_else(|| SelectionRange {
+                range: range_of(offset.min(m.rope.len_chars()), offset.min(m.rope.len_chars()), &m.line_index).unwrap_or_default(),
+                parent: None,
+            })
+        })
+        .collect()
+}
@@ -1,9 +1,15 @@
 use crate::project::Module;
+use crate::scheduler::CancellationToken;
 use crate::utils::format_global_ident;
 
 use super::common::*;
+use super::intern::Atom;
+use super::lexer::semantic_token_modifier_bit;
 use super::symbol::{NodeId, ScopeKind, SymbolKind, SymbolTable, GLOBAL_SCOPE_ID};
-use std::sync::{Arc, Mutex};
+use super::syntax::{LAMBDA_SHORT_PARAM, LOCAL_FN_NAME};
+use super::walk::walk_expr;
+use lsp_types::SemanticTokenModifier;
+use std::sync::{Arc, RwLock};
 
 #[derive(Debug)]
 pub struct Semantic<'a> {
@@ -12,7 +18,8 @@ pub struct Semantic<'a> {
     module: &'a mut Module,
     stmts: Vec<Box<Stmt>>,
     imports: Vec<ImportStmt>,
-    current_local_fn_list: Vec<Arc<Mutex<AstFnDef>>>,
+    current_local_fn_list: Vec<Arc<RwLock<AstFnDef>>>,
+    cancel: CancellationToken,
 }
 
 impl<'a> Semantic<'a> {
@@ -24,9 +31,18 @@ impl<'a> Semantic<'a> {
             imports: m.dependencies.clone(),
             module: m,
             current_local_fn_list: Vec::new(),
+            cancel: CancellationToken::default(),
         }
     }
 
+    /**
+     * 取消之后不再分析剩余的 stmt, 分析结果不完整, 只能被丢弃
+     */
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
     fn analyze_special_type_rewrite(&mut self, t: &mut Type) -> bool {
         assert!(matches!(t.kind, TypeKind::Alias(..)));
         let TypeKind::Alias(type_alias) = t.kind.clone() else { unreachable!() };
@@ -38,11 +54,7 @@ impl<'a> Semantic<'a> {
             t.origin_type_kind = TypeKind::Unknown;
 
             if type_alias.args.is_some() {
-                self.errors.push(AnalyzerError {
-                    start: t.start,
-                    end: t.end,
-                    message: format!("void_ptr cannot contains arg"),
-                });
+                self.errors.push(AnalyzerError::new(t.span, format!("void_ptr cannot contains arg")));
                 t.err = true;
             }
 
@@ -53,15 +65,11 @@ impl<'a> Semantic<'a> {
         if type_alias.ident == TypeKind::RawPtr(Box::new(Type::default())).to_string() {
             // extract first args to type_
             if let Some(args) = type_alias.args {
-                let mut first_arg_type = args[0].clone();
+                let mut first_arg_type = args[0].type_.clone();
                 self.analyze_type(&mut first_arg_type);
                 t.kind = TypeKind::RawPtr(Box::new(first_arg_type));
             } else {
-                self.errors.push(AnalyzerError {
-                    start: t.start,
-                    end: t.end,
-                    message: format!("raw_ptr must contains one arg"),
-                });
+                self.errors.push(AnalyzerError::new(t.span, format!("raw_ptr must contains one arg")));
             }
 
             t.origin_ident = None;
@@ -72,15 +80,11 @@ impl<'a> Semantic<'a> {
         // ptr rewrite
         if type_alias.ident == TypeKind::Ptr(Box::new(Type::default())).to_string() {
             if let Some(args) = type_alias.args {
-                let mut first_arg_type = args[0].clone();
+                let mut first_arg_type = args[0].type_.clone();
                 self.analyze_type(&mut first_arg_type);
                 t.kind = TypeKind::Ptr(Box::new(first_arg_type));
             } else {
-                self.errors.push(AnalyzerError {
-                    start: t.start,
-                    end: t.end,
-                    message: format!("ptr must contains one arg"),
-                });
+                self.errors.push(AnalyzerError::new(t.span, format!("ptr must contains one arg")));
             }
 
             t.origin_ident = None;
@@ -94,11 +98,7 @@ impl<'a> Semantic<'a> {
             t.origin_ident = None;
             t.origin_type_kind = TypeKind::Unknown;
             if type_alias.args.is_some() {
-                self.errors.push(AnalyzerError {
-                    start: t.start,
-                    end: t.end,
-                    message: format!("all_type cannot contains arg"),
-                });
+                self.errors.push(AnalyzerError::new(t.span, format!("all_type cannot contains arg")));
             }
             return true;
         }
@@ -109,11 +109,7 @@ impl<'a> Semantic<'a> {
             t.origin_ident = None;
             t.origin_type_kind = TypeKind::Unknown;
             if type_alias.args.is_some() {
-                self.errors.push(AnalyzerError {
-                    start: t.start,
-                    end: t.end,
-                    message: format!("fn_t cannot contains arg"),
-                });
+                self.errors.push(AnalyzerError::new(t.span, format!("fn_t cannot contains arg")));
             }
             return true;
         }
@@ -121,6 +117,23 @@ impl<'a> Semantic<'a> {
         return false;
     }
 
+    /**
+     * async fn foo(): T 的返回值类型为 promise<T>, 调用方不需要关心 is_async
+     * void async fn(包括 @async 生成的 closure) 没有需要等待的结果，不做处理
+     */
+    fn wrap_async_return_type(fndef: &mut AstFnDef) {
+        if !fndef.is_async || matches!(fndef.return_type.kind, TypeKind::Void | TypeKind::Promise(..)) {
+            return;
+        }
+
+        let value_type = fndef.return_type.clone();
+        fndef.return_type = Type {
+            kind: TypeKind::Promise(Box::new(value_type.clone())),
+            span: value_type.span,
+            ..Type::default()
+        };
+    }
+
     fn analyze_type(&mut self, t: &mut Type) {
         match &mut t.kind {
             TypeKind::Alias(type_alias) => {
@@ -131,11 +144,8 @@ impl<'a> Semantic<'a> {
                     // 只要存在 import as, 就必须能够在 导入表中找到对应的导入, 放在直接抛出异常
                     let import_ident = self.imports.iter().find(|i| i.as_name == *import_as);
                     if import_ident.is_none() {
-                        self.errors.push(AnalyzerError {
-                            start: t.start,
-                            end: t.end,
-                            message: format!("import type alias '{}' undeclared", ident),
-                        });
+                        self.errors
+                            .push(AnalyzerError::new(t.span, format!("import type alias '{}' undeclared", ident)));
                         t.err = true;
                         return;
                     }
@@ -146,11 +156,10 @@ impl<'a> Semantic<'a> {
                     // 从 symbol table 中查找相关的 global symbol id
                     let symbol_id = self.symbol_table.find_symbol_id(&global_pkg_ident, GLOBAL_SCOPE_ID);
                     if symbol_id.is_none() {
-                        self.errors.push(AnalyzerError {
-                            start: t.start,
-                            end: t.end,
-                            message: format!("global ident '{}' undeclared in {} module", global_pkg_ident, import_ident.module_ident),
-                        });
+                        self.errors.push(AnalyzerError::new(
+                            t.span,
+                            format!("global ident '{}' undeclared in {} module", global_pkg_ident, import_ident.module_ident),
+                        ));
                         t.err = true;
                         return;
                     }
@@ -169,20 +178,29 @@ impl<'a> Semantic<'a> {
                             return;
                         }
 
-                        self.errors.push(AnalyzerError {
-                            start: t.start,
-                            end: t.end,
-                            message: format!("type '{}' undeclared", ident),
-                        });
+                        self.errors.push(AnalyzerError::new(t.span, format!("type '{}' undeclared", ident)));
                         t.err = true;
                         return;
                     }
                 }
 
+                // 具名实参按照 params 的顺序排列, 正在分析的 type alias 自身无法获取锁, 由 typesys 排列
+                if let Some(symbol) = type_alias.symbol_id.and_then(|symbol_id| self.symbol_table.get_symbol(symbol_id)) {
+                    if let SymbolKind::TypeAlias(type_alias_stmt_mutex) = &symbol.kind {
+                        if let Ok(type_alias_stmt) = type_alias_stmt_mutex.try_read() {
+                            if let Err(message) = type_alias.order_args(&type_alias_stmt.params) {
+                                self.errors.push(AnalyzerError::new(t.span, message));
+                                t.err = true;
+                                return;
+                            }
+                        }
+                    }
+                }
+
                 // 处理泛型参数
                 if let Some(args) = &mut type_alias.args {
                     for arg in args {
-                        self.analyze_type(arg);
+                        self.analyze_type(&mut arg.type_);
                     }
                 }
             }
@@ -206,6 +224,9 @@ impl<'a> Semantic<'a> {
             TypeKind::Chan(element_type) => {
                 self.analyze_type(element_type);
             }
+            TypeKind::Promise(value_type) => {
+                self.analyze_type(value_type);
+            }
             TypeKind::Arr(_, element_type) => {
                 self.analyze_type(element_type);
             }
@@ -237,11 +258,10 @@ impl<'a> Semantic<'a> {
 
                         // value kind cannot is fndef
                         if let AstNode::FnDef(..) = value.node {
-                            self.errors.push(AnalyzerError {
-                                start: value.start,
-                                end: value.end,
-                                message: format!("struct field default value cannot be a fn def, use fn def ident instead"),
-                            });
+                            self.errors.push(AnalyzerError::new(
+                                value.span,
+                                format!("struct field default value cannot be a fn def, use fn def ident instead"),
+                            ));
                             t.err = true;
                         }
                     }
@@ -254,7 +274,7 @@ impl<'a> Semantic<'a> {
     }
 
     pub fn analyze(&mut self) {
-        let mut global_fn_stmt_list = Vec::<Arc<Mutex<AstFnDef>>>::new();
+        let mut global_fn_stmt_list = Vec::<Arc<RwLock<AstFnDef>>>::new();
 
         let mut var_assign_list = Vec::<Box<Stmt>>::new();
 
@@ -270,7 +290,7 @@ impl<'a> Semantic<'a> {
             match &mut stmt.node {
                 AstNode::Import(..) => continue,
                 AstNode::FnDef(fndef_mutex) => {
-                    let mut fndef = fndef_mutex.lock().unwrap();
+                    let mut fndef = fndef_mutex.write().unwrap();
                     let mut symbol_name = fndef.symbol_name.clone();
 
                     // fn string<T>.len() -> fn <T>.string_len to symbol_table
@@ -287,40 +307,37 @@ impl<'a> Semantic<'a> {
                         fndef.symbol_name = format_global_ident(self.module.ident.clone(), symbol_name.clone());
                     }
 
-                    if let Err(e) = self
+                    match self
                         .symbol_table
                         .define_symbol(fndef.symbol_name.clone(), SymbolKind::Fn(fndef_mutex.clone()), fndef.symbol_start)
                     {
-                        self.errors.push(AnalyzerError {
-                            start: fndef.symbol_start,
-                            end: fndef.symbol_end,
-                            message: e,
-                        });
+                        Ok(symbol_id) => {
+                            fndef.symbol_id = Some(symbol_id);
+                        }
+                        Err(e) => {
+                            self.errors.push(AnalyzerError::new(Span::new(fndef.symbol_start, fndef.symbol_end), e));
+                        }
                     }
 
                     global_fn_stmt_list.push(fndef_mutex.clone());
                 }
                 AstNode::VarDef(var_decl_mutex, expr) => {
-                    let mut var_decl = var_decl_mutex.lock().unwrap();
+                    let mut var_decl = var_decl_mutex.write().unwrap();
                     self.analyze_type(&mut var_decl.type_);
 
                     // ident rewrite
-                    var_decl.ident = format_global_ident(self.module.ident.clone(), var_decl.ident.clone());
+                    var_decl.ident = Atom::from(&format_global_ident(self.module.ident.clone(), var_decl.ident.to_string()));
 
                     // global ident to symbol_table
                     match self
                         .symbol_table
-                        .define_symbol(var_decl.ident.clone(), SymbolKind::Var(var_decl_mutex.clone()), var_decl.symbol_start)
+                        .define_symbol(var_decl.ident.to_string(), SymbolKind::Var(var_decl_mutex.clone()), var_decl.symbol_start)
                     {
                         Ok(symbol_id) => {
                             var_decl.symbol_id = Some(symbol_id);
                         }
                         Err(e) => {
-                            self.errors.push(AnalyzerError {
-                                start: var_decl.symbol_start,
-                                end: var_decl.symbol_end,
-                                message: e,
-                            });
+                            self.errors.push(AnalyzerError::new(Span::new(var_decl.symbol_start, var_decl.symbol_end), e));
                         }
                     }
 
@@ -329,21 +346,20 @@ impl<'a> Semantic<'a> {
 
                     // 将 vardef 转换成 assign 导入到 package init 中进行初始化
                     let assign_left = Box::new(Expr::ident(
-                        var_decl.symbol_start,
-                        var_decl.symbol_end,
-                        var_decl.ident.clone(),
+                        Span::new(var_decl.symbol_start, var_decl.symbol_end),
+                        var_decl.ident.to_string(),
                         var_decl.symbol_id,
                     ));
 
                     let assign_stmt = Box::new(Stmt {
+                        id: AstNodeId::default(),
                         node: AstNode::Assign(assign_left, expr.clone()),
-                        start: expr.start,
-                        end: expr.end,
+                        span: expr.span,
                     });
                     var_assign_list.push(assign_stmt);
                 }
                 AstNode::TypeAlias(type_alias_mutex) => {
-                    let mut type_alias = type_alias_mutex.lock().unwrap();
+                    let mut type_alias = type_alias_mutex.write().unwrap();
 
                     type_alias.ident = format_global_ident(self.module.ident.clone(), type_alias.ident.clone());
 
@@ -353,11 +369,8 @@ impl<'a> Semantic<'a> {
                         SymbolKind::TypeAlias(type_alias_mutex.clone()),
                         type_alias.symbol_start,
                     ) {
-                        self.errors.push(AnalyzerError {
-                            start: type_alias.symbol_start,
-                            end: type_alias.symbol_end,
-                            message: e,
-                        });
+                        self.errors
+                            .push(AnalyzerError::new(Span::new(type_alias.symbol_start, type_alias.symbol_end), e));
                     }
 
                     // 处理 params constraints, type foo<T:int|float, E:int:bool> = ...
@@ -393,11 +406,14 @@ impl<'a> Semantic<'a> {
             fn_init.return_type = Type::new(TypeKind::Void);
             fn_init.body = var_assign_list;
 
-            global_fn_stmt_list.push(Arc::new(Mutex::new(fn_init)));
+            global_fn_stmt_list.push(Arc::new(RwLock::new(fn_init)));
         }
 
         // 对 fn stmt list 进行 analyzer 处理。
         for fndef_mutex in &global_fn_stmt_list {
+            if self.cancel.is_cancelled() {
+                break;
+            }
             self.module.all_fndefs.push(fndef_mutex.clone());
             self.analyze_global_fn(fndef_mutex.clone());
         }
@@ -422,7 +438,7 @@ impl<'a> Semantic<'a> {
 
         // import x as * 产生的全局符号
         for i in &self.imports {
-            if i.as_name != "*" {
+            if !i.is_star {
                 continue;
             };
 
@@ -440,9 +456,9 @@ impl<'a> Semantic<'a> {
         return None;
     }
 
-    pub fn analyze_global_fn(&mut self, fndef_mutex: Arc<Mutex<AstFnDef>>) {
+    pub fn analyze_global_fn(&mut self, fndef_mutex: Arc<RwLock<AstFnDef>>) {
         {
-            let mut fndef = fndef_mutex.lock().unwrap();
+            let mut fndef = fndef_mutex.write().unwrap();
 
             fndef.is_local = false;
             fndef.module_index = self.module.index;
@@ -455,6 +471,7 @@ impl<'a> Semantic<'a> {
             }
 
             self.analyze_type(&mut fndef.return_type);
+            Self::wrap_async_return_type(&mut fndef);
 
             // 如果 impl type 是 type alias, 则从符号表中获取当前的 type alias 的全称进行更新
             // fn vec<T>.len() -> fn vec_len(vec<T> self)
@@ -472,11 +489,10 @@ impl<'a> Semantic<'a> {
                             alias_box.ident = unique_ident.clone();
                         }
                     } else {
-                        self.errors.push(AnalyzerError {
-                            start: fndef.symbol_start,
-                            end: fndef.symbol_end,
-                            message: format!("type alias '{}' undeclared", impl_ident),
-                        });
+                        self.errors.push(AnalyzerError::new(
+                            Span::new(fndef.symbol_start, fndef.symbol_end),
+                            format!("type alias '{}' undeclared", impl_ident),
+                        ));
                     };
                 }
 
@@ -484,16 +500,17 @@ impl<'a> Semantic<'a> {
                 let mut new_params = Vec::new();
                 let param_type = fndef.impl_type.clone();
                 let self_vardecl = VarDeclExpr {
-                    ident: String::from("self"),
+                    ident: Atom::from("self"),
                     type_: param_type,
                     be_capture: false,
                     heap_ident: None,
+                    doc: None,
                     symbol_start: fndef.symbol_start,
                     symbol_end: fndef.symbol_end,
                     symbol_id: None,
                 };
 
-                new_params.push(Arc::new(Mutex::new(self_vardecl)));
+                new_params.push(Arc::new(RwLock::new(self_vardecl)));
                 new_params.extend(fndef.params.iter().cloned());
                 fndef.params = new_params;
             }
@@ -502,23 +519,19 @@ impl<'a> Semantic<'a> {
 
             // 函数形参处理
             for param_mutex in &fndef.params {
-                let mut param = param_mutex.lock().unwrap();
+                let mut param = param_mutex.write().unwrap();
                 self.analyze_type(&mut param.type_);
 
                 // 将参数添加到符号表中
                 match self
                     .symbol_table
-                    .define_symbol(param.ident.clone(), SymbolKind::Var(param_mutex.clone()), param.symbol_start)
+                    .define_symbol(param.ident.to_string(), SymbolKind::Var(param_mutex.clone()), param.symbol_start)
                 {
                     Ok(symbol_id) => {
                         param.symbol_id = Some(symbol_id);
                     }
                     Err(e) => {
-                        self.errors.push(AnalyzerError {
-                            start: param.symbol_start,
-                            end: param.symbol_end,
-                            message: e,
-                        });
+                        self.errors.push(AnalyzerError::new(Span::new(param.symbol_start, param.symbol_end), e));
                     }
                 }
             }
@@ -526,7 +539,7 @@ impl<'a> Semantic<'a> {
 
         {
             let mut body = {
-                let mut fndef = fndef_mutex.lock().unwrap();
+                let mut fndef = fndef_mutex.write().unwrap();
                 std::mem::take(&mut fndef.body)
             };
 
@@ -536,7 +549,7 @@ impl<'a> Semantic<'a> {
 
             // 将当前的 fn 添加到 global fn 的 local_children 中
             {
-                let mut fndef = fndef_mutex.lock().unwrap();
+                let mut fndef = fndef_mutex.write().unwrap();
 
                 // 归还 body
                 fndef.body = body;
@@ -552,6 +565,9 @@ impl<'a> Semantic<'a> {
 
     pub fn analyze_body(&mut self, body: &mut Vec<Box<Stmt>>) {
         for stmt in body {
+            if self.cancel.is_cancelled() {
+                return;
+            }
             self.analyze_stmt(stmt);
         }
     }
@@ -559,7 +575,7 @@ impl<'a> Semantic<'a> {
     pub fn analyze_as_star_or_builtin(&mut self, ident: &str) -> Option<(NodeId, String)> {
         // import * ident
         for import in &self.imports {
-            if import.as_name == "*" {
+            if import.is_star {
                 let global_ident = format_global_ident(import.module_ident.clone(), ident.to_string());
                 if let Some(id) = self.symbol_table.find_symbol_id(&global_ident, GLOBAL_SCOPE_ID) {
                     return Some((id, global_ident));
@@ -591,7 +607,7 @@ impl<'a> Semantic<'a> {
             // find current package global ident
             if let Some(id) = self.symbol_table.find_symbol_id(&current_pkg_ident, GLOBAL_SCOPE_ID) {
                 // change ident to pkg_ident
-                *ident = current_pkg_ident;
+                *ident = Atom::from(&current_pkg_ident);
                 *symbol_id = Some(id);
                 return;
             }
@@ -600,19 +616,16 @@ impl<'a> Semantic<'a> {
             for import in &self.imports {
                 if import.as_name == *ident {
                     // ident 以及和 import 对应，则必须能够找到符号
-                    let global_ident = format_global_ident(import.module_ident.clone(), key.clone());
+                    let global_ident = format_global_ident(import.module_ident.clone(), key.to_string());
 
                     // 检查是否存在于 global import symbol 中
                     if let Some(id) = self.symbol_table.find_symbol_id(&global_ident, GLOBAL_SCOPE_ID) {
                         // expr 直接改写成 ident
-                        expr.node = AstNode::Ident(global_ident, Some(id));
+                        expr.node = AstNode::Ident(Atom::from(&global_ident), Some(id));
                         return;
                     } else {
-                        self.errors.push(AnalyzerError {
-                            start: expr.start,
-                            end: expr.end,
-                            message: format!("identifier '{}.{}' undeclared", ident, key),
-                        });
+                        self.errors
+                            .push(AnalyzerError::new(expr.span, format!("identifier '{}.{}' undeclared", ident, key)));
                         expr.err = true;
                         return;
                     }
@@ -621,16 +634,13 @@ impl<'a> Semantic<'a> {
 
             // builtin ident or import as * 也会产生 select expr, 如果是 import *
             if let Some((id, global_ident)) = self.analyze_as_star_or_builtin(ident) {
-                *ident = global_ident;
+                *ident = Atom::from(&global_ident);
                 *symbol_id = Some(id);
                 return;
             }
 
-            self.errors.push(AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: format!("identifier '{}.{}' undeclared", ident, key),
-            });
+            self.errors
+                .push(AnalyzerError::new(expr.span, format!("identifier '{}.{}' undeclared", ident, key)));
             expr.err = true;
 
             return;
@@ -639,7 +649,30 @@ impl<'a> Semantic<'a> {
         self.analyze_expr(left);
     }
 
-    pub fn analyze_ident(&mut self, ident: &mut String, symbol_id: &mut Option<NodeId>) -> bool {
+    /**
+     * parser 无法区分 @sizeof(x) 中的 x 是类型还是变量, x 能够解析为变量时返回变量名
+     */
+    fn sizeof_var_ident(&self, t: &Type) -> Option<String> {
+        let TypeKind::Alias(type_alias) = &t.kind else {
+            return None;
+        };
+        if type_alias.import_as.is_some() || type_alias.args.is_some() {
+            return None;
+        }
+
+        let ident = &type_alias.ident;
+        let symbol_id = self.symbol_table.lookup_symbol(ident).or_else(|| {
+            self.symbol_table
+                .find_symbol_id(&format_global_ident(self.module.ident.clone(), ident.clone()), GLOBAL_SCOPE_ID)
+        })?;
+
+        match self.symbol_table.find_symbol(symbol_id)?.kind {
+            SymbolKind::Var(..) => Some(ident.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn analyze_ident(&mut self, ident: &mut Atom, symbol_id: &mut Option<NodeId>) -> bool {
         // 尝试 find local or parent ident, 如果找到，将 symbol_id 添加到 Ident 中
         // symbol 可能是 parent local, 也可能是 parent fn，此时则发生闭包函数引用, 需要将 ident 改写成 env access
         if let Some(id) = self.symbol_table.lookup_symbol(ident) {
@@ -648,15 +681,15 @@ impl<'a> Semantic<'a> {
         }
 
         // current package ident
-        let current_pkg_ident = format_global_ident(self.module.ident.clone(), ident.clone());
+        let current_pkg_ident = format_global_ident(self.module.ident.clone(), ident.to_string());
         if let Some(id) = self.symbol_table.find_symbol_id(&current_pkg_ident, GLOBAL_SCOPE_ID) {
-            *ident = current_pkg_ident;
+            *ident = Atom::from(&current_pkg_ident);
             *symbol_id = Some(id);
             return true;
         }
 
         if let Some((id, global_ident)) = self.analyze_as_star_or_builtin(ident) {
-            *ident = global_ident;
+            *ident = Atom::from(&global_ident);
             *symbol_id = Some(id);
             return true;
         }
@@ -664,6 +697,35 @@ impl<'a> Semantic<'a> {
         return false;
     }
 
+    fn set_token_modifier(&mut self, start: usize, modifier: SemanticTokenModifier) {
+        let index = self.module.sem_token_db.partition_point(|t| (t.span.start as usize) < start);
+        if let Some(token) = self.module.sem_token_db.get_mut(index) {
+            if token.span.start as usize == start {
+                token.semantic_token_modifiers |= semantic_token_modifier_bit(modifier);
+            }
+        }
+    }
+
+    /**
+     * builtin 符号与 #deprecated fn 的引用点需要携带 semantic token modifier
+     */
+    fn analyze_ident_modifiers(&mut self, ident: &str, symbol_id: NodeId, start: usize) {
+        // builtin 符号注册在 global scope 中且不携带 module ident
+        if !ident.contains('.') && self.symbol_table.find_symbol_id(ident, GLOBAL_SCOPE_ID) == Some(symbol_id) {
+            self.set_token_modifier(start, SemanticTokenModifier::DEFAULT_LIBRARY);
+        }
+
+        let is_deprecated = match self.symbol_table.get_symbol(symbol_id).map(|s| &s.kind) {
+            // 递归调用时 fndef 可能已经被锁定
+            Some(SymbolKind::Fn(fndef_mutex)) => fndef_mutex.try_read().map(|fndef| fndef.is_deprecated).unwrap_or(false),
+            _ => false,
+        };
+
+        if is_deprecated {
+            self.set_token_modifier(start, SemanticTokenModifier::DEPRECATED);
+        }
+    }
+
     pub fn analyze_match(&mut self, subject: &mut Option<Box<Expr>>, cases: &mut Vec<MatchCase>) {
         let mut subject_ident: Option<String> = None;
         let mut subject_symbol_id: Option<NodeId> = None;
@@ -671,7 +733,7 @@ impl<'a> Semantic<'a> {
         if let Some(subject_expr) = subject {
             // if ident
             if let AstNode::Ident(ident, symbol_id) = &subject_expr.node {
-                subject_ident = Some(ident.clone());
+                subject_ident = Some(ident.to_string());
                 subject_symbol_id = symbol_id.clone();
             }
 
@@ -688,19 +750,15 @@ impl<'a> Semantic<'a> {
                 if let AstNode::Ident(ident, _symbol_id) = &cond.node {
                     if ident == "_" {
                         if cond_list_len != 1 {
-                            self.errors.push(AnalyzerError {
-                                start: cond.start,
-                                end: cond.end,
-                                message: "default case '_' conflict in a 'match' expression".to_string(),
-                            });
+                            self.errors
+                                .push(AnalyzerError::new(cond.span, "default case '_' conflict in a 'match' expression".to_string()));
                         }
 
                         if i != cases_len - 1 {
-                            self.errors.push(AnalyzerError {
-                                start: cond.start,
-                                end: cond.end,
-                                message: "default case '_' must be the last one in a 'match' expression".to_string(),
-                            });
+                            self.errors.push(AnalyzerError::new(
+                                cond.span,
+                                "default case '_' must be the last one in a 'match' expression".to_string(),
+                            ));
                         }
 
                         case.is_default = true;
@@ -721,10 +779,8 @@ impl<'a> Semantic<'a> {
                 let Some(subject_literal) = subject_ident.clone() else { unreachable!() };
                 let Some(cond_expr) = case.cond_list.first() else { unreachable!() };
                 let AstNode::MatchIs(target_type) = &cond_expr.node else { unreachable!() };
-                case.handle_body.insert(
-                    0,
-                    self.auto_as_stmt(cond_expr.start, cond_expr.end, &subject_literal, subject_symbol_id, target_type),
-                );
+                case.handle_body
+                    .insert(0, self.auto_as_stmt(cond_expr.span, &subject_literal, subject_symbol_id, target_type));
             }
 
             self.symbol_table.enter_create_scope(ScopeKind::Local);
@@ -739,13 +795,13 @@ impl<'a> Semantic<'a> {
         self.analyze_local_fndef(&async_expr.closure_fn_void);
 
         // closure_fn 的 fn_name 需要继承当前 fn 的 fn_name, 这样报错才会更加的精准, 当前 global 以及是 unlock 状态了，不太妥当
-        let mut fndef = async_expr.closure_fn.lock().unwrap();
+        let mut fndef = async_expr.closure_fn.write().unwrap();
         let Some(global_fn_mutex) = self.symbol_table.find_global_fn() else {
             panic!("global fn not found")
         };
 
         fndef.fn_name = {
-            let global_fn = global_fn_mutex.lock().unwrap();
+            let global_fn = global_fn_mutex.read().unwrap();
             global_fn.fn_name.clone()
         };
 
@@ -757,7 +813,7 @@ impl<'a> Semantic<'a> {
 
     pub fn analyze_expr(&mut self, expr: &mut Box<Expr>) {
         match &mut expr.node {
-            AstNode::Binary(_op, left, right) => {
+            AstNode::Binary(_, left, right) | AstNode::Range(left, right) => {
                 self.analyze_expr(left);
                 self.analyze_expr(right);
             }
@@ -776,16 +832,41 @@ impl<'a> Semantic<'a> {
                 self.analyze_type(type_);
                 self.analyze_expr(src);
             }
+            AstNode::TypeCast(_, src) => {
+                self.analyze_expr(src);
+            }
             AstNode::Is(target_type, src) => {
                 self.analyze_type(target_type);
                 self.analyze_expr(src);
             }
             AstNode::MacroSizeof(target_type) => {
+                if let Some(ident) = self.sizeof_var_ident(target_type) {
+                    expr.node = AstNode::Sizeof(Box::new(Expr::ident(target_type.span, ident, None)));
+                    self.analyze_expr(expr);
+                    return;
+                }
+
                 self.analyze_type(target_type);
             }
+            AstNode::Sizeof(src) => {
+                self.analyze_expr(src);
+            }
             AstNode::MacroUla(src) => {
                 self.analyze_expr(src);
             }
+            AstNode::MacroBitCast(target_type, src) => {
+                self.analyze_type(target_type);
+                self.analyze_expr(src);
+            }
+            AstNode::MacroAssert(cond, _) => {
+                self.analyze_expr(cond);
+            }
+            // language server 不生成代码, @min/@max 保留为 MacroMinMax 而不是改写为比较表达式, 参数的类型由 typesys 检查
+            AstNode::MacroMinMax(_, args) => {
+                for arg in args {
+                    self.analyze_expr(arg);
+                }
+            }
             AstNode::MacroReflectHash(target_type) => {
                 self.analyze_type(target_type);
             }
@@ -793,16 +874,19 @@ impl<'a> Semantic<'a> {
                 self.analyze_type(left_type);
                 self.analyze_type(right_type);
             }
-            AstNode::New(type_, properties) => {
+            AstNode::New(type_, properties, args) => {
                 self.analyze_type(type_);
                 for property in properties {
                     self.analyze_expr(&mut property.value);
                 }
+                for arg in args {
+                    self.analyze_expr(arg);
+                }
             }
-            AstNode::StructNew(_ident, type_, properties) => {
+            AstNode::StructNew(_ident, type_, entries) => {
                 self.analyze_type(type_);
-                for property in properties {
-                    self.analyze_expr(&mut property.value);
+                for entry in entries {
+                    self.analyze_expr(entry.value_mut());
                 }
             }
             AstNode::MapNew(elements) => {
@@ -838,24 +922,62 @@ impl<'a> Semantic<'a> {
             AstNode::SelectExpr(..) => self.analyze_select_expr(expr),
             AstNode::Ident(ident, symbol_id) => {
                 if !self.analyze_ident(ident, symbol_id) {
-                    self.errors.push(AnalyzerError {
-                        start: expr.start,
-                        end: expr.end,
-                        message: format!("identifier '{}' undeclared", ident),
-                    });
+                    self.errors.push(AnalyzerError::new(expr.span, format!("identifier '{}' undeclared", ident)));
                     expr.err = true;
+                } else if let Some(id) = symbol_id {
+                    self.analyze_ident_modifiers(ident, *id, expr.span.start as usize);
                 }
             }
-            AstNode::Match(subject, cases) => self.analyze_match(subject, cases),
+            AstNode::Match(subject, cases, _) => self.analyze_match(subject, cases),
             AstNode::Call(call) => self.analyze_call(call),
             AstNode::MacroAsync(async_expr) => self.analyze_async(async_expr),
             AstNode::FnDef(fndef_mutex) => self.analyze_local_fndef(fndef_mutex),
+            AstNode::LambdaShort(..) => {
+                let fndef_mutex = self.rewrite_lambda_short(expr);
+                self.analyze_local_fndef(&fndef_mutex);
+            }
             _ => {
                 return;
             }
         }
     }
 
+    /**
+     * => expr 改写为 fn 表达式, body 为 return expr, 参数与返回类型由 typesys 根据上下文中的 fn 类型确定
+     *
+     * expr 中引用了 _ 时注入参数 _, 嵌套的 => expr 中的 _ 属于内层的 lambda
+     */
+    fn rewrite_lambda_short(&mut self, expr: &mut Box<Expr>) -> Arc<RwLock<AstFnDef>> {
+        let AstNode::LambdaShort(body) = std::mem::replace(&mut expr.node, AstNode::None) else {
+            unreachable!()
+        };
+
+        let mut param_starts = Vec::new();
+        let mut nested_spans = Vec::new();
+        walk_expr(&body, &mut |e| match &e.node {
+            AstNode::Ident(ident, _) if ident == LAMBDA_SHORT_PARAM => param_starts.push(e.span.start as usize),
+            AstNode::LambdaShort(..) => nested_spans.push(e.span),
+            _ => {}
+        });
+        let uses_param = param_starts.iter().any(|start| !nested_spans.iter().any(|span| span.contains(*start)));
+
+        let span = expr.span;
+        let mut fndef = AstFnDef::synthetic(span, Type::default(), vec![Box::new(Stmt::ret(body.span, Some(body)))]);
+        fndef.is_lambda_short = true;
+        fndef.symbol_name = format!("{}_short{}", LOCAL_FN_NAME, expr.span.start);
+        fndef.fn_name = fndef.symbol_name.clone();
+        fndef.symbol_start = expr.span.start as usize;
+        fndef.symbol_end = expr.span.start as usize + "=>".len();
+        if uses_param {
+            let param = VarDeclExpr::new(LAMBDA_SHORT_PARAM, Type::default(), Span::new(fndef.symbol_start, fndef.symbol_end));
+            fndef.params.push(Arc::new(RwLock::new(param)));
+        }
+
+        let fndef_mutex = Arc::new(RwLock::new(fndef));
+        expr.node = AstNode::FnDef(fndef_mutex.clone());
+        fndef_mutex
+    }
+
     /* if (expr->assert_type == AST_VAR_DECL) {
         analyzer_var_decl(m, expr->value, true);
     } else if (expr->assert_type == AST_EXPR_TUPLE_DESTR) {
@@ -872,11 +994,8 @@ impl<'a> Semantic<'a> {
                 self.analyze_var_tuple_destr(elements);
             }
             _ => {
-                self.errors.push(AnalyzerError {
-                    start: item.start,
-                    end: item.end,
-                    message: "var tuple destr expr type exception".to_string(),
-                });
+                self.errors
+                    .push(AnalyzerError::new(item.span, "var tuple destr expr type exception".to_string()));
             }
         }
     }
@@ -902,10 +1021,10 @@ impl<'a> Semantic<'a> {
     /**
      * local fn in global fn
      */
-    pub fn analyze_local_fndef(&mut self, fndef_mutex: &Arc<Mutex<AstFnDef>>) {
+    pub fn analyze_local_fndef(&mut self, fndef_mutex: &Arc<RwLock<AstFnDef>>) {
         self.module.all_fndefs.push(fndef_mutex.clone());
 
-        let mut fndef = fndef_mutex.lock().unwrap();
+        let mut fndef = fndef_mutex.write().unwrap();
 
         // find global fn in symbol table
         let Some(global_fn_mutex) = self.symbol_table.find_global_fn() else {
@@ -918,53 +1037,47 @@ impl<'a> Semantic<'a> {
 
         // local fn 作为闭包函数, 不能进行类型扩展和泛型参数
         if fndef.impl_type.kind.is_exist() || fndef.generics_params.is_some() {
-            self.errors.push(AnalyzerError {
-                start: fndef.symbol_start,
-                end: fndef.symbol_end,
-                message: "closure fn cannot be generics or impl type alias".to_string(),
-            });
+            self.errors.push(AnalyzerError::new(
+                Span::new(fndef.symbol_start, fndef.symbol_end),
+                "closure fn cannot be generics or impl type alias".to_string(),
+            ));
         }
 
         // 闭包不能包含 macro ident
         if fndef.linkid.is_some() {
-            self.errors.push(AnalyzerError {
-                start: fndef.symbol_start,
-                end: fndef.symbol_end,
-                message: "closure fn cannot have #linkid label".to_string(),
-            });
+            self.errors.push(AnalyzerError::new(
+                Span::new(fndef.symbol_start, fndef.symbol_end),
+                "closure fn cannot have #linkid label".to_string(),
+            ));
         }
 
         if fndef.is_tpl {
-            self.errors.push(AnalyzerError {
-                start: fndef.symbol_start,
-                end: fndef.symbol_end,
-                message: "closure fn cannot be template".to_string(),
-            });
+            self.errors.push(AnalyzerError::new(
+                Span::new(fndef.symbol_start, fndef.symbol_end),
+                "closure fn cannot be template".to_string(),
+            ));
         }
 
         self.analyze_type(&mut fndef.return_type);
+        Self::wrap_async_return_type(&mut fndef);
 
         self.symbol_table.enter_create_scope(ScopeKind::LocalFn(fndef_mutex.clone()));
 
         // 形参处理
         for param_mutex in &fndef.params {
-            let mut param = param_mutex.lock().unwrap();
+            let mut param = param_mutex.write().unwrap();
             self.analyze_type(&mut param.type_);
 
             // 将参数添加到符号表中
             match self
                 .symbol_table
-                .define_symbol(param.ident.clone(), SymbolKind::Var(param_mutex.clone()), param.symbol_start)
+                .define_symbol(param.ident.to_string(), SymbolKind::Var(param_mutex.clone()), param.symbol_start)
             {
                 Ok(symbol_id) => {
                     param.symbol_id = Some(symbol_id);
                 }
                 Err(e) => {
-                    self.errors.push(AnalyzerError {
-                        start: param.symbol_start,
-                        end: param.symbol_end,
-                        message: e,
-                    });
+                    self.errors.push(AnalyzerError::new(Span::new(param.symbol_start, param.symbol_end), e));
                 }
             }
         }
@@ -996,11 +1109,7 @@ impl<'a> Semantic<'a> {
                 fndef.symbol_id = Some(symbol_id);
             }
             Err(e) => {
-                self.errors.push(AnalyzerError {
-                    start: fndef.symbol_start,
-                    end: fndef.symbol_end,
-                    message: e,
-                });
+                self.errors.push(AnalyzerError::new(Span::new(fndef.symbol_start, fndef.symbol_end), e));
             }
         }
     }
@@ -1021,11 +1130,8 @@ impl<'a> Semantic<'a> {
 
                 // condition expr cannot contains multiple is expr
                 if left_is.is_some() && right_is.is_some() {
-                    self.errors.push(AnalyzerError {
-                        start: cond.start,
-                        end: cond.end,
-                        message: "condition expr cannot contains multiple is expr".to_string(),
-                    });
+                    self.errors
+                        .push(AnalyzerError::new(cond.span, "condition expr cannot contains multiple is expr".to_string()));
                 }
 
                 return if left_is.is_some() { left_is } else { right_is };
@@ -1035,24 +1141,25 @@ impl<'a> Semantic<'a> {
         return None;
     }
 
-    pub fn auto_as_stmt(&mut self, start: usize, end: usize, subject_ident: &str, symbol_id: Option<NodeId>, target_type: &Type) -> Box<Stmt> {
+    pub fn auto_as_stmt(&mut self, span: Span, subject_ident: &str, symbol_id: Option<NodeId>, target_type: &Type) -> Box<Stmt> {
         // var x = x as T
-        let var_decl = Arc::new(Mutex::new(VarDeclExpr {
-            ident: subject_ident.to_string(),
+        let var_decl = Arc::new(RwLock::new(VarDeclExpr {
+            ident: Atom::from(subject_ident),
             type_: target_type.clone(),
             be_capture: false,
             heap_ident: None,
-            symbol_start: start,
-            symbol_end: end,
+            doc: None,
+            symbol_start: span.start as usize,
+            symbol_end: span.end as usize,
             symbol_id: None,
         }));
 
         // 创建标识符表达式作为 as 表达式的源
-        let src_expr = Box::new(Expr::ident(start, end, subject_ident.to_string(), symbol_id));
+        let src_expr = Box::new(Expr::ident(span, subject_ident.to_string(), symbol_id));
         let as_expr = Box::new(Expr {
+            id: AstNodeId::default(),
             node: AstNode::As(target_type.clone(), src_expr),
-            start,
-            end,
+            span,
             type_: Type::default(),
             target_type: Type::default(),
             err: false,
@@ -1060,9 +1167,9 @@ impl<'a> Semantic<'a> {
 
         // 创建最终的变量定义语句
         Box::new(Stmt {
+            id: AstNodeId::default(),
             node: AstNode::VarDef(var_decl, as_expr),
-            start,
-            end,
+            span,
         })
     }
 
@@ -1076,7 +1183,7 @@ impl<'a> Semantic<'a> {
 
             let AstNode::Ident(ident, symbol_id) = &src.node else { unreachable!() };
 
-            let ast_stmt = self.auto_as_stmt(is_expr.start, is_expr.end, &ident, *symbol_id, &target_type);
+            let ast_stmt = self.auto_as_stmt(is_expr.span, &ident, *symbol_id, &target_type);
             // insert ast_stmt to consequent first
             consequent.insert(0, ast_stmt);
         }
@@ -1092,7 +1199,7 @@ impl<'a> Semantic<'a> {
 
     pub fn analyze_stmt(&mut self, stmt: &mut Box<Stmt>) {
         match &mut stmt.node {
-            AstNode::Fake(expr) => {
+            AstNode::Fake(expr) | AstNode::Do(expr) => {
                 self.analyze_expr(expr);
             }
             AstNode::VarDecl(var_decl_mutex) => {
@@ -1136,11 +1243,8 @@ impl<'a> Semantic<'a> {
 
                     if case.is_default && i != len - 1 {
                         // push error
-                        self.errors.push(AnalyzerError {
-                            start: case.handle_body[0].start,
-                            end: case.handle_body[0].end,
-                            message: "default case must be the last case".to_string(),
-                        });
+                        self.errors
+                            .push(AnalyzerError::new(case.handle_body[0].span, "default case must be the last case".to_string()));
                     }
                 }
             }
@@ -1156,8 +1260,11 @@ impl<'a> Semantic<'a> {
                 self.analyze_body(body);
                 self.symbol_table.exit_scope();
             }
-            AstNode::ForIterator(iterate, first, second, body) => {
+            AstNode::ForIterator(iterate, first, second, step, body) => {
                 self.analyze_expr(iterate);
+                if let Some(step) = step {
+                    self.analyze_expr(step);
+                }
 
                 self.symbol_table.enter_create_scope(ScopeKind::Local);
                 self.analyze_var_decl(first);
@@ -1186,14 +1293,13 @@ impl<'a> Semantic<'a> {
                 }
             }
             AstNode::TypeAlias(type_alias_mutex) => {
-                let mut type_alias = type_alias_mutex.lock().unwrap();
+                let mut type_alias = type_alias_mutex.write().unwrap();
                 // local type alias 不允许携带 param
                 if type_alias.params.len() > 0 {
-                    self.errors.push(AnalyzerError {
-                        start: type_alias.symbol_start,
-                        end: type_alias.symbol_end,
-                        message: "local type alias cannot have params".to_string(),
-                    });
+                    self.errors.push(AnalyzerError::new(
+                        Span::new(type_alias.symbol_start, type_alias.symbol_end),
+                        "local type alias cannot have params".to_string(),
+                    ));
                 }
 
                 self.analyze_type(&mut type_alias.type_expr);
@@ -1207,11 +1313,8 @@ impl<'a> Semantic<'a> {
                         type_alias.symbol_id = Some(symbol_id);
                     }
                     Err(e) => {
-                        self.errors.push(AnalyzerError {
-                            start: type_alias.symbol_start,
-                            end: type_alias.symbol_end,
-                            message: e,
-                        });
+                        self.errors
+                            .push(AnalyzerError::new(Span::new(type_alias.symbol_start, type_alias.symbol_end), e));
                     }
                 }
             }
@@ -1221,25 +1324,21 @@ impl<'a> Semantic<'a> {
         }
     }
 
-    pub fn analyze_var_decl(&mut self, var_decl_mutex: &Arc<Mutex<VarDeclExpr>>) {
-        let mut var_decl = var_decl_mutex.lock().unwrap();
+    pub fn analyze_var_decl(&mut self, var_decl_mutex: &Arc<RwLock<VarDeclExpr>>) {
+        let mut var_decl = var_decl_mutex.write().unwrap();
 
         self.analyze_type(&mut var_decl.type_);
 
         // 添加到符号表，返回值 sysmbol_id 添加到 var_decl 中, 已经包含了 redeclare check
         match self
             .symbol_table
-            .define_symbol(var_decl.ident.clone(), SymbolKind::Var(var_decl_mutex.clone()), var_decl.symbol_start)
+            .define_symbol(var_decl.ident.to_string(), SymbolKind::Var(var_decl_mutex.clone()), var_decl.symbol_start)
         {
             Ok(symbol_id) => {
                 var_decl.symbol_id = Some(symbol_id);
             }
             Err(e) => {
-                self.errors.push(AnalyzerError {
-                    start: var_decl.symbol_start,
-                    end: var_decl.symbol_end,
-                    message: e,
-                });
+                self.errors.push(AnalyzerError::new(Span::new(var_decl.symbol_start, var_decl.symbol_end), e));
             }
         }
     }
@@ -0,0 +1,57 @@
+use super::lexer::Token;
+use crate::document::LineIndex;
+use lsp_types::{SemanticToken, SemanticTokensEdit};
+
+// 每个 SemanticToken 在 lsp 中被编码为 5 个 u32
+const TOKEN_ENCODE_SIZE: u32 = 5;
+
+/**
+ * 将 token 转换为 lsp 要求的相对位置编码
+ */
+pub fn encode_semantic_tokens(tokens: &[Token], line_index: &LineIndex) -> Vec<SemanticToken> {
+    let mut pre_line = 0;
+    let mut pre_start = 0;
+
+    tokens
+        .iter()
+        .filter_map(|token| {
+            let position = line_index.position(token.span.start as usize)?;
+            let (line, start) = (position.line, position.character);
+            let delta_line = line - pre_line;
+            let delta_start = if delta_line == 0 { start - pre_start } else { start };
+
+            let ret = Some(SemanticToken {
+                delta_line,
+                delta_start,
+                length: line_index.len(token.span.start as usize, token.span.end as usize) as u32,
+                token_type: token.semantic_token_type as u32,
+                token_modifiers_bitset: token.semantic_token_modifiers,
+            });
+            pre_line = line;
+            pre_start = start;
+            ret
+        })
+        .collect()
+}
+
+/**
+ * 基于公共前缀与公共后缀计算 semantic tokens delta, 由于 token 采用相对位置编码，单行编辑只会影响该行附近的 token
+ */
+pub fn semantic_tokens_delta(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old.iter().rev().zip(new.iter().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+
+    let delete_count = old.len() - prefix - suffix;
+    let data = &new[prefix..new.len() - suffix];
+    if delete_count == 0 && data.is_empty() {
+        return Vec::new();
+    }
+
+    vec![SemanticTokensEdit {
+        start: prefix as u32 * TOKEN_ENCODE_SIZE,
+        delete_count: delete_count as u32 * TOKEN_ENCODE_SIZE,
+        data: Some(data.to_vec()),
+    }]
+}
@@ -0,0 +1,361 @@
+use super::common::{AstFnDef, AstNode, TypeKind};
+use super::lexer::{Token, TokenType};
+use super::symbol::{SymbolKind, SymbolTable};
+use crate::project::Module;
+use crate::utils::format_global_ident;
+use lsp_types::{Documentation, ParameterInformation, ParameterLabel, SignatureHelp, SignatureInformation};
+use std::sync::{Arc, RwLock};
+
+/**
+ * 光标所在的最内层未闭合的 call
+ */
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    pub callee: Vec<String>, // foo(...) -> [foo], io.print(...) -> [io, print], @sizeof(...) -> [@sizeof]
+    pub active_param: usize,
+    pub left_paren: usize, // ( 的位置
+}
+
+/**
+ * 从光标位置向前扫描 token, 找到最内层未闭合的 (, 并统计同一层级中的 , 数量作为 active param
+ * 输入过程中括号可能并不匹配，所以只依赖光标之前的 token
+ */
+pub fn call_context(token_db: &[Token], token_indexes: &[usize], offset: usize) -> Option<CallContext> {
+    let tokens: Vec<&Token> = token_indexes
+        .iter()
+        .map(|i| &token_db[*i])
+        .filter(|t| t.span.end as usize <= offset && !matches!(t.token_type, TokenType::StmtEof | TokenType::Eof))
+        .collect();
+
+    let mut depth: usize = 0;
+    let mut commas = 0;
+    for (i, token) in tokens.iter().enumerate().rev() {
+        match token.token_type {
+            TokenType::RightParen | TokenType::RightSquare | TokenType::RightCurly => depth += 1,
+            TokenType::LeftParen if depth == 0 => {
+                // 找到未闭合的 (, 解析 callee
+                let callee = match i.checked_sub(1).map(|prev| tokens[prev]) {
+                    Some(prev) if prev.token_type == TokenType::MacroIdent => vec![format!("@{}", prev.literal)],
+                    _ => callee_path(&tokens[..i])?,
+                };
+                return Some(CallContext {
+                    callee,
+                    active_param: commas,
+                    left_paren: token.span.start as usize,
+                });
+            }
+            TokenType::LeftSquare if depth == 0 => {
+                // 处于 [a, b, 中, 之前统计的 , 属于 vec 元素
+                commas = 0;
+            }
+            TokenType::LeftCurly if depth == 0 => {
+                // 进入了 body 或者 struct new, 不再属于 call args
+                return None;
+            }
+            TokenType::LeftParen | TokenType::LeftSquare | TokenType::LeftCurly => depth -= 1,
+            TokenType::Comma if depth == 0 => commas += 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/**
+ * ( 之前的 ident.ident 路径, fn 声明与 Pair<int>( 这样的泛型调用不参与 signature help
+ */
+fn callee_path(tokens: &[&Token]) -> Option<Vec<String>> {
+    let mut path = Vec::new();
+    let mut i = tokens.len();
+
+    loop {
+        if i == 0 || tokens[i - 1].token_type != TokenType::Ident {
+            return None;
+        }
+        i -= 1;
+        path.insert(0, tokens[i].literal.to_string());
+
+        if i > 0 && tokens[i - 1].token_type == TokenType::Dot {
+            i -= 1;
+            continue;
+        }
+        break;
+    }
+
+    if i > 0 && tokens[i - 1].token_type == TokenType::Fn {
+        return None;
+    }
+
+    Some(path)
+}
+
+/**
+ * builtin 宏的签名, params 中的 type/expr/call 说明了参数需要的是类型, 表达式还是函数调用
+ */
+pub struct MacroSignature {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    pub rest_param: bool,
+    pub return_type: &'static str,
+    pub description: &'static str,
+}
+
+pub const MACRO_SIGNATURES: &[MacroSignature] = &[
+    MacroSignature {
+        name: "sizeof",
+        params: &["type T"],
+        rest_param: false,
+        return_type: "int",
+        description: "size of type T in bytes, evaluated at compile time",
+    },
+    MacroSignature {
+        name: "reflect_hash",
+        params: &["type T"],
+        rest_param: false,
+        return_type: "int",
+        description: "runtime reflection hash of type T",
+    },
+    MacroSignature {
+        name: "default",
+        params: &[],
+        rest_param: false,
+        return_type: "T",
+        description: "zero value of the expected type",
+    },
+    MacroSignature {
+        name: "async",
+        params: &["call fn_call", "expr flag"],
+        rest_param: false,
+        return_type: "future<T>",
+        description: "run fn_call in a new coroutine and return a future of its result",
+    },
+    MacroSignature {
+        name: "ula",
+        params: &["expr value"],
+        rest_param: false,
+        return_type: "ptr<T>",
+        description: "unsafe load address of value, value must be addressable (var, field or element access)",
+    },
+    MacroSignature {
+        name: "bitcast",
+        params: &["type T", "expr value"],
+        rest_param: false,
+        return_type: "T",
+        description: "reinterpret the bits of value as type T, also written as @bitcast<T>(value), both types must have the same size",
+    },
+    MacroSignature {
+        name: "line",
+        params: &[],
+        rest_param: false,
+        return_type: "int",
+        description: "line number of the macro call, starting from 1",
+    },
+    MacroSignature {
+        name: "file",
+        params: &[],
+        rest_param: false,
+        return_type: "string",
+        description: "path of the current source file",
+    },
+    MacroSignature {
+        name: "min",
+        params: &["expr a", "expr b", "expr rest"],
+        rest_param: true,
+        return_type: "T",
+        description: "smallest of at least 2 numbers of the same type",
+    },
+    MacroSignature {
+        name: "max",
+        params: &["expr a", "expr b", "expr rest"],
+        rest_param: true,
+        return_type: "T",
+        description: "largest of at least 2 numbers of the same type",
+    },
+    MacroSignature {
+        name: "assert",
+        params: &["expr cond"],
+        rest_param: false,
+        return_type: "void",
+        description: "panic with the source text of cond when cond is false",
+    },
+    MacroSignature {
+        name: "stringify",
+        params: &["expr value"],
+        rest_param: false,
+        return_type: "string",
+        description: "source text of value",
+    },
+];
+
+pub fn find_macro(name: &str) -> Option<&'static MacroSignature> {
+    MACRO_SIGNATURES.iter().find(|signature| signature.name == name)
+}
+
+/**
+ * @sizeof(type T):int
+ */
+pub fn macro_signature(signature: &MacroSignature, active_param: usize) -> SignatureInformation {
+    let mut label = format!("@{}(", signature.name);
+    let mut parameters = Vec::new();
+    for (i, param) in signature.params.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+
+        let param_label = if signature.rest_param && i == signature.params.len() - 1 {
+            format!("...{}", param)
+        } else {
+            param.to_string()
+        };
+
+        let start = label.chars().count() as u32;
+        label.push_str(&param_label);
+        let end = label.chars().count() as u32;
+
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+    }
+    label.push_str(&format!("):{}", signature.return_type));
+
+    let mut active_param = active_param;
+    if signature.rest_param && active_param >= signature.params.len() {
+        active_param = signature.params.len() - 1;
+    }
+
+    SignatureInformation {
+        label,
+        documentation: Some(Documentation::String(signature.description.to_string())),
+        parameters: Some(parameters),
+        active_parameter: Some(active_param as u32),
+    }
+}
+
+/**
+ * 根据 callee 查找 fn 定义, 查找顺序与 semantic 中的符号查找保持一致
+ */
+pub fn resolve_callee(m: &Module, symbol_table: &SymbolTable, callee: &[String]) -> Option<Arc<RwLock<AstFnDef>>> {
+    let mut global_idents = Vec::new();
+
+    if callee.len() == 1 {
+        let ident = &callee[0];
+
+        // 当前 module 中的 global fn
+        for stmt in &m.stmts {
+            if let AstNode::FnDef(fndef_mutex) = &stmt.node {
+                let fndef = fndef_mutex.read().unwrap();
+                if fndef.fn_name == *ident && !fndef.impl_type.kind.is_exist() {
+                    return Some(fndef_mutex.clone());
+                }
+            }
+        }
+
+        // import as * 与 builtin
+        for import in &m.dependencies {
+            if import.is_star {
+                global_idents.push(format_global_ident(import.module_ident.clone(), ident.clone()));
+            }
+        }
+        global_idents.push(ident.clone());
+    } else if callee.len() == 2 {
+        let import = m.dependencies.iter().find(|i| i.as_name == callee[0])?;
+        global_idents.push(format_global_ident(import.module_ident.clone(), callee[1].clone()));
+    }
+
+    for global_ident in global_idents {
+        if let Some(symbol) = symbol_table.find_global_symbol(&global_ident) {
+            if let SymbolKind::Fn(fndef_mutex) = &symbol.kind {
+                return Some(fndef_mutex.clone());
+            }
+        }
+    }
+
+    None
+}
+
+/**
+ * fn sum<T:int|float>(T a, ...[T] list):T
+ */
+pub fn fn_signature(fndef: &AstFnDef, active_param: usize) -> SignatureInformation {
+    let mut label = format!("fn {}", fndef.fn_name);
+
+    if let Some(generics_params) = &fndef.generics_params {
+        let params: Vec<String> = generics_params
+            .iter()
+            .map(|param| {
+                let (any, elements) = &param.constraints;
+                if *any || elements.is_empty() {
+                    param.ident.clone()
+                } else {
+                    let elements: Vec<String> = elements.iter().map(|t| t.to_string()).collect();
+                    format!("{}:{}", param.ident, elements.join("|"))
+                }
+            })
+            .collect();
+        label.push_str(&format!("<{}>", params.join(", ")));
+    }
+
+    label.push('(');
+    let mut parameters = Vec::new();
+    for (i, param_mutex) in fndef.params.iter().enumerate() {
+        let param = param_mutex.read().unwrap();
+        if i > 0 {
+            label.push_str(", ");
+        }
+
+        let param_label = if fndef.rest_param && i == fndef.params.len() - 1 {
+            format!("...{} {}", param.type_, param.ident)
+        } else {
+            format!("{} {}", param.type_, param.ident)
+        };
+
+        let start = label.chars().count() as u32;
+        label.push_str(&param_label);
+        let end = label.chars().count() as u32;
+
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+    }
+    label.push(')');
+
+    if !matches!(fndef.return_type.kind, TypeKind::Void) || fndef.is_errable {
+        label.push_str(&format!(":{}", fndef.return_type));
+        if fndef.is_errable {
+            label.push('!');
+        }
+    }
+
+    // 到达 rest param 之后 active param 固定在 rest param 上
+    let mut active_param = active_param;
+    if fndef.rest_param && !fndef.params.is_empty() && active_param >= fndef.params.len() - 1 {
+        active_param = fndef.params.len() - 1;
+    }
+
+    SignatureInformation {
+        label,
+        documentation: None,
+        parameters: Some(parameters),
+        active_parameter: Some(active_param as u32),
+    }
+}
+
+pub fn signature_help(m: &Module, symbol_table: &SymbolTable, offset: usize) -> Option<SignatureHelp> {
+    let context = call_context(&m.token_db, &m.token_indexes, offset)?;
+
+    let signature = if let Some(macro_name) = context.callee[0].strip_prefix('@') {
+        macro_signature(find_macro(macro_name)?, context.active_param)
+    } else {
+        let fndef_mutex = resolve_callee(m, symbol_table, &context.callee)?;
+        let fndef = fndef_mutex.read().unwrap();
+        fn_signature(&fndef, context.active_param)
+    };
+
+    Some(SignatureHelp {
+        active_parameter: signature.active_parameter,
+        signatures: vec![signature],
+        active_signature: Some(0),
+    })
+}
@@ -0,0 +1,263 @@
+use super::common::AnalyzerError;
+use super::diagnostic::to_severity;
+use super::lexer::{caret_indent, source_line};
+use crate::document::LineIndex;
+use lsp_types::DiagnosticSeverity;
+use std::io::IsTerminal;
+
+// 跨越超过该行数的 span 只显示首尾两行, 中间使用 ... 省略
+const MAX_SPAN_LINES: usize = 4;
+
+const STYLE_ERROR: &str = "1;31";
+const STYLE_WARNING: &str = "1;33";
+const STYLE_NOTE: &str = "1;36";
+const STYLE_GUTTER: &str = "1;34";
+const STYLE_BOLD: &str = "1";
+
+pub fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "information",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+/**
+ * 源码中的一段标注, start 与 end 为 char offset, start = end 时指向一个位置(例如缺失的 token)
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(start: usize, end: usize, message: impl Into<String>) -> Self {
+        Self {
+            start,
+            end,
+            message: message.into(),
+        }
+    }
+}
+
+/**
+ * 终端中展示的一条诊断, primary 使用 ^ 标注, secondary(同一个文件中的关联信息) 使用 - 标注, 无法在源码中标注的信息作为 note 跟随在之后
+ */
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub severity: DiagnosticSeverity,
+    pub code: Option<String>,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Snippet {
+    pub fn from_error(error: &AnalyzerError) -> Self {
+        Self {
+            severity: to_severity(error.severity),
+            code: error.code.clone(),
+            message: error.message.clone(),
+            primary: Label::new(error.span.start as usize, error.span.end as usize, ""),
+            secondary: error
+                .related
+                .iter()
+                .map(|info| Label::new(info.span.start as usize, info.span.end as usize, info.message.clone()))
+                .collect(),
+            notes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RenderConfig {
+    pub color: bool,
+}
+
+impl RenderConfig {
+    /**
+     * stdout 为终端并且没有设置 NO_COLOR(https://no-color.org) 时输出颜色
+     */
+    pub fn from_env() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self {
+            color: !no_color && std::io::stdout().is_terminal(),
+        }
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        if !self.color || text.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", style, text)
+    }
+}
+
+struct Line<'a> {
+    start: usize,  // char offset
+    text: &'a str, // 与 source_line 一致, 不包含行尾的 \r
+}
+
+impl Line<'_> {
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn indent(&self) -> usize {
+        self.text.chars().take_while(|c| c.is_whitespace()).count()
+    }
+}
+
+// 与 source_line 相同只按照 \n 拆分
+fn split_lines(source: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for text in source.split('\n') {
+        lines.push(Line {
+            start,
+            text: text.trim_end_matches('\r'),
+        });
+        start += text.chars().count() + 1;
+    }
+    lines
+}
+
+struct Mark<'a> {
+    label: &'a Label,
+    primary: bool,
+    first_line: usize,
+    last_line: usize,
+    start: usize, // 归一化之后的 char offset
+    end: usize,
+}
+
+/**
+ * 指向文件末尾的 span 在源码以换行结尾时指向最后一行的行尾, 而不是之后的空行
+ */
+fn mark<'a>(source: &str, source_len: usize, label: &'a Label, primary: bool) -> Mark<'a> {
+    let mut start = label.start.min(source_len);
+    let mut end = label.end.clamp(start, source_len);
+    if start == source_len && start > 0 && source.ends_with('\n') {
+        start -= 1;
+        end = start;
+    }
+
+    // span 以换行结尾时不占用下一行
+    let last_offset = if end > start { end - 1 } else { end };
+    let line_of = |offset: usize| source_line(source, offset).0 as usize - 1;
+    Mark {
+        label,
+        primary,
+        first_line: line_of(start),
+        last_line: line_of(last_offset),
+        start,
+        end,
+    }
+}
+
+/**
+ * 以 ariadne/rustc 的格式渲染诊断, 显示 path:line:col, 源码片段以及 span 下方的标注, 多行的 span 在每一行分别标注
+ *
+ * header 中的 line 与 col 从 1 开始, col 按照 line_index 的 position encoding 计算
+ */
+pub fn render_snippet(path: &str, source: &str, line_index: &LineIndex, snippet: &Snippet, config: RenderConfig) -> String {
+    let severity_style = match snippet.severity {
+        DiagnosticSeverity::ERROR => STYLE_ERROR,
+        DiagnosticSeverity::WARNING => STYLE_WARNING,
+        _ => STYLE_NOTE,
+    };
+
+    let mut header = severity_name(Some(snippet.severity)).to_string();
+    if let Some(code) = &snippet.code {
+        header.push_str(&format!("[{}]", code));
+    }
+    let mut out = format!(
+        "{}{}",
+        config.paint(&header, severity_style),
+        config.paint(&format!(": {}", snippet.message), STYLE_BOLD)
+    );
+
+    let lines = split_lines(source);
+    let source_len = source.chars().count();
+    let mut marks = vec![mark(source, source_len, &snippet.primary, true)];
+    let mut secondary: Vec<&Label> = snippet.secondary.iter().collect();
+    secondary.sort_by_key(|label| label.start);
+    marks.extend(secondary.into_iter().map(|label| mark(source, source_len, label, false)));
+
+    // 需要显示的行, 长的 span 只显示首尾两行
+    let mut shown: Vec<usize> = Vec::new();
+    for m in &marks {
+        if m.last_line - m.first_line + 1 > MAX_SPAN_LINES {
+            shown.extend([m.first_line, m.last_line]);
+        } else {
+            shown.extend(m.first_line..=m.last_line);
+        }
+    }
+    shown.sort();
+    shown.dedup();
+
+    let gutter_width = shown.last().map(|line| (line + 1).to_string().len()).unwrap_or(1);
+    let pad = " ".repeat(gutter_width);
+    let position = line_index.position(marks[0].start).unwrap_or_default();
+    out.push_str(&format!(
+        "\n{}{} {}:{}:{}",
+        pad,
+        config.paint("-->", STYLE_GUTTER),
+        path,
+        position.line + 1,
+        position.character + 1
+    ));
+
+    if !source.is_empty() {
+        let bar = config.paint("|", STYLE_GUTTER);
+        out.push_str(&format!("\n{} {}", pad, bar));
+
+        let mut prev: Option<usize> = None;
+        for &line_number in &shown {
+            if prev.is_some_and(|prev| line_number > prev + 1) {
+                out.push_str(&format!("\n{}", config.paint("...", STYLE_GUTTER)));
+            }
+            prev = Some(line_number);
+
+            let line = &lines[line_number];
+            let number = format!("{:>width$}", line_number + 1, width = gutter_width);
+            out.push_str(format!("\n{} {} {}", config.paint(&number, STYLE_GUTTER), bar, line.text).trim_end());
+
+            for m in marks.iter().filter(|m| m.first_line <= line_number && line_number <= m.last_line) {
+                // 多行 span 的首行标注到行尾, 之后的行从缩进之后开始标注
+                let from = if line_number == m.first_line { m.start - line.start } else { line.indent() };
+                let to = if line_number == m.last_line {
+                    m.end.saturating_sub(line.start)
+                } else {
+                    line.len()
+                };
+                let width = to.max(from).min(line.len()).saturating_sub(from).max(1);
+
+                let (marker, style) = if m.primary { ("^", severity_style) } else { ("-", STYLE_GUTTER) };
+                let mut underline = config.paint(&marker.repeat(width), style);
+                if line_number == m.last_line && !m.label.message.is_empty() {
+                    underline.push_str(&format!(" {}", config.paint(&m.label.message, style)));
+                }
+                out.push_str(&format!("\n{} {} {}{}", pad, bar, caret_indent(line.text, from), underline));
+            }
+        }
+    }
+
+    for note in &snippet.notes {
+        out.push_str(&format!(
+            "\n{} {} {}",
+            pad,
+            config.paint("=", STYLE_GUTTER),
+            config.paint(&format!("note: {}", note), STYLE_BOLD)
+        ));
+    }
+    out
+}
+
+pub fn render_error(path: &str, source: &str, line_index: &LineIndex, error: &AnalyzerError, config: RenderConfig) -> String {
+    render_snippet(path, source, line_index, &Snippet::from_error(error), config)
+}
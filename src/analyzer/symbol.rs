@@ -1,7 +1,7 @@
 use super::common::{AstFnDef, TypeAliasStmt, VarDeclExpr};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
 
 // 定义索引类型
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -40,9 +40,9 @@ impl<T> Arena<T> {
 //  引用自 AstNode
 #[derive(Debug, Clone)]
 pub enum SymbolKind {
-    Var(Arc<Mutex<VarDeclExpr>>), // 变量原始定义
-    Fn(Arc<Mutex<AstFnDef>>),
-    TypeAlias(Arc<Mutex<TypeAliasStmt>>),
+    Var(Arc<RwLock<VarDeclExpr>>), // 变量原始定义
+    Fn(Arc<RwLock<AstFnDef>>),
+    TypeAlias(Arc<RwLock<TypeAliasStmt>>),
 }
 
 // symbol table 可以同时处理多个文件的 scope, 存在一个 global scope 管理所有的全局 scope, 符号注册到 global scope 时，define_ident 需要携带 package_name 保证符号的唯一性
@@ -73,8 +73,8 @@ pub struct FreeIdent {
 #[derive(Debug, Clone)]
 pub enum ScopeKind {
     Global,
-    GlobalFn(Arc<Mutex<AstFnDef>>),
-    LocalFn(Arc<Mutex<AstFnDef>>),
+    GlobalFn(Arc<RwLock<AstFnDef>>),
+    LocalFn(Arc<RwLock<AstFnDef>>),
     Local,
 }
 
@@ -238,7 +238,7 @@ impl SymbolTable {
         None
     }
 
-    pub fn find_global_fn(&self) -> Option<Arc<Mutex<AstFnDef>>> {
+    pub fn find_global_fn(&self) -> Option<Arc<RwLock<AstFnDef>>> {
         let mut current = Some(self.current_scope_id);
 
         while let Some(scope_id) = current {
@@ -270,6 +270,23 @@ impl SymbolTable {
         }
     }
 
+    /**
+     * 移除 module 注册到 global scope 中的符号, symbol 本身依旧保留在 arena 中, 避免其他 module 持有的 symbol id 失效
+     */
+    pub fn remove_module_symbols(&mut self, module_ident: &str) {
+        let prefix = format!("{}.", module_ident);
+        let scope = self.scopes.get_mut(GLOBAL_SCOPE_ID).unwrap();
+
+        let removed: Vec<NodeId> = scope
+            .symbol_map
+            .iter()
+            .filter(|(ident, _)| ident.starts_with(&prefix))
+            .map(|(_, id)| *id)
+            .collect();
+        scope.symbol_map.retain(|ident, _| !ident.starts_with(&prefix));
+        scope.symbols.retain(|id| !removed.contains(id));
+    }
+
     pub fn symbol_exists_in_scope(&self, ident: &str, scope_id: NodeId) -> bool {
         if let Some(scope) = self.scopes.get(scope_id) {
             return scope.symbol_map.contains_key(ident);
@@ -290,6 +307,10 @@ impl SymbolTable {
         self.symbols.get_mut(id)
     }
 
+    pub fn find_symbol(&self, id: NodeId) -> Option<&Symbol> {
+        self.symbols.get(id)
+    }
+
     // 打印作用域树（用于调试）
     pub fn print_scope_tree(&self, scope_id: NodeId, indent: usize) {
         if let Some(scope) = self.scopes.get(scope_id) {
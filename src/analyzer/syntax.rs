@@ -1,29 +1,277 @@
 use super::common::*;
+use super::errors::merge_adjacent_errors;
+use super::lexer::semantic_token_modifier_bit;
 use super::lexer::semantic_token_type_index;
+use super::lexer::Lexer;
 use super::lexer::Token;
 use super::lexer::TokenType;
-use std::collections::HashMap;
+use super::printer::quote;
+use super::type_utils::render;
+use super::walk::{read_fndef, walk_body, walk_expr_mut, walk_stmt_mut, walk_stmts};
+use crate::analyzer::target_pointer_width;
+use lsp_types::{SemanticTokenModifier, SemanticTokenType};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
-use std::sync::{Arc, Mutex};
-use tower_lsp::lsp_types::SemanticTokenType;
-
-const LOCAL_FN_NAME: &str = "lambda";
+use std::num::IntErrorKind;
+use std::sync::{Arc, RwLock};
+
+pub const LOCAL_FN_NAME: &str = "lambda";
+pub const LAMBDA_SHORT_PARAM: &str = "_"; // => _ + 1 中的隐式参数
+
+/**
+ * parser 内部使用的错误, 通过 From 转换为 Diagnostic 之后对外返回
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError(pub Span, pub String);
+
+impl SyntaxError {
+    pub fn at_token(token: &Token, message: impl Into<String>) -> Self {
+        debug_assert!(token.span.start <= token.span.end, "token start {} > end {}", token.span.start, token.span.end);
+        SyntaxError(token.span, message.into())
+    }
 
-pub struct SyntaxError(usize, usize, String);
+    /**
+     * 错误位于当前尚未消费的 token
+     */
+    pub fn at_current(syntax: &Syntax, message: impl Into<String>) -> Self {
+        Self::at_token(syntax.peek(), message)
+    }
+}
 
 impl fmt::Display for SyntaxError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "SyntaxError: {}", self.2)
+        write!(f, "{}", self.1)
     }
 }
 
-impl fmt::Debug for SyntaxError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "SyntaxError: {}", self.2)
+impl Error for SyntaxError {}
+
+impl From<SyntaxError> for Diagnostic {
+    fn from(e: SyntaxError) -> Self {
+        Diagnostic::new(e.0, e.1).with_phase(DiagnosticPhase::Syntax)
     }
 }
-impl Error for SyntaxError {}
+
+/**
+ * 语法错误发生时期望出现的 token, 按照可能性排序, insert_at 为补全 token 时的插入位置
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedTokens {
+    pub span: Span,
+    pub insert_at: usize,
+    pub tokens: Vec<TokenType>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    pub source_path: String,    // @file 宏展开时使用的源文件路径
+    pub lambda_index: usize,    // local fn 名称的起始序号, 增量解析时需要避开未重新解析部分已经使用的名称
+    pub node_id: u32,           // 已经分配的最大 AstNodeId, 增量解析时新节点从其之后继续分配
+    pub require_stmt_eof: bool, // 严格模式, stmt 必须以 ; 或换行结束, 不再接受 EOF 与 } 作为隐式的结束符
+    pub pointer_width: u32,     // 编译目标的指针宽度, arr<T, N> 中的 N 不能超出该宽度的无符号整数
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            source_path: String::new(),
+            lambda_index: 0,
+            node_id: 0,
+            require_stmt_eof: false,
+            pointer_width: target_pointer_width(),
+        }
+    }
+}
+
+/**
+ * 收集顶层使用 #test 标记的函数
+ */
+pub fn collect_tests(stmts: &[Box<Stmt>]) -> Vec<Arc<RwLock<AstFnDef>>> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.node {
+            AstNode::FnDef(fndef) if fndef.read().unwrap().is_test => Some(fndef.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+ * 按出现顺序收集顶层的 import, import 只能出现在顶层因此不进入 fn body
+ */
+pub fn flatten_imports(stmts: &[Box<Stmt>]) -> Vec<&ImportStmt> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.node {
+            AstNode::Import(import) => Some(import),
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+ * 与 flatten_imports 相同但是会进入嵌套的 body, fn body 位于 mutex 中, 所以返回 clone 的 import
+ */
+pub fn flatten_imports_deep(stmts: &[Box<Stmt>]) -> Vec<ImportStmt> {
+    let mut imports = Vec::new();
+    walk_stmts(stmts, &mut |stmt: &Stmt| {
+        if let AstNode::Import(import) = &stmt.node {
+            imports.push(import.clone());
+        }
+    });
+    imports
+}
+
+/**
+ * 将单独的类型字符串解析为 Type, 例如 vec<map<string,int|null>>, 类型之后出现其他 token 时返回错误
+ */
+pub fn parse_type_str(source: &str) -> Result<Type, Diagnostic> {
+    let (token_db, token_indexes, errors) = Lexer::new(source.to_string()).scan();
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error);
+    }
+
+    let mut syntax = Syntax::new(token_db, token_indexes);
+    let t = syntax.parser_type_with_options(true, true)?;
+
+    // scanner 会在类型末尾自动插入 ;
+    syntax.consume(TokenType::StmtEof);
+    if !syntax.is(TokenType::Eof) {
+        let token = syntax.peek();
+        return Err(SyntaxError::at_token(token, format!("unexpected '{}' after type", token.literal)).into());
+    }
+
+    Ok(t)
+}
+
+/**
+ * find_decl_of 的返回值, 指向声明处的节点
+ */
+#[derive(Debug, Clone)]
+pub enum DeclNode {
+    Var(Arc<RwLock<VarDeclExpr>>),
+    Fn(Arc<RwLock<AstFnDef>>),
+}
+
+struct DeclSite {
+    ident: String,
+    start: usize,
+    end: usize,
+    scope: Option<(usize, usize)>, // 声明所在的 fn/if/for/match case/catch, None 表示全局
+    node: DeclNode,
+}
+
+/**
+ * 收集所有的声明位置, 作用域通过包含声明的最小 fn/if/for/match case/catch 的 span 近似计算
+ */
+fn collect_decl_sites(stmts: &[Box<Stmt>]) -> Vec<DeclSite> {
+    let mut scopes: Vec<(usize, usize)> = Vec::new();
+    let mut decls: Vec<(String, usize, usize, DeclNode)> = Vec::new();
+
+    let var_decl = |decls: &mut Vec<(String, usize, usize, DeclNode)>, var_decl_mutex: &Arc<RwLock<VarDeclExpr>>| {
+        let var_decl = var_decl_mutex.read().unwrap();
+        decls.push((
+            var_decl.ident.to_string(),
+            var_decl.symbol_start,
+            var_decl.symbol_end,
+            DeclNode::Var(var_decl_mutex.clone()),
+        ));
+    };
+
+    let mut visit = |node: &AstNode, start: usize, end: usize| match node {
+        AstNode::VarDef(var_decl_mutex, _) | AstNode::VarDecl(var_decl_mutex) => var_decl(&mut decls, var_decl_mutex),
+        AstNode::FnDef(fndef_mutex) => {
+            let fndef = read_fndef(fndef_mutex);
+            scopes.push((fndef.start, fndef.end));
+            if !fndef.symbol_name.is_empty() && fndef.symbol_end > fndef.symbol_start {
+                decls.push((
+                    fndef.symbol_name.clone(),
+                    fndef.symbol_start,
+                    fndef.symbol_end,
+                    DeclNode::Fn(fndef_mutex.clone()),
+                ));
+            }
+            for param in &fndef.params {
+                var_decl(&mut decls, param);
+            }
+        }
+        AstNode::ForIterator(_, first, second, _, _) => {
+            scopes.push((start, end));
+            var_decl(&mut decls, first);
+            if let Some(second) = second {
+                var_decl(&mut decls, second);
+            }
+        }
+        AstNode::TryCatch(_, catch_err, _) | AstNode::Catch(_, catch_err, _) => {
+            scopes.push((start, end));
+            var_decl(&mut decls, catch_err);
+        }
+        AstNode::Select(cases, ..) => {
+            for case in cases {
+                scopes.push((case.start, case.end));
+                if let Some(recv_var) = &case.recv_var {
+                    var_decl(&mut decls, recv_var);
+                }
+            }
+        }
+        AstNode::Match(_, cases, _) => scopes.extend(cases.iter().map(|case| (case.start, case.end))),
+        AstNode::If(..) | AstNode::ForCond(..) | AstNode::ForTradition(..) => scopes.push((start, end)),
+        _ => {}
+    };
+
+    walk_stmts(stmts, &mut |stmt| visit(&stmt.node, stmt.span.start as usize, stmt.span.end as usize));
+    walk_body(stmts, &mut |expr| visit(&expr.node, expr.span.start as usize, expr.span.end as usize));
+
+    decls
+        .into_iter()
+        .filter(|(_, start, end, _)| end > start)
+        .map(|(ident, start, end, node)| {
+            // fn 名称位于自身的 span 中, 需要排除
+            let scope = scopes
+                .iter()
+                .filter(|&&(scope_start, scope_end)| scope_start <= start && end <= scope_end)
+                .filter(|&&scope| match &node {
+                    DeclNode::Fn(fndef_mutex) => {
+                        let fndef = read_fndef(fndef_mutex);
+                        (fndef.start, fndef.end) != scope
+                    }
+                    _ => true,
+                })
+                .min_by_key(|(scope_start, scope_end)| scope_end - scope_start)
+                .copied();
+            DeclSite {
+                ident,
+                start,
+                end,
+                scope,
+                node,
+            }
+        })
+        .collect()
+}
+
+/**
+ * 去掉注释标记后的文本, 注释中的 markdown(包括 ``` 代码块) 原样保留
+ *
+ * // text -> text, /** text */ -> text, 块注释中间行的 * text -> text
+ */
+fn comment_text(token: &Token) -> String {
+    let mut text = token.literal.trim();
+    if token.token_type == TokenType::LineComment {
+        text = text.trim_start_matches('/');
+    } else {
+        text = text.strip_suffix("*/").unwrap_or(text);
+        text = match text.strip_prefix("/*") {
+            Some(rest) => rest.trim_start_matches('*'),
+            None => text.strip_prefix('*').unwrap_or(text),
+        };
+    }
+
+    let text = text.strip_prefix(' ').unwrap_or(text);
+    text.trim_end().to_string()
+}
 
 pub fn token_to_expr_op(token: &TokenType) -> ExprOp {
     match token {
@@ -32,6 +280,7 @@ pub fn token_to_expr_op(token: &TokenType) -> ExprOp {
         TokenType::Star => ExprOp::Mul,
         TokenType::Slash => ExprOp::Div,
         TokenType::Percent => ExprOp::Rem,
+        TokenType::StarStar => ExprOp::Pow,
         TokenType::EqualEqual => ExprOp::Ee,
         TokenType::NotEqual => ExprOp::Ne,
         TokenType::GreaterEqual => ExprOp::Ge,
@@ -95,11 +344,30 @@ pub fn token_to_type_kind(token: &TokenType) -> TypeKind {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Associativity {
+    Left,  // a - b - c -> (a - b) - c
+    Right, // a ** b ** c -> a ** (b ** c)
+}
+
 #[derive(Clone, Copy)]
 struct ParserRule {
     prefix: Option<fn(&mut Syntax) -> Result<Box<Expr>, SyntaxError>>,
     infix: Option<fn(&mut Syntax, Box<Expr>) -> Result<Box<Expr>, SyntaxError>>,
     infix_precedence: SyntaxPrecedence,
+    associativity: Associativity,
+}
+
+impl ParserRule {
+    /**
+     * 中缀运算符右侧表达式的最低优先级, 左结合时右侧只能包含更高优先级的运算符, 右结合时可以包含相同优先级的运算符
+     */
+    fn right_precedence(&self) -> SyntaxPrecedence {
+        match self.associativity {
+            Associativity::Left => self.infix_precedence.next().unwrap(),
+            Associativity::Right => self.infix_precedence,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
@@ -118,6 +386,7 @@ pub enum SyntaxPrecedence {
     Shift,    // << >>
     Term,     // + -
     Factor,   // * / %
+    Power,    // **
     TypeCast, // as/is
     Unary,    // - ! ~ * &
     Call,     // foo.bar foo["bar"] foo() foo().foo.bar
@@ -125,7 +394,7 @@ pub enum SyntaxPrecedence {
 }
 
 impl SyntaxPrecedence {
-    fn next(self) -> Option<Self> {
+    pub fn next(self) -> Option<Self> {
         let next_value = (self as u8).checked_add(1)?;
         if next_value <= (Self::Primary as u8) {
             // 使用 unsafe 是安全的,因为我们已经确保值在枚举范围内
@@ -142,7 +411,7 @@ pub struct Syntax {
     current: usize, // token index
 
     lambda_index: usize, // default 0
-    errors: Vec<AnalyzerError>,
+    errors: Vec<Diagnostic>,
 
     // parser 阶段辅助记录当前的 type_param, 当进入到 fn body 或者 struct def 时可以准确识别当前是 type param 还是 alias, 仅仅使用到 key
     // 默认是一个空 hashmap
@@ -153,11 +422,29 @@ pub struct Syntax {
 
     // match 表达式中 subject 的解析
     match_subject: bool,
+
+    // 已经解析的 import path -> is_star, 用于检测同一个 import 同时存在 star 和非 star 的情况
+    imports: HashMap<String, bool>,
+
+    // 解析过程中记录的期望 token, 部分错误会在回溯时被丢弃，所以需要和 errors 对照使用
+    expected_tokens: Vec<ExpectedTokens>,
+
+    config: ParserConfig,
+
+    // 原始源码, 存在时 @stringify 等宏直接截取源码, 否则通过 token 还原
+    source: Option<Arc<String>>,
+
+    // 最近一次分配的 AstNodeId, 通过 append_tokens 继续解析时不会重置
+    last_node_id: Cell<u32>,
 }
 
 impl Syntax {
     // static method new, Syntax::new(tokens)
     pub fn new(token_db: Vec<Token>, token_indexes: Vec<usize>) -> Self {
+        Self::with_config(token_db, token_indexes, ParserConfig::default())
+    }
+
+    pub fn with_config(token_db: Vec<Token>, token_indexes: Vec<usize>, config: ParserConfig) -> Self {
         Self {
             token_db: token_db,
             token_indexes: token_indexes,
@@ -166,10 +453,104 @@ impl Syntax {
             match_cond: false,
             match_subject: false,
             errors: Vec::new(),
-            lambda_index: 0,
+            lambda_index: config.lambda_index,
+            imports: HashMap::new(),
+            expected_tokens: Vec::new(),
+            last_node_id: Cell::new(config.node_id),
+            config,
+            source: None,
+        }
+    }
+
+    pub fn with_source(token_db: Vec<Token>, token_indexes: Vec<usize>, config: ParserConfig, source: Arc<String>) -> Self {
+        Self {
+            source: Some(source),
+            ..Self::with_config(token_db, token_indexes, config)
         }
     }
 
+    fn expect_tokens(&mut self, span: Span, tokens: Vec<TokenType>) {
+        // scanner 自动插入的 ; 位于换行符处, 需要插入到其之前
+        let insert_at = self
+            .prev()
+            .map(|t| if t.token_type == TokenType::StmtEof { t.span.start } else { t.span.end })
+            .unwrap_or(span.start) as usize;
+        self.expected_tokens.push(ExpectedTokens { span, insert_at, tokens });
+    }
+
+    /**
+     * 最终被报告的语法错误对应的期望 token
+     */
+    pub fn expected_tokens(&self) -> Vec<ExpectedTokens> {
+        self.expected_tokens
+            .iter()
+            .filter(|expected| self.errors.iter().any(|e| e.span == expected.span))
+            .cloned()
+            .collect()
+    }
+
+    /**
+     * 返回与 [start, end) 存在交集的顶层 stmt, stmts 需要按照 start 有序
+     */
+    pub fn statements_in_range(stmts: &[Box<Stmt>], start: usize, end: usize) -> impl Iterator<Item = &Box<Stmt>> + '_ {
+        let lower = stmts.partition_point(|stmt| (stmt.span.end as usize) <= start);
+        stmts[lower..].iter().take_while(move |stmt| (stmt.span.start as usize) < end)
+    }
+
+    /**
+     * 语法层面的查找, 不进行作用域分析, 返回所有名称为 name 的 ident 以及声明处的 span, 按照 start 排序
+     */
+    pub fn find_ident_usages(stmts: &[Box<Stmt>], name: &str) -> Vec<(usize, usize)> {
+        let mut usages: Vec<(usize, usize)> = collect_decl_sites(stmts)
+            .into_iter()
+            .filter(|decl| decl.ident == name)
+            .map(|decl| (decl.start, decl.end))
+            .collect();
+
+        walk_body(stmts, &mut |expr| {
+            if matches!(&expr.node, AstNode::Ident(ident, _) if ident == name) {
+                usages.push((expr.span.start as usize, expr.span.end as usize));
+            }
+        });
+
+        usages.sort();
+        usages.dedup();
+        usages
+    }
+
+    /**
+     * 查找 offset 处可见的 name 的声明, 局部声明需要位于 offset 之前, 全局声明在整个文件中可见, 内层作用域优先
+     */
+    pub fn find_decl_of(stmts: &[Box<Stmt>], name: &str, offset: usize) -> Option<DeclNode> {
+        Self::find_decl_site(&collect_decl_sites(stmts), name, offset).map(|decl| decl.node.clone())
+    }
+
+    fn find_decl_site<'a>(decls: &'a [DeclSite], name: &str, offset: usize) -> Option<&'a DeclSite> {
+        decls
+            .iter()
+            .filter(|decl| decl.ident == name)
+            .filter(|decl| match decl.scope {
+                Some((scope_start, scope_end)) => decl.start <= offset && offset <= scope_end && scope_start <= offset,
+                None => true,
+            })
+            .max_by_key(|decl| (decl.scope.map_or(0, |(scope_start, _)| scope_start + 1), decl.start))
+    }
+
+    /**
+     * 与 offset 处的 name 指向同一个声明的所有 ident 以及声明处的 span
+     */
+    pub fn find_references(stmts: &[Box<Stmt>], name: &str, offset: usize) -> Vec<(usize, usize)> {
+        let decls = collect_decl_sites(stmts);
+        let Some(target) = Self::find_decl_site(&decls, name, offset) else {
+            return Vec::new();
+        };
+
+        Self::find_ident_usages(stmts, name)
+            .into_iter()
+            .filter(|&(start, _)| Self::find_decl_site(&decls, name, start).is_some_and(|decl| std::ptr::eq(decl, target)))
+            .collect()
+    }
+
     fn set_current_token_type(&mut self, token_type: SemanticTokenType) {
         if self.current >= self.token_indexes.len() {
             panic!("syntax::peek: current index out of range");
@@ -178,6 +559,14 @@ impl Syntax {
         self.token_db[self.token_indexes[self.current]].semantic_token_type = semantic_token_type_index(token_type);
     }
 
+    fn set_current_token_modifier(&mut self, modifier: SemanticTokenModifier) {
+        if self.current >= self.token_indexes.len() {
+            panic!("syntax::peek: current index out of range");
+        }
+
+        self.token_db[self.token_indexes[self.current]].semantic_token_modifiers |= semantic_token_modifier_bit(modifier);
+    }
+
     fn advance(&mut self) -> &Token {
         assert!(self.current + 1 < self.token_indexes.len(), "Syntax::advance: current index out of range");
 
@@ -192,7 +581,7 @@ impl Syntax {
         let token_index = self.token_indexes[self.current];
 
         if self.token_db[token_index].token_type == TokenType::Eof {
-            return Err(SyntaxError(self.peek().start, self.peek().end, "unexpected end of file".to_string()));
+            return Err(SyntaxError::at_current(self, "unexpected end of file"));
         }
 
         self.current += 1;
@@ -238,11 +627,12 @@ impl Syntax {
 
         if token.token_type != expect {
             let message = format!("expected '{}'", expect.to_string());
-            return Err(SyntaxError(token.start, token.end, message));
+            self.expect_tokens(token.span, vec![expect]);
+            return Err(SyntaxError::at_token(&token, message));
         }
 
         if self.current + 1 >= self.token_indexes.len() {
-            return Err(SyntaxError(token.start, token.end, "unexpected end of file".to_string()));
+            return Err(SyntaxError::at_token(&token, "unexpected end of file"));
         }
 
         self.advance();
@@ -270,18 +660,34 @@ impl Syntax {
         self.is(TokenType::StmtEof) || self.is(TokenType::Eof)
     }
 
+    fn node_id(&self) -> AstNodeId {
+        self.last_node_id.set(self.last_node_id.get() + 1);
+        AstNodeId(self.last_node_id.get())
+    }
+
+    /**
+     * clone 得到的节点与原节点的 id 相同, 同时保留在 ast 中时需要重新分配 id
+     */
+    fn renumber_expr(&self, expr: &mut Expr) {
+        walk_expr_mut(expr, &mut |expr| expr.id = self.node_id(), &mut |stmt| stmt.id = self.node_id());
+    }
+
+    fn renumber_stmt(&self, stmt: &mut Stmt) {
+        walk_stmt_mut(stmt, &mut |expr| expr.id = self.node_id(), &mut |stmt| stmt.id = self.node_id());
+    }
+
     fn stmt_new(&self) -> Box<Stmt> {
         Box::new(Stmt {
-            start: self.peek().start,
-            end: self.peek().end,
+            id: self.node_id(),
+            span: self.peek().span,
             node: AstNode::None,
         })
     }
 
     fn expr_new(&self) -> Box<Expr> {
         Box::new(Expr {
-            start: self.peek().start,
-            end: self.peek().end,
+            id: self.node_id(),
+            span: self.peek().span,
             type_: Type::default(),
             target_type: Type::default(),
             node: AstNode::None,
@@ -291,8 +697,9 @@ impl Syntax {
 
     fn fake_new(&self, expr: Box<Expr>) -> Box<Stmt> {
         let mut stmt = self.stmt_new();
+        stmt.span.start = expr.span.start;
         stmt.node = AstNode::Fake(expr);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
 
         return stmt;
     }
@@ -304,148 +711,183 @@ impl Syntax {
                 prefix: Some(Self::parser_left_paren_expr),
                 infix: Some(Self::parser_call_expr),
                 infix_precedence: SyntaxPrecedence::Call,
+                associativity: Associativity::Left,
             },
             LeftSquare => ParserRule {
                 prefix: Some(Self::parser_vec_new),
                 infix: Some(Self::parser_access),
                 infix_precedence: SyntaxPrecedence::Call,
+                associativity: Associativity::Left,
             },
             LeftCurly => ParserRule {
                 prefix: Some(Self::parser_left_curly_expr),
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Null,
+                associativity: Associativity::Left,
             },
             LessThan => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Compare,
+                associativity: Associativity::Left,
             },
             LeftAngle => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_type_args_expr),
                 infix_precedence: SyntaxPrecedence::Call,
+                associativity: Associativity::Left,
             },
             MacroIdent => ParserRule {
                 prefix: Some(Self::parser_macro_call),
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Null,
+                associativity: Associativity::Left,
             },
             Dot => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_select_expr),
                 infix_precedence: SyntaxPrecedence::Call,
+                associativity: Associativity::Left,
             },
             Minus => ParserRule {
                 prefix: Some(Self::parser_unary),
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Term,
+                associativity: Associativity::Left,
             },
             Plus => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Term,
+                associativity: Associativity::Left,
             },
             Not => ParserRule {
                 prefix: Some(Self::parser_unary),
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Unary,
+                associativity: Associativity::Left,
             },
             Tilde => ParserRule {
                 prefix: Some(Self::parser_unary),
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Unary,
+                associativity: Associativity::Left,
             },
             And => ParserRule {
                 prefix: Some(Self::parser_unary),
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::And,
+                associativity: Associativity::Left,
             },
             Or => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Or,
+                associativity: Associativity::Left,
             },
             Xor => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Xor,
+                associativity: Associativity::Left,
             },
             LeftShift => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Shift,
+                associativity: Associativity::Left,
             },
             Percent => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Factor,
+                associativity: Associativity::Left,
             },
             Star => ParserRule {
                 prefix: Some(Self::parser_unary),
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Factor,
+                associativity: Associativity::Left,
+            },
+            StarStar => ParserRule {
+                prefix: None,
+                infix: Some(Self::parser_binary),
+                infix_precedence: SyntaxPrecedence::Power,
+                associativity: Associativity::Right,
             },
             Slash => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Factor,
+                associativity: Associativity::Left,
             },
             OrOr => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::OrOr,
+                associativity: Associativity::Left,
             },
             AndAnd => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::AndAnd,
+                associativity: Associativity::Left,
             },
             NotEqual | EqualEqual => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::CmpEqual,
+                associativity: Associativity::Left,
             },
 
             RightShift => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Shift,
+                associativity: Associativity::Left,
             },
 
             RightAngle | GreaterEqual | LessEqual => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_binary),
                 infix_precedence: SyntaxPrecedence::Compare,
+                associativity: Associativity::Left,
             },
             StringLiteral | IntLiteral | FloatLiteral | True | False | Null => ParserRule {
                 prefix: Some(Self::parser_literal),
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Null,
+                associativity: Associativity::Left,
             },
             As => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_as_expr),
                 infix_precedence: SyntaxPrecedence::TypeCast,
+                associativity: Associativity::Left,
             },
             Is => ParserRule {
                 prefix: Some(Self::parser_match_is_expr),
                 infix: Some(Self::parser_is_expr),
                 infix_precedence: SyntaxPrecedence::TypeCast,
+                associativity: Associativity::Left,
             },
             Catch => ParserRule {
                 prefix: None,
                 infix: Some(Self::parser_catch_expr),
                 infix_precedence: SyntaxPrecedence::Catch,
+                associativity: Associativity::Left,
             },
             Ident => ParserRule {
                 prefix: Some(Self::parser_ident_expr),
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Null,
+                associativity: Associativity::Left,
             },
             _ => ParserRule {
                 prefix: None,
                 infix: None,
                 infix_precedence: SyntaxPrecedence::Null,
+                associativity: Associativity::Left,
             },
         }
     }
@@ -470,11 +912,24 @@ impl Syntax {
             infix_token = TokenType::RightShift;
         }
 
+        // 处理相邻的 ** 合并, a * *p 中存在空白的情况依旧是乘法与解引用, 回溯后第二个 * 可能已经被标记为 StarStar
+        if infix_token == TokenType::Star
+            && self
+                .next(1)
+                .is_some_and(|next| matches!(next.token_type, TokenType::Star | TokenType::StarStar) && next.span.start == self.peek().span.end)
+        {
+            self.advance();
+
+            let token = self.peek_mut();
+            token.token_type = TokenType::StarStar;
+            infix_token = TokenType::StarStar;
+        }
+
         infix_token
     }
 
     fn must_stmt_end(&mut self) -> Result<(), SyntaxError> {
-        if self.is(TokenType::Eof) || self.is(TokenType::RightCurly) {
+        if !self.config.require_stmt_eof && (self.is(TokenType::Eof) || self.is(TokenType::RightCurly)) {
             return Ok(());
         }
 
@@ -484,14 +939,15 @@ impl Syntax {
             return Ok(());
         }
 
-        let prev_token = self.prev().unwrap();
+        let prev_token = self.prev().unwrap().clone();
         // stmt eof 失败。报告错误，并返回 false 即可
         // 获取前一个 token 的位置用于错误报告
-        return Err(SyntaxError(
-            prev_token.start,
-            prev_token.end,
-            "expected ';' or '}' at end of statement".to_string(),
-        ));
+        if self.config.require_stmt_eof {
+            self.expect_tokens(prev_token.span, vec![TokenType::StmtEof]);
+            return Err(SyntaxError::at_token(&prev_token, "expected ';' at end of statement"));
+        }
+        self.expect_tokens(prev_token.span, vec![TokenType::StmtEof, TokenType::RightCurly]);
+        return Err(SyntaxError::at_token(&prev_token, "expected ';' or '}' at end of statement"));
     }
 
     fn is_basic_type(&self) -> bool {
@@ -518,20 +974,60 @@ impl Syntax {
         )
     }
 
-    pub fn parser(&mut self) -> (Vec<Box<Stmt>>, Vec<Token>, Vec<AnalyzerError>) {
-        self.current = 0;
+    /**
+     * 流式场景(REPL, 增量编译)中向 token 流的末尾追加 token, 新 token 插入到 Eof 之前, Eof 移动到最后一个 token 之后
+     *
+     * 新 token 通常来自独立的 lexer, span 与 line 从头开始计算, 这里整体平移到原有 Eof 之后, 避免与已有的 token 重叠
+     * current 保持不变, 再次调用 parser 时从上一次停止的位置继续解析, 只返回新解析的 stmt
+     */
+    pub fn append_tokens(&mut self, new_tokens: Vec<Token>) {
+        let mut eof = self.token_db.pop().unwrap();
+        self.token_indexes.pop();
+
+        let offset = eof.span.end;
+        let line_offset = eof.line.saturating_sub(1);
+        for mut token in new_tokens {
+            token.span.start += offset;
+            token.span.end += offset;
+            token.line += line_offset;
+
+            match token.token_type {
+                // 新 token 流的 Eof 位于追加内容的末尾, 替换原有的 Eof
+                TokenType::Eof => {
+                    eof.span = token.span;
+                    eof.line = token.line;
+                    continue;
+                }
+                // 注释不参与语法解析, 只记录在 token_db 中
+                TokenType::LineComment | TokenType::BlockComment => {}
+                _ => self.token_indexes.push(self.token_db.len()),
+            }
+            self.token_db.push(token);
+        }
+
+        // 追加的 token 中没有 Eof 时, Eof 移动到最后一个 token 之后, 且不会向前移动
+        if let Some(last) = self.token_db.last().filter(|last| last.span.end > eof.span.start) {
+            eof.span = Span::new(last.span.end as usize, last.span.end as usize + 1);
+            eof.line = last.line;
+        }
+        self.token_indexes.push(self.token_db.len());
+        self.token_db.push(eof);
+    }
 
+    pub fn parser(&mut self) -> (Vec<Box<Stmt>>, Vec<Token>, Vec<Diagnostic>) {
+        // 通过 append_tokens 继续解析时只返回新产生的错误
+        let errors_start = self.errors.len();
         let mut stmt_list = Vec::new();
 
         while !self.is(TokenType::Eof) {
+            let first_index = self.token_indexes[self.current];
             match self.parser_global_stmt() {
-                Ok(stmt) => stmt_list.push(stmt),
+                Ok(stmt) => {
+                    self.attach_doc(&stmt, first_index);
+                    stmt_list.push(stmt);
+                }
                 Err(e) => {
-                    self.errors.push(AnalyzerError {
-                        start: e.0,
-                        end: e.1,
-                        message: e.2,
-                    });
+                    self.errors.push(e.into());
 
                     // 查找到下一个同步点
                     let found = self.synchronize(0);
@@ -543,7 +1039,35 @@ impl Syntax {
             }
         }
 
-        return (stmt_list, self.token_db.clone(), self.errors.clone());
+        // expected_tokens 基于 self.errors 中的原始范围进行匹配, 只合并返回的错误
+        return (stmt_list, self.token_db.clone(), merge_adjacent_errors(self.errors[errors_start..].to_vec()));
+    }
+
+    /**
+     * 检查 parser 生成的 ast 是否满足结构上的约束, 只用于诊断, 不会阻止后续的分析
+     */
+    pub fn validate_ast(stmts: &[Box<Stmt>]) -> Vec<Diagnostic> {
+        let mut errors = Vec::new();
+        let mut push_error = |stmt: &Stmt, message: &str| errors.push(Diagnostic::new(stmt.span, message).with_phase(DiagnosticPhase::Validate));
+
+        walk_stmts(stmts, &mut |stmt| match &stmt.node {
+            AstNode::FnDef(fndef) if fndef.read().unwrap().symbol_name.is_empty() => push_error(stmt, "fn definition has empty symbol name"),
+            AstNode::VarDef(var_decl, _) if var_decl.read().unwrap().ident.is_empty() => push_error(stmt, "var definition has empty ident"),
+            // parser_for_init_stmt 只会生成单个声明, 赋值或者调用语句
+            AstNode::ForTradition(init, ..)
+                if !matches!(
+                    init.node,
+                    AstNode::VarDef(..) | AstNode::VarTupleDestr(..) | AstNode::Assign(..) | AstNode::Call(..) | AstNode::Catch(..)
+                ) =>
+            {
+                push_error(init, "for init must be a single declaration, assignment or call statement")
+            }
+            // 作为语句的 catch 会丢弃表达式的值, 只有 call 才有意义
+            AstNode::Catch(try_expr, ..) if !matches!(try_expr.node, AstNode::Call(..)) => push_error(stmt, "catch statement must follow a call expression"),
+            _ => {}
+        });
+
+        errors
     }
 
     fn parser_body(&mut self) -> Result<Vec<Box<Stmt>>, SyntaxError> {
@@ -552,21 +1076,15 @@ impl Syntax {
 
         while !self.is(TokenType::RightCurly) {
             if self.is(TokenType::Eof) {
-                return Err(SyntaxError(
-                    self.prev().unwrap().start,
-                    self.prev().unwrap().end,
-                    "unexpected end of file, expected '}'".to_string(),
-                ));
+                let prev_token = self.prev().unwrap().clone();
+                self.expect_tokens(prev_token.span, vec![TokenType::RightCurly]);
+                return Err(SyntaxError::at_token(&prev_token, "unexpected end of file, expected '}'"));
             }
 
             match self.parser_stmt() {
                 Ok(stmt) => stmt_list.push(stmt),
                 Err(e) => {
-                    self.errors.push(AnalyzerError {
-                        start: e.0,
-                        end: e.1,
-                        message: e.2,
-                    });
+                    self.errors.push(e.into());
 
                     let found = self.synchronize(1);
                     if !found && !self.is(TokenType::Eof) {
@@ -649,12 +1167,12 @@ impl Syntax {
     fn parser_single_type(&mut self) -> Result<Type, SyntaxError> {
         let mut t = Type::default();
         t.status = ReductionStatus::Undo;
-        t.start = self.peek().start;
+        t.span.start = self.peek().span.start;
 
         // any union type
         if self.consume(TokenType::Any) {
             t.kind = TypeKind::Union(true, Vec::new());
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
@@ -666,67 +1184,67 @@ impl Syntax {
             t.impl_ident = Some(t.kind.to_string());
 
             if matches!(type_token.token_type, TokenType::Int | TokenType::Uint | TokenType::Float) {
-                t.origin_ident = Some(type_token.literal.clone());
+                t.origin_ident = Some(type_token.literal.to_string());
                 t.origin_type_kind = t.kind.clone();
             }
 
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // ptr<type>
         if self.consume(TokenType::Ptr) {
             self.must(TokenType::LeftAngle)?;
-            let value_type = self.parser_type()?;
+            let value_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Ptr(Box::new(value_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // [type]
         if self.consume(TokenType::LeftSquare) {
-            let element_type = self.parser_type()?;
+            let element_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::RightSquare)?;
 
             t.kind = TypeKind::Vec(Box::new(element_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // vec<type>
         if self.consume(TokenType::Vec) {
             self.must(TokenType::LeftAngle)?;
-            let element_type = self.parser_type()?;
+            let element_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Vec(Box::new(element_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // map<type,type>
         if self.consume(TokenType::Map) {
             self.must(TokenType::LeftAngle)?;
-            let key_type = self.parser_type()?;
+            let key_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::Comma)?;
-            let value_type = self.parser_type()?;
+            let value_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Map(Box::new(key_type), Box::new(value_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // set<type>
         if self.consume(TokenType::Set) {
             self.must(TokenType::LeftAngle)?;
-            let element_type = self.parser_type()?;
+            let element_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Set(Box::new(element_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
@@ -736,7 +1254,7 @@ impl Syntax {
             let mut elements = Vec::new();
 
             loop {
-                let element_type = self.parser_type()?;
+                let element_type = self.parser_type_with_options(true, true)?;
                 elements.push(element_type);
 
                 if !self.consume(TokenType::Comma) {
@@ -746,44 +1264,56 @@ impl Syntax {
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Tuple(elements, 0);
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // chan<type>
         if self.consume(TokenType::Chan) {
             self.must(TokenType::LeftAngle)?;
-            let element_type = self.parser_type()?;
+            let element_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Chan(Box::new(element_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
+            return Ok(t);
+        }
+
+        // promise<type>
+        if self.consume(TokenType::Promise) {
+            self.must(TokenType::LeftAngle)?;
+            let value_type = self.parser_type_with_options(true, true)?;
+            self.must(TokenType::RightAngle)?;
+
+            t.kind = TypeKind::Promise(Box::new(value_type));
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // arr<type,length>
         if self.consume(TokenType::Arr) {
             self.must(TokenType::LeftAngle)?;
-            let element_type = self.parser_type()?;
+            let element_type = self.parser_type_with_options(true, true)?;
             self.must(TokenType::Comma)?;
+            let pointer_width = self.config.pointer_width;
             let length_token = self.must(TokenType::IntLiteral)?;
 
-            let length = length_token
-                .literal
-                .parse::<u64>()
-                .map_err(|_| SyntaxError(length_token.start, length_token.end, "array length must be a valid integer".to_string()))?;
+            let length = length_token.literal.replace('_', "").parse::<u64>().map_err(|e| match e.kind() {
+                IntErrorKind::PosOverflow => SyntaxError::at_token(length_token, "array length overflows u64"),
+                _ => SyntaxError::at_token(length_token, "array length must be a valid integer"),
+            })?;
 
             if length == 0 {
-                return Err(SyntaxError(
-                    length_token.start,
-                    length_token.end,
-                    "array length must be greater than 0".to_string(),
-                ));
+                return Err(SyntaxError::at_token(length_token, "array length must be greater than 0"));
+            }
+            // 32 位目标上 u64 可能超出指针宽度的范围
+            if pointer_width < u64::BITS && length >> pointer_width != 0 {
+                return Err(SyntaxError::at_token(length_token, "array length exceeds platform maximum"));
             }
             self.must(TokenType::RightAngle)?;
 
             t.kind = TypeKind::Arr(length, Box::new(element_type));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
@@ -791,7 +1321,7 @@ impl Syntax {
         if self.consume(TokenType::LeftParen) {
             let mut elements = Vec::new();
             loop {
-                let element_type = self.parser_type()?;
+                let element_type = self.parser_type_with_options(true, true)?;
                 elements.push(element_type);
                 if !self.consume(TokenType::Comma) {
                     break;
@@ -800,22 +1330,22 @@ impl Syntax {
             self.must(TokenType::RightParen)?;
 
             t.kind = TypeKind::Tuple(elements, 0);
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
         // {Type:Type} or {Type}
         if self.consume(TokenType::LeftCurly) {
-            let key_type = self.parser_type()?;
+            let key_type = self.parser_type_with_options(true, true)?;
 
             if self.consume(TokenType::Colon) {
                 // map 类型
-                let value_type = self.parser_type()?;
+                let value_type = self.parser_type_with_options(true, true)?;
                 self.must(TokenType::RightCurly)?;
 
                 t.kind = TypeKind::Map(Box::new(key_type), Box::new(value_type));
 
-                t.end = self.prev().unwrap().end;
+                t.span.end = self.prev().unwrap().span.end;
                 return Ok(t);
             } else {
                 // set 类型
@@ -823,7 +1353,7 @@ impl Syntax {
 
                 t.kind = TypeKind::Set(Box::new(key_type));
 
-                t.end = self.prev().unwrap().end;
+                t.span.end = self.prev().unwrap().span.end;
                 return Ok(t);
             }
         }
@@ -836,7 +1366,7 @@ impl Syntax {
 
             while !self.is(TokenType::RightCurly) {
                 let field_type = self.parser_type()?;
-                let field_name = self.must(TokenType::Ident)?.literal.clone();
+                let field_name = self.must(TokenType::Ident)?.literal.atom();
 
                 let mut default_value = None;
 
@@ -846,11 +1376,7 @@ impl Syntax {
 
                     // 不允许是函数定义
                     if let AstNode::FnDef(_) = expr.node {
-                        return Err(SyntaxError(
-                            expr.start,
-                            expr.end,
-                            "struct field default value cannot be a function definition".to_string(),
-                        ));
+                        return Err(SyntaxError(expr.span, "struct field default value cannot be a function definition".to_string()));
                     }
 
                     default_value = Some(expr);
@@ -860,8 +1386,8 @@ impl Syntax {
                     type_: field_type.clone(),
                     key: field_name,
                     value: default_value,
-                    start: field_type.start,
-                    end: field_type.end,
+                    start: field_type.span.start as usize,
+                    end: field_type.span.end as usize,
                 });
 
                 self.must_stmt_end()?;
@@ -870,7 +1396,7 @@ impl Syntax {
             self.must(TokenType::RightCurly)?;
 
             t.kind = TypeKind::Struct("".to_string(), 0, properties);
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
@@ -881,7 +1407,7 @@ impl Syntax {
 
             if !self.consume(TokenType::RightParen) {
                 loop {
-                    let param_type = self.parser_type()?;
+                    let param_type = self.parser_type_with_options(true, true)?;
                     param_types.push(param_type);
 
                     if !self.consume(TokenType::Comma) {
@@ -891,8 +1417,9 @@ impl Syntax {
                 self.must(TokenType::RightParen)?;
             }
 
+            // 返回值之后的 | 属于返回值, fn(int):string|null 的返回值为 string|null
             let return_type = if self.consume(TokenType::Colon) {
-                self.parser_type()?
+                self.parser_type_with_options(true, true)?
             } else {
                 Type::new(TypeKind::Void)
             };
@@ -907,7 +1434,7 @@ impl Syntax {
                 tpl: false,
                 errable,
             }));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
@@ -917,10 +1444,11 @@ impl Syntax {
             let first = self.must(TokenType::Ident)?.clone();
 
             // handle param
-            if !self.type_params_table.is_empty() && self.type_params_table.contains_key(&first.literal) {
-                t.kind = TypeKind::Param(first.literal.clone());
-                t.origin_ident = Some(first.literal.clone());
+            if !self.type_params_table.is_empty() && self.type_params_table.contains_key(first.literal.as_str()) {
+                t.kind = TypeKind::Param(first.literal.to_string());
+                t.origin_ident = Some(first.literal.to_string());
                 t.origin_type_kind = t.kind.clone();
+                t.span.end = self.prev().unwrap().span.end;
                 return Ok(t);
             }
 
@@ -937,8 +1465,8 @@ impl Syntax {
                 first.clone()
             };
             let mut alias = TypeAlias {
-                ident: ident.literal,
-                import_as: if second.is_some() { Some(first.literal.clone()) } else { None },
+                ident: ident.literal.to_string(),
+                import_as: if second.is_some() { Some(first.literal.to_string()) } else { None },
                 symbol_id: None,
                 args: None,
             };
@@ -947,11 +1475,27 @@ impl Syntax {
                 t.origin_ident = Some(format!("{}.{}", import_as, alias.ident.clone()));
             }
 
-            // alias<arg1, arg2, ...>
+            // alias<arg1, arg2, ...> 或者 alias<T=arg1, U=arg2, ...>
             if self.consume(TokenType::LeftAngle) {
-                let mut args = Vec::new();
+                let mut args: Vec<TypeArg> = Vec::new();
                 loop {
-                    args.push(self.parser_single_type()?);
+                    let named = self.is(TokenType::Ident) && self.next_is(1, TokenType::Equal);
+                    if args.first().is_some_and(|first| first.name.is_some() != named) {
+                        return Err(SyntaxError::at_current(self, "named and positional type args cannot be mixed"));
+                    }
+
+                    let name = if named {
+                        let name = self.must(TokenType::Ident)?.literal.to_string();
+                        self.must(TokenType::Equal)?;
+                        Some(name)
+                    } else {
+                        None
+                    };
+
+                    args.push(TypeArg {
+                        name,
+                        type_: self.parser_type_with_options(true, true)?,
+                    });
                     if !self.consume(TokenType::Comma) {
                         break;
                     }
@@ -961,53 +1505,81 @@ impl Syntax {
             }
 
             t.kind = TypeKind::Alias(Box::new(alias));
-            t.end = self.prev().unwrap().end;
+            t.span.end = self.prev().unwrap().span.end;
             return Ok(t);
         }
 
-        return Err(SyntaxError(self.peek().start, self.peek().end, "Type definition exception".to_string()));
+        return Err(SyntaxError::at_current(self, "Type definition exception"));
     }
 
+    /**
+     * var/param/return/struct field 等声明位置的类型, 这些位置中的 union 只能通过 type alias 声明
+     */
     fn parser_type(&mut self) -> Result<Type, SyntaxError> {
-        let t = self.parser_single_type()?;
-
-        // Type|Type or Type?
-        if !self.is(TokenType::Or) && !self.is(TokenType::Question) {
-            return Ok(t);
+        let t = self.parser_type_with_options(false, true)?;
+        if self.is(TokenType::Or) {
+            return Err(SyntaxError::at_current(self, "union type only be declared in type alias"));
         }
+        Ok(t)
+    }
 
-        // T?, T? 后面不在允许直接携带 |
-        if self.consume(TokenType::Question) {
-            let mut union_t = Type::default();
-            union_t.status = ReductionStatus::Undo;
-            union_t.start = self.peek().start;
-            let mut elements = Vec::new();
-            elements.push(t);
+    /**
+     * allow_union/allow_question 控制是否消费类型之后的 | 与 ?, 未消费的 | 与 ? 留给外层处理
+     *
+     * <>, [], (), {} 中的类型以及 fn(...):R 中的参数与返回值由分隔符界定, 允许直接声明 union,
+     * 例如 vec<int|null>, (int|null, bool), fn(int|string):bool, fn(int):string|null 的返回值为 string|null
+     */
+    fn parser_type_with_options(&mut self, allow_union: bool, allow_question: bool) -> Result<Type, SyntaxError> {
+        let t = self.parser_single_type()?;
 
-            let null_type = Type::new(TypeKind::Null);
-            elements.push(null_type);
+        // T?, T? 后面不在允许直接携带 |
+        if allow_question && self.consume(TokenType::Question) {
+            let start = t.span.start;
+            let elements = vec![t, Type::new(TypeKind::Null)];
 
-            union_t.kind = TypeKind::Union(false, elements);
-            union_t.end = self.prev().unwrap().end;
+            let mut union_t = Type::undo_new(TypeKind::Union(false, elements));
+            union_t.span.start = start;
+            union_t.span.end = self.prev().unwrap().span.end;
             return Ok(union_t);
         }
 
-        if self.is(TokenType::Or) {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
-                "union type only be declared in type alias".to_string(),
-            ));
+        // Type|Type|...
+        if allow_union && self.is(TokenType::Or) {
+            let start = t.span.start;
+            let mut elements = vec![t];
+
+            while self.consume(TokenType::Or) {
+                elements.push(self.parser_single_type()?);
+            }
+
+            if self.is(TokenType::Question) {
+                return Err(SyntaxError::at_current(self, "union type declaration cannot use '?'"));
+            }
+
+            let mut union_t = Type::undo_new(TypeKind::Union(false, elements));
+            union_t.span.start = start;
+            union_t.span.end = self.prev().unwrap().span.end;
+            return Ok(union_t);
         }
 
-        return Ok(t);
+        Ok(t)
     }
 
+    /**
+     * type_params_table 只在当前声明内有效, 无论解析成功还是提前返回错误都需要清空, 避免泛型参数泄漏到后续的解析中
+     */
     fn parser_type_alias_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
+        let result = self.parser_type_alias_decl();
+        self.type_params_table = HashMap::new();
+        result
+    }
+
+    fn parser_type_alias_decl(&mut self) -> Result<Box<Stmt>, SyntaxError> {
         let mut stmt = self.stmt_new();
 
         self.must(TokenType::Type)?;
         self.set_current_token_type(SemanticTokenType::TYPE);
+        self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
         let ident_token = self.must(TokenType::Ident)?;
         let alias_ident = ident_token.clone();
 
@@ -1015,14 +1587,14 @@ impl Syntax {
         let mut alias_params = Vec::new();
         if self.consume(TokenType::LeftAngle) {
             if self.is(TokenType::RightAngle) {
-                return Err(SyntaxError(self.peek().start, self.peek().end, "type alias params cannot be empty".to_string()));
+                return Err(SyntaxError::at_current(self, "type alias params cannot be empty"));
             }
 
             // 临时保存当前的 type_params_table
             self.type_params_table = HashMap::new();
 
             loop {
-                let ident = self.must(TokenType::Ident)?.literal.clone();
+                let ident = self.must(TokenType::Ident)?.literal.to_string();
                 let mut param = GenericsParam::new(ident.clone());
 
                 // 可选的泛型类型约束 <T:t1|t2, U:t1|t2>
@@ -1052,13 +1624,14 @@ impl Syntax {
 
         self.must(TokenType::Equal)?;
 
-        let type_expr = if self.consume(TokenType::Struct) {
+        let type_start = self.peek().span.start;
+        let mut type_expr = if self.consume(TokenType::Struct) {
             self.must(TokenType::LeftCurly)?;
 
             let mut properties = Vec::new();
             while !self.is(TokenType::RightCurly) {
                 let field_type = self.parser_type()?;
-                let field_name = self.must(TokenType::Ident)?.literal.clone();
+                let field_name = self.must(TokenType::Ident)?.literal.atom();
 
                 let mut default_value = None;
 
@@ -1068,11 +1641,7 @@ impl Syntax {
 
                     // 不允许是函数定义
                     if let AstNode::FnDef(_) = expr.node {
-                        return Err(SyntaxError(
-                            expr.start,
-                            expr.end,
-                            "struct field default value cannot be a function definition".to_string(),
-                        ));
+                        return Err(SyntaxError(expr.span, "struct field default value cannot be a function definition".to_string()));
                     }
 
                     default_value = Some(expr);
@@ -1082,8 +1651,8 @@ impl Syntax {
                     type_: field_type.clone(),
                     key: field_name,
                     value: default_value,
-                    start: field_type.start,
-                    end: field_type.end,
+                    start: field_type.span.start as usize,
+                    end: field_type.span.end as usize,
                 });
 
                 self.must_stmt_end()?;
@@ -1102,11 +1671,7 @@ impl Syntax {
 
                 // 不能再接 |
                 if self.is(TokenType::Or) {
-                    return Err(SyntaxError(
-                        self.peek().start,
-                        self.peek().end,
-                        "union type declaration cannot use '?'".to_string(),
-                    ));
+                    return Err(SyntaxError::at_current(self, "union type declaration cannot use '?'"));
                 }
 
                 alias_type = Type::undo_new(TypeKind::Union(false, elements));
@@ -1128,19 +1693,20 @@ impl Syntax {
 
             alias_type
         };
+        type_expr.span.start = type_start;
+        type_expr.span.end = self.prev().unwrap().span.end;
 
-        // 恢复之前的 type_params_table
-        self.type_params_table = HashMap::new();
-
-        stmt.node = AstNode::TypeAlias(Arc::new(Mutex::new(TypeAliasStmt {
-            ident: alias_ident.literal,
-            symbol_start: alias_ident.start,
-            symbol_end: alias_ident.end,
+        stmt.node = AstNode::TypeAlias(Arc::new(RwLock::new(TypeAliasStmt {
+            ident: alias_ident.literal.to_string(),
+            symbol_start: alias_ident.span.start as usize,
+            symbol_end: alias_ident.span.end as usize,
             params: alias_params,
             type_expr,
             symbol_id: None,
+            decorators: Vec::new(),
+            doc: None,
         })));
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
 
         Ok(stmt)
     }
@@ -1148,35 +1714,35 @@ impl Syntax {
     fn expr_to_type_alias(&self, left_expr: &Expr, generics_args: Option<Vec<Type>>) -> Type {
         let mut t = Type::default();
         t.status = ReductionStatus::Undo;
-        t.start = self.peek().start;
-        t.end = self.peek().end;
+        t.span.start = left_expr.span.start;
+        t.span.end = self.prev().unwrap().span.end;
 
         // 根据左值表达式类型构造 TypeAlias
         let alias = match &left_expr.node {
             // 简单标识符: foo
             AstNode::Ident(ident, _) => {
-                t.origin_ident = Some(ident.clone());
+                t.origin_ident = Some(ident.to_string());
                 t.origin_type_kind = TypeKind::Alias(Box::new(TypeAlias::default()));
 
                 TypeAlias {
-                    ident: ident.clone(),
+                    ident: ident.to_string(),
                     import_as: None,
                     symbol_id: None,
-                    args: generics_args,
+                    args: generics_args.map(|args| args.into_iter().map(TypeArg::new).collect()),
                 }
             }
 
             // 包选择器: pkg.foo
             AstNode::SelectExpr(left, key) => {
                 if let AstNode::Ident(left_ident, _) = &left.node {
-                    t.origin_ident = Some(key.clone());
+                    t.origin_ident = Some(key.to_string());
                     t.origin_type_kind = TypeKind::Alias(Box::new(TypeAlias::default()));
 
                     TypeAlias {
-                        ident: key.clone(),
-                        import_as: Some(left_ident.clone()),
+                        ident: key.to_string(),
+                        import_as: Some(left_ident.to_string()),
                         symbol_id: None,
-                        args: generics_args,
+                        args: generics_args.map(|args| args.into_iter().map(TypeArg::new).collect()),
                     }
                 } else {
                     panic!("struct new left type exception");
@@ -1190,19 +1756,21 @@ impl Syntax {
     }
 
     // 解析变量声明
-    fn parser_var_decl(&mut self) -> Result<Arc<Mutex<VarDeclExpr>>, SyntaxError> {
+    fn parser_var_decl(&mut self) -> Result<Arc<RwLock<VarDeclExpr>>, SyntaxError> {
         let var_type = self.parser_type()?;
 
         // 变量名必须是标识符
+        self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
         let var_ident = self.must(TokenType::Ident)?;
 
-        Ok(Arc::new(Mutex::new(VarDeclExpr {
+        Ok(Arc::new(RwLock::new(VarDeclExpr {
             type_: var_type,
-            ident: var_ident.literal.clone(),
-            symbol_start: var_ident.start,
-            symbol_end: var_ident.end,
+            ident: var_ident.literal.atom(),
+            symbol_start: var_ident.span.start as usize,
+            symbol_end: var_ident.span.end as usize,
             be_capture: false,
             heap_ident: None,
+            doc: None,
             symbol_id: None,
         })))
     }
@@ -1225,11 +1793,7 @@ impl Syntax {
 
             // 可变参数必须是最后一个参数
             if fn_decl.rest_param && !self.is(TokenType::RightParen) {
-                return Err(SyntaxError(
-                    self.peek().start,
-                    self.peek().end,
-                    "can only use '...' as the final argument in the list".to_string(),
-                ));
+                return Err(SyntaxError::at_current(self, "can only use '...' as the final argument in the list"));
             }
 
             if !self.consume(TokenType::Comma) {
@@ -1244,17 +1808,17 @@ impl Syntax {
     // 解析二元表达式
     fn parser_binary(&mut self, left: Box<Expr>) -> Result<Box<Expr>, SyntaxError> {
         let mut expr = self.expr_new();
-        expr.start = left.start;
+        expr.span.start = left.span.start;
 
         let operator_token = self.safe_advance()?.clone();
 
-        // 获取运算符优先级
-        let precedence = self.find_rule(operator_token.token_type.clone()).infix_precedence;
+        // 根据运算符的优先级与结合性确定右侧表达式的优先级
+        let rule = self.find_rule(operator_token.token_type.clone());
 
-        let right = self.parser_precedence_expr(precedence.next().unwrap(), TokenType::Unknown)?;
+        let right = self.parser_precedence_expr(rule.right_precedence(), TokenType::Unknown)?;
 
         expr.node = AstNode::Binary(token_to_expr_op(&operator_token.token_type), left, right);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1327,7 +1891,7 @@ impl Syntax {
         assert!(self.is(TokenType::LeftAngle));
 
         let mut expr = self.expr_new();
-        expr.start = left.start;
+        expr.span.start = left.span.start;
 
         // 解析泛型参数
         let mut generics_args = Vec::new();
@@ -1368,26 +1932,40 @@ impl Syntax {
     }
 
     fn parser_struct_new(&mut self, type_: Type) -> Result<Box<Expr>, SyntaxError> {
+        // foo{...} 的位置从 type 开始, 从而包含 type
         let mut expr = self.expr_new();
-        let mut properties = Vec::new();
+        expr.span.start = type_.span.start;
+        let mut entries = Vec::new();
+        let mut keys = HashSet::new();
 
         self.must(TokenType::LeftCurly)?;
 
         if !self.consume(TokenType::RightCurly) {
             loop {
-                let key = self.must(TokenType::Ident)?.literal.clone();
+                // foo{a = 1, ...other, b = 2}
+                if self.consume(TokenType::Ellipsis) {
+                    entries.push(StructNewEntry::Spread(self.parser_expr()?));
+                } else {
+                    let key_token = self.must(TokenType::Ident)?.clone();
+                    let key = key_token.literal.atom();
+                    // 重复的 field 不影响后续解析, 只记录错误
+                    if !keys.insert(key) {
+                        self.errors
+                            .push(SyntaxError::at_token(&key_token, format!("duplicate field '{}' in struct literal", key)).into());
+                    }
 
-                self.must(TokenType::Equal)?;
+                    self.must(TokenType::Equal)?;
 
-                let value = self.parser_expr()?;
+                    let value = self.parser_expr()?;
 
-                properties.push(StructNewProperty {
-                    type_: Type::default(), // 类型会在语义分析阶段填充
-                    key,
-                    start: value.start,
-                    end: value.end,
-                    value,
-                });
+                    entries.push(StructNewEntry::Property(Box::new(StructNewProperty {
+                        type_: Type::default(), // 类型会在语义分析阶段填充
+                        key,
+                        start: value.span.start as usize,
+                        end: value.span.end as usize,
+                        value,
+                    })));
+                }
 
                 if !self.consume(TokenType::Comma) {
                     break;
@@ -1398,8 +1976,8 @@ impl Syntax {
             self.must(TokenType::RightCurly)?;
         }
 
-        expr.node = AstNode::StructNew(String::new(), type_, properties);
-        expr.end = self.prev().unwrap().end;
+        expr.node = AstNode::StructNew(String::new(), type_, entries);
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1414,13 +1992,13 @@ impl Syntax {
                 // 检查是否可以直接合并成字面量
                 if self.is(TokenType::IntLiteral) {
                     let int_token = self.must(TokenType::IntLiteral)?;
-                    expr.node = AstNode::Literal(TypeKind::Int, format!("-{}", int_token.literal));
+                    expr.node = AstNode::Literal(TypeKind::Int, format!("-{}", int_token.literal), LiteralSource::UserWritten);
                     return Ok(expr);
                 }
 
                 if self.is(TokenType::FloatLiteral) {
                     let float_token = self.must(TokenType::FloatLiteral)?;
-                    expr.node = AstNode::Literal(TypeKind::Float, format!("-{}", float_token.literal));
+                    expr.node = AstNode::Literal(TypeKind::Float, format!("-{}", float_token.literal), LiteralSource::UserWritten);
                     return Ok(expr);
                 }
 
@@ -1430,9 +2008,8 @@ impl Syntax {
             TokenType::And => ExprOp::La,
             TokenType::Star => ExprOp::Ia,
             _ => {
-                return Err(SyntaxError(
-                    operator_token.start,
-                    operator_token.end,
+                return Err(SyntaxError::at_token(
+                    &operator_token,
                     format!("unknown unary operator '{}'", operator_token.literal),
                 ));
             }
@@ -1440,32 +2017,33 @@ impl Syntax {
 
         let operand = self.parser_precedence_expr(SyntaxPrecedence::Unary, TokenType::Unknown)?;
         expr.node = AstNode::Unary(operator, operand);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
 
     fn parser_catch_expr(&mut self, left: Box<Expr>) -> Result<Box<Expr>, SyntaxError> {
         let mut expr = self.expr_new();
-        expr.start = left.start;
+        expr.span.start = left.span.start;
         self.must(TokenType::Catch)?;
 
         let error_ident = self.must(TokenType::Ident)?;
 
         let catch_err = VarDeclExpr {
-            ident: error_ident.literal.clone(),
-            symbol_start: error_ident.start,
-            symbol_end: error_ident.end,
+            ident: error_ident.literal.atom(),
+            symbol_start: error_ident.span.start as usize,
+            symbol_end: error_ident.span.end as usize,
             type_: Type::default(), // 实际上就是 error type
             be_capture: false,
             heap_ident: None,
+            doc: None,
             symbol_id: None,
         };
 
         let catch_body = self.parser_body()?;
 
-        expr.node = AstNode::Catch(left, Arc::new(Mutex::new(catch_err)), catch_body);
-        expr.end = self.prev().unwrap().end;
+        expr.node = AstNode::Catch(left, Arc::new(RwLock::new(catch_err)), catch_body);
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1476,9 +2054,9 @@ impl Syntax {
 
         let target_type = self.parser_single_type()?;
 
-        expr.start = left.start;
+        expr.span.start = left.span.start;
         expr.node = AstNode::As(target_type, left);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1489,17 +2067,13 @@ impl Syntax {
 
         // 确保在 match 表达式中使用 is
         if !self.match_cond {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
-                "is type must be specified in the match expression".to_string(),
-            ));
+            return Err(SyntaxError::at_current(self, "is type must be specified in the match expression"));
         }
 
         let target_type = self.parser_single_type()?;
 
         expr.node = AstNode::MatchIs(target_type);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1510,9 +2084,9 @@ impl Syntax {
 
         let target_type = self.parser_single_type()?;
 
-        expr.start = left.start;
+        expr.span.start = left.span.start;
         expr.node = AstNode::Is(target_type, left);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1548,7 +2122,7 @@ impl Syntax {
 
         let mut tuple_expr = self.expr_new();
         tuple_expr.node = AstNode::TupleNew(elements);
-        tuple_expr.end = self.prev().unwrap().end;
+        tuple_expr.span.end = self.prev().unwrap().span.end;
 
         Ok(tuple_expr)
     }
@@ -1559,8 +2133,8 @@ impl Syntax {
 
         let kind = token_to_type_kind(&literal_token.token_type);
 
-        expr.node = AstNode::Literal(kind, literal_token.literal.clone());
-        expr.end = self.prev().unwrap().end;
+        expr.node = AstNode::Literal(kind, literal_token.literal.to_string(), LiteralSource::UserWritten);
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1606,8 +2180,8 @@ impl Syntax {
         let mut expr = self.expr_new();
         let ident_token = self.must(TokenType::Ident)?;
 
-        expr.node = AstNode::Ident(ident_token.literal.clone(), None);
-        expr.end = self.prev().unwrap().end;
+        expr.node = AstNode::Ident(ident_token.literal.atom(), None);
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1619,9 +2193,9 @@ impl Syntax {
         let key = self.parser_expr()?;
         self.must(TokenType::RightSquare)?;
 
-        expr.start = left.start;
+        expr.span.start = left.span.start;
         expr.node = AstNode::AccessExpr(left, key);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1632,9 +2206,9 @@ impl Syntax {
         self.must(TokenType::Dot)?;
 
         let property_token = self.must(TokenType::Ident)?;
-        expr.start = left.start;
-        expr.node = AstNode::SelectExpr(left, property_token.literal.clone());
-        expr.end = self.prev().unwrap().end;
+        expr.span.start = left.span.start;
+        expr.node = AstNode::SelectExpr(left, property_token.literal.atom());
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -1662,18 +2236,17 @@ impl Syntax {
 
             // 可变参数必须是最后一个参数
             if call.spread && !self.is(TokenType::RightParen) {
-                return Err(SyntaxError(
-                    self.peek().start,
-                    self.peek().end,
-                    "can only use '...' as the final argument in the list".to_string(),
-                ));
+                return Err(SyntaxError::at_current(self, "can only use '...' as the final argument in the list"));
             }
 
             // call args 结尾可能存在 , 或者 ) 可以避免换行符识别异常，所以 parser 需要支持最后一个 TokenComma 可选情况
             if self.is(TokenType::RightParen) {
                 break;
-            } else {
-                self.must(TokenType::Comma)?;
+            } else if !self.consume(TokenType::Comma) {
+                // 参数之后缺少 , 时大概率是参数列表没有闭合
+                let token = self.peek().clone();
+                self.expect_tokens(token.span, vec![TokenType::RightParen, TokenType::Comma]);
+                return Err(SyntaxError::at_token(&token, "expected ')' or ','"));
             }
         }
 
@@ -1683,7 +2256,7 @@ impl Syntax {
 
     fn parser_call_expr(&mut self, left: Box<Expr>) -> Result<Box<Expr>, SyntaxError> {
         let mut expr = self.expr_new();
-        expr.start = left.start;
+        expr.span.start = left.span.start;
 
         let mut call = AstCall {
             return_type: Type::default(),
@@ -1696,7 +2269,7 @@ impl Syntax {
         call.args = self.parser_args(&mut call)?;
 
         expr.node = AstNode::Call(call);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
         Ok(expr)
     }
 
@@ -1725,7 +2298,7 @@ impl Syntax {
         };
 
         stmt.node = AstNode::If(condition, consequent, alternate);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
 
         Ok(stmt)
     }
@@ -1740,7 +2313,7 @@ impl Syntax {
             let t = &self.token_db[self.token_indexes[pos]];
 
             if t.token_type == TokenType::Eof {
-                return Err(SyntaxError(self.peek().start, self.peek().end, "unexpected end of file".to_string()));
+                return Err(SyntaxError::at_current(self, "unexpected end of file"));
             }
 
             if close == 0 && t.token_type == TokenType::StmtEof {
@@ -1763,11 +2336,7 @@ impl Syntax {
         }
 
         if semicolon_count != 0 && semicolon_count != 2 {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
-                "for statement must have two semicolons".to_string(),
-            ));
+            return Err(SyntaxError::at_current(self, "for statement must have two semicolons"));
         }
 
         Ok(semicolon_count == 2)
@@ -1795,7 +2364,7 @@ impl Syntax {
         // 内置复合类型
         if matches!(
             self.peek().token_type,
-            TokenType::Arr | TokenType::Map | TokenType::Tup | TokenType::Vec | TokenType::Set | TokenType::Chan
+            TokenType::Arr | TokenType::Map | TokenType::Tup | TokenType::Vec | TokenType::Set | TokenType::Chan | TokenType::Promise
         ) {
             return true;
         }
@@ -1863,33 +2432,40 @@ impl Syntax {
             let body = self.parser_body()?;
 
             stmt.node = AstNode::ForTradition(init, cond, update, body);
-            stmt.end = self.prev().unwrap().end;
+            stmt.span.end = self.prev().unwrap().span.end;
 
             return Ok(stmt);
         }
 
         // for k,v in map {}
         if self.is(TokenType::Ident) && (self.next_is(1, TokenType::Comma) || self.next_is(1, TokenType::In)) {
+            // 迭代变量不允许修改
+            self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
+            self.set_current_token_modifier(SemanticTokenModifier::READONLY);
             let first_ident = self.must(TokenType::Ident)?;
             let first = VarDeclExpr {
                 type_: Type::default(),
-                ident: first_ident.literal.clone(),
-                symbol_start: first_ident.start,
-                symbol_end: first_ident.end,
+                ident: first_ident.literal.atom(),
+                symbol_start: first_ident.span.start as usize,
+                symbol_end: first_ident.span.end as usize,
                 be_capture: false,
                 symbol_id: None,
                 heap_ident: None,
+                doc: None,
             };
 
             let second = if self.consume(TokenType::Comma) {
+                self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
+                self.set_current_token_modifier(SemanticTokenModifier::READONLY);
                 let second_ident = self.must(TokenType::Ident)?;
-                Some(Arc::new(Mutex::new(VarDeclExpr {
+                Some(Arc::new(RwLock::new(VarDeclExpr {
                     type_: Type::default(),
-                    ident: second_ident.literal.clone(),
-                    symbol_start: second_ident.start,
-                    symbol_end: second_ident.end,
+                    ident: second_ident.literal.atom(),
+                    symbol_start: second_ident.span.start as usize,
+                    symbol_end: second_ident.span.end as usize,
                     be_capture: false,
                     heap_ident: None,
+                    doc: None,
                     symbol_id: None,
                 })))
             } else {
@@ -1897,10 +2473,31 @@ impl Syntax {
             };
 
             self.must(TokenType::In)?;
-            let iterate = self.parser_precedence_expr(SyntaxPrecedence::TypeCast, TokenType::Unknown)?;
+            let mut iterate = self.parser_precedence_expr(SyntaxPrecedence::TypeCast, TokenType::Unknown)?;
+
+            // for i in 0..10
+            if self.consume(TokenType::DotDot) {
+                let end = self.parser_precedence_expr(SyntaxPrecedence::TypeCast, TokenType::Unknown)?;
+                let mut range = self.expr_new();
+                range.span.start = iterate.span.start;
+                range.span.end = end.span.end;
+                range.node = AstNode::Range(iterate, end);
+                iterate = range;
+            }
+
+            // step 是软关键字, 只在 for in 的迭代表达式之后生效
+            let step = if self.is(TokenType::Ident) && self.peek().literal == "step" {
+                self.set_current_token_type(SemanticTokenType::KEYWORD);
+                self.advance();
+                Some(self.parser_precedence_expr(SyntaxPrecedence::TypeCast, TokenType::Unknown)?)
+            } else {
+                None
+            };
+
             let body = self.parser_body()?;
 
-            stmt.node = AstNode::ForIterator(iterate, Arc::new(Mutex::new(first)), second, body);
+            stmt.node = AstNode::ForIterator(iterate, Arc::new(RwLock::new(first)), second, step, body);
+            stmt.span.end = self.prev().unwrap().span.end;
 
             return Ok(stmt);
         }
@@ -1910,22 +2507,37 @@ impl Syntax {
         let body = self.parser_body()?;
 
         stmt.node = AstNode::ForCond(condition, body);
+        stmt.span.end = self.prev().unwrap().span.end;
 
         Ok(stmt)
     }
 
+    /**
+     * 赋值语句与元组解构的左值检查, 错误位于不可赋值的表达式本身
+     */
+    fn check_lvalue(expr: &Expr) -> Result<(), SyntaxError> {
+        if expr.node.can_assign() {
+            return Ok(());
+        }
+        Err(SyntaxError(expr.span, format!("cannot assign to {}", expr.node.describe())))
+    }
+
     fn parser_assign(&mut self, left: Box<Expr>) -> Result<Box<Stmt>, SyntaxError> {
         let mut stmt = self.stmt_new();
 
-        let stmt_start = left.start.clone();
+        if self.is(TokenType::Equal) || self.peek().is_complex_assign() {
+            Self::check_lvalue(&left)?;
+        }
+
+        let stmt_start = left.span.start.clone();
 
         // 简单赋值
         if self.consume(TokenType::Equal) {
             let right = self.parser_expr()?;
 
             stmt.node = AstNode::Assign(left, right);
-            stmt.start = stmt_start;
-            stmt.end = self.prev().unwrap().end;
+            stmt.span.start = stmt_start;
+            stmt.span.end = self.prev().unwrap().span.end;
 
             return Ok(stmt);
         }
@@ -1933,16 +2545,26 @@ impl Syntax {
         // 复合赋值
         let t = self.safe_advance()?.clone();
         if !t.is_complex_assign() {
-            return Err(SyntaxError(t.start, t.end, format!("expected '=' actual '{}'", t.token_type)));
+            return Err(SyntaxError::at_token(&t, format!("expected '=' actual '{}'", t.token_type)));
         }
 
-        let mut right = self.expr_new();
-        right.node = AstNode::Binary(token_to_expr_op(&t.token_type), left.clone(), self.parser_expr_with_precedence()?);
-        right.end = self.prev().unwrap().end;
+        // a += 1 展开为 a = a + 1, a + 1 的位置覆盖整个赋值语句, 从而包含 clone 得到的 a
+        let right_id = self.node_id();
+        let mut binary_left = left.clone();
+        self.renumber_expr(&mut binary_left);
+        let value = self.parser_expr_with_precedence()?;
+        let span = Span {
+            start: stmt_start,
+            end: self.prev().unwrap().span.end,
+        };
+        let right = Box::new(Expr {
+            id: right_id,
+            ..Expr::binary(span, token_to_expr_op(&t.token_type), binary_left, value)
+        });
 
         stmt.node = AstNode::Assign(left, right);
-        stmt.start = stmt_start;
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.start = stmt_start;
+        stmt.span.end = self.prev().unwrap().span.end;
 
         Ok(stmt)
     }
@@ -1950,39 +2572,33 @@ impl Syntax {
     fn parser_expr_begin_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
         let left = self.parser_expr()?;
 
-        // 处理函数调用语句
-        if let AstNode::Call(call) = left.node {
-            if self.is(TokenType::Equal) {
-                return Err(SyntaxError(self.peek().start, self.peek().end, "call expr cannot assign".to_string()));
+        // 处理函数调用语句, 调用之后紧跟赋值时由 parser_assign 报告左值错误
+        if !self.is(TokenType::Equal) && !self.peek().is_complex_assign() {
+            if let AstNode::Call(call) = left.node {
+                let mut stmt = self.stmt_new();
+                stmt.node = AstNode::Call(call);
+                stmt.span.start = left.span.start;
+                stmt.span.end = self.prev().unwrap().span.end;
+                return Ok(stmt);
             }
-
-            let mut stmt = self.stmt_new();
-            stmt.node = AstNode::Call(call);
-            stmt.start = left.start;
-            stmt.end = self.prev().unwrap().end;
-            return Ok(stmt);
         }
 
         // 处理 catch 语句
         if let AstNode::Catch(try_expr, catch_err, catch_body) = left.node {
             if self.is(TokenType::Equal) || self.is(TokenType::Catch) {
-                return Err(SyntaxError(
-                    self.peek().start,
-                    self.peek().end,
-                    "catch expr cannot assign or immediately next catch".to_string(),
-                ));
+                return Err(SyntaxError::at_current(self, "catch expr cannot assign or immediately next catch"));
             }
 
             let mut stmt = self.stmt_new();
             stmt.node = AstNode::Catch(try_expr, catch_err, catch_body);
-            stmt.start = left.start;
-            stmt.end = self.prev().unwrap().end;
+            stmt.span.start = left.span.start;
+            stmt.span.end = self.prev().unwrap().span.end;
             return Ok(stmt);
         }
 
         // 检查表达式完整性
         if self.is_stmt_eof() {
-            return Err(SyntaxError(self.peek().start, self.peek().end, "expr incompleteness".to_string()));
+            return Err(SyntaxError::at_current(self, "expr incompleteness"));
         }
 
         // 处理赋值语句
@@ -2000,7 +2616,7 @@ impl Syntax {
         };
 
         stmt.node = AstNode::Break(expr);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2009,7 +2625,7 @@ impl Syntax {
         self.must(TokenType::Continue)?;
 
         stmt.node = AstNode::Continue;
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2024,7 +2640,7 @@ impl Syntax {
         };
 
         stmt.node = AstNode::Return(expr);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2033,48 +2649,84 @@ impl Syntax {
         self.must(TokenType::Import)?;
 
         let token = self.safe_advance()?.clone();
-        let mut import_end = token.end;
+        let mut import_end = token.span.end;
 
         let (file, ast_package) = if token.token_type == TokenType::StringLiteral {
-            (Some(token.literal.clone()), None)
+            (Some(token.literal.to_string()), None)
         } else if token.token_type == TokenType::Ident {
-            let mut package = vec![token.literal.clone()];
+            let mut package = vec![token.literal.to_string()];
             while self.consume(TokenType::Dot) {
                 let ident = self.must(TokenType::Ident)?;
-                package.push(ident.literal.clone());
-                import_end = ident.end;
+                package.push(ident.literal.to_string());
+                import_end = ident.span.end;
             }
             (None, Some(package))
         } else {
-            return Err(SyntaxError(token.start, token.end, "import token must be string or ident".to_string()));
+            return Err(SyntaxError::at_token(&token, "import token must be string or ident"));
+        };
+
+        // import math@2.0, lexer 将 @v2 识别为 MacroIdent
+        let version = if ast_package.is_some() && self.is(TokenType::MacroIdent) {
+            let t = self.advance().clone();
+            import_end = t.span.end;
+            Some(t.literal.to_string())
+        } else if ast_package.is_some() && self.consume(TokenType::At) {
+            let t = self.peek().clone();
+            if !matches!(t.token_type, TokenType::IntLiteral | TokenType::FloatLiteral | TokenType::Ident) {
+                return Err(SyntaxError::at_token(&t, "expected package version after '@'"));
+            }
+            self.advance();
+            import_end = t.span.end;
+            Some(t.literal.to_string())
+        } else {
+            None
         };
 
         let as_name = if self.consume(TokenType::As) {
             let t = self.safe_advance()?.clone();
 
             if !matches!(t.token_type, TokenType::Ident | TokenType::ImportStar) {
-                return Err(SyntaxError(t.start, t.end, "import as token must be ident or *".to_string()));
+                return Err(SyntaxError::at_token(&t, "import as token must be ident or *"));
             }
-            t.literal.clone()
+            t.literal.to_string()
         } else {
             "".to_string()
         };
+        let is_star = as_name == "*";
+
+        // import math 与 import math as * 同时存在时给出提示
+        let import_path = file.clone().unwrap_or_else(|| ast_package.as_ref().unwrap().join("."));
+        if let Some(exists_star) = self.imports.get(&import_path) {
+            if *exists_star != is_star {
+                // 不影响解析结果, 只作为 warning
+                let error: Diagnostic = SyntaxError(
+                    stmt.span.merge(self.prev().unwrap().span),
+                    format!("import '{}' is duplicated, with and without star", import_path),
+                )
+                .into();
+                self.errors.push(error.with_severity(Severity::Warning));
+            }
+        } else {
+            self.imports.insert(import_path, is_star);
+        }
 
         stmt.node = AstNode::Import(ImportStmt {
             file,
             ast_package,
             as_name,
+            is_star,
+            version,
             module_type: 0,
             full_path: String::new(),
             package_conf: None,
             package_dir: String::new(),
             use_links: false,
             module_ident: String::new(),
-            start: stmt.start,
-            end: import_end,
+            start: stmt.span.start as usize,
+            end: import_end as usize,
         });
 
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2096,7 +2748,7 @@ impl Syntax {
         }
 
         expr.node = AstNode::VecNew(elements, None, None);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -2108,7 +2760,7 @@ impl Syntax {
         self.must(TokenType::LeftCurly)?;
         if self.consume(TokenType::RightCurly) {
             expr.node = AstNode::EmptyCurlyNew;
-            expr.end = self.prev().unwrap().end;
+            expr.span.end = self.prev().unwrap().span.end;
             return Ok(expr);
         }
 
@@ -2134,7 +2786,7 @@ impl Syntax {
             self.must(TokenType::RightCurly)?;
 
             expr.node = AstNode::MapNew(elements);
-            expr.end = self.prev().unwrap().end;
+            expr.span.end = self.prev().unwrap().span.end;
             return Ok(expr);
         }
 
@@ -2149,25 +2801,66 @@ impl Syntax {
 
         self.must(TokenType::RightCurly)?;
         expr.node = AstNode::SetNew(elements);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
 
-    fn parser_fndef_expr(&mut self) -> Result<Box<Expr>, SyntaxError> {
+    /**
+     * => expr, 参数与返回类型均由上下文推导, 例如 list.map(=> _ + 1)
+     */
+    fn parser_lambda_short(&mut self) -> Result<Box<Expr>, SyntaxError> {
         let mut expr = self.expr_new();
-        let start = self.peek().start;
-        let end = self.peek().end;
-
-        self.must(TokenType::Fn)?;
+        self.must(TokenType::FatArrow)?;
 
-        let mut fndef = AstFnDef::default();
-        fndef.start = start;
-        fndef.end = end;
+        let body = self.parser_expr()?;
+        expr.node = AstNode::LambdaShort(body);
+        expr.span.end = self.prev().unwrap().span.end;
+        Ok(expr)
+    }
+
+    /**
+     * fndef 的返回类型, 多个返回类型 fn foo(): int, string 等价于 fn foo(): (int, string)
+     *
+     * fn 类型(例如 map<string, fn():int>)中的逗号用于分隔泛型参数, 因此只在 fndef 中使用
+     */
+    fn parser_return_type(&mut self) -> Result<Type, SyntaxError> {
+        let first = self.parser_type()?;
+        if !self.is(TokenType::Comma) {
+            return Ok(first);
+        }
+
+        let start = first.span.start;
+        let mut elements = vec![first];
+        while self.consume(TokenType::Comma) {
+            elements.push(self.parser_type()?);
+        }
+
+        Ok(Type {
+            kind: TypeKind::Tuple(elements, 0),
+            status: ReductionStatus::Undo,
+            span: Span {
+                start,
+                end: self.prev().unwrap().span.end,
+            },
+            ..Default::default()
+        })
+    }
+
+    fn parser_fndef_expr(&mut self) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        let start = self.peek().span.start as usize;
+        let end = self.peek().span.end as usize;
+
+        self.must(TokenType::Fn)?;
+
+        let mut fndef = AstFnDef::default();
+        fndef.start = start;
+        fndef.end = end;
 
         if self.is(TokenType::Ident) {
             // fn expr 不能包含名称
-            return Err(SyntaxError(self.peek().start, self.peek().end, "local fn must be anonymous".to_string()));
+            return Err(SyntaxError::at_current(self, "local fn must be anonymous"));
         } else {
             // gen unique lambda name
             let name = format!("{}{}", LOCAL_FN_NAME, self.lambda_index);
@@ -2181,7 +2874,7 @@ impl Syntax {
 
         // parse return type
         if self.consume(TokenType::Colon) {
-            fndef.return_type = self.parser_type()?;
+            fndef.return_type = self.parser_return_type()?;
         } else {
             fndef.return_type = Type::new(TypeKind::Void);
         }
@@ -2191,7 +2884,7 @@ impl Syntax {
         }
 
         fndef.body = self.parser_body()?;
-        expr.node = AstNode::FnDef(Arc::new(Mutex::new(fndef)));
+        expr.node = AstNode::FnDef(Arc::new(RwLock::new(fndef)));
 
         // parse immediately call fn expr
         if self.is(TokenType::LeftParen) {
@@ -2206,11 +2899,11 @@ impl Syntax {
 
             let mut call_expr = self.expr_new();
             call_expr.node = AstNode::Call(call);
-            call_expr.end = self.prev().unwrap().end;
+            call_expr.span.end = self.prev().unwrap().span.end;
             return Ok(call_expr);
         }
 
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -2220,24 +2913,56 @@ impl Syntax {
         self.must(TokenType::New)?;
 
         let t = self.parser_type()?;
-        self.must(TokenType::LeftParen)?;
         let mut properties: Vec<StructNewProperty> = Vec::new();
+        let mut args: Vec<Box<Expr>> = Vec::new();
+
+        // new Foo
+        if !self.is(TokenType::LeftParen) {
+            expr.node = AstNode::New(t, properties, args);
+            expr.span.end = self.prev().unwrap().span.end;
+            return Ok(expr);
+        }
+
+        // new Foo(args), 构造参数与 key=value 形式的 properties 通过 ( 后的 ident = 区分
+        let is_properties = self.next_is(1, TokenType::RightParen) || (self.next_is(1, TokenType::Ident) && self.next_is(2, TokenType::Equal));
+        if !is_properties {
+            let mut call = AstCall {
+                return_type: Type::default(),
+                left: self.expr_new(),
+                generics_args: Vec::new(),
+                args: Vec::new(),
+                spread: false,
+            };
 
+            args = self.parser_args(&mut call)?;
+            if call.spread {
+                return Err(SyntaxError(
+                    expr.span.merge(self.prev().unwrap().span),
+                    "new expr cannot use '...' argument".to_string(),
+                ));
+            }
+
+            expr.node = AstNode::New(t, properties, args);
+            expr.span.end = self.prev().unwrap().span.end;
+            return Ok(expr);
+        }
+
+        self.must(TokenType::LeftParen)?;
         if !self.consume(TokenType::RightParen) {
             loop {
                 if self.is(TokenType::RightParen) {
                     break;
                 }
 
-                let start = self.peek().start;
+                let start = self.peek().span.start as usize;
                 let key = self.must(TokenType::Ident)?.clone();
                 self.must(TokenType::Equal)?;
                 let value = self.parser_expr()?;
-                let end = value.end;
+                let end = value.span.end as usize;
 
                 properties.push(StructNewProperty {
                     type_: Type::default(),
-                    key: key.literal,
+                    key: key.literal.atom(),
                     value,
                     start,
                     end,
@@ -2252,8 +2977,8 @@ impl Syntax {
             self.must(TokenType::RightParen)?;
         }
 
-        expr.node = AstNode::New(t, properties);
-        expr.end = self.prev().unwrap().end;
+        expr.node = AstNode::New(t, properties, args);
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -2266,20 +2991,13 @@ impl Syntax {
             let element = if self.is(TokenType::LeftParen) {
                 let mut expr = self.expr_new();
                 expr.node = AstNode::TupleDestr(self.parser_tuple_destr()?);
-                expr.end = self.prev().unwrap().end;
+                expr.span.end = self.prev().unwrap().span.end;
                 expr
             } else {
                 let mut expr = self.parser_expr()?;
 
-                // 检查表达式是否可赋值
-                if !expr.node.can_assign() {
-                    return Err(SyntaxError(
-                        self.peek().start,
-                        self.peek().end,
-                        "tuple destr src operand assign failed".to_string(),
-                    ));
-                }
-                expr.end = self.prev().unwrap().end;
+                Self::check_lvalue(&expr)?;
+                expr.span.end = self.prev().unwrap().span.end;
                 expr
             };
 
@@ -2303,22 +3021,23 @@ impl Syntax {
             let element = if self.is(TokenType::LeftParen) {
                 let mut expr = self.expr_new();
                 expr.node = AstNode::TupleDestr(self.parser_var_tuple_destr()?);
-                expr.end = self.prev().unwrap().end;
+                expr.span.end = self.prev().unwrap().span.end;
                 expr
             } else {
                 let ident_token = self.must(TokenType::Ident)?.clone();
                 let mut expr = self.expr_new();
 
-                expr.node = AstNode::VarDecl(Arc::new(Mutex::new(VarDeclExpr {
+                expr.node = AstNode::VarDecl(Arc::new(RwLock::new(VarDeclExpr {
                     type_: Type::default(),
-                    ident: ident_token.literal.clone(),
-                    symbol_start: ident_token.start,
-                    symbol_end: ident_token.end,
+                    ident: ident_token.literal.atom(),
+                    symbol_start: ident_token.span.start as usize,
+                    symbol_end: ident_token.span.end as usize,
                     be_capture: false,
                     heap_ident: None,
+                    doc: None,
                     symbol_id: None,
                 })));
-                expr.end = self.prev().unwrap().end;
+                expr.span.end = self.prev().unwrap().span.end;
                 expr
             };
 
@@ -2334,9 +3053,20 @@ impl Syntax {
         Ok(elements)
     }
 
+    /**
+     * void 类型的值无法被保存, ptr<void> 等包含 void 的复合类型不受影响, 错误不影响后续的解析
+     */
+    fn check_var_type(&mut self, type_decl: &Type) {
+        if type_decl.kind == TypeKind::Void {
+            self.errors
+                .push(SyntaxError(type_decl.span, "void cannot be used as a variable type; use var for type inference".to_string()).into());
+        }
+    }
+
     fn parser_var_begin_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
         let mut stmt = self.stmt_new();
         let type_decl = self.parser_type()?;
+        self.check_var_type(&type_decl);
 
         // 处理 var (a, b) 形式
         if self.is(TokenType::LeftParen) {
@@ -2345,60 +3075,61 @@ impl Syntax {
             let right = self.parser_expr()?;
 
             stmt.node = AstNode::VarTupleDestr(tuple_destr, right);
-            stmt.end = self.prev().unwrap().end;
+            stmt.span.end = self.prev().unwrap().span.end;
             return Ok(stmt);
         }
 
         // 处理 var a = 1 形式
+        self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
         let ident = self.must(TokenType::Ident)?.clone();
         self.must(TokenType::Equal)?;
 
         stmt.node = AstNode::VarDef(
-            Arc::new(Mutex::new(VarDeclExpr {
+            Arc::new(RwLock::new(VarDeclExpr {
                 type_: type_decl,
-                ident: ident.literal,
-                symbol_start: ident.start,
-                symbol_end: ident.end,
+                ident: ident.literal.atom(),
+                symbol_start: ident.span.start as usize,
+                symbol_end: ident.span.end as usize,
                 be_capture: false,
                 heap_ident: None,
+                doc: None,
                 symbol_id: None,
             })),
             self.parser_expr()?,
         );
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
     fn parser_type_begin_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
         let mut stmt = self.stmt_new();
         let type_decl = self.parser_type()?;
+        self.check_var_type(&type_decl);
+        self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
         let ident = self.must(TokenType::Ident)?.clone();
 
         // 仅 var 支持元组解构
         if self.is(TokenType::LeftParen) {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
-                "type begin stmt not support tuple destr".to_string(),
-            ));
+            return Err(SyntaxError::at_current(self, "type begin stmt not support tuple destr"));
         }
 
         // 声明必须赋值
         self.must(TokenType::Equal)?;
 
         stmt.node = AstNode::VarDef(
-            Arc::new(Mutex::new(VarDeclExpr {
+            Arc::new(RwLock::new(VarDeclExpr {
                 type_: type_decl,
-                ident: ident.literal,
-                symbol_start: ident.start,
-                symbol_end: ident.end,
+                ident: ident.literal.atom(),
+                symbol_start: ident.span.start as usize,
+                symbol_end: ident.span.end as usize,
                 be_capture: false,
                 heap_ident: None,
+                doc: None,
                 symbol_id: None,
             })),
             self.parser_expr()?,
         );
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2498,9 +3229,18 @@ impl Syntax {
         )
     }
 
-    fn parser_fndef_stmt(&mut self, mut fndef: AstFnDef) -> Result<Box<Stmt>, SyntaxError> {
+    /**
+     * 同 parser_type_alias_stmt, 泛型参数只在当前函数内有效
+     */
+    fn parser_fndef_stmt(&mut self, fndef: AstFnDef) -> Result<Box<Stmt>, SyntaxError> {
+        let result = self.parser_fndef_decl(fndef);
+        self.type_params_table = HashMap::new();
+        result
+    }
+
+    fn parser_fndef_decl(&mut self, mut fndef: AstFnDef) -> Result<Box<Stmt>, SyntaxError> {
         let mut stmt = self.stmt_new();
-        fndef.start = self.peek().start;
+        fndef.start = self.peek().span.start as usize;
         self.must(TokenType::Fn)?;
 
         // 检查是否是类型实现函数
@@ -2517,7 +3257,7 @@ impl Syntax {
                 loop {
                     let ident = self.must(TokenType::Ident)?.clone();
 
-                    let mut param = GenericsParam::new(ident.literal.clone());
+                    let mut param = GenericsParam::new(ident.literal.to_string());
 
                     // 处理泛型约束 <T:t1|t2, U:t1|t2>
                     if self.consume(TokenType::Colon) {
@@ -2535,7 +3275,7 @@ impl Syntax {
                         params.push(param);
                     }
 
-                    self.type_params_table.insert(ident.literal.clone(), ident.literal.clone());
+                    self.type_params_table.insert(ident.literal.to_string(), ident.literal.to_string());
 
                     if !self.consume(TokenType::Comma) {
                         break;
@@ -2552,11 +3292,11 @@ impl Syntax {
                 let mut t = Type::default();
                 t.kind = TypeKind::Alias(Box::new(TypeAlias {
                     import_as: None,
-                    ident: first_token.literal.clone(),
+                    ident: first_token.literal.to_string(),
                     symbol_id: None,
                     args: None,
                 }));
-                t.impl_ident = Some(self.must(TokenType::Ident)?.literal.clone());
+                t.impl_ident = Some(self.must(TokenType::Ident)?.literal.to_string());
 
                 if fndef.generics_params.is_some() {
                     self.must(TokenType::LeftAngle)?;
@@ -2574,7 +3314,7 @@ impl Syntax {
                                 }
                             }
                         }
-                        args.push(param_type);
+                        args.push(TypeArg::new(param_type));
 
                         if !self.consume(TokenType::Comma) {
                             break;
@@ -2590,17 +3330,13 @@ impl Syntax {
                 t
             } else {
                 let mut t = self.parser_single_type()?;
-                t.impl_ident = Some(first_token.literal.clone());
+                t.impl_ident = Some(first_token.literal.to_string());
                 t
             };
 
             // 类型检查
             if !self.is_impl_type(&impl_type.kind) {
-                return Err(SyntaxError(
-                    self.peek().start,
-                    self.peek().end,
-                    format!("type '{}' cannot impl fn", impl_type.kind),
-                ));
+                return Err(SyntaxError::at_current(self, format!("type '{}' cannot impl fn", render(&impl_type))));
             }
 
             fndef.impl_type = impl_type;
@@ -2613,10 +3349,22 @@ impl Syntax {
 
         // 处理函数名
         self.set_current_token_type(SemanticTokenType::FUNCTION);
-        let ident = self.must(TokenType::Ident)?;
+        self.set_current_token_modifier(SemanticTokenModifier::DECLARATION);
+        if fndef.is_deprecated {
+            self.set_current_token_modifier(SemanticTokenModifier::DEPRECATED);
+        }
 
-        fndef.symbol_name = ident.literal.clone();
-        fndef.fn_name = ident.literal.clone();
+        // #linkid 只是指定链接符号, fn 自身依旧需要声明名称
+        if fndef.linkid.is_some() && !self.is(TokenType::Ident) {
+            return Err(SyntaxError::at_current(self, "linkid function must have a name"));
+        }
+
+        let ident = self.must(TokenType::Ident)?.clone();
+
+        fndef.symbol_name = ident.literal.to_string();
+        fndef.fn_name = ident.literal.to_string();
+        fndef.symbol_start = ident.span.start as usize;
+        fndef.symbol_end = ident.span.end as usize;
 
         // 处理非实现类型的泛型参数
         if !is_impl_type && self.consume(TokenType::LeftAngle) {
@@ -2624,7 +3372,7 @@ impl Syntax {
             fndef.generics_params = Some(Vec::new());
 
             loop {
-                let ident = self.must(TokenType::Ident)?.literal.clone();
+                let ident = self.must(TokenType::Ident)?.literal.to_string();
                 let mut param = GenericsParam::new(ident.clone());
 
                 if self.consume(TokenType::Colon) {
@@ -2655,33 +3403,64 @@ impl Syntax {
         self.parser_params(&mut fndef)?;
 
         // 处理返回类型
-        if self.consume(TokenType::Colon) {
-            fndef.return_type = self.parser_type()?;
+        let has_return_type = self.consume(TokenType::Colon);
+        if has_return_type {
+            fndef.return_type = self.parser_return_type()?;
         } else {
             fndef.return_type = Type::new(TypeKind::Void);
-            fndef.return_type.start = self.peek().start;
-            fndef.return_type.end = self.peek().end;
+            fndef.return_type.span.start = self.peek().span.start;
+            fndef.return_type.span.end = self.peek().span.end;
         }
 
         if self.consume(TokenType::Not) {
             fndef.is_errable = true;
         }
 
-        // tpl fn not body;
+        // #test 函数由测试框架直接调用, 不能声明参数与返回值
+        if fndef.is_test {
+            if !fndef.params.is_empty() {
+                return Err(SyntaxError::at_token(&ident, "test function cannot have parameters"));
+            }
+
+            if has_return_type && !matches!(fndef.return_type.kind, TypeKind::Void) {
+                return Err(SyntaxError(fndef.return_type.span, "test function must return void".to_string()));
+            }
+        }
+
+        // intrinsic fn 只有声明, 不属于 tpl fn
+        if fndef.is_intrinsic {
+            if !self.is_stmt_eof() {
+                return Err(SyntaxError::at_token(&ident, "intrinsic function cannot have a body"));
+            }
+
+            fndef.end = self.prev().unwrap().span.end as usize;
+            stmt.node = AstNode::FnDef(Arc::new(RwLock::new(fndef)));
+            stmt.span.end = self.prev().unwrap().span.end;
+            return Ok(stmt);
+        }
+
+        // tpl fn not body; 只根据是否存在 body 判断, 只包含注释的 body 不会进入 token_indexes, 解析结果为空 body 而不是 tpl fn
         if self.is_stmt_eof() {
+            // linkid 对应的外部符号无法推导返回值类型, 必须显式声明
+            if fndef.linkid.is_some() && !has_return_type {
+                return Err(SyntaxError::at_token(&ident, "linkid template function must declare a return type"));
+            }
+
             fndef.is_tpl = true;
-            stmt.node = AstNode::FnDef(Arc::new(Mutex::new(fndef)));
+            stmt.node = AstNode::FnDef(Arc::new(RwLock::new(fndef)));
             return Ok(stmt);
         }
 
         fndef.body = self.parser_body()?;
 
-        fndef.end = if let Some(prev) = self.prev() { prev.end } else { self.peek().end };
-
-        self.type_params_table = HashMap::new();
+        fndef.end = if let Some(prev) = self.prev() {
+            prev.span.end as usize
+        } else {
+            self.peek().span.end as usize
+        };
 
-        stmt.node = AstNode::FnDef(Arc::new(Mutex::new(fndef)));
-        stmt.end = self.prev().unwrap().end;
+        stmt.node = AstNode::FnDef(Arc::new(RwLock::new(fndef)));
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2694,18 +3473,29 @@ impl Syntax {
             if token.literal == "linkid" {
                 if self.is(TokenType::Ident) {
                     let linkto = self.must(TokenType::Ident)?;
-                    fndef.linkid = Some(linkto.literal.clone());
+                    fndef.linkid = Some(linkto.literal.to_string());
                 } else {
                     let literal = self.must(TokenType::StringLiteral)?;
-                    fndef.linkid = Some(literal.literal.clone());
+                    fndef.linkid = Some(literal.literal.to_string());
                 }
             } else if token.literal == "local" {
                 fndef.is_private = true;
+            } else if token.literal == "deprecated" {
+                fndef.is_deprecated = true;
+            } else if token.literal == "test" {
+                fndef.is_test = true;
+            } else if token.literal == "intrinsic" {
+                fndef.is_intrinsic = true;
             } else if token.literal == "runtime_use" {
                 self.must(TokenType::Ident)?;
             } else {
                 // TODO 不认识的 label 进行 advance 直到下一个 label 开始
-                return Err(SyntaxError(token.start, token.end, format!("unknown fn label '{}'", token.literal)));
+                return Err(SyntaxError::at_token(token, format!("unknown fn label '{}'", token.literal)));
+            }
+
+            // 多个 label 可以分别位于不同的行, 行尾的 label 之后会自动插入 StmtEof
+            if self.is(TokenType::StmtEof) && self.next_is(1, TokenType::Label) {
+                self.must(TokenType::StmtEof)?;
             }
         }
 
@@ -2716,12 +3506,124 @@ impl Syntax {
         } else if self.is(TokenType::Fn) {
             self.parser_fndef_stmt(fndef)
         } else {
-            Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
-                format!("the label can only be used in type alias or fn"),
-            ))
+            Err(SyntaxError::at_current(self, "the label can only be used in type alias or fn"))
+        }
+    }
+
+    // @name 或者 @name(args)
+    fn parser_decorator(&mut self) -> Result<Decorator, SyntaxError> {
+        let start = self.peek().span.start as usize;
+        let name = if self.consume(TokenType::At) {
+            self.must(TokenType::Ident)?.literal.to_string()
+        } else {
+            self.must(TokenType::MacroIdent)?.literal.to_string()
+        };
+
+        let mut args = Vec::new();
+        if self.is(TokenType::LeftParen) {
+            let mut call = AstCall {
+                return_type: Type::default(),
+                left: self.expr_new(),
+                generics_args: Vec::new(),
+                args: Vec::new(),
+                spread: false,
+            };
+
+            args = self.parser_args(&mut call)?;
+            if call.spread {
+                return Err(SyntaxError(
+                    Span::new(start, self.prev().unwrap().span.end as usize),
+                    "decorator cannot use '...' argument".to_string(),
+                ));
+            }
+        }
+
+        Ok(Decorator {
+            name,
+            args,
+            start,
+            end: self.prev().unwrap().span.end as usize,
+        })
+    }
+
+    /**
+     * 只有后面跟随 fn 或者 type 声明时 @ident 才是 decorator, 其他情况例如 @sizeof(int) 依旧是 macro
+     */
+    fn is_decorator_stmt(&self) -> bool {
+        let mut step = 0;
+        loop {
+            match self.next(step).map(|t| &t.token_type) {
+                Some(TokenType::MacroIdent) => step += 1,
+                Some(TokenType::At) if self.next_is(step + 1, TokenType::Ident) => step += 2,
+                _ => break,
+            }
+
+            // 跳过 decorator 的参数
+            if self.next_is(step, TokenType::LeftParen) {
+                let mut depth = 0;
+                loop {
+                    match self.next(step).map(|t| &t.token_type) {
+                        Some(TokenType::LeftParen) => depth += 1,
+                        Some(TokenType::RightParen) => depth -= 1,
+                        Some(TokenType::Eof) | None => return false,
+                        _ => {}
+                    }
+                    step += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+
+            if self.next_is(step, TokenType::StmtEof) {
+                step += 1;
+            }
         }
+
+        if step == 0 {
+            return false;
+        }
+        self.next_is(step, TokenType::Fn)
+            || self.next_is(step, TokenType::Type)
+            || (self.next_is(step, TokenType::Async) && self.next_is(step + 1, TokenType::Fn))
+    }
+
+    fn parser_decorator_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
+        let mut decorators = Vec::new();
+
+        while self.is(TokenType::MacroIdent) || self.is(TokenType::At) {
+            decorators.push(self.parser_decorator()?);
+
+            // decorator 可以和 fn 在同一行，也可以单独一行
+            self.consume(TokenType::StmtEof);
+        }
+
+        // 多个 decorator 按照 bottom-up 的顺序应用
+        decorators.reverse();
+
+        let is_async = self.is(TokenType::Async) && self.next_is(1, TokenType::Fn);
+        if is_async {
+            self.must(TokenType::Async)?;
+        }
+
+        if self.is(TokenType::Fn) {
+            let fndef = AstFnDef {
+                decorators,
+                is_async,
+                ..Default::default()
+            };
+            return self.parser_fndef_stmt(fndef);
+        }
+
+        if self.is(TokenType::Type) {
+            let stmt = self.parser_type_alias_stmt()?;
+            if let AstNode::TypeAlias(type_alias_mutex) = &stmt.node {
+                type_alias_mutex.write().unwrap().decorators = decorators;
+            }
+            return Ok(stmt);
+        }
+
+        Err(SyntaxError::at_current(self, "the decorator can only be used in type alias or fn"))
     }
 
     fn parser_let_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
@@ -2732,11 +3634,23 @@ impl Syntax {
 
         // 确保是 as 表达式
         if !matches!(expr.node, AstNode::As(..)) {
-            return Err(SyntaxError(expr.start, expr.end, "must be 'as' expr".to_string()));
+            return Err(SyntaxError(expr.span, "must be 'as' expr".to_string()));
         }
 
         stmt.node = AstNode::Let(expr);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
+        Ok(stmt)
+    }
+
+    /**
+     * do expr, 显式丢弃 expr 的值, 与 fake stmt 不同的是 expr 可以是任意表达式
+     */
+    fn parser_do_stmt(&mut self) -> Result<Box<Stmt>, SyntaxError> {
+        let mut stmt = self.stmt_new();
+        self.must(TokenType::Do)?;
+
+        stmt.node = AstNode::Do(self.parser_expr()?);
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2745,7 +3659,7 @@ impl Syntax {
         self.must(TokenType::Throw)?;
 
         stmt.node = AstNode::Throw(self.parser_expr()?);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
         Ok(stmt)
     }
 
@@ -2766,13 +3680,13 @@ impl Syntax {
             let mut stmt = self.stmt_new();
             let mut left = self.expr_new();
             left.node = AstNode::TupleDestr(self.parser_tuple_destr()?);
-            left.end = self.prev().unwrap().end;
+            left.span.end = self.prev().unwrap().span.end;
 
             self.must(TokenType::Equal)?;
             let right = self.parser_expr()?;
 
             stmt.node = AstNode::Assign(left, right);
-            stmt.end = self.prev().unwrap().end;
+            stmt.span.end = self.prev().unwrap().span.end;
             Ok(stmt)
         } else {
             // 普通表达式语句
@@ -2787,16 +3701,23 @@ impl Syntax {
             self.parser_type_begin_stmt()?
         } else if self.is(TokenType::Label) {
             self.parser_label()?
+        } else if self.is_decorator_stmt() {
+            self.parser_decorator_stmt()?
         } else if self.is(TokenType::Fn) {
             self.parser_fndef_stmt(AstFnDef::default())?
+        } else if self.is(TokenType::Async) && self.next_is(1, TokenType::Fn) {
+            self.must(TokenType::Async)?;
+            self.parser_fndef_stmt(AstFnDef {
+                is_async: true,
+                ..Default::default()
+            })?
         } else if self.is(TokenType::Import) {
             self.parser_import_stmt()?
         } else if self.is(TokenType::Type) {
             self.parser_type_alias_stmt()?
         } else {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
+            return Err(SyntaxError::at_current(
+                self,
                 format!("global statement cannot start with '{}'", self.peek().literal),
             ));
         };
@@ -2824,9 +3745,8 @@ impl Syntax {
             // 普通赋值语句
             self.parser_expr_begin_stmt()?
         } else {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
+            return Err(SyntaxError::at_current(
+                self,
                 format!("for init statement cannot start with '{}'", self.peek().literal),
             ));
         };
@@ -2845,7 +3765,9 @@ impl Syntax {
             self.parser_throw_stmt()?
         } else if self.is(TokenType::Let) {
             self.parser_let_stmt()?
-        } else if self.is(TokenType::Ident) {
+        } else if self.is(TokenType::Do) {
+            self.parser_do_stmt()?
+        } else if self.is(TokenType::Ident) || self.is(TokenType::Star) {
             self.parser_expr_begin_stmt()?
         } else if self.is(TokenType::Label) {
             self.parser_label()?
@@ -2875,9 +3797,8 @@ impl Syntax {
             let expr = self.parser_expr_with_precedence()?;
             self.fake_new(expr)
         } else {
-            return Err(SyntaxError(
-                self.peek().start,
-                self.peek().end,
+            return Err(SyntaxError::at_current(
+                self,
                 format!("local statement cannot start with '{}'", self.peek().literal),
             ));
         };
@@ -2893,7 +3814,7 @@ impl Syntax {
 
         let prefix_fn = rule
             .prefix
-            .ok_or_else(|| SyntaxError(self.peek().start, self.peek().end, format!("<expr> expected, found '{}'", self.peek().literal)))?;
+            .ok_or_else(|| SyntaxError::at_current(self, format!("<expr> expected, found '{}'", self.peek().literal)))?;
 
         let mut expr = prefix_fn(self)?;
 
@@ -3009,19 +3930,66 @@ impl Syntax {
         self.must(TokenType::RightParen)?;
 
         expr.node = AstNode::MacroDefault;
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
         Ok(expr)
     }
 
+    /**
+     * @line() 展开为宏所在的行号(从 1 开始)
+     */
+    fn parser_macro_line(&mut self, macro_token: &Token) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        expr.span.start = macro_token.span.start;
+        self.must(TokenType::LeftParen)?;
+        self.must(TokenType::RightParen)?;
+
+        expr.node = AstNode::Literal(TypeKind::Int, macro_token.line.to_string(), LiteralSource::Synthesized);
+        expr.span.end = self.prev().unwrap().span.end;
+        Ok(expr)
+    }
+
+    /**
+     * @file() 展开为当前源文件的路径
+     */
+    fn parser_macro_file(&mut self, macro_token: &Token) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        expr.span.start = macro_token.span.start;
+        self.must(TokenType::LeftParen)?;
+        self.must(TokenType::RightParen)?;
+
+        expr.node = AstNode::Literal(TypeKind::String, self.config.source_path.clone(), LiteralSource::Synthesized);
+        expr.span.end = self.prev().unwrap().span.end;
+        Ok(expr)
+    }
+
+    /**
+     * @sizeof(T) 在编译时计算类型的大小, @sizeof(expr) 计算表达式类型的大小
+     *
+     * 优先解析为类型, 解析失败或者类型之后不是 ) 时回退并解析为表达式. @sizeof(x) 此时同样被解析为类型,
+     * 在 semantic 阶段 x 解析为变量时改写为 Sizeof(expr)
+     */
     fn parser_macro_sizeof(&mut self) -> Result<Box<Expr>, SyntaxError> {
         let mut expr = self.expr_new();
         self.must(TokenType::LeftParen)?;
 
-        let target_type = self.parser_single_type()?;
+        // 按类型尝试解析失败时需要回退, 同时丢弃尝试过程中产生的错误与 expected tokens
+        let current_pos = self.current;
+        let errors_len = self.errors.len();
+        let expected_len = self.expected_tokens.len();
+        match self.parser_single_type() {
+            Ok(target_type) if self.is(TokenType::RightParen) => {
+                expr.node = AstNode::MacroSizeof(target_type);
+            }
+            _ => {
+                self.current = current_pos;
+                self.errors.truncate(errors_len);
+                self.expected_tokens.truncate(expected_len);
+                expr.node = AstNode::Sizeof(self.parser_expr()?);
+            }
+        }
         self.must(TokenType::RightParen)?;
 
-        expr.node = AstNode::MacroSizeof(target_type);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
         Ok(expr)
     }
 
@@ -3033,74 +4001,49 @@ impl Syntax {
         self.must(TokenType::RightParen)?;
 
         expr.node = AstNode::MacroReflectHash(target_type);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
         Ok(expr)
     }
 
+    /**
+     * 协程闭包由 parser 生成, 其中所有节点的位置都与 call_expr 相同, 使得父节点始终包含子节点
+     */
     fn coroutine_fn_closure(&mut self, call_expr: &Box<Expr>) -> AstFnDef {
-        let mut fndef = AstFnDef::default();
-        fndef.is_async = true;
-        fndef.is_errable = true;
-        fndef.params = Vec::new();
-        fndef.return_type = Type::new(TypeKind::Void);
+        let span = call_expr.span;
 
-        let mut stmt_list = Vec::new();
-
-        // var a = call(x, x, x)
-        let mut vardef_stmt = self.stmt_new();
-        vardef_stmt.node = AstNode::VarDef(
-            Arc::new(Mutex::new(VarDeclExpr {
-                type_: Type::default(),
-                ident: "result".to_string(),
-                symbol_start: 0,
-                symbol_end: 0,
-                be_capture: false,
-                heap_ident: None,
-                symbol_id: None,
-            })),
-            call_expr.clone(),
-        );
-        vardef_stmt.end = self.prev().unwrap().end;
+        // var result = call(x, x, x)
+        let vardef_stmt = Stmt::var_def(span, VarDeclExpr::new("result", Type::default(), Span::default()), call_expr.clone());
 
         // co_return(&result)
-        let mut call_stmt = self.stmt_new();
-        let call = AstCall {
-            return_type: Type::default(),
-            left: Box::new(Expr::ident(fndef.start, fndef.end, "co_return".to_string(), None)),
-            args: vec![Box::new(Expr {
-                node: AstNode::Unary(ExprOp::La, Box::new(Expr::ident(fndef.start, fndef.end, "result".to_string(), None))),
-                ..Default::default()
-            })],
-            generics_args: Vec::new(),
-            spread: false,
-        };
-        call_stmt.node = AstNode::Call(call);
-        call_stmt.end = self.prev().unwrap().end;
-
-        stmt_list.push(vardef_stmt);
-        stmt_list.push(call_stmt);
-        fndef.body = stmt_list;
+        let result_ref = Expr::unary(span, ExprOp::La, Box::new(Expr::ident(span, "result".to_string(), None)));
+        let call_stmt = Stmt::call(span, Box::new(Expr::ident(span, "co_return".to_string(), None)), vec![Box::new(result_ref)]);
 
-        fndef
+        self.coroutine_fndef(span, vec![vardef_stmt, call_stmt])
     }
 
     fn coroutine_fn_void_closure(&mut self, call_expr: &Box<Expr>) -> AstFnDef {
-        let mut fndef = AstFnDef::default();
-        fndef.is_async = true;
-        fndef.is_errable = true;
-        fndef.params = Vec::new();
-        fndef.return_type = Type::new(TypeKind::Void);
-
-        let mut stmt_list = Vec::new();
+        let span = call_expr.span;
 
         // call(x, x, x)
-        let mut call_stmt = self.stmt_new();
+        let mut call_stmt = Stmt::new(span, AstNode::None);
         if let AstNode::Call(call) = &call_expr.node {
             call_stmt.node = AstNode::Call(call.clone());
-            call_stmt.end = self.prev().unwrap().end;
         }
-        stmt_list.push(call_stmt);
-        fndef.body = stmt_list;
+
+        self.coroutine_fndef(span, vec![call_stmt])
+    }
+
+    // 构造函数生成的节点没有 id, 加入 body 前统一分配
+    fn coroutine_fndef(&mut self, span: Span, stmts: Vec<Stmt>) -> AstFnDef {
+        let mut body = Vec::new();
+        for mut stmt in stmts {
+            self.renumber_stmt(&mut stmt);
+            body.push(Box::new(stmt));
+        }
+
+        let mut fndef = AstFnDef::synthetic(span, Type::new(TypeKind::Void), body);
+        fndef.is_async = true;
+        fndef.is_errable = true;
         fndef
     }
 
@@ -3115,7 +4058,7 @@ impl Syntax {
         let mut send_count = 0;
 
         while !self.consume(TokenType::RightCurly) {
-            let start = self.peek().start;
+            let start = self.peek().span.start as usize;
             let mut select_case = SelectCase {
                 on_call: None,
                 recv_var: None,
@@ -3129,11 +4072,7 @@ impl Syntax {
             // 处理默认分支 _ -> { ... }
             if self.is(TokenType::Ident) && self.peek().literal == "_" {
                 if has_default {
-                    return Err(SyntaxError(
-                        self.peek().start,
-                        self.peek().end,
-                        "select statement can only have one default case".to_string(),
-                    ));
+                    return Err(SyntaxError::at_current(self, "select statement can only have one default case"));
                 }
                 self.advance();
                 self.must(TokenType::RightArrow)?;
@@ -3142,7 +4081,7 @@ impl Syntax {
                 select_case.handle_body = self.parser_body()?;
                 has_default = true;
 
-                select_case.end = self.prev().unwrap().end;
+                select_case.end = self.prev().unwrap().span.end as usize;
                 cases.push(select_case);
 
                 self.must_stmt_end()?;
@@ -3164,19 +4103,15 @@ impl Syntax {
                             select_case.is_recv = false;
                         }
                         _ => {
-                            return Err(SyntaxError(
-                                call_expr.start,
-                                call_expr.end,
-                                "only on_recv or on_send can be used in select case".to_string(),
-                            ));
+                            return Err(SyntaxError(call_expr.span, "only on_recv or on_send can be used in select case".to_string()));
                         }
                     }
                     select_case.on_call = Some(call.clone());
                 } else {
-                    return Err(SyntaxError(call_expr.start, call_expr.end, "select case must be chan select call".to_string()));
+                    return Err(SyntaxError(call_expr.span, "select case must be chan select call".to_string()));
                 }
             } else {
-                return Err(SyntaxError(call_expr.start, call_expr.end, "select case must be chan select call".to_string()));
+                return Err(SyntaxError(call_expr.span, "select case must be chan select call".to_string()));
             }
 
             self.must(TokenType::RightArrow)?;
@@ -3184,19 +4119,20 @@ impl Syntax {
             // 处理接收变量声明 -> msg { ... }
             if self.is(TokenType::Ident) {
                 let ident_token = self.must(TokenType::Ident)?;
-                select_case.recv_var = Some(Arc::new(Mutex::new(VarDeclExpr {
+                select_case.recv_var = Some(Arc::new(RwLock::new(VarDeclExpr {
                     type_: Type::default(),
-                    ident: ident_token.literal.clone(),
-                    symbol_start: ident_token.start,
-                    symbol_end: ident_token.end,
+                    ident: ident_token.literal.atom(),
+                    symbol_start: ident_token.span.start as usize,
+                    symbol_end: ident_token.span.end as usize,
                     be_capture: false,
                     heap_ident: None,
+                    doc: None,
                     symbol_id: None,
                 })));
             }
 
             select_case.handle_body = self.parser_body()?;
-            select_case.end = self.prev().unwrap().end;
+            select_case.end = self.prev().unwrap().span.end as usize;
             cases.push(select_case);
 
             self.must_stmt_end()?;
@@ -3204,21 +4140,22 @@ impl Syntax {
 
         // 检查是否只有default分支
         if has_default && cases.len() == 1 {
-            return Err(SyntaxError(self.peek().start, self.peek().end, "select must contains on_call case".to_string()));
+            return Err(SyntaxError::at_current(self, "select must contains on_call case"));
         }
 
         //     Select(Vec<SelectCase>, bool, i16, i16), // (cases, has_default, send_count, recv_count)
         stmt.node = AstNode::Select(cases, has_default, send_count, recv_count);
-        stmt.end = self.prev().unwrap().end;
+        stmt.span.end = self.prev().unwrap().span.end;
 
         Ok(stmt)
     }
 
     fn parser_match_expr(&mut self) -> Result<Box<Expr>, SyntaxError> {
-        self.must(TokenType::Match)?;
         let mut expr = self.expr_new();
+        self.must(TokenType::Match)?;
         let mut subject = None;
         let mut cases = Vec::new();
+        let mut has_default = false;
 
         // match ({a, b, c}) {}
         if !self.is(TokenType::LeftCurly) {
@@ -3229,77 +4166,148 @@ impl Syntax {
         self.must(TokenType::LeftCurly)?;
 
         while !self.consume(TokenType::RightCurly) {
-            self.match_cond = true;
-
-            let start = self.peek().start;
-            let mut cond_list = Vec::new();
+            if self.is(TokenType::Eof) {
+                let prev_token = self.prev().unwrap().clone();
+                self.expect_tokens(prev_token.span, vec![TokenType::RightCurly]);
+                return Err(SyntaxError::at_token(&prev_token, "unexpected end of file, expected '}'"));
+            }
 
-            if subject.is_some() {
-                loop {
-                    let expr = self.parser_precedence_expr(SyntaxPrecedence::Assign, TokenType::Or)?;
-                    cond_list.push(expr);
-                    if !self.consume(TokenType::Or) {
-                        break;
+            match self.parser_match_case(subject.is_some()) {
+                Ok(case) => {
+                    // default case '_' 或者 'is' 匹配任意类型时, 记录 has_default 用于跳过穷尽检查
+                    if case.cond_list.len() == 1 {
+                        match &case.cond_list[0].node {
+                            AstNode::Ident(ident, _) if ident == "_" => has_default = true,
+                            AstNode::MatchIs(target_type) if target_type.kind.is_unknown() => has_default = true,
+                            _ => {}
+                        }
                     }
+                    cases.push(case);
+                }
+                Err(e) => {
+                    // 丢弃当前 case 并跳过到下一个 case, 其余 case 继续解析
+                    self.match_cond = false;
+                    self.errors.push(e.into());
+                    self.synchronize_match_case();
                 }
-            } else {
-                cond_list.push(self.parser_expr()?);
             }
+        }
 
-            self.must(TokenType::RightArrow)?;
-            self.match_cond = false;
+        self.match_subject = false;
+        expr.node = AstNode::Match(subject, cases, has_default);
+        expr.span.end = self.prev().unwrap().span.end;
+        Ok(expr)
+    }
 
-            let exec_body = if self.is(TokenType::LeftCurly) {
-                self.parser_body()?
-            } else {
-                let exec_expr = self.parser_expr()?;
+    fn parser_match_case(&mut self, has_subject: bool) -> Result<MatchCase, SyntaxError> {
+        self.match_cond = true;
 
-                // gen retrun stmt
-                let mut stmt = self.stmt_new();
-                stmt.node = AstNode::Break(Some(exec_expr.clone()));
-                stmt.start = exec_expr.start.clone();
-                stmt.end = exec_expr.end.clone();
-                vec![stmt]
-            };
+        let start = self.peek().span.start as usize;
+        let mut cond_list = Vec::new();
 
-            self.must_stmt_end()?;
+        if has_subject {
+            loop {
+                let expr = self.parser_precedence_expr(SyntaxPrecedence::Assign, TokenType::Or)?;
+                cond_list.push(expr);
+                if !self.consume(TokenType::Or) {
+                    break;
+                }
+            }
+        } else {
+            cond_list.push(self.parser_expr()?);
+        }
 
-            cases.push(MatchCase {
-                cond_list,
-                handle_body: exec_body,
-                is_default: false,
-                start,
-                end: self.prev().unwrap().end,
-            });
+        self.must(TokenType::RightArrow)?;
+        self.match_cond = false;
+
+        let exec_body = if self.is(TokenType::LeftCurly) {
+            self.parser_body()?
+        } else {
+            let exec_expr = self.parser_expr()?;
+
+            // gen retrun stmt
+            let mut stmt = self.stmt_new();
+            stmt.node = AstNode::Break(Some(exec_expr.clone()));
+            stmt.span = exec_expr.span;
+            vec![stmt]
+        };
+
+        self.must_stmt_end()?;
+
+        Ok(MatchCase {
+            cond_list,
+            handle_body: exec_body,
+            is_default: false,
+            start,
+            end: self.prev().unwrap().span.end as usize,
+        })
+    }
+
+    /**
+     * 跳过解析失败的 match case, 停止在同一层级的语句结束符之后或者 match 的 } 之前
+     */
+    fn synchronize_match_case(&mut self) {
+        let mut depth: usize = 0;
+
+        loop {
+            match self.peek().token_type {
+                TokenType::Eof => return,
+                TokenType::StmtEof if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                TokenType::RightCurly if depth == 0 => return,
+                TokenType::LeftCurly | TokenType::LeftParen | TokenType::LeftSquare => depth += 1,
+                TokenType::RightCurly | TokenType::RightParen | TokenType::RightSquare => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+
+            self.advance();
         }
+    }
 
-        self.match_subject = false;
-        expr.node = AstNode::Match(subject, cases);
-        expr.end = self.prev().unwrap().end;
-        Ok(expr)
+    /**
+     * go 与 @async 会将 call 包装为 closure, callee 可以是任意表达式, 例如 foo(), a.b(), list[0](), fn(){...}()
+     * 但是 a.b().c() 这样 callee 中包含 call 的链式调用无法正确包装
+     */
+    fn check_direct_call(call_expr: &Expr) -> Result<(), SyntaxError> {
+        let AstNode::Call(call) = &call_expr.node else {
+            return Ok(());
+        };
+
+        let mut left = &call.left;
+        loop {
+            match &left.node {
+                AstNode::SelectExpr(inner, _) | AstNode::AccessExpr(inner, _) => left = inner,
+                AstNode::Call(_) => return Err(SyntaxError(call_expr.span, "go/@async requires a direct function call".to_string())),
+                _ => return Ok(()),
+            }
+        }
     }
 
     fn parser_go_expr(&mut self) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
         self.must(TokenType::Go)?;
         let call_expr = self.parser_expr()?;
 
         // expr 的 type 必须是 call
         if !matches!(call_expr.node, AstNode::Call(_)) {
-            return Err(SyntaxError(call_expr.start, call_expr.end, "go expr must be call".to_string()));
+            return Err(SyntaxError(call_expr.span, "go expr must be call".to_string()));
         }
+        Self::check_direct_call(&call_expr)?;
 
-        let mut expr = self.expr_new();
         expr.node = AstNode::MacroAsync(MacroAsyncExpr {
             origin_call: if let AstNode::Call(call) = &call_expr.node {
                 Box::new(call.clone())
             } else {
                 panic!("go expr must be call")
             },
-            closure_fn: Arc::new(Mutex::new(self.coroutine_fn_closure(&call_expr))),
-            closure_fn_void: Arc::new(Mutex::new(self.coroutine_fn_void_closure(&call_expr))),
+            closure_fn: Arc::new(RwLock::new(self.coroutine_fn_closure(&call_expr))),
+            closure_fn_void: Arc::new(RwLock::new(self.coroutine_fn_void_closure(&call_expr))),
             flag_expr: None,
             return_type: Type::new(TypeKind::Void),
         });
+        expr.span.end = self.prev().unwrap().span.end;
 
         Ok(expr)
     }
@@ -3309,14 +4317,19 @@ impl Syntax {
         self.must(TokenType::LeftParen)?;
 
         let call_expr = self.parser_expr()?;
+        if !matches!(call_expr.node, AstNode::Call(_)) {
+            return Err(SyntaxError(call_expr.span, "async expr must be call".to_string()));
+        }
+        Self::check_direct_call(&call_expr)?;
+
         let mut r#async = MacroAsyncExpr {
             origin_call: if let AstNode::Call(call) = &call_expr.node {
                 Box::new(call.clone())
             } else {
                 panic!("async expr must be call")
             },
-            closure_fn: Arc::new(Mutex::new(self.coroutine_fn_closure(&call_expr))),
-            closure_fn_void: Arc::new(Mutex::new(self.coroutine_fn_void_closure(&call_expr))),
+            closure_fn: Arc::new(RwLock::new(self.coroutine_fn_closure(&call_expr))),
+            closure_fn_void: Arc::new(RwLock::new(self.coroutine_fn_void_closure(&call_expr))),
             flag_expr: None,
             return_type: Type::new(TypeKind::Void),
         };
@@ -3327,24 +4340,200 @@ impl Syntax {
         self.must(TokenType::RightParen)?;
 
         expr.node = AstNode::MacroAsync(r#async);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
         Ok(expr)
     }
 
+    /**
+     * @ula(value), value 必须是可以取地址的表达式, 例如变量, 字段与元素访问, @ula(42) 没有意义
+     */
     fn parser_macro_ula_expr(&mut self) -> Result<Box<Expr>, SyntaxError> {
         let mut expr = self.expr_new();
         self.must(TokenType::LeftParen)?;
 
         let src = self.parser_expr()?;
+        if !src.node.can_assign() {
+            return Err(SyntaxError(src.span, "ula requires an addressable expression".to_string()));
+        }
         self.must(TokenType::RightParen)?;
 
         expr.node = AstNode::MacroUla(src);
-        expr.end = self.prev().unwrap().end;
+        expr.span.end = self.prev().unwrap().span.end;
+        Ok(expr)
+    }
+
+    /**
+     * @bitcast<T>(expr) 或者 @bitcast(T, expr), 类型参数与值参数都是必须的
+     */
+    fn parser_macro_bitcast(&mut self, macro_token: &Token) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        expr.span.start = macro_token.span.start;
+
+        let target_type = if self.consume(TokenType::LeftAngle) {
+            let target_type = self.parser_single_type()?;
+            self.must(TokenType::RightAngle)?;
+            self.must(TokenType::LeftParen)?;
+            target_type
+        } else {
+            self.must(TokenType::LeftParen)?;
+            let target_type = self.parser_single_type()?;
+            self.must(TokenType::Comma)?;
+            target_type
+        };
+
+        let src = self.parser_expr()?;
+        self.must(TokenType::RightParen)?;
+
+        expr.node = AstNode::MacroBitCast(target_type, src);
+        expr.span.end = self.prev().unwrap().span.end;
+        Ok(expr)
+    }
+
+    /**
+     * @min(a, b, ...) / @max(a, b, ...), 至少需要两个参数
+     */
+    fn parser_macro_min_max(&mut self, macro_token: &Token, kind: MinMaxKind) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        expr.span.start = macro_token.span.start;
+        self.must(TokenType::LeftParen)?;
+
+        let mut args = Vec::new();
+        if !self.is(TokenType::RightParen) {
+            loop {
+                args.push(self.parser_expr()?);
+                if !self.consume(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.must(TokenType::RightParen)?;
+        expr.span.end = self.prev().unwrap().span.end;
+
+        if args.len() < 2 {
+            return Err(SyntaxError(expr.span, format!("{} requires at least 2 arguments", kind)));
+        }
+
+        expr.node = AstNode::MacroMinMax(kind, args);
+        Ok(expr)
+    }
+
+    /**
+     * 全局 fn, type alias 与 var 声明上方紧邻的注释作为文档注释, first_index 为声明(包括 decorator)的第一个 token 在 token_db 中的位置
+     */
+    fn attach_doc(&self, stmt: &Stmt, first_index: usize) {
+        let Some(doc) = self.leading_doc(first_index) else {
+            return;
+        };
+
+        match &stmt.node {
+            AstNode::FnDef(fndef_mutex) => fndef_mutex.write().unwrap().doc = Some(doc),
+            AstNode::TypeAlias(type_alias_mutex) => type_alias_mutex.write().unwrap().doc = Some(doc),
+            AstNode::VarDef(var_decl_mutex, _) | AstNode::VarDecl(var_decl_mutex) => var_decl_mutex.write().unwrap().doc = Some(doc),
+            _ => {}
+        }
+    }
+
+    /**
+     * 从声明向上收集连续行中的注释, 遇到空行或者代码时停止, 与代码位于同一行的尾随注释不属于文档注释
+     */
+    fn leading_doc(&self, first_index: usize) -> Option<String> {
+        let mut line = self.token_db[first_index].line;
+        let mut comments: Vec<&Token> = Vec::new();
+
+        for token in self.token_db[..first_index].iter().rev() {
+            match token.token_type {
+                TokenType::StmtEof => continue,
+                TokenType::LineComment | TokenType::BlockComment if token.line + 1 >= line => {
+                    line = token.line;
+                    comments.push(token);
+                }
+                _ => {
+                    // 上一行代码的尾随注释
+                    if token.line == line {
+                        comments.retain(|c| c.line != line);
+                    }
+                    break;
+                }
+            }
+        }
+
+        comments.reverse();
+        let lines: Vec<String> = comments.iter().map(|c| comment_text(c)).collect();
+        let doc = lines.join("\n").trim_matches('\n').to_string();
+        (!doc.trim().is_empty()).then_some(doc)
+    }
+
+    /**
+     * [start, end) 范围内的源码, 没有原始源码时通过 token 还原, token 之间的空白统一为一个空格
+     */
+    fn source_text(&self, span: Span) -> String {
+        // span 是字符偏移
+        if let Some(source) = self.source.as_deref() {
+            return source.chars().skip(span.start as usize).take(span.len()).collect();
+        }
+
+        let mut text = String::new();
+        let mut prev_end: Option<u32> = None;
+
+        let index = self.token_db.partition_point(|t| t.span.start < span.start);
+        for token in self.token_db[index..].iter().take_while(|t| t.span.end <= span.end) {
+            if matches!(token.token_type, TokenType::LineComment | TokenType::BlockComment | TokenType::StmtEof) {
+                continue;
+            }
+            if prev_end.is_some_and(|prev_end| token.span.start > prev_end) {
+                text.push(' ');
+            }
+            if token.token_type == TokenType::StringLiteral {
+                // token 中的字符串已经完成了转义, 需要重新转义为源码形式
+                text.push_str(&quote(&token.literal));
+            } else {
+                text.push_str(&token.literal);
+            }
+            prev_end = Some(token.span.end);
+        }
+
+        text
+    }
+
+    /**
+     * @assert(cond), 断言失败时的提示信息为 cond 的源码, 例如 assertion failed: x > 0
+     */
+    fn parser_macro_assert(&mut self, macro_token: &Token) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        expr.span.start = macro_token.span.start;
+        self.must(TokenType::LeftParen)?;
+
+        let cond = self.parser_expr()?;
+        self.must(TokenType::RightParen)?;
+        expr.span.end = self.prev().unwrap().span.end;
+
+        let mut implicit_msg = self.expr_new();
+        implicit_msg.span.start = cond.span.start;
+        implicit_msg.span.end = cond.span.end;
+        implicit_msg.node = AstNode::Literal(TypeKind::String, self.source_text(cond.span), LiteralSource::Synthesized);
+
+        expr.node = AstNode::MacroAssert(cond, implicit_msg);
+        Ok(expr)
+    }
+
+    /**
+     * @stringify(expr) 展开为 expr 的源码字符串
+     */
+    fn parser_macro_stringify(&mut self, macro_token: &Token) -> Result<Box<Expr>, SyntaxError> {
+        let mut expr = self.expr_new();
+        expr.span.start = macro_token.span.start;
+        self.must(TokenType::LeftParen)?;
+
+        let target = self.parser_expr()?;
+        self.must(TokenType::RightParen)?;
+
+        expr.node = AstNode::Literal(TypeKind::String, self.source_text(target.span), LiteralSource::Synthesized);
+        expr.span.end = self.prev().unwrap().span.end;
         Ok(expr)
     }
 
     fn parser_macro_call(&mut self) -> Result<Box<Expr>, SyntaxError> {
-        let token = self.must(TokenType::MacroIdent)?;
+        let token = self.must(TokenType::MacroIdent)?.clone();
 
         // 根据宏名称选择对应的解析器
         match token.literal.as_str() {
@@ -3353,7 +4542,14 @@ impl Syntax {
             "default" => self.parser_macro_default_expr(),
             "async" => self.parser_macro_async_expr(),
             "ula" => self.parser_macro_ula_expr(),
-            _ => Err(SyntaxError(token.start, token.end, format!("macro '{}' not defined", token.literal))),
+            "bitcast" => self.parser_macro_bitcast(&token),
+            "line" => self.parser_macro_line(&token),
+            "file" => self.parser_macro_file(&token),
+            "min" => self.parser_macro_min_max(&token, MinMaxKind::Min),
+            "max" => self.parser_macro_min_max(&token, MinMaxKind::Max),
+            "assert" => self.parser_macro_assert(&token),
+            "stringify" => self.parser_macro_stringify(&token),
+            _ => Err(SyntaxError::at_token(&token, format!("macro '{}' not defined", token.literal))),
         }
     }
 
@@ -3367,6 +4563,8 @@ impl Syntax {
             self.parser_match_expr()
         } else if self.is(TokenType::Fn) {
             self.parser_fndef_expr()
+        } else if self.is(TokenType::FatArrow) {
+            self.parser_lambda_short()
         } else if self.is(TokenType::New) {
             self.parser_new_expr()
         } else {
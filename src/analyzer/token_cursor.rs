@@ -0,0 +1,224 @@
+use super::lexer::{Token, TokenType};
+
+/**
+ * 基于 lexer 输出的 token 列表进行导航, 注释作为 trivia 在 prev/next 时被跳过
+ *
+ * cursor 只借用 token 列表, 构造时不做任何预处理。lexer 输出中所有的 < 与 > 均为 LeftAngle/RightAngle,
+ * matching_bracket 通过启发式规则判断其是否为泛型参数的括号; parser 输出的 token(sem_token_db) 中比较运算符已经被区分为 LessThan/RightShift
+ */
+#[derive(Debug, Clone)]
+pub struct TokenCursor<'a> {
+    tokens: &'a [Token],
+    index: usize,
+}
+
+impl<'a> TokenCursor<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self { tokens, index: 0 }
+    }
+
+    pub fn current(&self) -> Option<&'a Token> {
+        self.tokens.get(self.index)
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /**
+     * 将 cursor 移动到包含 offset 的 token, offset 位于 token 之间(空白)时 cursor 停在之后的 token 并返回 None
+     *
+     * token 的 end 为开区间, offset 恰好位于两个相邻 token 的边界时返回后一个
+     */
+    pub fn token_at(&mut self, offset: usize) -> Option<&'a Token> {
+        self.index = self.tokens.partition_point(|t| (t.span.end as usize) <= offset);
+        let token = self.current()?;
+        if token.span.start as usize <= offset {
+            Some(token)
+        } else {
+            None
+        }
+    }
+
+    pub fn prev_non_trivia(&mut self) -> Option<&'a Token> {
+        let index = self.tokens[..self.index.min(self.tokens.len())]
+            .iter()
+            .rposition(|t| !is_trivia(&t.token_type))?;
+        self.index = index;
+        self.current()
+    }
+
+    // 到达 Eof 时返回 None
+    pub fn next_non_trivia(&mut self) -> Option<&'a Token> {
+        let start = self.index + 1;
+        let offset = self.tokens.get(start..)?.iter().position(|t| !is_trivia(&t.token_type))?;
+        if self.tokens[start + offset].token_type == TokenType::Eof {
+            return None;
+        }
+        self.index = start + offset;
+        self.current()
+    }
+
+    /**
+     * 当前 token 为括号时将 cursor 移动到与之匹配的括号, 支持 () [] {} 以及泛型参数的 <>
+     *
+     * 括号不匹配或者 < > 不是泛型参数(例如比较运算符)时返回 None, 此时 cursor 不移动
+     */
+    pub fn matching_bracket(&mut self) -> Option<&'a Token> {
+        let token_type = self.current()?.token_type.clone();
+        let index = match token_type {
+            TokenType::LeftAngle => self.match_angle_forward()?,
+            TokenType::RightAngle => self.match_angle_backward()?,
+            _ if bracket_close(&token_type).is_some() => self.match_forward()?,
+            _ if bracket_open(&token_type).is_some() => self.match_backward()?,
+            _ => return None,
+        };
+        self.index = index;
+        self.current()
+    }
+
+    // () [] {} 的匹配忽略其中的 < >
+    fn match_forward(&self) -> Option<usize> {
+        let mut stack: Vec<TokenType> = Vec::new();
+        for (i, token) in self.tokens.iter().enumerate().skip(self.index) {
+            if let Some(close) = bracket_close(&token.token_type) {
+                stack.push(close);
+            } else if bracket_open(&token.token_type).is_some() {
+                if stack.pop()? != token.token_type {
+                    return None;
+                }
+                if stack.is_empty() {
+                    return Some(i);
+                }
+            }
+        }
+        None
+    }
+
+    fn match_backward(&self) -> Option<usize> {
+        let mut stack: Vec<TokenType> = Vec::new();
+        for i in (0..=self.index).rev() {
+            let token_type = &self.tokens[i].token_type;
+            if let Some(open) = bracket_open(token_type) {
+                stack.push(open);
+            } else if bracket_close(token_type).is_some() {
+                if stack.pop()? != *token_type {
+                    return None;
+                }
+                if stack.is_empty() {
+                    return Some(i);
+                }
+            }
+        }
+        None
+    }
+
+    /**
+     * 与 parser 的判断方式一致, < 之前需要是 ident 或者类型关键字, 并且 <> 之间只能出现类型中的 token
+     */
+    fn match_angle_forward(&self) -> Option<usize> {
+        if !self.is_type_args_owner(self.index) {
+            return None;
+        }
+
+        let mut depth = 0;
+        let mut stack: Vec<TokenType> = Vec::new();
+        for (i, token) in self.tokens.iter().enumerate().skip(self.index) {
+            match &token.token_type {
+                TokenType::LeftAngle => depth += 1,
+                TokenType::RightAngle => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return if stack.is_empty() { Some(i) } else { None };
+                    }
+                }
+                t if bracket_close(t).is_some() => stack.push(bracket_close(t)?),
+                t if bracket_open(t).is_some() => {
+                    let open = stack.pop()?;
+                    if open != *t {
+                        return None;
+                    }
+                }
+                t if !is_type_token(t) => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn match_angle_backward(&self) -> Option<usize> {
+        let mut depth = 0;
+        let mut stack: Vec<TokenType> = Vec::new();
+        for i in (0..=self.index).rev() {
+            match &self.tokens[i].token_type {
+                TokenType::RightAngle => depth += 1,
+                TokenType::LeftAngle => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return if stack.is_empty() && self.is_type_args_owner(i) { Some(i) } else { None };
+                    }
+                }
+                t if bracket_open(t).is_some() => stack.push(bracket_open(t)?),
+                t if bracket_close(t).is_some() => {
+                    let open = stack.pop()?;
+                    if open != *t {
+                        return None;
+                    }
+                }
+                t if !is_type_token(t) => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn is_type_args_owner(&self, angle_index: usize) -> bool {
+        self.tokens[..angle_index]
+            .iter()
+            .rfind(|t| !is_trivia(&t.token_type))
+            .is_some_and(|t| t.token_type == TokenType::Ident || t.token_type.is_type_keyword())
+    }
+}
+
+pub fn is_trivia(t: &TokenType) -> bool {
+    matches!(t, TokenType::LineComment | TokenType::BlockComment)
+}
+
+fn bracket_close(t: &TokenType) -> Option<TokenType> {
+    match t {
+        TokenType::LeftParen => Some(TokenType::RightParen),
+        TokenType::LeftSquare => Some(TokenType::RightSquare),
+        TokenType::LeftCurly => Some(TokenType::RightCurly),
+        _ => None,
+    }
+}
+
+fn bracket_open(t: &TokenType) -> Option<TokenType> {
+    match t {
+        TokenType::RightParen => Some(TokenType::LeftParen),
+        TokenType::RightSquare => Some(TokenType::LeftSquare),
+        TokenType::RightCurly => Some(TokenType::LeftCurly),
+        _ => None,
+    }
+}
+
+// 泛型参数中可能出现的 token, 例如 map<string, [int]>, fn(int):bool, tup<int, T?>
+fn is_type_token(t: &TokenType) -> bool {
+    is_trivia(t)
+        || t.is_type_keyword()
+        || matches!(
+            t,
+            TokenType::Ident
+                | TokenType::Dot
+                | TokenType::Comma
+                | TokenType::Colon
+                | TokenType::Question
+                | TokenType::Or
+                | TokenType::Equal
+                | TokenType::IntLiteral
+                | TokenType::Not
+                | TokenType::Null
+                | TokenType::Fn
+                | TokenType::Struct
+        )
+}
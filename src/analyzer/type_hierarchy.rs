@@ -0,0 +1,140 @@
+use super::common::{AstNode, Type, TypeKind};
+use crate::project::Module;
+use crate::utils::range_of;
+use lsp_types::{Range, SymbolKind, TypeHierarchyItem, Url};
+use serde_json::json;
+use std::collections::HashMap;
+
+struct AliasEntry {
+    item: TypeHierarchyItem,
+    members: Vec<String>, // union 的成员, 非 union alias 为空
+}
+
+/**
+ * nature 中没有继承, 类型层级由 union 构成: union 的 subtypes 为其成员, 成员的 supertypes 为包含它的 union
+ *
+ * alias 通过 global ident(module_ident.ident) 标识, builtin 类型通过类型名称标识
+ */
+#[derive(Default)]
+pub struct TypeHierarchyIndex {
+    aliases: HashMap<String, AliasEntry>,
+    containing: HashMap<String, Vec<String>>, // member -> 包含该成员的 union
+}
+
+// 类型推导后 alias 会被 reduction, 此时 impl_ident 记录了原始的 alias ident
+fn member_ident(t: &Type) -> String {
+    match &t.kind {
+        TypeKind::Alias(alias) => alias.ident.clone(),
+        _ => t.impl_ident.clone().unwrap_or_else(|| t.kind.to_string()),
+    }
+}
+
+fn display_name(ident: &str) -> String {
+    ident.rsplit('.').next().unwrap_or(ident).to_string()
+}
+
+impl TypeHierarchyIndex {
+    pub fn build(modules: &[Module]) -> Self {
+        let mut index = Self::default();
+
+        for m in modules {
+            let Ok(uri) = Url::from_file_path(&m.path) else {
+                continue;
+            };
+
+            for stmt in &m.stmts {
+                let AstNode::TypeAlias(type_alias_mutex) = &stmt.node else {
+                    continue;
+                };
+                let type_alias = type_alias_mutex.read().unwrap();
+                let (Some(range), Some(selection_range)) = (
+                    range_of(stmt.span.start as usize, stmt.span.end as usize, &m.line_index),
+                    range_of(type_alias.symbol_start, type_alias.symbol_end, &m.line_index),
+                ) else {
+                    continue;
+                };
+
+                let (kind, members) = match &type_alias.type_expr.kind {
+                    TypeKind::Union(_, elements) => (SymbolKind::ENUM, elements.iter().map(member_ident).collect::<Vec<_>>()),
+                    TypeKind::Struct(..) => (SymbolKind::STRUCT, Vec::new()),
+                    _ => (SymbolKind::CLASS, Vec::new()),
+                };
+
+                for member in &members {
+                    index.containing.entry(member.clone()).or_default().push(type_alias.ident.clone());
+                }
+
+                let item = TypeHierarchyItem {
+                    name: display_name(&type_alias.ident),
+                    kind,
+                    tags: None,
+                    detail: Some(m.ident.clone()),
+                    uri: uri.clone(),
+                    range,
+                    selection_range,
+                    data: Some(json!({ "ident": type_alias.ident })),
+                };
+                index.aliases.insert(type_alias.ident.clone(), AliasEntry { item, members });
+            }
+        }
+
+        index
+    }
+
+    fn item(&self, ident: &str, uri: &Url) -> TypeHierarchyItem {
+        if let Some(entry) = self.aliases.get(ident) {
+            return entry.item.clone();
+        }
+
+        // builtin 类型没有定义位置, 作为叶子节点出现
+        TypeHierarchyItem {
+            name: ident.to_string(),
+            kind: SymbolKind::CLASS,
+            tags: None,
+            detail: Some("builtin".to_string()),
+            uri: uri.clone(),
+            range: Range::default(),
+            selection_range: Range::default(),
+            data: Some(json!({ "ident": ident })),
+        }
+    }
+
+    fn ident_of(item: &TypeHierarchyItem) -> Option<&str> {
+        item.data.as_ref()?.get("ident")?.as_str()
+    }
+
+    /**
+     * 光标位于 type alias 的声明处时返回对应的 item
+     */
+    pub fn prepare(&self, m: &Module, offset: usize) -> Option<TypeHierarchyItem> {
+        m.stmts.iter().find_map(|stmt| {
+            let AstNode::TypeAlias(type_alias_mutex) = &stmt.node else {
+                return None;
+            };
+            let type_alias = type_alias_mutex.read().unwrap();
+            if offset < type_alias.symbol_start || offset > type_alias.symbol_end {
+                return None;
+            }
+            self.aliases.get(&type_alias.ident).map(|entry| entry.item.clone())
+        })
+    }
+
+    pub fn supertypes(&self, item: &TypeHierarchyItem) -> Vec<TypeHierarchyItem> {
+        let Some(ident) = Self::ident_of(item) else {
+            return Vec::new();
+        };
+
+        self.containing
+            .get(ident)
+            .map(|unions| unions.iter().map(|union| self.item(union, &item.uri)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn subtypes(&self, item: &TypeHierarchyItem) -> Vec<TypeHierarchyItem> {
+        let Some(entry) = Self::ident_of(item).and_then(|ident| self.aliases.get(ident)) else {
+            return Vec::new();
+        };
+
+        entry.members.iter().map(|member| self.item(member, &entry.item.uri)).collect()
+    }
+}
@@ -0,0 +1,520 @@
+use super::common::{Type, TypeAlias, TypeFn, TypeKind, TypeStructProperty};
+use super::symbol::{SymbolKind, SymbolTable};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/**
+ * struct 中 field 的类型, 非 struct 类型或者 field 不存在时返回 None
+ */
+pub fn struct_field_type<'a>(kind: &'a TypeKind, field: &str) -> Option<&'a Type> {
+    let TypeKind::Struct(_, _, properties) = kind else {
+        return None;
+    };
+    properties.iter().find(|p| p.key == field).map(|p| &p.type_)
+}
+
+/**
+ * field 在 struct 中的声明顺序, 从 0 开始
+ */
+pub fn struct_field_index(kind: &TypeKind, field: &str) -> Option<usize> {
+    let TypeKind::Struct(_, _, properties) = kind else {
+        return None;
+    };
+    properties.iter().position(|p| p.key == field)
+}
+
+/**
+ * 去除 origin_ident, impl_ident, 位置等元信息, 只保留结构化的 kind, [int] 与 vec<int> 等写法 normalize 之后相同
+ */
+pub fn normalize_type(t: &Type) -> Type {
+    let normalize_all = |types: &[Type]| types.iter().map(normalize_type).collect::<Vec<_>>();
+    let normalize_box = |t: &Type| Box::new(normalize_type(t));
+
+    let kind = match &t.kind {
+        TypeKind::Vec(element_type) => TypeKind::Vec(normalize_box(element_type)),
+        TypeKind::Arr(length, element_type) => TypeKind::Arr(*length, normalize_box(element_type)),
+        TypeKind::Map(key_type, value_type) => TypeKind::Map(normalize_box(key_type), normalize_box(value_type)),
+        TypeKind::Set(element_type) => TypeKind::Set(normalize_box(element_type)),
+        TypeKind::Tuple(elements, align) => TypeKind::Tuple(normalize_all(elements), *align),
+        TypeKind::Chan(element_type) => TypeKind::Chan(normalize_box(element_type)),
+        TypeKind::Promise(value_type) => TypeKind::Promise(normalize_box(value_type)),
+        TypeKind::Ptr(value_type) => TypeKind::Ptr(normalize_box(value_type)),
+        TypeKind::RawPtr(value_type) => TypeKind::RawPtr(normalize_box(value_type)),
+        TypeKind::Union(any, elements) => TypeKind::Union(*any, normalize_all(elements)),
+        TypeKind::Struct(ident, align, properties) => TypeKind::Struct(
+            ident.clone(),
+            *align,
+            properties
+                .iter()
+                .map(|p| TypeStructProperty {
+                    type_: normalize_type(&p.type_),
+                    key: p.key,
+                    value: None,
+                    start: 0,
+                    end: 0,
+                })
+                .collect(),
+        ),
+        TypeKind::Fn(type_fn) => TypeKind::Fn(Box::new(TypeFn {
+            name: String::new(),
+            return_type: normalize_type(&type_fn.return_type),
+            param_types: normalize_all(&type_fn.param_types),
+            ..*type_fn.clone()
+        })),
+        TypeKind::Alias(alias) => {
+            let mut alias = alias.clone();
+            for arg in alias.args.iter_mut().flatten() {
+                arg.type_ = normalize_type(&arg.type_);
+            }
+            TypeKind::Alias(alias)
+        }
+        kind => kind.clone(),
+    };
+
+    Type {
+        in_heap: Type::kind_in_heap(&kind),
+        kind,
+        ..Default::default()
+    }
+}
+
+/**
+ * 结构化比较两个类型, 只比较 normalize_type 保留的部分, origin_ident, 位置, fn name 等元信息不参与比较
+ */
+pub fn type_eq(a: &Type, b: &Type) -> bool {
+    let all = |a: &[Type], b: &[Type]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| type_eq(a, b));
+
+    match (&a.kind, &b.kind) {
+        (TypeKind::Vec(a), TypeKind::Vec(b))
+        | (TypeKind::Set(a), TypeKind::Set(b))
+        | (TypeKind::Chan(a), TypeKind::Chan(b))
+        | (TypeKind::Promise(a), TypeKind::Promise(b))
+        | (TypeKind::Ptr(a), TypeKind::Ptr(b))
+        | (TypeKind::RawPtr(a), TypeKind::RawPtr(b)) => type_eq(a, b),
+        (TypeKind::Arr(a_length, a), TypeKind::Arr(b_length, b)) => a_length == b_length && type_eq(a, b),
+        (TypeKind::Map(a_key, a_value), TypeKind::Map(b_key, b_value)) => type_eq(a_key, b_key) && type_eq(a_value, b_value),
+        (TypeKind::Tuple(a, _), TypeKind::Tuple(b, _)) => all(a, b),
+        (TypeKind::Union(a_any, a), TypeKind::Union(b_any, b)) => a_any == b_any && all(a, b),
+        (TypeKind::Struct(a_ident, _, a), TypeKind::Struct(b_ident, _, b)) => {
+            a_ident == b_ident && a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.key == b.key && type_eq(&a.type_, &b.type_))
+        }
+        (TypeKind::Fn(a), TypeKind::Fn(b)) => {
+            a.errable == b.errable && a.rest == b.rest && type_eq(&a.return_type, &b.return_type) && all(&a.param_types, &b.param_types)
+        }
+        (TypeKind::Alias(a), TypeKind::Alias(b)) => {
+            let args_eq = match (&a.args, &b.args) {
+                (Some(a), Some(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.name == b.name && type_eq(&a.type_, &b.type_)),
+                (None, None) => true,
+                _ => false,
+            };
+            a.ident == b.ident && a.import_as == b.import_as && args_eq
+        }
+        (TypeKind::Param(a), TypeKind::Param(b)) => a == b,
+        // 其余 variant 不携带数据, TypeKind 的 == 只比较 variant
+        (a, b) => a == b,
+    }
+}
+
+/**
+ * 面向用户展示的类型字符串(诊断, hover, inlay hint), 与 Display 不同, 结果不保证能够重新解析
+ *
+ * 元素与实参之间使用 ", " 分隔, T|null 展示为 T?, 具名 struct 展示为 struct person, alias 归约后的类型使用 origin_ident 与实参展示
+ */
+pub fn render(t: &Type) -> String {
+    render_depth(t, 0, usize::MAX)
+}
+
+/**
+ * 超过 max_length 时优先将深层的元素与实参折叠为 …, 例如 map<string, vec<…>>, 仍然超过时直接截断
+ */
+pub fn render_truncated(t: &Type, max_length: usize) -> String {
+    let full = render(t);
+    if full.chars().count() <= max_length {
+        return full;
+    }
+
+    for max_depth in (0..MAX_RENDER_DEPTH).rev() {
+        let collapsed = render_depth(t, 0, max_depth);
+        if collapsed.chars().count() <= max_length {
+            return collapsed;
+        }
+    }
+
+    let truncated: String = full.chars().take(max_length.saturating_sub(1)).collect();
+    format!("{}…", truncated)
+}
+
+const MAX_RENDER_DEPTH: usize = 8;
+
+fn render_depth(t: &Type, depth: usize, max_depth: usize) -> String {
+    let join = |types: &[&Type]| -> String {
+        if depth >= max_depth {
+            return "…".to_string();
+        }
+        types.iter().map(|t| render_depth(t, depth + 1, max_depth)).collect::<Vec<_>>().join(", ")
+    };
+
+    // alias 归约之后的类型, 例如 type box<T> = struct{...} 中的 box<int>
+    if let Some(ident) = &t.origin_ident {
+        if !matches!(t.kind, TypeKind::Alias(..) | TypeKind::Param(..)) {
+            let mut result = ident.clone();
+            if !t.impl_args.is_empty() {
+                result = format!("{}<{}>", result, join(&t.impl_args.iter().collect::<Vec<_>>()));
+            }
+            if matches!(t.kind, TypeKind::Struct(..)) {
+                result = format!("struct {}", result);
+            }
+            return result;
+        }
+    }
+
+    match &t.kind {
+        TypeKind::Vec(element_type) => format!("vec<{}>", join(&[element_type])),
+        TypeKind::Set(element_type) => format!("set<{}>", join(&[element_type])),
+        TypeKind::Chan(element_type) => format!("chan<{}>", join(&[element_type])),
+        TypeKind::Promise(value_type) => format!("promise<{}>", join(&[value_type])),
+        TypeKind::Ptr(value_type) => format!("ptr<{}>", join(&[value_type])),
+        TypeKind::RawPtr(value_type) => format!("raw_ptr<{}>", join(&[value_type])),
+        TypeKind::Arr(length, element_type) => format!("arr<{}, {}>", join(&[element_type]), length),
+        TypeKind::Map(key_type, value_type) => format!("map<{}>", join(&[key_type, value_type])),
+        TypeKind::Tuple(elements, _) => format!("({})", join(&elements.iter().collect::<Vec<_>>())),
+        TypeKind::Fn(type_fn) => {
+            let params = join(&type_fn.param_types.iter().collect::<Vec<_>>());
+            let return_type = if depth >= max_depth {
+                "…".to_string()
+            } else {
+                render_depth(&type_fn.return_type, depth + 1, max_depth)
+            };
+            format!("fn({}):{}{}", params, return_type, if type_fn.errable { "!" } else { "" })
+        }
+        TypeKind::Struct(ident, ..) if !ident.is_empty() => format!("struct {}", ident),
+        TypeKind::Struct(_, _, properties) => {
+            if depth >= max_depth {
+                return "struct{…}".to_string();
+            }
+            let fields: Vec<String> = properties
+                .iter()
+                .map(|p| format!("{} {}", render_depth(&p.type_, depth + 1, max_depth), p.key))
+                .collect();
+            format!("struct{{{}}}", fields.join(", "))
+        }
+        TypeKind::Alias(alias) => {
+            let mut result = match &alias.import_as {
+                Some(import_as) => format!("{}.{}", import_as, alias.ident),
+                None => alias.ident.clone(),
+            };
+            if let Some(args) = &alias.args {
+                let rendered = if depth >= max_depth {
+                    "…".to_string()
+                } else {
+                    let args: Vec<String> = args
+                        .iter()
+                        .map(|arg| {
+                            let type_ = render_depth(&arg.type_, depth + 1, max_depth);
+                            match &arg.name {
+                                Some(name) => format!("{}={}", name, type_),
+                                None => type_,
+                            }
+                        })
+                        .collect();
+                    args.join(", ")
+                };
+                result = format!("{}<{}>", result, rendered);
+            }
+            result
+        }
+        TypeKind::Param(ident) => ident.clone(),
+        TypeKind::Union(true, _) => "any".to_string(),
+        // union 的成员与 union 处于同一层级, T|U|null 展示为 T|U?
+        TypeKind::Union(false, elements) => {
+            let members: Vec<String> = elements
+                .iter()
+                .filter(|e| !matches!(e.kind, TypeKind::Null))
+                .map(|e| render_depth(e, depth, max_depth))
+                .collect();
+            let nullable = members.len() < elements.len();
+            if members.is_empty() {
+                return "null".to_string();
+            }
+            format!("{}{}", members.join("|"), if nullable { "?" } else { "" })
+        }
+        kind => kind.to_string(),
+    }
+}
+
+// 递归的 alias(例如链表节点)展开后会无限嵌套, 超过该深度时认为两侧类型一致
+const MAX_RELATION_DEPTH: usize = 64;
+
+/**
+ * assignable_to 与 compatible_for_eq 使用的上下文
+ *
+ * aliases 记录 alias 的 params 与右值类型, 未记录的 alias 通过 symbol table 中的 type 声明展开
+ * generics 记录泛型参数在比较过程中绑定的类型, 同一个 env 中的多次比较共享绑定
+ */
+#[derive(Debug, Default)]
+pub struct TypeEnv<'a> {
+    aliases: HashMap<String, (Vec<String>, Type)>,
+    symbol_table: Option<&'a SymbolTable>,
+    generics: RefCell<HashMap<String, Type>>,
+    pub literal: bool, // 源值为没有声明类型的数字字面量, 例如 1 与 1.5
+}
+
+impl<'a> TypeEnv<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // alias 的 symbol_id 由 semantic 写入, 没有 symbol_id 时按照全局名称(module.ident)查找
+    pub fn with_symbol_table(symbol_table: &'a SymbolTable) -> Self {
+        Self {
+            symbol_table: Some(symbol_table),
+            ..Self::default()
+        }
+    }
+
+    // type pair<T, U> = struct{...} 对应 define_alias("pair", vec!["T", "U"], struct{...})
+    pub fn define_alias(&mut self, ident: impl Into<String>, params: Vec<String>, type_: Type) {
+        self.aliases.insert(ident.into(), (params, type_));
+    }
+
+    pub fn generics_binding(&self, param: &str) -> Option<Type> {
+        self.generics.borrow().get(param).cloned()
+    }
+
+    fn alias_definition(&self, alias: &TypeAlias) -> Option<(Vec<String>, Type)> {
+        if let Some(definition) = self.aliases.get(&alias.ident) {
+            return Some(definition.clone());
+        }
+
+        let symbol_table = self.symbol_table?;
+        let symbol = match alias.symbol_id {
+            Some(symbol_id) => symbol_table.find_symbol(symbol_id),
+            None => symbol_table.find_global_symbol(&alias.ident),
+        }?;
+        let SymbolKind::TypeAlias(type_alias_stmt) = &symbol.kind else {
+            return None;
+        };
+        let type_alias_stmt = type_alias_stmt.read().unwrap();
+        let params = type_alias_stmt.params.iter().map(|param| param.ident.clone()).collect();
+        Some((params, type_alias_stmt.type_expr.clone()))
+    }
+
+    /**
+     * 展开 alias 直到得到非 alias 类型, alias 的实参会替换右值中对应的泛型参数, 无法展开时返回 alias 本身
+     */
+    fn resolve(&self, t: &Type) -> Type {
+        let mut t = t.clone();
+        for _ in 0..MAX_RELATION_DEPTH {
+            let TypeKind::Alias(alias) = &t.kind else {
+                break;
+            };
+            let Some((params, value_type)) = self.alias_definition(alias) else {
+                break;
+            };
+
+            let args: Vec<&Type> = alias.args.iter().flatten().map(|arg| &arg.type_).collect();
+            if args.len() != params.len() {
+                break;
+            }
+            let table: HashMap<&str, &Type> = params.iter().map(|param| param.as_str()).zip(args).collect();
+            t = substitute(&value_type, &table);
+        }
+        t
+    }
+
+    // 比较失败时撤销比较过程中产生的泛型绑定
+    fn attempt(&self, f: impl FnOnce() -> bool) -> bool {
+        let snapshot = self.generics.borrow().clone();
+        let result = f();
+        if !result {
+            *self.generics.borrow_mut() = snapshot;
+        }
+        result
+    }
+
+    fn bind(&self, param: &str, src: &Type, depth: usize) -> bool {
+        let bound = self.generics_binding(param);
+        match bound {
+            Some(bound) => self.exact(src, &bound, depth + 1),
+            None => {
+                self.generics.borrow_mut().insert(param.to_string(), src.clone());
+                true
+            }
+        }
+    }
+
+    fn assignable(&self, src: &Type, dst: &Type, literal: bool, depth: usize) -> bool {
+        if src.err || dst.err || src.kind.is_unknown() || dst.kind.is_unknown() {
+            return false;
+        }
+        if depth > MAX_RELATION_DEPTH {
+            return true;
+        }
+        let (src, dst) = (self.resolve(src), self.resolve(dst));
+
+        if literal && ((Type::is_integer(&src.kind) && Type::is_number(&dst.kind)) || (Type::is_float(&src.kind) && Type::is_float(&dst.kind))) {
+            return true;
+        }
+
+        match (&src.kind, &dst.kind) {
+            (TypeKind::Union(src_any, src_elements), TypeKind::Union(false, dst_elements)) => {
+                !src_any
+                    && src_elements
+                        .iter()
+                        .all(|s| dst_elements.iter().any(|d| self.attempt(|| self.exact(s, d, depth + 1))))
+            }
+            (_, TypeKind::Union(false, dst_elements)) => dst_elements.iter().any(|d| self.attempt(|| self.assignable(&src, d, literal, depth + 1))),
+            (TypeKind::Null, TypeKind::RawPtr(_)) => true,
+            (TypeKind::Ptr(src_value), TypeKind::RawPtr(dst_value)) => self.exact(src_value, dst_value, depth + 1),
+            _ => self.exact(&src, &dst, depth),
+        }
+    }
+
+    /**
+     * 嵌套位置(容器元素, fn 参数等)要求类型一致, 只保留 all_t, any, fn_t 与泛型参数的匹配
+     */
+    fn exact(&self, src: &Type, dst: &Type, depth: usize) -> bool {
+        if src.err || dst.err || src.kind.is_unknown() || dst.kind.is_unknown() {
+            return false;
+        }
+        if depth > MAX_RELATION_DEPTH {
+            return true;
+        }
+        let (src, dst) = (self.resolve(src), self.resolve(dst));
+        let depth = depth + 1;
+
+        match &dst.kind {
+            TypeKind::AllT | TypeKind::Union(true, _) => return !matches!(src.kind, TypeKind::Void),
+            TypeKind::FnT if matches!(src.kind, TypeKind::Fn(..)) => return true,
+            TypeKind::Param(param) => return self.bind(param, &src, depth),
+            _ => {}
+        }
+
+        if src.kind != dst.kind {
+            return false;
+        }
+
+        let all = |src: &[Type], dst: &[Type]| src.len() == dst.len() && src.iter().zip(dst).all(|(s, d)| self.exact(s, d, depth));
+
+        match (&src.kind, &dst.kind) {
+            (TypeKind::Union(src_any, src_elements), TypeKind::Union(dst_any, dst_elements)) => {
+                let contains = |elements: &[Type], t: &Type| elements.iter().any(|e| self.attempt(|| self.exact(t, e, depth)));
+                src_any == dst_any && src_elements.iter().all(|s| contains(dst_elements, s)) && dst_elements.iter().all(|d| contains(src_elements, d))
+            }
+            (TypeKind::Vec(s), TypeKind::Vec(d))
+            | (TypeKind::Set(s), TypeKind::Set(d))
+            | (TypeKind::Chan(s), TypeKind::Chan(d))
+            | (TypeKind::Promise(s), TypeKind::Promise(d))
+            | (TypeKind::Ptr(s), TypeKind::Ptr(d))
+            | (TypeKind::RawPtr(s), TypeKind::RawPtr(d)) => self.exact(s, d, depth),
+            (TypeKind::Arr(src_len, s), TypeKind::Arr(dst_len, d)) => src_len == dst_len && self.exact(s, d, depth),
+            (TypeKind::Map(src_key, src_value), TypeKind::Map(dst_key, dst_value)) => {
+                self.exact(src_key, dst_key, depth) && self.exact(src_value, dst_value, depth)
+            }
+            (TypeKind::Tuple(src_elements, _), TypeKind::Tuple(dst_elements, _)) => all(src_elements, dst_elements),
+            (TypeKind::Fn(src_fn), TypeKind::Fn(dst_fn)) => {
+                src_fn.rest == dst_fn.rest
+                    && src_fn.errable == dst_fn.errable
+                    && self.exact(&src_fn.return_type, &dst_fn.return_type, depth)
+                    && all(&src_fn.param_types, &dst_fn.param_types)
+            }
+            (TypeKind::Struct(_, _, src_properties), TypeKind::Struct(_, _, dst_properties)) => {
+                src_properties.len() == dst_properties.len()
+                    && src_properties
+                        .iter()
+                        .zip(dst_properties)
+                        .all(|(s, d)| s.key == d.key && self.exact(&s.type_, &d.type_, depth))
+            }
+            (TypeKind::Alias(src_alias), TypeKind::Alias(dst_alias)) => {
+                let args = |alias: &TypeAlias| alias.args.iter().flatten().map(|arg| arg.type_.clone()).collect::<Vec<_>>();
+                src_alias.ident == dst_alias.ident && src_alias.import_as == dst_alias.import_as && all(&args(src_alias), &args(dst_alias))
+            }
+            (TypeKind::Param(src_param), TypeKind::Param(dst_param)) => src_param == dst_param,
+            _ => true,
+        }
+    }
+}
+
+/**
+ * 将 t 中的泛型参数替换为 table 中对应的类型
+ */
+fn substitute(t: &Type, table: &HashMap<&str, &Type>) -> Type {
+    let all = |types: &[Type]| types.iter().map(|t| substitute(t, table)).collect::<Vec<_>>();
+    let boxed = |t: &Type| Box::new(substitute(t, table));
+
+    let kind = match &t.kind {
+        TypeKind::Param(param) => match table.get(param.as_str()) {
+            Some(arg) => return (*arg).clone(),
+            None => t.kind.clone(),
+        },
+        TypeKind::Alias(alias) => {
+            let mut alias = alias.clone();
+            for arg in alias.args.iter_mut().flatten() {
+                arg.type_ = substitute(&arg.type_, table);
+            }
+            TypeKind::Alias(alias)
+        }
+        TypeKind::Vec(element_type) => TypeKind::Vec(boxed(element_type)),
+        TypeKind::Arr(length, element_type) => TypeKind::Arr(*length, boxed(element_type)),
+        TypeKind::Map(key_type, value_type) => TypeKind::Map(boxed(key_type), boxed(value_type)),
+        TypeKind::Set(element_type) => TypeKind::Set(boxed(element_type)),
+        TypeKind::Tuple(elements, align) => TypeKind::Tuple(all(elements), *align),
+        TypeKind::Chan(element_type) => TypeKind::Chan(boxed(element_type)),
+        TypeKind::Promise(value_type) => TypeKind::Promise(boxed(value_type)),
+        TypeKind::Ptr(value_type) => TypeKind::Ptr(boxed(value_type)),
+        TypeKind::RawPtr(value_type) => TypeKind::RawPtr(boxed(value_type)),
+        TypeKind::Union(any, elements) => TypeKind::Union(*any, all(elements)),
+        TypeKind::Struct(ident, align, properties) => TypeKind::Struct(
+            ident.clone(),
+            *align,
+            properties
+                .iter()
+                .map(|p| TypeStructProperty {
+                    type_: substitute(&p.type_, table),
+                    ..p.clone()
+                })
+                .collect(),
+        ),
+        TypeKind::Fn(type_fn) => TypeKind::Fn(Box::new(TypeFn {
+            return_type: substitute(&type_fn.return_type, table),
+            param_types: all(&type_fn.param_types),
+            ..*type_fn.clone()
+        })),
+        kind => kind.clone(),
+    };
+
+    Type { kind, ..t.clone() }
+}
+
+impl Type {
+    /**
+     * self 类型的值能否用于需要 other 类型的位置(赋值, 传参, 返回值), 规则按照顺序匹配:
+     *
+     * 1. 任意一侧为 unknown 或者存在 err 时不能赋值
+     * 2. alias 通过 env 中记录的 alias 或者 symbol table 中的 type 声明展开后比较, 无法展开的 alias 按照名称与实参比较
+     * 3. env.literal 为 true 时, 整数字面量可以赋值给任意宽度的整数与浮点数, 浮点数字面量可以赋值给任意宽度的浮点数, 数值范围由字面量转换检查
+     * 4. other 为 union 时, self 需要能够赋值给其中一个成员, self 同样为 union 时每个成员都需要被 other 包含,
+     *    因此 T 可以赋值给 T?, 而 T? 不能赋值给 T, any 不能赋值给非 any 的 union
+     * 5. null 与 ptr<T> 可以赋值给 raw_ptr<T>
+     * 6. 其余情况要求类型一致: all_t 与 any 接受任意非 void 类型, fn_t 接受任意 fn, 泛型参数 T 在第一次匹配时绑定为对应的类型, 之后需要与绑定的类型一致。
+     *    复合类型逐个比较元素, 元素之间不再进行上述隐式转换(vec<int> 不能赋值给 vec<int?>),
+     *    struct 按照 field 的名称与顺序进行结构化比较, 不比较 struct 的名称
+     */
+    pub fn assignable_to(&self, other: &Type, env: &TypeEnv) -> bool {
+        env.attempt(|| env.assignable(self, other, env.literal, 0))
+    }
+
+    /**
+     * == 与 != 两侧的类型能否比较, 同类的数值类型(浮点数或者符号相同的整数)比较时会扩展为较宽的类型, 其余情况需要一侧能够赋值给另一侧
+     */
+    pub fn compatible_for_eq(&self, other: &Type, env: &TypeEnv) -> bool {
+        let (left, right) = (env.resolve(self), env.resolve(other));
+        let same_class = (Type::is_float(&left.kind) && Type::is_float(&right.kind))
+            || (Type::is_integer(&left.kind) && Type::is_integer(&right.kind) && left.kind.is_unsigned() == right.kind.is_unsigned());
+        if same_class && !left.err && !right.err {
+            return true;
+        }
+
+        self.assignable_to(other, env) || other.assignable_to(self, env)
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::{Arc, RwLock},
 };
 
 use std::collections::hash_map::DefaultHasher;
@@ -12,9 +12,14 @@ use crate::{
         symbol::{SymbolKind, GLOBAL_SCOPE_ID},
     },
     project::Module,
+    scheduler::CancellationToken,
     utils::format_generics_ident,
 };
 
+use super::intern::Atom;
+use super::syntax::LAMBDA_SHORT_PARAM;
+use super::type_utils::render;
+
 use super::{
     common::{AnalyzerError, AstCall, AstNode, Expr, Stmt, Type, TypeAliasStmt, TypeFn, VarDeclExpr},
     symbol::{NodeId, SymbolTable},
@@ -23,12 +28,12 @@ use super::{
 #[derive(Debug, Clone)]
 pub struct GenericSpecialFnClone {
     // default 是 none, clone 过程中, 当 global fn clone 完成后，将 clone 完成的 global fn 赋值给 global_parent
-    global_parent: Option<Arc<Mutex<AstFnDef>>>,
+    global_parent: Option<Arc<RwLock<AstFnDef>>>,
 }
 
 impl GenericSpecialFnClone {
-    pub fn deep_clone(&mut self, fn_mutex: &Arc<Mutex<AstFnDef>>) -> Arc<Mutex<AstFnDef>> {
-        let fn_def = fn_mutex.lock().unwrap();
+    pub fn deep_clone(&mut self, fn_mutex: &Arc<RwLock<AstFnDef>>) -> Arc<RwLock<AstFnDef>> {
+        let fn_def = fn_mutex.read().unwrap();
         let mut fn_def_clone = fn_def.clone();
 
         // type 中不包含 arc, 所以可以直接进行 clone
@@ -38,8 +43,8 @@ impl GenericSpecialFnClone {
             .params
             .iter()
             .map(|param| {
-                let param_clone = param.lock().unwrap().clone();
-                Arc::new(Mutex::new(param_clone))
+                let param_clone = param.read().unwrap().clone();
+                Arc::new(RwLock::new(param_clone))
             })
             .collect();
 
@@ -55,13 +60,13 @@ impl GenericSpecialFnClone {
             assert!(self.global_parent.is_some());
             fn_def_clone.global_parent = self.global_parent.clone();
             {
-                let mut global_parent = self.global_parent.as_ref().unwrap().lock().unwrap();
-                let result = Arc::new(Mutex::new(fn_def_clone));
+                let mut global_parent = self.global_parent.as_ref().unwrap().write().unwrap();
+                let result = Arc::new(RwLock::new(fn_def_clone));
                 global_parent.local_children.push(result.clone());
                 return result;
             }
         } else {
-            let result = Arc::new(Mutex::new(fn_def_clone));
+            let result = Arc::new(RwLock::new(fn_def_clone));
             self.global_parent = Some(result.clone());
             return result;
         }
@@ -73,11 +78,12 @@ impl GenericSpecialFnClone {
 
     fn clone_expr(&mut self, expr: &Expr) -> Expr {
         let node = match &expr.node {
-            AstNode::Literal(kind, value) => AstNode::Literal(kind.clone(), value.clone()),
-            AstNode::Ident(ident, symbol_id) => AstNode::Ident(ident.clone(), symbol_id.clone()),
-            AstNode::EnvAccess(index, ident, symbol_id) => AstNode::EnvAccess(*index, ident.clone(), symbol_id.clone()),
+            AstNode::Literal(kind, value, source) => AstNode::Literal(kind.clone(), value.clone(), *source),
+            AstNode::Ident(ident, symbol_id) => AstNode::Ident(*ident, symbol_id.clone()),
+            AstNode::EnvAccess(index, ident, symbol_id) => AstNode::EnvAccess(*index, *ident, symbol_id.clone()),
 
             AstNode::Binary(op, left, right) => AstNode::Binary(op.clone(), Box::new(self.clone_expr(left)), Box::new(self.clone_expr(right))),
+            AstNode::Range(start, end) => AstNode::Range(Box::new(self.clone_expr(start)), Box::new(self.clone_expr(end))),
             AstNode::Unary(op, operand) => AstNode::Unary(op.clone(), Box::new(self.clone_expr(operand))),
             AstNode::AccessExpr(left, key) => AstNode::AccessExpr(Box::new(self.clone_expr(left)), Box::new(self.clone_expr(key))),
             AstNode::VecNew(elements, len, cap) => AstNode::VecNew(
@@ -104,26 +110,29 @@ impl GenericSpecialFnClone {
                 Box::new(self.clone_expr(left)),
                 Box::new(self.clone_expr(key)),
             ),
-            AstNode::StructNew(ident, type_, props) => AstNode::StructNew(
+            AstNode::StructNew(ident, type_, entries) => AstNode::StructNew(
                 ident.clone(),
                 type_.clone(),
-                props
+                entries
                     .iter()
-                    .map(|p| StructNewProperty {
-                        type_: p.type_.clone(),
-                        key: p.key.clone(),
-                        value: Box::new(self.clone_expr(&p.value)),
-                        start: p.start,
-                        end: p.end,
+                    .map(|entry| match entry {
+                        StructNewEntry::Property(p) => StructNewEntry::Property(Box::new(StructNewProperty {
+                            type_: p.type_.clone(),
+                            key: p.key,
+                            value: Box::new(self.clone_expr(&p.value)),
+                            start: p.start,
+                            end: p.end,
+                        })),
+                        StructNewEntry::Spread(expr) => StructNewEntry::Spread(Box::new(self.clone_expr(expr))),
                     })
                     .collect(),
             ),
             AstNode::StructSelect(instance, key, property) => AstNode::StructSelect(
                 Box::new(self.clone_expr(instance)),
-                key.clone(),
+                *key,
                 TypeStructProperty {
                     type_: property.type_.clone(),
-                    key: property.key.clone(),
+                    key: property.key,
                     start: property.start,
                     end: property.end,
                     value: Some(Box::new(self.clone_expr(&property.value.as_ref().unwrap()))),
@@ -144,44 +153,55 @@ impl GenericSpecialFnClone {
             }),
 
             AstNode::FnDef(fn_def_mutex) => AstNode::FnDef(self.deep_clone(fn_def_mutex)),
-            AstNode::New(type_, props) => AstNode::New(
+            AstNode::New(type_, props, args) => AstNode::New(
                 type_.clone(),
                 props
                     .iter()
                     .map(|p| StructNewProperty {
                         type_: p.type_.clone(),
-                        key: p.key.clone(),
+                        key: p.key,
                         value: Box::new(self.clone_expr(&p.value)),
                         start: p.start,
                         end: p.end,
                     })
                     .collect(),
+                args.iter().map(|e| Box::new(self.clone_expr(e))).collect(),
             ),
             AstNode::As(type_, src) => AstNode::As(type_.clone(), Box::new(self.clone_expr(src))),
+            // 隐式转换在重新推导时会再次插入
+            AstNode::TypeCast(_, src) => return self.clone_expr(src),
             AstNode::Is(type_, src) => AstNode::Is(type_.clone(), Box::new(self.clone_expr(src))),
             AstNode::MatchIs(type_) => AstNode::MatchIs(type_.clone()),
             AstNode::Catch(try_expr, catch_err, catch_body) => AstNode::Catch(
                 Box::new(self.clone_expr(try_expr)),
-                Arc::new(Mutex::new(catch_err.lock().unwrap().clone())),
+                Arc::new(RwLock::new(catch_err.read().unwrap().clone())),
                 self.clone_body(catch_body),
             ),
-            AstNode::Match(subject, cases) => AstNode::Match(subject.as_ref().map(|s| Box::new(self.clone_expr(s))), self.clone_match_cases(cases)),
+            AstNode::Match(subject, cases, has_default) => AstNode::Match(
+                subject.as_ref().map(|s| Box::new(self.clone_expr(s))),
+                self.clone_match_cases(cases),
+                *has_default,
+            ),
 
             AstNode::MacroSizeof(type_) => AstNode::MacroSizeof(type_.clone()),
             AstNode::MacroUla(src) => AstNode::MacroUla(Box::new(self.clone_expr(src))),
+            AstNode::Sizeof(src) => AstNode::Sizeof(Box::new(self.clone_expr(src))),
+            AstNode::MacroBitCast(type_, src) => AstNode::MacroBitCast(type_.clone(), Box::new(self.clone_expr(src))),
             AstNode::MacroReflectHash(type_) => AstNode::MacroReflectHash(type_.clone()),
             AstNode::MacroTypeEq(left, right) => AstNode::MacroTypeEq(left.clone(), right.clone()),
             AstNode::MacroDefault => AstNode::MacroDefault,
+            AstNode::MacroAssert(cond, implicit_msg) => AstNode::MacroAssert(Box::new(self.clone_expr(cond)), Box::new(self.clone_expr(implicit_msg))),
+            AstNode::MacroMinMax(kind, args) => AstNode::MacroMinMax(*kind, args.iter().map(|e| Box::new(self.clone_expr(e))).collect()),
 
             AstNode::ArrayAccess(type_, left, index) => AstNode::ArrayAccess(type_.clone(), Box::new(self.clone_expr(left)), Box::new(self.clone_expr(index))),
 
-            AstNode::SelectExpr(left, key) => AstNode::SelectExpr(Box::new(self.clone_expr(left)), key.clone()),
+            AstNode::SelectExpr(left, key) => AstNode::SelectExpr(Box::new(self.clone_expr(left)), *key),
             _ => expr.node.clone(),
         };
 
         Expr {
-            start: expr.start,
-            end: expr.end,
+            id: expr.id,
+            span: expr.span,
             type_: expr.type_.clone(),
             target_type: expr.target_type.clone(),
             node,
@@ -192,8 +212,9 @@ impl GenericSpecialFnClone {
     fn clone_stmt(&mut self, stmt: &Stmt) -> Stmt {
         let node = match &stmt.node {
             AstNode::Fake(expr) => AstNode::Fake(Box::new(self.clone_expr(expr))),
-            AstNode::VarDecl(var_decl) => AstNode::VarDecl(Arc::new(Mutex::new(var_decl.lock().unwrap().clone()))),
-            AstNode::VarDef(var_decl, right) => AstNode::VarDef(Arc::new(Mutex::new(var_decl.lock().unwrap().clone())), Box::new(self.clone_expr(right))),
+            AstNode::Do(expr) => AstNode::Do(Box::new(self.clone_expr(expr))),
+            AstNode::VarDecl(var_decl) => AstNode::VarDecl(Arc::new(RwLock::new(var_decl.read().unwrap().clone()))),
+            AstNode::VarDef(var_decl, right) => AstNode::VarDef(Arc::new(RwLock::new(var_decl.read().unwrap().clone())), Box::new(self.clone_expr(right))),
             AstNode::VarTupleDestr(elements, expr) => {
                 let new_elements: Vec<Box<Expr>> = elements.iter().map(|e| Box::new(self.clone_expr(e))).collect();
                 AstNode::VarTupleDestr(new_elements, Box::new(self.clone_expr(expr)))
@@ -203,10 +224,11 @@ impl GenericSpecialFnClone {
                 AstNode::If(Box::new(self.clone_expr(condition)), self.clone_body(consequent), self.clone_body(alternate))
             }
             AstNode::ForCond(condition, body) => AstNode::ForCond(Box::new(self.clone_expr(condition)), self.clone_body(body)),
-            AstNode::ForIterator(iterate, first, second, body) => AstNode::ForIterator(
+            AstNode::ForIterator(iterate, first, second, step, body) => AstNode::ForIterator(
                 Box::new(self.clone_expr(iterate)),
-                Arc::new(Mutex::new(first.lock().unwrap().clone())),
-                second.as_ref().map(|s| Arc::new(Mutex::new(s.lock().unwrap().clone()))),
+                Arc::new(RwLock::new(first.read().unwrap().clone())),
+                second.as_ref().map(|s| Arc::new(RwLock::new(s.read().unwrap().clone()))),
+                step.as_ref().map(|s| Box::new(self.clone_expr(s))),
                 self.clone_body(body),
             ),
             AstNode::ForTradition(init, cond, update, body) => AstNode::ForTradition(
@@ -223,27 +245,31 @@ impl GenericSpecialFnClone {
             AstNode::Break(expr_opt) => AstNode::Break(expr_opt.as_ref().map(|e| Box::new(self.clone_expr(e)))),
             AstNode::Catch(try_expr, catch_err, catch_body) => AstNode::Catch(
                 Box::new(self.clone_expr(try_expr)),
-                Arc::new(Mutex::new(catch_err.lock().unwrap().clone())),
+                Arc::new(RwLock::new(catch_err.read().unwrap().clone())),
                 self.clone_body(catch_body),
             ),
             AstNode::Select(cases, has_default, send_count, recv_count) => {
                 AstNode::Select(self.clone_select_cases(cases), *has_default, *send_count, *recv_count)
             }
-            AstNode::Match(subject, cases) => AstNode::Match(subject.as_ref().map(|s| Box::new(self.clone_expr(s))), self.clone_match_cases(cases)),
+            AstNode::Match(subject, cases, has_default) => AstNode::Match(
+                subject.as_ref().map(|s| Box::new(self.clone_expr(s))),
+                self.clone_match_cases(cases),
+                *has_default,
+            ),
 
             AstNode::TryCatch(try_expr, catch_err, catch_body) => AstNode::TryCatch(
                 Box::new(self.clone_expr(try_expr)),
-                Arc::new(Mutex::new(catch_err.lock().unwrap().clone())),
+                Arc::new(RwLock::new(catch_err.read().unwrap().clone())),
                 self.clone_body(catch_body),
             ),
 
-            AstNode::TypeAlias(alias) => AstNode::TypeAlias(Arc::new(Mutex::new(alias.lock().unwrap().clone()))),
+            AstNode::TypeAlias(alias) => AstNode::TypeAlias(Arc::new(RwLock::new(alias.read().unwrap().clone()))),
             _ => stmt.node.clone(),
         };
 
         Stmt {
-            start: stmt.start,
-            end: stmt.end,
+            id: stmt.id,
+            span: stmt.span,
             node,
         }
     }
@@ -266,7 +292,7 @@ impl GenericSpecialFnClone {
             .iter()
             .map(|case| SelectCase {
                 on_call: case.on_call.as_ref().map(|call| self.clone_call(call)),
-                recv_var: case.recv_var.as_ref().map(|var| Arc::new(Mutex::new(var.lock().unwrap().clone()))),
+                recv_var: case.recv_var.as_ref().map(|var| Arc::new(RwLock::new(var.read().unwrap().clone()))),
                 is_recv: case.is_recv,
                 is_default: case.is_default,
                 handle_body: self.clone_body(&case.handle_body),
@@ -291,12 +317,13 @@ impl GenericSpecialFnClone {
 pub struct Typesys<'a> {
     symbol_table: &'a mut SymbolTable,
     module: &'a mut Module,
-    current_fn_mutex: Arc<Mutex<AstFnDef>>,
-    worklist: Vec<Arc<Mutex<AstFnDef>>>,
+    current_fn_mutex: Arc<RwLock<AstFnDef>>,
+    worklist: Vec<Arc<RwLock<AstFnDef>>>,
     generics_args_stack: Vec<HashMap<String, Type>>,
     be_caught: bool,
     break_target_types: Vec<Type>,
     errors: Vec<AnalyzerError>,
+    cancel: CancellationToken,
 }
 
 impl<'a> Typesys<'a> {
@@ -308,13 +335,22 @@ impl<'a> Typesys<'a> {
             module,
             worklist: Vec::new(),
             generics_args_stack: Vec::new(),
-            current_fn_mutex: Arc::new(Mutex::new(AstFnDef::default())),
+            current_fn_mutex: Arc::new(RwLock::new(AstFnDef::default())),
             be_caught: false,
             break_target_types: Vec::new(),
             errors: Vec::new(),
+            cancel: CancellationToken::default(),
         }
     }
 
+    /**
+     * 取消之后不再推导剩余的 stmt, 推导结果不完整, 只能被丢弃
+     */
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
     fn finalize_type(&mut self, t: Type, origin_ident: Option<String>, origin_type_kind: TypeKind) -> Type {
         let mut result = t.clone();
         result.origin_ident = origin_ident;
@@ -327,18 +363,13 @@ impl<'a> Typesys<'a> {
 
     fn reduction_type_alias(&mut self, t: Type) -> Result<Type, AnalyzerError> {
         let TypeKind::Alias(alias) = &t.kind else { unreachable!() };
-        let start = t.start;
-        let end = t.end;
+        let span = t.span;
 
         // 获取类型别名的标识符和参数
         let impl_ident = alias.ident.clone();
 
         if alias.symbol_id.is_none() {
-            return Err(AnalyzerError {
-                start: 0,
-                end: 0,
-                message: format!("type alias '{}' symbol_id not found", alias.ident),
-            });
+            return Err(AnalyzerError::new(Span::new(0, 0), format!("type alias '{}' symbol_id not found", alias.ident)));
         }
 
         let symbol = self.symbol_table.get_symbol(alias.symbol_id.unwrap()).unwrap(); // 确保找到的符号是类型别名
@@ -346,15 +377,11 @@ impl<'a> Typesys<'a> {
         let type_alias_stmt_mutex = match symbol.kind.clone() {
             SymbolKind::TypeAlias(stmt) => stmt,
             _ => {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: format!("'{}' is not a type", symbol.ident),
-                });
+                return Err(AnalyzerError::new(span, format!("'{}' is not a type", symbol.ident)));
             }
         };
 
-        let mut type_alias = match type_alias_stmt_mutex.try_lock() {
+        let mut type_alias = match type_alias_stmt_mutex.try_write() {
             Ok(guard) => guard,
             Err(_) => return Ok(t), // 无法获取锁,说明存在递归调用,返回原始类型
         };
@@ -364,26 +391,23 @@ impl<'a> Typesys<'a> {
         // 处理带参数的类型别名
         if type_alias.params.len() > 0 {
             if alias.args.is_none() {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: format!("type alias '{}' need param", alias.ident),
-                });
+                return Err(AnalyzerError::new(span, format!("type alias '{}' need param", alias.ident)));
             }
 
-            let args = alias.args.as_ref().unwrap();
+            // semantic 无法排列引用自身的具名实参, 例如 type node<T> = struct{ ptr<node<T=T>> next }
+            let mut ordered_alias = alias.as_ref().clone();
+            ordered_alias
+                .order_args(&type_alias.params)
+                .map_err(|message| AnalyzerError::new(span, message))?;
+            let args = ordered_alias.args.as_ref().unwrap();
 
             if args.len() != type_alias.params.len() {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: format!("type alias '{}' param not match", alias.ident),
-                });
+                return Err(AnalyzerError::new(span, format!("type alias '{}' param not match", alias.ident)));
             }
 
             // 对每个参数进行类型归约和约束检查
             for (i, undo_arg_type) in args.iter().enumerate() {
-                let arg_type = self.reduction_type(undo_arg_type.clone())?;
+                let arg_type = self.reduction_type(undo_arg_type.type_.clone())?;
                 impl_args.push(arg_type.clone());
 
                 let param = &mut type_alias.params[i];
@@ -396,18 +420,14 @@ impl<'a> Typesys<'a> {
                 }
 
                 if !self.union_type_contains(&param.constraints, &arg_type) {
-                    return Err(AnalyzerError {
-                        start,
-                        end,
-                        message: format!("type alias '{}' param constraint not match", alias.ident),
-                    });
+                    return Err(AnalyzerError::new(span, format!("type alias '{}' param constraint not match", alias.ident)));
                 }
             }
 
             // 处理类型参数栈
             let mut args_table: HashMap<String, Type> = HashMap::new();
             for (i, undo_arg_type) in args.iter().enumerate() {
-                let arg_type = self.reduction_type(undo_arg_type.clone())?;
+                let arg_type = self.reduction_type(undo_arg_type.type_.clone())?;
                 let param = &type_alias.params[i];
 
                 args_table.insert(param.ident.clone(), arg_type.clone());
@@ -468,6 +488,10 @@ impl<'a> Typesys<'a> {
                 *element_type = Box::new(self.reduction_type(*element_type.clone())?);
             }
 
+            TypeKind::Promise(value_type) => {
+                **value_type = self.reduction_type(*value_type.clone())?;
+            }
+
             // 处理通道类型
             TypeKind::Chan(element_type) => {
                 *element_type = Box::new(self.reduction_type(*element_type.clone())?);
@@ -489,11 +513,7 @@ impl<'a> Typesys<'a> {
 
                 // 检查键类型是否合法
                 if !Type::is_map_key_type(&key_type.kind) {
-                    return Err(AnalyzerError {
-                        start: t.start,
-                        end: t.end,
-                        message: format!("type '{}' not support as map key", key_type),
-                    });
+                    return Err(AnalyzerError::new(t.span, format!("type '{}' not support as map key", key_type)));
                 }
 
                 result.impl_ident = Some(kind_str);
@@ -506,11 +526,7 @@ impl<'a> Typesys<'a> {
 
                 // 检查元素类型是否合法
                 if !Type::is_map_key_type(&element_type.kind) {
-                    return Err(AnalyzerError {
-                        start: t.start,
-                        end: t.end,
-                        message: format!("type '{}' not support as set element", element_type),
-                    });
+                    return Err(AnalyzerError::new(t.span, format!("type '{}' not support as set element", element_type)));
                 }
 
                 result.impl_ident = Some("set".to_string());
@@ -553,18 +569,14 @@ impl<'a> Typesys<'a> {
                                 if property.type_.kind.is_unknown() {
                                     // 如果属性类型未知,则 必须能够推导其类型
                                     if !self.type_confirm(&right_type) {
-                                        self.errors_push(
-                                            right_value.start,
-                                            right_value.end,
-                                            format!("struct property '{}' type not confirmed", property.key),
-                                        );
+                                        self.errors_push(right_value.span, format!("struct property '{}' type not confirmed", property.key));
                                     }
 
                                     property.type_ = right_type;
                                 }
                             }
                             Err(e) => {
-                                self.errors_push(e.start, e.end, e.message);
+                                self.error_push(e);
                             }
                         }
                     }
@@ -573,11 +585,7 @@ impl<'a> Typesys<'a> {
                     max_align = max_align.max(item_align);
 
                     if !self.type_confirm(&property.type_) {
-                        self.errors_push(
-                            property.type_.start,
-                            property.type_.end,
-                            format!("struct property '{}' type not confirmed", property.key),
-                        );
+                        self.errors_push(property.type_.span, format!("struct property '{}' type not confirmed", property.key));
                     }
                 }
 
@@ -595,11 +603,7 @@ impl<'a> Typesys<'a> {
         let origin_type_kind = t.origin_type_kind.clone();
 
         if t.err {
-            return Err(AnalyzerError {
-                start: 0,
-                end: 0,
-                message: format!("type {} already has error", t),
-            });
+            return Err(AnalyzerError::new(Span::new(0, 0), format!("type {} already has error", t)));
         }
 
         if t.kind.is_unknown() {
@@ -663,11 +667,7 @@ impl<'a> Typesys<'a> {
         // 处理raw指针转换的特殊情况
         if matches!(src_type.kind, TypeKind::RawPtr(..)) {
             if !matches!(target_type.kind, TypeKind::VoidPtr) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: format!("{} can only as void_ptr", src_type),
-                });
+                return Err(AnalyzerError::new(expr.span, format!("{} can only as void_ptr", src_type)));
             }
             return Ok(target_type.clone());
         }
@@ -680,20 +680,12 @@ impl<'a> Typesys<'a> {
         // 处理联合类型转换
         if let TypeKind::Union(any, elements) = &src_type.kind {
             if matches!(target_type.kind, TypeKind::Union(..)) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: "union to union type is not supported".to_string(),
-                });
+                return Err(AnalyzerError::new(expr.span, "union to union type is not supported".to_string()));
             }
 
             // 检查目标类型是否包含在联合类型中
             if !self.union_type_contains(&(*any, elements.clone()), &target_type) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: format!("type = {} not contains in union type", target_type),
-                });
+                return Err(AnalyzerError::new(expr.span, format!("type = {} not contains in union type", target_type)));
             }
             return Ok(target_type.clone());
         }
@@ -719,11 +711,7 @@ impl<'a> Typesys<'a> {
 
         // 检查目标类型是否可以进行类型转换
         if !Type::can_type_casting(&target_type.kind) {
-            return Err(AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: format!("cannot casting to '{}'", target_type),
-            });
+            return Err(AnalyzerError::new(expr.span, format!("cannot casting to '{}'", target_type)));
         }
 
         Ok(target_type.clone())
@@ -733,9 +721,9 @@ impl<'a> Typesys<'a> {
         &mut self,
         subject: &mut Option<Box<Expr>>,
         cases: &mut Vec<MatchCase>,
+        has_default_hint: bool,
         target_type: Type,
-        start: usize,
-        end: usize,
+        span: Span,
     ) -> Result<Type, AnalyzerError> {
         // 默认 subject_type 为 bool 类型(用于无 subject 的情况)
         let mut subject_type = Type::new(TypeKind::Bool);
@@ -746,11 +734,7 @@ impl<'a> Typesys<'a> {
 
             // 确保 subject 类型已确定
             if !self.type_confirm(&subject_type) {
-                return Err(AnalyzerError {
-                    start: subject_expr.start,
-                    end: subject_expr.end,
-                    message: "match subject type not confirm".to_string(),
-                });
+                return Err(AnalyzerError::new(subject_expr.span, "match subject type not confirm".to_string()));
             }
         }
 
@@ -759,7 +743,8 @@ impl<'a> Typesys<'a> {
 
         // 用于跟踪联合类型的匹配情况
         let mut union_types = HashMap::new();
-        let mut has_default = false;
+        // parser 已经识别出 default case 时可以跳过穷尽检查
+        let mut has_default = has_default_hint;
 
         // 遍历所有 case
         for case in cases {
@@ -771,22 +756,14 @@ impl<'a> Typesys<'a> {
                     // 对于联合类型,只能使用 is 匹配
                     if matches!(subject_type.kind, TypeKind::Union(..)) {
                         if !matches!(cond_expr.node, AstNode::MatchIs(..)) {
-                            return Err(AnalyzerError {
-                                start: cond_expr.start,
-                                end: cond_expr.end,
-                                message: "match 'union type' only support 'is' assert".to_string(),
-                            });
+                            return Err(AnalyzerError::new(cond_expr.span, "match 'union type' only support 'is' assert".to_string()));
                         }
                     }
 
                     // 处理 is 类型匹配
                     if let AstNode::MatchIs(_target_type) = cond_expr.node.clone() {
                         if !matches!(subject_type.kind, TypeKind::Union(..)) {
-                            return Err(AnalyzerError {
-                                start: cond_expr.start,
-                                end: cond_expr.end,
-                                message: "only union type can use is assert".to_string(),
-                            });
+                            return Err(AnalyzerError::new(cond_expr.span, "only union type can use is assert".to_string()));
                         }
 
                         let cond_type = self.infer_right_expr(cond_expr, Type::default())?;
@@ -814,29 +791,20 @@ impl<'a> Typesys<'a> {
                 if !any {
                     for element_type in elements {
                         if !union_types.contains_key(&element_type.hash()) {
-                            return Err(AnalyzerError {
-                                start,
-                                end,
-                                message: format!(
+                            return Err(AnalyzerError::new(
+                                span,
+                                format!(
                                     "match expression lacks a default case '_' and union element type lacks, for example 'is {}'",
                                     element_type
                                 ),
-                            });
+                            ));
                         }
                     }
                 } else {
-                    return Err(AnalyzerError {
-                        start,
-                        end,
-                        message: "match expression lacks a default case '_'".to_string(),
-                    });
+                    return Err(AnalyzerError::new(span, "match expression lacks a default case '_'".to_string()));
                 }
             } else {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: "match expression lacks a default case '_'".to_string(),
-                });
+                return Err(AnalyzerError::new(span, "match expression lacks a default case '_'".to_string()));
             }
         }
 
@@ -844,10 +812,58 @@ impl<'a> Typesys<'a> {
         return Ok(self.break_target_types.pop().unwrap());
     }
 
+    /**
+     * spread 表达式必须与 struct new 的类型一致, 属性部分的推导与 infer_struct_properties 一致
+     */
+    pub fn infer_struct_entries(&mut self, type_: &mut Type, entries: &mut [StructNewEntry], span: Span) -> Result<Vec<StructNewEntry>, AnalyzerError> {
+        let mut properties = Vec::new();
+        let mut has_spread = false;
+        for entry in entries.iter_mut() {
+            match entry {
+                StructNewEntry::Property(property) => properties.push(*property.clone()),
+                StructNewEntry::Spread(spread) => {
+                    has_spread = true;
+
+                    let spread_type = match self.infer_right_expr(spread, Type::default()) {
+                        Ok(t) => t,
+                        Err(e) => {
+                            self.error_push(e);
+                            continue;
+                        }
+                    };
+
+                    if !matches!(spread_type.kind, TypeKind::Struct(..)) || !self.type_compare(type_, &spread_type) {
+                        self.errors_push(spread.span, format!("spread expr must be struct '{}', actual '{}'", type_, spread_type));
+                    }
+                }
+            }
+        }
+
+        let TypeKind::Struct(_, _, type_properties) = &mut type_.kind else {
+            unreachable!()
+        };
+        let result = self.infer_struct_properties(type_properties, &mut properties, has_spread, span)?;
+
+        // 按照原有顺序还原 entries, 默认值属性追加在最后
+        let mut result = result.into_iter();
+        let mut new_entries: Vec<StructNewEntry> = entries
+            .iter()
+            .map(|entry| match entry {
+                StructNewEntry::Property(_) => StructNewEntry::Property(Box::new(result.next().unwrap())),
+                StructNewEntry::Spread(spread) => StructNewEntry::Spread(spread.clone()),
+            })
+            .collect();
+        new_entries.extend(result.map(|property| StructNewEntry::Property(Box::new(property))));
+
+        Ok(new_entries)
+    }
+
     pub fn infer_struct_properties(
         &mut self,
         type_properties: &mut Vec<TypeStructProperty>,
         properties: &mut Vec<StructNewProperty>,
+        has_spread: bool,
+        span: Span,
     ) -> Result<Vec<StructNewProperty>, AnalyzerError> {
         // 用于跟踪已经处理过的属性
         let mut exists = HashMap::new();
@@ -855,25 +871,27 @@ impl<'a> Typesys<'a> {
         // 处理显式指定的属性
         for property in properties.iter_mut() {
             // 在类型定义中查找对应的属性
-            let expect_property = type_properties.iter().find(|p| p.key == property.key).ok_or_else(|| AnalyzerError {
-                start: property.start,
-                end: property.end,
-                message: format!("not found property '{}'", property.key),
-            })?;
+            let expect_property = type_properties
+                .iter()
+                .find(|p| p.key == property.key)
+                .ok_or_else(|| AnalyzerError::new(Span::new(property.start, property.end), format!("not found property '{}'", property.key)))?;
 
-            exists.insert(property.key.clone(), true);
+            exists.insert(property.key, true);
 
             // 推导属性值的类型
             if let Err(e) = self.infer_right_expr(&mut property.value, expect_property.type_.clone()) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
 
             // 冗余属性类型(用于计算size)
             property.type_ = expect_property.type_.clone();
         }
 
-        // 处理默认值
+        // 处理默认值, spread 表达式已经包含了所有属性的值
         let mut result = properties.clone();
+        if has_spread {
+            return Ok(result);
+        }
 
         // 遍历类型定义中的所有属性
         for type_prop in type_properties.iter() {
@@ -882,12 +900,12 @@ impl<'a> Typesys<'a> {
                 continue;
             }
 
-            exists.insert(type_prop.key.clone(), true);
+            exists.insert(type_prop.key, true);
 
             // 添加默认值属性
             result.push(StructNewProperty {
                 type_: type_prop.type_.clone(),
-                key: type_prop.key.clone(),
+                key: type_prop.key,
                 value: type_prop.value.clone().unwrap(),
                 start: type_prop.start,
                 end: type_prop.end,
@@ -900,25 +918,111 @@ impl<'a> Typesys<'a> {
                 continue;
             }
 
-            // 检查是否是必须赋值的类型
+            // 检查是否是必须赋值的类型, 错误位置指向 struct new 表达式
             if Type::must_assign_value(&type_prop.type_.kind) {
-                return Err(AnalyzerError {
-                    start: type_prop.start,
-                    end: type_prop.end,
-                    message: format!("property '{}' type '{}' must assign value", type_prop.key, type_prop.type_),
-                });
+                return Err(
+                    AnalyzerError::new(span, format!("property '{}' type '{}' must assign value", type_prop.key, type_prop.type_))
+                        .with_code(MISSING_FIELDS_CODE),
+                );
             }
         }
 
         Ok(result)
     }
 
-    pub fn infer_binary(&mut self, op: ExprOp, left: &mut Box<Expr>, right: &mut Box<Expr>, _infer_target_type: Type) -> Result<Type, AnalyzerError> {
-        // 先推导左操作数的类型
-        let left_type = self.infer_right_expr(left, Type::default())?;
+    /**
+     * 同为有符号整数、无符号整数或浮点数且宽度不同时, 返回较宽的类型, 例如 i8 + i16 扩展为 i16
+     */
+    fn widen_number_type(&self, left: &Type, right: &Type) -> Option<Type> {
+        let same_class = (Type::is_float(&left.kind) && Type::is_float(&right.kind))
+            || (Type::is_integer(&left.kind) && Type::is_integer(&right.kind) && left.kind.is_unsigned() == right.kind.is_unsigned());
+        if !same_class {
+            return None;
+        }
+
+        let (left_size, right_size) = (Type::sizeof(&left.kind), Type::sizeof(&right.kind));
+        if left_size == right_size {
+            return None;
+        }
+
+        Some(if left_size > right_size { left.clone() } else { right.clone() })
+    }
+
+    /**
+     * 使用 TypeCast 包裹表达式, 与用户编写的 as 区分
+     */
+    fn implicit_cast(expr: &mut Box<Expr>, target_type: Type) {
+        let src = expr.clone();
+        expr.node = AstNode::TypeCast(target_type.clone(), src);
+        expr.type_ = target_type.clone();
+        expr.target_type = target_type;
+    }
+
+    /**
+     * @min/@max 的所有参数必须是相同的数值类型, 第一个参数决定了整体的类型
+     */
+    pub fn infer_min_max(&mut self, kind: MinMaxKind, args: &mut [Box<Expr>], infer_target_type: Type) -> Result<Type, AnalyzerError> {
+        let (first, rest) = args.split_first_mut().unwrap();
+
+        let target_type = if Type::is_number(&infer_target_type.kind) {
+            infer_target_type
+        } else {
+            Type::default()
+        };
+        let first_type = self.infer_right_expr(first, target_type)?;
+        if !Type::is_number(&first_type.kind) {
+            return Err(AnalyzerError::new(
+                first.span,
+                format!("{} only support number operand, actual '{}'", kind, first_type),
+            ));
+        }
+
+        for arg in rest {
+            // 字面量基于第一个参数的类型进行推导, 其余参数独立推导后比较
+            let target_type = if matches!(arg.node, AstNode::Literal(..)) {
+                first_type.clone()
+            } else {
+                Type::default()
+            };
+            let arg_type = self.infer_right_expr(arg, target_type)?;
+            if !self.type_compare(&first_type, &arg_type) {
+                return Err(AnalyzerError::new(
+                    arg.span,
+                    format!("{} args must be the same type, expected '{}', actual '{}'", kind, first_type, arg_type),
+                ));
+            }
+        }
+
+        Ok(first_type)
+    }
+
+    /**
+     * 数值运算的非字面量操作数, 目标类型只用于推导内部的字面量(例如 (2 + 3) * 4)
+     * 不与目标类型进行比较, 操作数宽度不同时由 infer_binary 进行隐式扩展
+     */
+    fn infer_number_operand(&mut self, expr: &mut Box<Expr>, target_type: Type) -> Result<Type, AnalyzerError> {
+        if expr.type_.kind.is_unknown() {
+            let t = self.infer_expr(expr, target_type)?;
+            expr.type_ = self.reduction_type(t)?;
+        }
+        Ok(expr.type_.clone())
+    }
+
+    pub fn infer_binary(&mut self, op: ExprOp, left: &mut Box<Expr>, right: &mut Box<Expr>, infer_target_type: Type) -> Result<Type, AnalyzerError> {
+        // 先推导左操作数的类型, 数值运算中的字面量(例如 a + 2*3 中的 2)基于推导目标类型进行推导
+        let left_type = match &left.node {
+            AstNode::Literal(kind, ..) if Type::is_number(kind) && Type::is_number(&infer_target_type.kind) => {
+                self.infer_right_expr(left, infer_target_type)?
+            }
+            AstNode::Binary(..) if Type::is_number(&infer_target_type.kind) => self.infer_number_operand(left, infer_target_type)?,
+            _ => self.infer_right_expr(left, Type::default())?,
+        };
 
         // 推导右操作数的类型
-        let right_type = if !matches!(left_type.kind, TypeKind::Union(..)) {
+        let mut right_type = if Type::is_number(&left_type.kind) && !matches!(right.node, AstNode::Literal(..)) {
+            // 以左操作数类型作为推导目标, 宽度不同时进行隐式扩展
+            self.infer_number_operand(right, left_type.clone())?
+        } else if !matches!(left_type.kind, TypeKind::Union(..)) {
             // 如果左操作数不是联合类型,则基于左操作数类型进行隐式类型转换
             self.infer_right_expr(right, left_type.clone())?
         } else {
@@ -926,27 +1030,36 @@ impl<'a> Typesys<'a> {
             self.infer_right_expr(right, Type::default())?
         };
 
+        let mut left_type = left_type;
+        if let Some(widen_type) = self.widen_number_type(&left_type, &right_type) {
+            if left_type.kind != widen_type.kind {
+                Self::implicit_cast(left, widen_type.clone());
+            } else {
+                Self::implicit_cast(right, widen_type.clone());
+            }
+            left_type = widen_type.clone();
+            right_type = widen_type;
+        }
+
         // 检查左右操作数类型是否一致
         if !self.type_compare(&left_type, &right_type) {
-            return Err(AnalyzerError {
-                start: left.start,
-                end: right.end,
-                message: format!("binary type inconsistency: left={}, right={}", left_type, right_type),
-            });
+            return Err(AnalyzerError::new(
+                left.span.merge(right.span),
+                format!("binary type inconsistency: left={}, right={}", left_type, right_type),
+            ));
         }
 
         // 处理数值类型运算
         if Type::is_number(&left_type.kind) {
             // 检查右操作数也必须是数值类型
             if !Type::is_number(&right_type.kind) {
-                return Err(AnalyzerError {
-                    start: right.start,
-                    end: right.end,
-                    message: format!(
+                return Err(AnalyzerError::new(
+                    right.span,
+                    format!(
                         "binary operator '{}' only support number operand, actual '{} {} {}'",
                         op, left_type, op, right_type
                     ),
-                });
+                ));
             }
         }
 
@@ -954,14 +1067,13 @@ impl<'a> Typesys<'a> {
         if matches!(left_type.kind, TypeKind::String) {
             // 检查右操作数也必须是字符串类型
             if !matches!(right_type.kind, TypeKind::String) {
-                return Err(AnalyzerError {
-                    start: right.start,
-                    end: right.end,
-                    message: format!(
+                return Err(AnalyzerError::new(
+                    right.span,
+                    format!(
                         "binary operator '{}' only support string operand, actual '{} {} {}'",
                         op, left_type, op, right_type
                     ),
-                });
+                ));
             }
         }
 
@@ -969,11 +1081,10 @@ impl<'a> Typesys<'a> {
         if op.is_integer() {
             // 检查操作数必须是整数类型
             if !Type::is_integer(&left_type.kind) || !Type::is_integer(&right_type.kind) {
-                return Err(AnalyzerError {
-                    start: left.start,
-                    end: right.end,
-                    message: format!("binary operator '{}' only integer operand", op),
-                });
+                return Err(AnalyzerError::new(
+                    left.span.merge(right.span),
+                    format!("binary operator '{}' only integer operand", op),
+                ));
             }
         }
 
@@ -986,11 +1097,7 @@ impl<'a> Typesys<'a> {
             Ok(Type::new(TypeKind::Bool))
         } else {
             // 未知运算符
-            Err(AnalyzerError {
-                start: left.start,
-                end: right.end,
-                message: format!("unknown operator '{}'", op),
-            })
+            Err(AnalyzerError::new(left.span.merge(right.span), format!("unknown operator '{}'", op)))
         }
     }
 
@@ -1006,31 +1113,19 @@ impl<'a> Typesys<'a> {
 
         // 处理负号运算符
         if op == ExprOp::Neg && !Type::is_number(&operand_type.kind) {
-            return Err(AnalyzerError {
-                start: operand.start,
-                end: operand.end,
-                message: "neg operand must applies to int or float type".to_string(),
-            });
+            return Err(AnalyzerError::new(operand.span, "neg operand must applies to int or float type".to_string()));
         }
 
         // 处理取地址运算符 &
         if op == ExprOp::La {
             // 检查是否是字面量或函数调用
             if matches!(operand.node, AstNode::Literal(..) | AstNode::Call(..)) {
-                return Err(AnalyzerError {
-                    start: operand.start,
-                    end: operand.end,
-                    message: "cannot load address of an literal or call".to_string(),
-                });
+                return Err(AnalyzerError::new(operand.span, "cannot load address of an literal or call".to_string()));
             }
 
             // 检查是否是联合类型
             if matches!(operand_type.kind, TypeKind::Union(..)) {
-                return Err(AnalyzerError {
-                    start: operand.start,
-                    end: operand.end,
-                    message: "cannot load address of an union type".to_string(),
-                });
+                return Err(AnalyzerError::new(operand.span, "cannot load address of an union type".to_string()));
             }
 
             return Ok(Type::raw_ptr_of(operand_type));
@@ -1040,20 +1135,12 @@ impl<'a> Typesys<'a> {
         if op == ExprOp::UnsafeLa {
             // 检查是否是字面量或函数调用
             if matches!(operand.node, AstNode::Literal(..) | AstNode::Call(..)) {
-                return Err(AnalyzerError {
-                    start: operand.start,
-                    end: operand.end,
-                    message: "cannot safe load address of an literal or call".to_string(),
-                });
+                return Err(AnalyzerError::new(operand.span, "cannot safe load address of an literal or call".to_string()));
             }
 
             // 检查是否是联合类型
             if matches!(operand_type.kind, TypeKind::Union(..)) {
-                return Err(AnalyzerError {
-                    start: operand.start,
-                    end: operand.end,
-                    message: "cannot safe load address of an union type".to_string(),
-                });
+                return Err(AnalyzerError::new(operand.span, "cannot safe load address of an union type".to_string()));
             }
 
             return Ok(Type::ptr_of(operand_type));
@@ -1067,11 +1154,10 @@ impl<'a> Typesys<'a> {
                     return Ok(*value_type);
                 }
                 _ => {
-                    return Err(AnalyzerError {
-                        start: operand.start,
-                        end: operand.end,
-                        message: format!("cannot dereference non-pointer type '{}'", operand_type),
-                    });
+                    return Err(AnalyzerError::new(
+                        operand.span,
+                        format!("cannot dereference non-pointer type '{}'", operand_type),
+                    ));
                 }
             }
         }
@@ -1080,13 +1166,9 @@ impl<'a> Typesys<'a> {
         Ok(operand_type)
     }
 
-    pub fn infer_ident(&mut self, ident: &mut String, symbol_id_option: &mut Option<NodeId>, start: usize, end: usize) -> Result<Type, AnalyzerError> {
+    pub fn infer_ident(&mut self, ident: &mut Atom, symbol_id_option: &mut Option<NodeId>, span: Span) -> Result<Type, AnalyzerError> {
         let Some(symbol_id) = symbol_id_option else {
-            return Err(AnalyzerError {
-                start: 0,
-                end: 0,
-                message: format!("ident '{}' symbol_id is None", ident),
-            });
+            return Err(AnalyzerError::new(Span::new(0, 0), format!("ident '{}' symbol_id is None", ident)));
         };
 
         let symbol = self.symbol_table.get_symbol(*symbol_id).unwrap();
@@ -1096,10 +1178,10 @@ impl<'a> Typesys<'a> {
 
         // 判断符号是否是 local symbol
         if is_local {
-            if let Some(hash) = self.current_fn_mutex.lock().unwrap().generics_args_hash {
-                let new_ident = format_generics_ident(ident.clone(), hash);
+            if let Some(hash) = self.current_fn_mutex.read().unwrap().generics_args_hash {
+                let new_ident = format_generics_ident(ident.to_string(), hash);
                 if let Some(new_symbol_id) = symbol.generics_id_map.get(&new_ident) {
-                    *ident = new_ident;
+                    *ident = Atom::from(&new_ident);
                     *symbol_id = *new_symbol_id;
 
                     symbol_kind = self.symbol_table.get_symbol(*symbol_id).unwrap().kind.clone();
@@ -1111,15 +1193,11 @@ impl<'a> Typesys<'a> {
 
         match symbol_kind {
             SymbolKind::Var(var_decl) => {
-                let mut var_decl = var_decl.lock().unwrap();
+                let mut var_decl = var_decl.write().unwrap();
                 var_decl.type_ = self.reduction_type(var_decl.type_.clone())?;
 
                 if var_decl.type_.kind.is_unknown() {
-                    return Err(AnalyzerError {
-                        start,
-                        end,
-                        message: "unknown type".to_string(),
-                    });
+                    return Err(AnalyzerError::new(span, "unknown type".to_string()));
                 }
                 assert!(var_decl.type_.kind.is_exist());
 
@@ -1129,11 +1207,7 @@ impl<'a> Typesys<'a> {
                 return self.infer_fn_decl(fndef.clone());
             }
             _ => {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: format!("unknown symbol kind: {:?}", symbol_kind),
-                });
+                return Err(AnalyzerError::new(span, format!("unknown symbol kind: {:?}", symbol_kind)));
             }
         }
     }
@@ -1157,7 +1231,7 @@ impl<'a> Typesys<'a> {
             // 对所有元素进行类型推导
             for element in elements {
                 if let Err(e) = self.infer_right_expr(element, *element_type.clone()) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
             }
 
@@ -1175,11 +1249,7 @@ impl<'a> Typesys<'a> {
         // 如果向量为空，必须能从目标类型确定元素类型
         if elements.is_empty() {
             if !self.type_confirm(&element_type) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: "vec element type not confirm".to_string(),
-                });
+                return Err(AnalyzerError::new(expr.span, "vec element type not confirm".to_string()));
             }
             return self.reduction_type(Type::undo_new(TypeKind::Vec(Box::new(element_type))));
         }
@@ -1192,7 +1262,7 @@ impl<'a> Typesys<'a> {
         // 对所有元素进行类型推导和检查
         for element in elements.iter_mut() {
             if let Err(e) = self.infer_right_expr(element, element_type.clone()) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
 
@@ -1200,7 +1270,7 @@ impl<'a> Typesys<'a> {
         self.reduction_type(Type::undo_new(TypeKind::Vec(Box::new(element_type))))
     }
 
-    pub fn infer_map_new(&mut self, elements: &mut Vec<MapElement>, infer_target_type: Type, start: usize, end: usize) -> Result<Type, AnalyzerError> {
+    pub fn infer_map_new(&mut self, elements: &mut Vec<MapElement>, infer_target_type: Type, span: Span) -> Result<Type, AnalyzerError> {
         // 创建一个新的 Map 类型，初始化 key 和 value 类型为未知
         let mut key_type = Type::default(); // TYPE_UNKNOWN
         let mut value_type = Type::default(); // TYPE_UNKNOWN
@@ -1214,18 +1284,10 @@ impl<'a> Typesys<'a> {
         // 如果 Map 为空，必须能从目标类型确定 key 和 value 类型
         if elements.is_empty() {
             if !self.type_confirm(&key_type) {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: "map key type not confirm".to_string(),
-                });
+                return Err(AnalyzerError::new(span, "map key type not confirm".to_string()));
             }
             if !self.type_confirm(&value_type) {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: "map value type not confirm".to_string(),
-                });
+                return Err(AnalyzerError::new(span, "map value type not confirm".to_string()));
             }
             return self.reduction_type(Type::undo_new(TypeKind::Map(Box::new(key_type), Box::new(value_type))));
         }
@@ -1239,11 +1301,11 @@ impl<'a> Typesys<'a> {
         // 对所有元素进行类型推导和检查
         for element in elements.iter_mut() {
             if let Err(e) = self.infer_right_expr(&mut element.key, key_type.clone()) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
 
             if let Err(e) = self.infer_right_expr(&mut element.value, value_type.clone()) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
 
@@ -1251,7 +1313,7 @@ impl<'a> Typesys<'a> {
         self.reduction_type(Type::undo_new(TypeKind::Map(Box::new(key_type), Box::new(value_type))))
     }
 
-    pub fn infer_set_new(&mut self, elements: &mut Vec<Box<Expr>>, infer_target_type: Type, start: usize, end: usize) -> Result<Type, AnalyzerError> {
+    pub fn infer_set_new(&mut self, elements: &mut Vec<Box<Expr>>, infer_target_type: Type, span: Span) -> Result<Type, AnalyzerError> {
         // 创建一个新的 Set 类型
         let mut element_type = Type::default(); // TYPE_UNKNOWN
 
@@ -1263,11 +1325,7 @@ impl<'a> Typesys<'a> {
         // 如果集合为空，必须能从目标类型确定元素类型
         if elements.is_empty() {
             if !self.type_confirm(&element_type) {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: "empty set element type not confirm".to_string(),
-                });
+                return Err(AnalyzerError::new(span, "empty set element type not confirm".to_string()));
             }
             return self.reduction_type(Type::undo_new(TypeKind::Set(Box::new(element_type))));
         }
@@ -1280,7 +1338,7 @@ impl<'a> Typesys<'a> {
         // 对所有元素进行类型推导和检查
         for element in elements.iter_mut() {
             if let Err(e) = self.infer_right_expr(element, element_type.clone()) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
 
@@ -1288,14 +1346,10 @@ impl<'a> Typesys<'a> {
         self.reduction_type(Type::undo_new(TypeKind::Set(Box::new(element_type))))
     }
 
-    pub fn infer_tuple_new(&mut self, elements: &mut Vec<Box<Expr>>, infer_target_type: Type, start: usize, end: usize) -> Result<Type, AnalyzerError> {
+    pub fn infer_tuple_new(&mut self, elements: &mut Vec<Box<Expr>>, infer_target_type: Type, span: Span) -> Result<Type, AnalyzerError> {
         // 检查元组元素不能为空
         if elements.is_empty() {
-            return Err(AnalyzerError {
-                start,
-                end,
-                message: "tuple elements empty".to_string(),
-            });
+            return Err(AnalyzerError::new(span, "tuple elements empty".to_string()));
         }
 
         // 收集所有元素的类型
@@ -1312,11 +1366,7 @@ impl<'a> Typesys<'a> {
 
             // 检查元素类型是否已确定
             if !self.type_confirm(&expr_type) {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: "tuple element type cannot be confirmed".to_string(),
-                });
+                return Err(AnalyzerError::new(span, "tuple element type cannot be confirmed".to_string()));
             }
 
             element_types.push(expr_type);
@@ -1377,30 +1427,18 @@ impl<'a> Typesys<'a> {
             self.infer_right_expr(key, Type::new(TypeKind::Int))?;
 
             // 获取索引值
-            let index: u64 = if let AstNode::Literal(kind, value) = &key.node {
+            let index: u64 = if let AstNode::Literal(kind, value, _) = &key.node {
                 if !Type::is_integer(kind) {
-                    return Err(AnalyzerError {
-                        start: key.start,
-                        end: key.end,
-                        message: "tuple index must be integer literal".to_string(),
-                    });
+                    return Err(AnalyzerError::new(key.span, "tuple index must be integer literal".to_string()));
                 }
-                value.parse::<u64>().unwrap_or(u64::MAX)
+                value.replace('_', "").parse::<u64>().unwrap_or(u64::MAX)
             } else {
-                return Err(AnalyzerError {
-                    start: key.start,
-                    end: key.end,
-                    message: "tuple index must be immediate value".to_string(),
-                });
+                return Err(AnalyzerError::new(key.span, "tuple index must be immediate value".to_string()));
             };
 
             // 检查索引是否越界
             if index >= elements.len() as u64 {
-                return Err(AnalyzerError {
-                    start: key.start,
-                    end: key.end,
-                    message: format!("tuple index {} out of range", index),
-                });
+                return Err(AnalyzerError::new(key.span, format!("tuple index {} out of range", index)));
             }
 
             let element_type = elements[index as usize].clone();
@@ -1412,11 +1450,10 @@ impl<'a> Typesys<'a> {
         }
 
         // 不支持的类型访问
-        Err(AnalyzerError {
-            start: expr.start,
-            end: expr.end,
-            message: format!("access only support map/vec/string/array/tuple, cannot '{}'", left_type),
-        })
+        Err(AnalyzerError::new(
+            expr.span,
+            format!("access only support map/vec/string/array/tuple, cannot '{}'", left_type),
+        ))
     }
 
     pub fn infer_select_expr(&mut self, expr: &mut Box<Expr>) -> Result<Type, AnalyzerError> {
@@ -1448,10 +1485,10 @@ impl<'a> Typesys<'a> {
                 // StructSelect(Box<Expr>, String, StructNewProperty), // (instance, key, property)
                 expr.node = AstNode::StructSelect(
                     left.clone(),
-                    key.clone(),
+                    *key,
                     TypeStructProperty {
                         type_: property_type.clone(),
-                        key: property.key.clone(),
+                        key: property.key,
                         value: property.value.clone(),
                         start: property.start,
                         end: property.end,
@@ -1461,35 +1498,29 @@ impl<'a> Typesys<'a> {
                 return Ok(property_type);
             } else {
                 // 如果找不到属性，报错
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: format!("type struct '{}' no property '{}'", deref_type.origin_ident.unwrap_or("_".to_string()), key),
-                });
+                return Err(AnalyzerError::new(
+                    expr.span,
+                    format!("type struct '{}' no property '{}'", deref_type.origin_ident.unwrap_or("_".to_string()), key),
+                ));
             }
         }
 
         // 如果不是结构体类型，报错
-        Err(AnalyzerError {
-            start: expr.start,
-            end: expr.end,
-            message: format!("type '{}' no property {}", left_type, key),
-        })
+        Err(AnalyzerError::new(expr.span, format!("type '{}' no property {}", left_type, key)))
     }
 
     pub fn infer_async(&mut self, expr: &mut Box<Expr>) -> Result<Type, AnalyzerError> {
         let AstNode::MacroAsync(async_expr) = &mut expr.node else { unreachable!() };
-        let start = expr.start;
-        let end = expr.end;
+        let span = expr.span;
 
         // 处理 flag_expr，如果为 None 则创建一个默认值 0
         if async_expr.flag_expr.is_none() {
             async_expr.flag_expr = Some(Box::new(Expr {
-                start,
-                end,
+                id: AstNodeId::default(),
+                span,
                 type_: Type::default(),
                 target_type: Type::default(),
-                node: AstNode::Literal(TypeKind::Int, "0".to_string()),
+                node: AstNode::Literal(TypeKind::Int, "0".to_string(), LiteralSource::Synthesized),
                 err: false,
             }));
         }
@@ -1497,7 +1528,7 @@ impl<'a> Typesys<'a> {
         // 推导 flag_expr 类型
         if let Some(flag_expr) = &mut async_expr.flag_expr {
             if let Err(e) = self.infer_right_expr(flag_expr, Type::new(TypeKind::Int)) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
 
@@ -1509,11 +1540,7 @@ impl<'a> Typesys<'a> {
 
             self.infer_call_args(&mut async_expr.origin_call, *type_fn);
         } else {
-            return Err(AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: "async expression must call a fn".to_string(),
-            });
+            return Err(AnalyzerError::new(expr.span, "async expression must call a fn".to_string()));
         }
 
         // 构造异步调用
@@ -1527,8 +1554,8 @@ impl<'a> Typesys<'a> {
 
             // 清空闭包函数体以避免推导异常
             {
-                async_expr.closure_fn.lock().unwrap().body.clear();
-                async_expr.closure_fn_void.lock().unwrap().body.clear();
+                async_expr.closure_fn.write().unwrap().body.clear();
+                async_expr.closure_fn_void.write().unwrap().body.clear();
             }
 
             left
@@ -1539,17 +1566,17 @@ impl<'a> Typesys<'a> {
 
             let closure_fn = if async_expr.return_type.kind == TypeKind::Void {
                 // 使用 void 版本的闭包
-                async_expr.closure_fn.lock().unwrap().body.clear();
+                async_expr.closure_fn.write().unwrap().body.clear();
                 async_expr.closure_fn_void.clone()
             } else {
                 async_expr.closure_fn.clone()
             };
 
-            let type_ = closure_fn.lock().unwrap().type_.clone();
+            let type_ = closure_fn.read().unwrap().type_.clone();
 
             Box::new(Expr {
-                start,
-                end,
+                id: AstNodeId::default(),
+                span,
                 type_: type_.clone(),
                 target_type: type_,
                 node: AstNode::FnDef(closure_fn.clone()),
@@ -1563,7 +1590,7 @@ impl<'a> Typesys<'a> {
         // 构造 async 调用
         let call = AstCall {
             return_type: Type::default(),
-            left: Box::new(Expr::ident(start, end, "async".to_string(), symbol_id)),
+            left: Box::new(Expr::ident(span, "async".to_string(), symbol_id)),
             args: vec![first_arg, async_expr.flag_expr.clone().unwrap()],
             generics_args: vec![async_expr.return_type.clone()],
             spread: false,
@@ -1581,6 +1608,11 @@ impl<'a> Typesys<'a> {
 
         return match &mut expr.node {
             AstNode::As(_, _) => self.infer_as_expr(expr),
+            AstNode::TypeCast(target_type, src) => {
+                let target_type = target_type.clone();
+                self.infer_right_expr(src, Type::default())?;
+                Ok(target_type)
+            }
             AstNode::Catch(try_expr, catch_err_mutex, catch_body) => {
                 self.be_caught = true;
                 let try_expr_type = self.infer_right_expr(try_expr, Type::default())?;
@@ -1589,7 +1621,7 @@ impl<'a> Typesys<'a> {
                 {
                     let mut errort = Type::errort(self.symbol_table);
                     errort = self.reduction_type(errort)?;
-                    let mut catch_err = catch_err_mutex.lock().unwrap();
+                    let mut catch_err = catch_err_mutex.write().unwrap();
                     catch_err.type_ = errort;
                 }
 
@@ -1599,7 +1631,7 @@ impl<'a> Typesys<'a> {
                 self.infer_body(catch_body);
                 return Ok(self.break_target_types.pop().unwrap());
             }
-            AstNode::Match(subject, cases) => self.infer_match(subject, cases, infer_target_type, expr.start, expr.end),
+            AstNode::Match(subject, cases, has_default) => self.infer_match(subject, cases, *has_default, infer_target_type, expr.span),
             AstNode::MatchIs(target_type) => {
                 *target_type = self.reduction_type(target_type.clone())?;
                 return Ok(Type::new(TypeKind::Bool));
@@ -1609,11 +1641,7 @@ impl<'a> Typesys<'a> {
 
                 *target_type = self.reduction_type(target_type.clone())?;
                 if !matches!(src_type.kind, TypeKind::Union(..)) {
-                    return Err(AnalyzerError {
-                        start: expr.start,
-                        end: expr.end,
-                        message: format!("{} cannot use 'is' operator", src_type),
-                    });
+                    return Err(AnalyzerError::new(expr.span, format!("{} cannot use 'is' operator", src_type)));
                 }
 
                 return Ok(Type::new(TypeKind::Bool));
@@ -1630,26 +1658,62 @@ impl<'a> Typesys<'a> {
                 let src_type = self.infer_right_expr(src, Type::default())?;
                 return Ok(Type::ptr_of(src_type));
             }
+            AstNode::Sizeof(src) => {
+                self.infer_right_expr(src, Type::default())?;
+                return Ok(Type::new(TypeKind::Int));
+            }
+            AstNode::MacroBitCast(target_type, src) => {
+                *target_type = self.reduction_type(target_type.clone())?;
+                let src_type = self.infer_right_expr(src, Type::default())?;
+
+                // 按位重新解释, 两侧的类型必须占用相同的字节数
+                let (src_size, target_size) = (Type::sizeof(&src_type.kind), Type::sizeof(&target_type.kind));
+                if !src_type.err && src_size != target_size {
+                    return Err(AnalyzerError::new(
+                        expr.span,
+                        format!(
+                            "bitcast size mismatch, '{}' is {} bytes but '{}' is {} bytes",
+                            src_type, src_size, target_type, target_size
+                        ),
+                    ));
+                }
+
+                return Ok(target_type.clone());
+            }
             AstNode::MacroDefault => {
                 return Ok(infer_target_type);
             }
-            AstNode::New(type_, properties) => {
+            AstNode::MacroMinMax(kind, args) => return self.infer_min_max(*kind, args, infer_target_type),
+            AstNode::MacroAssert(cond, implicit_msg) => {
+                self.infer_right_expr(cond, Type::new(TypeKind::Bool))?;
+                self.infer_right_expr(implicit_msg, Type::new(TypeKind::String))?;
+                return Ok(Type::new(TypeKind::Void));
+            }
+            AstNode::New(type_, properties, args) => {
                 *type_ = self.reduction_type(type_.clone())?;
+
+                // new 只能用于 struct, struct 通过 key=value 初始化 field, 位置参数没有可以匹配的构造函数
+                if let (Some(first), Some(last)) = (args.first(), args.last()) {
+                    let message = if matches!(type_.kind, TypeKind::Struct(..)) {
+                        format!("'new {}' does not accept positional arguments, initialize fields with key=value", type_)
+                    } else {
+                        format!("cannot use 'new' operator with arguments on type '{}'", type_)
+                    };
+                    return Err(AnalyzerError::new(first.span.merge(last.span), message));
+                }
+
                 if let TypeKind::Struct(_, _, type_properties) = &mut type_.kind {
-                    *properties = self.infer_struct_properties(type_properties, properties)?;
+                    *properties = self.infer_struct_properties(type_properties, properties, false, expr.span)?;
                 } else {
-                    return Err(AnalyzerError {
-                        start: expr.start,
-                        end: expr.end,
-                        message: "cannot use 'new' operator on non-struct type".to_string(),
-                    });
+                    return Err(AnalyzerError::new(expr.span, "cannot use 'new' operator on non-struct type".to_string()));
                 }
 
                 return self.reduction_type(Type::ptr_of(type_.clone()));
             }
             AstNode::Binary(op, left, right) => self.infer_binary(op.clone(), left, right, infer_target_type),
+            AstNode::Range(start, end) => self.infer_range(start, end),
             AstNode::Unary(op, operand) => self.infer_unary(op.clone(), operand),
-            AstNode::Ident(ident, symbol_id) => self.infer_ident(ident, symbol_id, expr.start, expr.end),
+            AstNode::Ident(ident, symbol_id) => self.infer_ident(ident, symbol_id, expr.span),
             AstNode::VecNew(..) => self.infer_vec_new(expr, infer_target_type),
             AstNode::EmptyCurlyNew => {
                 // 必须通过 target_type 引导才能推断出具体的类型, 所以 target_type kind 必须存在
@@ -1662,42 +1726,35 @@ impl<'a> Typesys<'a> {
                         expr.node = AstNode::SetNew(Vec::new());
                     }
                     _ => {
-                        return Err(AnalyzerError {
-                            start: expr.start,
-                            end: expr.end,
-                            message: format!("empty curly new cannot ref type {}", infer_target_type),
-                        });
+                        return Err(AnalyzerError::new(expr.span, format!("empty curly new cannot ref type {}", infer_target_type)));
                     }
                 }
 
                 return Ok(infer_target_type);
             }
-            AstNode::MapNew(elements) => self.infer_map_new(elements, infer_target_type, expr.start, expr.end),
-            AstNode::SetNew(elements) => self.infer_set_new(elements, infer_target_type, expr.start, expr.end),
-            AstNode::TupleNew(elements) => self.infer_tuple_new(elements, infer_target_type, expr.start, expr.end),
-            AstNode::StructNew(_ident, type_, properties) => {
+            AstNode::MapNew(elements) => self.infer_map_new(elements, infer_target_type, expr.span),
+            AstNode::SetNew(elements) => self.infer_set_new(elements, infer_target_type, expr.span),
+            AstNode::TupleNew(elements) => self.infer_tuple_new(elements, infer_target_type, expr.span),
+            AstNode::StructNew(_ident, type_, entries) => {
                 *type_ = self.reduction_type(type_.clone())?;
 
-                if let TypeKind::Struct(_, _, type_properties) = &mut type_.kind {
-                    *properties = self.infer_struct_properties(type_properties, properties)?;
+                if matches!(type_.kind, TypeKind::Struct(..)) {
+                    *entries = self.infer_struct_entries(type_, entries, expr.span)?;
                 } else {
-                    return Err(AnalyzerError {
-                        start: expr.start,
-                        end: expr.end,
-                        message: format!("cannot use 'new' operator on non-struct type {}", type_),
-                    });
+                    return Err(AnalyzerError::new(expr.span, format!("cannot use 'new' operator on non-struct type {}", type_)));
                 }
 
                 return Ok(type_.clone());
             }
             AstNode::AccessExpr(..) => self.infer_access_expr(expr),
             AstNode::SelectExpr(..) => self.infer_select_expr(expr),
-            AstNode::Call(call) => self.infer_call(call, infer_target_type, expr.start, expr.end),
+            AstNode::Call(call) => self.infer_call(call, infer_target_type, expr.span),
             AstNode::MacroAsync(_) => self.infer_async(expr),
+            AstNode::FnDef(fndef) if fndef.read().unwrap().is_lambda_short => self.infer_lambda_short(fndef.clone(), infer_target_type, expr.span),
             AstNode::FnDef(fndef) => self.infer_fn_decl(fndef.clone()),
-            AstNode::Literal(kind, _value) => self.reduction_type(Type::new(kind.clone())),
+            AstNode::Literal(kind, _value, _) => self.reduction_type(Type::new(kind.clone())),
             AstNode::EnvAccess(_, unique_ident, symbol_id_option) => {
-                return self.infer_ident(unique_ident, symbol_id_option, expr.start, expr.end);
+                return self.infer_ident(unique_ident, symbol_id_option, expr.span);
             }
             _ => panic!("unknown operand {:?}", expr.node),
         };
@@ -1713,7 +1770,7 @@ impl<'a> Typesys<'a> {
     }
 
     fn literal_integer_casting(&mut self, expr: &mut Box<Expr>, target_type: Type) -> Result<(), AnalyzerError> {
-        if let AstNode::Literal(kind, value) = &mut expr.node {
+        if let AstNode::Literal(kind, value, _) = &mut expr.node {
             let mut target_kind = target_type.kind.clone();
 
             if matches!(target_kind, TypeKind::VoidPtr) {
@@ -1721,13 +1778,14 @@ impl<'a> Typesys<'a> {
             }
 
             if !Type::is_integer(&kind) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: format!("integer literal type inconsistency: expect={}, actual={}", target_type, expr.type_),
-                });
+                return Err(AnalyzerError::new(
+                    expr.span,
+                    format!("integer literal type inconsistency: expect={}, actual={}", target_type, expr.type_),
+                ));
             }
 
+            // 数字分隔符不参与解析
+            let value = value.replace('_', "");
             let i = if value.starts_with("0x") {
                 i64::from_str_radix(&value[2..], 16)
             } else if value.starts_with("0b") {
@@ -1737,19 +1795,11 @@ impl<'a> Typesys<'a> {
             } else {
                 value.parse::<i64>()
             }
-            .map_err(|_| AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: "invalid integer literal".to_string(),
-            })?;
+            .map_err(|_| AnalyzerError::new(expr.span, "invalid integer literal".to_string()))?;
 
             let target_kind = self.cross_kind_trans(&target_kind);
             if !self.integer_range_check(&target_kind, i) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: "integer out of range".to_string(),
-                });
+                return Err(AnalyzerError::new(expr.span, "integer out of range".to_string()));
             }
 
             *kind = target_kind;
@@ -1757,45 +1807,35 @@ impl<'a> Typesys<'a> {
 
             Ok(())
         } else {
-            Err(AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: "integer casting only support literal".to_string(),
-            })
+            Err(AnalyzerError::new(expr.span, "integer casting only support literal".to_string()))
         }
     }
 
     fn literal_float_casting(&mut self, expr: &mut Box<Expr>, target_type: Type) -> Result<(), AnalyzerError> {
         // 检查是否为字面量表达式
-        if let AstNode::Literal(kind, value) = &mut expr.node {
+        if let AstNode::Literal(kind, value, _) = &mut expr.node {
             let target_kind = target_type.kind.clone();
 
             // 检查源类型是否为数字类型
             if !Type::is_number(kind) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: format!("type inconsistency: '{}' cannot casting float", expr.type_),
-                });
+                return Err(AnalyzerError::new(
+                    expr.span,
+                    format!("type inconsistency: '{}' cannot casting float", expr.type_),
+                ));
             }
 
             // 将字符串解析为浮点数
-            let f = value.parse::<f64>().map_err(|_| AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: "invalid float literal".to_string(),
-            })?;
+            let f = value
+                .replace('_', "")
+                .parse::<f64>()
+                .map_err(|_| AnalyzerError::new(expr.span, "invalid float literal".to_string()))?;
 
             // 获取目标类型的标准形式(处理类型别名等)
             let target_kind = self.cross_kind_trans(&target_kind);
 
             // 检查值是否在目标类型的范围内
             if !self.float_range_check(&target_kind, f) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: "float out of range".to_string(),
-                });
+                return Err(AnalyzerError::new(expr.span, "float out of range".to_string()));
             }
 
             // 更新类型信息
@@ -1805,11 +1845,7 @@ impl<'a> Typesys<'a> {
             Ok(())
         } else {
             // 如果不是字面量表达式，返回错误
-            Err(AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: "float casting only support literal".to_string(),
-            })
+            Err(AnalyzerError::new(expr.span, "float casting only support literal".to_string()))
         }
     }
 
@@ -1876,26 +1912,21 @@ impl<'a> Typesys<'a> {
         if matches!(target_type.kind, TypeKind::Union(..)) && self.can_assign_to_union(&expr.type_) {
             let TypeKind::Union(any, elements) = &target_type.kind else { unreachable!() };
             if !self.union_type_contains(&(*any, elements.clone()), &expr.type_) {
-                return Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: format!("union type not contains '{}'", expr.type_),
-                });
+                return Err(AnalyzerError::new(expr.span, format!("union type not contains '{}'", expr.type_)));
             }
 
             // expr 改成成 union 类型
-            expr.node = AstNode::As(target_type.clone(), expr.clone());
+            expr.node = AstNode::TypeCast(target_type.clone(), expr.clone());
             expr.type_ = target_type.clone();
             expr.target_type = target_type.clone();
         }
 
         // 最后进行类型比较
         if !self.type_compare(&target_type, &expr.type_) {
-            return Err(AnalyzerError {
-                start: expr.start,
-                end: expr.end,
-                message: format!("type inconsistency: expect={}, actual={}", target_type, expr.type_),
-            });
+            return Err(AnalyzerError::new(
+                expr.span,
+                format!("type inconsistency: expect={}, actual={}", target_type, expr.type_),
+            ));
         }
 
         Ok(expr.type_.clone())
@@ -1904,7 +1935,7 @@ impl<'a> Typesys<'a> {
     pub fn infer_left_expr(&mut self, expr: &mut Box<Expr>) -> Result<Type, AnalyzerError> {
         let type_result = match &mut expr.node {
             // 标识符
-            AstNode::Ident(ident, symbol_id) => self.infer_ident(ident, symbol_id, expr.start, expr.end),
+            AstNode::Ident(ident, symbol_id) => self.infer_ident(ident, symbol_id, expr.span),
 
             // 元组解构
             AstNode::TupleDestr(elements) => {
@@ -1925,18 +1956,14 @@ impl<'a> Typesys<'a> {
             AstNode::SelectExpr(..) => self.infer_select_expr(expr),
 
             // 环境变量访问
-            AstNode::EnvAccess(_, ident, symbol_id_option) => self.infer_ident(ident, symbol_id_option, expr.start, expr.end),
+            AstNode::EnvAccess(_, ident, symbol_id_option) => self.infer_ident(ident, symbol_id_option, expr.span),
 
             // 函数调用
-            AstNode::Call(call) => self.infer_call(call, Type::default(), expr.start, expr.end),
+            AstNode::Call(call) => self.infer_call(call, Type::default(), expr.span),
 
             _ => {
                 // 对于不能作为左值的表达式，添加错误
-                Err(AnalyzerError {
-                    start: expr.start,
-                    end: expr.end,
-                    message: "operand cannot be used as left value".to_string(),
-                })
+                Err(AnalyzerError::new(expr.span, "operand cannot be used as left value".to_string()))
             }
         };
 
@@ -1954,9 +1981,9 @@ impl<'a> Typesys<'a> {
         };
     }
 
-    pub fn infer_vardef(&mut self, var_decl_mutex: &Arc<Mutex<VarDeclExpr>>, right_expr: &mut Box<Expr>) -> Result<(), AnalyzerError> {
+    pub fn infer_vardef(&mut self, var_decl_mutex: &Arc<RwLock<VarDeclExpr>>, right_expr: &mut Box<Expr>) -> Result<(), AnalyzerError> {
         {
-            let mut var_decl = var_decl_mutex.lock().unwrap();
+            let mut var_decl = var_decl_mutex.write().unwrap();
             var_decl.type_ = self.reduction_type(var_decl.type_.clone())?;
         }
 
@@ -1965,39 +1992,33 @@ impl<'a> Typesys<'a> {
 
         // 检查变量类型是否为void(非参数类型的情况下)
         {
-            let var_decl = var_decl_mutex.lock().unwrap();
+            let var_decl = var_decl_mutex.read().unwrap();
             if !matches!(var_decl.type_.origin_type_kind, TypeKind::Param(..)) && matches!(var_decl.type_.kind, TypeKind::Void) {
-                return Err(AnalyzerError {
-                    start: var_decl.symbol_start,
-                    end: var_decl.symbol_end,
-                    message: "cannot assign to void".to_string(),
-                });
+                return Err(AnalyzerError::new(
+                    Span::new(var_decl.symbol_start, var_decl.symbol_end),
+                    "cannot assign to void".to_string(),
+                ));
             }
         }
 
         // 获取变量声明的类型用于右值表达式的类型推导
-        let mut var_decl = var_decl_mutex.lock().unwrap();
+        let mut var_decl = var_decl_mutex.write().unwrap();
 
         // 推导右值表达式的类型
         let right_type = self.infer_right_expr(right_expr, var_decl.type_.clone())?;
 
         // 检查右值类型是否为void
         if matches!(right_type.kind, TypeKind::Void) {
-            return Err(AnalyzerError {
-                start: right_expr.start,
-                end: right_expr.end,
-                message: "cannot assign void to var".to_string(),
-            });
+            return Err(AnalyzerError::new(right_expr.span, "cannot assign void to var".to_string()));
         }
 
         if matches!(var_decl.type_.kind, TypeKind::Unknown) {
             // 检查右值类型是否已确定
             if !self.type_confirm(&right_type) {
-                return Err(AnalyzerError {
-                    start: right_expr.start,
-                    end: right_expr.end,
-                    message: "type inference error, right type not confirmed".to_string(),
-                });
+                return Err(AnalyzerError::new(
+                    right_expr.span,
+                    "type inference error, right type not confirmed".to_string(),
+                ));
             }
 
             // 使用右值类型作为变量类型
@@ -2007,7 +2028,7 @@ impl<'a> Typesys<'a> {
         Ok(())
     }
 
-    pub fn infer_var_tuple_destr(&mut self, elements: &mut Vec<Box<Expr>>, right_type: Type, start: usize, end: usize) -> Result<(), AnalyzerError> {
+    pub fn infer_var_tuple_destr(&mut self, elements: &mut Vec<Box<Expr>>, right_type: Type, span: Span) -> Result<(), AnalyzerError> {
         // check length
         // right_type.kind
         let TypeKind::Tuple(type_elements, _type_align) = right_type.kind else {
@@ -2015,18 +2036,17 @@ impl<'a> Typesys<'a> {
         };
 
         if type_elements.len() != elements.len() {
-            return Err(AnalyzerError {
-                start,
-                end,
-                message: format!("tuple length mismatch, expect {}, got {}", type_elements.len(), elements.len()),
-            });
+            return Err(AnalyzerError::new(
+                span,
+                format!("tuple length mismatch, expect {}, got {}", type_elements.len(), elements.len()),
+            ));
         }
 
         // 遍历按顺序对比类型,并且顺便 rewrite
         for (i, expr) in elements.iter_mut().enumerate() {
             let target_type = type_elements[i].clone();
             if !self.type_confirm(&target_type) {
-                self.errors_push(expr.start, expr.end, format!("tuple operand i = {} type not confirm", i));
+                self.errors_push(expr.span, format!("tuple operand i = {} type not confirm", i));
                 continue;
             }
 
@@ -2036,7 +2056,7 @@ impl<'a> Typesys<'a> {
             // 递归处理
             if let AstNode::VarDecl(var_decl_mutex) = &expr.node {
                 {
-                    let mut var_decl = var_decl_mutex.lock().unwrap();
+                    let mut var_decl = var_decl_mutex.write().unwrap();
                     assert!(var_decl.symbol_id.is_some());
 
                     var_decl.type_ = target_type.clone();
@@ -2045,11 +2065,11 @@ impl<'a> Typesys<'a> {
 
                 self.rewrite_var_decl(var_decl_mutex.clone());
             } else if let AstNode::TupleDestr(sub_elements) = &mut expr.node {
-                if let Err(e) = self.infer_var_tuple_destr(sub_elements, target_type, expr.start, expr.end) {
-                    self.errors_push(e.start, e.end, e.message);
+                if let Err(e) = self.infer_var_tuple_destr(sub_elements, target_type, expr.span) {
+                    self.error_push(e);
                 }
             } else {
-                self.errors_push(expr.start, expr.end, format!("var tuple destr mut var or tuple_destr"));
+                self.errors_push(expr.span, format!("var tuple destr mut var or tuple_destr"));
             }
         }
 
@@ -2059,7 +2079,7 @@ impl<'a> Typesys<'a> {
     pub fn infer_catch(
         &mut self,
         try_expr: &mut Box<Expr>,
-        catch_err_mutex: &Arc<Mutex<VarDeclExpr>>,
+        catch_err_mutex: &Arc<RwLock<VarDeclExpr>>,
         catch_body: &mut Vec<Box<Stmt>>,
     ) -> Result<(), AnalyzerError> {
         self.be_caught = true;
@@ -2073,7 +2093,7 @@ impl<'a> Typesys<'a> {
         // reduction errort
         {
             errort = self.reduction_type(errort)?;
-            let mut catch_err = catch_err_mutex.lock().unwrap();
+            let mut catch_err = catch_err_mutex.write().unwrap();
             catch_err.type_ = errort;
         }
 
@@ -2149,11 +2169,10 @@ impl<'a> Typesys<'a> {
                 if let Some(symbol_id) = self.symbol_table.find_symbol_id(&impl_symbol_name, GLOBAL_SCOPE_ID) {
                     (impl_symbol_name, Some(symbol_id.clone()))
                 } else {
-                    return Err(AnalyzerError {
-                        start: call.left.start,
-                        end: call.left.end,
-                        message: format!("type '{}' no impl fn '{}({})':", select_left_type, key, impl_symbol_name),
-                    });
+                    return Err(AnalyzerError::new(
+                        call.left.span,
+                        format!("type '{}' no impl fn '{}({})':", select_left_type, key, impl_symbol_name),
+                    ));
                 }
             }
         } else {
@@ -2161,16 +2180,12 @@ impl<'a> Typesys<'a> {
             if let Some(symbol_id) = self.symbol_table.find_symbol_id(&impl_symbol_name, GLOBAL_SCOPE_ID) {
                 (impl_symbol_name, Some(symbol_id.clone()))
             } else {
-                return Err(AnalyzerError {
-                    start: call.left.start,
-                    end: call.left.end,
-                    message: format!("type '{}' no impl fn '{}'", select_left_type, key),
-                });
+                return Err(AnalyzerError::new(call.left.span, format!("type '{}' no impl fn '{}'", select_left_type, key)));
             }
         };
 
         // 重写 select call 为 ident call, TODO 没有 symbol!?
-        call.left = Box::new(Expr::ident(call.left.start, call.left.end, final_symbol_name, symbol_id));
+        call.left = Box::new(Expr::ident(call.left.span, final_symbol_name, symbol_id));
 
         // 构建新的参数列表
         let mut new_args = Vec::new();
@@ -2196,12 +2211,12 @@ impl<'a> Typesys<'a> {
         &mut self,
         call_data: (Vec<Box<Expr>>, Vec<Type>, bool),
         return_target_type: Type,
-        temp_fndef_mutex: Arc<Mutex<AstFnDef>>,
+        temp_fndef_mutex: Arc<RwLock<AstFnDef>>,
     ) -> Result<HashMap<String, Type>, String> {
         let (mut args, generics_args, spread) = call_data;
         let mut table = HashMap::new();
 
-        let temp_fndef = temp_fndef_mutex.lock().unwrap();
+        let temp_fndef = temp_fndef_mutex.read().unwrap();
 
         assert!(temp_fndef.is_generics);
 
@@ -2293,10 +2308,10 @@ impl<'a> Typesys<'a> {
         &mut self,
         call_data: (Vec<Box<Expr>>, Vec<Type>, bool),
         target_type: Type,
-        temp_fndef_mutex: Arc<Mutex<AstFnDef>>,
-    ) -> Result<Arc<Mutex<AstFnDef>>, String> {
+        temp_fndef_mutex: Arc<RwLock<AstFnDef>>,
+    ) -> Result<Arc<RwLock<AstFnDef>>, String> {
         {
-            let temp_fndef = temp_fndef_mutex.lock().unwrap();
+            let temp_fndef = temp_fndef_mutex.read().unwrap();
             assert!(!temp_fndef.is_local);
             assert!(temp_fndef.is_generics);
             assert!(temp_fndef.generics_params.is_some());
@@ -2305,12 +2320,12 @@ impl<'a> Typesys<'a> {
         let args_table: HashMap<String, Type> = self.generics_args_table(call_data, target_type, temp_fndef_mutex.clone())?;
 
         let args_hash = {
-            let temp_fndef = temp_fndef_mutex.lock().unwrap();
+            let temp_fndef = temp_fndef_mutex.read().unwrap();
             self.generics_args_hash(temp_fndef.generics_params.as_ref().unwrap(), args_table.clone())
         };
 
         let symbol_name = {
-            let temp_fndef = temp_fndef_mutex.lock().unwrap();
+            let temp_fndef = temp_fndef_mutex.read().unwrap();
             //temp_fndef.symbol_name@args_hash
             format_generics_ident(temp_fndef.symbol_name.clone(), args_hash)
         };
@@ -2319,7 +2334,7 @@ impl<'a> Typesys<'a> {
 
         // 如果当前类型的 special_fn 已经生成，则直接返回即可
         {
-            let mut tpl_fn = tpl_fn_mutex.lock().unwrap();
+            let mut tpl_fn = tpl_fn_mutex.write().unwrap();
             if tpl_fn.generics_hash_table.is_none() {
                 tpl_fn.generics_hash_table = Some(HashMap::new());
             }
@@ -2335,14 +2350,14 @@ impl<'a> Typesys<'a> {
         // lsp 中无论是 否 singleton 都会 clone 一份, 因为 lsp 会随时新增泛型示例，必须保证 tpl fn 是无污染的
         let special_fn_mutex = {
             let result = GenericSpecialFnClone { global_parent: None }.deep_clone(&tpl_fn_mutex);
-            let mut tpl_fn = tpl_fn_mutex.lock().unwrap();
+            let mut tpl_fn = tpl_fn_mutex.write().unwrap();
 
             tpl_fn.generics_hash_table.as_mut().unwrap().insert(args_hash, result.clone());
             result
         };
 
         {
-            let mut special_fn = special_fn_mutex.lock().unwrap();
+            let mut special_fn = special_fn_mutex.write().unwrap();
             special_fn.generics_args_hash = Some(args_hash);
             special_fn.generics_args_table = Some(args_table);
             special_fn.symbol_name = symbol_name;
@@ -2374,7 +2389,7 @@ impl<'a> Typesys<'a> {
 
         // handle child
         {
-            let special_fn = special_fn_mutex.lock().unwrap();
+            let special_fn = special_fn_mutex.read().unwrap();
             for child in special_fn.local_children.iter() {
                 self.rewrite_local_fndef(child.clone());
                 self.infer_fn_decl(child.clone()).map_err(|e| e.message)?;
@@ -2385,8 +2400,8 @@ impl<'a> Typesys<'a> {
         Ok(special_fn_mutex)
     }
 
-    fn rewrite_local_fndef(&mut self, fndef_mutex: Arc<Mutex<AstFnDef>>) {
-        let mut fndef = fndef_mutex.lock().unwrap();
+    fn rewrite_local_fndef(&mut self, fndef_mutex: Arc<RwLock<AstFnDef>>) {
+        let mut fndef = fndef_mutex.write().unwrap();
 
         // 已经注册并改写完毕，不需要重复改写
         if fndef.generics_args_hash.is_some() {
@@ -2397,7 +2412,7 @@ impl<'a> Typesys<'a> {
         // 这么做也是为了兼容 generic 的情况
         // 否则 local fn 根本不会存在同名的情况, 另外 local fn 的调用作用域仅仅在当前函数内
         if let Some(global_parent) = &fndef.global_parent {
-            let args_hash = global_parent.lock().unwrap().generics_args_hash.unwrap();
+            let args_hash = global_parent.read().unwrap().generics_args_hash.unwrap();
             fndef.generics_args_hash = Some(args_hash);
         } else {
             assert!(false);
@@ -2427,12 +2442,12 @@ impl<'a> Typesys<'a> {
         target_type: Type,
         symbol_id: NodeId,
         call_data: (Vec<Box<Expr>>, Vec<Type>, bool),
-    ) -> Result<Option<Arc<Mutex<AstFnDef>>>, String> {
+    ) -> Result<Option<Arc<RwLock<AstFnDef>>>, String> {
         let symbol = self.symbol_table.get_symbol(symbol_id).unwrap();
 
         if let SymbolKind::Fn(fndef_mutex) = symbol.kind.clone() {
             {
-                let fndef = fndef_mutex.lock().unwrap();
+                let fndef = fndef_mutex.read().unwrap();
                 if fndef.is_local {
                     return Ok(None);
                 }
@@ -2460,7 +2475,7 @@ impl<'a> Typesys<'a> {
 
         if temp_fndef.rest_param && index >= temp_fndef.params.len() - 1 {
             let last_param_mutex = temp_fndef.params.last().unwrap();
-            let last_param_type = last_param_mutex.lock().unwrap().type_.clone();
+            let last_param_type = last_param_mutex.read().unwrap().type_.clone();
 
             if let TypeKind::Vec(element_type) = &last_param_type.kind {
                 if is_spread {
@@ -2477,7 +2492,7 @@ impl<'a> Typesys<'a> {
             return Type::error();
         }
 
-        let param = temp_fndef.params[index].lock().unwrap();
+        let param = temp_fndef.params[index].read().unwrap();
         return param.type_.clone();
     }
 
@@ -2506,11 +2521,11 @@ impl<'a> Typesys<'a> {
 
     pub fn infer_call_args(&mut self, call: &mut AstCall, target_type_fn: TypeFn) {
         if !target_type_fn.rest && call.args.len() > target_type_fn.param_types.len() {
-            self.errors_push(call.left.start, call.left.end, format!("too many args"));
+            self.errors_push(call.left.span, format!("too many args"));
         }
 
         if !target_type_fn.rest && call.args.len() < target_type_fn.param_types.len() {
-            self.errors_push(call.left.start, call.left.end, format!("not enough args"));
+            self.errors_push(call.left.span, format!("not enough args"));
         }
 
         let call_args_len = call.args.len();
@@ -2520,27 +2535,40 @@ impl<'a> Typesys<'a> {
             let formal_type = self.select_fn_param(i, target_type_fn.clone(), is_spread);
 
             if let Err(e) = self.infer_right_expr(arg, formal_type) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
     }
 
-    pub fn infer_call(&mut self, call: &mut AstCall, target_type: Type, start: usize, end: usize) -> Result<Type, AnalyzerError> {
+    /**
+     * 被调用的 fn 不存在时依旧推导 receiver 与参数的类型, code action 会基于这些类型生成 fn 声明
+     */
+    fn infer_unresolved_call(&mut self, call: &mut AstCall) {
+        if let AstNode::SelectExpr(select_left, _) = &mut call.left.node {
+            let _ = self.infer_right_expr(select_left, Type::default());
+        }
+
+        for arg in call.args.iter_mut().filter(|arg| !arg.err) {
+            let _ = self.infer_right_expr(arg, Type::default());
+        }
+    }
+
+    pub fn infer_call(&mut self, call: &mut AstCall, target_type: Type, span: Span) -> Result<Type, AnalyzerError> {
         if call.left.err {
+            self.infer_unresolved_call(call);
             return Ok(Type::error());
         }
 
         if matches!(call.left.node, AstNode::SelectExpr(_, _)) {
-            let _is_rewrite = self.infer_select_call_rewrite(call)?;
+            if let Err(e) = self.infer_select_call_rewrite(call) {
+                self.infer_unresolved_call(call);
+                return Err(e);
+            }
         }
 
         if let AstNode::Ident(ident, symbol_id_option) = &mut call.left.node {
             if symbol_id_option.is_none() {
-                return Err(AnalyzerError {
-                    start: 0,
-                    end: 0,
-                    message: format!("ident '{}' symbol_id is none", ident),
-                });
+                return Err(AnalyzerError::new(Span::new(0, 0), format!("ident '{}' symbol_id is none", ident)));
             }
 
             let symbol_id = symbol_id_option.unwrap();
@@ -2552,14 +2580,14 @@ impl<'a> Typesys<'a> {
             ) {
                 Ok(result) => result,
                 Err(e) => {
-                    return Err(AnalyzerError { start, end, message: e });
+                    return Err(AnalyzerError::new(span, e));
                 }
             };
 
             match special_fn {
                 Some(special_fn) => {
-                    let special_fn = special_fn.lock().unwrap();
-                    *ident = special_fn.symbol_name.clone();
+                    let special_fn = special_fn.read().unwrap();
+                    *ident = Atom::from(&special_fn.symbol_name);
                     *symbol_id_option = Some(special_fn.symbol_id.unwrap());
                 }
                 None => {} // local fn 或者 no generics param 都不是 generics fn
@@ -2569,11 +2597,7 @@ impl<'a> Typesys<'a> {
         let left_type = self.infer_right_expr(&mut call.left, Type::default())?;
 
         if !matches!(left_type.kind, TypeKind::Fn(..)) {
-            return Err(AnalyzerError {
-                start,
-                end,
-                message: "cannot call non-fn".to_string(),
-            });
+            return Err(AnalyzerError::new(span, "cannot call non-fn".to_string()));
         }
 
         let TypeKind::Fn(type_fn) = left_type.kind else { unreachable!() };
@@ -2582,17 +2606,16 @@ impl<'a> Typesys<'a> {
 
         if type_fn.errable {
             // 当前 fn 必须允许 is_errable 或者当前位于 be_caught 中
-            let current_fn = self.current_fn_mutex.lock().unwrap();
+            let current_fn = self.current_fn_mutex.read().unwrap();
             if !self.be_caught && !current_fn.is_errable {
-                return Err(AnalyzerError {
-                    start,
-                    end,
-                    message: format!(
+                return Err(AnalyzerError::new(
+                    span,
+                    format!(
                         "calling an errable! fn `{}` requires the current `fn {}` errable! as well or be caught.",
                         if type_fn.name.is_empty() { "lambda".to_string() } else { type_fn.name },
                         current_fn.fn_name
                     ),
-                });
+                ));
             }
         }
 
@@ -2602,25 +2625,54 @@ impl<'a> Typesys<'a> {
     pub fn infer_for_iterator(
         &mut self,
         iterate: &mut Box<Expr>,
-        first: &mut Arc<Mutex<VarDeclExpr>>,
-        second: &mut Option<Arc<Mutex<VarDeclExpr>>>,
+        first: &mut Arc<RwLock<VarDeclExpr>>,
+        second: &mut Option<Arc<RwLock<VarDeclExpr>>>,
+        step: &mut Option<Box<Expr>>,
         body: &mut Vec<Box<Stmt>>,
     ) {
         let iterate_type = match self.infer_right_expr(iterate, Type::default()) {
             Ok(iterate_type) => iterate_type,
             Err(e) => {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
                 return;
             }
         };
 
+        // for i in 0..10 step 2, range 的 step 与 range 类型一致, 其余情况的 step 为 int
+        if let Some(step) = step {
+            let step_target = if matches!(iterate.node, AstNode::Range(..)) {
+                iterate_type.clone()
+            } else {
+                Type::new(TypeKind::Int)
+            };
+
+            if let Err(e) = self.infer_right_expr(step, step_target) {
+                self.error_push(e);
+            }
+        }
+
+        if matches!(iterate.node, AstNode::Range(..)) {
+            if second.is_some() {
+                self.errors_push(iterate.span, "for range only have one iteration parameter".to_string());
+                return;
+            }
+
+            self.rewrite_var_decl(first.clone());
+            first.write().unwrap().type_ = iterate_type;
+
+            let t = Type::new(TypeKind::Void);
+            self.break_target_types.push(t);
+            self.infer_body(body);
+            self.break_target_types.pop();
+            return;
+        }
+
         // 检查迭代类型
         match iterate_type.kind {
             TypeKind::Map(..) | TypeKind::Vec(..) | TypeKind::String | TypeKind::Chan(..) => {}
             _ => {
                 self.errors_push(
-                    iterate.start,
-                    iterate.end,
+                    iterate.span,
                     format!("for in iterate type must be map/list/string/chan, actual={}", iterate_type),
                 );
                 return;
@@ -2635,13 +2687,13 @@ impl<'a> Typesys<'a> {
 
         // 检查 chan 类型的特殊情况
         if matches!(iterate_type.kind, TypeKind::Chan(..)) && second.is_some() {
-            self.errors_push(iterate.start, iterate.end, "for chan only have one receive parameter".to_string());
+            self.errors_push(iterate.span, "for chan only have one receive parameter".to_string());
             return;
         }
 
         // 为变量设置类型
         {
-            let mut first_decl = first.lock().unwrap();
+            let mut first_decl = first.write().unwrap();
             match &iterate_type.kind {
                 TypeKind::Map(key_type, _) => {
                     first_decl.type_ = *key_type.clone();
@@ -2669,7 +2721,7 @@ impl<'a> Typesys<'a> {
 
         // 处理第二个变量的类型
         if let Some(second) = second {
-            let mut second_decl = second.lock().unwrap();
+            let mut second_decl = second.write().unwrap();
             match &iterate_type.kind {
                 TypeKind::Map(_, value_type) => {
                     second_decl.type_ = *value_type.clone();
@@ -2691,9 +2743,26 @@ impl<'a> Typesys<'a> {
         self.break_target_types.pop();
     }
 
+    /**
+     * range 的类型即迭代变量的类型, start 与 end 必须是相同的整数类型
+     */
+    fn infer_range(&mut self, start: &mut Box<Expr>, end: &mut Box<Expr>) -> Result<Type, AnalyzerError> {
+        let start_type = self.infer_right_expr(start, Type::default())?;
+        if !Type::is_integer(&start_type.kind) {
+            return Err(AnalyzerError::new(
+                start.span,
+                format!("range start must be integer type, actual={}", start_type),
+            ));
+        }
+
+        self.infer_right_expr(end, start_type.clone())?;
+
+        Ok(start_type)
+    }
+
     pub fn infer_stmt(&mut self, stmt: &mut Box<Stmt>) -> Result<(), AnalyzerError> {
         match &mut stmt.node {
-            AstNode::Fake(expr) => {
+            AstNode::Fake(expr) | AstNode::Do(expr) => {
                 self.infer_right_expr(expr, Type::default())?;
             }
             AstNode::VarDef(var_decl_mutex, expr) => {
@@ -2705,23 +2774,15 @@ impl<'a> Typesys<'a> {
                 let right_type = self.infer_right_expr(right, Type::default())?;
 
                 if !matches!(right_type.kind, TypeKind::Tuple(..)) {
-                    return Err(AnalyzerError {
-                        start: right.start,
-                        end: right.end,
-                        message: format!("cannot assign {} to tuple", right_type),
-                    });
+                    return Err(AnalyzerError::new(right.span, format!("cannot assign {} to tuple", right_type)));
                 }
 
-                self.infer_var_tuple_destr(elements, right_type, stmt.start, stmt.end)?;
+                self.infer_var_tuple_destr(elements, right_type, stmt.span)?;
             }
             AstNode::Assign(left, right) => match self.infer_left_expr(left) {
                 Ok(left_type) => {
                     if !matches!(left_type.kind, TypeKind::Param(..)) && matches!(left_type.kind, TypeKind::Void) {
-                        return Err(AnalyzerError {
-                            start: left.start,
-                            end: left.end,
-                            message: format!("cannot assign to void"),
-                        });
+                        return Err(AnalyzerError::new(left.span, format!("cannot assign to void")));
                     }
 
                     self.infer_right_expr(right, left_type)?;
@@ -2732,7 +2793,7 @@ impl<'a> Typesys<'a> {
             },
             AstNode::FnDef(_) => {}
             AstNode::Call(call) => {
-                self.infer_call(call, Type::new(TypeKind::Void), stmt.start, stmt.end)?;
+                self.infer_call(call, Type::new(TypeKind::Void), stmt.span)?;
             }
             AstNode::Catch(try_expr, catch_err_mutex, catch_body) => {
                 self.infer_catch(try_expr, catch_err_mutex, catch_body)?;
@@ -2740,8 +2801,8 @@ impl<'a> Typesys<'a> {
             AstNode::Select(cases, _has_default, _send_count, _recv_count) => {
                 for case in cases.iter_mut() {
                     if let Some(on_call) = &mut case.on_call {
-                        if let Err(e) = self.infer_call(on_call, Type::default(), case.start, case.end) {
-                            self.errors_push(e.start, e.end, e.message);
+                        if let Err(e) = self.infer_call(on_call, Type::default(), Span::new(case.start, case.end)) {
+                            self.error_push(e);
                         }
                     }
 
@@ -2749,13 +2810,12 @@ impl<'a> Typesys<'a> {
                         {
                             // 存在 recv_var 必定存在 on_call
                             let Some(on_call) = &case.on_call else { unreachable!() };
-                            let mut recv_var = recv_var_mutex.lock().unwrap();
+                            let mut recv_var = recv_var_mutex.write().unwrap();
                             recv_var.type_ = on_call.return_type.clone();
 
                             if matches!(recv_var.type_.kind, TypeKind::Unknown | TypeKind::Void | TypeKind::Null) {
                                 self.errors_push(
-                                    recv_var.symbol_start,
-                                    recv_var.symbol_end,
+                                    Span::new(recv_var.symbol_start, recv_var.symbol_end),
                                     format!("variable declaration cannot use type {}", recv_var.type_),
                                 );
                             }
@@ -2769,7 +2829,7 @@ impl<'a> Typesys<'a> {
             }
             AstNode::If(cond, consequent, alternate) => {
                 if let Err(e) = self.infer_right_expr(cond, Type::new(TypeKind::Bool)) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
 
                 self.infer_body(consequent);
@@ -2777,7 +2837,7 @@ impl<'a> Typesys<'a> {
             }
             AstNode::ForCond(condition, body) => {
                 if let Err(e) = self.infer_right_expr(condition, Type::new(TypeKind::Bool)) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
 
                 let t = Type::new(TypeKind::Void);
@@ -2786,20 +2846,20 @@ impl<'a> Typesys<'a> {
                 self.infer_body(body);
                 self.break_target_types.pop();
             }
-            AstNode::ForIterator(iterate, first, second, body) => {
-                self.infer_for_iterator(iterate, first, second, body);
+            AstNode::ForIterator(iterate, first, second, step, body) => {
+                self.infer_for_iterator(iterate, first, second, step, body);
             }
             AstNode::ForTradition(init, condition, update, body) => {
                 if let Err(e) = self.infer_stmt(init) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
 
                 if let Err(e) = self.infer_right_expr(condition, Type::new(TypeKind::Bool)) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
 
                 if let Err(e) = self.infer_stmt(update) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
 
                 let t = Type::new(TypeKind::Void);
@@ -2810,15 +2870,14 @@ impl<'a> Typesys<'a> {
             AstNode::Throw(expr) => {
                 {
                     let (is_errable, fn_name, return_type) = {
-                        let current_fn = self.current_fn_mutex.lock().unwrap();
+                        let current_fn = self.current_fn_mutex.read().unwrap();
                         (current_fn.is_errable, current_fn.fn_name.clone(), current_fn.return_type.clone())
                     };
 
                     if !is_errable {
                         //  "can't use throw stmt in a fn without an errable! declaration. example: fn %s(...):%s!",
                         self.errors_push(
-                            expr.start,
-                            expr.end,
+                            expr.span,
                             format!(
                                 "can't use throw stmt in a fn without an errable! declaration. example: fn {}(...):{}!",
                                 fn_name, return_type
@@ -2829,18 +2888,23 @@ impl<'a> Typesys<'a> {
             }
             AstNode::Return(expr_option) => {
                 let target_type = {
-                    let current_fn = self.current_fn_mutex.lock().unwrap();
-                    current_fn.return_type.clone()
+                    let current_fn = self.current_fn_mutex.read().unwrap();
+
+                    // async fn 中 return 的是 promise 的 value
+                    match &current_fn.return_type.kind {
+                        TypeKind::Promise(value_type) if current_fn.is_async => *value_type.clone(),
+                        _ => current_fn.return_type.clone(),
+                    }
                 };
 
                 if let Some(expr) = expr_option {
                     if let Err(e) = self.infer_right_expr(expr, target_type) {
-                        self.errors_push(e.start, e.end, e.message);
+                        self.error_push(e);
                     }
                 } else {
                     // target_type kind mut void
                     if !matches!(target_type.kind, TypeKind::Void) {
-                        self.errors_push(stmt.start, stmt.end, format!("fn expect return type {}, but got void", target_type));
+                        self.errors_push(stmt.span, format!("fn expect return type {}, but got void", target_type));
                     }
                 }
             }
@@ -2858,11 +2922,7 @@ impl<'a> Typesys<'a> {
                 } else {
                     // must void
                     if !matches!(target_type.kind, TypeKind::Void) {
-                        return Err(AnalyzerError {
-                            start: stmt.start,
-                            end: stmt.end,
-                            message: format!("break missing value expre"),
-                        });
+                        return Err(AnalyzerError::new(stmt.span, format!("break missing value expre")));
                     }
                 }
             }
@@ -2877,8 +2937,11 @@ impl<'a> Typesys<'a> {
 
     pub fn infer_body(&mut self, body: &mut Vec<Box<Stmt>>) {
         for stmt in body {
+            if self.cancel.is_cancelled() {
+                return;
+            }
             if let Err(e) = self.infer_stmt(stmt) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
     }
@@ -3019,6 +3082,8 @@ impl<'a> Typesys<'a> {
 
             (TypeKind::Chan(left_element), TypeKind::Chan(right_element)) => self.type_compare_with_generics(left_element, right_element, generics_param_table),
 
+            (TypeKind::Promise(left_value), TypeKind::Promise(right_value)) => self.type_compare_with_generics(left_value, right_value, generics_param_table),
+
             (TypeKind::Vec(left_element), TypeKind::Vec(right_element)) => self.type_compare_with_generics(left_element, right_element, generics_param_table),
 
             (TypeKind::Arr(left_len, left_element), TypeKind::Arr(right_len, right_element)) => {
@@ -3070,14 +3135,19 @@ impl<'a> Typesys<'a> {
         }
     }
 
-    pub fn infer_fn_decl(&mut self, fndef_mutex: Arc<Mutex<AstFnDef>>) -> Result<Type, AnalyzerError> {
+    pub fn infer_fn_decl(&mut self, fndef_mutex: Arc<RwLock<AstFnDef>>) -> Result<Type, AnalyzerError> {
         // 如果已经完成类型推导，直接返回
-        let mut fndef = fndef_mutex.lock().unwrap();
+        let mut fndef = fndef_mutex.write().unwrap();
 
         if fndef.type_.status == ReductionStatus::Done {
             return Ok(fndef.type_.clone());
         }
 
+        // => expr 的类型只能由上下文确定, 见 infer_lambda_short
+        if fndef.is_lambda_short {
+            return Ok(fndef.type_.clone());
+        }
+
         // 对返回类型进行还原
         match self.reduction_type(fndef.return_type.clone()) {
             Ok(return_type) => {
@@ -3092,7 +3162,7 @@ impl<'a> Typesys<'a> {
         let mut param_types = Vec::new();
 
         for (i, param_mutex) in fndef.params.iter().enumerate() {
-            let mut param = param_mutex.lock().unwrap();
+            let mut param = param_mutex.write().unwrap();
 
             // 对参数类型进行还原
             param.type_ = self.reduction_type(param.type_.clone())?;
@@ -3118,19 +3188,69 @@ impl<'a> Typesys<'a> {
         Ok(result)
     }
 
-    fn infer_global_vardef(&mut self, var_decl_mutex: &Arc<Mutex<VarDeclExpr>>, right_expr: &mut Box<Expr>) -> Result<(), AnalyzerError> {
-        let mut var_decl = var_decl_mutex.lock().unwrap();
+    /**
+     * => expr 的参数与返回类型来自上下文中的 fn 类型(例如调用参数的形参类型), 不存在 fn 类型的上下文时无法推导
+     *
+     * 注入了参数 _ 时上下文中的 fn 类型必须只有一个参数, 返回类型为 void 时 body 中的 return expr 改写为表达式语句
+     */
+    fn infer_lambda_short(&mut self, fndef_mutex: Arc<RwLock<AstFnDef>>, target_type: Type, span: Span) -> Result<Type, AnalyzerError> {
+        let mut fndef = fndef_mutex.write().unwrap();
+        if fndef.type_.status == ReductionStatus::Done {
+            return Ok(fndef.type_.clone());
+        }
+
+        let TypeKind::Fn(type_fn) = &target_type.kind else {
+            return Err(AnalyzerError::new(span, "cannot infer the type of '=>' lambda without an expected fn type"));
+        };
+
+        if let Some(param_mutex) = fndef.params.first() {
+            if type_fn.param_types.len() != 1 {
+                return Err(AnalyzerError::new(
+                    span,
+                    format!(
+                        "'=>' lambda uses '{}' but expected fn type '{}' has {} params",
+                        LAMBDA_SHORT_PARAM,
+                        render(&target_type),
+                        type_fn.param_types.len()
+                    ),
+                ));
+            }
+            param_mutex.write().unwrap().type_ = type_fn.param_types[0].clone();
+        }
+
+        fndef.return_type = type_fn.return_type.clone();
+        fndef.is_errable = type_fn.errable;
+        if matches!(fndef.return_type.kind, TypeKind::Void) {
+            for stmt in fndef.body.iter_mut() {
+                if let AstNode::Return(value) = &mut stmt.node {
+                    if let Some(value) = value.take() {
+                        stmt.node = AstNode::Fake(value);
+                    }
+                }
+            }
+        }
+
+        fndef.type_ = Type::new(TypeKind::Fn(Box::new(TypeFn {
+            name: fndef.fn_name.clone(),
+            tpl: false,
+            errable: type_fn.errable,
+            rest: type_fn.rest,
+            param_types: type_fn.param_types.clone(),
+            return_type: type_fn.return_type.clone(),
+        })));
+
+        Ok(fndef.type_.clone())
+    }
+
+    fn infer_global_vardef(&mut self, var_decl_mutex: &Arc<RwLock<VarDeclExpr>>, right_expr: &mut Box<Expr>) -> Result<(), AnalyzerError> {
+        let mut var_decl = var_decl_mutex.write().unwrap();
         var_decl.type_ = self.reduction_type(var_decl.type_.clone())?;
 
         let right_expr_type = self.infer_right_expr(right_expr, var_decl.type_.clone())?;
 
         if var_decl.type_.kind.is_unknown() {
             if !self.type_confirm(&right_expr_type) {
-                return Err(AnalyzerError {
-                    message: "type infer failed, has type unknown".to_string(),
-                    start: right_expr.start,
-                    end: right_expr.end,
-                });
+                return Err(AnalyzerError::new(right_expr.span, "type infer failed, has type unknown".to_string()));
             }
             var_decl.type_ = right_expr_type;
         }
@@ -3149,11 +3269,11 @@ impl<'a> Typesys<'a> {
     //     f(module)
     // }
 
-    pub fn rewrite_type_alias(&mut self, type_alias_mutex: &Arc<Mutex<TypeAliasStmt>>) {
+    pub fn rewrite_type_alias(&mut self, type_alias_mutex: &Arc<RwLock<TypeAliasStmt>>) {
         // 如果不存在 params_hash 表示当前 fndef 不存在基于泛型的重写，所以 alias 也不需要进行重写
-        if let Some(hash) = self.current_fn_mutex.lock().unwrap().generics_args_hash {
+        if let Some(hash) = self.current_fn_mutex.read().unwrap().generics_args_hash {
             // alias.ident@hash
-            let mut type_alias = type_alias_mutex.lock().unwrap();
+            let mut type_alias = type_alias_mutex.write().unwrap();
 
             let original_symbol_defined_in = self.symbol_table.get_symbol(type_alias.symbol_id.unwrap()).unwrap().defined_in;
 
@@ -3178,9 +3298,9 @@ impl<'a> Typesys<'a> {
         }
     }
 
-    pub fn rewrite_var_decl(&mut self, var_decl_mutex: Arc<Mutex<VarDeclExpr>>) {
-        if let Some(hash) = self.current_fn_mutex.lock().unwrap().generics_args_hash {
-            let mut var_decl = var_decl_mutex.lock().unwrap();
+    pub fn rewrite_var_decl(&mut self, var_decl_mutex: Arc<RwLock<VarDeclExpr>>) {
+        if let Some(hash) = self.current_fn_mutex.read().unwrap().generics_args_hash {
+            let mut var_decl = var_decl_mutex.write().unwrap();
             assert!(var_decl.symbol_id.is_some(), "var_decl must have symbol_id");
 
             // 不能包含 @ 符号
@@ -3190,13 +3310,13 @@ impl<'a> Typesys<'a> {
             let original_symbol_defined_in = self.symbol_table.get_symbol(var_decl.symbol_id.unwrap()).unwrap().defined_in;
 
             // ident@arg_hash
-            var_decl.ident = format_generics_ident(var_decl.ident.clone(), hash);
+            var_decl.ident = Atom::from(&format_generics_ident(var_decl.ident.to_string(), hash));
 
             // TODO redefine symbol 需要自定义 scope id, 不然全部定义到 global 中了
 
             // symbol register
             match self.symbol_table.define_symbol_in_scope(
-                var_decl.ident.clone(),
+                var_decl.ident.to_string(),
                 SymbolKind::Var(var_decl_mutex.clone()),
                 var_decl.symbol_start,
                 original_symbol_defined_in,
@@ -3204,7 +3324,7 @@ impl<'a> Typesys<'a> {
                 Ok(symbol_id) => {
                     // 基于 old symbol id 获取 symbol 并建立 generics_id_map 映射
                     let original_symbol = self.symbol_table.get_symbol(var_decl.symbol_id.unwrap()).unwrap();
-                    original_symbol.generics_id_map.insert(var_decl.ident.clone(), symbol_id);
+                    original_symbol.generics_id_map.insert(var_decl.ident.to_string(), symbol_id);
 
                     var_decl.symbol_id = Some(symbol_id);
                 }
@@ -3215,26 +3335,25 @@ impl<'a> Typesys<'a> {
         }
     }
 
-    pub fn infer_var_decl(&mut self, var_decl_mutex: Arc<Mutex<VarDeclExpr>>) -> Result<(), AnalyzerError> {
-        let mut var_decl = var_decl_mutex.lock().unwrap();
+    pub fn infer_var_decl(&mut self, var_decl_mutex: Arc<RwLock<VarDeclExpr>>) -> Result<(), AnalyzerError> {
+        let mut var_decl = var_decl_mutex.write().unwrap();
         var_decl.type_ = self.reduction_type(var_decl.type_.clone())?;
 
         if matches!(var_decl.type_.kind, TypeKind::Unknown | TypeKind::Void | TypeKind::Null) {
-            return Err(AnalyzerError {
-                message: format!("variable declaration cannot use type {}", var_decl.type_),
-                start: var_decl.symbol_start,
-                end: var_decl.symbol_end,
-            });
+            return Err(AnalyzerError::new(
+                Span::new(var_decl.symbol_start, var_decl.symbol_end),
+                format!("variable declaration cannot use type {}", var_decl.type_),
+            ));
         }
 
         Ok(())
     }
 
-    pub fn infer_fndef(&mut self, fndef_mutex: Arc<Mutex<AstFnDef>>) {
+    pub fn infer_fndef(&mut self, fndef_mutex: Arc<RwLock<AstFnDef>>) {
         self.current_fn_mutex = fndef_mutex;
 
         let params = {
-            let fndef: std::sync::MutexGuard<'_, AstFnDef> = self.current_fn_mutex.lock().unwrap();
+            let fndef: std::sync::RwLockReadGuard<'_, AstFnDef> = self.current_fn_mutex.read().unwrap();
             if fndef.type_.status != ReductionStatus::Done {
                 return;
             }
@@ -3250,14 +3369,14 @@ impl<'a> Typesys<'a> {
         {
             // handle body - 修改这部分
             let mut body = {
-                let mut fndef = self.current_fn_mutex.lock().unwrap();
+                let mut fndef = self.current_fn_mutex.write().unwrap();
                 std::mem::take(&mut fndef.body) // 临时取出 body 的所有权
             };
 
             self.infer_body(&mut body);
 
             {
-                let mut fndef = self.current_fn_mutex.lock().unwrap();
+                let mut fndef = self.current_fn_mutex.write().unwrap();
                 fndef.body = body;
             }
         }
@@ -3293,7 +3412,7 @@ impl<'a> Typesys<'a> {
 
             // symbol.kind 为 TypeAlias
             if let SymbolKind::TypeAlias(type_alias_mutex) = symbol.kind.clone() {
-                let mut type_alias = type_alias_mutex.lock().unwrap();
+                let mut type_alias = type_alias_mutex.write().unwrap();
 
                 // params.length == generics_params.length
                 if type_alias.params.len() != generics_params.len() {
@@ -3397,7 +3516,7 @@ impl<'a> Typesys<'a> {
             let AstNode::VarDef(var_decl_mutex, right_expr) = node else { unreachable!() };
 
             if let Err(e) = self.infer_global_vardef(var_decl_mutex, right_expr) {
-                self.errors_push(e.start, e.end, e.message);
+                self.error_push(e);
             }
         }
         self.module.global_vardefs = vardefs;
@@ -3405,26 +3524,30 @@ impl<'a> Typesys<'a> {
         // 遍历 module 下的所有的 fndef, 包含 global fn 和 local fn
         let global_fndefs = self.module.global_fndefs.clone();
         for fndef_mutex in global_fndefs {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
             let (is_generics, local_children) = {
-                let fndef = fndef_mutex.lock().unwrap();
+                let fndef = fndef_mutex.read().unwrap();
                 (fndef.is_generics, fndef.local_children.clone())
             };
 
             // 不是泛型函数
             if !is_generics {
                 if let Err(e) = self.infer_fn_decl(fndef_mutex.clone()) {
-                    self.errors_push(e.start, e.end, e.message);
+                    self.error_push(e);
                 }
 
                 for child_fndef_mutex in local_children {
                     if let Err(e) = self.infer_fn_decl(child_fndef_mutex) {
-                        self.errors_push(e.start, e.end, e.message);
+                        self.error_push(e);
                     }
                 }
 
                 continue;
             } else {
-                let mut fndef = fndef_mutex.lock().unwrap();
+                let mut fndef = fndef_mutex.write().unwrap();
                 assert!(fndef.generics_params.is_some());
 
                 match self.generics_constraints_product(fndef.impl_type.clone(), fndef.generics_params.as_mut().unwrap()) {
@@ -3445,7 +3568,7 @@ impl<'a> Typesys<'a> {
                         }
                     }
                     Err(e) => {
-                        self.errors_push(fndef.symbol_start, fndef.symbol_end, e);
+                        self.errors_push(Span::new(fndef.symbol_start, fndef.symbol_end), e);
                     }
                 }
             }
@@ -3454,19 +3577,26 @@ impl<'a> Typesys<'a> {
         return self.errors.clone();
     }
 
-    pub fn errors_push(&mut self, start: usize, end: usize, message: String) {
+    pub fn errors_push(&mut self, span: Span, message: String) {
+        self.error_push(AnalyzerError::new(span, message));
+    }
+
+    /**
+     * 保留诊断的 code 等附加信息, code action 依赖 code 识别诊断
+     */
+    pub fn error_push(&mut self, error: AnalyzerError) {
         // 判断 current fn 是否属于当前 module
-        let current_fn = self.current_fn_mutex.lock().unwrap();
+        let current_fn = self.current_fn_mutex.read().unwrap();
         if current_fn.module_index > 0 && current_fn.module_index != self.module.index {
             return;
         }
 
-        self.errors.push(AnalyzerError { start, end, message });
+        self.errors.push(error);
     }
 
     pub fn infer(&mut self) -> Vec<AnalyzerError> {
         for fndef_mutex in self.module.global_fndefs.clone() {
-            let fndef = fndef_mutex.lock().unwrap();
+            let fndef = fndef_mutex.read().unwrap();
             // generics fn 不需要进行类型推倒，因为其 param 是不确定的，只需要被调用时由调用方进行 推导
             if fndef.is_generics {
                 continue;
@@ -3481,9 +3611,13 @@ impl<'a> Typesys<'a> {
 
         // handle infer worklist, temp data to self
         while let Some(fndef_mutex) = self.worklist.pop() {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
             // 先获取需要的数据
             let generics_args_table = {
-                let fndef = fndef_mutex.lock().unwrap();
+                let fndef = fndef_mutex.read().unwrap();
                 fndef.generics_args_table.clone()
             };
 
@@ -3494,7 +3628,7 @@ impl<'a> Typesys<'a> {
             // clone 只是增加了 arc 的引用计数，内部还是共用的一个锁，所以需要注意死锁问题
             self.infer_fndef(fndef_mutex.clone());
 
-            let fndef = fndef_mutex.lock().unwrap();
+            let fndef = fndef_mutex.read().unwrap();
             // handle child, 共用 generics arg table
             for child_fndef_mutex in fndef.local_children.clone() {
                 self.infer_fndef(child_fndef_mutex);
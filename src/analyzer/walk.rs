@@ -0,0 +1,436 @@
+use super::common::{AstCall, AstFnDef, AstNode, Expr, MacroArg, Stmt};
+use std::sync::{RwLock, RwLockReadGuard};
+
+/**
+ * 只读遍历的回调, visit 在子节点之前调用, leave 在所有子节点访问完成之后调用, 因此可以维护嵌套的上下文(例如所在的 fn 与 expr 的深度)
+ *
+ * fn def(包括 closure) 的 body 作为其所在 stmt/expr 的子节点被访问, 每个 fn body 只会被访问一次
+ */
+pub trait Visitor {
+    fn visit_stmt(&mut self, _stmt: &Stmt) {}
+    fn leave_stmt(&mut self, _stmt: &Stmt) {}
+    fn visit_expr(&mut self, _expr: &Expr) {}
+    fn leave_expr(&mut self, _expr: &Expr) {}
+}
+
+struct Callbacks<'a> {
+    on_expr: &'a mut dyn FnMut(&Expr),
+    on_stmt: &'a mut dyn FnMut(&Stmt),
+}
+
+impl Visitor for Callbacks<'_> {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        (self.on_stmt)(stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        (self.on_expr)(expr);
+    }
+}
+
+/**
+ * 遍历过程中读取 fn def, walker 以及各个遍历的回调统一通过这里获取锁
+ *
+ * 加锁顺序见 AstNode 的说明: 遍历只获取 read, 并且按照 ast 的嵌套关系由外向内获取。回调在 walker 读取 fn def 之前调用,
+ * 回调中读取的 fn def 要么已经释放, 要么位于 walker 持有的 fn def 之内, 因此同一个线程不会重复锁定同一个 fn def。
+ * 已经持有某个 fn def 的调用方需要直接遍历 fndef.body, 而不是包含该 fn def 的节点。
+ * semantic/typesys 正在改写时等待 write 释放而不是跳过, 否则遍历的结果会缺少整个 fn
+ */
+pub fn read_fndef(fndef_mutex: &RwLock<AstFnDef>) -> RwLockReadGuard<'_, AstFnDef> {
+    fndef_mutex.read().unwrap()
+}
+
+struct Walker<'a> {
+    visitor: &'a mut dyn Visitor,
+}
+
+/**
+ * 深度优先遍历 body 中的所有 expr(包括 closure 与 match/catch body 中的 expr), 父 expr 先于子 expr 被访问
+ */
+pub fn walk_body(body: &[Box<Stmt>], f: &mut dyn FnMut(&Expr)) {
+    let mut callbacks = Callbacks {
+        on_expr: f,
+        on_stmt: &mut |_| {},
+    };
+    Walker { visitor: &mut callbacks }.body(body);
+}
+
+pub fn walk_expr(expr: &Expr, f: &mut dyn FnMut(&Expr)) {
+    let mut callbacks = Callbacks {
+        on_expr: f,
+        on_stmt: &mut |_| {},
+    };
+    Walker { visitor: &mut callbacks }.expr(expr);
+}
+
+/**
+ * 遍历 node 的子节点, node 自身由调用方处理
+ */
+pub fn walk_node(node: &AstNode, f: &mut dyn FnMut(&Expr)) {
+    let mut callbacks = Callbacks {
+        on_expr: f,
+        on_stmt: &mut |_| {},
+    };
+    Walker { visitor: &mut callbacks }.node(node);
+}
+
+/**
+ * 与 walk_body 的遍历顺序一致, 但是访问的是 stmt(包括 if/for/match 等嵌套 body 中的 stmt)
+ */
+pub fn walk_stmts(body: &[Box<Stmt>], f: &mut dyn FnMut(&Stmt)) {
+    let mut callbacks = Callbacks {
+        on_expr: &mut |_| {},
+        on_stmt: f,
+    };
+    Walker { visitor: &mut callbacks }.body(body);
+}
+
+/**
+ * 与 walk_body 的遍历顺序一致, 同时访问 stmt 与 expr
+ */
+pub fn walk_with(body: &[Box<Stmt>], visitor: &mut dyn Visitor) {
+    Walker { visitor }.body(body);
+}
+
+struct WalkerMut<'a> {
+    on_expr: &'a mut dyn FnMut(&mut Expr),
+    on_stmt: &'a mut dyn FnMut(&mut Stmt),
+}
+
+/**
+ * 可以修改节点的遍历, 与 walk_body 不同, parser 生成的 implicit_msg 同样会被访问
+ *
+ * fn def 通过 Arc 共享, clone 之后仍然是同一个节点, 因此不进入 fn body
+ */
+pub fn walk_stmt_mut(stmt: &mut Stmt, on_expr: &mut dyn FnMut(&mut Expr), on_stmt: &mut dyn FnMut(&mut Stmt)) {
+    WalkerMut { on_expr, on_stmt }.stmt(stmt);
+}
+
+pub fn walk_expr_mut(expr: &mut Expr, on_expr: &mut dyn FnMut(&mut Expr), on_stmt: &mut dyn FnMut(&mut Stmt)) {
+    WalkerMut { on_expr, on_stmt }.expr(expr);
+}
+
+impl Walker<'_> {
+    fn body(&mut self, body: &[Box<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.visitor.visit_stmt(stmt);
+        self.node(&stmt.node);
+        self.visitor.leave_stmt(stmt);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.visitor.visit_expr(expr);
+        self.node(&expr.node);
+        self.visitor.leave_expr(expr);
+    }
+
+    fn call(&mut self, call: &AstCall) {
+        self.expr(&call.left);
+        for arg in &call.args {
+            self.expr(arg);
+        }
+    }
+
+    fn node(&mut self, node: &AstNode) {
+        match node {
+            AstNode::Binary(_, left, right) | AstNode::Range(left, right) | AstNode::Assign(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            AstNode::Unary(_, expr)
+            | AstNode::As(_, expr)
+            | AstNode::TypeCast(_, expr)
+            | AstNode::Is(_, expr)
+            | AstNode::MacroUla(expr)
+            | AstNode::LambdaShort(expr)
+            | AstNode::Sizeof(expr)
+            | AstNode::MacroBitCast(_, expr)
+            | AstNode::MacroAssert(expr, _) // implicit_msg 由 parser 生成, 不对应用户编写的代码
+            | AstNode::Fake(expr)
+            | AstNode::Do(expr)
+            | AstNode::Throw(expr)
+            | AstNode::Let(expr)
+            | AstNode::TupleAccess(_, expr, _)
+            | AstNode::StructSelect(expr, _, _)
+            | AstNode::SelectExpr(expr, _)
+            | AstNode::Return(Some(expr))
+            | AstNode::Break(Some(expr)) => self.expr(expr),
+            AstNode::MapAccess(_, _, left, key) | AstNode::VecAccess(_, left, key) | AstNode::ArrayAccess(_, left, key) | AstNode::AccessExpr(left, key) => {
+                self.expr(left);
+                self.expr(key);
+            }
+            AstNode::MacroAsync(async_expr) => {
+                self.call(&async_expr.origin_call);
+                if let Some(flag_expr) = &async_expr.flag_expr {
+                    self.expr(flag_expr);
+                }
+            }
+            AstNode::MacroCall(_, args) => {
+                for arg in args {
+                    match arg {
+                        MacroArg::Stmt(stmt) => self.stmt(stmt),
+                        MacroArg::Expr(expr) => self.expr(expr),
+                        MacroArg::Type(_) => {}
+                    }
+                }
+            }
+            AstNode::New(_, properties, args) => {
+                for property in properties {
+                    self.expr(&property.value);
+                }
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            AstNode::StructNew(_, _, entries) => {
+                for entry in entries {
+                    self.expr(entry.value());
+                }
+            }
+            AstNode::VecNew(elements, len, cap) => {
+                for element in elements {
+                    self.expr(element);
+                }
+                if let Some(len) = len {
+                    self.expr(len);
+                }
+                if let Some(cap) = cap {
+                    self.expr(cap);
+                }
+            }
+            AstNode::ArrayNew(elements)
+            | AstNode::SetNew(elements)
+            | AstNode::TupleNew(elements)
+            | AstNode::TupleDestr(elements)
+            | AstNode::MacroMinMax(_, elements) => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            AstNode::MapNew(elements) => {
+                for element in elements {
+                    self.expr(&element.key);
+                    self.expr(&element.value);
+                }
+            }
+            AstNode::Try(try_expr, _, body) | AstNode::TryCatch(try_expr, _, body) | AstNode::Catch(try_expr, _, body) => {
+                self.expr(try_expr);
+                self.body(body);
+            }
+            AstNode::VarTupleDestr(elements, right) => {
+                for element in elements {
+                    self.expr(element);
+                }
+                self.expr(right);
+            }
+            AstNode::VarDef(_, right) => self.expr(right),
+            AstNode::If(condition, consequent, alternate) => {
+                self.expr(condition);
+                self.body(consequent);
+                self.body(alternate);
+            }
+            AstNode::ForIterator(iterate, _, _, step, body) => {
+                self.expr(iterate);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.body(body);
+            }
+            AstNode::ForCond(condition, body) => {
+                self.expr(condition);
+                self.body(body);
+            }
+            AstNode::ForTradition(init, condition, update, body) => {
+                self.stmt(init);
+                self.expr(condition);
+                self.stmt(update);
+                self.body(body);
+            }
+            AstNode::Call(call) => self.call(call),
+            AstNode::Match(subject, cases, _) => {
+                if let Some(subject) = subject {
+                    self.expr(subject);
+                }
+                for case in cases {
+                    for cond in &case.cond_list {
+                        self.expr(cond);
+                    }
+                    self.body(&case.handle_body);
+                }
+            }
+            AstNode::Select(cases, _, _, _) => {
+                for case in cases {
+                    if let Some(on_call) = &case.on_call {
+                        self.call(on_call);
+                    }
+                    self.body(&case.handle_body);
+                }
+            }
+            AstNode::FnDef(fndef_mutex) => self.body(&read_fndef(fndef_mutex).body),
+            _ => {}
+        }
+    }
+}
+
+impl WalkerMut<'_> {
+    fn body(&mut self, body: &mut [Box<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        (self.on_stmt)(stmt);
+        self.node(&mut stmt.node);
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        (self.on_expr)(expr);
+        self.node(&mut expr.node);
+    }
+
+    fn exprs(&mut self, exprs: &mut [Box<Expr>]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn call(&mut self, call: &mut AstCall) {
+        self.expr(&mut call.left);
+        self.exprs(&mut call.args);
+    }
+
+    fn node(&mut self, node: &mut AstNode) {
+        match node {
+            AstNode::Binary(_, left, right)
+            | AstNode::Range(left, right)
+            | AstNode::Assign(left, right)
+            | AstNode::MacroAssert(left, right)
+            | AstNode::MapAccess(_, _, left, right)
+            | AstNode::VecAccess(_, left, right)
+            | AstNode::ArrayAccess(_, left, right)
+            | AstNode::AccessExpr(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            AstNode::Unary(_, expr)
+            | AstNode::As(_, expr)
+            | AstNode::TypeCast(_, expr)
+            | AstNode::Is(_, expr)
+            | AstNode::MacroUla(expr)
+            | AstNode::LambdaShort(expr)
+            | AstNode::Sizeof(expr)
+            | AstNode::MacroBitCast(_, expr)
+            | AstNode::Fake(expr)
+            | AstNode::Do(expr)
+            | AstNode::Throw(expr)
+            | AstNode::Let(expr)
+            | AstNode::TupleAccess(_, expr, _)
+            | AstNode::StructSelect(expr, _, _)
+            | AstNode::SelectExpr(expr, _)
+            | AstNode::Return(Some(expr))
+            | AstNode::Break(Some(expr)) => self.expr(expr),
+            AstNode::MacroAsync(async_expr) => {
+                self.call(&mut async_expr.origin_call);
+                if let Some(flag_expr) = &mut async_expr.flag_expr {
+                    self.expr(flag_expr);
+                }
+            }
+            AstNode::MacroCall(_, args) => {
+                for arg in args {
+                    match arg {
+                        MacroArg::Stmt(stmt) => self.stmt(stmt),
+                        MacroArg::Expr(expr) => self.expr(expr),
+                        MacroArg::Type(_) => {}
+                    }
+                }
+            }
+            AstNode::New(_, properties, args) => {
+                for property in properties {
+                    self.expr(&mut property.value);
+                }
+                self.exprs(args);
+            }
+            AstNode::StructNew(_, _, entries) => {
+                for entry in entries {
+                    self.expr(entry.value_mut());
+                }
+            }
+            AstNode::VecNew(elements, len, cap) => {
+                self.exprs(elements);
+                if let Some(len) = len {
+                    self.expr(len);
+                }
+                if let Some(cap) = cap {
+                    self.expr(cap);
+                }
+            }
+            AstNode::ArrayNew(elements)
+            | AstNode::SetNew(elements)
+            | AstNode::TupleNew(elements)
+            | AstNode::TupleDestr(elements)
+            | AstNode::MacroMinMax(_, elements) => self.exprs(elements),
+            AstNode::MapNew(elements) => {
+                for element in elements {
+                    self.expr(&mut element.key);
+                    self.expr(&mut element.value);
+                }
+            }
+            AstNode::Try(try_expr, _, body) | AstNode::TryCatch(try_expr, _, body) | AstNode::Catch(try_expr, _, body) => {
+                self.expr(try_expr);
+                self.body(body);
+            }
+            AstNode::VarTupleDestr(elements, right) => {
+                self.exprs(elements);
+                self.expr(right);
+            }
+            AstNode::VarDef(_, right) => self.expr(right),
+            AstNode::If(condition, consequent, alternate) => {
+                self.expr(condition);
+                self.body(consequent);
+                self.body(alternate);
+            }
+            AstNode::ForIterator(iterate, _, _, step, body) => {
+                self.expr(iterate);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.body(body);
+            }
+            AstNode::ForCond(condition, body) => {
+                self.expr(condition);
+                self.body(body);
+            }
+            AstNode::ForTradition(init, condition, update, body) => {
+                self.stmt(init);
+                self.expr(condition);
+                self.stmt(update);
+                self.body(body);
+            }
+            AstNode::Call(call) => self.call(call),
+            AstNode::Match(subject, cases, _) => {
+                if let Some(subject) = subject {
+                    self.expr(subject);
+                }
+                for case in cases {
+                    self.exprs(&mut case.cond_list);
+                    self.body(&mut case.handle_body);
+                }
+            }
+            AstNode::Select(cases, _, _, _) => {
+                for case in cases {
+                    if let Some(on_call) = &mut case.on_call {
+                        self.call(on_call);
+                    }
+                    self.body(&mut case.handle_body);
+                }
+            }
+            _ => {}
+        }
+    }
+}
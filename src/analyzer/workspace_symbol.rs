@@ -0,0 +1,80 @@
+use super::common::AstNode;
+use crate::project::Module;
+use crate::utils::offset_to_position;
+use lsp_types::{Location, Range, SymbolInformation, SymbolKind, Url};
+
+/**
+ * 忽略大小写的子序列匹配, 例如 hsrv 可以匹配 http_server
+ */
+fn matches_query(name: &str, query: &str) -> bool {
+    let mut chars = name.chars().flat_map(char::to_lowercase);
+    query.chars().flat_map(char::to_lowercase).all(|q| chars.any(|c| c == q))
+}
+
+/**
+ * semantic 之后全局 var 与 type 的 ident 携带了 module ident 前缀
+ */
+fn local_ident<'a>(m: &Module, ident: &'a str) -> &'a str {
+    ident.strip_prefix(&m.ident).and_then(|rest| rest.strip_prefix('.')).unwrap_or(ident)
+}
+
+/**
+ * workspace/symbol, module 中与 query 匹配的全局 fn/var/type, query 为空时返回所有全局符号
+ */
+pub fn workspace_symbols(m: &Module, query: &str) -> Vec<SymbolInformation> {
+    let Ok(uri) = Url::from_file_path(&m.path) else {
+        return Vec::new();
+    };
+
+    let mut symbols = Vec::new();
+    for stmt in &m.stmts {
+        let (name, kind, container_name, start, end) = match &stmt.node {
+            AstNode::FnDef(fndef_mutex) => {
+                let fndef = fndef_mutex.read().unwrap();
+                if fndef.fn_name.is_empty() {
+                    continue;
+                }
+
+                // impl fn 的 container 为其所属的类型
+                let (kind, container_name) = if fndef.impl_type.kind.is_exist() {
+                    (SymbolKind::METHOD, fndef.impl_type.to_string())
+                } else {
+                    (SymbolKind::FUNCTION, m.ident.clone())
+                };
+                (fndef.fn_name.clone(), kind, container_name, fndef.symbol_start, fndef.symbol_end)
+            }
+            AstNode::VarDef(var_decl_mutex, _) => {
+                let var_decl = var_decl_mutex.read().unwrap();
+                let name = local_ident(m, &var_decl.ident).to_string();
+                (name, SymbolKind::VARIABLE, m.ident.clone(), var_decl.symbol_start, var_decl.symbol_end)
+            }
+            AstNode::TypeAlias(type_alias_mutex) => {
+                let type_alias = type_alias_mutex.read().unwrap();
+                let name = local_ident(m, &type_alias.ident).to_string();
+                (name, SymbolKind::STRUCT, m.ident.clone(), type_alias.symbol_start, type_alias.symbol_end)
+            }
+            _ => continue,
+        };
+
+        if !matches_query(&name, query) {
+            continue;
+        }
+
+        let (Some(start), Some(end)) = (offset_to_position(start, &m.line_index), offset_to_position(end, &m.line_index)) else {
+            continue;
+        };
+
+        // deprecated 字段已经被 tags 取代, 但是仍然需要初始化
+        #[allow(deprecated)]
+        symbols.push(SymbolInformation {
+            name,
+            kind,
+            tags: None,
+            deprecated: None,
+            location: Location::new(uri.clone(), Range::new(start, end)),
+            container_name: (!container_name.is_empty()).then_some(container_name),
+        });
+    }
+
+    symbols
+}
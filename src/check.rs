@@ -0,0 +1,281 @@
+use crate::analyzer::diagnostic::{file_diagnostics, DiagnosticConfig};
+use crate::analyzer::snippet::{render_snippet, severity_name, Label, RenderConfig, Snippet};
+use crate::document::{LineIndex, PositionEncoding};
+use crate::project::Project;
+use crate::settings::Settings;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub const CHECK_USAGE: &str = "usage: nls check <path> [--format human|json] [--deny-warnings]";
+
+// 参数错误或者 path 不存在时的退出码, 存在 error 时退出码为 1
+pub const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheckFormat {
+    #[default]
+    Human,
+    Json, // 每行一个 json object(ndjson)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckOptions {
+    pub path: String,
+    pub format: CheckFormat,
+    pub deny_warnings: bool, // warning 视为 error 上报, 同样会导致非 0 的退出码
+}
+
+impl CheckOptions {
+    /**
+     * 解析 nls check 之后的参数, path 默认为当前目录
+     */
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut path = None;
+        let mut format = CheckFormat::default();
+        let mut deny_warnings = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--deny-warnings" => deny_warnings = true,
+                "--format" => {
+                    format = match args.next().map(|s| s.as_str()) {
+                        Some("human") => CheckFormat::Human,
+                        Some("json") => CheckFormat::Json,
+                        Some(other) => return Err(format!("unknown format '{}', expected human or json", other)),
+                        None => return Err("missing value for --format".to_string()),
+                    }
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ if path.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+                _ => path = Some(arg.clone()),
+            }
+        }
+
+        Ok(Self {
+            path: path.unwrap_or(".".to_string()),
+            format,
+            deny_warnings,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileDiagnostic {
+    pub path: String,
+    pub diagnostic: Diagnostic,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub files: Vec<String>,
+    pub diagnostics: Vec<FileDiagnostic>, // 按照文件路径排序, 同一个文件中 error 在前 warning 在后
+    pub sources: HashMap<String, String>, // key = path, 用于渲染源码片段
+}
+
+impl CheckReport {
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.diagnostic.severity == Some(DiagnosticSeverity::WARNING))
+            .count()
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.error_count() > 0 {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/**
+ * 对 path(文件或者目录) 下所有的 .n 文件进行词法, 语法与语义分析, 只收集 path 下文件的诊断, import 的 std module 不会上报
+ *
+ * 诊断的 position 使用 utf-8 编码计算 character, 与 lsp 的诊断一致, error 的数量不受 max_per_file 限制
+ */
+pub async fn check(options: &CheckOptions) -> Result<CheckReport, String> {
+    let path = Path::new(&options.path)
+        .canonicalize()
+        .map_err(|e| format!("cannot access '{}': {}", options.path, e))?;
+    let (root, files) = if path.is_dir() {
+        (path.to_string_lossy().to_string(), Vec::new())
+    } else {
+        let root = path.parent().unwrap_or(Path::new("/")).to_string_lossy().to_string();
+        (root, vec![path.to_string_lossy().to_string()])
+    };
+
+    let mut project = Project::new(root, PositionEncoding::Utf8, Arc::new(Mutex::new(Settings::default()))).await;
+    let files = if files.is_empty() { project.workspace_files() } else { files };
+    let failures = project.index_workspace(&files, |_| {}).await;
+
+    let config = DiagnosticConfig {
+        max_per_file: usize::MAX,
+        ..project.settings.lock().unwrap().diagnostic_config()
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut sources = HashMap::new();
+    let module_handled = project.module_handled.lock().unwrap().clone();
+    let module_db = project.module_db.lock().unwrap();
+    for file in &files {
+        // 读取失败的文件作为 error 上报在文件开头
+        if let Some((_, reason)) = failures.iter().find(|(path, _)| path == file) {
+            diagnostics.push(FileDiagnostic {
+                path: file.clone(),
+                diagnostic: Diagnostic {
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    message: reason.clone(),
+                    ..Default::default()
+                },
+            });
+            continue;
+        }
+
+        let Some(m) = module_handled.get(file).map(|index| &module_db[*index]) else {
+            continue;
+        };
+        sources.insert(file.clone(), m.source.clone());
+        for mut diagnostic in file_diagnostics(m, &config) {
+            if options.deny_warnings && diagnostic.severity == Some(DiagnosticSeverity::WARNING) {
+                diagnostic.severity = Some(DiagnosticSeverity::ERROR);
+            }
+            diagnostics.push(FileDiagnostic {
+                path: file.clone(),
+                diagnostic,
+            });
+        }
+    }
+
+    Ok(CheckReport { files, diagnostics, sources })
+}
+
+fn code_text(code: &Option<NumberOrString>) -> Option<String> {
+    match code {
+        Some(NumberOrString::Number(n)) => Some(n.to_string()),
+        Some(NumberOrString::String(s)) => Some(s.clone()),
+        None => None,
+    }
+}
+
+fn uri_path(uri: &Url) -> String {
+    uri.to_file_path().map(|p| p.to_string_lossy().to_string()).unwrap_or(uri.to_string())
+}
+
+// line 与 column 从 1 开始, column 为行内的 utf-8 byte offset
+fn range_json(range: &Range) -> Value {
+    json!({
+        "start": { "line": range.start.line + 1, "column": range.start.character + 1 },
+        "end": { "line": range.end.line + 1, "column": range.end.character + 1 },
+    })
+}
+
+/**
+ * 单条诊断的 json 格式, 所有的 key 总是存在, 没有错误码时 code 为 null, 没有关联信息时 related 为空数组
+ *
+ * {"file", "range": {"start": {"line", "column"}, "end": {...}}, "severity", "code", "source", "message", "related": [{"file", "range", "message"}]}
+ */
+pub fn diagnostic_json(d: &FileDiagnostic) -> Value {
+    let diagnostic = &d.diagnostic;
+    let related: Vec<Value> = diagnostic
+        .related_information
+        .iter()
+        .flatten()
+        .map(|info| json!({ "file": uri_path(&info.location.uri), "range": range_json(&info.location.range), "message": info.message }))
+        .collect();
+
+    json!({
+        "file": d.path,
+        "range": range_json(&diagnostic.range),
+        "severity": severity_name(diagnostic.severity),
+        "code": code_text(&diagnostic.code),
+        "source": diagnostic.source,
+        "message": diagnostic.message,
+        "related": related,
+    })
+}
+
+/**
+ * 带有源码片段的诊断, 同一个文件中的关联信息作为 secondary 标注, 其他文件中的关联信息作为 note
+ */
+pub fn diagnostic_human(d: &FileDiagnostic, source: &str, config: RenderConfig) -> String {
+    let diagnostic = &d.diagnostic;
+    let line_index = LineIndex::new(source, PositionEncoding::Utf8);
+    let label = |range: &Range, message: &str| Label::new(line_index.offset(range.start), line_index.offset(range.end), message);
+
+    let mut snippet = Snippet {
+        severity: diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR),
+        code: code_text(&diagnostic.code),
+        message: diagnostic.message.clone(),
+        primary: label(&diagnostic.range, ""),
+        secondary: Vec::new(),
+        notes: Vec::new(),
+    };
+    for info in diagnostic.related_information.iter().flatten() {
+        let path = uri_path(&info.location.uri);
+        if path == d.path {
+            snippet.secondary.push(label(&info.location.range, &info.message));
+        } else {
+            let position = info.location.range.start;
+            snippet
+                .notes
+                .push(format!("{}:{}:{}: {}", path, position.line + 1, position.character + 1, info.message));
+        }
+    }
+
+    render_snippet(&d.path, source, &line_index, &snippet, config)
+}
+
+/**
+ * nls check 的入口, 诊断输出到 stdout, 参数错误输出到 stderr, 返回进程的退出码
+ */
+pub async fn run(args: &[String]) -> i32 {
+    let options = match CheckOptions::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n{}", message, CHECK_USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let report = match check(&options).await {
+        Ok(report) => report,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return EXIT_USAGE;
+        }
+    };
+
+    let config = RenderConfig::from_env();
+    for d in &report.diagnostics {
+        match options.format {
+            CheckFormat::Human => {
+                let source = report.sources.get(&d.path).map(|s| s.as_str()).unwrap_or_default();
+                println!("{}\n", diagnostic_human(d, source, config));
+            }
+            CheckFormat::Json => println!("{}", diagnostic_json(d)),
+        }
+    }
+
+    if options.format == CheckFormat::Human {
+        println!(
+            "checked {} files: {} errors, {} warnings",
+            report.files.len(),
+            report.error_count(),
+            report.warning_count()
+        );
+    }
+
+    report.exit_code()
+}
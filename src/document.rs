@@ -0,0 +1,169 @@
+use lsp_types::{Position, PositionEncodingKind, TextDocumentContentChangeEvent};
+use ropey::Rope;
+
+/**
+ * lsp position 中 character 的计算方式, 通过 initialize 协商, 客户端未声明时使用 lsp 默认的 utf-16
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /**
+     * 按照客户端声明的优先级选择第一个支持的编码
+     */
+    pub fn negotiate(client_encodings: Option<&[PositionEncodingKind]>) -> Self {
+        client_encodings
+            .unwrap_or_default()
+            .iter()
+            .find_map(|kind| match kind.as_str() {
+                "utf-8" => Some(PositionEncoding::Utf8),
+                "utf-16" => Some(PositionEncoding::Utf16),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn kind(&self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    fn char_len(&self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+        }
+    }
+}
+
+// 与 ropey 的换行规则保持一致
+fn is_line_break(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+/**
+ * analyzer 中的 offset 都是 char offset, 与 lsp position 之间的转换统一经过 LineIndex, 只有这里需要关心 position encoding
+ */
+#[derive(Debug, Clone, Default)]
+pub struct LineIndex {
+    rope: Rope,
+    encoding: PositionEncoding,
+}
+
+impl LineIndex {
+    pub fn new(text: &str, encoding: PositionEncoding) -> Self {
+        Self::from_rope(Rope::from_str(text), encoding)
+    }
+
+    pub fn from_rope(rope: Rope, encoding: PositionEncoding) -> Self {
+        Self { rope, encoding }
+    }
+
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /**
+     * char offset 转换为 position, offset 超出文件末尾时返回 None
+     */
+    pub fn position(&self, offset: usize) -> Option<Position> {
+        let line = self.rope.try_char_to_line(offset).ok()?;
+        let line_start = self.rope.line_to_char(line);
+        Some(Position::new(line as u32, self.len(line_start, offset) as u32))
+    }
+
+    /**
+     * position 转换为 char offset
+     *
+     * 超出文件末尾的 line 对应文件末尾, 超出行尾的 character 对应行尾(换行符之前)
+     * character 位于多字节字符(utf-16 代理对或者 utf-8 多字节序列)中间时向后对齐到完整的字符
+     */
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+
+        let line_start = self.rope.line_to_char(line);
+        let mut column = 0;
+        for (i, c) in self.rope.line(line).chars().enumerate() {
+            if column >= position.character as usize || is_line_break(c) {
+                return line_start + i;
+            }
+            column += self.encoding.char_len(c);
+        }
+
+        line_start + self.rope.line(line).len_chars()
+    }
+
+    /**
+     * position 对应的 byte offset, 用于修改 String 中的内容
+     */
+    pub fn byte_offset(&self, position: Position) -> usize {
+        self.rope.char_to_byte(self.offset(position))
+    }
+
+    /**
+     * [start, end) 范围内的字符按照 position encoding 计算的长度
+     */
+    pub fn len(&self, start: usize, end: usize) -> usize {
+        let end = end.min(self.rope.len_chars());
+        if start >= end {
+            return 0;
+        }
+        self.rope.slice(start..end).chars().map(|c| self.encoding.char_len(c)).sum()
+    }
+}
+
+/**
+ * 编辑器中打开的文件内容, 通过 didChange 中的增量修改保持与编辑器一致
+ */
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub version: i32,
+    text: String,
+    line_index: LineIndex,
+}
+
+impl Document {
+    pub fn new(text: String, version: i32) -> Self {
+        Self::with_encoding(text, version, PositionEncoding::default())
+    }
+
+    pub fn with_encoding(text: String, version: i32, encoding: PositionEncoding) -> Self {
+        let line_index = LineIndex::new(&text, encoding);
+        Self { version, text, line_index }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /**
+     * 按顺序应用一次 didChange 中的所有修改, 没有 range 的修改表示替换整个文件
+     *
+     * 每个修改中的 range 基于前一个修改应用之后的内容, 因此每次修改之后都需要重建 line index
+     */
+    pub fn apply_changes(&mut self, changes: Vec<TextDocumentContentChangeEvent>, version: i32) {
+        let encoding = self.line_index.encoding();
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = self.line_index.byte_offset(range.start);
+                    let end = self.line_index.byte_offset(range.end).max(start);
+                    self.text.replace_range(start..end, &change.text);
+                }
+                None => self.text = change.text,
+            }
+            self.line_index = LineIndex::new(&self.text, encoding);
+        }
+
+        self.version = version;
+    }
+}
@@ -1,4 +1,11 @@
 pub mod analyzer;
+pub mod check;
+pub mod document;
+pub mod package;
 pub mod project;
+pub mod scheduler;
+pub mod settings;
 pub mod utils;
-pub mod package;
\ No newline at end of file
+pub mod wasm;
+
+pub use analyzer::parse::{parse, ParseResult};
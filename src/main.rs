@@ -1,12 +1,35 @@
 use dashmap::DashMap;
 use log::debug;
-use nls::analyzer::lexer::LEGEND_TYPE;
+use nls::analyzer::code_action::{
+    create_function_action, create_method_action, insert_expected_token_actions, missing_fields_action, specify_type_action, use_var_action,
+};
+use nls::analyzer::code_lens::{code_lenses, count_references, lens_target, resolve_reference_lens};
+use nls::analyzer::completion::{auto_import_completions, completion_prefix, AutoImportContext, CompletionConfig};
+use nls::analyzer::diagnostic::{document_diagnostic_report, file_diagnostics, workspace_document_report, DIAGNOSTIC_SOURCE};
+use nls::analyzer::document_link::document_links;
+use nls::analyzer::file_rename::import_rename_edits;
+use nls::analyzer::folding_range::folding_ranges;
+use nls::analyzer::formatter::format_source;
+use nls::analyzer::hover::hover;
+use nls::analyzer::inlay_hint::inlay_hints;
+use nls::analyzer::lexer::{LEGEND_MODIFIER, LEGEND_TYPE};
 use nls::analyzer::module_unique_ident;
+use nls::analyzer::reference::{document_highlights, linked_editing_ranges, ReferenceIndex};
+use nls::analyzer::selection_range::selection_ranges;
+use nls::analyzer::semantic_tokens::{encode_semantic_tokens, semantic_tokens_delta};
+use nls::analyzer::signature_help::signature_help;
+use nls::analyzer::type_hierarchy::TypeHierarchyIndex;
+use nls::analyzer::workspace_symbol::workspace_symbols;
+use nls::document::{Document, LineIndex, PositionEncoding};
 use nls::package::parse_package;
-use nls::project::Project;
+use nls::project::{Module, Project};
+use nls::scheduler::AnalysisScheduler;
+use nls::settings::{Settings, SETTINGS_SECTION};
 use nls::utils::offset_to_position;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::notification::Notification;
@@ -17,19 +40,37 @@ use tower_lsp::{Client, LanguageServer, LspService, Server};
 struct Backend {
     client: Client,
     // document_map: DashMap<String, Rope>,
-    projects: DashMap<String, Project>, // key 是工作区 URI，value 是对应的项目
+    projects: DashMap<String, Project>,                                   // key 是工作区 URI，value 是对应的项目
+    semantic_tokens_cache: DashMap<String, (String, Vec<SemanticToken>)>, // key 是 file path, value 是 (result_id, tokens), 用于计算 delta
+    semantic_tokens_result_id: AtomicUsize,
+    reference_index_cache: DashMap<String, Arc<ReferenceIndex>>, // key 是 file path, 光标移动时会频繁请求 document highlight
+    scheduler: AnalysisScheduler,                                // didChange 之后的分析任务, 连续输入时只分析最新的版本
+    settings: Arc<Mutex<Settings>>,                              // nls.* 配置, 所有 project 共享
 }
 
 // backend 除了实现自身的方法，还实现了 LanguageServer trait 的方法
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let client_encodings = params.capabilities.general.as_ref().and_then(|general| general.position_encodings.as_deref());
+        let position_encoding = PositionEncoding::negotiate(client_encodings);
+
+        // nature root 等需要在创建 project 时确定的配置通过 initializationOptions 传递
+        if let Some(options) = &params.initialization_options {
+            *self.settings.lock().unwrap() = Settings::from_value(options.get(SETTINGS_SECTION).unwrap_or(options));
+        }
+
         // 获取工作区根目录
         if let Some(workspace_folders) = params.workspace_folders {
             for folder in workspace_folders {
                 // folder.uri 是工作区根目录的 URI
-                let project_root = folder.uri.to_file_path().expect("Failed to convert URI to file path").to_string_lossy().to_string();
-                let project = Project::new(project_root.clone()).await;
+                let project_root = folder
+                    .uri
+                    .to_file_path()
+                    .expect("Failed to convert URI to file path")
+                    .to_string_lossy()
+                    .to_string();
+                let project = Project::new(project_root.clone(), position_encoding, self.settings.clone()).await;
                 project.backend_handle_queue();
 
                 // 多工作区处理
@@ -41,12 +82,13 @@ impl LanguageServer for Backend {
             server_info: None,
             offset_encoding: None,
             capabilities: ServerCapabilities {
+                position_encoding: Some(position_encoding.kind()),
                 //  开启内联提示
                 inlay_hint_provider: Some(OneOf::Left(true)),
                 // 文档同步配置
                 text_document_sync: Some(TextDocumentSyncCapability::Options(TextDocumentSyncOptions {
                     open_close: Some(true),
-                    change: Some(TextDocumentSyncKind::FULL),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
                     save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions { include_text: Some(true) })),
                     ..Default::default()
                 })),
@@ -58,6 +100,36 @@ impl LanguageServer for Backend {
                     all_commit_characters: None,
                     completion_item: None,
                 }),
+                // 函数签名提示
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                // quickfix
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                // 代码折叠
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                // 代码格式化
+                document_formatting_provider: Some(OneOf::Left(true)),
+                // 语法结构选择范围
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions { resolve_provider: Some(true) }),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                // import 路径跳转
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                // pull diagnostics, 与 publishDiagnostics 同时存在
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                    identifier: Some(DIAGNOSTIC_SOURCE.to_string()),
+                    inter_file_dependencies: true,
+                    workspace_diagnostics: true,
+                    work_done_progress_options: Default::default(),
+                })),
                 execute_command_provider: Some(ExecuteCommandOptions {
                     commands: vec!["dummy.do_something".to_string()],
                     work_done_progress_options: Default::default(),
@@ -69,7 +141,30 @@ impl LanguageServer for Backend {
                         supported: Some(true),
                         change_notifications: Some(OneOf::Left(true)),
                     }),
-                    file_operations: None,
+                    // 移动 .n 文件或目录时同步改写 import
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(FileOperationRegistrationOptions {
+                            filters: vec![
+                                FileOperationFilter {
+                                    scheme: Some("file".to_string()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**/*.n".to_string(),
+                                        matches: Some(FileOperationPatternKind::File),
+                                        options: None,
+                                    },
+                                },
+                                FileOperationFilter {
+                                    scheme: Some("file".to_string()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**".to_string(),
+                                        matches: Some(FileOperationPatternKind::Folder),
+                                        options: None,
+                                    },
+                                },
+                            ],
+                        }),
+                        ..Default::default()
+                    }),
                 }),
                 // 语义标记配置
                 semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
@@ -87,17 +182,18 @@ impl LanguageServer for Backend {
                             work_done_progress_options: WorkDoneProgressOptions::default(),
                             legend: SemanticTokensLegend {
                                 // // LEGEND_TYPE 通常定义在 semantic_token.rs 中，包含所有支持的标记类型
-                                token_types: LEGEND_TYPE.into(), // 支持的标记类型, 如函数、变量、字符串等
-                                token_modifiers: vec![],         // 支持的标记修饰符, 例如 readonly, static 等
+                                token_types: LEGEND_TYPE.into(),         // 支持的标记类型, 如函数、变量、字符串等
+                                token_modifiers: LEGEND_MODIFIER.into(), // 支持的标记修饰符, 例如 readonly, static 等
                             },
                             range: Some(true), // 范围增量更新语义
-                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
                         },
                         static_registration_options: StaticRegistrationOptions::default(),
                     },
                 )),
                 // definition: Some(GotoCapability::default()),
                 definition_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Left(true)),
                 ..ServerCapabilities::default()
@@ -106,6 +202,48 @@ impl LanguageServer for Backend {
     }
     async fn initialized(&self, _: InitializedParams) {
         debug!("initialized");
+
+        // lsp-types 的 ServerCapabilities 中没有 type hierarchy, 通过动态注册声明, documentSelector 为 null 时使用 client 侧的配置
+        let registration = Registration {
+            id: "type-hierarchy".to_string(),
+            method: "textDocument/prepareTypeHierarchy".to_string(),
+            register_options: Some(serde_json::json!({ "documentSelector": null })),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            debug!("register type hierarchy failed: {}", e);
+        }
+
+        // 监听未在编辑器中打开的文件的变化
+        let watchers = ["**/*.n", "**/package.toml"]
+            .iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.to_string()),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "watched-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions { watchers }).ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            debug!("register watched files failed: {}", e);
+        }
+
+        // 配置变化时客户端通过 didChangeConfiguration 通知, 具体的配置通过 workspace/configuration 拉取
+        let registration = Registration {
+            id: "configuration".to_string(),
+            method: "workspace/didChangeConfiguration".to_string(),
+            register_options: None,
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            debug!("register configuration failed: {}", e);
+        }
+        if let Some(settings) = self.fetch_settings().await {
+            *self.settings.lock().unwrap() = settings;
+        }
+
+        self.index_workspace().await;
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -114,6 +252,12 @@ impl LanguageServer for Backend {
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         debug!("file opened");
+        let file_path = params.text_document.uri.path();
+        if let Some(project) = self.get_file_project(file_path) {
+            let document = Document::with_encoding(params.text_document.text.clone(), params.text_document.version, project.position_encoding);
+            project.documents.lock().unwrap().insert(file_path.to_string(), document);
+        }
+
         self.on_change(TextDocumentItem {
             uri: params.text_document.uri,
             text: &params.text_document.text,
@@ -123,10 +267,26 @@ impl LanguageServer for Backend {
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let file_path = params.text_document.uri.path();
+        let version = params.text_document.version;
+        let Some(project) = self.get_file_project(file_path) else {
+            return;
+        };
+
+        // 增量修改应用到打开的文件上, 未通过 didOpen 打开的文件以磁盘中的内容为基础
+        let text = {
+            let mut documents = project.documents.lock().unwrap();
+            let document = documents
+                .entry(file_path.to_string())
+                .or_insert_with(|| Document::with_encoding(std::fs::read_to_string(file_path).unwrap_or_default(), version, project.position_encoding));
+            document.apply_changes(params.content_changes, version);
+            document.text().to_string()
+        };
+
         self.on_change(TextDocumentItem {
-            text: &params.content_changes[0].text,
+            text: &text,
             uri: params.text_document.uri,
-            version: Some(params.text_document.version),
+            version: Some(version),
         })
         .await
     }
@@ -134,7 +294,7 @@ impl LanguageServer for Backend {
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
         if let Some(text) = params.text {
             let item = TextDocumentItem {
-                uri: params.text_document.uri,
+                uri: params.text_document.uri.clone(),
                 text: &text,
                 version: None,
             };
@@ -142,8 +302,14 @@ impl LanguageServer for Backend {
             _ = self.client.semantic_tokens_refresh().await;
         }
         debug!("file saved!");
+
+        self.refresh_dependents(params.text_document.uri.path()).await;
     }
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let file_path = params.text_document.uri.path();
+        if let Some(project) = self.get_file_project(file_path) {
+            project.documents.lock().unwrap().remove(file_path);
+        }
         debug!("file closed!");
     }
 
@@ -225,7 +391,48 @@ impl LanguageServer for Backend {
         let file_path = params.text_document.uri.path();
         debug!("semantic_token_full");
 
-        // semantic_tokens 是一个闭包, 返回 vscode 要求的 SemanticToken 结构
+        let Some(semantic_tokens) = self.module_semantic_tokens(file_path) else {
+            return Ok(None);
+        };
+
+        let result_id = self.cache_semantic_tokens(file_path, semantic_tokens.clone());
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: Some(result_id),
+            data: semantic_tokens,
+        })))
+    }
+
+    async fn semantic_tokens_full_delta(&self, params: SemanticTokensDeltaParams) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let file_path = params.text_document.uri.path();
+        debug!("semantic_token_full_delta");
+
+        let Some(semantic_tokens) = self.module_semantic_tokens(file_path) else {
+            return Ok(None);
+        };
+
+        // 只有 previous_result_id 与缓存一致时才能计算 delta, 否则返回完整的 tokens
+        let edits = self
+            .semantic_tokens_cache
+            .get(file_path)
+            .filter(|cache| cache.0 == params.previous_result_id)
+            .map(|cache| semantic_tokens_delta(&cache.1, &semantic_tokens));
+
+        let result_id = self.cache_semantic_tokens(file_path, semantic_tokens.clone());
+        if let Some(edits) = edits {
+            return Ok(Some(SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                result_id: Some(result_id),
+                edits,
+            })));
+        }
+
+        Ok(Some(SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+            result_id: Some(result_id),
+            data: semantic_tokens,
+        })))
+    }
+
+    async fn semantic_tokens_range(&self, params: SemanticTokensRangeParams) -> Result<Option<SemanticTokensRangeResult>> {
+        let file_path = params.text_document.uri.path();
         let semantic_tokens = || -> Option<Vec<SemanticToken>> {
             let Some(project) = self.get_file_project(&file_path) else { unreachable!() };
 
@@ -235,203 +442,439 @@ impl LanguageServer for Backend {
                 module_handled.get(file_path)?.clone()
             };
 
-            let mut module_db = project.module_db.lock().unwrap();
-            let m = &mut module_db[module_index];
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+            Some(encode_semantic_tokens(&m.sem_token_db, &m.line_index))
+        }();
+        Ok(semantic_tokens.map(|data| SemanticTokensRangeResult::Tokens(SemanticTokens { result_id: None, data })))
+    }
 
-            // 获取 semantic_token_map 中的 token
-            let im_complete_tokens = m.sem_token_db.clone();
-            let rope = m.rope.clone();
+    async fn inlay_hint(&self, params: tower_lsp::lsp_types::InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let file_path = params.text_document.uri.path();
+        let range = params.range;
 
-            // im_complete_tokens.sort_by(|a, b| a.start.cmp(&b.start));
+        let hints = || -> Option<Vec<InlayHint>> {
+            let project = self.get_file_project(&file_path)?;
 
-            let mut pre_line = 0;
-            let mut pre_start = 0;
-            let semantic_tokens: Vec<SemanticToken> = im_complete_tokens
-                .iter()
-                .filter_map(|token| {
-                    // dbg!(token.clone(), pre_line, pre_start);
-
-                    let line = rope.try_char_to_line(token.start).ok()? as u32;
-                    let first = rope.try_line_to_char(line as usize).ok()? as u32;
-                    let start = token.start as u32 - first;
-                    let delta_line = line - pre_line;
-                    let delta_start = if delta_line == 0 { start - pre_start } else { start };
-
-                    // dbg!(line, first, start, delta_line, delta_start, "--------------------------------");
-
-                    let ret = Some(SemanticToken {
-                        delta_line,
-                        delta_start,
-                        length: token.length as u32,
-                        token_type: token.semantic_token_type.clone() as u32,
-                        token_modifiers_bitset: 0,
-                    });
-                    pre_line = line;
-                    pre_start = start;
-                    ret
-                })
-                .collect::<Vec<_>>();
-            Some(semantic_tokens)
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let symbol_table = project.symbol_table.lock().unwrap();
+            let config = project.settings.lock().unwrap().inlay_hint_config();
+            let hints = inlay_hints(&module_db[module_index], &symbol_table, &config)
+                .into_iter()
+                .filter(|hint| hint.position >= range.start && hint.position <= range.end)
+                .collect();
+            Some(hints)
         }();
-        if let Some(semantic_token) = semantic_tokens {
-            return Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
-                result_id: None,
-                data: semantic_token,
-            })));
+        Ok(hints)
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let file_path = uri.path();
+
+        let completions = || -> Option<Vec<CompletionItem>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let package_config = project.package_config.as_ref().map(|p| p.lock().unwrap().clone());
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let offset = m.line_index.offset(position);
+            let prefix = completion_prefix(&m.rope, offset);
+
+            let config = CompletionConfig::default();
+            let ctx = AutoImportContext {
+                nature_root: &project.nature_root,
+                package_config: package_config.as_ref(),
+                config: &config,
+            };
+
+            Some(auto_import_completions(m, &module_db, &prefix, &ctx))
+        }();
+        Ok(completions.map(CompletionResponse::Array))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let file_path = params.text_document_position_params.text_document.uri.path();
+        let position = params.text_document_position_params.position;
+
+        let signature_help = || -> Option<SignatureHelp> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let symbol_table = project.symbol_table.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let offset = m.line_index.offset(position);
+            signature_help(m, &symbol_table, offset)
+        }();
+        Ok(signature_help)
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let file_path = uri.path();
+
+        let actions = || -> Option<CodeActionResponse> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let package_config = project.package_config.as_ref().map(|p| p.lock().unwrap().clone());
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let config = CompletionConfig::default();
+            let ctx = AutoImportContext {
+                nature_root: &project.nature_root,
+                package_config: package_config.as_ref(),
+                config: &config,
+            };
+
+            let mut actions = Vec::new();
+            for diagnostic in &params.context.diagnostics {
+                if let Some(action) = missing_fields_action(m, &uri, diagnostic) {
+                    actions.push(action);
+                }
+                actions.extend(insert_expected_token_actions(m, &uri, diagnostic));
+                actions.extend(create_method_action(m, &module_db, &uri, diagnostic, &ctx));
+                actions.extend(create_function_action(m, &module_db, &uri, diagnostic, &ctx));
+            }
+
+            actions.extend(specify_type_action(m, &module_db, &uri, params.range, &ctx));
+            actions.extend(use_var_action(m, &module_db, &uri, params.range, &ctx));
+            Some(actions.into_iter().map(CodeActionOrCommand::CodeAction).collect())
+        }();
+        Ok(actions)
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let file_path = params.text_document.uri.path();
+
+        let ranges = || -> Option<Vec<FoldingRange>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            Some(folding_ranges(&module_db[module_index]))
+        }();
+        Ok(ranges)
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let file_path = params.text_document_position_params.text_document.uri.path();
+        let position = params.text_document_position_params.position;
+
+        let result = || -> Option<Hover> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let symbol_table = project.symbol_table.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let offset = m.line_index.offset(position);
+            hover(m, &symbol_table, &self.reference_index(m), offset)
+        }();
+        Ok(result)
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let file_path = params.text_document.uri.path();
+
+        let links = || -> Option<Vec<DocumentLink>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            Some(document_links(&module_db[module_index]))
+        }();
+        Ok(links)
+    }
+
+    async fn diagnostic(&self, params: DocumentDiagnosticParams) -> Result<DocumentDiagnosticReportResult> {
+        let file_path = params.text_document.uri.path();
+
+        let report = || -> Option<DocumentDiagnosticReport> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let config = project.settings.lock().unwrap().diagnostic_config();
+            let module_db = project.module_db.lock().unwrap();
+            Some(document_diagnostic_report(
+                &module_db[module_index],
+                &config,
+                params.previous_result_id.as_deref(),
+            ))
+        }();
+
+        // 尚未分析的文件返回空的 report
+        let report = report.unwrap_or_else(|| DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport::default()));
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    async fn workspace_diagnostic(&self, params: WorkspaceDiagnosticParams) -> Result<WorkspaceDiagnosticReportResult> {
+        let previous_result_ids: HashMap<Url, String> = params.previous_result_ids.into_iter().map(|p| (p.uri, p.value)).collect();
+
+        // 只上报工作区中的文件, std 中的 module 不属于工作区
+        let mut items = Vec::new();
+        for entry in self.projects.iter() {
+            let project = entry.value();
+            let config = project.settings.lock().unwrap().diagnostic_config();
+            let documents = project.documents.lock().unwrap();
+            let module_db = project.module_db.lock().unwrap();
+
+            for m in module_db.iter().filter(|m| m.path.starts_with(&project.root)) {
+                let Ok(uri) = Url::from_file_path(&m.path) else {
+                    continue;
+                };
+                let version = documents.get(&m.path).map(|document| document.version as i64);
+                let previous_result_id = previous_result_ids.get(&uri).map(|id| id.as_str());
+                items.push(workspace_document_report(m, &config, uri, version, previous_result_id));
+            }
         }
-        Ok(None)
+
+        // 客户端支持 partial result 时逐个文件发送, 最终的 response 为空
+        let Some(token) = params.partial_result_params.partial_result_token else {
+            return Ok(WorkspaceDiagnosticReportResult::Report(WorkspaceDiagnosticReport { items }));
+        };
+
+        for item in items {
+            let params = DiagnosticPartialResult {
+                token: token.clone(),
+                value: WorkspaceDiagnosticReportPartialResult { items: vec![item] },
+            };
+            self.client.send_notification::<DiagnosticPartialResultNotification>(params).await;
+        }
+        Ok(WorkspaceDiagnosticReportResult::Report(WorkspaceDiagnosticReport::default()))
     }
 
-    async fn semantic_tokens_range(&self, params: SemanticTokensRangeParams) -> Result<Option<SemanticTokensRangeResult>> {
+    async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+        // 索引过程中只返回已经完成 build 的 module
+        let mut symbols = Vec::new();
+        for entry in self.projects.iter() {
+            let project = entry.value();
+            let module_db = project.module_db.lock().unwrap();
+            for m in module_db.iter().filter(|m| m.path.starts_with(&project.root)) {
+                symbols.extend(workspace_symbols(m, &params.query));
+            }
+        }
+
+        Ok(Some(symbols))
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
         let file_path = params.text_document.uri.path();
-        let semantic_tokens = || -> Option<Vec<SemanticToken>> {
-            let Some(project) = self.get_file_project(&file_path) else {
-                unreachable!()
+
+        let edits = || -> Option<Vec<TextEdit>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
             };
 
-            // 直接从 module_handled 中获取
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+            if m.read_only {
+                return None;
+            }
+
+            let config = project.settings.lock().unwrap().format_config(params.options.tab_size as usize);
+
+            // 存在语法错误时不进行格式化
+            let formatted = format_source(&m.source, &config)?;
+            if formatted == m.source {
+                return Some(Vec::new());
+            }
+
+            let end = offset_to_position(m.rope.len_chars(), &m.line_index)?;
+            Some(vec![TextEdit {
+                range: Range {
+                    start: Position::new(0, 0),
+                    end,
+                },
+                new_text: formatted,
+            }])
+        }();
+        Ok(edits)
+    }
+
+    async fn selection_range(&self, params: SelectionRangeParams) -> Result<Option<Vec<SelectionRange>>> {
+        let file_path = params.text_document.uri.path();
+
+        let ranges = || -> Option<Vec<SelectionRange>> {
+            let project = self.get_file_project(&file_path)?;
+
             let module_index = {
                 let module_handled = project.module_handled.lock().unwrap();
                 module_handled.get(file_path)?.clone()
             };
 
-            let mut module_db = project.module_db.lock().unwrap();
-            let m = &mut module_db[module_index];
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
 
-            // 获取 semantic_token_map 中的 token
-            let im_complete_tokens = m.sem_token_db.clone();
-            let rope = m.rope.clone();
+            let offsets: Vec<usize> = params.positions.iter().map(|position| m.line_index.offset(*position)).collect();
+            Some(selection_ranges(m, &offsets))
+        }();
+        Ok(ranges)
+    }
 
-            let mut pre_line = 0;
-            let mut pre_start = 0;
-            let semantic_tokens = im_complete_tokens
-                .iter()
-                .filter_map(|token| {
-                    let line = rope.try_byte_to_line(token.start).ok()? as u32;
-                    let first = rope.try_line_to_char(line as usize).ok()? as u32;
-                    let start = rope.try_byte_to_char(token.start).ok()? as u32 - first;
-                    let ret = Some(SemanticToken {
-                        delta_line: line - pre_line,
-                        delta_start: if start >= pre_start { start - pre_start } else { start },
-                        length: token.length as u32,
-                        token_type: token.token_type.clone() as u32,
-                        token_modifiers_bitset: 0,
-                    });
-                    pre_line = line;
-                    pre_start = start;
-                    ret
-                })
-                .collect::<Vec<_>>();
-            Some(semantic_tokens)
+    async fn document_highlight(&self, params: DocumentHighlightParams) -> Result<Option<Vec<DocumentHighlight>>> {
+        let file_path = params.text_document_position_params.text_document.uri.path();
+        let position = params.text_document_position_params.position;
+
+        let highlights = || -> Option<Vec<DocumentHighlight>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let index = self.reference_index(m);
+
+            let offset = m.line_index.offset(position);
+            Some(document_highlights(&index, m, offset))
         }();
-        Ok(semantic_tokens.map(|data| SemanticTokensRangeResult::Tokens(SemanticTokens { result_id: None, data })))
+        Ok(highlights)
     }
 
-    async fn inlay_hint(&self, _params: tower_lsp::lsp_types::InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
-        // debug!("inlay hint");
-        // let uri = &params.text_document.uri;
-        // let mut hashmap = HashMap::new();
-        // if let Some(ast) = self.ast_map.get(uri.as_str()) {
-        //     ast.iter().for_each(|(func, _)| {
-        //         type_inference(&func.body, &mut hashmap);
-        //     });
-        // }
-
-        // let document = match self.document_map.get(uri.as_str()) {
-        //     Some(rope) => rope,
-        //     None => return Ok(None),
-        // };
-        // let inlay_hint_list = hashmap
-        //     .into_iter()
-        //     .map(|(k, v)| {
-        //         (
-        //             k.start,
-        //             k.end,
-        //             match v {
-        //                 nls::nrs_lang::Value::Null => "null".to_string(),
-        //                 nls::nrs_lang::Value::Bool(_) => "bool".to_string(),
-        //                 nls::nrs_lang::Value::Num(_) => "number".to_string(),
-        //                 nls::nrs_lang::Value::Str(_) => "string".to_string(),
-        //             },
-        //         )
-        //     })
-        //     .filter_map(|item| {
-        //         // let start_position = offset_to_position(item.0, document)?;
-        //         let end_position = offset_to_position(item.1, &document)?;
-        //         let inlay_hint = InlayHint {
-        //             text_edits: None,
-        //             tooltip: None,
-        //             kind: Some(InlayHintKind::TYPE),
-        //             padding_left: None,
-        //             padding_right: None,
-        //             data: None,
-        //             position: end_position,
-        //             label: InlayHintLabel::LabelParts(vec![InlayHintLabelPart {
-        //                 value: item.2,
-        //                 tooltip: None,
-        //                 location: Some(Location {
-        //                     uri: params.text_document.uri.clone(),
-        //                     range: Range {
-        //                         start: Position::new(0, 4),
-        //                         end: Position::new(0, 10),
-        //                     },
-        //                 }),
-        //                 command: None,
-        //             }]),
-        //         };
-        //         Some(inlay_hint)
-        //     })
-        //     .collect::<Vec<_>>();
-
-        // Ok(Some(inlay_hint_list))
-        Ok(None)
+    async fn linked_editing_range(&self, params: LinkedEditingRangeParams) -> Result<Option<LinkedEditingRanges>> {
+        let file_path = params.text_document_position_params.text_document.uri.path();
+        let position = params.text_document_position_params.position;
+
+        let ranges = || -> Option<LinkedEditingRanges> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let index = self.reference_index(m);
+
+            let offset = m.line_index.offset(position);
+            linked_editing_ranges(&index, m, offset)
+        }();
+        Ok(ranges)
     }
 
-    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let _uri = params.text_document_position.text_document.uri;
-        let _position = params.text_document_position.position;
-        let completions = || -> Option<Vec<CompletionItem>> {
-            // let rope = self.document_map.get(&uri.to_string())?;
-            // let ast = self.ast_map.get(&uri.to_string())?;
-            // let char = rope.try_line_to_char(position.line as usize).ok()?;
-            // let offset = char + position.character as usize;
-            // let completions = completion(&ast, offset);
-            // let mut ret = Vec::with_capacity(completions.len());
-            // for (_, item) in completions {
-            //     match item {
-            //         nls::completion::ImCompleteCompletionItem::Variable(var) => {
-            //             ret.push(CompletionItem {
-            //                 label: var.clone(),
-            //                 insert_text: Some(var.clone()),
-            //                 kind: Some(CompletionItemKind::VARIABLE),
-            //                 detail: Some(var),
-            //                 ..Default::default()
-            //             });
-            //         }
-            //         nls::completion::ImCompleteCompletionItem::Function(name, args) => {
-            //             ret.push(CompletionItem {
-            //                 label: name.clone(),
-            //                 kind: Some(CompletionItemKind::FUNCTION),
-            //                 detail: Some(name.clone()),
-            //                 insert_text: Some(format!(
-            //                     "{}({})",
-            //                     name,
-            //                     args.iter()
-            //                         .enumerate()
-            //                         .map(|(index, item)| { format!("${{{}:{}}}", index + 1, item) })
-            //                         .collect::<Vec<_>>()
-            //                         .join(",")
-            //                 )),
-            //                 insert_text_format: Some(InsertTextFormat::SNIPPET),
-            //                 ..Default::default()
-            //             });
-            //         }
-            //     }
-            // }
-            // Some(ret)
-            None
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let file_path = params.text_document.uri.path();
+
+        let lenses = || -> Option<Vec<CodeLens>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let config = project.settings.lock().unwrap().code_lens_config();
+            Some(code_lenses(&module_db[module_index], &config))
         }();
-        Ok(completions.map(CompletionResponse::Array))
+        Ok(lenses)
+    }
+
+    async fn code_lens_resolve(&self, lens: CodeLens) -> Result<CodeLens> {
+        let Some((path, offset)) = lens_target(&lens) else {
+            return Ok(lens);
+        };
+
+        let count = || -> Option<usize> {
+            let project = self.get_file_project(&path)?;
+            let module_db = project.module_db.lock().unwrap();
+            count_references(&module_db, &|m| self.reference_index(m), &path, offset)
+        }();
+
+        // 声明已经不存在(例如文件在 resolve 之前被修改), 保持 unresolved
+        match count {
+            Some(count) => Ok(resolve_reference_lens(lens, count)),
+            None => Ok(lens),
+        }
+    }
+
+    async fn prepare_type_hierarchy(&self, params: TypeHierarchyPrepareParams) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let file_path = params.text_document_position_params.text_document.uri.path();
+        let position = params.text_document_position_params.position;
+
+        let items = || -> Option<Vec<TypeHierarchyItem>> {
+            let project = self.get_file_project(&file_path)?;
+
+            let module_index = {
+                let module_handled = project.module_handled.lock().unwrap();
+                module_handled.get(file_path)?.clone()
+            };
+
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
+
+            let offset = m.line_index.offset(position);
+            let item = TypeHierarchyIndex::build(&module_db).prepare(m, offset)?;
+            Some(vec![item])
+        }();
+        Ok(items)
+    }
+
+    async fn supertypes(&self, params: TypeHierarchySupertypesParams) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let project = self.get_file_project(params.item.uri.path());
+        let items = project.map(|project| {
+            let module_db = project.module_db.lock().unwrap();
+            TypeHierarchyIndex::build(&module_db).supertypes(&params.item)
+        });
+        Ok(items)
+    }
+
+    async fn subtypes(&self, params: TypeHierarchySubtypesParams) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let project = self.get_file_project(params.item.uri.path());
+        let items = project.map(|project| {
+            let module_db = project.module_db.lock().unwrap();
+            TypeHierarchyIndex::build(&module_db).subtypes(&params.item)
+        });
+        Ok(items)
     }
 
     async fn rename(&self, _params: RenameParams) -> Result<Option<WorkspaceEdit>> {
@@ -462,8 +905,64 @@ impl LanguageServer for Backend {
         Ok(workspace_edit)
     }
 
-    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
+    async fn will_rename_files(&self, params: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+        let renames: Vec<(String, String)> = params
+            .files
+            .iter()
+            .filter_map(|file| {
+                let old_uri = Url::parse(&file.old_uri).ok()?;
+                let new_uri = Url::parse(&file.new_uri).ok()?;
+                Some((old_uri.path().to_string(), new_uri.path().to_string()))
+            })
+            .collect();
+
+        let mut changes = HashMap::new();
+        for entry in self.projects.iter() {
+            let module_db = entry.value().module_db.lock().unwrap();
+            changes.extend(import_rename_edits(&module_db, &renames));
+        }
+
+        Ok((!changes.is_empty()).then(|| WorkspaceEdit::new(changes)))
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         debug!("configuration changed!");
+
+        // 客户端不支持 workspace/configuration 时使用通知中携带的配置
+        let settings = match self.fetch_settings().await {
+            Some(settings) => settings,
+            None => Settings::from_value(params.settings.get(SETTINGS_SECTION).unwrap_or(&params.settings)),
+        };
+        *self.settings.lock().unwrap() = settings;
+
+        // import 查找目录与 lint 开关都会影响诊断, 重新分析所有打开的文件
+        let opened: Vec<(String, String, i32)> = self
+            .projects
+            .iter()
+            .flat_map(|entry| {
+                let documents = entry.value().documents.lock().unwrap();
+                documents
+                    .iter()
+                    .map(|(path, document)| (path.clone(), document.text().to_string(), document.version))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        for (path, text, version) in opened {
+            let Ok(uri) = Url::from_file_path(&path) else {
+                continue;
+            };
+            self.on_change(TextDocumentItem {
+                uri,
+                text: &text,
+                version: Some(version),
+            })
+            .await;
+        }
+
+        let _ = self.client.inlay_hint_refresh().await;
+        let _ = self.client.code_lens_refresh().await;
+        let _ = self.client.workspace_diagnostic_refresh().await;
     }
 
     async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
@@ -471,8 +970,36 @@ impl LanguageServer for Backend {
     }
 
     async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
-        dbg!(params);
         debug!("watched files have changed!");
+
+        for change in params.changes {
+            let file_path = change.uri.path();
+            let Some(mut project) = self.get_file_project(file_path) else {
+                continue;
+            };
+
+            let _running = self.scheduler.exclusive().await;
+            let affected = if file_path.ends_with("package.toml") {
+                let rebuilt = project.reload_package_config().await;
+                if let Some(mut entry) = self.projects.get_mut(&project.root) {
+                    entry.package_config = project.package_config.clone();
+                }
+                rebuilt
+            } else {
+                let deleted = change.typ == FileChangeType::DELETED;
+                if deleted {
+                    self.semantic_tokens_cache.remove(file_path);
+                    self.client.publish_diagnostics(change.uri.clone(), Vec::new(), None).await;
+                }
+                project.apply_file_change(file_path, deleted).await
+            };
+
+            // symbol id 可能发生变化
+            self.reference_index_cache.clear();
+            self.publish_module_diagnostics(&project, &affected).await;
+        }
+
+        let _ = self.client.workspace_diagnostic_refresh().await;
     }
 
     async fn execute_command(&self, _: ExecuteCommandParams) -> Result<Option<Value>> {
@@ -498,6 +1025,19 @@ impl Notification for CustomNotification {
     type Params = InlayHintParams;
     const METHOD: &'static str = "custom/notification";
 }
+#[derive(Debug, Deserialize, Serialize)]
+struct DiagnosticPartialResult {
+    token: ProgressToken,
+    value: WorkspaceDiagnosticReportPartialResult,
+}
+
+// workspace/diagnostic 的 partial result 通过 $/progress 发送
+enum DiagnosticPartialResultNotification {}
+impl Notification for DiagnosticPartialResultNotification {
+    type Params = DiagnosticPartialResult;
+    const METHOD: &'static str = "$/progress";
+}
+
 struct TextDocumentItem<'a> {
     uri: Url,
     text: &'a str, // 'a 是声明周期引用，表示 str 的生命周期与 TextDocumentItem 的生命周期一致, 而不是 } 后就结束
@@ -518,6 +1058,206 @@ impl Backend {
         None
     }
 
+    fn reference_index(&self, m: &Module) -> Arc<ReferenceIndex> {
+        self.reference_index_cache
+            .entry(m.path.clone())
+            .or_insert_with(|| Arc::new(ReferenceIndex::build(m)))
+            .clone()
+    }
+
+    fn module_semantic_tokens(&self, file_path: &str) -> Option<Vec<SemanticToken>> {
+        let project = self.get_file_project(file_path)?;
+
+        // 直接从 module_handled 中获取
+        let module_index = {
+            let module_handled = project.module_handled.lock().unwrap();
+            module_handled.get(file_path)?.clone()
+        };
+
+        let module_db = project.module_db.lock().unwrap();
+        let m = &module_db[module_index];
+        Some(encode_semantic_tokens(&m.sem_token_db, &m.line_index))
+    }
+
+    fn cache_semantic_tokens(&self, file_path: &str, tokens: Vec<SemanticToken>) -> String {
+        let result_id = self.semantic_tokens_result_id.fetch_add(1, Ordering::Relaxed).to_string();
+        self.semantic_tokens_cache.insert(file_path.to_string(), (result_id.clone(), tokens));
+        result_id
+    }
+
+    async fn send_progress(&self, token: &ProgressToken, progress: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(progress),
+        };
+        self.client.send_notification::<notification::Progress>(params).await;
+    }
+
+    async fn fetch_settings(&self) -> Option<Settings> {
+        let items = vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some(SETTINGS_SECTION.to_string()),
+        }];
+        let values = self.client.configuration(items).await.ok()?;
+        Some(Settings::from_value(values.first()?))
+    }
+
+    /**
+     * 文件保存之后在 worker 线程中重新分析 import 了该文件的 module, 并发布这些文件(通常没有在编辑器中聚焦)的诊断
+     */
+    async fn refresh_dependents(&self, file_path: &str) {
+        let Some(project) = self.get_file_project(file_path) else {
+            return;
+        };
+        let version = project
+            .documents
+            .lock()
+            .unwrap()
+            .get(file_path)
+            .map(|document| document.version)
+            .unwrap_or_default();
+
+        // 使用单独的 key 调度, 不会取消当前文件自身的分析任务, 再次保存时取消上一次尚未完成的 fan-out
+        let key = format!("{}#dependents", file_path);
+        let path = file_path.to_string();
+        let mut worker_project = project.clone();
+        let affected = self
+            .scheduler
+            .run(&key, version, move |cancel| {
+                tokio::runtime::Handle::current().block_on(worker_project.rebuild_dependents(&path, &cancel))
+            })
+            .await;
+
+        let Some(affected) = affected.filter(|affected| !affected.is_empty()) else {
+            return;
+        };
+
+        self.reference_index_cache.clear();
+        self.publish_module_diagnostics(&project, &affected).await;
+        let _ = self.client.workspace_diagnostic_refresh().await;
+    }
+
+    async fn publish_module_diagnostics(&self, project: &Project, paths: &[String]) {
+        let config = project.settings.lock().unwrap().diagnostic_config();
+        let published: Vec<(Url, Vec<Diagnostic>)> = {
+            let module_handled = project.module_handled.lock().unwrap();
+            let module_db = project.module_db.lock().unwrap();
+            paths
+                .iter()
+                .filter_map(|path| {
+                    let m = &module_db[*module_handled.get(path)?];
+                    Some((Url::from_file_path(path).ok()?, file_diagnostics(m, &config)))
+                })
+                .collect()
+        };
+
+        for (uri, diagnostics) in published {
+            self.client.publish_diagnostics(uri, diagnostics, None).await;
+        }
+    }
+
+    /**
+     * 在后台线程中 build 工作区中的所有文件, 通过 window/workDoneProgress 报告进度
+     *
+     * 索引期间的请求基于已经完成 build 的 module 响应, 不需要等待索引结束
+     */
+    async fn index_workspace(&self) {
+        let projects: Vec<Project> = self.projects.iter().map(|entry| entry.value().clone()).collect();
+
+        for project in projects {
+            let files = project.workspace_files();
+            let total = files.len();
+
+            // 客户端不支持 workDoneProgress 时依旧进行索引, 只是不报告进度
+            let token = ProgressToken::String(format!("nature/index:{}", project.root));
+            let progress = self
+                .client
+                .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams { token: token.clone() })
+                .await
+                .is_ok();
+            if progress {
+                let begin = WorkDoneProgressBegin {
+                    title: "Indexing".to_string(),
+                    cancellable: Some(false),
+                    message: Some(format!("0/{} files", total)),
+                    percentage: Some(0),
+                };
+                self.send_progress(&token, WorkDoneProgress::Begin(begin)).await;
+            }
+
+            // 索引期间 didChange 的分析任务等待索引结束之后再 build
+            let running = self.scheduler.exclusive().await;
+            let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+            let mut worker_project = project.clone();
+            let worker = tokio::task::spawn_blocking(move || {
+                let on_progress = |done: usize| {
+                    let _ = sender.send(done);
+                };
+                tokio::runtime::Handle::current().block_on(worker_project.index_workspace(&files, on_progress))
+            });
+
+            while let Some(done) = receiver.recv().await {
+                if progress {
+                    let report = WorkDoneProgressReport {
+                        cancellable: Some(false),
+                        message: Some(format!("{}/{} files", done, total)),
+                        percentage: Some((done * 100 / total.max(1)) as u32),
+                    };
+                    self.send_progress(&token, WorkDoneProgress::Report(report)).await;
+                }
+            }
+            let failures = worker.await.unwrap_or_default();
+            drop(running);
+
+            if progress {
+                let end = WorkDoneProgressEnd {
+                    message: Some(format!("indexed {} files", total)),
+                };
+                self.send_progress(&token, WorkDoneProgress::End(end)).await;
+            }
+
+            // symbol id 可能发生变化
+            self.reference_index_cache.clear();
+
+            let mut published: Vec<(Url, Vec<Diagnostic>)> = {
+                let config = project.settings.lock().unwrap().diagnostic_config();
+                let module_db = project.module_db.lock().unwrap();
+                module_db
+                    .iter()
+                    .filter(|m| m.path.starts_with(&project.root))
+                    .filter_map(|m| {
+                        let diagnostics = file_diagnostics(m, &config);
+                        if diagnostics.is_empty() {
+                            return None;
+                        }
+                        Some((Url::from_file_path(&m.path).ok()?, diagnostics))
+                    })
+                    .collect()
+            };
+
+            // 读取失败的文件只有一个 placeholder module, 错误位于文件开头
+            for (path, message) in failures {
+                let Ok(uri) = Url::from_file_path(&path) else {
+                    continue;
+                };
+                let diagnostic = Diagnostic {
+                    range: Range::default(),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some(DIAGNOSTIC_SOURCE.to_string()),
+                    message,
+                    ..Default::default()
+                };
+                published.push((uri, vec![diagnostic]));
+            }
+
+            for (uri, diagnostics) in published {
+                self.client.publish_diagnostics(uri, diagnostics, None).await;
+            }
+        }
+
+        let _ = self.client.workspace_diagnostic_refresh().await;
+    }
+
     async fn on_change<'a>(&self, params: TextDocumentItem<'a>) {
         dbg!(&params.uri);
         debug!(
@@ -539,11 +1279,13 @@ impl Backend {
                     project.package_config = Some(Arc::new(Mutex::new(package_conf)));
                 }
                 Err(e) => {
-                    // 创建 rope 用于将偏移量转换为位置
+                    // toml 错误中的 span 为 byte offset
                     let rope = ropey::Rope::from_str(params.text);
-                    let start_position = offset_to_position(e.start, &rope).unwrap_or(Position::new(0, 0));
+                    let line_index = LineIndex::from_rope(rope.clone(), project.position_encoding);
+                    let to_position = |offset: usize| rope.try_byte_to_char(offset).ok().and_then(|offset| offset_to_position(offset, &line_index));
+                    let start_position = to_position(e.span.start as usize).unwrap_or(Position::new(0, 0));
 
-                    let end_position = offset_to_position(e.end, &rope).unwrap_or(Position::new(0, 0));
+                    let end_position = to_position(e.span.end as usize).unwrap_or(Position::new(0, 0));
 
                     let diagnostic = Diagnostic::new_simple(Range::new(start_position, end_position), format!("parser package.toml failed: {}", e.message));
                     self.client.publish_diagnostics(params.uri.clone(), vec![diagnostic], params.version).await;
@@ -554,30 +1296,43 @@ impl Backend {
 
         let module_ident = module_unique_ident(&project.root, &file_path);
         debug!("will build, module ident: {}", module_ident);
-        //  基于 project path 计算 moudle ident
-        let module_index = project.build(&file_path, &module_ident).await;
-        debug!("build success");
 
-        let diagnostics = {
-            let mut module_db = project.module_db.lock().unwrap();
-            let m = &mut module_db[module_index];
+        // didSave 不携带 version, 使用编辑器中打开的文件的最新 version
+        let version = params
+            .version
+            .or_else(|| project.documents.lock().unwrap().get(file_path).map(|document| document.version))
+            .unwrap_or_default();
+
+        //  基于 project path 计算 moudle ident
+        let path = file_path.to_string();
+        let config = project.settings.lock().unwrap().diagnostic_config();
+        let analysis = self.scheduler.run(file_path, version, move |cancel| {
+            let module_index = tokio::runtime::Handle::current().block_on(project.build_cancellable(&path, &module_ident, &cancel))?;
 
+            let module_db = project.module_db.lock().unwrap();
+            let m = &module_db[module_index];
             dbg!(&m.ident, &m.analyzer_errors);
 
-            m.analyzer_errors
-                .clone()
-                .into_iter()
-                .filter(|error|  error.end > 0)  // 过滤掉 start = end = 0 的错误
-                .filter_map(|error| {
-                    let start_position = offset_to_position(error.start, &m.rope)?;
-                    let end_position = offset_to_position(error.end, &m.rope)?;
-                    Some(Diagnostic::new_simple(Range::new(start_position, end_position), error.message))
-                })
-                .collect::<Vec<_>>()
-        }; // MutexGuard 在这里被释放
+            Some(file_diagnostics(m, &config))
+        });
+
+        // 被更新的版本取代时不发布任何结果
+        let Some(diagnostics) = analysis.await else {
+            debug!("analysis of {} version {} is stale, discard", file_path, version);
+            return;
+        };
+        debug!("build success");
+
+        // 依赖当前文件的 module 也会被重新分析, symbol id 可能发生变化, 所有 reference index 都需要失效
+        self.reference_index_cache.clear();
 
         // 现在可以安全地使用 await
         self.client.publish_diagnostics(params.uri.clone(), diagnostics, params.version).await;
+
+        // 引用数量可能发生变化, 客户端不支持 refresh 时忽略错误
+        let _ = self.client.code_lens_refresh().await;
+        // 依赖当前文件的其他文件的诊断可能发生变化, 通知支持 pull diagnostics 的客户端重新拉取
+        let _ = self.client.workspace_diagnostic_refresh().await;
     }
 }
 
@@ -585,6 +1340,12 @@ impl Backend {
 async fn main() {
     env_logger::init();
 
+    // nls check <path>, 不启动 lsp server, 直接输出诊断
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "check") {
+        std::process::exit(nls::check::run(&args[1..]).await);
+    }
+
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
@@ -592,6 +1353,11 @@ async fn main() {
         client,
         // document_map: DashMap::new(),
         projects: DashMap::new(),
+        semantic_tokens_cache: DashMap::new(),
+        semantic_tokens_result_id: AtomicUsize::new(0),
+        reference_index_cache: DashMap::new(),
+        scheduler: AnalysisScheduler::default(),
+        settings: Arc::new(Mutex::new(Settings::default())),
     })
     .finish();
 
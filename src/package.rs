@@ -1,15 +1,11 @@
-use crate::analyzer::common::{AnalyzerError, PackageConfig};
-
+use crate::analyzer::common::{AnalyzerError, PackageConfig, Span};
+use std::path::Path;
 
 /**
  * 解析 toml 解析正确返回 package config, 如果解析错误则返回 AnalyzerError 错误信息
  */
 pub fn parse_package(path: &str) -> Result<PackageConfig, AnalyzerError> {
-    let content = std::fs::read_to_string(path).map_err(|e| AnalyzerError {
-        start: 0,
-        end: 0,
-        message: e.to_string(),
-    })?;
+    let content = std::fs::read_to_string(path).map_err(|e| AnalyzerError::new(Span::new(0, 0), e.to_string()))?;
 
     match toml::from_str(&content) {
         Ok(package) => {
@@ -17,7 +13,7 @@ pub fn parse_package(path: &str) -> Result<PackageConfig, AnalyzerError> {
                 path: path.to_string(),
                 package_data: package,
             };
-            
+
             Ok(package_config)
         }
         Err(e) => {
@@ -27,14 +23,33 @@ pub fn parse_package(path: &str) -> Result<PackageConfig, AnalyzerError> {
 
             // let diagnostic = Diagnostic::new_simple(Range::new(start_position, end_position), e.message().to_string());
             // self.client
-                // .publish_diagnostics(Url::parse(&format!("file://{}", path)).unwrap(), vec![diagnostic], None)
-                // .await;
+            // .publish_diagnostics(Url::parse(&format!("file://{}", path)).unwrap(), vec![diagnostic], None)
+            // .await;
+
+            Err(AnalyzerError::new(Span::new(span.start, span.end), e.message().to_string()))
+        }
+    }
+}
+/**
+ * 从文件所在目录向上查找最近的 package.toml, 位于 stop_dir 中的文件最多查找到 stop_dir, 避免使用工作区之外的 package.toml
+ */
+pub fn find_package_toml(file_path: &str, stop_dir: &str) -> Option<String> {
+    // 内置的 builtin 等虚拟路径不属于任何 package
+    if !Path::new(file_path).is_absolute() {
+        return None;
+    }
 
-            Err(AnalyzerError {
-                start: span.start,
-                end: span.end,
-                message: e.message().to_string(),
-            })
+    let stop_dir = Path::new(stop_dir);
+    let within = Path::new(file_path).starts_with(stop_dir);
+
+    for dir in Path::new(file_path).ancestors().skip(1) {
+        let package_path = dir.join("package.toml");
+        if package_path.is_file() {
+            return Some(package_path.to_string_lossy().into_owned());
+        }
+        if within && dir == stop_dir {
+            break;
         }
     }
-}
\ No newline at end of file
+    None
+}
@@ -1,20 +1,30 @@
-use crate::analyzer::common::{AnalyzerError, AstFnDef, AstNode, ImportStmt, PackageConfig, Stmt};
+use crate::analyzer::common::{AnalyzerError, AstFnDef, AstNode, ImportStmt, PackageConfig, Span, Stmt};
 use crate::analyzer::lexer::{Lexer, Token};
 use crate::analyzer::semantic::Semantic;
 use crate::analyzer::symbol::SymbolTable;
-use crate::analyzer::syntax::Syntax;
+use crate::analyzer::syntax::{ExpectedTokens, ParserConfig, Syntax};
 use crate::analyzer::typesys::Typesys;
-use crate::analyzer::{analyze_imports, register_global_symbol};
-use crate::package::parse_package;
+use crate::analyzer::{analyze_imports, module_unique_ident, register_global_symbol};
+use crate::document::{Document, LineIndex, PositionEncoding};
+use crate::package::{find_package_toml, parse_package};
+use crate::scheduler::CancellationToken;
+use crate::settings::Settings;
 use ropey::Rope;
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
 // TODO
 // const DEFAULT_NATURE_ROOT: &str = "/usr/local/nature";
 pub const DEFAULT_NATURE_ROOT: &str = "/Users/weiwenhao/Code/nature";
 
+// 找不到 std/builtin 时使用内置的 builtin 签名, 保证 println 等常用的 builtin 依旧能够被解析
+pub const FALLBACK_BUILTIN_PATH: &str = "<builtin>/builtin.n";
+pub const FALLBACK_BUILTIN_SOURCE: &str = include_str!("fallback_builtin.n");
+
+// 保存文件之后重新分析的反向依赖层数, 避免保存底层 module 时同步地重新分析整个 workspace
+pub const DEPENDENT_REBUILD_DEPTH: usize = 2;
+
 // 单个文件称为 module, package 通常是包含 package.toml 的多级目录
 #[derive(Debug, Clone)]
 pub struct Module {
@@ -22,19 +32,24 @@ pub struct Module {
     pub ident: String,
     pub source: String, //  源码内容
     pub rope: Rope,
-    pub path: String, // 文件 路径
-    pub dir: String,  //  文件 所在目录
+    pub line_index: LineIndex, // offset 与 lsp position 之间的转换
+    pub path: String,          // 文件 路径
+    pub dir: String,           //  文件 所在目录
     pub token_db: Vec<Token>,
     pub token_indexes: Vec<usize>,
     pub sem_token_db: Vec<Token>,
     pub stmts: Vec<Box<Stmt>>,
     pub global_vardefs: Vec<AstNode>,
-    pub global_fndefs: Vec<Arc<Mutex<AstFnDef>>>,
-    pub all_fndefs: Vec<Arc<Mutex<AstFnDef>>>, // 包含 global 和 local fn def
+    pub global_fndefs: Vec<Arc<RwLock<AstFnDef>>>,
+    pub all_fndefs: Vec<Arc<RwLock<AstFnDef>>>, // 包含 global 和 local fn def
     pub analyzer_errors: Vec<AnalyzerError>,
+    pub expected_tokens: Vec<ExpectedTokens>, // 语法错误对应的期望 token, 用于生成 quickfix
+
+    pub references: Vec<usize>,                            // 哪些模块依赖于当前模块
+    pub dependencies: Vec<ImportStmt>,                     // 当前模块依赖 哪些模块
+    pub package_config: Option<Arc<Mutex<PackageConfig>>>, // module 所属 package 的配置, 来自最近的 package.toml
 
-    pub references: Vec<usize>,        // 哪些模块依赖于当前模块
-    pub dependencies: Vec<ImportStmt>, // 当前模块依赖 哪些模块
+    pub read_only: bool, // nature root 中的 std 与内置的 builtin, 不进行格式化与 lint
 }
 
 impl Module {
@@ -44,6 +59,7 @@ impl Module {
         let dir = Path::new(&path).parent().and_then(|p| p.to_str()).unwrap_or("").to_string();
 
         let rope = ropey::Rope::from_str(&source);
+        let line_index = LineIndex::from_rope(rope.clone(), PositionEncoding::default());
 
         Self {
             index,
@@ -52,6 +68,7 @@ impl Module {
             path,
             dir,
             rope,
+            line_index,
             token_db: Vec::new(),
             token_indexes: Vec::new(),
             sem_token_db: Vec::new(),
@@ -60,8 +77,11 @@ impl Module {
             global_fndefs: Vec::new(),
             all_fndefs: Vec::new(),
             analyzer_errors: Vec::new(),
+            expected_tokens: Vec::new(),
             references: Vec::new(),
             dependencies: Vec::new(),
+            package_config: None,
+            read_only: false,
         }
     }
 
@@ -93,12 +113,15 @@ impl Default for Module {
             global_fndefs: Vec::new(),
             all_fndefs: Vec::new(),
             analyzer_errors: Vec::new(),
+            expected_tokens: Vec::new(),
             rope: Rope::default(),
+            line_index: LineIndex::default(),
+            package_config: None,
+            read_only: false,
         }
     }
 }
 
-
 #[derive(Debug, Clone)]
 pub struct QueueItem {
     pub path: String,
@@ -109,18 +132,27 @@ pub struct QueueItem {
 pub struct Project {
     pub nature_root: String,
     pub root: String,
-    pub module_db: Arc<Mutex<Vec<Module>>>,                             // key = uri, 记录所有已经编译的 module
+    pub module_db: Arc<Mutex<Vec<Module>>>,                 // key = uri, 记录所有已经编译的 module
     pub module_handled: Arc<Mutex<HashMap<String, usize>>>, // key = path, 记录所有已经编译的 module, usize 指向 module db
     // queue 中的每一个 module 都可以视为 main.n 来编译，主要是由于用户打开文件 A import B or C 产生的 B 和 C 注册到 queue 中进行处理
     pub queue: Arc<Mutex<Vec<QueueItem>>>,
     pub package_config: Option<Arc<Mutex<PackageConfig>>>, // 当前 project 如果包含 package.toml, 则可以解析出 package_config 等信息，import 需要借助该信息进行解析
     pub symbol_table: Arc<Mutex<SymbolTable>>,
+    pub documents: Arc<Mutex<HashMap<String, Document>>>, // key = path, 编辑器中打开的文件, 编译时优先于磁盘中的内容
+    pub position_encoding: PositionEncoding,              // initialize 时与客户端协商的 position encoding
+    pub settings: Arc<Mutex<Settings>>,                   // 与 backend 共享, didChangeConfiguration 时更新
 }
 
 impl Project {
-    pub async fn new(project_root: String) -> Self {
-        // 1. check nature root by env
-        let nature_root = std::env::var("NATURE_ROOT").unwrap_or(DEFAULT_NATURE_ROOT.to_string());
+    pub async fn new(project_root: String, position_encoding: PositionEncoding, settings: Arc<Mutex<Settings>>) -> Self {
+        // 1. nature root: settings 中配置的路径 > NATURE_ROOT 环境变量 > 默认路径
+        let nature_root = settings
+            .lock()
+            .unwrap()
+            .nature_root
+            .clone()
+            .or_else(|| std::env::var("NATURE_ROOT").ok())
+            .unwrap_or(DEFAULT_NATURE_ROOT.to_string());
 
         let mut builtin_list: Vec<String> = Vec::new();
 
@@ -129,7 +161,7 @@ impl Project {
         let std_builtin_dir = std_dir.join("builtin");
 
         // 加载 builtin 中的所有文件(.n 结尾)
-        let dirs = std::fs::read_dir(std_builtin_dir).unwrap();
+        let dirs = std::fs::read_dir(std_builtin_dir).into_iter().flatten();
         for dir in dirs {
             let dir_path = dir.unwrap().path();
             if !dir_path.is_file() {
@@ -147,14 +179,14 @@ impl Project {
             builtin_list.push(file_path.to_string());
         }
 
+        if builtin_list.is_empty() {
+            builtin_list.push(FALLBACK_BUILTIN_PATH.to_string());
+        }
+
         let package_path = Path::new(&project_root).join("package.toml").to_str().unwrap().to_string();
         let package_config = match parse_package(&package_path) {
-            Ok(package_config) => {
-                Some(Arc::new(Mutex::new(package_config)))
-            }
-            Err(_e) => {
-                None
-            }
+            Ok(package_config) => Some(Arc::new(Mutex::new(package_config))),
+            Err(_e) => None,
         };
 
         let mut project = Self {
@@ -165,6 +197,9 @@ impl Project {
             queue: Arc::new(Mutex::new(Vec::new())),
             package_config,
             symbol_table: Arc::new(Mutex::new(SymbolTable::new())),
+            documents: Arc::new(Mutex::new(HashMap::new())),
+            position_encoding,
+            settings,
         };
 
         // handle builtin list
@@ -175,6 +210,7 @@ impl Project {
         return project;
     }
 
+    #[cfg(feature = "lsp")]
     pub fn backend_handle_queue(&self) {
         let mut self_clone = self.clone();
         tokio::spawn(async move {
@@ -194,6 +230,7 @@ impl Project {
         }
     }
 
+    #[cfg(feature = "lsp")]
     pub async fn handle_queue(&mut self) {
         loop {
             // 尝试从 await_queue 中获取一个 file, 当前语句结束之后，await_queue 会自动解锁
@@ -214,6 +251,73 @@ impl Project {
         }
     }
 
+    /**
+     * 打开的文件使用编辑器中的内容, 其余文件从磁盘读取
+     */
+    fn read_source(&self, path: &str) -> std::io::Result<String> {
+        if path == FALLBACK_BUILTIN_PATH {
+            return Ok(FALLBACK_BUILTIN_SOURCE.to_string());
+        }
+        if let Some(document) = self.documents.lock().unwrap().get(path) {
+            return Ok(document.text().to_string());
+        }
+        std::fs::read_to_string(path)
+    }
+
+    /**
+     * root 下所有的 .n 文件, 忽略 . 开头的隐藏目录
+     */
+    pub fn workspace_files(&self) -> Vec<String> {
+        let mut files = Vec::new();
+        let mut dirs = vec![Path::new(&self.root).to_path_buf()];
+
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let hidden = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+                if path.is_dir() {
+                    if !hidden {
+                        dirs.push(path);
+                    }
+                } else if path.extension().is_some_and(|ext| ext == "n") {
+                    files.push(path.to_string_lossy().to_string());
+                }
+            }
+        }
+
+        files.sort();
+        files
+    }
+
+    /**
+     * 依次 build files 中尚未被 build 的文件, 每处理一个文件调用一次 on_progress(已处理的文件数量)
+     *
+     * 读取失败的文件不会中断索引, 而是注册一个空的 placeholder module, 返回值中记录了这些文件的 (path, 失败原因)
+     */
+    pub async fn index_workspace(&mut self, files: &[String], mut on_progress: impl FnMut(usize)) -> Vec<(String, String)> {
+        let mut failures = Vec::new();
+
+        for (i, path) in files.iter().enumerate() {
+            // 已经作为其他文件的 import 被 build
+            let handled = self.module_handled.lock().unwrap().contains_key(path);
+            if !handled {
+                if let Err(e) = self.read_source(path) {
+                    failures.push((path.clone(), format!("read file failed: {}", e)));
+                }
+
+                let module_ident = module_unique_ident(&self.root, path);
+                self.build(path, &module_ident).await;
+            }
+
+            on_progress(i + 1);
+        }
+
+        failures
+    }
+
     /**
      * 当前 module 更新后，需要更新所有依赖了当前 module 的 module
      */
@@ -234,12 +338,12 @@ impl Project {
 
             let module_db = self.module_db.lock().unwrap();
             let current_module = &module_db[current_index];
-            
+
             // 遍历所有引用当前模块的模块
             for &ref_index in &current_module.references {
                 let ref_module = &module_db[ref_index];
                 result.push(ref_module.path.clone());
-                
+
                 // 将引用模块加入工作列表，以便递归查找
                 worklist.push(ref_index);
             }
@@ -248,7 +352,209 @@ impl Project {
         result
     }
 
+    /**
+     * 直接或者间接 import 了 path 的 module, path 对应的文件不存在(import 失败)时同样可以通过 import 的 full_path 找到
+     */
+    pub fn dependents(&self, path: &str) -> Vec<String> {
+        let direct: Vec<(usize, String)> = {
+            let module_db = self.module_db.lock().unwrap();
+            module_db
+                .iter()
+                .filter(|m| m.path != path && m.dependencies.iter().any(|dep| dep.full_path == path))
+                .map(|m| (m.index, m.path.clone()))
+                .collect()
+        };
+
+        let mut result: Vec<String> = Vec::new();
+        for (index, dependent) in direct {
+            let mut paths = vec![dependent];
+            paths.extend(self.all_references(index));
+
+            for p in paths {
+                if p != path && !result.contains(&p) {
+                    result.push(p);
+                }
+            }
+        }
+
+        result
+    }
+
+    /**
+     * import 了 path 的 module, 只向上追溯 max_depth 层(1 表示只包含直接 import 的 module)
+     *
+     * 被 import 的 module 在 importer 之后才注册到 module db, references 可能并不完整, 因此与 dependents 一样基于 import 的 full_path 查找
+     */
+    pub fn dependents_within(&self, path: &str, max_depth: usize) -> Vec<String> {
+        let module_db = self.module_db.lock().unwrap();
+
+        let mut result: Vec<String> = Vec::new();
+        let mut current = vec![path.to_string()];
+        for _ in 0..max_depth {
+            let mut next = Vec::new();
+            for m in module_db.iter() {
+                if m.path == path || result.contains(&m.path) {
+                    continue;
+                }
+                if m.dependencies.iter().any(|dep| current.contains(&dep.full_path)) {
+                    result.push(m.path.clone());
+                    next.push(m.path.clone());
+                }
+            }
+            current = next;
+        }
+
+        result
+    }
+
+    /**
+     * path 保存之后重新分析依赖它的 module, 使得跨 module 的诊断(例如 import 的 fn 被删除)在未编辑的文件中同样生效,
+     * 返回重新 build 的文件, 被取消时返回 None
+     */
+    pub async fn rebuild_dependents(&mut self, path: &str, cancel: &CancellationToken) -> Option<Vec<String>> {
+        let dependents: Vec<String> = self
+            .dependents_within(path, DEPENDENT_REBUILD_DEPTH)
+            .into_iter()
+            .filter(|p| self.is_buildable(p))
+            .collect();
+        for dependent in &dependents {
+            self.build_cancellable(dependent, "", cancel).await?;
+        }
+        Some(dependents)
+    }
+
+    /**
+     * 文件被删除, 其他 module 通过 index 引用 module db, 所以只清空 module 中的内容而不从 module db 中移除
+     */
+    pub fn remove_module(&mut self, path: &str) {
+        let mut module_handled = self.module_handled.lock().unwrap();
+        let Some(index) = module_handled.remove(path) else {
+            return;
+        };
+
+        let mut module_db = self.module_db.lock().unwrap();
+        self.symbol_table.lock().unwrap().remove_module_symbols(&module_db[index].ident);
+
+        // 依赖的 module 不再被当前 module 引用
+        let dependencies = std::mem::take(&mut module_db[index].dependencies);
+        for dep in dependencies {
+            if let Some(&dep_index) = module_handled.get(&dep.full_path) {
+                module_db[dep_index].references.retain(|&x| x != index);
+            }
+        }
+
+        module_db[index] = Module { index, ..Default::default() };
+    }
+
+    /**
+     * 处理 workspace/didChangeWatchedFiles 中的单个文件变化, 返回需要重新发布诊断的文件
+     *
+     * 编辑器中打开的文件以编辑器中的内容为准, 忽略磁盘上的变化. 重命名会拆分为 deleted + created 两个事件
+     */
+    pub async fn apply_file_change(&mut self, path: &str, deleted: bool) -> Vec<String> {
+        if self.documents.lock().unwrap().contains_key(path) {
+            return Vec::new();
+        }
+
+        // 在清空 module 之前收集依赖关系
+        let dependents = self.dependents(path);
+        let mut affected = Vec::new();
+
+        // created/changed 事件到达时文件可能已经被再次删除
+        if deleted || !Path::new(path).is_file() {
+            self.remove_module(path);
+        } else {
+            let module_ident = {
+                let module_handled = self.module_handled.lock().unwrap();
+                match module_handled.get(path) {
+                    Some(&index) => self.module_db.lock().unwrap()[index].ident.clone(),
+                    None => module_unique_ident(&self.root, path),
+                }
+            };
+
+            // 声明的位置可能发生了变化, 重新注册 global symbol
+            self.symbol_table.lock().unwrap().remove_module_symbols(&module_ident);
+            self.build(path, &module_ident).await;
+            affected.push(path.to_string());
+        }
+
+        // global symbol 重新注册之后 symbol id 发生了变化, 依赖当前文件的 module 都需要重新分析
+        for dependent in dependents {
+            if !self.is_buildable(&dependent) {
+                continue;
+            }
+            self.build(&dependent, "").await;
+            affected.push(dependent);
+        }
+
+        affected
+    }
+
+    /**
+     * 依赖关系中的文件可能已经被删除, 例如同时删除了 a.n 与 import 了 a.n 的 b.n, 先到达的 a.n 事件不能重新 build b.n,
+     * 这些文件等待自身的删除事件从 module db 中移除
+     */
+    fn is_buildable(&self, path: &str) -> bool {
+        if !self.module_handled.lock().unwrap().contains_key(path) {
+            return false;
+        }
+        self.documents.lock().unwrap().contains_key(path) || Path::new(path).is_file()
+    }
+
+    /**
+     * package.toml 变化之后重新解析, 并重新 build 工作区中包含 import 的 module, 返回重新 build 的文件
+     */
+    pub async fn reload_package_config(&mut self) -> Vec<String> {
+        let package_path = Path::new(&self.root).join("package.toml").to_string_lossy().to_string();
+        self.package_config = parse_package(&package_path).ok().map(|package_config| Arc::new(Mutex::new(package_config)));
+
+        let paths: Vec<String> = {
+            let module_db = self.module_db.lock().unwrap();
+            module_db
+                .iter()
+                .filter(|m| m.path.starts_with(&self.root) && !m.dependencies.is_empty())
+                .map(|m| m.path.clone())
+                .collect()
+        };
+        let paths: Vec<String> = paths.into_iter().filter(|path| self.is_buildable(path)).collect();
+
+        for path in &paths {
+            self.build(path, "").await;
+        }
+
+        paths
+    }
+
+    /**
+     * module 所属 package 的配置, 最近的 package.toml 为 project root 中的 package.toml 时共享 project 中的配置
+     *
+     * 解析失败时返回 package.toml 的路径与错误
+     */
+    fn module_package_config(&self, path: &str) -> Result<Option<Arc<Mutex<PackageConfig>>>, (String, AnalyzerError)> {
+        let Some(package_path) = find_package_toml(path, &self.root) else {
+            return Ok(None);
+        };
+
+        if let Some(package_config) = &self.package_config {
+            if package_config.lock().unwrap().path == package_path {
+                return Ok(Some(package_config.clone()));
+            }
+        }
+
+        match parse_package(&package_path) {
+            Ok(package_config) => Ok(Some(Arc::new(Mutex::new(package_config)))),
+            Err(e) => Err((package_path, e)),
+        }
+    }
+
     pub async fn build(&mut self, main_path: &str, module_ident: &str) -> usize {
+        self.build_cancellable(main_path, module_ident, &CancellationToken::default()).await.unwrap()
+    }
+
+    /**
+     * cancel 被取消时提前结束并返回 None, 此时 module 中的分析结果不完整, 需要等待下一次 build 覆盖
+     */
+    pub async fn build_cancellable(&mut self, main_path: &str, module_ident: &str, cancel: &CancellationToken) -> Option<usize> {
         // 所有未编译的 import 模块, 都需要进行关联处理
         let mut worklist: Vec<ImportStmt> = Vec::new();
         let mut handled: HashSet<String> = HashSet::new();
@@ -264,6 +570,10 @@ impl Project {
 
         dbg!("{} handle work list", main_path);
         while let Some(import_stmt) = worklist.pop() {
+            if cancel.is_cancelled() {
+                return None;
+            }
+
             let module_handled = self.module_handled.lock().unwrap();
             let index_option = module_handled.get(&import_stmt.full_path).copied();
             drop(module_handled);
@@ -271,23 +581,26 @@ impl Project {
             let index: usize = if let Some(i) = index_option {
                 // 如果 import module 已经存在 module 则不需要进行重复编译, main path module 则进行强制更新
                 if import_stmt.full_path == main_path {
-                    // 需要更新现有模块的内容
-                    let content = std::fs::read_to_string(&import_stmt.full_path).unwrap();
+                    // 需要更新现有模块的内容, 文件已经被删除时与读取失败的新 module 一样使用空的内容
+                    let content = self.read_source(&import_stmt.full_path).unwrap_or_default();
                     let mut module_db = self.module_db.lock().unwrap();
                     let m = &mut module_db[i];
                     m.source = content;
                     m.rope = ropey::Rope::from_str(&m.source);
+
+                    // 已经被删除的声明不能继续被其他 module 引用, global symbol 在 parser 之后重新注册
+                    self.symbol_table.lock().unwrap().remove_module_symbols(&m.ident);
                     i.clone()
                 } else {
                     continue;
                 }
             } else {
-                let content_option = std::fs::read_to_string(import_stmt.full_path.clone());
-                if content_option.is_err() {
-                    continue;
-                }
-                let content = content_option.unwrap();
-
+                // 读取失败的 main module 使用空的 placeholder, 保证 build 能够返回 main module 的 index
+                let content = match self.read_source(&import_stmt.full_path) {
+                    Ok(content) => content,
+                    Err(_) if import_stmt.full_path == main_path => String::new(),
+                    Err(_) => continue,
+                };
 
                 // push to module_db get index lock
                 let mut module_handled = self.module_handled.lock().unwrap();
@@ -306,6 +619,8 @@ impl Project {
 
             let mut module_db = self.module_db.lock().unwrap();
             let m = &mut module_db[index];
+            m.line_index = LineIndex::from_rope(m.rope.clone(), self.position_encoding);
+            m.read_only = m.path == FALLBACK_BUILTIN_PATH || Path::new(&m.path).starts_with(Path::new(&self.nature_root).join("std"));
 
             // - lexer
             let (token_db, token_indexes, lexer_errors) = Lexer::new(m.source.clone()).scan();
@@ -314,10 +629,17 @@ impl Project {
             m.analyzer_errors = lexer_errors; // 清空 error 从 analyzer 起重新计算
 
             // - parser
-            let (mut stmts, sem_token_db, syntax_errors) = Syntax::new(token_db, token_indexes).parser();
+            let config = ParserConfig {
+                source_path: m.path.clone(),
+                ..Default::default()
+            };
+            let mut syntax = Syntax::with_source(token_db, token_indexes, config, Arc::new(m.source.clone()));
+            let (mut stmts, sem_token_db, syntax_errors) = syntax.parser();
             m.sem_token_db = sem_token_db.clone();
+            m.expected_tokens = syntax.expected_tokens();
             // m.stmts = stmts;
             m.analyzer_errors.extend(syntax_errors);
+            m.analyzer_errors.extend(Syntax::validate_ast(&stmts));
 
             // collection all relation module
             module_indexes.push(index);
@@ -328,7 +650,24 @@ impl Project {
             drop(symbol_table);
 
             // analyzer imports to worklist
-            let imports = analyze_imports(&self.package_config, m, &mut stmts);
+            let import_paths = self.settings.lock().unwrap().import_paths.clone();
+            let (package_config, package_error) = match self.module_package_config(&m.path) {
+                Ok(package_config) => (package_config, None),
+                Err(e) => (None, Some(e)),
+            };
+            let imports = analyze_imports(&package_config, &self.nature_root, &import_paths, m, &mut stmts);
+            m.package_config = package_config;
+
+            // package.toml 解析失败时, 依赖 package 的 import 的错误替换为 package.toml 的错误
+            if let Some((package_path, e)) = package_error {
+                for import in imports.iter().filter(|import| import.ast_package.is_some() && import.full_path.is_empty()) {
+                    m.analyzer_errors.retain(|error| error.span != Span::new(import.start, import.end));
+                    m.analyzer_errors.push(AnalyzerError::new(
+                        Span::new(import.start, import.end),
+                        format!("invalid package config {}: {}", package_path, e.message),
+                    ));
+                }
+            }
             m.stmts = stmts;
 
             let mut filter_imports: Vec<ImportStmt> = Vec::new();
@@ -337,11 +676,8 @@ impl Project {
             for import in imports {
                 // handle 重复进入表示 build module 发生了循环引用, 发送错误并跳过该 import 处理。
                 if handled.contains(&import.full_path) {
-                    m.analyzer_errors.push(AnalyzerError {
-                        start: import.start,
-                        end: import.end,
-                        message: format!("circular import"),
-                    });
+                    m.analyzer_errors
+                        .push(AnalyzerError::new(Span::new(import.start, import.end), format!("circular import")));
 
                     dbg!("circular import");
                     continue;
@@ -363,7 +699,10 @@ impl Project {
             let mut module_db = self.module_db.lock().unwrap();
             let mut symbol_table = self.symbol_table.lock().unwrap();
             let m = &mut module_db[index];
-            Semantic::new(m, &mut symbol_table).analyze();
+            Semantic::new(m, &mut symbol_table).with_cancel(cancel.clone()).analyze();
+        }
+        if cancel.is_cancelled() {
+            return None;
         }
 
         // all pre infer
@@ -371,17 +710,23 @@ impl Project {
             let mut module_db = self.module_db.lock().unwrap();
             let mut symbol_table = self.symbol_table.lock().unwrap();
             let m = &mut module_db[index];
-            let errors = Typesys::new(&mut symbol_table, m).pre_infer();
+            let errors = Typesys::new(&mut symbol_table, m).with_cancel(cancel.clone()).pre_infer();
             m.analyzer_errors.extend(errors);
         }
+        if cancel.is_cancelled() {
+            return None;
+        }
 
         for index in module_indexes.clone() {
             let mut module_db = self.module_db.lock().unwrap();
             let mut symbol_table = self.symbol_table.lock().unwrap();
             let m = &mut module_db[index];
-            let errors = Typesys::new(&mut symbol_table, m).infer();
+            let errors = Typesys::new(&mut symbol_table, m).with_cancel(cancel.clone()).infer();
             m.analyzer_errors.extend(errors);
         }
+        if cancel.is_cancelled() {
+            return None;
+        }
 
         // handle all refers
         let module_handled = self.module_handled.lock().unwrap();
@@ -398,7 +743,7 @@ impl Project {
             });
         }
 
-        return *main_index;
+        Some(*main_index)
     }
 
     /**
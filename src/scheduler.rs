@@ -0,0 +1,155 @@
+#[cfg(feature = "lsp")]
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "lsp")]
+use std::sync::Mutex;
+#[cfg(feature = "lsp")]
+use std::time::Duration;
+
+#[cfg(feature = "lsp")]
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/**
+ * analyzer 在每个 stmt 开始前检查, 被取消之后尽快结束当前的分析
+ */
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/**
+ * run 返回的 future 被 drop(lsp 请求被取消, 调用方 abort 了任务等)时取消对应的分析, 否则 worker 会继续执行到结束
+ *
+ * run 正常结束时同样会触发, 此时任务已经完成, 取消不会产生影响
+ */
+#[cfg(feature = "lsp")]
+struct CancelOnDrop(CancellationToken);
+
+#[cfg(feature = "lsp")]
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(feature = "lsp")]
+#[derive(Debug)]
+struct PendingAnalysis {
+    version: i32,
+    cancel: CancellationToken,
+}
+
+/**
+ * 按文件调度分析任务
+ *
+ * 同一个文件的新版本到达时取消正在等待或者正在执行的旧任务, 任务在 debounce 时间内没有被新版本取代才会真正执行,
+ * 执行结束时如果文件已经有了更新的版本, 则丢弃执行结果
+ *
+ * 分析任务在 tokio 的 blocking 线程池中执行, 只在 lsp feature 中可用
+ */
+#[cfg(feature = "lsp")]
+#[derive(Debug)]
+pub struct AnalysisScheduler {
+    debounce: Duration,
+    pending: Mutex<HashMap<String, PendingAnalysis>>, // key = path
+    running: Arc<tokio::sync::Mutex<()>>,             // 同一时间只有一个分析任务修改 module db
+}
+
+#[cfg(feature = "lsp")]
+impl Default for AnalysisScheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEBOUNCE)
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl AnalysisScheduler {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            pending: Mutex::new(HashMap::new()),
+            running: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /**
+     * 记录文件的最新版本并取消旧版本的任务, 返回新任务使用的 cancellation token
+     */
+    pub fn schedule(&self, path: &str, version: i32) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        let previous = self.pending.lock().unwrap().insert(
+            path.to_string(),
+            PendingAnalysis {
+                version,
+                cancel: cancel.clone(),
+            },
+        );
+        if let Some(previous) = previous {
+            previous.cancel.cancel();
+        }
+        cancel
+    }
+
+    /**
+     * 不经过调度直接修改 module db 的任务(workspace 索引, 监听到的文件变更等)需要持有该锁, 避免与分析任务同时 build
+     */
+    pub async fn exclusive(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.running.lock().await
+    }
+
+    pub fn is_latest(&self, path: &str, version: i32) -> bool {
+        self.pending.lock().unwrap().get(path).is_some_and(|p| p.version == version)
+    }
+
+    /**
+     * analyze 在 worker 线程中执行, 返回 None 表示任务被取消或者结果已经过期, 调用方不应该发布任何结果
+     */
+    pub async fn run<T, F>(&self, path: &str, version: i32, analyze: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(CancellationToken) -> Option<T> + Send + 'static,
+    {
+        let cancel = self.schedule(path, version);
+        let _cancel_on_drop = CancelOnDrop(cancel.clone());
+
+        // debounce, 连续输入时只有最后一次修改会继续执行
+        tokio::time::sleep(self.debounce).await;
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        // 锁随 worker 一起释放, future 被 drop 之后被取消的 worker 仍然在执行时, 下一个任务不会同时修改 module db
+        let running = self.running.clone().lock_owned().await;
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        let worker_cancel = cancel.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let _running = running;
+            analyze(worker_cancel)
+        })
+        .await
+        .ok()??;
+
+        // 分析期间到达了新的版本
+        if cancel.is_cancelled() || !self.is_latest(path, version) {
+            return None;
+        }
+
+        Some(result)
+    }
+}
@@ -0,0 +1,147 @@
+use crate::analyzer::code_lens::CodeLensConfig;
+use crate::analyzer::diagnostic::DiagnosticConfig;
+use crate::analyzer::formatter::FormatConfig;
+use crate::analyzer::inlay_hint::InlayHintConfig;
+use crate::analyzer::lint::LintConfig;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+// workspace/configuration 中的 section, 客户端中对应 nls.* 配置
+pub const SETTINGS_SECTION: &str = "nls";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DiagnosticSettings {
+    pub max_per_file: usize,
+    pub unused_variable: bool,
+    pub shadowing: bool,
+}
+
+impl Default for DiagnosticSettings {
+    fn default() -> Self {
+        Self {
+            max_per_file: 100,
+            unused_variable: true,
+            shadowing: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InlayHintSettings {
+    pub type_hints: bool,
+    pub parameter_hints: bool,
+    pub max_type_length: usize,
+}
+
+impl Default for InlayHintSettings {
+    fn default() -> Self {
+        let config = InlayHintConfig::default();
+        Self {
+            type_hints: config.type_hints,
+            parameter_hints: config.parameter_hints,
+            max_type_length: config.max_length,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CodeLensSettings {
+    pub references: bool,
+    pub references_max_lines: usize,
+}
+
+impl Default for CodeLensSettings {
+    fn default() -> Self {
+        let config = CodeLensConfig::default();
+        Self {
+            references: config.references,
+            references_max_lines: config.references_max_lines,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FormatSettings {
+    pub indent_width: Option<usize>, // 未配置时使用编辑器中的 tab size
+    pub max_line_width: usize,
+}
+
+impl Default for FormatSettings {
+    fn default() -> Self {
+        Self {
+            indent_width: None,
+            max_line_width: FormatConfig::default().max_width,
+        }
+    }
+}
+
+/**
+ * 服务端配置, 通过 workspace/configuration 获取, analyzer 中的各个 pass 通过 project 中共享的 Arc 读取
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    pub diagnostics: DiagnosticSettings,
+    pub inlay_hints: InlayHintSettings,
+    pub code_lens: CodeLensSettings,
+    pub format: FormatSettings,
+    pub import_paths: Vec<String>,   // 除了 nature root 中的 std 之外额外的 package 查找目录
+    pub nature_root: Option<String>, // std 所在的 nature root, 只在 initialize 时通过 initializationOptions 生效
+}
+
+// 每个 section 单独解析, 某个 section 中的值类型错误时只有该 section 使用默认值
+fn section<T: DeserializeOwned + Default>(value: &Value, key: &str) -> T {
+    value.get(key).and_then(|v| serde_json::from_value(v.clone()).ok()).unwrap_or_default()
+}
+
+impl Settings {
+    /**
+     * 解析 nls section 中的配置, 未知的 key 会被忽略, 缺失的 key 使用默认值
+     */
+    pub fn from_value(value: &Value) -> Self {
+        Self {
+            diagnostics: section(value, "diagnostics"),
+            inlay_hints: section(value, "inlayHints"),
+            code_lens: section(value, "codeLens"),
+            format: section(value, "format"),
+            import_paths: section(value, "importPaths"),
+            nature_root: section(value, "natureRoot"),
+        }
+    }
+
+    pub fn diagnostic_config(&self) -> DiagnosticConfig {
+        DiagnosticConfig {
+            max_per_file: self.diagnostics.max_per_file,
+            lint: LintConfig {
+                unused_variable: self.diagnostics.unused_variable,
+                shadowing: self.diagnostics.shadowing,
+            },
+        }
+    }
+
+    pub fn inlay_hint_config(&self) -> InlayHintConfig {
+        InlayHintConfig {
+            type_hints: self.inlay_hints.type_hints,
+            parameter_hints: self.inlay_hints.parameter_hints,
+            max_length: self.inlay_hints.max_type_length,
+        }
+    }
+
+    pub fn code_lens_config(&self) -> CodeLensConfig {
+        CodeLensConfig {
+            references: self.code_lens.references,
+            references_max_lines: self.code_lens.references_max_lines,
+        }
+    }
+
+    pub fn format_config(&self, tab_size: usize) -> FormatConfig {
+        FormatConfig {
+            indent_width: self.format.indent_width.unwrap_or(tab_size),
+            max_width: self.format.max_line_width,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::analyzer::ast_json::ast_to_json;
+use crate::analyzer::diagnostic::{file_diagnostics, DiagnosticConfig};
+use crate::analyzer::lexer::Lexer;
+use crate::analyzer::semantic::Semantic;
+use crate::analyzer::symbol::SymbolTable;
+use crate::analyzer::syntax::{ParserConfig, Syntax};
+use crate::analyzer::typesys::Typesys;
+use crate::analyzer::{analyze_imports, register_global_symbol};
+use crate::check::{diagnostic_json, FileDiagnostic};
+use crate::parse;
+use crate::project::{Module, FALLBACK_BUILTIN_PATH, FALLBACK_BUILTIN_SOURCE};
+use serde_json::Value;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// playground 中的源码没有对应的文件
+pub const PLAYGROUND_PATH: &str = "main.n";
+
+/**
+ * 模块加载时注册 panic hook, panic 的信息输出到 console.error, 并以 js 异常的形式抛出, 而不是没有任何信息的 abort
+ */
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(start)]
+pub fn start() {
+    console_error_panic_hook::set_once();
+}
+
+/**
+ * 解析源码并返回 ast_to_json 格式的 stmt 列表, 语法错误不包含在结果中, 通过 diagnostics 获取
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn parse_to_json(source: &str) -> String {
+    ast_to_json(&parse(source).stmts)
+}
+
+/**
+ * 对单个文件进行完整的分析, 返回诊断的 json 数组, 每个元素的格式与 nls check --format json 一致
+ *
+ * 浏览器中没有文件系统, 因此 import 无法被解析, builtin 使用内置的 fallback builtin。column 按照 utf-16 计算, 与 js string 的下标一致
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn diagnostics(source: &str) -> String {
+    let mut symbol_table = SymbolTable::new();
+    let mut modules = [
+        Module::new(String::new(), FALLBACK_BUILTIN_SOURCE.to_string(), FALLBACK_BUILTIN_PATH.to_string(), 0),
+        Module::new("main".to_string(), source.to_string(), PLAYGROUND_PATH.to_string(), 1),
+    ];
+    modules[0].read_only = true;
+
+    for m in modules.iter_mut() {
+        let (token_db, token_indexes, lexer_errors) = Lexer::new(m.source.clone()).scan();
+        m.token_db = token_db.clone();
+        m.token_indexes = token_indexes.clone();
+        m.analyzer_errors = lexer_errors;
+
+        let config = ParserConfig {
+            source_path: m.path.clone(),
+            ..Default::default()
+        };
+        let (mut stmts, sem_token_db, syntax_errors) = Syntax::with_config(token_db, token_indexes, config).parser();
+        m.sem_token_db = sem_token_db;
+        m.analyzer_errors.extend(syntax_errors);
+        m.analyzer_errors.extend(Syntax::validate_ast(&stmts));
+
+        register_global_symbol(m, &mut symbol_table, &stmts);
+        analyze_imports(&None, "", &[], m, &mut stmts);
+        m.stmts = stmts;
+    }
+
+    // 与 project build 相同, 所有 module 完成 semantic 之后再进行类型推导
+    for m in modules.iter_mut() {
+        Semantic::new(m, &mut symbol_table).analyze();
+    }
+    for m in modules.iter_mut() {
+        let errors = Typesys::new(&mut symbol_table, m).pre_infer();
+        m.analyzer_errors.extend(errors);
+    }
+    for m in modules.iter_mut() {
+        let errors = Typesys::new(&mut symbol_table, m).infer();
+        m.analyzer_errors.extend(errors);
+    }
+
+    let items: Vec<Value> = file_diagnostics(&modules[1], &DiagnosticConfig::default())
+        .into_iter()
+        .map(|diagnostic| {
+            diagnostic_json(&FileDiagnostic {
+                path: PLAYGROUND_PATH.to_string(),
+                diagnostic,
+            })
+        })
+        .collect();
+    Value::Array(items).to_string()
+}
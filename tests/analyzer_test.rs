@@ -1,22 +1,131 @@
-use nls::project::Project;
+use nls::analyzer::ast_json::{ast_to_json, body_structurally_eq, structurally_eq};
+use nls::analyzer::code_action::{
+    create_function_action, create_method_action, insert_expected_token_actions, missing_fields_action, specify_type_action, use_var_action,
+};
+use nls::analyzer::code_lens::{code_lenses, count_references, lens_target, resolve_reference_lens, CodeLensConfig, RUN_TEST_COMMAND};
+use nls::analyzer::common::{
+    AnalyzerError, AstFnDef, AstNode, AstNodeId, DiagnosticPhase, Expr, ExprOp, IdMap, ImportStmt, LiteralSource, MinMaxKind, Severity, Span, Stmt,
+    StructNewEntry, Type, TypeAlias, TypeArg, TypeFn, TypeKind, TypeStructProperty, VarDeclExpr,
+};
+use nls::analyzer::completion::{auto_import_completions, import_insert_position, AutoImportContext, CompletionConfig};
+use nls::analyzer::diagnostic::{
+    document_diagnostic_report, file_diagnostics, module_diagnostics, to_diagnostic, workspace_document_report, DiagnosticConfig, DIAGNOSTIC_SOURCE,
+};
+use nls::analyzer::diff::{diff_tokens, DiffKind, TokenDiff};
+use nls::analyzer::document_link::document_links;
+use nls::analyzer::errors::merge_adjacent_errors;
+use nls::analyzer::file_rename::import_rename_edits;
+use nls::analyzer::folding_range::folding_ranges;
+use nls::analyzer::formatter::{format_source, FormatConfig};
+use nls::analyzer::hover::hover;
+use nls::analyzer::inlay_hint::{inlay_hints, InlayHintConfig};
+use nls::analyzer::intern::{intern, resolve, Atom};
+use nls::analyzer::lexer::{semantic_token_modifier_bit, source_line, Lexer, Token, TokenText, TokenType};
+use nls::analyzer::lint::LintConfig;
+use nls::analyzer::metrics::metrics;
+use nls::analyzer::parse::{is_comment_only, is_effectively_empty, parse_with_options, ParseOptions};
+use nls::analyzer::printer::{body_to_source, to_source};
+use nls::analyzer::query::{node_at, NodeKind};
+use nls::analyzer::reference::{document_highlights, linked_editing_ranges, ReferenceIndex, IDENT_WORD_PATTERN};
+use nls::analyzer::reparse::TextEdit;
+use nls::analyzer::selection_range::{node_path_at, selection_ranges};
+use nls::analyzer::semantic::Semantic;
+use nls::analyzer::semantic_tokens::{encode_semantic_tokens, semantic_tokens_delta};
+use nls::analyzer::signature_help::{call_context, signature_help};
+use nls::analyzer::snippet::{render_error, render_snippet, Label, RenderConfig, Snippet};
+use nls::analyzer::symbol::{SymbolTable, GLOBAL_SCOPE_ID};
+use nls::analyzer::syntax::{collect_tests, flatten_imports, flatten_imports_deep, parse_type_str, DeclNode, ParserConfig, Syntax, SyntaxError};
+use nls::analyzer::token_cursor::TokenCursor;
+use nls::analyzer::type_hierarchy::TypeHierarchyIndex;
+use nls::analyzer::type_utils::{normalize_type, render, render_truncated, struct_field_index, struct_field_type, TypeEnv};
+use nls::analyzer::typesys::Typesys;
+use nls::analyzer::walk::{walk_body, walk_node, walk_stmts};
+use nls::analyzer::workspace_symbol::workspace_symbols;
+use nls::analyzer::{analyze_imports, module_unique_ident};
+use nls::check::{check, diagnostic_human, diagnostic_json, CheckFormat, CheckOptions};
+use nls::document::{Document, LineIndex, PositionEncoding};
+use nls::package::parse_package;
+use nls::project::{Module, Project, DEFAULT_NATURE_ROOT, DEPENDENT_REBUILD_DEPTH, FALLBACK_BUILTIN_PATH};
+use nls::scheduler::{AnalysisScheduler, CancellationToken};
+use nls::settings::Settings;
+use nls::utils::{format_global_ident, offset_to_position};
+use nls::wasm::{diagnostics, parse_to_json};
+use proptest::prelude::*;
 use ropey::Rope;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentDiagnosticReport, DocumentHighlightKind, FoldingRangeKind, InlayHintKind, InlayHintLabel, NumberOrString, Position,
+    Range, SemanticToken, SemanticTokenModifier, TextDocumentContentChangeEvent, Url, WorkspaceDocumentDiagnosticReport,
+};
+
+fn parse_with_errors(source: &str) -> (Vec<Box<Stmt>>, Vec<AnalyzerError>) {
+    let result = nls::parse(source);
+    assert!(result.errors.iter().all(|e| e.phase != DiagnosticPhase::Lexer), "{:?}", result.errors);
+    let errors = result.errors.into_iter().filter(|e| e.phase == DiagnosticPhase::Syntax).collect();
+    (result.stmts, errors)
+}
+
+fn parse(source: &str) -> Vec<Box<Stmt>> {
+    let (stmts, errors) = parse_with_errors(source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    stmts
+}
+
+fn module_new(source: &str, path: &str, index: usize) -> Module {
+    let mut m = Module::new(String::new(), source.to_string(), path.to_string(), index);
+    m.stmts = parse(source);
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    m.token_db = token_db;
+    m.token_indexes = token_indexes;
+    m
+}
+
+// semantic 与 typesys 分析单个 module
+fn module_analyze(source: &str) -> Module {
+    module_analyze_with_symbols(source).0
+}
+
+fn module_analyze_with_symbols(source: &str) -> (Module, SymbolTable) {
+    let mut m = module_new(source, "/project/main.n", 0);
+    let mut symbol_table = SymbolTable::new();
+    Semantic::new(&mut m, &mut symbol_table).analyze();
+    let errors = Typesys::new(&mut symbol_table, &mut m).pre_infer();
+    m.analyzer_errors.extend(errors);
+    let errors = Typesys::new(&mut symbol_table, &mut m).infer();
+    m.analyzer_errors.extend(errors);
+    (m, symbol_table)
+}
+
+fn import_stmt(stmt: &Stmt) -> &ImportStmt {
+    let AstNode::Import(import) = &stmt.node else {
+        panic!("expect import, got {:?}", stmt.node)
+    };
+    import
+}
+
+fn var_def_right(stmt: &Stmt) -> &Expr {
+    let AstNode::VarDef(_, right) = &stmt.node else {
+        panic!("expect var def, got {:?}", stmt.node)
+    };
+    right
+}
 
 #[test]
 fn test_rope() {
     let text = "你好\n世界"; // 8个字节(你=3字节,好=3字节,\n=1字节,世=3字节,界=3字节)
     let rope = Rope::from_str(text);
 
-    assert_eq!(rope.try_byte_to_line(0).unwrap(), 0);  // 第一行
-    assert_eq!(rope.try_byte_to_line(7).unwrap(), 1);  // 第二行
-    assert!(rope.try_byte_to_line(14).is_err());       // 超出范围，应该返回错误
+    assert_eq!(rope.try_byte_to_line(0).unwrap(), 0); // 第一行
+    assert_eq!(rope.try_byte_to_line(7).unwrap(), 1); // 第二行
+    assert!(rope.try_byte_to_line(14).is_err()); // 超出范围，应该返回错误
 }
 
-
 #[tokio::test]
 async fn test_project() {
     let project_root = "/Users/weiwenhao/Code/nature-test";
 
-    let mut project = Project::new(project_root.to_string()).await;
+    let mut project = Project::new(project_root.to_string(), PositionEncoding::default(), Default::default()).await;
     project.backend_handle_queue();
 
     let module_ident = "nature-test.main";
@@ -24,4 +133,5067 @@ async fn test_project() {
 
     let module_index = project.build(&file_path, &module_ident).await;
     dbg!(module_index);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_match_has_default() {
+    let stmts = parse("var a = match b {\n1 -> 2\n_ -> 3\n}\n");
+    let AstNode::Match(_, _, has_default) = &var_def_right(&stmts[0]).node else {
+        panic!("expect match")
+    };
+    assert!(has_default);
+
+    let stmts = parse("var a = match b {\n1 -> 2\n3 -> 4\n}\n");
+    let AstNode::Match(_, _, has_default) = &var_def_right(&stmts[0]).node else {
+        panic!("expect match")
+    };
+    assert!(!has_default);
+}
+
+#[test]
+fn test_match_case_error_recovery() {
+    let (stmts, errors) = parse_with_errors("var a = match b {\n1 -> 2\n2 -> )\n_ -> 3\n}\nvar c = 1\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].message, "<expr> expected, found ')'");
+
+    // 解析失败的 case 被丢弃, match 之后的语句不受影响
+    assert_eq!(stmts.len(), 2);
+    let AstNode::Match(_, cases, has_default) = &var_def_right(&stmts[0]).node else {
+        panic!("expect match")
+    };
+    assert_eq!(cases.len(), 2);
+    assert!(has_default);
+    assert!(matches!(var_def_right(&stmts[1]).node, AstNode::Literal(..)));
+
+    // case 中未闭合的括号, 跳过时不会越过文件末尾
+    let (_, errors) = parse_with_errors("var a = match b {\n1 -> call(1, {\n2 -> 3\n}\n");
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn test_import_star() {
+    let stmts = parse("import math as *\nimport std.io as io\n");
+    assert!(import_stmt(&stmts[0]).is_star);
+    assert_eq!(import_stmt(&stmts[0]).as_name, "*");
+
+    assert!(!import_stmt(&stmts[1]).is_star);
+    assert_eq!(import_stmt(&stmts[1]).as_name, "io");
+
+    let (_, errors) = parse_with_errors("import math\nimport math as *\n");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.contains("duplicated"));
+    assert_eq!(errors[0].severity, Severity::Warning);
+}
+
+#[test]
+fn test_auto_import_completion() {
+    let config = CompletionConfig::default();
+    let ctx = AutoImportContext {
+        nature_root: "/nature",
+        package_config: None,
+        config: &config,
+    };
+
+    let http = module_new("fn serve_http() {\n}\n", "/nature/std/net/http/main.n", 1);
+
+    // 不存在 import 时插入到文件开头
+    let main = module_new("fn main() {\n}\n", "/project/main.n", 0);
+    assert_eq!(import_insert_position(&main), Position::new(0, 0));
+
+    let items = auto_import_completions(&main, &[main.clone(), http.clone()], "serve", &ctx);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].label, "serve_http (import net.http)");
+    assert_eq!(items[0].insert_text.as_deref(), Some("http.serve_http"));
+    let edits = items[0].additional_text_edits.as_ref().unwrap();
+    assert_eq!(edits[0].range.start, Position::new(0, 0));
+    assert_eq!(edits[0].new_text, "import net.http\n");
+
+    // 存在 import 时插入到最后一个 import 的下一行
+    let main = module_new("import fmt\nimport strings\n\nfn main() {\n}\n", "/project/main.n", 0);
+    assert_eq!(import_insert_position(&main), Position::new(2, 0));
+
+    // 已经 import 的 module 不会重复插入 import
+    let mut main = module_new("import net.http\n\nfn main() {\n}\n", "/project/main.n", 0);
+    let mut import = import_stmt(&main.stmts[0]).clone();
+    import.full_path = http.path.clone();
+    import.as_name = "http".to_string();
+    main.dependencies.push(import);
+
+    let items = auto_import_completions(&main, &[main.clone(), http.clone()], "serve", &ctx);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].label, "serve_http");
+    assert!(items[0].additional_text_edits.is_none());
+}
+
+#[test]
+fn test_new_args() {
+    let stmts = parse("var t = new Thread(fn() {\n})\nvar b = new Buffer(1024)\nvar f = new Foo\nvar p = new Point(x=1, y=2)\n");
+
+    let AstNode::New(_, properties, args) = &var_def_right(&stmts[0]).node else {
+        panic!("expect new")
+    };
+    assert!(properties.is_empty());
+    assert_eq!(args.len(), 1);
+    assert!(matches!(args[0].node, AstNode::FnDef(..)));
+
+    let AstNode::New(_, _, args) = &var_def_right(&stmts[1]).node else {
+        panic!("expect new")
+    };
+    assert_eq!(args.len(), 1);
+    assert!(matches!(args[0].node, AstNode::Literal(..)));
+
+    let AstNode::New(_, properties, args) = &var_def_right(&stmts[2]).node else {
+        panic!("expect new")
+    };
+    assert!(properties.is_empty() && args.is_empty());
+
+    let AstNode::New(_, properties, args) = &var_def_right(&stmts[3]).node else {
+        panic!("expect new")
+    };
+    assert_eq!(properties.len(), 2);
+    assert!(args.is_empty());
+}
+
+#[test]
+fn test_new_args_check() {
+    let source = "type point = struct {\n    int x\n    int y\n}\n\nfn main() {\n    var p = new point(x=1, y=2)\n    var q = new point(1, 2)\n    var n = new int(1)\n}\n";
+    let m = module_analyze(source);
+    let messages: Vec<&str> = m.analyzer_errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+    assert!(messages[0].contains("does not accept positional arguments"), "{}", messages[0]);
+    assert!(messages[1].starts_with("cannot use 'new' operator with arguments"), "{}", messages[1]);
+
+    // 错误位于参数之上
+    let start = source.find("1, 2)").unwrap();
+    assert_eq!(m.analyzer_errors[0].span, Span::new(start, start + 4));
+}
+
+#[test]
+fn test_label_stmt_end() {
+    // 单独位于一行的 label 之后自动插入 StmtEof, label 与 fn 声明之间不再需要位于同一行
+    let source = "#local\nfn foo() {\n}\n";
+    let (tokens, indexes, _) = Lexer::new(source.to_string()).scan();
+    let literals: Vec<&str> = indexes.iter().take(3).map(|&i| tokens[i].literal.as_str()).collect();
+    assert_eq!(literals, vec!["local", ";", "fn"]);
+
+    let stmts = parse(source);
+    assert_eq!(stmts.len(), 1);
+    assert!(matches!(stmts[0].node, AstNode::FnDef(..)));
+
+    // 行尾不是 label 时不受影响
+    let stmts = parse("#linkid foo_impl\nfn foo() {\n}\n");
+    assert_eq!(stmts.len(), 1);
+}
+
+#[test]
+fn test_macro_hover_and_signature_help() {
+    let source = "fn work():int {\n    return 1\n}\n\nfn main() {\n    var size = @sizeof(int)\n    var fut = @async(work(), \n}\n";
+    let mut m = Module::new(String::new(), source.to_string(), "/project/main.n".to_string(), 0);
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    m.token_db = token_db;
+    m.token_indexes = token_indexes;
+    let symbol_table = SymbolTable::new();
+
+    let hover_text = |m: &Module, offset: usize| -> (String, Range) {
+        let hover = hover(m, &symbol_table, &ReferenceIndex::default(), offset).unwrap();
+        let tower_lsp::lsp_types::HoverContents::Markup(markup) = hover.contents else {
+            panic!("expect markup")
+        };
+        (markup.value, hover.range.unwrap())
+    };
+
+    // hover range 为宏 token 的范围
+    let (text, range) = hover_text(&m, source.find("sizeof").unwrap() + 2);
+    assert_eq!(text, "```n\n@sizeof(type T):int\n```\n\nsize of type T in bytes, evaluated at compile time");
+    assert_eq!(range, Range::new(Position::new(5, 15), Position::new(5, 22)));
+
+    // 宏参数中触发 signature help
+    let offset = source.find("work(), ").unwrap() + 8;
+    let help = signature_help(&m, &symbol_table, offset).unwrap();
+    assert_eq!(help.signatures[0].label, "@async(call fn_call, expr flag):future<T>");
+    assert_eq!(help.active_parameter, Some(1));
+    let help = signature_help(&m, &symbol_table, source.find("@async(").unwrap() + 7).unwrap();
+    assert_eq!(help.active_parameter, Some(0));
+
+    // 未定义的宏
+    let source = "fn main() {\n    var a = @foo(1)\n}\n";
+    let mut m = Module::new(String::new(), source.to_string(), "/project/main.n".to_string(), 0);
+    (m.stmts, m.analyzer_errors) = parse_with_errors(source);
+    (m.token_db, m.token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let (text, _) = hover_text(&m, source.find("foo").unwrap());
+    assert_eq!(text, "unknown macro `@foo`\n\nmacro 'foo' not defined");
+}
+
+#[test]
+fn test_signature_help() {
+    let source = "fn sum<T:int|float>(T a, ...[T] list):T {\n}\n\nfn main() {\n    sum(1, sum(2, 3, 4), 5)\n    sum(\n        1,\n        2,\n        3\n}\n";
+    // 输入过程中的 source 并不完整
+    let mut m = Module::new(String::new(), source.to_string(), "/project/main.n".to_string(), 0);
+    m.stmts = parse_with_errors(source).0;
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    m.token_db = token_db;
+    m.token_indexes = token_indexes;
+    let symbol_table = SymbolTable::new();
+
+    // 嵌套调用时最内层未闭合的 call 生效
+    let offset = source.find("3, 4").unwrap();
+    let context = call_context(&m.token_db, &m.token_indexes, offset).unwrap();
+    assert_eq!(context.callee, vec!["sum".to_string()]);
+    assert_eq!(context.active_param, 1);
+    assert_eq!(context.left_paren, source.find("sum(2").unwrap() + 3);
+
+    // 闭合的嵌套调用不影响外层 call 的 active param
+    let offset = source.find("5)").unwrap();
+    let help = signature_help(&m, &symbol_table, offset).unwrap();
+    assert_eq!(help.signatures[0].label, "fn sum<T:int|float>(T a, ...vec<T> list):T");
+    assert_eq!(help.active_parameter, Some(1));
+
+    // 跨越多行的 call, rest param 之后 active param 固定在 rest 上
+    let offset = source.find("3\n}").unwrap();
+    let context = call_context(&m.token_db, &m.token_indexes, offset).unwrap();
+    assert_eq!(context.active_param, 2);
+    let help = signature_help(&m, &symbol_table, offset).unwrap();
+    assert_eq!(help.active_parameter, Some(1));
+
+    // fn 声明中的 ( 不会触发
+    let offset = source.find("T a").unwrap();
+    assert!(call_context(&m.token_db, &m.token_indexes, offset).is_none());
+}
+
+#[test]
+fn test_diff_tokens() {
+    let old = Lexer::new("var a = 1\nvar b = a + 1\n".to_string()).scan().0;
+
+    // 修改一个 ident
+    let new = Lexer::new("var a = 1\nvar c = a + 1\n".to_string()).scan().0;
+    assert_eq!(
+        diff_tokens(&old, &new),
+        vec![TokenDiff {
+            kind: DiffKind::Changed,
+            range: 6..7
+        }]
+    );
+
+    // 新增一行
+    let new = Lexer::new("var a = 1\nvar x = 2\nvar b = a + 1\n".to_string()).scan().0;
+    let diffs = diff_tokens(&old, &new);
+    assert!(!diffs.is_empty());
+    assert!(diffs.iter().all(|d| d.kind == DiffKind::Added));
+    assert_eq!(diffs.iter().map(|d| d.range.len()).sum::<usize>(), 5);
+
+    assert!(diff_tokens(&old, &old).is_empty());
+
+    // 较大的文件中分散的修改, 每一处修改单独返回
+    let source: String = (0..2000).map(|i| format!("var a{} = {}\n", i, i)).collect();
+    let old = Lexer::new(source.clone()).scan().0;
+    let new = Lexer::new(source.replace("var a100 =", "var b100 =").replace("var a1500 = 1500\n", ""))
+        .scan()
+        .0;
+    let diffs = diff_tokens(&old, &new);
+    assert_eq!(diffs.len(), 2);
+    assert_eq!(
+        diffs[0],
+        TokenDiff {
+            kind: DiffKind::Changed,
+            range: 501..502
+        }
+    );
+    assert_eq!(diffs[1].kind, DiffKind::Removed);
+    assert_eq!(diffs[1].range.len(), 5);
+}
+
+#[test]
+fn test_statements_in_range() {
+    let source: String = (0..10).map(|i| format!("var a{} = {}\n", i, i)).collect();
+    let stmts = parse(&source);
+    assert_eq!(stmts.len(), 10);
+
+    let start = source.find("var a3").unwrap();
+    let end = source.find("var a8").unwrap();
+    let selected: Vec<_> = Syntax::statements_in_range(&stmts, start, end).collect();
+    assert_eq!(selected.len(), 5);
+    assert_eq!(selected[0].span.start, stmts[3].span.start);
+    assert_eq!(selected[4].span.start, stmts[7].span.start);
+
+    // range 跨越 stmt 边界时两个 stmt 都会返回
+    let start = source.find("= 4").unwrap();
+    let end = source.find("var a5").unwrap() + 2;
+    let selected: Vec<_> = Syntax::statements_in_range(&stmts, start, end).collect();
+    assert_eq!(selected.len(), 2);
+    assert_eq!(selected[0].span.start, stmts[4].span.start);
+    assert_eq!(selected[1].span.start, stmts[5].span.start);
+}
+
+fn sem_tokens(source: &str) -> Vec<Token> {
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    Syntax::new(token_db, token_indexes).parser().1
+}
+
+fn encode(source: &str) -> Vec<SemanticToken> {
+    encode_semantic_tokens(&sem_tokens(source), &LineIndex::new(source, PositionEncoding::Utf16))
+}
+
+#[test]
+fn test_semantic_token_modifiers() {
+    let source = "#deprecated\nfn old() {\n}\n\nfn main() {\n    var a = 1\n    for k in list {\n    }\n}\n";
+    let tokens = sem_tokens(source);
+    let modifiers = |literal: &str| tokens.iter().find(|t| t.literal == literal).unwrap().semantic_token_modifiers;
+
+    let declaration = semantic_token_modifier_bit(SemanticTokenModifier::DECLARATION);
+    let readonly = semantic_token_modifier_bit(SemanticTokenModifier::READONLY);
+    let deprecated = semantic_token_modifier_bit(SemanticTokenModifier::DEPRECATED);
+
+    assert_eq!(modifiers("old"), declaration | deprecated);
+    assert_eq!(modifiers("main"), declaration);
+    assert_eq!(modifiers("a"), declaration);
+    assert_eq!(modifiers("k"), declaration | readonly);
+    assert_eq!(modifiers("list"), 0);
+}
+
+#[test]
+fn test_stacked_labels() {
+    let stmts = parse("#local\n#deprecated\nfn old() {\n}\n\n#local #test\nfn check() {\n}\n");
+    assert_eq!(stmts.len(), 2);
+
+    let AstNode::FnDef(fndef) = &stmts[0].node else {
+        panic!("expect fn def, got {:?}", stmts[0].node)
+    };
+    let fndef = fndef.read().unwrap();
+    assert_eq!(fndef.fn_name, "old");
+    assert!(fndef.is_private && fndef.is_deprecated);
+
+    let AstNode::FnDef(fndef) = &stmts[1].node else {
+        panic!("expect fn def, got {:?}", stmts[1].node)
+    };
+    let fndef = fndef.read().unwrap();
+    assert_eq!(fndef.fn_name, "check");
+    assert!(fndef.is_private && fndef.is_test);
+}
+
+#[test]
+fn test_semantic_tokens_delta() {
+    let old_source = "var a = 1\nvar b = 2\nvar c = 3\nvar d = 4\n";
+    let new_source = "var a = 1\nvar b = foo + 2\nvar c = 3\nvar d = 4\n";
+    let old = encode(old_source);
+    let new = encode(new_source);
+
+    // 修改第二行只会影响第二行的 token
+    let edits = semantic_tokens_delta(&old, &new);
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].start, 8 * 5);
+    assert_eq!(edits[0].delete_count, 0);
+    assert_eq!(edits[0].data.as_ref().unwrap().len(), 2);
+
+    assert!(semantic_tokens_delta(&old, &old).is_empty());
+}
+
+#[test]
+fn test_fn_decorators() {
+    let source = "@memoize fn fib(int n):int {\n    return n\n}\n\n@retry(3)\nfn call_api():string {\n    return 'ok'\n}\n\n@deprecated fn old() {\n}\n\n@first\n@second\nfn both() {\n}\n";
+    let stmts = parse(source);
+
+    let decorators = |stmt: &Stmt| {
+        let AstNode::FnDef(fndef_mutex) = &stmt.node else { panic!("expect fn def") };
+        let fndef = fndef_mutex.read().unwrap();
+        fndef.decorators.iter().map(|d| (d.name.clone(), d.args.len())).collect::<Vec<_>>()
+    };
+
+    assert_eq!(decorators(&stmts[0]), vec![("memoize".to_string(), 0)]);
+    assert_eq!(decorators(&stmts[1]), vec![("retry".to_string(), 1)]);
+    assert_eq!(decorators(&stmts[2]), vec![("deprecated".to_string(), 0)]);
+
+    // bottom-up
+    assert_eq!(decorators(&stmts[3]), vec![("second".to_string(), 0), ("first".to_string(), 0)]);
+
+    // 没有跟随 fn 或者 type 声明的 @ident 依旧是 macro
+    let stmts = parse("var size = @sizeof(int)\n@retry(3) fn call() {\n}\n");
+    assert!(matches!(stmts[0].node, AstNode::VarDef(..)));
+    assert_eq!(decorators(&stmts[1]), vec![("retry".to_string(), 1)]);
+
+    let (_, errors) = parse_with_errors("@sizeof(int)\nfn main() {\n}\n");
+    assert!(!errors.is_empty());
+    assert!(errors.iter().all(|e| !e.message.contains("decorator")), "{:?}", errors);
+}
+
+#[test]
+fn test_folding_ranges() {
+    let source = r#"import fmt
+import strings
+
+/*
+ * block comment
+ */
+type point = struct {
+    int x
+    int y
+}
+
+fn main() {
+    if true {
+        var a = 1
+    } else {
+        var b = 2
+    }
+
+    for i in [1, 2] {
+        var c = i
+    }
+
+    var v = match 1 {
+        1 -> {
+            var d = 1
+            break d
+        }
+        _ -> 2
+    }
+
+    var p = point{
+        x = 1,
+        y = 2
+    }
+
+    var list = [
+        1,
+        2]
+}
+"#;
+    let m = module_new(source, "/project/main.n", 0);
+
+    let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> = folding_ranges(&m).into_iter().map(|r| (r.start_line, r.end_line, r.kind)).collect();
+    assert_eq!(
+        ranges,
+        vec![
+            (0, 1, Some(FoldingRangeKind::Imports)),
+            (3, 5, Some(FoldingRangeKind::Comment)),
+            (6, 8, None),   // type struct
+            (11, 37, None), // fn main
+            (12, 13, None), // if
+            (14, 15, None), // else
+            (18, 19, None), // for
+            (22, 27, None), // match
+            (23, 25, None), // match case
+            (30, 32, None), // struct new
+            (35, 36, None), // vec new
+        ]
+    );
+
+    // 语法错误时已经解析的结构依旧可以折叠
+    let source = "fn foo() {
+    var a = 1
+}
+
+fn bar() {
+    var b = 
+";
+    let mut m = Module::new(String::new(), source.to_string(), "/project/main.n".to_string(), 0);
+    m.stmts = parse_with_errors(source).0;
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    m.token_db = token_db;
+    m.token_indexes = token_indexes;
+    let ranges: Vec<(u32, u32)> = folding_ranges(&m).into_iter().map(|r| (r.start_line, r.end_line)).collect();
+    assert_eq!(ranges, vec![(0, 1)]);
+}
+
+#[test]
+fn test_parser_type_union_options() {
+    let stmts = parse("type check = fn(int|string):bool\ntype maybe = fn(int):string|null\n\nfn apply([int|null] list, vec<int|string> v, fn(int):string|null f):int {\n    return 1\n}\n");
+    let alias_type = |stmt: &Stmt| {
+        let AstNode::TypeAlias(type_alias_mutex) = &stmt.node else {
+            panic!("expect type alias")
+        };
+        let type_alias = type_alias_mutex.read().unwrap();
+        type_alias.type_expr.kind.clone()
+    };
+    let union_kinds = |kind: &TypeKind| -> Vec<String> {
+        let TypeKind::Union(_, elements) = kind else {
+            panic!("expect union, got {:?}", kind)
+        };
+        elements.iter().map(|t| t.kind.to_string()).collect()
+    };
+
+    // fn 参数中的 union 由 , 与 ) 界定
+    let TypeKind::Fn(type_fn) = alias_type(&stmts[0]) else {
+        panic!("expect fn type")
+    };
+    assert_eq!(union_kinds(&type_fn.param_types[0].kind), vec!["int", "string"]);
+    assert_eq!(type_fn.return_type.kind, TypeKind::Bool);
+
+    // fn 返回值之后的 | 属于返回值
+    let TypeKind::Fn(type_fn) = alias_type(&stmts[1]) else {
+        panic!("expect fn type")
+    };
+    assert_eq!(union_kinds(&type_fn.return_type.kind), vec!["string", "null"]);
+
+    // [] 与 <> 中的 union, 以及 param 中 fn 类型的返回值
+    let AstNode::FnDef(fndef_mutex) = &stmts[2].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    let TypeKind::Vec(element_type) = &fndef.params[0].read().unwrap().type_.kind else {
+        panic!("expect vec")
+    };
+    assert_eq!(union_kinds(&element_type.kind), vec!["int", "null"]);
+    let TypeKind::Vec(element_type) = &fndef.params[1].read().unwrap().type_.kind else {
+        panic!("expect vec")
+    };
+    assert_eq!(union_kinds(&element_type.kind), vec!["int", "string"]);
+    let TypeKind::Fn(type_fn) = &fndef.params[2].read().unwrap().type_.kind else {
+        panic!("expect fn type")
+    };
+    assert_eq!(union_kinds(&type_fn.return_type.kind), vec!["string", "null"]);
+
+    // 声明位置中的 union 只能通过 type alias 声明, T? 依旧可以使用
+    let union_errors = |source: &str| -> Vec<String> {
+        let (_, errors) = parse_with_errors(source);
+        errors.into_iter().map(|e| e.message).filter(|m| m.starts_with("union type")).collect()
+    };
+    let alias_only = vec!["union type only be declared in type alias".to_string()];
+    assert_eq!(union_errors("fn main() {\n    int|string a = 1\n}\n"), alias_only);
+    assert_eq!(union_errors("fn apply(int|null a) {\n}\n"), alias_only);
+    assert_eq!(union_errors("fn apply():int|string {\n    return 1\n}\n"), alias_only);
+    assert_eq!(union_errors("type foo = struct {\n    int|null a\n}\n"), alias_only);
+    assert!(union_errors("fn apply(int? a):string? {\n}\n").is_empty());
+
+    let (_, errors) = parse_with_errors("fn main() {\n    vec<int|string?> a = []\n}\n");
+    assert!(errors.iter().any(|e| e.message == "union type declaration cannot use '?'"), "{:?}", errors);
+}
+
+// 以括号展示二元表达式的结合方式
+fn binary_shape(expr: &Expr) -> String {
+    match &expr.node {
+        AstNode::Binary(op, left, right) => format!("({} {} {})", binary_shape(left), op, binary_shape(right)),
+        AstNode::Unary(op, operand) => format!("{}{}", op, binary_shape(operand)),
+        AstNode::Ident(ident, _) => ident.to_string(),
+        AstNode::Literal(_, value, _) => value.clone(),
+        node => panic!("unexpected node {:?}", node),
+    }
+}
+
+#[test]
+fn test_binary_associativity() {
+    let stmts = parse("var a = x - y - z\nvar b = x ** y ** z\nvar c = x * y ** 2 * z\nvar d = x * *p\nvar e = -x ** 2 + y / z / 2\n");
+    let shapes: Vec<String> = stmts.iter().map(|stmt| binary_shape(var_def_right(stmt))).collect();
+    assert_eq!(
+        shapes,
+        vec![
+            "((x - y) - z)",
+            "(x ** (y ** z))",
+            "((x * (y ** 2)) * z)",
+            "(x * *p)",
+            "((-x ** 2) + ((y / z) / 2))",
+        ]
+    );
+
+    // ** 不会被格式化拆开
+    let formatted = format_source("fn main() {\n    var a = x**y * *p\n}\n", &FormatConfig::default()).unwrap();
+    assert_eq!(formatted, "fn main() {\n    var a = x ** y * *p\n}\n");
+}
+
+#[test]
+fn test_promise_type() {
+    let source = "fn foo():promise<int> {\n}\n\nasync fn bar():int {\n    return 1\n}\n\nasync fn baz() {\n}\n";
+    let mut m = module_new(source, "/project/main.n", 0);
+
+    let return_type = |stmt: &Stmt| {
+        let AstNode::FnDef(fndef_mutex) = &stmt.node else { panic!("expect fn def") };
+        let fndef = fndef_mutex.read().unwrap();
+        (fndef.is_async, fndef.return_type.kind.clone())
+    };
+
+    let (is_async, kind) = return_type(&m.stmts[0]);
+    assert!(!is_async);
+    assert!(matches!(kind, TypeKind::Promise(ref value) if value.kind == TypeKind::Int));
+
+    let (is_async, kind) = return_type(&m.stmts[1]);
+    assert!(is_async);
+    assert_eq!(kind, TypeKind::Int);
+
+    let mut symbol_table = SymbolTable::new();
+    Semantic::new(&mut m, &mut symbol_table).analyze();
+
+    // async fn 的返回值类型被包装为 promise
+    let (_, kind) = return_type(&m.stmts[1]);
+    assert!(matches!(kind, TypeKind::Promise(ref value) if value.kind == TypeKind::Int));
+    let AstNode::FnDef(fndef_mutex) = &m.stmts[1].node else {
+        panic!("expect fn def")
+    };
+    assert_eq!(fndef_mutex.read().unwrap().return_type.to_string(), "promise<int>");
+
+    // 已经是 promise 或者 void 的返回值类型保持不变
+    let (_, kind) = return_type(&m.stmts[0]);
+    assert!(matches!(kind, TypeKind::Promise(ref value) if value.kind == TypeKind::Int));
+    let (_, kind) = return_type(&m.stmts[2]);
+    assert_eq!(kind, TypeKind::Void);
+}
+
+#[test]
+fn test_inlay_type_hints() {
+    let source = r#"fn make_pair():(int, bool) {
+    return (1, true)
+}
+
+fn main() {
+    var x = make_pair()
+    int y = 1
+    var (a, b) = make_pair()
+    var m = {'a': 1}
+    for k, v in m {
+    }
+    var u = not_exist()
+}
+"#;
+    let (m, symbol_table) = module_analyze_with_symbols(source);
+
+    let labels = |config: &InlayHintConfig| -> Vec<(Position, String)> {
+        inlay_hints(&m, &symbol_table, config)
+            .into_iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("expect string label")
+                };
+                (hint.position, label)
+            })
+            .collect()
+    };
+
+    let config = InlayHintConfig::default();
+    assert_eq!(
+        labels(&config),
+        vec![
+            (Position::new(5, 9), ": (int, bool)".to_string()),
+            (Position::new(7, 10), ": int".to_string()),
+            (Position::new(7, 13), ": bool".to_string()),
+            (Position::new(8, 9), ": map<string, i64>".to_string()),
+            (Position::new(9, 9), ": string".to_string()),
+            (Position::new(9, 12), ": i64".to_string()),
+        ]
+    );
+
+    // 接受 hint 时将 var 替换为推导出的类型
+    let hints = inlay_hints(&m, &symbol_table, &config);
+    let edits = hints[3].text_edits.as_ref().unwrap();
+    assert_eq!(edits[0].range, Range::new(Position::new(8, 4), Position::new(8, 7)));
+    assert_eq!(edits[0].new_text, "map<string,i64>");
+    assert!(hints[1].text_edits.is_none());
+
+    // 超过长度的类型被截断
+    let config = InlayHintConfig {
+        max_length: 5,
+        ..Default::default()
+    };
+    assert_eq!(labels(&config)[3].1, ": map<…");
+
+    let config = InlayHintConfig {
+        type_hints: false,
+        ..Default::default()
+    };
+    assert!(labels(&config).is_empty());
+}
+
+#[test]
+fn test_inlay_parameter_hints() {
+    let source = r#"fn resize(int width, int height, bool animate) {
+}
+
+fn log(string msg) {
+}
+
+fn sum(int base, ...[int] list):int {
+    return base
+}
+
+fn main() {
+    resize(10, 20, true)
+    int height = 20
+    resize(1, height, false)
+    log('hello')
+    var total = sum(1, 2, 3)
+    not_exist(1, 2)
+}
+"#;
+    let (m, symbol_table) = module_analyze_with_symbols(source);
+
+    let labels = |config: &InlayHintConfig| -> Vec<(Position, String)> {
+        inlay_hints(&m, &symbol_table, config)
+            .into_iter()
+            .filter(|hint| hint.kind == Some(InlayHintKind::PARAMETER))
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("expect string label")
+                };
+                (hint.position, label)
+            })
+            .collect()
+    };
+
+    // 同名的 ident 实参, 单个实参的 call, rest param 与未解析的 callee 都没有 hint
+    let config = InlayHintConfig::default();
+    assert_eq!(
+        labels(&config),
+        vec![
+            (Position::new(11, 11), "width:".to_string()),
+            (Position::new(11, 15), "height:".to_string()),
+            (Position::new(11, 19), "animate:".to_string()),
+            (Position::new(13, 11), "width:".to_string()),
+            (Position::new(13, 22), "animate:".to_string()),
+            (Position::new(15, 20), "base:".to_string()),
+        ]
+    );
+
+    // 与 type hint 分别开关
+    let config = InlayHintConfig {
+        type_hints: false,
+        ..Default::default()
+    };
+    assert_eq!(labels(&config).len(), 6);
+    assert!(inlay_hints(&m, &symbol_table, &config)
+        .iter()
+        .all(|hint| hint.kind == Some(InlayHintKind::PARAMETER)));
+
+    let config = InlayHintConfig {
+        parameter_hints: false,
+        ..Default::default()
+    };
+    assert!(labels(&config).is_empty());
+    assert_eq!(inlay_hints(&m, &symbol_table, &config).len(), 1);
+}
+
+#[test]
+fn test_comment_only_fn_body() {
+    let fndef = |source: &str| {
+        let stmts = parse(source);
+        let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+            panic!("expect fn def")
+        };
+        let fndef = fndef_mutex.read().unwrap();
+        (fndef.is_tpl, fndef.body.len())
+    };
+
+    assert_eq!(fndef("fn foo() { /* empty */ }\n"), (false, 0));
+    assert_eq!(fndef("fn foo() {\n    // empty\n}\n"), (false, 0));
+    assert_eq!(fndef("fn foo() {}\n"), (false, 0));
+    assert_eq!(fndef("fn foo()\n"), (true, 0));
+}
+
+#[test]
+fn test_literal_source() {
+    let stmts = parse("var a = null\nvar b = -1\n");
+    assert!(matches!(
+        var_def_right(&stmts[0]).node,
+        AstNode::Literal(TypeKind::Null, _, LiteralSource::UserWritten)
+    ));
+    assert!(matches!(
+        var_def_right(&stmts[1]).node,
+        AstNode::Literal(TypeKind::Int, _, LiteralSource::UserWritten)
+    ));
+
+    // @async 缺省的 flag 参数由 typesys 生成
+    let m = module_analyze("fn foo():int {\n    return 1\n}\n\nfn main() {\n    var f = @async(foo())\n}\n");
+    let main = m
+        .stmts
+        .iter()
+        .find_map(|stmt| match &stmt.node {
+            AstNode::FnDef(fndef_mutex) if fndef_mutex.read().unwrap().fn_name == "main" => Some(fndef_mutex.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let main = main.read().unwrap();
+    let AstNode::Call(call) = &var_def_right(&main.body[0]).node else {
+        panic!("expect async call")
+    };
+    assert!(matches!(call.args[1].node, AstNode::Literal(_, _, LiteralSource::Synthesized)));
+}
+
+#[test]
+fn test_missing_fields_action() {
+    let source = r#"type point = struct {
+    int x
+    string name = 'origin'
+    string path = 'a\\b\'c'
+    fn():void handler
+}
+
+fn main() {
+    var a = point{}
+    var b = point{
+        x = 1
+    }
+}
+"#;
+    let m = module_analyze(source);
+    let uri = Url::parse("file:///project/main.n").unwrap();
+
+    let diagnostics: Vec<Diagnostic> = m.analyzer_errors.iter().filter_map(|e| to_diagnostic(&m, e)).collect();
+    assert_eq!(diagnostics.len(), 2, "{:?}", m.analyzer_errors);
+
+    let edit = |diagnostic: &Diagnostic| {
+        let action = missing_fields_action(&m, &uri, diagnostic).unwrap();
+        assert_eq!(action.title, "Add missing fields");
+        assert_eq!(action.diagnostics.as_ref().unwrap()[0], *diagnostic);
+        action.edit.unwrap().changes.unwrap()[&uri][0].clone()
+    };
+
+    let empty = edit(&diagnostics[0]);
+    assert_eq!(empty.range.start, Position::new(8, 18));
+    assert_eq!(empty.new_text, "x = 0, name = 'origin', path = 'a\\\\b\\'c', handler = @default()");
+
+    let partial = edit(&diagnostics[1]);
+    assert_eq!(partial.range.start, Position::new(10, 13));
+    assert_eq!(
+        partial.new_text,
+        ",\n        name = 'origin',\n        path = 'a\\\\b\\'c',\n        handler = @default()"
+    );
+
+    // 同一范围上的其他诊断不提供补全属性的 action
+    let unrelated = Diagnostic::new_simple(diagnostics[0].range, "type inconsistency".to_string());
+    assert!(missing_fields_action(&m, &uri, &unrelated).is_none());
+}
+
+#[test]
+fn test_insert_expected_token_action() {
+    let uri = Url::parse("file:///project/main.n").unwrap();
+
+    // diagnostics -> code action -> apply edit
+    let fix = |source: &str| -> Vec<(String, String)> {
+        let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+        let mut syntax = Syntax::new(token_db.clone(), token_indexes.clone());
+        let (stmts, _, errors) = syntax.parser();
+
+        let mut m = Module::new(String::new(), source.to_string(), "/project/main.n".to_string(), 0);
+        m.stmts = stmts;
+        m.token_db = token_db;
+        m.token_indexes = token_indexes;
+        m.expected_tokens = syntax.expected_tokens();
+
+        let error = &errors[0];
+        let range = Range::new(
+            offset_to_position(error.span.start as usize, &m.line_index).unwrap(),
+            offset_to_position(error.span.end as usize, &m.line_index).unwrap(),
+        );
+        let diagnostic = Diagnostic::new_simple(range, error.message.clone());
+
+        insert_expected_token_actions(&m, &uri, &diagnostic)
+            .into_iter()
+            .map(|action| {
+                let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+                let mut rope = m.rope.clone();
+                let offset = rope.line_to_char(edit.range.start.line as usize) + edit.range.start.character as usize;
+                rope.insert(offset, &edit.new_text);
+                (action.title, rope.to_string())
+            })
+            .collect()
+    };
+
+    let fixed = fix("fn main() {\n    var a = foo(1, 2\n    var b = 1\n}\n");
+    assert_eq!(fixed.iter().map(|f| f.0.as_str()).collect::<Vec<_>>(), vec!["Insert ')'", "Insert ','"]);
+    parse(&fixed[0].1);
+
+    let fixed = fix("fn main() {\n    var a = 1 var b = 2\n}\n");
+    assert_eq!(fixed.iter().map(|f| f.0.as_str()).collect::<Vec<_>>(), vec!["Insert ';'", "Insert '}'"]);
+    assert_eq!(fixed[0].1, "fn main() {\n    var a = 1; var b = 2\n}\n");
+    parse(&fixed[0].1);
+
+    let fixed = fix("fn main() {\n    var a = 1\n");
+    assert_eq!(fixed[0].0, "Insert '}'");
+    parse(&fixed[0].1);
+
+    // 相邻的两个错误被合并为一个诊断, 依旧提供两个错误各自期望的 token
+    let fixed = fix("fn main() {\n    if a {\n    var b = [1, 2\n}\n");
+    assert_eq!(fixed.iter().map(|f| f.0.as_str()).collect::<Vec<_>>(), vec!["Insert ']'", "Insert '}'"]);
+    assert_eq!(fixed[0].1, "fn main() {\n    if a {\n    var b = [1, 2]\n}\n");
+}
+
+#[test]
+fn test_for_range_step() {
+    let source = "fn main() {\n    for i in 0..10 step 2 {\n        var a = i\n    }\n    for i in 10..0 step -1 {\n        var b = i\n    }\n    var list = [1, 2, 3]\n    for v in list step 2 {\n        var c = v\n    }\n}\n";
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let AstNode::FnDef(fndef_mutex) = &m.stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+
+    let AstNode::ForIterator(iterate, first, second, step, _) = &fndef.body[0].node else {
+        panic!("expect for iterator")
+    };
+    assert!(matches!(iterate.node, AstNode::Range(..)));
+    assert!(second.is_none());
+    assert_eq!(first.read().unwrap().type_.kind, TypeKind::Int64);
+    assert!(matches!(step.as_ref().unwrap().node, AstNode::Literal(_, ref value, _) if value == "2"));
+
+    let AstNode::ForIterator(iterate, _, _, step, _) = &fndef.body[1].node else {
+        panic!("expect for iterator")
+    };
+    let AstNode::Range(start, end) = &iterate.node else { panic!("expect range") };
+    assert!(matches!(start.node, AstNode::Literal(_, ref value, _) if value == "10"));
+    assert!(matches!(end.node, AstNode::Literal(_, ref value, _) if value == "0"));
+    assert!(matches!(step.as_ref().unwrap().node, AstNode::Literal(_, ref value, _) if value == "-1"));
+
+    let AstNode::ForIterator(iterate, _, _, step, _) = &fndef.body[3].node else {
+        panic!("expect for iterator")
+    };
+    assert!(matches!(iterate.node, AstNode::Ident(..)));
+    assert!(step.is_some());
+
+    // step 必须是整数
+    let m = module_analyze("fn main() {\n    for i in 0..10 step true {\n    }\n}\n");
+    assert!(!m.analyzer_errors.is_empty());
+}
+
+#[test]
+fn test_macro_line_file() {
+    let source = format!("{}fn main() {{\n    var line = @line()\n    var file = @file()\n}}\n", "\n".repeat(40));
+    let (token_db, token_indexes, _) = Lexer::new(source).scan();
+    let config = ParserConfig {
+        source_path: "/project/main.n".to_string(),
+        ..Default::default()
+    };
+    let (stmts, _, errors) = Syntax::with_config(token_db, token_indexes, config).parser();
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+
+    let line = var_def_right(&fndef.body[0]);
+    assert!(matches!(line.node, AstNode::Literal(TypeKind::Int, ref value, LiteralSource::Synthesized) if value == "42"));
+
+    let file = var_def_right(&fndef.body[1]);
+    assert!(matches!(file.node, AstNode::Literal(TypeKind::String, ref value, _) if value == "/project/main.n"));
+}
+
+#[test]
+fn test_require_stmt_eof() {
+    let parse_strict = |source: &str, require_stmt_eof: bool| {
+        let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+        let config = ParserConfig {
+            require_stmt_eof,
+            ..Default::default()
+        };
+        Syntax::with_config(token_db, token_indexes, config).parser().2
+    };
+
+    // 默认 EOF 与 } 可以作为隐式的结束符
+    assert!(parse_strict("int x = 1", false).is_empty());
+    assert!(parse_strict("fn main() {\n    int x = 1; int y = 2 }\n", false).is_empty());
+
+    // 严格模式下缺少 ; 的错误报告在 stmt 的最后一个 token 上
+    let errors = parse_strict("int x = 1", true);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].span, Span::new(8, 9));
+    assert_eq!(errors[0].message, "expected ';' at end of statement");
+
+    let errors = parse_strict("fn main() {\n    int x = 1; int y = 2 }\n", true);
+    assert!(
+        errors.iter().any(|e| e.span.start == 35 && e.message == "expected ';' at end of statement"),
+        "{:?}",
+        errors
+    );
+
+    // 显式的 ; 或换行满足严格模式
+    assert!(parse_strict("int x = 1;", true).is_empty());
+    assert!(parse_strict("fn main() {\n    int x = 1\n    int y = 2;\n};", true).is_empty());
+}
+
+#[test]
+fn test_macro_assert() {
+    let stmts = parse("fn main() {\n    var a = 1\n    var b = 2\n    @assert(a == b)\n    @assert(a  >  0 && b != 'x') // check\n}\n");
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+
+    let implicit_msg = |stmt: &Stmt| -> String {
+        let AstNode::Fake(expr) = &stmt.node else {
+            panic!("expect fake, got {:?}", stmt.node)
+        };
+        let AstNode::MacroAssert(_, implicit_msg) = &expr.node else {
+            panic!("expect assert, got {:?}", expr.node)
+        };
+        let AstNode::Literal(TypeKind::String, value, LiteralSource::Synthesized) = &implicit_msg.node else {
+            panic!("expect string literal")
+        };
+        value.clone()
+    };
+    assert_eq!(implicit_msg(&fndef.body[2]), "a == b");
+    assert_eq!(implicit_msg(&fndef.body[3]), "a  >  0 && b != 'x'");
+
+    // 没有源码时通过 token 还原, 字符串中的引号与转义字符需要重新转义
+    let source = "fn main() {\n    @assert(s != 'a\"b\\n')\n}\n";
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let (stmts, _, _) = Syntax::new(token_db, token_indexes).parser();
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    assert_eq!(implicit_msg(&fndef_mutex.read().unwrap().body[0]), "s != \"a\\\"b\\n\"");
+
+    // 有源码时直接使用源码文本
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    assert_eq!(implicit_msg(&fndef_mutex.read().unwrap().body[0]), "s != 'a\"b\\n'");
+
+    // cond 必须是 bool 类型
+    let m = module_analyze("fn main() {\n    var a = 1\n    @assert(a > 0)\n}\n");
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    let m = module_analyze("fn main() {\n    var a = 1\n    @assert(a)\n}\n");
+    assert!(!m.analyzer_errors.is_empty());
+}
+
+#[test]
+fn test_macro_ula_addressable() {
+    let stmts = parse("fn main() {\n    var p = @ula(x)\n    var q = @ula(list[0])\n    var r = @ula(foo.bar)\n}\n");
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    assert!(matches!(var_def_right(&fndef.body[0]).node, AstNode::MacroUla(ref src) if matches!(src.node, AstNode::Ident(..))));
+    assert!(matches!(var_def_right(&fndef.body[1]).node, AstNode::MacroUla(ref src) if matches!(src.node, AstNode::AccessExpr(..))));
+    assert!(matches!(var_def_right(&fndef.body[2]).node, AstNode::MacroUla(_)));
+
+    let source = "fn main() {\n    var p = @ula(42)\n    var q = @ula(a + 1)\n}\n";
+    let (_, errors) = parse_with_errors(source);
+    let messages: Vec<(usize, &str)> = errors.iter().map(|e| (e.span.start as usize, e.message.as_str())).collect();
+    assert_eq!(
+        messages,
+        vec![
+            (source.find("42").unwrap(), "ula requires an addressable expression"),
+            (source.find("a + 1").unwrap(), "ula requires an addressable expression"),
+        ]
+    );
+}
+
+#[test]
+fn test_macro_bitcast() {
+    let stmts = parse("fn main() {\n    var a = @bitcast<u32>(x)\n    var b = @bitcast(f64, y + 1)\n}\n");
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    assert!(
+        matches!(var_def_right(&fndef.body[0]).node, AstNode::MacroBitCast(ref t, ref src) if t.kind == TypeKind::Uint32 && matches!(src.node, AstNode::Ident(..)))
+    );
+    assert!(
+        matches!(var_def_right(&fndef.body[1]).node, AstNode::MacroBitCast(ref t, ref src) if t.kind == TypeKind::Float64 && matches!(src.node, AstNode::Binary(..)))
+    );
+    drop(fndef);
+
+    // 类型参数与值参数都是必须的
+    let (_, errors) = parse_with_errors("fn main() {\n    var a = @bitcast<u32>()\n    var b = @bitcast(x)\n}\n");
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+
+    let source = "fn main() {\n    f32 x = 1.0\n    u32 a = @bitcast<u32>(x)\n    u64 z = 0\n    f64 b = @bitcast(f64, z)\n    u8 c = @bitcast<u8>(x)\n}\n";
+    let m = module_analyze(source);
+    let messages: Vec<(usize, &str)> = m.analyzer_errors.iter().map(|e| (e.span.start as usize, e.message.as_str())).collect();
+    assert_eq!(
+        messages,
+        vec![(
+            source.find("@bitcast<u8>").unwrap(),
+            "bitcast size mismatch, 'f32' is 4 bytes but 'u8' is 1 bytes"
+        )]
+    );
+}
+
+#[test]
+fn test_macro_min_max() {
+    let stmts = parse("fn main() {\n    var a = @min(1, 2)\n    var b = @max(a, 3, a + 1)\n}\n");
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    assert!(matches!(var_def_right(&fndef.body[0]).node, AstNode::MacroMinMax(MinMaxKind::Min, ref args) if args.len() == 2));
+    assert!(matches!(var_def_right(&fndef.body[1]).node, AstNode::MacroMinMax(MinMaxKind::Max, ref args) if args.len() == 3));
+
+    // 结果类型与参数类型一致
+    let m = module_analyze("fn main() {\n    f64 x = 1.5\n    f64 y = 2.5\n    f64 z = 0.5\n    var m = @max(x, y, z)\n    i8 n = @min(1, 2)\n}\n");
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    let AstNode::FnDef(fndef_mutex) = &m.stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    let AstNode::VarDef(var_decl, _) = &fndef.body[3].node else {
+        panic!("expect var def")
+    };
+    assert!(matches!(var_decl.read().unwrap().type_.kind, TypeKind::Float64));
+    assert!(matches!(var_def_right(&fndef.body[4]).type_.kind, TypeKind::Int8));
+
+    // semantic 与 typesys 之后仍然是 MacroMinMax, 参数保持原样, 不会被改写为比较表达式
+    let AstNode::MacroMinMax(MinMaxKind::Max, args) = &var_def_right(&fndef.body[3]).node else {
+        panic!("expect min max")
+    };
+    assert_eq!(args.len(), 3);
+    assert!(args
+        .iter()
+        .all(|arg| matches!(arg.node, AstNode::Ident(..)) && matches!(arg.type_.kind, TypeKind::Float64)));
+
+    let (_, errors) = parse_with_errors("fn main() {\n    var a = @min(1)\n}\n");
+    assert!(errors.iter().any(|e| e.message == "min requires at least 2 arguments"), "{:?}", errors);
+
+    let m = module_analyze("fn main() {\n    f64 x = 1.5\n    int y = 2\n    var m = @max(x, y)\n}\n");
+    assert!(
+        m.analyzer_errors
+            .iter()
+            .any(|e| e.message == "max args must be the same type, expected 'f64', actual 'int'"),
+        "{:?}",
+        m.analyzer_errors
+    );
+    let m = module_analyze("fn main() {\n    var m = @min('a', 'b')\n}\n");
+    assert!(
+        m.analyzer_errors
+            .iter()
+            .any(|e| e.message == "min only support number operand, actual 'string'"),
+        "{:?}",
+        m.analyzer_errors
+    );
+}
+
+#[test]
+fn test_specify_type_action() {
+    let source = r#"type box<T> = struct {
+    T value
+}
+
+fn make():box<int> {
+    return box<int>{value = 1}
+}
+
+fn anon():struct{int x} {
+    return @default()
+}
+
+fn main() {
+    var a = make()
+    var b = {'k': [make()]}
+    var c = anon()
+    box<int> d = make()
+    i8 e = 1
+}
+"#;
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    let uri = Url::parse("file:///project/main.n").unwrap();
+
+    let config = CompletionConfig::default();
+    let ctx = AutoImportContext {
+        nature_root: "/nature",
+        package_config: None,
+        config: &config,
+    };
+    let module_db: Vec<Module> = Vec::new();
+    let at = |line: u32, character: u32| Range::new(Position::new(line, character), Position::new(line, character));
+
+    let specify = |range: Range| {
+        let action = specify_type_action(&m, &module_db, &uri, range, &ctx)?;
+        let edit = action.edit.unwrap().changes.unwrap()[&uri][0].clone();
+        Some((action.title, edit))
+    };
+
+    // 光标位于 var 或者变量名(inlay hint 位置)上
+    let (title, edit) = specify(at(13, 4)).unwrap();
+    assert_eq!(title, "Specify type: box<int>");
+    assert_eq!(edit.range, Range::new(Position::new(13, 4), Position::new(13, 7)));
+    assert_eq!(edit.new_text, "box<int>");
+
+    let (title, _) = specify(at(14, 9)).unwrap();
+    assert_eq!(title, "Specify type: map<string,vec<box<int>>>");
+
+    // 匿名 struct 无法书写, 显式声明类型的变量不需要 specify
+    assert!(specify(at(15, 8)).is_none());
+    assert!(specify(at(16, 13)).is_none());
+    assert!(specify(at(13, 20)).is_none());
+
+    // use var 的右值必须自带类型
+    let action = use_var_action(&m, &module_db, &uri, at(16, 4), &ctx).unwrap();
+    let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+    assert_eq!(action.title, "Use var");
+    assert_eq!(edit.range, Range::new(Position::new(16, 4), Position::new(16, 13)));
+    assert_eq!(edit.new_text, "var ");
+    assert!(use_var_action(&m, &module_db, &uri, at(17, 4), &ctx).is_none());
+}
+
+#[test]
+fn test_create_fn_stub_action() {
+    let source = r#"type person = struct {
+    string name
+}
+
+type pair<T, U> = struct {
+    T first
+    U second
+}
+
+fn main() {
+    var p = person{name = 'alice'}
+    int count = 1
+    string title = 'hi'
+    p.greet(count, title)
+    bool ok = p.check(count)
+    var pa = pair<int, bool>{first = 1, second = true}
+    pa.swap(title)
+    say(title, count)
+}"#;
+    let m = module_analyze(source);
+    let uri = Url::parse("file:///project/main.n").unwrap();
+
+    let config = CompletionConfig::default();
+    let ctx = AutoImportContext {
+        nature_root: "/nature",
+        package_config: None,
+        config: &config,
+    };
+    let module_db: Vec<Module> = Vec::new();
+
+    let diagnostics: Vec<Diagnostic> = m
+        .analyzer_errors
+        .iter()
+        .map(|e| {
+            let range = Range::new(
+                offset_to_position(e.span.start as usize, &m.line_index).unwrap(),
+                offset_to_position(e.span.end as usize, &m.line_index).unwrap(),
+            );
+            Diagnostic::new_simple(range, e.message.clone())
+        })
+        .collect();
+    // semantic 阶段的 undeclared 错误先于 typesys 阶段的错误
+    assert_eq!(diagnostics.len(), 4, "{:?}", m.analyzer_errors);
+
+    let method = |diagnostic: &Diagnostic| {
+        let action = create_method_action(&m, &module_db, &uri, diagnostic, &ctx).unwrap();
+        let edit = action.edit.unwrap().changes.unwrap()[&uri][0].clone();
+        (action.title, edit)
+    };
+
+    let (title, edit) = method(&diagnostics[1]);
+    assert_eq!(title, "Create method person.greet");
+    assert_eq!(edit.range.start, Position::new(2, 1));
+    assert_eq!(edit.new_text, "\n\nfn person.greet(int count, string title):void {\n}");
+
+    // 返回值类型来自显式声明的变量
+    let (_, edit) = method(&diagnostics[2]);
+    assert_eq!(edit.new_text, "\n\nfn person.check(int count):bool {\n}");
+
+    let (title, edit) = method(&diagnostics[3]);
+    assert_eq!(title, "Create method pair.swap");
+    assert_eq!(edit.range.start, Position::new(7, 1));
+    assert_eq!(edit.new_text, "\n\nfn pair<T, U>.swap(string title):void {\n}");
+
+    assert!(create_method_action(&m, &module_db, &uri, &diagnostics[0], &ctx).is_none());
+    let action = create_function_action(&m, &module_db, &uri, &diagnostics[0], &ctx).unwrap();
+    let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+    assert_eq!(action.title, "Create function say");
+    assert_eq!(edit.new_text, "\n\nfn say(string title, int count):void {\n}\n");
+}
+
+#[test]
+fn test_linkid_fn_requires_name() {
+    let (_, errors) = parse_with_errors("#linkid malloc\nfn(int size):anyptr\n");
+    assert!(!errors.is_empty());
+    assert_eq!(errors[0].message, "linkid function must have a name");
+    assert_eq!(errors[0].span, Span::new(17, 18));
+
+    let (_, errors) = parse_with_errors("#linkid rt_exit\nfn exit(int code)\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "linkid template function must declare a return type");
+
+    parse("#linkid rt_exit\nfn exit(int code):void\n\n#linkid malloc\nfn malloc(int size):anyptr\n");
+}
+
+#[test]
+fn test_format_idempotent() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/format");
+    let config = FormatConfig::default();
+
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let source = std::fs::read_to_string(&path).unwrap();
+
+        let formatted = format_source(&source, &config).unwrap_or_else(|| panic!("format {:?} failed", path));
+        assert_eq!(format_source(&formatted, &config).as_deref(), Some(formatted.as_str()), "{:?}", path);
+        count += 1;
+    }
+    assert!(count > 0);
+}
+
+#[test]
+fn test_format_source() {
+    let config = FormatConfig::default();
+    let formatted = format_source("fn main(){\nvar a=1+2 // sum\n\n\n  var b=[a,-a] ; call(a,b)\n}", &config).unwrap();
+    assert_eq!(formatted, "fn main() {\n    var a = 1 + 2 // sum\n\n    var b = [a, -a];\n    call(a, b)\n}\n");
+
+    // 超过 max_width 时每个参数独占一行, 右括号跟随最后一个参数
+    let config = FormatConfig {
+        indent_width: 2,
+        max_width: 20,
+    };
+    let formatted = format_source("fn main() {\n  call(first, second, third)\n}\n", &config).unwrap();
+    assert_eq!(formatted, "fn main() {\n  call(\n    first,\n    second,\n    third)\n}\n");
+
+    // 存在语法错误时不进行格式化
+    assert!(format_source("fn main() {\n  var a = \n", &FormatConfig::default()).is_none());
+}
+
+#[test]
+fn test_implicit_type_cast() {
+    let source = "type number = int|string\n\nfn main() {\n    i8 a = 1\n    i16 b = 2\n    var c = a + b\n    var d = b * a\n    number e = 1\n}\n";
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let AstNode::FnDef(fndef_mutex) = &m.stmts[1].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+
+    // 较窄的操作数被 TypeCast 包裹, 不会产生 as 节点
+    let right = var_def_right(&fndef.body[2]);
+    assert_eq!(right.type_.kind, TypeKind::Int16);
+    let AstNode::Binary(_, left, _) = &right.node else { panic!("expect binary") };
+    let AstNode::TypeCast(target_type, src) = &left.node else {
+        panic!("expect type cast, got {:?}", left.node)
+    };
+    assert_eq!(target_type.kind, TypeKind::Int16);
+    assert!(matches!(src.node, AstNode::Ident(ref ident, _) if ident == "a"));
+
+    let AstNode::Binary(_, _, right) = &var_def_right(&fndef.body[3]).node else {
+        panic!("expect binary")
+    };
+    assert!(matches!(right.node, AstNode::TypeCast(..)));
+
+    assert!(matches!(var_def_right(&fndef.body[4]).node, AstNode::TypeCast(..)));
+
+    // 符号不同时不会隐式扩展
+    let m = module_analyze("fn main() {\n    i8 a = 1\n    u16 b = 2\n    var c = a + b\n}\n");
+    assert!(!m.analyzer_errors.is_empty());
+
+    // 复合的右操作数中的字面量基于左操作数的类型推导, 不会扩展为 int
+    let m = module_analyze("fn main() {\n    i8 a = 1\n    i8 c = a + 2*3\n    i8 d = a + (1)\n    i8 e = a - (2 + 3) * 4\n}\n");
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let AstNode::FnDef(fndef_mutex) = &m.stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    for stmt in &fndef.body[1..] {
+        let right = var_def_right(stmt);
+        assert_eq!(right.type_.kind, TypeKind::Int8);
+        let AstNode::Binary(_, left, right) = &right.node else {
+            panic!("expect binary")
+        };
+        assert!(matches!(left.node, AstNode::Ident(..)), "{:?}", left.node);
+        assert_eq!(right.type_.kind, TypeKind::Int8);
+    }
+}
+
+#[test]
+fn test_type_params_reset_on_error() {
+    let return_type_kind = |source: &str, fn_name: &str| {
+        let (stmts, errors) = parse_with_errors(source);
+        assert!(!errors.is_empty());
+
+        let fndef = stmts
+            .iter()
+            .find_map(|s| match &s.node {
+                AstNode::FnDef(fndef) if fndef.read().unwrap().fn_name == fn_name => Some(fndef.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let kind = fndef.read().unwrap().return_type.kind.clone();
+        kind
+    };
+
+    // 错误的泛型声明不会影响后续函数中同名的类型
+    let kind = return_type_kind("type box<T> int\n\nfn get(T value):T {\n    return value\n}\n", "get");
+    assert!(matches!(kind, TypeKind::Alias(..)), "{:?}", kind);
+
+    let kind = return_type_kind(
+        "fn get<T>(T value) T {\n    return value\n}\n\nfn put(T value):T {\n    return value\n}\n",
+        "put",
+    );
+    assert!(matches!(kind, TypeKind::Alias(..)), "{:?}", kind);
+}
+
+#[test]
+fn test_fn_test_label() {
+    let stmts = parse("#test\nfn my_test() {\n    var a = 1\n}\n\n#test\nfn check():void {\n}\n\nfn helper(int a):int {\n    return a\n}\n");
+    let tests = collect_tests(&stmts);
+    assert_eq!(tests.len(), 2);
+    assert_eq!(tests[0].read().unwrap().fn_name, "my_test");
+    assert_eq!(tests[1].read().unwrap().fn_name, "check");
+
+    let (_, errors) = parse_with_errors("#test\nfn my_test(int a) {\n}\n");
+    assert_eq!(errors[0].message, "test function cannot have parameters");
+    assert_eq!(errors[0].span, Span::new(9, 16));
+
+    let (_, errors) = parse_with_errors("#test\nfn my_test():int {\n    return 1\n}\n");
+    assert_eq!(errors[0].message, "test function must return void");
+}
+
+#[test]
+fn test_selection_range() {
+    let source = "fn main() {\n    var a = 1\n    match a {\n        1 -> {\n            call(sum(a, 2))\n        }\n        _ -> call(0)\n    }\n}\n";
+    let m = module_new(source, "/project/main.n", 0);
+
+    let chars: Vec<char> = source.chars().collect();
+    let offset = source.find("sum(a").unwrap() + 4;
+    let path: Vec<String> = node_path_at(&m, offset).iter().map(|span| chars[span.range()].iter().collect()).collect();
+
+    let arm = "1 -> {\n            call(sum(a, 2))\n        }";
+    let cases = format!("{}\n        _ -> call(0)", arm);
+    let fn_body = format!("var a = 1\n    match a {{\n        {}\n    }}", cases);
+    assert_eq!(
+        path,
+        vec![
+            "a".to_string(),
+            "sum(a, 2)".to_string(),
+            "call(sum(a, 2))".to_string(),
+            "{\n            call(sum(a, 2))\n        }".to_string(),
+            arm.to_string(),
+            cases.clone(),
+            format!("{{\n        {}\n    }}", cases),
+            format!("match a {{\n        {}\n    }}", cases),
+            fn_body.clone(),
+            format!("{{\n    {}\n}}", fn_body),
+            format!("fn main() {{\n    {}\n}}", fn_body),
+            source.to_string(),
+        ]
+    );
+
+    // lsp selection range 由内到外通过 parent 连接, 且严格嵌套
+    let ranges = selection_ranges(&m, &[offset]);
+    assert_eq!(ranges.len(), 1);
+    let mut depth = 0;
+    let mut current = Some(&ranges[0]);
+    while let Some(selection) = current {
+        if let Some(parent) = &selection.parent {
+            assert!(parent.range.start <= selection.range.start && selection.range.end <= parent.range.end);
+            assert_ne!(parent.range, selection.range);
+        }
+        depth += 1;
+        current = selection.parent.as_deref();
+    }
+    assert_eq!(depth, path.len());
+    assert_eq!(ranges[0].range, Range::new(Position::new(4, 21), Position::new(4, 22)));
+
+    // 每个 position 都有对应的结果, 超出文件范围的 position 同样返回 range
+    let ranges = selection_ranges(&m, &[offset, source.len() + 10, 0]);
+    assert_eq!(ranges.len(), 3);
+    assert_eq!(ranges[0].range, Range::new(Position::new(4, 21), Position::new(4, 22)));
+    assert_eq!(ranges[2].range.start, Position::new(0, 0));
+
+    // 类型标注同样参与选择
+    let source = "fn main() {\n    vec<[int]> list = []\n}\n";
+    let m = module_new(source, "/project/main.n", 0);
+    let chars: Vec<char> = source.chars().collect();
+    let path: Vec<String> = node_path_at(&m, source.find("int").unwrap() + 1)
+        .iter()
+        .map(|span| chars[span.range()].iter().collect())
+        .collect();
+    assert_eq!(path[..4], ["int", "[int]", "vec<[int]>", "vec<[int]> list = []"]);
+}
+
+#[test]
+fn test_struct_new_spread() {
+    let source = "type foo = struct {\n    int a\n    int b\n    int c\n}\n\nfn main() {\n    var partial = foo{a = 0, b = 0, c = 0}\n    var f = foo{a = 1, ...partial, b = 2}\n    var g = foo{...partial, ...partial}\n}\n";
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let AstNode::FnDef(fndef_mutex) = &m.stmts[1].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+
+    let AstNode::StructNew(_, _, entries) = &var_def_right(&fndef.body[1]).node else {
+        panic!("expect struct new")
+    };
+    assert_eq!(entries.len(), 3);
+    assert!(matches!(&entries[0], StructNewEntry::Property(p) if p.key == "a"));
+    assert!(matches!(&entries[1], StructNewEntry::Spread(e) if matches!(e.node, AstNode::Ident(ref ident, _) if ident == "partial")));
+    assert!(matches!(&entries[2], StructNewEntry::Property(p) if p.key == "b"));
+
+    let AstNode::StructNew(_, _, entries) = &var_def_right(&fndef.body[2]).node else {
+        panic!("expect struct new")
+    };
+    assert!(entries.iter().all(|e| matches!(e, StructNewEntry::Spread(_))));
+
+    // spread 表达式必须是相同类型的 struct
+    let source = "type foo = struct {\n    int a\n}\n\nfn main() {\n    int x = 1\n    var f = foo{...x}\n}\n";
+    let m = module_analyze(source);
+    assert_eq!(m.analyzer_errors.len(), 1);
+    assert_eq!(m.analyzer_errors[0].message, "spread expr must be struct 'foo', actual 'int'");
+}
+
+#[test]
+fn test_document_highlight() {
+    let source = "type foo = struct {\n    int a\n    int b\n}\n\nfn call(int v) {\n}\n\nfn main() {\n    var x = 1\n    var y = x + 2\n    call(x)\n    x += y\n    var f = foo{a = x, b = 2}\n    f.b = f.a + 1\n}\n";
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let index = ReferenceIndex::build(&m);
+    let chars: Vec<char> = source.chars().collect();
+    let highlights_at = |offset: usize| -> Vec<(String, DocumentHighlightKind)> {
+        document_highlights(&index, &m, offset)
+            .into_iter()
+            .map(|h| {
+                let line_start = m.rope.line_to_char(h.range.start.line as usize);
+                let start = line_start + h.range.start.character as usize;
+                let end = line_start + h.range.end.character as usize;
+                (
+                    format!("{}:{}", h.range.start.line, chars[start..end].iter().collect::<String>()),
+                    h.kind.unwrap(),
+                )
+            })
+            .collect()
+    };
+
+    // 声明与复合赋值为 Write, 其余为 Read
+    let offset = source.find("call(x)").unwrap() + 5;
+    assert_eq!(
+        highlights_at(offset),
+        vec![
+            ("9:x".to_string(), DocumentHighlightKind::WRITE),
+            ("10:x".to_string(), DocumentHighlightKind::READ),
+            ("11:x".to_string(), DocumentHighlightKind::READ),
+            ("12:x".to_string(), DocumentHighlightKind::WRITE),
+            ("13:x".to_string(), DocumentHighlightKind::READ),
+        ]
+    );
+
+    // struct field 包含定义, 字面量中的 key 以及 select
+    let offset = source.find("f.a").unwrap() + 2;
+    assert_eq!(
+        highlights_at(offset),
+        vec![
+            ("1:a".to_string(), DocumentHighlightKind::WRITE),
+            ("13:a".to_string(), DocumentHighlightKind::WRITE),
+            ("14:a".to_string(), DocumentHighlightKind::READ),
+        ]
+    );
+    let offset = source.find("f.b").unwrap() + 2;
+    assert_eq!(
+        highlights_at(offset).into_iter().map(|(text, _)| text).collect::<Vec<_>>(),
+        vec!["2:b", "13:b", "14:b"]
+    );
+
+    // 非符号位置没有 highlight
+    assert!(highlights_at(source.find("= 1").unwrap()).is_empty());
+}
+
+#[test]
+fn test_reparse_range() {
+    let mut source = String::from("import fmt\n\ntype point = struct {\n    int x\n    int y = 1\n}\n\nvar count = 0\n\n");
+    for i in 0..17 {
+        source.push_str(&format!(
+            "// f{i}\nfn f{i}(point p, [int] list):int {{\n    var total = p.x + {i}\n    for v in list {{\n        total += v\n    }}\n    return total as int\n}}\n\n"
+        ));
+    }
+
+    let (token_db, token_indexes, _) = Lexer::new(source.clone()).scan();
+    let (old_stmts, old_tokens, errors) = Syntax::new(token_db, token_indexes).parser();
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(old_stmts.len(), 20);
+
+    let fn_ptr = |stmt: &Stmt| -> *const std::sync::RwLock<nls::analyzer::common::AstFnDef> {
+        let AstNode::FnDef(fndef_mutex) = &stmt.node else { panic!("expect fn def") };
+        std::sync::Arc::as_ptr(fndef_mutex)
+    };
+
+    // 修改 f7 的函数体, 长度发生变化
+    let chars: Vec<char> = source.chars().collect();
+    let char_offset = |s: &str, pat: &str| s[..s.find(pat).unwrap()].chars().count();
+    let start = char_offset(&source, "p.x + 7") + "p.x + ".len();
+    let edit = TextEdit {
+        start,
+        end: start + 1,
+        new_text: "700 * count".to_string(),
+    };
+    let new_source: String = chars[..edit.start].iter().collect::<String>() + &edit.new_text + &chars[edit.end..].iter().collect::<String>();
+
+    let (stmts, errors) = Syntax::reparse_range(&old_stmts, &old_tokens, &edit, &new_source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(stmts.len(), 20);
+
+    // 与完整解析的结果一致(包括位置信息)
+    let (full_stmts, full_errors) = parse_with_errors(&new_source);
+    assert!(full_errors.is_empty());
+    assert_eq!(strip_ids(&format!("{:?}", stmts)), strip_ids(&format!("{:?}", full_stmts)));
+
+    // 只有 f7 被重新解析, 之前的 stmt 直接复用, 之后的 stmt 平移位置后复制
+    for i in 3..10 {
+        assert_eq!(fn_ptr(&stmts[i]), fn_ptr(&old_stmts[i]));
+    }
+    assert_ne!(fn_ptr(&stmts[10]), fn_ptr(&old_stmts[10]));
+    assert_eq!(old_stmts[11].span.start as usize + "700 * count".len() - 1, stmts[11].span.start as usize);
+
+    // 引入语法错误, 错误位置基于新源码
+    let start = char_offset(&source, "return total as int\n}\n\n// f12") + "return total".len();
+    let edit = TextEdit {
+        start,
+        end: start + 3,
+        new_text: " +".to_string(),
+    };
+    let new_source: String = chars[..edit.start].iter().collect::<String>() + &edit.new_text + &chars[edit.end..].iter().collect::<String>();
+    let (stmts, errors) = Syntax::reparse_range(&old_stmts, &old_tokens, &edit, &new_source);
+    assert!(!errors.is_empty());
+    let fn_start = char_offset(&new_source, "// f11");
+    let fn_end = char_offset(&new_source, "// f12");
+    assert!(
+        errors.iter().all(|e| fn_start <= (e.span.start as usize) && (e.span.end as usize) <= fn_end),
+        "{:?}",
+        errors
+    );
+    assert_eq!(stmts[stmts.len() - 1].span.start as usize, char_offset(&new_source, "fn f16"));
+
+    // 在 stmt 之间插入新的 stmt
+    let start = char_offset(&source, "// f3");
+    let edit = TextEdit {
+        start,
+        end: start,
+        new_text: "var other = count\n".to_string(),
+    };
+    let new_source: String = chars[..edit.start].iter().collect::<String>() + &edit.new_text + &chars[edit.end..].iter().collect::<String>();
+    let (stmts, errors) = Syntax::reparse_range(&old_stmts, &old_tokens, &edit, &new_source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(strip_ids(&format!("{:?}", stmts)), strip_ids(&format!("{:?}", parse(&new_source))));
+}
+
+#[test]
+fn test_find_ident_usages() {
+    let source =
+        "fn call(int v) {\n}\n\nfn main() {\n    var x = 1\n    var y = x + 1\n    call(x)\n    if y > 1 {\n        var x = 2\n        x += 1\n    }\n}\n";
+    let stmts = parse(source);
+    let chars: Vec<char> = source.chars().collect();
+    let text = |(start, end): (usize, usize)| chars[start..end].iter().collect::<String>();
+
+    // 语法层面的查找包含所有同名 ident 与声明
+    let usages = Syntax::find_ident_usages(&stmts, "x");
+    assert_eq!(usages.len(), 5);
+    assert!(usages.iter().all(|&span| text(span) == "x"));
+    assert_eq!(Syntax::find_ident_usages(&stmts, "y").len(), 2);
+    assert!(Syntax::find_ident_usages(&stmts, "z").is_empty());
+
+    // 声明查找, 内层作用域优先
+    let outer = source.find("var x = 1").unwrap() + 4;
+    let inner = source.find("var x = 2").unwrap() + 4;
+    let decl_start = |offset: usize| match Syntax::find_decl_of(&stmts, "x", offset) {
+        Some(DeclNode::Var(var_decl)) => var_decl.read().unwrap().symbol_start,
+        other => panic!("expect var decl, got {:?}", other),
+    };
+    assert_eq!(decl_start(source.find("call(x)").unwrap() + 5), outer);
+    assert_eq!(decl_start(source.find("x += 1").unwrap()), inner);
+
+    let Some(DeclNode::Fn(fndef)) = Syntax::find_decl_of(&stmts, "call", source.find("call(x)").unwrap()) else {
+        panic!("expect fn decl")
+    };
+    assert_eq!(fndef.read().unwrap().symbol_start, source.find("call").unwrap());
+
+    let Some(DeclNode::Var(param)) = Syntax::find_decl_of(&stmts, "v", source.find("{\n}").unwrap()) else {
+        panic!("expect param decl")
+    };
+    assert_eq!(param.read().unwrap().ident, "v");
+
+    // 引用区分同名的不同声明
+    let references = Syntax::find_references(&stmts, "x", source.find("call(x)").unwrap() + 5);
+    let read = source.find("x + 1").unwrap();
+    let call = source.find("call(x)").unwrap() + 5;
+    assert_eq!(references, vec![(outer, outer + 1), (read, read + 1), (call, call + 1)]);
+    let references = Syntax::find_references(&stmts, "x", inner);
+    assert_eq!(references.len(), 2);
+}
+
+#[test]
+fn test_code_lens() {
+    let source = "type point = struct {\n    int x\n}\n\nfn add(point p, int v):int {\n    return p.x + v\n}\n\nfn main() {\n    point p = point{x = 1}\n    var a = add(p, 1)\n    var b = add(p, a)\n}\n\n#test\nfn add_test() {\n    var c = add(point{x = 2}, 3)\n}\n";
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let (_, sem_token_db, _) = Syntax::new(token_db, token_indexes).parser();
+    let mut m = module_new(source, "/project/main.n", 0);
+    m.sem_token_db = sem_token_db;
+    let mut symbol_table = SymbolTable::new();
+    Semantic::new(&mut m, &mut symbol_table).analyze();
+    let errors = Typesys::new(&mut symbol_table, &mut m).pre_infer();
+    m.analyzer_errors.extend(errors);
+    let errors = Typesys::new(&mut symbol_table, &mut m).infer();
+    m.analyzer_errors.extend(errors);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let lenses = code_lenses(&m, &CodeLensConfig::default());
+    let ranges = lenses.iter().map(|lens| lens.range).collect::<Vec<_>>();
+    assert_eq!(
+        ranges,
+        vec![
+            Range::new(Position::new(0, 5), Position::new(0, 10)),
+            Range::new(Position::new(4, 3), Position::new(4, 6)),
+            Range::new(Position::new(8, 3), Position::new(8, 7)),
+            Range::new(Position::new(15, 3), Position::new(15, 11)),
+            Range::new(Position::new(15, 3), Position::new(15, 11)),
+        ]
+    );
+
+    // #test fn 的 run test lens 无需 resolve
+    let run_test = lenses[3].command.as_ref().unwrap();
+    assert_eq!(run_test.command, RUN_TEST_COMMAND);
+    assert_eq!(
+        run_test.arguments,
+        Some(vec![serde_json::json!({ "fn": "add_test", "file": "/project/main.n" })])
+    );
+    assert!(lenses.iter().filter(|lens| lens.command.is_none()).all(|lens| lens_target(lens).is_some()));
+
+    // type alias 的引用来自类型位置, fn 的引用来自调用
+    let modules = vec![m];
+    let titles = lenses
+        .into_iter()
+        .filter(|lens| lens.command.is_none())
+        .map(|lens| {
+            let (path, offset) = lens_target(&lens).unwrap();
+            let count = count_references(&modules, &|m| std::sync::Arc::new(ReferenceIndex::build(m)), &path, offset).unwrap();
+            resolve_reference_lens(lens, count).command.unwrap().title
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(titles, vec!["4 references", "3 references", "0 references", "0 references"]);
+
+    // 超过行数限制时只保留 run test lens
+    let config = CodeLensConfig {
+        references_max_lines: 10,
+        ..Default::default()
+    };
+    assert_eq!(code_lenses(&modules[0], &config).len(), 1);
+
+    // 通过 nls.codeLens 配置关闭 references lens, 未配置的 key 使用默认值
+    let settings = Settings::from_value(&serde_json::json!({ "codeLens": { "references": false } }));
+    assert_eq!(code_lenses(&modules[0], &settings.code_lens_config()).len(), 1);
+    assert_eq!(settings.code_lens.references_max_lines, CodeLensConfig::default().references_max_lines);
+    assert_eq!(code_lenses(&modules[0], &Settings::default().code_lens_config()).len(), 5);
+}
+
+#[test]
+fn test_document_links() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/document_link");
+    let package_config = parse_package(dir.join("package.toml").to_str().unwrap()).unwrap();
+
+    let source = "import \"lib/util.n\"\nimport my.pkg as p\nimport \"lib/missing.n\"\nimport my.nope\n";
+    let mut m = module_new(source, dir.join("main.n").to_str().unwrap(), 0);
+    let mut stmts = std::mem::take(&mut m.stmts);
+    m.dependencies = analyze_imports(
+        &Some(std::sync::Arc::new(std::sync::Mutex::new(package_config))),
+        DEFAULT_NATURE_ROOT,
+        &[],
+        &mut m,
+        &mut stmts,
+    );
+    m.stmts = stmts;
+
+    let links = document_links(&m);
+    assert_eq!(links.len(), 4);
+
+    // 字符串路径不包含引号, 包路径不包含 as 子句
+    assert_eq!(links[0].range, Range::new(Position::new(0, 8), Position::new(0, 18)));
+    assert_eq!(links[0].target, Some(Url::from_file_path(dir.join("lib/util.n")).unwrap()));
+    assert_eq!(links[1].range, Range::new(Position::new(1, 7), Position::new(1, 13)));
+    assert_eq!(links[1].target, Some(Url::from_file_path(dir.join("pkg.n")).unwrap()));
+    assert!(links[0].tooltip.is_none() && links[1].tooltip.is_none());
+
+    // 解析失败时没有 target, tooltip 为失败原因
+    assert_eq!(links[2].range, Range::new(Position::new(2, 8), Position::new(2, 21)));
+    assert!(links[2].target.is_none());
+    assert_eq!(links[2].tooltip.as_deref(), Some("import file lib/missing.n not found"));
+    assert!(links[3].target.is_none());
+    assert_eq!(links[3].tooltip.as_deref(), Some("cannot find import file in package my"));
+}
+
+#[test]
+fn test_import_hover() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/document_link");
+    let package_config = parse_package(dir.join("package.toml").to_str().unwrap()).unwrap();
+
+    let source = "import \"lib/util.n\"\nimport my.pkg as p\nimport my.nope\n\nfn main() {\n}\n";
+    let mut m = module_new(source, dir.join("main.n").to_str().unwrap(), 0);
+    let mut stmts = std::mem::take(&mut m.stmts);
+    m.dependencies = analyze_imports(
+        &Some(std::sync::Arc::new(std::sync::Mutex::new(package_config))),
+        DEFAULT_NATURE_ROOT,
+        &[],
+        &mut m,
+        &mut stmts,
+    );
+    m.stmts = stmts;
+
+    let symbol_table = SymbolTable::new();
+    let hover_text = |offset: usize| -> Option<String> {
+        let contents = hover(&m, &symbol_table, &ReferenceIndex::default(), offset)?.contents;
+        let tower_lsp::lsp_types::HoverContents::Markup(markup) = contents else {
+            panic!("expect markup")
+        };
+        Some(markup.value)
+    };
+
+    // 行内任意位置都可以触发
+    let text = hover_text(source.find("util.n").unwrap()).unwrap();
+    assert_eq!(text, hover_text(0).unwrap());
+    assert!(text.contains(&format!("path: `{}`", dir.join("lib/util.n").display())), "{}", text);
+    assert!(text.contains("module: `document_link.lib.util`"), "{}", text);
+    assert!(text.contains("as: `util`"), "{}", text);
+    assert!(!text.contains("package:"), "{}", text);
+
+    let text = hover_text(source.find(" as p").unwrap() + 4).unwrap();
+    assert!(text.contains(&format!("path: `{}`", dir.join("pkg.n").display())), "{}", text);
+    assert!(text.contains("module: `document_link.pkg`"), "{}", text);
+    assert!(text.contains("as: `p`"), "{}", text);
+    assert!(text.contains("package: `my` 0.1.0"), "{}", text);
+
+    // 解析失败时展示尝试过的路径
+    let text = hover_text(source.find("nope").unwrap()).unwrap();
+    assert!(text.contains("**unresolved**: cannot find import file in package my"), "{}", text);
+    assert!(text.contains(&format!("- `{}`", dir.join("nope.n").display())), "{}", text);
+
+    // 非 import 行
+    assert!(hover_text(source.find("main").unwrap()).is_none());
+}
+
+#[test]
+fn test_type_hierarchy() {
+    let source = "type Circle = struct {\n    int r\n}\n\ntype Square = struct {\n    int w\n}\n\ntype Shape = Circle|Square|int\n\ntype Round = Circle|float\n\nfn main() {\n    Shape s = 1\n}\n";
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let modules = vec![m];
+    let index = TypeHierarchyIndex::build(&modules);
+    let names = |items: Vec<tower_lsp::lsp_types::TypeHierarchyItem>| items.into_iter().map(|item| item.name).collect::<Vec<_>>();
+
+    // union 的 subtypes 为其成员, builtin 成员没有定义位置
+    let shape = index.prepare(&modules[0], source.find("Shape").unwrap() + 1).unwrap();
+    assert_eq!(shape.name, "Shape");
+    assert_eq!(shape.selection_range, Range::new(Position::new(8, 5), Position::new(8, 10)));
+    let subtypes = index.subtypes(&shape);
+    assert_eq!(names(subtypes.clone()), vec!["Circle", "Square", "int"]);
+    assert_eq!(subtypes[0].selection_range, Range::new(Position::new(0, 5), Position::new(0, 11)));
+    assert_eq!(subtypes[2].range, Range::default());
+    assert!(index.subtypes(&subtypes[2]).is_empty());
+
+    // 成员的 supertypes 为包含它的所有 union
+    let circle = index.prepare(&modules[0], source.find("Circle").unwrap()).unwrap();
+    assert_eq!(names(index.supertypes(&circle)), vec!["Shape", "Round"]);
+    assert!(index.subtypes(&circle).is_empty());
+    assert_eq!(names(index.supertypes(&subtypes[1])), vec!["Shape"]);
+    assert_eq!(names(index.supertypes(&subtypes[2])), vec!["Shape"]);
+    assert!(index.supertypes(&shape).is_empty());
+
+    // 非 type alias 声明处
+    assert!(index.prepare(&modules[0], source.find("main").unwrap()).is_none());
+}
+
+#[test]
+fn test_token_type_classify() {
+    let source = "fn for if else return match import type var let async go as is in new struct throw try catch select continue break ptr void any int u8 f64 string bool vec map set tup chan arr promise";
+    let (token_db, _, _) = Lexer::new(source.to_string()).scan();
+    let words: Vec<&Token> = token_db.iter().filter(|t| t.token_type != TokenType::Eof).collect();
+    assert_eq!(words.len(), source.split(' ').count());
+    for token in words {
+        assert!(token.token_type.is_keyword(), "{} should be keyword", token.literal);
+        assert!(!token.token_type.is_literal(), "{} should not be literal", token.literal);
+    }
+
+    let (token_db, _, _) = Lexer::new("1 1.5 \"s\" true false null".to_string()).scan();
+    for token in token_db.iter().filter(|t| t.token_type != TokenType::Eof) {
+        assert!(token.token_type.is_literal(), "{} should be literal", token.literal);
+        assert!(!token.token_type.is_keyword(), "{} should not be keyword", token.literal);
+    }
+
+    // 标识符与运算符既不是关键字也不是字面量
+    let (token_db, _, _) = Lexer::new("foo + - * / == != && || = += ( ) { } [ ] . , : -> ...".to_string()).scan();
+    for token in &token_db {
+        assert!(!token.token_type.is_keyword(), "{} should not be keyword", token.literal);
+        assert!(!token.token_type.is_literal(), "{} should not be literal", token.literal);
+    }
+    assert!(TokenType::ElseIf.is_keyword());
+}
+
+fn hover_markdown(m: &Module, symbol_table: &SymbolTable, offset: usize) -> Option<String> {
+    let contents = hover(m, symbol_table, &ReferenceIndex::build(m), offset)?.contents;
+    let tower_lsp::lsp_types::HoverContents::Markup(markup) = contents else {
+        panic!("expect markup")
+    };
+    Some(markup.value)
+}
+
+#[test]
+fn test_doc_comment_hover() {
+    let source = "// adds two numbers\n//\n// ```n\n// add(1, 2)\n// ```\nfn add(int a, int b):int {\n    return a + b\n}\n\n/**\n * a point\n *\n *     indented\n */\ntype point = struct {\n    int x\n}\n\nint count = 1 // trailing\n// total doc\nint total = 2\n// detached\n\nfn main() {\n    var c = add(count, total)\n    point p = point{x = c}\n}\n";
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let (_, sem_token_db, _) = Syntax::new(token_db, token_indexes).parser();
+    let mut m = module_new(source, "/project/main.n", 0);
+    m.sem_token_db = sem_token_db;
+    let mut symbol_table = SymbolTable::new();
+    Semantic::new(&mut m, &mut symbol_table).analyze();
+    let errors = Typesys::new(&mut symbol_table, &mut m).pre_infer();
+    m.analyzer_errors.extend(errors);
+    let errors = Typesys::new(&mut symbol_table, &mut m).infer();
+    m.analyzer_errors.extend(errors);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    // 代码块原样保留
+    let text = hover_markdown(&m, &symbol_table, source.find("add(count").unwrap()).unwrap();
+    assert_eq!(text, "```n\nfn add(int a, int b):int\n```\n\n---\n\nadds two numbers\n\n```n\nadd(1, 2)\n```");
+
+    let text = hover_markdown(&m, &symbol_table, source.find("point p").unwrap()).unwrap();
+    assert_eq!(text, "```n\ntype point\n```\n\n---\n\na point\n\n    indented");
+
+    // 上一行的尾随注释不属于文档注释
+    let text = hover_markdown(&m, &symbol_table, source.find("total)").unwrap()).unwrap();
+    assert!(text.ends_with("---\n\ntotal doc"), "{}", text);
+    let text = hover_markdown(&m, &symbol_table, source.find("count,").unwrap()).unwrap();
+    assert!(!text.contains("---"), "{}", text);
+
+    // 与声明之间存在空行
+    let stmts = parse(source);
+    let AstNode::FnDef(main) = &stmts.last().unwrap().node else {
+        panic!("expect fn def")
+    };
+    assert!(main.read().unwrap().doc.is_none());
+}
+
+#[test]
+fn test_doc_comment_completion_detail() {
+    let config = CompletionConfig::default();
+    let ctx = AutoImportContext {
+        nature_root: "/nature",
+        package_config: None,
+        config: &config,
+    };
+
+    let http = module_new("/**\n * serve http requests\n * on the given port\n *\n * ```n\n * http.serve_http(80)\n * ```\n */\nfn serve_http(int port) {\n}\n\nfn serve_none() {\n}\n", "/nature/std/net/http/main.n", 1);
+    let main = module_new("fn main() {\n}\n", "/project/main.n", 0);
+
+    let items = auto_import_completions(&main, &[main.clone(), http.clone()], "serve", &ctx);
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].detail.as_deref(), Some("serve http requests on the given port"));
+    let Some(tower_lsp::lsp_types::Documentation::MarkupContent(doc)) = &items[0].documentation else {
+        panic!("expect markup")
+    };
+    assert_eq!(doc.value, "serve http requests\non the given port\n\n```n\nhttp.serve_http(80)\n```");
+
+    assert!(items[1].detail.is_none() && items[1].documentation.is_none());
+}
+
+#[test]
+fn test_doc_comment_cross_module_hover() {
+    let mut symbol_table = SymbolTable::new();
+
+    let util_source = "// sum of a and b\nfn sum(int a, int b):int {\n    return a + b\n}\n";
+    let mut util = module_new(util_source, "/project/util.n", 1);
+    util.ident = "util".to_string();
+    nls::analyzer::register_global_symbol(&util, &mut symbol_table, &util.stmts);
+
+    let source = "fn main() {\n    var c = util.sum(1, 2)\n}\n";
+    let mut m = module_new(source, "/project/main.n", 0);
+    m.ident = "main".to_string();
+    m.dependencies.push(ImportStmt {
+        as_name: "util".to_string(),
+        module_ident: "util".to_string(),
+        full_path: util.path.clone(),
+        ..Default::default()
+    });
+    Semantic::new(&mut m, &mut symbol_table).analyze();
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    // util 中的声明通过 symbol table 获取, 不需要 util module
+    drop(util);
+    let text = hover_markdown(&m, &symbol_table, source.find("sum").unwrap()).unwrap();
+    assert_eq!(text, "```n\nfn sum(int a, int b):int\n```\n\n---\n\nsum of a and b");
+}
+
+fn content_change(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+    TextDocumentContentChangeEvent {
+        range: Some(Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))),
+        range_length: None,
+        text: text.to_string(),
+    }
+}
+
+#[test]
+fn test_document_incremental_changes() {
+    let mut document = Document::new("var a = '中文'\nvar b = 😀 + 1\r\nfn main() {\n}\n".to_string(), 1);
+
+    // character 为 utf16 编码的列, 😀 占两列
+    document.apply_changes(vec![content_change((1, 10), (1, 14), "2")], 2);
+    assert_eq!(document.text(), "var a = '中文'\nvar b = 😀2\r\nfn main() {\n}\n");
+
+    // 同一批修改中后面的 range 基于前面修改之后的内容
+    document.apply_changes(vec![content_change((0, 9), (0, 11), "x\ny"), content_change((1, 0), (1, 1), "")], 3);
+    assert_eq!(document.text(), "var a = 'x\n'\nvar b = 😀2\r\nfn main() {\n}\n");
+
+    // 跨行删除, 超出行尾的 character 对齐到 \r\n 之前
+    document.apply_changes(vec![content_change((2, 20), (3, 9), "")], 4);
+    assert_eq!(document.text(), "var a = 'x\n'\nvar b = 😀2 {\n}\n");
+
+    // 超出文件末尾的 line 对应文件末尾
+    document.apply_changes(vec![content_change((9, 0), (9, 0), "// end")], 5);
+    assert_eq!(document.text(), "var a = 'x\n'\nvar b = 😀2 {\n}\n// end");
+    assert_eq!(document.version, 5);
+
+    // 不携带 range 时替换整个文件
+    let full = TextDocumentContentChangeEvent {
+        range: None,
+        range_length: None,
+        text: "fn main() {}".to_string(),
+    };
+    document.apply_changes(vec![content_change((0, 0), (0, 0), "ignored"), full], 6);
+    assert_eq!(document.text(), "fn main() {}");
+}
+
+/**
+ * 基于 utf16 code unit 应用修改, 作为 Document 的对照实现
+ */
+fn utf16_apply(text: &str, range: Range, insert: &str) -> String {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let mut line_starts = vec![0];
+    line_starts.extend(units.iter().enumerate().filter(|(_, u)| **u == b'\n' as u16).map(|(i, _)| i + 1));
+
+    let offset = |position: Position| -> usize {
+        let line = position.line as usize;
+        let Some(&start) = line_starts.get(line) else {
+            return units.len();
+        };
+        let mut end = match line_starts.get(line + 1) {
+            Some(next) => next - 1,
+            None => units.len(),
+        };
+        if end > start && line_starts.get(line + 1).is_some() && units[end - 1] == b'\r' as u16 {
+            end -= 1;
+        }
+        start + (position.character as usize).min(end - start)
+    };
+
+    let start = offset(range.start);
+    let end = offset(range.end).max(start);
+    let mut result = units[..start].to_vec();
+    result.extend(insert.encode_utf16());
+    result.extend(&units[end..]);
+    String::from_utf16(&result).unwrap()
+}
+
+// char offset 对应的 lsp position
+fn char_position(text: &str, char_offset: usize) -> Position {
+    let prefix: Vec<char> = text.chars().take(char_offset).collect();
+    let line = prefix.iter().filter(|c| **c == '\n').count();
+    let line_start = prefix.iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+    let column: usize = prefix[line_start..].iter().map(|c| c.len_utf16()).sum();
+    Position::new(line as u32, column as u32)
+}
+
+proptest! {
+    #[test]
+    fn test_document_incremental_matches_full_replacement(
+        initial in "(\r\n|[a-z \n=é中😀]){0,40}",
+        edits in prop::collection::vec((any::<usize>(), 0usize..12, "(\r\n|[a-z \n=é中😀]){0,6}", 0u32..3), 1..16),
+    ) {
+        let mut incremental = Document::new(initial.clone(), 0);
+        let mut full = Document::new(initial.clone(), 0);
+        let mut expected = initial;
+        let mut changes = Vec::new();
+
+        for (seed, len, insert, overshoot) in edits {
+            let char_count = expected.chars().count();
+            let start = seed % (char_count + 1);
+            let end = (start + len).min(char_count);
+
+            // overshoot > 0 时 end 位于行尾之后
+            let mut end_position = char_position(&expected, end);
+            if overshoot > 0 {
+                let line_end = end + expected.chars().skip(end).take_while(|c| *c != '\n').count();
+                end_position.character = char_position(&expected, line_end).character + overshoot;
+            }
+            let range = Range::new(char_position(&expected, start), end_position);
+
+            expected = utf16_apply(&expected, range, &insert);
+            changes.push(TextDocumentContentChangeEvent { range: Some(range), range_length: None, text: insert });
+
+            let full_change = TextDocumentContentChangeEvent { range: None, range_length: None, text: expected.clone() };
+            full.apply_changes(vec![full_change], changes.len() as i32);
+        }
+
+        // 一次 didChange 中携带所有修改
+        let version = changes.len() as i32;
+        incremental.apply_changes(changes, version);
+        prop_assert_eq!(incremental.text(), expected.as_str());
+        prop_assert_eq!(incremental.text(), full.text());
+    }
+}
+
+#[test]
+fn test_pull_diagnostics() {
+    let mut m = module_analyze("fn main() {\n    var a = b\n}\n");
+    m.analyzer_errors.push(AnalyzerError::new(Span::new(0, 0), "ignored".to_string()));
+    assert!(!m.analyzer_errors.is_empty());
+
+    // 只验证 error 的上报, 关闭 lint
+    let config = DiagnosticConfig {
+        lint: LintConfig {
+            unused_variable: false,
+            shadowing: false,
+        },
+        ..Default::default()
+    };
+
+    // start = end = 0 的错误不会上报
+    let DocumentDiagnosticReport::Full(report) = document_diagnostic_report(&m, &config, None) else {
+        panic!("expected full report");
+    };
+    let items = &report.full_document_diagnostic_report.items;
+    assert_eq!(items.len(), m.analyzer_errors.iter().filter(|e| e.span.end > 0).count());
+    assert!(!items.is_empty());
+    assert!(items
+        .iter()
+        .all(|d| d.severity == Some(DiagnosticSeverity::ERROR) && d.source.as_deref() == Some(DIAGNOSTIC_SOURCE)));
+    assert!(items.iter().all(|d| d.message != "ignored"));
+    let result_id = report.full_document_diagnostic_report.result_id.clone().unwrap();
+
+    // 没有重新分析时 result id 不变
+    let DocumentDiagnosticReport::Unchanged(report) = document_diagnostic_report(&m, &config, Some(&result_id)) else {
+        panic!("expected unchanged report");
+    };
+    assert_eq!(report.unchanged_document_diagnostic_report.result_id, result_id);
+
+    // 重新分析之后返回新的 full report
+    let fixed = module_analyze("fn main() {\n    var a = 1\n}\n");
+    let DocumentDiagnosticReport::Full(report) = document_diagnostic_report(&fixed, &config, Some(&result_id)) else {
+        panic!("expected full report");
+    };
+    assert!(report.full_document_diagnostic_report.items.is_empty());
+    assert_ne!(report.full_document_diagnostic_report.result_id, Some(result_id.clone()));
+
+    let uri = Url::from_file_path("/project/main.n").unwrap();
+    match workspace_document_report(&m, &config, uri.clone(), Some(3), Some(&result_id)) {
+        WorkspaceDocumentDiagnosticReport::Unchanged(report) => {
+            assert_eq!(report.uri, uri);
+            assert_eq!(report.version, Some(3));
+        }
+        WorkspaceDocumentDiagnosticReport::Full(_) => panic!("expected unchanged report"),
+    }
+    match workspace_document_report(&m, &config, uri.clone(), None, None) {
+        WorkspaceDocumentDiagnosticReport::Full(report) => {
+            assert_eq!(report.uri, uri);
+            assert_eq!(report.full_document_diagnostic_report.items, *items);
+        }
+        WorkspaceDocumentDiagnosticReport::Unchanged(_) => panic!("expected full report"),
+    }
+}
+
+// 不依赖 Type 的 Display, 用于比较两个类型的结构
+fn type_tree(t: &Type) -> String {
+    let mut label = t.kind.to_string();
+    let children: Vec<&Type> = match &t.kind {
+        TypeKind::Vec(e) | TypeKind::Set(e) | TypeKind::Chan(e) | TypeKind::Promise(e) | TypeKind::Ptr(e) | TypeKind::RawPtr(e) => vec![e.as_ref()],
+        TypeKind::Arr(length, e) => {
+            label.push_str(&length.to_string());
+            vec![e.as_ref()]
+        }
+        TypeKind::Map(k, v) => vec![k.as_ref(), v.as_ref()],
+        TypeKind::Tuple(elements, _) => elements.iter().collect(),
+        TypeKind::Union(any, elements) => {
+            if *any {
+                label = "any".to_string();
+            }
+            elements.iter().collect()
+        }
+        TypeKind::Fn(type_fn) => {
+            if type_fn.errable {
+                label.push('!');
+            }
+            type_fn.param_types.iter().chain(std::iter::once(&type_fn.return_type)).collect()
+        }
+        TypeKind::Alias(alias) => {
+            let names: Vec<&str> = alias.args.iter().flatten().filter_map(|arg| arg.name.as_deref()).collect();
+            label = format!("{}:{:?}.{}:{}", label, alias.import_as, alias.ident, names.join(","));
+            alias.args.iter().flatten().map(|arg| &arg.type_).collect()
+        }
+        TypeKind::Struct(ident, _, properties) => {
+            let keys: Vec<&str> = properties.iter().map(|p| p.key.as_str()).collect();
+            label = format!("{}:{}:{}", label, ident, keys.join(","));
+            properties.iter().map(|p| &p.type_).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    let children: Vec<String> = children.into_iter().map(type_tree).collect();
+    format!("{}({})", label, children.join(","))
+}
+
+fn assert_type_round_trip(t: &Type) {
+    let display = t.to_string();
+    let parsed = parse_type_str(&display).unwrap_or_else(|e| panic!("parse '{}' failed: {}", display, e.message));
+    assert_eq!(type_tree(&parsed), type_tree(t), "{}", display);
+    assert_eq!(parsed.to_string(), display);
+}
+
+#[test]
+fn test_type_display_round_trip() {
+    let boxed = |kind: TypeKind| Box::new(Type::new(kind));
+    let type_fn = |param_types: Vec<Type>, return_type: Type, errable: bool| {
+        TypeKind::Fn(Box::new(TypeFn {
+            name: String::new(),
+            param_types,
+            return_type,
+            rest: false,
+            tpl: false,
+            errable,
+        }))
+    };
+    let nullable = |kind: TypeKind| TypeKind::Union(false, vec![Type::new(kind), Type::new(TypeKind::Null)]);
+
+    // 每个可以在源码中书写的 TypeKind, param 只能在泛型声明中解析, 其余的编译器内部类型没有对应的语法
+    let kinds = vec![
+        TypeKind::Null,
+        TypeKind::Bool,
+        TypeKind::Int8,
+        TypeKind::Uint8,
+        TypeKind::Int16,
+        TypeKind::Uint16,
+        TypeKind::Int32,
+        TypeKind::Uint32,
+        TypeKind::Int64,
+        TypeKind::Uint64,
+        TypeKind::Int,
+        TypeKind::Uint,
+        TypeKind::Float32,
+        TypeKind::Float,
+        TypeKind::Float64,
+        TypeKind::String,
+        TypeKind::Void,
+        TypeKind::Vec(boxed(TypeKind::Int)),
+        TypeKind::Arr(
+            3,
+            Box::new(Type::new(TypeKind::Tuple(vec![Type::new(TypeKind::Int), Type::new(TypeKind::String)], 0))),
+        ),
+        TypeKind::Map(boxed(TypeKind::String), boxed(TypeKind::Bool)),
+        TypeKind::Set(boxed(TypeKind::Float)),
+        TypeKind::Tuple(vec![Type::new(TypeKind::Int), Type::new(TypeKind::Bool)], 0),
+        TypeKind::Chan(boxed(TypeKind::String)),
+        TypeKind::Promise(boxed(TypeKind::Int)),
+        TypeKind::Ptr(boxed(TypeKind::Int)),
+        type_fn(
+            vec![Type::new(type_fn(vec![Type::new(TypeKind::Int)], Type::new(TypeKind::String), false))],
+            Type::new(TypeKind::Bool),
+            false,
+        ),
+        type_fn(Vec::new(), Type::new(nullable(TypeKind::Int)), true),
+        TypeKind::Union(true, Vec::new()),
+        nullable(TypeKind::String),
+        TypeKind::Alias(Box::new(TypeAlias {
+            import_as: Some("pkg".to_string()),
+            ident: "box".to_string(),
+            symbol_id: None,
+            args: Some(vec![
+                TypeArg::new(Type::new(TypeKind::Int)),
+                TypeArg::new(Type::new(nullable(TypeKind::String))),
+            ]),
+        })),
+        TypeKind::Alias(Box::new(TypeAlias {
+            import_as: None,
+            ident: "pair".to_string(),
+            symbol_id: None,
+            args: Some(vec![
+                TypeArg {
+                    name: Some("T".to_string()),
+                    type_: Type::new(TypeKind::Int),
+                },
+                TypeArg {
+                    name: Some("U".to_string()),
+                    type_: Type::new(TypeKind::String),
+                },
+            ]),
+        })),
+    ];
+    for kind in kinds {
+        assert_type_round_trip(&Type::new(kind));
+    }
+
+    let cases = [
+        ("fn(fn(int):string):bool", "fn(fn(int):string):bool"),
+        ("vec<map<string,int|null>>", "vec<map<string,int|null>>"),
+        ("arr<(int,string), 3>", "arr<(int,string),3>"),
+        ("fn(int):string|null", "fn(int):string?"),
+        ("fn(int):string|bool!", "fn(int):string|bool!"),
+        ("(int|null,bool)", "(int|null,bool)"),
+        ("fn():int?!", "fn():int?!"),
+        ("[int]", "vec<int>"),
+        ("{string:int}", "map<string,int>"),
+        ("tup<int,bool>", "(int,bool)"),
+        ("struct{int a;bool b}", "struct{int a;bool b}"),
+    ];
+    for (source, expected) in cases {
+        let t = parse_type_str(source).unwrap();
+        assert_eq!(t.to_string(), expected);
+        assert_type_round_trip(&t);
+    }
+
+    assert!(parse_type_str("int string").is_err());
+    assert!(parse_type_str("map<int>").is_err());
+}
+
+fn arb_leaf_type() -> impl Strategy<Value = Type> {
+    let kinds = vec![
+        TypeKind::Null,
+        TypeKind::Bool,
+        TypeKind::Int8,
+        TypeKind::Uint16,
+        TypeKind::Int,
+        TypeKind::Uint,
+        TypeKind::Float32,
+        TypeKind::Float,
+        TypeKind::String,
+        TypeKind::Union(true, Vec::new()),
+    ];
+    prop_oneof![
+        proptest::sample::select(kinds).prop_map(Type::new),
+        "[a-z]{1,4}"
+            .prop_filter("keyword", |s| parse_type_str(s).is_ok_and(|t| matches!(t.kind, TypeKind::Alias(..))))
+            .prop_map(|ident| {
+                Type::new(TypeKind::Alias(Box::new(TypeAlias {
+                    import_as: None,
+                    ident,
+                    symbol_id: None,
+                    args: None,
+                })))
+            }),
+    ]
+}
+
+fn arb_type() -> impl Strategy<Value = Type> {
+    arb_leaf_type().prop_recursive(4, 32, 4, |inner| {
+        let non_union = inner.clone().prop_filter("union", |t| !matches!(t.kind, TypeKind::Union(false, _)));
+        // fn 的返回值会消费之后的 | 与 ?, 因此 union 中的元素不能是 fn
+        let union_element = non_union.clone().prop_filter("fn", |t| !matches!(t.kind, TypeKind::Fn(..)));
+        let union = proptest::collection::vec(union_element.clone(), 2..4).prop_map(|elements| Type::new(TypeKind::Union(false, elements)));
+        let return_type = prop_oneof![
+            non_union.clone(),
+            union.clone(),
+            union_element.prop_map(|t| Type::new(TypeKind::Union(false, vec![t, Type::new(TypeKind::Null)]))),
+        ];
+
+        prop_oneof![
+            inner.clone().prop_map(|t| Type::new(TypeKind::Vec(Box::new(t)))),
+            inner.clone().prop_map(|t| Type::new(TypeKind::Set(Box::new(t)))),
+            inner.clone().prop_map(|t| Type::new(TypeKind::Chan(Box::new(t)))),
+            inner.clone().prop_map(|t| Type::new(TypeKind::Ptr(Box::new(t)))),
+            (1..1000u64, inner.clone()).prop_map(|(length, t)| Type::new(TypeKind::Arr(length, Box::new(t)))),
+            (inner.clone(), inner.clone()).prop_map(|(k, v)| Type::new(TypeKind::Map(Box::new(k), Box::new(v)))),
+            proptest::collection::vec(inner.clone(), 1..4).prop_map(|elements| Type::new(TypeKind::Tuple(elements, 0))),
+            union,
+            // 同理返回值为 fn 时 ! 属于内层 fn
+            (proptest::collection::vec(inner.clone(), 0..3), return_type, any::<bool>()).prop_map(|(param_types, return_type, errable)| {
+                let errable = errable && !matches!(return_type.kind, TypeKind::Fn(..));
+                Type::new(TypeKind::Fn(Box::new(TypeFn {
+                    name: String::new(),
+                    param_types,
+                    return_type,
+                    rest: false,
+                    tpl: false,
+                    errable,
+                })))
+            }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn test_type_display_round_trip_generated(t in arb_type()) {
+        let display = t.to_string();
+        let parsed = parse_type_str(&display);
+        prop_assert!(parsed.is_ok(), "parse '{}' failed", display);
+        let parsed = parsed.unwrap();
+        prop_assert_eq!(type_tree(&parsed), type_tree(&t), "{}", display);
+        prop_assert_eq!(parsed.to_string(), display);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_scheduler_publishes_latest_version() {
+    let scheduler = Arc::new(AnalysisScheduler::new(Duration::from_millis(50)));
+    let published = Arc::new(Mutex::new(Vec::new()));
+    let analyzed = Arc::new(Mutex::new(Vec::new()));
+    let mut document = Document::new("fn main() {\n}\n".to_string(), 1);
+
+    // 连续输入, 每个版本引入不同的错误
+    let mut handles = Vec::new();
+    for version in 2..=6 {
+        let text = format!("fn main() {{\n    var a = b{}\n}}\n", version);
+        document.apply_changes(
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text,
+            }],
+            version,
+        );
+
+        let source = document.text().to_string();
+        let (scheduler, published, analyzed) = (scheduler.clone(), published.clone(), analyzed.clone());
+        handles.push(tokio::spawn(async move {
+            let diagnostics = scheduler
+                .run("/project/main.n", version, move |_| {
+                    analyzed.lock().unwrap().push(version);
+                    Some(module_diagnostics(&module_analyze(&source)))
+                })
+                .await;
+            if let Some(diagnostics) = diagnostics {
+                published.lock().unwrap().push((version, diagnostics));
+            }
+        }));
+        // 时间处于暂停状态, 只有 advance 之后 debounce 才会推进
+        tokio::time::advance(Duration::from_millis(5)).await;
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    let published = published.lock().unwrap();
+    assert_eq!(*analyzed.lock().unwrap(), vec![6]);
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].0, 6);
+    assert!(!published[0].1.is_empty());
+    assert!(published[0].1.iter().all(|d| d.message.contains("b6")));
+}
+
+#[tokio::test]
+async fn test_scheduler_discards_stale_result() {
+    let scheduler = Arc::new(AnalysisScheduler::new(Duration::ZERO));
+
+    // 分析期间到达了新的版本, 旧版本的分析被取消且结果被丢弃
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let stale = tokio::spawn({
+        let scheduler = scheduler.clone();
+        async move {
+            scheduler
+                .run("/project/main.n", 1, |cancel| {
+                    started_tx.send(()).unwrap();
+                    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+                    while !cancel.is_cancelled() && std::time::Instant::now() < deadline {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    Some(cancel.is_cancelled())
+                })
+                .await
+        }
+    });
+    started_rx.await.unwrap();
+
+    let latest = scheduler.run("/project/main.n", 2, |_| Some(false)).await;
+    assert_eq!(stale.await.unwrap(), None);
+    assert_eq!(latest, Some(false));
+    assert!(scheduler.is_latest("/project/main.n", 2));
+
+    // 不同的文件互不影响
+    assert_eq!(scheduler.run("/project/util.n", 1, |_| Some(true)).await, Some(true));
+    assert!(scheduler.is_latest("/project/main.n", 2));
+}
+
+#[tokio::test]
+async fn test_scheduler_cancels_dropped_run() {
+    let scheduler = Arc::new(AnalysisScheduler::new(Duration::ZERO));
+
+    // 分析期间 run 的 future 被 drop, worker 观察到取消并尽快结束
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let (finished_tx, finished_rx) = tokio::sync::oneshot::channel();
+    let run = tokio::spawn({
+        let scheduler = scheduler.clone();
+        async move {
+            scheduler
+                .run("/project/main.n", 1, move |cancel| {
+                    started_tx.send(()).unwrap();
+                    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+                    while !cancel.is_cancelled() && std::time::Instant::now() < deadline {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    finished_tx.send(cancel.is_cancelled()).unwrap();
+                    Some(())
+                })
+                .await
+        }
+    });
+    started_rx.await.unwrap();
+    run.abort();
+    assert!(run.await.unwrap_err().is_cancelled());
+    assert!(finished_rx.await.unwrap());
+
+    // 被取消的 worker 结束之后下一个任务才会执行
+    assert_eq!(scheduler.run("/project/main.n", 2, |cancel| Some(cancel.is_cancelled())).await, Some(false));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_scheduler_waits_for_exclusive() {
+    let scheduler = Arc::new(AnalysisScheduler::new(Duration::ZERO));
+    let analyzed = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    // workspace 索引等任务持有锁期间, 分析任务不会开始执行
+    let running = scheduler.exclusive().await;
+    let handle = tokio::spawn({
+        let (scheduler, analyzed) = (scheduler.clone(), analyzed.clone());
+        async move {
+            scheduler
+                .run("/project/main.n", 1, move |_| {
+                    analyzed.store(true, std::sync::atomic::Ordering::SeqCst);
+                    Some(())
+                })
+                .await
+        }
+    });
+    tokio::time::advance(Duration::from_millis(100)).await;
+    assert!(!analyzed.load(std::sync::atomic::Ordering::SeqCst));
+
+    drop(running);
+    assert_eq!(handle.await.unwrap(), Some(()));
+    assert!(analyzed.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+fn test_analyzer_cancellation() {
+    let source = "fn main() {\n    var a = b\n    var c = d\n}\n";
+
+    let mut m = module_new(source, "/project/main.n", 0);
+    let mut symbol_table = SymbolTable::new();
+    Semantic::new(&mut m, &mut symbol_table).analyze();
+    assert!(!m.analyzer_errors.is_empty());
+
+    // 取消之后不再分析 fn body 中的 stmt
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let mut m = module_new(source, "/project/main.n", 0);
+    let mut symbol_table = SymbolTable::new();
+    Semantic::new(&mut m, &mut symbol_table).with_cancel(cancel.clone()).analyze();
+    let errors = Typesys::new(&mut symbol_table, &mut m).with_cancel(cancel.clone()).pre_infer();
+    assert!(errors.is_empty());
+    let errors = Typesys::new(&mut symbol_table, &mut m).with_cancel(cancel).infer();
+    assert!(errors.is_empty());
+    assert!(m.analyzer_errors.is_empty());
+}
+
+#[test]
+fn test_struct_field_lookup() {
+    let t = parse_type_str("struct{int id;string name;vec<bool> flags}").unwrap();
+
+    assert_eq!(struct_field_type(&t.kind, "id").unwrap().kind, TypeKind::Int);
+    assert_eq!(struct_field_type(&t.kind, "name").unwrap().kind, TypeKind::String);
+    assert_eq!(struct_field_type(&t.kind, "flags").unwrap().to_string(), "vec<bool>");
+    assert!(struct_field_type(&t.kind, "missing").is_none());
+
+    assert_eq!(struct_field_index(&t.kind, "id"), Some(0));
+    assert_eq!(struct_field_index(&t.kind, "name"), Some(1));
+    assert_eq!(struct_field_index(&t.kind, "flags"), Some(2));
+    assert_eq!(struct_field_index(&t.kind, "missing"), None);
+
+    // 非 struct 类型
+    assert!(struct_field_type(&TypeKind::Int, "id").is_none());
+    assert_eq!(struct_field_index(&TypeKind::String, "id"), None);
+}
+
+#[tokio::test]
+async fn test_index_workspace() {
+    let root = std::env::temp_dir().join(format!("nls_index_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("lib")).unwrap();
+    std::fs::create_dir_all(root.join(".git")).unwrap();
+
+    // 48 个正常的文件, 一部分 import 了前一个文件, 另外包含一个语法错误的文件与一个无法读取的文件
+    for i in 0..48 {
+        let mut source = String::new();
+        if i % 4 != 0 {
+            source.push_str(&format!("import \"mod_{}.n\"\n\n", i - 1));
+        }
+        source.push_str(&format!(
+            "fn helper_{}():int {{\n    return {}\n}}\n\ntype point_{} = struct {{\n    int x\n}}\n",
+            i, i, i
+        ));
+        std::fs::write(root.join(format!("lib/mod_{}.n", i)), source).unwrap();
+    }
+    std::fs::write(root.join("broken.n"), "fn broken() {\n    var a = )\n}\n").unwrap();
+    std::fs::write(root.join("binary.n"), [0xff, 0xfe, 0x00]).unwrap();
+    std::fs::write(root.join(".git/ignored.n"), "fn ignored() {}\n").unwrap();
+    std::fs::write(root.join("notes.txt"), "not a source file").unwrap();
+
+    let mut project = Project {
+        nature_root: String::new(),
+        root: root.to_string_lossy().to_string(),
+        module_db: Arc::new(Mutex::new(Vec::new())),
+        module_handled: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        queue: Arc::new(Mutex::new(Vec::new())),
+        package_config: None,
+        symbol_table: Arc::new(Mutex::new(SymbolTable::new())),
+        documents: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        position_encoding: PositionEncoding::default(),
+        settings: Default::default(),
+    };
+
+    let files = project.workspace_files();
+    assert_eq!(files.len(), 50);
+
+    let mut progress = Vec::new();
+    let failures = project.index_workspace(&files, |done| progress.push(done)).await;
+    assert_eq!(progress, (1..=50).collect::<Vec<_>>());
+
+    // 读取失败的文件不会中断索引, 而是被注册为 placeholder
+    let binary_path = root.join("binary.n").to_string_lossy().to_string();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, binary_path);
+    assert!(failures[0].1.starts_with("read file failed"));
+
+    let module_handled = project.module_handled.lock().unwrap().clone();
+    let module_db = project.module_db.lock().unwrap();
+    assert_eq!(module_db.len(), 50);
+    assert!(files.iter().all(|path| module_handled.contains_key(path)));
+    assert!(module_db[module_handled[&binary_path]].source.is_empty());
+
+    let broken_path = root.join("broken.n").to_string_lossy().to_string();
+    assert!(!module_diagnostics(&module_db[module_handled[&broken_path]]).is_empty());
+
+    let symbols: Vec<_> = module_db.iter().flat_map(|m| workspace_symbols(m, "helper_42")).collect();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].name, "helper_42");
+    assert_eq!(symbols[0].kind, tower_lsp::lsp_types::SymbolKind::FUNCTION);
+    assert_eq!(symbols[0].location.uri, Url::from_file_path(root.join("lib/mod_42.n")).unwrap());
+    assert_eq!(symbols[0].location.range, Range::new(Position::new(2, 3), Position::new(2, 12)));
+
+    // 子序列匹配, 忽略大小写, 全局 type 同样可以被查询
+    let symbols: Vec<_> = module_db.iter().flat_map(|m| workspace_symbols(m, "PNT_47")).collect();
+    let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["point_47"]);
+
+    // 48 个 helper 与 point, 以及 broken.n 中解析出的 broken
+    let symbols: Vec<_> = module_db.iter().flat_map(|m| workspace_symbols(m, "")).collect();
+    assert_eq!(symbols.len(), 97);
+
+    drop(module_db);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_watched_file_changes() {
+    let root = std::env::temp_dir().join(format!("nls_watch_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+
+    // 没有 package.toml 时 file import 无法解析到其他 module 的符号
+    let path = |name: &str| root.join(name).to_string_lossy().to_string();
+    std::fs::write(
+        path("package.toml"),
+        "name = \"watch\"\nversion = \"0.1.0\"\ntype = \"bin\"\n\n[dependencies]\n",
+    )
+    .unwrap();
+    std::fs::write(path("a.n"), "fn greet():int {\n    return 1\n}\n").unwrap();
+    std::fs::write(path("b.n"), "import \"a.n\"\n\nfn main() {\n    int x = a.greet()\n}\n").unwrap();
+
+    let mut project = Project {
+        nature_root: String::new(),
+        root: root.to_string_lossy().to_string(),
+        module_db: Arc::new(Mutex::new(Vec::new())),
+        module_handled: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        queue: Arc::new(Mutex::new(Vec::new())),
+        package_config: Some(Arc::new(Mutex::new(parse_package(&path("package.toml")).unwrap()))),
+        symbol_table: Arc::new(Mutex::new(SymbolTable::new())),
+        documents: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        position_encoding: PositionEncoding::default(),
+        settings: Default::default(),
+    };
+    let files = project.workspace_files();
+    project.index_workspace(&files, |_| {}).await;
+
+    let find = |project: &Project, query: &str| -> Vec<Url> {
+        let module_db = project.module_db.lock().unwrap();
+        module_db.iter().flat_map(|m| workspace_symbols(m, query)).map(|s| s.location.uri).collect()
+    };
+    let diagnostics = |project: &Project, name: &str| {
+        let index = project.module_handled.lock().unwrap()[&path(name)];
+        module_diagnostics(&project.module_db.lock().unwrap()[index])
+    };
+    assert!(diagnostics(&project, "b.n").is_empty());
+
+    // 声明的位置发生变化时不会产生 redeclare 错误, 依赖 a.n 的 b.n 同样被重新分析
+    std::fs::write(
+        path("a.n"),
+        "// moved\n\nfn farewell():int {\n    return 2\n}\n\nfn greet():int {\n    return 1\n}\n",
+    )
+    .unwrap();
+    let affected = project.apply_file_change(&path("a.n"), false).await;
+    assert_eq!(affected, vec![path("a.n"), path("b.n")]);
+    assert!(diagnostics(&project, "a.n").is_empty());
+    assert!(diagnostics(&project, "b.n").is_empty());
+    assert_eq!(find(&project, "farewell").len(), 1);
+    assert_eq!(find(&project, "greet").len(), 1);
+
+    // 删除之后符号不再可见, import 了 a.n 的 b.n 产生诊断
+    std::fs::remove_file(path("a.n")).unwrap();
+    let affected = project.apply_file_change(&path("a.n"), true).await;
+    assert_eq!(affected, vec![path("b.n")]);
+    assert!(find(&project, "greet").is_empty());
+    assert!(!project.module_handled.lock().unwrap().contains_key(&path("a.n")));
+    assert!(!diagnostics(&project, "b.n").is_empty());
+
+    // 新文件可以被 workspace symbol 查询
+    std::fs::write(path("c.n"), "fn shout():int {\n    return 3\n}\n").unwrap();
+    let affected = project.apply_file_change(&path("c.n"), false).await;
+    assert_eq!(affected, vec![path("c.n")]);
+    assert_eq!(find(&project, "shout"), vec![Url::from_file_path(path("c.n")).unwrap()]);
+
+    // 重命名拆分为 deleted + created, 不会遗留旧文件的符号
+    std::fs::rename(path("c.n"), path("d.n")).unwrap();
+    project.apply_file_change(&path("c.n"), true).await;
+    project.apply_file_change(&path("d.n"), false).await;
+    assert_eq!(find(&project, "shout"), vec![Url::from_file_path(path("d.n")).unwrap()]);
+
+    // 重新创建 a.n 之后, 之前 import 失败的 b.n 恢复正常
+    std::fs::write(path("a.n"), "fn greet():int {\n    return 1\n}\n").unwrap();
+    let affected = project.apply_file_change(&path("a.n"), false).await;
+    assert_eq!(affected, vec![path("a.n"), path("b.n")]);
+    assert!(diagnostics(&project, "b.n").is_empty());
+
+    // 同时删除 a.n 与 import 了 a.n 的 b.n, 先处理 a.n 时不会重新 build 已经不存在的 b.n
+    std::fs::remove_file(path("a.n")).unwrap();
+    std::fs::remove_file(path("b.n")).unwrap();
+    assert!(project.apply_file_change(&path("a.n"), true).await.is_empty());
+    assert!(project.apply_file_change(&path("b.n"), true).await.is_empty());
+    assert!(!project.module_handled.lock().unwrap().contains_key(&path("b.n")));
+    assert!(find(&project, "main").is_empty());
+
+    std::fs::write(path("a.n"), "fn greet():int {\n    return 1\n}\n").unwrap();
+    std::fs::write(path("b.n"), "import \"a.n\"\n\nfn main() {\n    int x = a.greet()\n}\n").unwrap();
+    assert_eq!(project.apply_file_change(&path("a.n"), false).await, vec![path("a.n")]);
+    assert_eq!(project.apply_file_change(&path("b.n"), false).await, vec![path("b.n")]);
+    assert!(diagnostics(&project, "b.n").is_empty());
+
+    // 编辑器中打开的文件忽略磁盘上的变化
+    project.documents.lock().unwrap().insert(path("b.n"), Document::new(String::new(), 1));
+    assert!(project.apply_file_change(&path("b.n"), false).await.is_empty());
+
+    // package.toml 被删除之后重新 build 包含 import 的 module
+    std::fs::remove_file(path("package.toml")).unwrap();
+    assert_eq!(project.reload_package_config().await, vec![path("b.n")]);
+    assert!(project.package_config.is_none());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_syntax_error_at_token() {
+    let token = Token::new(TokenType::Ident, Atom::from("foo"), 4, 7, 0);
+    let error = SyntaxError::at_token(&token, "unexpected ident");
+    assert_eq!(error, SyntaxError(Span::new(4, 7), "unexpected ident".to_string()));
+    assert_eq!(
+        SyntaxError::at_token(&token, format!("got '{}'", token.literal)),
+        SyntaxError(Span::new(4, 7), "got 'foo'".to_string())
+    );
+
+    // at_current 使用尚未消费的 token 的位置
+    let (token_db, token_indexes, _) = Lexer::new("var a = 1".to_string()).scan();
+    let syntax = Syntax::new(token_db, token_indexes);
+    assert_eq!(
+        SyntaxError::at_current(&syntax, "unexpected var"),
+        SyntaxError(Span::new(0, 3), "unexpected var".to_string())
+    );
+}
+
+#[test]
+fn test_position_encoding() {
+    use tower_lsp::lsp_types::PositionEncodingKind;
+
+    // 按照客户端声明的优先级选择, 未声明时使用 utf-16
+    assert_eq!(PositionEncoding::negotiate(None), PositionEncoding::Utf16);
+    assert_eq!(
+        PositionEncoding::negotiate(Some(&[PositionEncodingKind::UTF32, PositionEncodingKind::UTF8])),
+        PositionEncoding::Utf8
+    );
+    assert_eq!(
+        PositionEncoding::negotiate(Some(&[PositionEncodingKind::UTF16, PositionEncodingKind::UTF8])),
+        PositionEncoding::Utf16
+    );
+    assert_eq!(PositionEncoding::negotiate(Some(&[PositionEncodingKind::UTF32])), PositionEncoding::Utf16);
+
+    let source = "fn show(string s, int n) {\n}\n\nfn main() {\n    int count = 1\n    show('😀中文', count)\n    show('😀中文', missing)\n}\n";
+    let char_offset = |pattern: &str| source[..source.find(pattern).unwrap()].chars().count();
+
+    // (encoding, count 与 missing 在行内的起始列)
+    for (encoding, column) in [(PositionEncoding::Utf16, 17), (PositionEncoding::Utf8, 23)] {
+        let mut m = module_new(source, "/project/main.n", 0);
+        m.line_index = LineIndex::new(source, encoding);
+        let mut symbol_table = SymbolTable::new();
+        Semantic::new(&mut m, &mut symbol_table).analyze();
+        let errors = Typesys::new(&mut symbol_table, &mut m).pre_infer();
+        m.analyzer_errors.extend(errors);
+        let errors = Typesys::new(&mut symbol_table, &mut m).infer();
+        m.analyzer_errors.extend(errors);
+
+        let offset = char_offset("count)");
+        let hover = hover(&m, &symbol_table, &ReferenceIndex::build(&m), offset).unwrap();
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(5, column), Position::new(5, column + 5))),
+            "{:?}",
+            encoding
+        );
+
+        let diagnostics = module_diagnostics(&m);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(6, column), Position::new(6, column + 7)),
+            "{:?}",
+            encoding
+        );
+
+        // position 与 offset 相互转换
+        assert_eq!(m.line_index.position(offset), Some(Position::new(5, column)));
+        assert_eq!(m.line_index.offset(Position::new(5, column)), offset);
+    }
+
+    // 位于多字节字符中间的 position 向后对齐到完整的字符
+    let emoji = char_offset("😀");
+    assert_eq!(LineIndex::new(source, PositionEncoding::Utf16).offset(Position::new(5, 11)), emoji + 1);
+    assert_eq!(LineIndex::new(source, PositionEncoding::Utf8).offset(Position::new(5, 12)), emoji + 1);
+
+    // 编辑器中的增量修改同样按照协商的编码解析
+    let mut document = Document::with_encoding("var a = '😀中文'\n".to_string(), 1, PositionEncoding::Utf8);
+    document.apply_changes(vec![content_change((0, 13), (0, 16), "英")], 2);
+    assert_eq!(document.text(), "var a = '😀英文'\n");
+    let mut document = Document::with_encoding("var a = '😀中文'\n".to_string(), 1, PositionEncoding::Utf16);
+    document.apply_changes(vec![content_change((0, 11), (0, 12), "英")], 2);
+    assert_eq!(document.text(), "var a = '😀英文'\n");
+}
+
+#[test]
+fn test_validate_ast() {
+    let source = "fn main() {\n    var a = 1\n    for int i = 0; i < 10; i += 1 {\n    }\n    a catch e {\n    }\n    foo() catch e {\n    }\n}\n";
+    let result = nls::parse(source);
+
+    // 作为语句的 catch 只能用于 call
+    let errors = &result.errors;
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].phase, DiagnosticPhase::Validate);
+    assert_eq!(errors[0].message, "catch statement must follow a call expression");
+    assert_eq!(&source[errors[0].span.start as usize..errors[0].span.start as usize + 7], "a catch");
+    let mut stmts = result.stmts;
+
+    // 手动破坏 ast 中的约束
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let mut fndef = fndef_mutex.write().unwrap();
+    fndef.symbol_name.clear();
+    let AstNode::VarDef(var_decl, _) = &fndef.body[0].node else {
+        panic!("expect var def")
+    };
+    var_decl.write().unwrap().ident = Atom::default();
+    let AstNode::ForTradition(init, ..) = &mut fndef.body[1].node else {
+        panic!("expect for tradition")
+    };
+    init.node = AstNode::Continue;
+    drop(fndef);
+
+    let messages: Vec<String> = Syntax::validate_ast(&stmts).into_iter().map(|e| e.message).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "fn definition has empty symbol name",
+            "var definition has empty ident",
+            "for init must be a single declaration, assignment or call statement",
+            "catch statement must follow a call expression",
+        ]
+    );
+    stmts.clear();
+    assert!(Syntax::validate_ast(&stmts).is_empty());
+}
+
+#[test]
+fn test_settings_lint_toggle() {
+    let source = "fn sum(int a):int {\n    var unused = 1\n    var _ignored = 2\n    var b = a\n    if b > 0 {\n        var a = b + 1\n        return a\n    }\n    return b\n}\n";
+    let m = module_analyze(source);
+    assert!(module_diagnostics(&m).is_empty(), "{:?}", module_diagnostics(&m));
+
+    let messages = |settings: &Settings| -> Vec<String> { file_diagnostics(&m, &settings.diagnostic_config()).into_iter().map(|d| d.message).collect() };
+
+    let settings = Settings::default();
+    assert_eq!(
+        messages(&settings),
+        vec!["unused variable 'unused'", "variable 'a' shadows a declaration in an outer scope"]
+    );
+    let diagnostics = file_diagnostics(&m, &settings.diagnostic_config());
+    assert!(diagnostics.iter().all(|d| d.severity == Some(DiagnosticSeverity::WARNING)));
+    assert_eq!(diagnostics[0].range, Range::new(Position::new(1, 8), Position::new(1, 14)));
+
+    // 关闭 unused variable 之后对应的诊断消失, 未知的 key 被忽略
+    let settings = Settings::from_value(&serde_json::json!({
+        "diagnostics": { "unusedVariable": false, "unknown": true },
+        "unknown": 1,
+    }));
+    assert_eq!(messages(&settings), vec!["variable 'a' shadows a declaration in an outer scope"]);
+    assert_eq!(settings.diagnostics.max_per_file, Settings::default().diagnostics.max_per_file);
+
+    let settings = Settings::from_value(&serde_json::json!({ "diagnostics": { "shadowing": false, "maxPerFile": 1 } }));
+    assert_eq!(messages(&settings), vec!["unused variable 'unused'"]);
+    let settings = Settings::from_value(&serde_json::json!({ "diagnostics": { "maxPerFile": 1 } }));
+    assert_eq!(messages(&settings).len(), 1);
+
+    // 类型错误的 section 使用默认值
+    let settings = Settings::from_value(&serde_json::json!({ "format": { "indentWidth": 2 }, "inlayHints": "off", "importPaths": ["/opt/nature"] }));
+    assert_eq!(settings.format_config(4).indent_width, 2);
+    assert_eq!(Settings::default().format_config(4).indent_width, 4);
+    assert_eq!(settings.inlay_hints, Settings::default().inlay_hints);
+    assert_eq!(settings.import_paths, vec!["/opt/nature".to_string()]);
+}
+
+#[test]
+fn test_macro_stringify_with_source() {
+    let source = "fn main() {\n    var s = '中文'\n    var a = @stringify(s  +  'x')\n    var b = @stringify(foo(1,\n        2))\n}\n";
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let (stmts, _, errors) = Syntax::with_source(token_db, token_indexes, ParserConfig::default(), Arc::new(source.to_string())).parser();
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    let stringified = |stmt: &Stmt| -> String {
+        let AstNode::Literal(TypeKind::String, value, LiteralSource::Synthesized) = &var_def_right(stmt).node else {
+            panic!("expect string literal")
+        };
+        value.clone()
+    };
+
+    // 存在原始源码时保留原样的空白与引号
+    assert_eq!(stringified(&fndef.body[1]), "s  +  'x'");
+    assert_eq!(stringified(&fndef.body[2]), "foo(1,\n        2)");
+    drop(fndef);
+
+    // nls::parse 同样保留原始源码
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    assert_eq!(stringified(&fndef_mutex.read().unwrap().body[1]), "s  +  'x'");
+
+    // 没有源码时通过 token 还原
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let (stmts, _, _) = Syntax::new(token_db, token_indexes).parser();
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    assert_eq!(stringified(&fndef.body[1]), "s + \"x\"");
+}
+
+#[test]
+fn test_linked_editing_ranges() {
+    let source = "var total = 0\n\nfn scale(int factor):int {\n    var a = factor * 2\n    var b = factor + a\n    return factor + b\n}\n\nfn main() {\n    var x = scale(1)\n    total = x\n}\n";
+    let m = module_analyze(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    let index = ReferenceIndex::build(&m);
+
+    // 参数的声明与三处使用
+    let ranges = linked_editing_ranges(&index, &m, source.find("factor + a").unwrap()).unwrap();
+    assert_eq!(
+        ranges.ranges,
+        vec![
+            Range::new(Position::new(2, 13), Position::new(2, 19)),
+            Range::new(Position::new(3, 12), Position::new(3, 18)),
+            Range::new(Position::new(4, 12), Position::new(4, 18)),
+            Range::new(Position::new(5, 11), Position::new(5, 17)),
+        ]
+    );
+    assert_eq!(ranges.word_pattern.as_deref(), Some(IDENT_WORD_PATTERN));
+    assert_eq!(linked_editing_ranges(&index, &m, source.find("var x").unwrap() + 4).unwrap().ranges.len(), 2);
+
+    // global fn 与 global var 可能被其他文件引用
+    assert!(linked_editing_ranges(&index, &m, source.find("scale(1)").unwrap()).is_none());
+    assert!(linked_editing_ranges(&index, &m, source.find("fn scale").unwrap() + 3).is_none());
+    assert!(linked_editing_ranges(&index, &m, source.find("total = x").unwrap()).is_none());
+    assert!(linked_editing_ranges(&index, &m, source.find("int factor").unwrap()).is_none());
+}
+
+#[test]
+fn test_flatten_imports() {
+    let mut stmts = parse("import math\nimport 'utils/str.n'\n\nfn main() {\n    var a = 1\n}\n\nimport std.io as io\n");
+    let describe = |import: &ImportStmt| -> String { import.file.clone().unwrap_or_else(|| import.ast_package.clone().unwrap_or_default().join(".")) };
+
+    let imports: Vec<String> = flatten_imports(&stmts).into_iter().map(describe).collect();
+    assert_eq!(imports, vec!["math", "utils/str.n", "std.io"]);
+    assert!(flatten_imports(&parse("fn main() {\n}\n")).is_empty());
+    assert!(flatten_imports_deep(&parse("fn main() {\n}\n")).is_empty());
+
+    // 宏展开等场景下 body 中可能出现 import, 只有 deep 版本会进入 body
+    let nested = stmts[0].clone();
+    let AstNode::FnDef(fndef_mutex) = &stmts[2].node else {
+        panic!("expect fn def")
+    };
+    fndef_mutex.write().unwrap().body.push(nested);
+    assert_eq!(flatten_imports(&stmts).len(), 3);
+    let imports: Vec<String> = flatten_imports_deep(&stmts).iter().map(describe).collect();
+    assert_eq!(imports, vec!["math", "utils/str.n", "math", "std.io"]);
+
+    stmts.clear();
+    assert!(flatten_imports(&stmts).is_empty());
+}
+
+#[tokio::test]
+async fn test_std_index() {
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let root = std::env::temp_dir().join(format!("nls_std_index_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let root_str = root.to_string_lossy().to_string();
+    let main_path = root.join("main.n").to_string_lossy().to_string();
+
+    let build = |nature_root: std::path::PathBuf, source: &'static str| {
+        let (root_str, main_path) = (root_str.clone(), main_path.clone());
+        async move {
+            std::fs::write(&main_path, source).unwrap();
+            let settings = Settings {
+                nature_root: Some(nature_root.to_string_lossy().to_string()),
+                ..Default::default()
+            };
+            let mut project = Project::new(root_str.clone(), PositionEncoding::default(), Arc::new(Mutex::new(settings))).await;
+            let index = project.build(&main_path, &module_unique_ident(&root_str, &main_path)).await;
+            (project, index)
+        }
+    };
+
+    // builtin 与 std package 从 nature root 中解析, 并且标记为只读
+    let (project, index) = build(
+        fixtures.join("stdlib"),
+        "import fmt\n\nfn main() {\n    var s = fmt.sprintf('%d', 1)\n    println(s, s.len())\n}\n",
+    )
+    .await;
+    {
+        let module_db = project.module_db.lock().unwrap();
+        assert!(module_db[index].analyzer_errors.is_empty(), "{:?}", module_db[index].analyzer_errors);
+        assert!(!module_db[index].read_only);
+        assert!(module_db.iter().any(|m| m.path.ends_with("std/fmt/main.n") && m.read_only));
+        assert!(module_db.iter().any(|m| m.path.ends_with("std/builtin/builtin.n") && m.read_only));
+        assert!(module_db.iter().all(|m| m.path != FALLBACK_BUILTIN_PATH));
+    }
+
+    // 找不到 std 时使用内置的 builtin 签名
+    let missing = fixtures.join("missing_nature_root");
+    let (project, index) = build(missing.clone(), "fn main() {\n    println('hello', 'x'.len())\n    assert(true)\n}\n").await;
+    {
+        let module_db = project.module_db.lock().unwrap();
+        assert!(module_db[index].analyzer_errors.is_empty(), "{:?}", module_db[index].analyzer_errors);
+        assert!(module_db
+            .iter()
+            .any(|m| m.path == FALLBACK_BUILTIN_PATH && m.read_only && m.analyzer_errors.is_empty()));
+    }
+
+    let (project, index) = build(missing, "import fmt\n\nfn main() {\n}\n").await;
+    let messages: Vec<String> = project.module_db.lock().unwrap()[index]
+        .analyzer_errors
+        .iter()
+        .map(|e| e.message.clone())
+        .collect();
+    assert_eq!(messages, vec!["package 'fmt' not found"]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_merge_adjacent_errors() {
+    let error = |start: usize, end: usize, message: &str| AnalyzerError::new(Span::new(start, end), message.to_string());
+    let spans = |errors: &[AnalyzerError]| -> Vec<(usize, usize, String)> {
+        errors.iter().map(|e| (e.span.start as usize, e.span.end as usize, e.message.clone())).collect()
+    };
+
+    // 三个重叠的错误合并为一个, 不要求输入有序
+    let merged = merge_adjacent_errors(vec![error(8, 12, "c"), error(2, 6, "a"), error(5, 9, "b")]);
+    assert_eq!(spans(&merged), vec![(2, 12, "a; b; c".to_string())]);
+
+    // 不重叠的错误保持独立, 相同的 message 只保留一个
+    let merged = merge_adjacent_errors(vec![error(10, 14, "b"), error(0, 4, "a"), error(3, 4, "a")]);
+    assert_eq!(spans(&merged), vec![(0, 4, "a".to_string()), (10, 14, "b".to_string())]);
+    assert!(merge_adjacent_errors(Vec::new()).is_empty());
+}
+
+#[tokio::test]
+async fn test_package_import_resolution() {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/package_import");
+    let root_str = root.to_string_lossy().to_string();
+    let main_path = root.join("main.n").to_string_lossy().to_string();
+
+    let mut project = Project::new(root_str.clone(), PositionEncoding::default(), Default::default()).await;
+    let index = project.build(&main_path, &module_unique_ident(&root_str, &main_path)).await;
+
+    let module_db = project.module_db.lock().unwrap();
+    let m = &module_db[index];
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    assert_eq!(m.package_config.as_ref().unwrap().lock().unwrap().package_data.name, "app");
+
+    // import mydep.utils 解析到 path 指定的 local 依赖
+    let import = &m.dependencies[0];
+    assert_eq!(import.full_path, root.join("deps/mydep/utils.n").to_string_lossy());
+    assert_eq!(import.package_dir, root.join("deps/mydep").to_string_lossy());
+    assert_eq!(import.package_conf.as_ref().unwrap().package_data.name, "mydep");
+    assert_eq!(import.as_name, "utils");
+
+    // 依赖中的 module 使用自身最近的 package.toml, import mydep.helper 属于当前 package
+    let utils = module_db.iter().find(|m| m.path == import.full_path).unwrap();
+    assert!(utils.analyzer_errors.is_empty(), "{:?}", utils.analyzer_errors);
+    assert_eq!(utils.package_config.as_ref().unwrap().lock().unwrap().package_data.name, "mydep");
+    assert_eq!(utils.dependencies[0].full_path, root.join("deps/mydep/helper.n").to_string_lossy());
+    drop(module_db);
+
+    // package.toml 缺少必要的字段时, 错误位于依赖 package 的 import 上
+    let dir = std::env::temp_dir().join(format!("nls_package_import_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("package.toml"), "name = \"app\"\ntype = \"bin\"\n").unwrap();
+    std::fs::write(dir.join("lib/util.n"), "fn f() {\n}\n").unwrap();
+    let source = "import app.lib.util\nimport 'lib/util.n'\n\nfn main() {\n}\n";
+    std::fs::write(dir.join("main.n"), source).unwrap();
+
+    let dir_str = dir.to_string_lossy().to_string();
+    let main_path = dir.join("main.n").to_string_lossy().to_string();
+    let mut project = Project::new(dir_str.clone(), PositionEncoding::default(), Default::default()).await;
+    assert!(project.package_config.is_none());
+    let index = project.build(&main_path, &module_unique_ident(&dir_str, &main_path)).await;
+
+    let errors = project.module_db.lock().unwrap()[index].analyzer_errors.clone();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].span, Span::new(0, "import app.lib.util".len()));
+    assert!(errors[0].message.starts_with("invalid package config"), "{}", errors[0].message);
+    assert!(errors[0].message.contains("version"), "{}", errors[0].message);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_go_requires_direct_call() {
+    let messages = |source: &str| -> Vec<String> { parse_with_errors(source).1.into_iter().map(|e| e.message).collect() };
+
+    assert!(messages("fn main() {\n    go foo()\n    go a.b()\n    go a.b.c(1)\n    var f = @async(a.b(), 0)\n}\n").is_empty());
+
+    // callee 可以是 index 与 closure 等任意表达式
+    let source = "fn main() {\n    go list[0]()\n    go m.handlers[1](2)\n    var f = @async(list[0]())\n    go fn() {\n        foo()\n    }()\n}\n";
+    assert!(messages(source).is_empty(), "{:?}", messages(source));
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    let callee = |stmt: &Stmt| -> AstNode {
+        let AstNode::Fake(expr) = &stmt.node else {
+            panic!("expect fake, got {:?}", stmt.node)
+        };
+        let AstNode::MacroAsync(async_expr) = &expr.node else {
+            panic!("expect async, got {:?}", expr.node)
+        };
+        async_expr.origin_call.left.node.clone()
+    };
+    assert!(matches!(callee(&fndef.body[0]), AstNode::AccessExpr(..)));
+    assert!(matches!(callee(&fndef.body[3]), AstNode::FnDef(..)));
+
+    let source = "fn main() {\n    go a.b().c()\n    go a.b()[0]()\n    var g = @async(1 + 2)\n}\n";
+    let (_, errors) = parse_with_errors(source);
+    let errors: Vec<(&str, &str)> = errors.iter().map(|e| (&source[e.span.range()], e.message.as_str())).collect();
+    assert_eq!(
+        errors,
+        vec![
+            ("a.b().c()", "go/@async requires a direct function call"),
+            ("a.b()[0]()", "go/@async requires a direct function call"),
+            ("1 + 2", "async expr must be call"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_import_rename_edits() {
+    let root = std::env::temp_dir().join(format!("nls_rename_files_{}", std::process::id()));
+    std::fs::create_dir_all(root.join("lib")).unwrap();
+    std::fs::write(
+        root.join("package.toml"),
+        "name = \"app\"\nversion = \"0.1.0\"\ntype = \"bin\"\n\n[dependencies]\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("lib/util.n"), "fn f() {\n}\n").unwrap();
+    std::fs::write(root.join("main.n"), "import 'lib/util.n'\n\nfn main() {\n}\n").unwrap();
+    std::fs::write(root.join("cli.n"), "import app.lib.util\n\nfn main() {\n}\n").unwrap();
+    std::fs::write(root.join("other.n"), "import \"lib/util.n\"\n\nfn main() {\n}\n").unwrap();
+    let path = |name: &str| root.join(name).to_string_lossy().to_string();
+    let root_str = root.to_string_lossy().to_string();
+
+    let mut project = Project::new(root_str.clone(), PositionEncoding::default(), Default::default()).await;
+    // other.n 中尚未保存的内容, edit 基于编辑器中的内容计算
+    let unsaved = "// unsaved\n\nimport \"lib/util.n\"\n\nfn main() {\n}\n";
+    project.documents.lock().unwrap().insert(path("other.n"), Document::new(unsaved.to_string(), 2));
+    for name in ["main.n", "other.n", "cli.n"] {
+        project.build(&path(name), &module_unique_ident(&root_str, &path(name))).await;
+    }
+
+    let module_db = project.module_db.lock().unwrap();
+    let edits_of = |changes: &std::collections::HashMap<Url, Vec<tower_lsp::lsp_types::TextEdit>>, name: &str| -> Vec<(Range, String)> {
+        let uri = Url::from_file_path(path(name)).unwrap();
+        changes
+            .get(&uri)
+            .map(|edits| edits.iter().map(|e| (e.range, e.new_text.clone())).collect())
+            .unwrap_or_default()
+    };
+    let range = |line: u32, start: u32, end: u32| Range::new(Position::new(line, start), Position::new(line, end));
+
+    // 文件 rename, 两个 importer 都只改写引号中的路径
+    let changes = import_rename_edits(&module_db, &[(path("lib/util.n"), path("lib/helpers.n"))]);
+    assert_eq!(changes.len(), 3, "{:?}", changes);
+    assert_eq!(edits_of(&changes, "main.n"), vec![(range(0, 8, 18), "lib/helpers.n".to_string())]);
+    assert_eq!(edits_of(&changes, "other.n"), vec![(range(2, 8, 18), "lib/helpers.n".to_string())]);
+    assert_eq!(edits_of(&changes, "cli.n"), vec![(range(0, 7, 19), "app.lib.helpers".to_string())]);
+
+    // 目录 rename
+    let changes = import_rename_edits(&module_db, &[(path("lib"), path("core/base"))]);
+    assert_eq!(edits_of(&changes, "main.n"), vec![(range(0, 8, 18), "core/base/util.n".to_string())]);
+    assert_eq!(edits_of(&changes, "cli.n"), vec![(range(0, 7, 19), "app.core.base.util".to_string())]);
+
+    // importer 与 target 一起移动时相对路径不变, package 中的路径仍然需要改写
+    let changes = import_rename_edits(
+        &module_db,
+        &[
+            (path("main.n"), path("src/main.n")),
+            (path("cli.n"), path("src/cli.n")),
+            (path("lib"), path("src/lib")),
+        ],
+    );
+    assert!(edits_of(&changes, "main.n").is_empty());
+    assert_eq!(edits_of(&changes, "cli.n"), vec![(range(0, 7, 19), "app.src.lib.util".to_string())]);
+
+    // 无关的 rename 不产生 edit
+    assert!(import_rename_edits(&module_db, &[(path("unused.n"), path("x.n"))]).is_empty());
+    drop(module_db);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_struct_new_duplicate_field() {
+    parse("fn main() {\n    var f = Foo { a = 1, b = 2 }\n}\n");
+
+    let source = "fn main() {\n    var f = Foo { a = 1, b = 2, a = 3 }\n}\n";
+    let (_, errors) = parse_with_errors(source);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].message, "duplicate field 'a' in struct literal");
+    assert_eq!(errors[0].span.start as usize, source.rfind("a = 3").unwrap());
+    assert_eq!(errors[0].span.end, errors[0].span.start + 1);
+}
+
+#[tokio::test]
+async fn test_rebuild_dependents_on_save() {
+    let root = std::env::temp_dir().join(format!("nls_save_dependents_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+
+    let path = |name: &str| root.join(name).to_string_lossy().to_string();
+    std::fs::write(path("package.toml"), "name = \"save\"\nversion = \"0.1.0\"\ntype = \"bin\"\n\n[dependencies]\n").unwrap();
+    std::fs::write(path("a.n"), "fn greet():int {\n    return 1\n}\n").unwrap();
+    std::fs::write(path("b.n"), "import \"a.n\"\n\nfn b_main():int {\n    return a.greet()\n}\n").unwrap();
+    std::fs::write(path("c.n"), "import \"b.n\"\n\nfn c_main():int {\n    return b.b_main()\n}\n").unwrap();
+    std::fs::write(path("d.n"), "import \"c.n\"\n\nfn main() {\n    int x = c.c_main()\n}\n").unwrap();
+
+    let root_str = root.to_string_lossy().to_string();
+    let mut project = Project::new(root_str.clone(), PositionEncoding::default(), Default::default()).await;
+    project.build(&path("d.n"), &module_unique_ident(&root_str, &path("d.n"))).await;
+
+    let diagnostics = |project: &Project, name: &str| {
+        let index = project.module_handled.lock().unwrap()[&path(name)];
+        module_diagnostics(&project.module_db.lock().unwrap()[index])
+    };
+    assert!(diagnostics(&project, "b.n").is_empty());
+
+    // 反向依赖按层数截断
+    assert_eq!(project.dependents_within(&path("a.n"), 1), vec![path("b.n")]);
+    assert_eq!(project.dependents_within(&path("a.n"), DEPENDENT_REBUILD_DEPTH), vec![path("b.n"), path("c.n")]);
+    assert_eq!(project.dependents_within(&path("a.n"), 10), vec![path("b.n"), path("c.n"), path("d.n")]);
+
+    // 删除 a.n 中的 fn 并保存, b.n 没有被编辑但是产生了新的诊断
+    let source = "fn farewell():int {\n    return 2\n}\n";
+    std::fs::write(path("a.n"), source).unwrap();
+    project.documents.lock().unwrap().insert(path("a.n"), Document::new(source.to_string(), 2));
+    project.build(&path("a.n"), &module_unique_ident(&root_str, &path("a.n"))).await;
+    assert!(diagnostics(&project, "b.n").is_empty());
+
+    let rebuilt = project.rebuild_dependents(&path("a.n"), &CancellationToken::new()).await;
+    assert_eq!(rebuilt, Some(vec![path("b.n"), path("c.n")]));
+    let b_diagnostics = diagnostics(&project, "b.n");
+    assert!(b_diagnostics.iter().any(|d| d.message.contains("greet")), "{:?}", b_diagnostics);
+
+    // 被取消时不返回结果
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    assert_eq!(project.rebuild_dependents(&path("a.n"), &cancel).await, None);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_void_var_type() {
+    let source = "fn main() {\n    void x = 1\n}\n";
+    let (_, errors) = parse_with_errors(source);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].message, "void cannot be used as a variable type; use var for type inference");
+    assert_eq!(&source[errors[0].span.range()], "void");
+
+    // 全局变量同样不能声明为 void
+    let (_, errors) = parse_with_errors("void x = 1\n");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+
+    parse("fn main() {\n    ptr<void> p = null_ptr()\n    var v = 1\n}\n");
+    parse("fn foo(): void {\n}\n");
+}
+
+#[test]
+fn test_normalize_type() {
+    let vec_short = parse_type_str("[int]").unwrap();
+    let vec_long = parse_type_str("vec<int>").unwrap();
+    assert_eq!(normalize_type(&vec_short).to_string(), "vec<int>");
+    assert_eq!(vec_short, vec_long);
+    assert_ne!(vec_short, parse_type_str("vec<string>").unwrap());
+
+    let map_short = parse_type_str("{string:int}").unwrap();
+    let map_long = parse_type_str("map<string,int>").unwrap();
+    assert_eq!(map_short, map_long);
+    assert_ne!(map_short, parse_type_str("map<string,bool>").unwrap());
+
+    // 位置与 type alias 等元信息不参与比较
+    let mut aliased = parse_type_str("  vec<[int]>").unwrap();
+    aliased.origin_ident = Some("matrix".to_string());
+    assert_eq!(aliased.to_string(), "matrix");
+    assert_eq!(aliased, parse_type_str("[[int]]").unwrap());
+    let normalized = normalize_type(&aliased);
+    assert!(normalized.origin_ident.is_none());
+    assert_eq!(normalized.span, Span::new(0, 0));
+
+    // 逐层比较 kind 中携带的数据
+    assert_eq!(parse_type_str("arr<int,4>").unwrap(), parse_type_str("arr<int,4>").unwrap());
+    assert_ne!(parse_type_str("arr<int,4>").unwrap(), parse_type_str("arr<int,5>").unwrap());
+    assert_ne!(parse_type_str("fn(int):bool").unwrap(), parse_type_str("fn(int):bool!").unwrap());
+    assert_ne!(parse_type_str("fn(int):bool").unwrap(), parse_type_str("fn(i8):bool").unwrap());
+    assert_eq!(parse_type_str("struct{int a}").unwrap(), parse_type_str("struct{int a}").unwrap());
+    assert_ne!(parse_type_str("struct{int a}").unwrap(), parse_type_str("struct{int b}").unwrap());
+    assert_ne!(parse_type_str("box<int>").unwrap(), parse_type_str("box<i8>").unwrap());
+}
+
+// AstNodeId 只在单次解析中唯一, 比较不同解析得到的 ast 时需要忽略
+fn strip_ids(debug: &str) -> String {
+    let key = "AstNodeId(";
+    let mut stripped = String::new();
+    let mut rest = debug;
+    while let Some(index) = rest.find(key) {
+        stripped.push_str(&rest[..index + key.len()]);
+        stripped.push('_');
+        rest = rest[index + key.len()..].trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+// 忽略位置与 id 比较两段 ast, 失败时输出两侧的源码
+macro_rules! assert_ast_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        assert_ast_eq!($left, $right, "ast not equal")
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                let (left, right): (&[Box<Stmt>], &[Box<Stmt>]) = (left.as_ref(), right.as_ref());
+                assert!(
+                    body_structurally_eq(left, right),
+                    "{}\n--- left\n{}\n--- right\n{}\n",
+                    format_args!($($arg)+),
+                    body_to_source(left),
+                    body_to_source(right)
+                );
+            }
+        }
+    };
+}
+
+#[test]
+fn test_printer_precedence() {
+    let cases = [
+        ("var a = (1 + 2) * 3", "var a = (1 + 2) * 3"),
+        ("var a = 1 + (2 * 3)", "var a = 1 + 2 * 3"),
+        ("var a = (a - b) - c", "var a = a - b - c"),
+        ("var a = a - (b - c)", "var a = a - (b - c)"),
+        ("var a = 2 ** (3 ** 2)", "var a = 2 ** 3 ** 2"),
+        ("var a = (2 ** 3) ** 2", "var a = (2 ** 3) ** 2"),
+        ("var a = -(x ** 2)", "var a = -(x ** 2)"),
+        ("var a = !(a && b) || c", "var a = !(a && b) || c"),
+        ("var a = (a + 1) as float", "var a = (a + 1) as float"),
+        ("var a = (foo{x = 1}).x", "var a = (foo{x = 1}).x"),
+        ("var a = (fn() {})()", "var a = (fn() {})()"),
+        ("var s = \"a\\n\\\"b\\\"\"", "var s = \"a\\n\\\"b\\\"\""),
+        ("int a = a * -b", "int a = a * -b"),
+    ];
+
+    for (source, expected) in cases {
+        let stmts = parse(source);
+        assert_eq!(to_source(&stmts[0]), expected);
+        assert_eq!(stmts[0].to_string(), expected);
+        assert_ast_eq!(parse(expected), stmts, "{}", source);
+    }
+}
+
+#[test]
+fn test_printer_round_trip() {
+    let source = r#"import "lib/util.n"
+import co.mutex as m
+
+var global_count = 0
+int limit = 10
+
+type point = struct {
+    int x = 1
+    float y
+    [string] tags
+}
+
+type box<T:int|float, U> = struct {
+    T value
+    U? extra
+}
+
+type id = int|string
+
+#local
+fn int.double():int {
+    return self * 2
+}
+
+fn box<T:int|float, U>.get():T {
+    return self.value
+}
+
+@inline
+@where(1, "a")
+fn sum(...[int] list):int! {
+    var total = 0
+    for v in list {
+        total += v
+    }
+    return total
+}
+
+fn add<T:int|float>(T a, T b):T {
+    return a + b
+}
+
+fn tpl_fn(int a):int
+
+#linkid puts
+fn c_puts(string s):int
+
+fn main():void {
+    var a = (1 + 2) * 3
+    int b = -1 - -2
+    var c = !(a > b) && a != b || a <= 3
+    var d = -(a ** 2) ** 3
+    var e = a as float
+    var g = a * -b
+    var s = "line\n\"quoted\"\t\\"
+    var list = [1, 2, 3]
+    var m = {"a": 1, "b": 2}
+    var uniq = {1, 2}
+    var empty = {}
+    var tu = (1, "a", true)
+    var (x, (y, z)) = (1, (2, 3))
+    (x, y) = (y, x)
+    list[0] = list[1] + list[a - 1]
+    var p = point{x = 1, y = 2.5}
+    var q = point{...p, x = 3}
+    var bx = box<int, string>{value = 1}
+    var f = fn(int v):int {
+        return v + 1
+    }
+    var r = f(1) catch err {
+        throw err
+    }
+    sum(...list) catch err {
+        break
+    }
+    var n = new point(x = 1)
+    var n2 = new point
+    var pa = &a
+    var deref = *pa
+    var sz = @sizeof(point)
+    var mx = @max(1, 2, 3)
+    var bits = @bitcast<i64>(1.5)
+    point p2 = @default()
+    var is_int = r is int
+    go sum(1, 2)
+    var fut = @async(sum(1), 0)
+    if a > 1 {
+        a = 1
+    } else if a < 0 {
+        a = 0
+    } else {
+        a = 2
+    }
+    for int i = 0; i < 10; i += 1 {
+        continue
+    }
+    for a > 0 {
+        a -= 1
+    }
+    for k, v in m {
+    }
+    for i in 0..10 step 2 {
+    }
+    var res = match a {
+        1 | 2 -> "small"
+        is int -> {
+            break "int"
+        }
+        _ -> "other"
+    }
+    match {
+        a > 1 -> println("gt")
+        _ -> {}
+    }
+    select {
+        ch.on_recv() -> msg {
+            println(msg)
+        }
+        _ -> {}
+    }
+    let e as int
+    var gen = add<int>(1, 2)
+    var sel = p.x.y
+    @assert(a > 0)
+}
+"#;
+
+    let stmts = parse(source);
+    let printed = body_to_source(&stmts);
+
+    // 重新解析输出的源码, 得到的 ast 与原 ast 结构一致, 再次输出的源码保持不变
+    let reparsed = parse(&printed);
+    assert_ast_eq!(reparsed, stmts);
+    assert_eq!(body_to_source(&reparsed), printed);
+
+    assert!(printed.contains("type box<T:int|float, U> = struct {\n    T value\n    U? extra\n}"));
+    assert!(printed.contains("@inline\n@where(1, \"a\")\nfn sum(...vec<int> list):int! {"));
+    assert!(printed.contains("fn main() {"));
+    assert!(printed.contains("    } else if a < 0 {\n"));
+    assert!(printed.contains("        total = total + v\n"));
+    assert!(printed.contains("        is int -> \"int\"\n"));
+}
+
+#[test]
+fn test_do_stmt() {
+    let stmts = parse("fn main() {\n    do channel_send(ch, value)\n    do map_insert(m, k, v)\n    do a + 1\n}");
+    let AstNode::FnDef(fndef) = &stmts[0].node else { panic!("expected fn") };
+    let body = &fndef.read().unwrap().body;
+    assert_eq!(body.len(), 3);
+
+    for (stmt, ident) in body.iter().zip(["channel_send", "map_insert"]) {
+        let AstNode::Do(expr) = &stmt.node else { panic!("expected do stmt") };
+        let AstNode::Call(call) = &expr.node else { panic!("expected call") };
+        assert!(matches!(&call.left.node, AstNode::Ident(left, _) if left == ident));
+    }
+    assert!(matches!(&body[2].node, AstNode::Do(expr) if matches!(expr.node, AstNode::Binary(..))));
+    assert_eq!(to_source(&body[1]), "do map_insert(m, k, v)");
+
+    // do 之后必须是表达式
+    let (_, errors) = parse_with_errors("fn main() {\n    do\n}");
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn test_intrinsic_fn() {
+    let stmts = parse("#intrinsic\nfn memcpy(ptr<void> dst, ptr<void> src, uint n)\n");
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    assert!(fndef.is_intrinsic);
+    assert!(!fndef.is_tpl);
+    assert!(fndef.body.is_empty());
+    assert_eq!(fndef.symbol_name, "memcpy");
+    assert_eq!(fndef.params.len(), 3);
+    drop(fndef);
+    assert_eq!(to_source(&stmts[0]), "#intrinsic\nfn memcpy(ptr<void> dst, ptr<void> src, uint n)");
+
+    let (_, errors) = parse_with_errors("#intrinsic\nfn memcpy(ptr<void> dst, ptr<void> src, uint n) {\n}\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "intrinsic function cannot have a body");
+}
+
+#[test]
+fn test_ast_to_json() {
+    let stmts = parse("var a = [1]\nfn add(int x):int {\n    return x + 1\n}");
+    let expected = r#"[
+  {
+    "end": 11,
+    "kind": "VarDef",
+    "right": {
+      "cap": null,
+      "elements": [
+        {
+          "end": 10,
+          "kind": "Literal",
+          "literal_kind": "int",
+          "start": 9,
+          "value": "1"
+        }
+      ],
+      "end": 11,
+      "kind": "VecNew",
+      "len": null,
+      "start": 8
+    },
+    "start": 0,
+    "var_decl": {
+      "doc": null,
+      "id": 1,
+      "ident": "a",
+      "kind": "VarDecl",
+      "symbol_end": 5,
+      "symbol_start": 4,
+      "type": {
+        "end": 3,
+        "kind": "unknown",
+        "start": 0
+      }
+    }
+  },
+  {
+    "body": [
+      {
+        "end": 48,
+        "expr": {
+          "end": 48,
+          "kind": "Binary",
+          "left": {
+            "end": 44,
+            "ident": "x",
+            "kind": "Ident",
+            "start": 43
+          },
+          "op": "+",
+          "right": {
+            "end": 48,
+            "kind": "Literal",
+            "literal_kind": "int",
+            "start": 47,
+            "value": "1"
+          },
+          "start": 43
+        },
+        "kind": "Return",
+        "start": 36
+      }
+    ],
+    "decorators": [],
+    "doc": null,
+    "end": 50,
+    "fn_name": "add",
+    "generics_params": null,
+    "id": 2,
+    "impl_type": null,
+    "is_async": false,
+    "is_deprecated": false,
+    "is_errable": false,
+    "is_intrinsic": false,
+    "is_test": false,
+    "is_tpl": false,
+    "kind": "FnDef",
+    "linkid": null,
+    "params": [
+      {
+        "doc": null,
+        "id": 3,
+        "ident": "x",
+        "kind": "VarDecl",
+        "symbol_end": 24,
+        "symbol_start": 23,
+        "type": {
+          "end": 22,
+          "kind": "int",
+          "origin_ident": "int",
+          "start": 19
+        }
+      }
+    ],
+    "rest_param": false,
+    "return_type": {
+      "end": 29,
+      "kind": "int",
+      "origin_ident": "int",
+      "start": 26
+    },
+    "start": 12,
+    "symbol_end": 18,
+    "symbol_name": "add",
+    "symbol_start": 15
+  }
+]"#;
+    assert_eq!(ast_to_json(&stmts), expected);
+    assert_eq!(ast_to_json(&stmts), ast_to_json(&stmts));
+
+    // 同一个 Arc 节点只内联输出一次, 之后通过 id 引用
+    let shared = vec![stmts[1].clone(), stmts[1].clone()];
+    let value: serde_json::Value = serde_json::from_str(&ast_to_json(&shared)).unwrap();
+    assert_eq!(value[0]["id"], 1);
+    assert_eq!(value[1]["ref"], 1);
+    assert!(value[1].get("body").is_none());
+
+    // 单个节点同样可以通过 Serialize 输出
+    let value = serde_json::to_value(&*stmts[0]).unwrap();
+    assert_eq!(value["kind"], "VarDef");
+    assert_eq!(value["right"]["elements"][0]["value"], "1");
+}
+
+#[test]
+fn test_syntax_append_tokens() {
+    let (token_db, token_indexes, _) = Lexer::new("int x = 1\n".to_string()).scan();
+    let mut syntax = Syntax::new(token_db, token_indexes);
+    let (mut stmts, _, errors) = syntax.parser();
+    assert!(errors.is_empty());
+    assert_eq!(stmts.len(), 1);
+
+    let (new_tokens, _, _) = Lexer::new("int y = 2\n".to_string()).scan();
+    syntax.append_tokens(new_tokens);
+    let (appended, token_db, errors) = syntax.parser();
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(appended.len(), 1);
+    assert_eq!(token_db.last().unwrap().token_type, TokenType::Eof);
+    assert_eq!(token_db.iter().filter(|t| t.token_type == TokenType::Eof).count(), 1);
+
+    // 追加的 token 平移到原有 Eof(10..11) 之后, 不与已有的 token 重叠
+    assert!(token_db.windows(2).all(|pair| pair[0].span.end <= pair[1].span.start), "{:?}", token_db);
+    let y = token_db.iter().find(|t| t.literal == "y").unwrap();
+    assert_eq!((y.span, y.line), (Span::new(15, 16), 2));
+    assert_eq!(token_db.last().unwrap().span, Span::new(21, 22));
+    let AstNode::VarDef(var_decl, _) = &appended[0].node else {
+        panic!("expect var def")
+    };
+    assert_eq!(var_decl.read().unwrap().symbol_start, 15);
+
+    // 只追加 Eof 时 Eof 不会向前移动
+    syntax.append_tokens(Lexer::new(String::new()).scan().0);
+    let (empty, token_db, _) = syntax.parser();
+    assert!(empty.is_empty());
+    assert!(token_db.last().unwrap().span.start >= 22);
+
+    stmts.extend(appended);
+    let idents: Vec<String> = stmts
+        .iter()
+        .map(|stmt| {
+            let AstNode::VarDef(var_decl, _) = &stmt.node else { panic!("expect var def") };
+            let ident = var_decl.read().unwrap().ident.to_string();
+            ident
+        })
+        .collect();
+    assert_eq!(idents, vec!["x", "y"]);
+}
+
+#[test]
+fn test_span() {
+    let a = Span::new(4, 7);
+    let b = Span::new(10, 12);
+    assert_eq!(a.merge(b), Span::new(4, 12));
+    assert_eq!(b.merge(a), Span::new(4, 12));
+    assert_eq!(a.merge(Span::new(5, 6)), a);
+    assert_eq!(a.len(), 3);
+    assert!(Span::new(3, 3).is_empty());
+    assert!(a.contains(4) && a.contains(6));
+    assert!(!a.contains(3) && !a.contains(7));
+    assert!(a.touches(7) && !a.touches(8));
+    assert_eq!(a.range(), 4..7);
+    assert_eq!(a.to_string(), "4..7");
+
+    // 节点与错误的范围
+    let stmts = parse("var a = 1 + 22");
+    let AstNode::VarDef(_, right) = &stmts[0].node else {
+        panic!("expect var def")
+    };
+    let AstNode::Binary(_, left, operand) = &right.node else {
+        panic!("expect binary")
+    };
+    assert_eq!(left.span.merge(operand.span), right.span);
+    assert_eq!(stmts[0].span, Span::new(0, 14));
+
+    let error = AnalyzerError::new(right.span, "invalid");
+    assert_eq!(error.span, Span::new(8, 14));
+    assert_eq!(error.span, right.span);
+}
+
+#[test]
+fn test_sizeof_expr() {
+    let stmts = parse("var a = @sizeof(int)\nvar b = @sizeof(vec<int>)\nvar c = @sizeof(x + 1)\nvar d = @sizeof(list[0])");
+    assert!(matches!(&var_def_right(&stmts[0]).node, AstNode::MacroSizeof(t) if matches!(t.kind, TypeKind::Int)));
+    assert!(matches!(&var_def_right(&stmts[1]).node, AstNode::MacroSizeof(t) if matches!(t.kind, TypeKind::Vec(..))));
+    assert!(matches!(&var_def_right(&stmts[2]).node, AstNode::Sizeof(src) if matches!(src.node, AstNode::Binary(..))));
+    assert!(matches!(&var_def_right(&stmts[3]).node, AstNode::Sizeof(src) if matches!(src.node, AstNode::AccessExpr(..))));
+    assert_eq!(to_source(&stmts[2]), "var c = @sizeof(x + 1)");
+
+    // 按类型解析失败回退时不保留尝试过程中的 expected tokens, 否则同一位置会出现重复的 quickfix
+    let source = "var e = @sizeof([a b])";
+    let (token_db, token_indexes, _) = Lexer::new(source.to_string()).scan();
+    let mut syntax = Syntax::new(token_db, token_indexes);
+    let (_, _, errors) = syntax.parser();
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    let expected = syntax.expected_tokens();
+    assert_eq!(expected.len(), 1, "{:?}", expected);
+    assert_eq!(expected[0].tokens, vec![TokenType::RightSquare]);
+
+    // @sizeof(x) 中的 x 为变量时, semantic 将其改写为 Sizeof(expr)
+    let m = module_analyze("type point = struct {\n    int x\n}\n\nfn main() {\n    i8 x = 1\n    var n = @sizeof(x)\n    var m = @sizeof(point)\n}\n");
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    let AstNode::FnDef(main) = &m.stmts[1].node else { panic!("expect fn def") };
+    let main = main.read().unwrap();
+
+    let sizeof_var = var_def_right(&main.body[1]);
+    let AstNode::Sizeof(src) = &sizeof_var.node else {
+        panic!("expect runtime sizeof, got {:?}", sizeof_var.node)
+    };
+    assert!(matches!(&src.node, AstNode::Ident(ident, Some(_)) if ident == "x"));
+    assert!(matches!(src.type_.kind, TypeKind::Int8));
+
+    // 两种形式的结果类型一致
+    let sizeof_type = var_def_right(&main.body[2]);
+    assert!(matches!(&sizeof_type.node, AstNode::MacroSizeof(..)));
+    assert_eq!(sizeof_var.type_, sizeof_type.type_);
+}
+
+// 收集 body 中所有 stmt 与 expr 的 id, 包括 @async 展开得到的 closure fn
+fn collect_node_ids(body: &[Box<Stmt>], ids: &mut Vec<u32>) {
+    walk_stmts(body, &mut |stmt| ids.push(stmt.id.0));
+    walk_body(body, &mut |expr| {
+        ids.push(expr.id.0);
+        if let AstNode::MacroAsync(async_expr) = &expr.node {
+            collect_node_ids(&async_expr.closure_fn.read().unwrap().body, ids);
+            collect_node_ids(&async_expr.closure_fn_void.read().unwrap().body, ids);
+        }
+    });
+}
+
+#[test]
+fn test_ast_node_ids() {
+    let mut source = String::from("type point = struct {\n    int x\n    int y\n}\n\nfn work(int n):int {\n    return n * 2\n}\n\n");
+    for i in 0..50 {
+        source.push_str(&format!(
+            "fn handle{i}([int] list, point p):int! {{\n    var total = 0\n    for v in list {{\n        total += v * {i}\n    }}\n    if p.x > {i} {{\n        p.y -= 1\n    }} else {{\n        go work(p.x)\n    }}\n    var fut = @async(work(total + {i}), 0)\n    var f = fn(int a):int {{\n        return a + p.x\n    }}\n    return match total {{\n        0 -> f(1)\n        _ -> total\n    }}\n}}\n\n"
+        ));
+    }
+
+    let stmts = parse(&source);
+    let mut ids = Vec::new();
+    collect_node_ids(&stmts, &mut ids);
+    assert!(ids.len() > 2000, "{}", ids.len());
+    assert!(ids.iter().all(|&id| id > 0));
+    let unique: std::collections::HashSet<u32> = ids.iter().copied().collect();
+    assert_eq!(unique.len(), ids.len());
+
+    // go 与 @async 展开得到的 closure fn 中的节点同样分配了 id, 且与 origin_call 的 id 不同
+    let AstNode::FnDef(fndef_mutex) = &stmts[2].node else {
+        panic!("expect fn def")
+    };
+    let mut async_count = 0;
+    walk_body(&fndef_mutex.read().unwrap().body, &mut |expr| {
+        let AstNode::MacroAsync(async_expr) = &expr.node else {
+            return;
+        };
+        async_count += 1;
+        let mut origin_ids = Vec::new();
+        walk_node(&AstNode::Call(*async_expr.origin_call.clone()), &mut |expr| origin_ids.push(expr.id.0));
+        for closure_mutex in [&async_expr.closure_fn, &async_expr.closure_fn_void] {
+            let mut closure_ids = Vec::new();
+            collect_node_ids(&closure_mutex.read().unwrap().body, &mut closure_ids);
+            assert!(!closure_ids.is_empty());
+            assert!(closure_ids.iter().all(|id| *id > 0 && !origin_ids.contains(id)));
+        }
+    });
+    assert_eq!(async_count, 2);
+
+    // 增量解析时重新解析的部分从未使用的 id 开始分配
+    let (token_db, token_indexes, _) = Lexer::new(source.clone()).scan();
+    let (old_stmts, old_tokens, _) = Syntax::new(token_db, token_indexes).parser();
+    let start = source.find("v * 3").unwrap();
+    let edit = TextEdit {
+        start,
+        end: start + 1,
+        new_text: "count".to_string(),
+    };
+    let new_source = source[..edit.start].to_string() + &edit.new_text + &source[edit.end..];
+    let (new_stmts, _) = Syntax::reparse_range(&old_stmts, &old_tokens, &edit, &new_source);
+    let mut new_ids = Vec::new();
+    collect_node_ids(&new_stmts, &mut new_ids);
+    let unique: std::collections::HashSet<u32> = new_ids.iter().copied().collect();
+    assert_eq!(unique.len(), new_ids.len());
+    assert!(new_ids.iter().max() > ids.iter().max());
+
+    // IdMap 以 id 为下标存储节点信息
+    let mut map = IdMap::new();
+    assert!(map.is_empty());
+    for stmt in &stmts {
+        map.insert(stmt.id, stmt.span.start);
+    }
+    assert_eq!(map.len(), stmts.len());
+    assert_eq!(map.get(stmts[1].id), Some(&stmts[1].span.start));
+    assert!(map.get(AstNodeId::default()).is_none());
+    *map.get_mut(stmts[0].id).unwrap() = 42;
+    assert_eq!(map.remove(stmts[0].id), Some(42));
+    assert!(!map.contains_key(stmts[0].id));
+    assert_eq!(map.len(), stmts.len() - 1);
+    let keys: Vec<AstNodeId> = map.iter().map(|(id, _)| id).collect();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn test_node_at() {
+    let source = "type box<T> = struct {\n    [int] list\n    T value\n}\n\nfn main() {\n    var b = box<int>{list = [1, 2], value = 3}\n    foo(bar(1, 2), 3)\n\n    int x = 1\n    x += 2\n    go foo(x, 1)\n}\n";
+    let stmts = parse(source);
+    let names = |offset: usize| -> Vec<(NodeKind, &'static str)> { node_at(&stmts, offset).unwrap().iter().map(|node| (node.kind, node.name)).collect() };
+
+    // 嵌套 call 中的参数, 由内到外依次为其祖先节点
+    let path = node_at(&stmts, source.find("1, 2)").unwrap()).unwrap();
+    assert_eq!(
+        path.iter().map(|node| (node.kind, node.name)).collect::<Vec<_>>(),
+        vec![
+            (NodeKind::Expr, "Literal"),
+            (NodeKind::Expr, "Call"),
+            (NodeKind::Stmt, "Call"),
+            (NodeKind::Stmt, "FnDef")
+        ]
+    );
+    let bar = source.find("bar(").unwrap();
+    assert_eq!(path[1].span, Span::new(bar, bar + "bar(1, 2)".len()));
+    assert!(path.iter().all(|node| node.id.0 > 0));
+    assert!(path.windows(2).all(|pair| pair[0].id != pair[1].id));
+
+    // 类型声明中的类型, type 节点没有 id
+    let path = node_at(&stmts, source.find("int] list").unwrap()).unwrap();
+    assert_eq!(
+        path.iter().map(|node| (node.kind, node.name)).collect::<Vec<_>>(),
+        vec![
+            (NodeKind::Type, "int"),
+            (NodeKind::Type, "vec"),
+            (NodeKind::Type, "struct"),
+            (NodeKind::Stmt, "TypeAlias")
+        ]
+    );
+    assert_eq!(path[0].id, AstNodeId::default());
+    assert_eq!(
+        names(source.find("int>{").unwrap())[..3],
+        [(NodeKind::Type, "int"), (NodeKind::Type, "alias"), (NodeKind::Expr, "StructNew")]
+    );
+
+    // stmt 之间的空白返回距离最近的 stmt
+    assert_eq!(
+        names(source.find("\n\n    int x").unwrap() + 1),
+        vec![(NodeKind::Stmt, "Call"), (NodeKind::Stmt, "FnDef")]
+    );
+    assert_eq!(names(source.find("\n\nfn main").unwrap() + 1), vec![(NodeKind::Stmt, "TypeAlias")]);
+
+    // 复合赋值展开的 Binary 与 go 展开的协程闭包同样满足父节点包含子节点
+    assert_eq!(
+        names(source.find("2\n    go").unwrap()),
+        vec![
+            (NodeKind::Expr, "Literal"),
+            (NodeKind::Expr, "Binary"),
+            (NodeKind::Stmt, "Assign"),
+            (NodeKind::Stmt, "FnDef")
+        ]
+    );
+    assert_eq!(
+        names(source.find("x, 1)").unwrap())[..2],
+        [(NodeKind::Expr, "Ident"), (NodeKind::Expr, "MacroAsync")]
+    );
+    let AstNode::FnDef(fndef_mutex) = &stmts[1].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+    let AstNode::Fake(go_expr) = &fndef.body.last().unwrap().node else {
+        panic!("expect fake")
+    };
+    let AstNode::MacroAsync(async_expr) = &go_expr.node else {
+        panic!("expect async")
+    };
+    for closure_mutex in [&async_expr.closure_fn, &async_expr.closure_fn_void] {
+        let closure = closure_mutex.read().unwrap();
+        walk_stmts(&closure.body, &mut |stmt| {
+            assert!(go_expr.span.start <= stmt.span.start && stmt.span.end <= go_expr.span.end, "{:?}", stmt.span)
+        });
+        walk_body(&closure.body, &mut |expr| {
+            assert!(go_expr.span.start <= expr.span.start && expr.span.end <= go_expr.span.end, "{:?}", expr.span)
+        });
+    }
+
+    // 任意 offset 得到的节点链都是逐层包含的
+    for offset in 0..source.len() {
+        if let Some(path) = node_at(&stmts, offset) {
+            assert!(
+                path.windows(2)
+                    .all(|pair| pair[1].span.start <= pair[0].span.start && pair[0].span.end <= pair[1].span.end),
+                "{offset} {:?}",
+                path
+            );
+        }
+    }
+
+    // 位于文件末尾之后
+    assert!(node_at(&stmts, source.len() + 3).is_none());
+    assert!(node_at(&[], 0).is_none());
+}
+
+#[test]
+fn test_coroutine_closure_generics_args() {
+    let stmts = parse("fn main() {\n    go sort<int>(list)\n    var fut = @async(sort<int>(list), 0)\n}\n");
+    let AstNode::FnDef(fndef_mutex) = &stmts[0].node else {
+        panic!("expect fn def")
+    };
+    let fndef = fndef_mutex.read().unwrap();
+
+    let mut async_count = 0;
+    walk_body(&fndef.body, &mut |expr| {
+        let AstNode::MacroAsync(async_expr) = &expr.node else {
+            return;
+        };
+        async_count += 1;
+        assert_eq!(async_expr.origin_call.generics_args.len(), 1);
+
+        // var result = sort<int>(list)
+        let closure = async_expr.closure_fn.read().unwrap();
+        let AstNode::VarDef(_, right) = &closure.body[0].node else {
+            panic!("expect var def")
+        };
+        let AstNode::Call(call) = &right.node else { panic!("expect call") };
+        assert_eq!(call.generics_args.len(), 1);
+        assert!(matches!(call.generics_args[0].kind, TypeKind::Int));
+
+        // sort<int>(list)
+        let closure_void = async_expr.closure_fn_void.read().unwrap();
+        let AstNode::Call(call) = &closure_void.body[0].node else {
+            panic!("expect call")
+        };
+        assert_eq!(call.generics_args.len(), 1);
+        assert!(matches!(call.generics_args[0].kind, TypeKind::Int));
+    });
+    assert_eq!(async_count, 2);
+}
+
+#[test]
+fn test_concurrent_ast_read() {
+    let source = "type point = struct {\n    int x\n    int y\n}\n\nfn point.sum():int {\n    return self.x + self.y\n}\n\nfn main() {\n    var p = point{x = 1, y = 2}\n    var total = p.sum()\n    for i in 0..10 {\n        total += i\n    }\n    var f = fn(int a):int {\n        return a + total\n    }\n}\n";
+    let m = module_analyze(source);
+    let expected_source = body_to_source(&m.stmts);
+    let expected_json = ast_to_json(&m.stmts);
+
+    // 两个线程同时持有同一个 fndef 的读锁, 之后各自遍历整个 ast
+    let AstNode::FnDef(main_mutex) = &m.stmts[2].node else {
+        panic!("expect fn def")
+    };
+    let barrier = std::sync::Barrier::new(2);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                scope.spawn(|| {
+                    let main_fn = main_mutex.read().unwrap();
+                    barrier.wait();
+
+                    let mut count = 0;
+                    walk_body(&main_fn.body, &mut |_| count += 1);
+                    let path = node_at(&m.stmts, source.find("total += i").unwrap()).unwrap();
+                    (count, path, body_to_source(&m.stmts), ast_to_json(&m.stmts))
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        for (count, path, printed, json) in &results {
+            assert!(*count > 10);
+            assert_eq!(path[0].name, "Ident");
+            assert_eq!(printed, &expected_source);
+            assert_eq!(json, &expected_json);
+        }
+        assert_eq!(results[0].1, results[1].1);
+    });
+}
+
+#[test]
+fn test_source_line() {
+    let source = "fn main() {\r\n    var a = 1\n\tvar b = \"名\" 2\n}";
+    let char_offset = |pat: &str| source[..source.find(pat).unwrap()].chars().count();
+
+    assert_eq!(source_line(source, 0), (1, 1, "fn main() {"));
+    assert_eq!(source_line(source, char_offset("var a")), (2, 5, "    var a = 1"));
+    // offset 为 char offset, 多字节字符只占一列
+    assert_eq!(source_line(source, char_offset(" 2")), (3, 13, "\tvar b = \"名\" 2"));
+    assert_eq!(source_line(source, source.chars().count() + 10), (4, 2, "}"));
+
+    let (_, errors) = parse_with_errors(source);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(
+        errors[0].display_with_source(source),
+        "error: expected ';' or '}' at end of statement\n --> 3:10\n  |\n3 | \tvar b = \"名\" 2\n  | \t        ^^^"
+    );
+
+    // 跨越多行的错误只标记到行尾
+    let error = AnalyzerError::new(Span::new(char_offset("var a"), char_offset("}")), "multi line");
+    assert!(error.display_with_source(source).ends_with("2 |     var a = 1\n  |     ^^^^^^^^^"));
+}
+
+#[test]
+fn test_ast_builders() {
+    let span = Span::new(0, 0);
+    let int = |value: &str| Box::new(Expr::literal(span, TypeKind::Int, value));
+    let ident = |name: &str| Box::new(Expr::ident(Span::default(), name.to_string(), None));
+
+    // var x = 1 + 2 * 3
+    let product = Expr::binary(span, ExprOp::Mul, int("2"), int("3"));
+    let sum = Expr::binary(span, ExprOp::Add, int("1"), Box::new(product));
+    let var_def = Stmt::var_def(span, VarDeclExpr::new("x", Type::default(), span), Box::new(sum));
+    assert_eq!(to_source(&var_def), "var x = 1 + 2 * 3");
+
+    // print(-x)
+    let negate = Expr::unary(span, ExprOp::Neg, ident("x"));
+    let call = Stmt::call(span, ident("print"), vec![Box::new(negate)]);
+    assert_eq!(to_source(&call), "print(-x)");
+
+    let mut fndef = AstFnDef::synthetic(
+        span,
+        Type::new(TypeKind::Int),
+        vec![Box::new(var_def), Box::new(call), Box::new(Stmt::ret(span, Some(ident("x"))))],
+    );
+    fndef.fn_name = "main".to_string();
+    let fn_stmt = Stmt::fn_def(fndef);
+    let printed = to_source(&fn_stmt);
+    assert_eq!(printed, "fn main():int {\n    var x = 1 + 2 * 3\n    print(-x)\n    return x\n}");
+
+    // 输出的源码可以重新解析
+    let stmts = parse(&printed);
+    assert_eq!(body_to_source(&stmts), printed);
+    assert_ast_eq!(stmts, [Box::new(fn_stmt.clone())]);
+    assert_eq!(fn_stmt.id, AstNodeId::default());
+}
+
+#[test]
+fn test_named_type_args() {
+    let t = parse_type_str("pair<T=int, U=string>").unwrap();
+    let TypeKind::Alias(alias) = &t.kind else {
+        panic!("expect alias, got {:?}", t.kind)
+    };
+    let names: Vec<Option<&str>> = alias.args.iter().flatten().map(|arg| arg.name.as_deref()).collect();
+    assert_eq!(names, vec![Some("T"), Some("U")]);
+    assert_eq!(t.to_string(), "pair<T=int,U=string>");
+    assert_eq!(parse_type_str("pair<int, string>").unwrap().to_string(), "pair<int,string>");
+
+    for source in ["pair<T=int, string>", "pair<int, U=string>"] {
+        let error = parse_type_str(source).unwrap_err();
+        assert_eq!(error.message, "named and positional type args cannot be mixed", "{}", source);
+    }
+
+    // 具名实参按照 param 的名称匹配, 与书写顺序无关
+    let declare = "type pair<T, U> = struct {\n    T first\n    U second\n}\n\n";
+    let source = format!("{}fn main() {{\n    pair<U=string, T=int> a = pair<int, string>{{first = 1, second = 'x'}}\n    pair<T=int, U=string> b = a\n    pair<int, string> c = b\n}}\n", declare);
+    let m = module_analyze(&source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let m = module_analyze(&format!(
+        "{}fn main() {{\n    pair<U=int, T=string> a = pair<int, string>{{first = 1, second = 'x'}}\n}}\n",
+        declare
+    ));
+    assert!(m.analyzer_errors[0].message.starts_with("type inconsistency"), "{:?}", m.analyzer_errors);
+
+    let cases = [
+        ("pair<T=int, V=string>", "type alias 'pair' has no param 'V'"),
+        ("pair<T=int, T=string>", "type alias 'pair' param 'T' specified more than once"),
+        ("pair<T=int>", "type alias 'pair' missing param 'U'"),
+    ];
+    for (type_source, expected) in cases {
+        let source = format!("{}fn main() {{\n    {} a = null\n}}\n", declare, type_source);
+        let m = module_analyze(&source);
+        assert_eq!(m.analyzer_errors[0].message, expected, "{}: {:?}", type_source, m.analyzer_errors);
+    }
+}
+
+#[test]
+fn test_parse_result() {
+    let result = nls::parse("fn main() {\n    var a = 1\n}\n");
+    assert!(result.is_error_free());
+    assert_eq!(result.stmts.len(), 1);
+    assert!(result.tokens.is_none());
+    assert_eq!(result.line_index.position(16), Some(Position::new(1, 4)));
+
+    // lexer 错误在 syntax 错误之前
+    let source = "fn main() {\n    var s = 'abc\n}\n\nfn foo() {\n    var = 1\n}\n";
+    let result = nls::parse(source);
+    assert!(!result.is_error_free());
+    let phases: Vec<DiagnosticPhase> = result.errors.iter().map(|e| e.phase).collect();
+    assert_eq!(phases.first(), Some(&DiagnosticPhase::Lexer), "{:?}", result.errors);
+    assert_eq!(phases.last(), Some(&DiagnosticPhase::Syntax), "{:?}", result.errors);
+
+    let foo_start = source.find("fn foo").unwrap();
+    let in_foo: Vec<DiagnosticPhase> = result.errors_in(Span::new(foo_start, source.len())).map(|e| e.phase).collect();
+    assert_eq!(in_foo, vec![DiagnosticPhase::Syntax]);
+    assert_eq!(result.errors_in(Span::new(0, 0)).count(), 0);
+
+    let options = ParseOptions {
+        keep_tokens: true,
+        ..Default::default()
+    };
+    let result = parse_with_options("var a = 1", options);
+    let tokens = result.tokens.unwrap();
+    assert_eq!(tokens[0].literal, "var");
+}
+
+#[test]
+fn test_import_version() {
+    let stmts = parse("import math@2.0 as m\nimport math as m2\nimport net.http@1.2.3\nimport json@v2\n");
+    let versions: Vec<Option<&str>> = stmts.iter().map(|stmt| import_stmt(stmt).version.as_deref()).collect();
+    assert_eq!(versions, vec![Some("2.0"), None, Some("1.2.3"), Some("v2")]);
+    assert_eq!(import_stmt(&stmts[0]).as_name, "m");
+    assert_eq!(import_stmt(&stmts[2]).ast_package, Some(vec!["net".to_string(), "http".to_string()]));
+    assert_eq!(
+        body_to_source(&stmts),
+        "import math@2.0 as m\nimport math as m2\nimport net.http@1.2.3\nimport json@v2"
+    );
+    let source = "import math@2.0 as m\n";
+    assert_eq!(format_source(source, &FormatConfig::default()).unwrap(), source);
+
+    let (_, errors) = parse_with_errors("import math@\n");
+    assert_eq!(errors[0].message, "expected package version after '@'", "{:?}", errors);
+    let (_, errors) = parse_with_errors("import math@ as m\n");
+    assert_eq!(errors[0].message, "expected package version after '@'", "{:?}", errors);
+}
+
+// parser 将 ptr<T> 与泛型参数 T 解析为 alias, 这里按照 semantic 的方式改写, 单个大写字母视为泛型参数
+fn lower_type(t: Type) -> Type {
+    let kind = match t.kind {
+        TypeKind::Alias(alias) if alias.ident.len() == 1 && alias.ident.chars().all(|c| c.is_ascii_uppercase()) => TypeKind::Param(alias.ident),
+        TypeKind::Alias(alias) if alias.ident == "ptr" || alias.ident == "raw_ptr" => {
+            let value = Box::new(lower_type(alias.args.unwrap().remove(0).type_));
+            if alias.ident == "ptr" {
+                TypeKind::Ptr(value)
+            } else {
+                TypeKind::RawPtr(value)
+            }
+        }
+        TypeKind::Alias(mut alias) => {
+            for arg in alias.args.iter_mut().flatten() {
+                arg.type_ = lower_type(arg.type_.clone());
+            }
+            TypeKind::Alias(alias)
+        }
+        TypeKind::Vec(element) => TypeKind::Vec(Box::new(lower_type(*element))),
+        TypeKind::Map(key, value) => TypeKind::Map(Box::new(lower_type(*key)), Box::new(lower_type(*value))),
+        TypeKind::Union(any, elements) => TypeKind::Union(any, elements.into_iter().map(lower_type).collect()),
+        TypeKind::Struct(ident, align, properties) => TypeKind::Struct(
+            ident,
+            align,
+            properties
+                .into_iter()
+                .map(|p| TypeStructProperty {
+                    type_: lower_type(p.type_.clone()),
+                    ..p
+                })
+                .collect(),
+        ),
+        kind => kind,
+    };
+    Type { kind, ..t }
+}
+
+#[test]
+fn test_type_assignable() {
+    let ty = |source: &str| lower_type(parse_type_str(source).unwrap_or_else(|e| panic!("parse '{}' failed: {}", source, e.message)));
+
+    let mut env = TypeEnv::new();
+    env.define_alias("pair", vec!["T".to_string(), "U".to_string()], ty("struct{T first;U second}"));
+    env.define_alias("id", Vec::new(), ty("int"));
+    env.define_alias("node", Vec::new(), ty("struct{int value;ptr<node> next}"));
+
+    // (src, dst, literal, expected)
+    let cases = [
+        ("int", "int", false, true),
+        ("int", "i8", false, false),
+        ("int", "i8", true, true),
+        ("int", "float", true, true),
+        ("float", "f32", true, true),
+        ("float", "int", true, false),
+        ("string", "int", false, false),
+        ("int", "any", false, true),
+        ("int?", "any", false, true),
+        ("any", "int|string", false, false),
+        ("int", "int|string", false, true),
+        ("bool", "int|string", false, false),
+        ("int", "int?", false, true),
+        ("null", "int?", false, true),
+        ("int?", "int", false, false),
+        ("int", "i8?", true, true),
+        ("int|string", "string|int|bool", false, true),
+        ("int|string", "int|bool", false, false),
+        ("null", "raw_ptr<int>", false, true),
+        ("ptr<int>", "raw_ptr<int>", false, true),
+        ("raw_ptr<int>", "ptr<int>", false, false),
+        ("null", "ptr<int>", false, false),
+        ("vec<int>", "vec<int>", false, true),
+        ("vec<int>", "vec<int?>", false, false),
+        ("vec<i8>", "vec<int>", true, false),
+        ("map<string,int|bool>", "map<string,bool|int>", false, true),
+        ("arr<int,3>", "arr<int,4>", false, false),
+        ("(int,string)", "(int,string)", false, true),
+        ("(int,string)", "(string,int)", false, false),
+        ("fn(int):string", "fn(int):string", false, true),
+        ("fn(int):string", "fn(int):string!", false, false),
+        ("id", "int", false, true),
+        ("int", "id", false, true),
+        ("vec<id>", "vec<int>", false, true),
+        ("pair<int,string>", "struct{int first;string second}", false, true),
+        ("pair<int,string>", "pair<T=int,U=string>", false, true),
+        ("pair<int,string>", "struct{string second;int first}", false, false),
+        ("struct{int first;string second}", "struct{int first;string other}", false, false),
+        ("node", "struct{int value;ptr<node> next}", false, true),
+        ("unknown_alias<int>", "unknown_alias<int>", false, true),
+        ("unknown_alias<int>", "unknown_alias<string>", false, false),
+    ];
+    for (src, dst, literal, expected) in cases {
+        env.literal = literal;
+        assert_eq!(ty(src).assignable_to(&ty(dst), &env), expected, "{} -> {} (literal: {})", src, dst, literal);
+    }
+
+    // 泛型参数在第一次匹配时绑定
+    let env = TypeEnv::new();
+    assert!(ty("int").assignable_to(&ty("T"), &env));
+    assert_eq!(env.generics_binding("T").map(|t| t.to_string()), Some("int".to_string()));
+    assert!(ty("vec<int>").assignable_to(&ty("vec<T>"), &env));
+    assert!(!ty("string").assignable_to(&ty("T"), &env));
+    assert!(ty("map<string,int>").assignable_to(&ty("map<U,T>"), &env));
+    assert_eq!(env.generics_binding("U").map(|t| t.to_string()), Some("string".to_string()));
+
+    // 比较失败时不保留绑定
+    let env = TypeEnv::new();
+    assert!(!ty("(int,string)").assignable_to(&ty("(T,int)"), &env));
+    assert!(env.generics_binding("T").is_none());
+
+    let env = TypeEnv::new();
+    let eq_cases = [
+        ("int", "i8", true),
+        ("u8", "u64", true),
+        ("int", "u8", false),
+        ("f32", "float", true),
+        ("int", "float", false),
+        ("int?", "null", true),
+        ("null", "int?", true),
+        ("string", "int", false),
+        ("vec<int>", "vec<int>", true),
+    ];
+    for (left, right, expected) in eq_cases {
+        assert_eq!(ty(left).compatible_for_eq(&ty(right), &env), expected, "{} == {}", left, right);
+    }
+
+    // 没有通过 define_alias 记录的 alias 从 symbol table 中的 type 声明展开
+    let source = "type id = int\ntype pair<T, U> = struct {\n    T first\n    U second\n}\n";
+    let (m, symbol_table) = module_analyze_with_symbols(source);
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+    let env = TypeEnv::with_symbol_table(&symbol_table);
+    let global = |source: &str, by_symbol_id: bool| {
+        let mut t = ty(source);
+        let TypeKind::Alias(alias) = &mut t.kind else { panic!("expect alias") };
+        alias.ident = format_global_ident(m.ident.clone(), alias.ident.clone());
+        if by_symbol_id {
+            alias.symbol_id = symbol_table.find_symbol_id(&alias.ident, GLOBAL_SCOPE_ID);
+            assert!(alias.symbol_id.is_some());
+        }
+        t
+    };
+    for by_symbol_id in [true, false] {
+        assert!(global("id", by_symbol_id).assignable_to(&ty("int"), &env));
+        assert!(ty("int").assignable_to(&global("id", by_symbol_id), &env));
+        assert!(!global("id", by_symbol_id).assignable_to(&ty("string"), &env));
+        assert!(global("pair<int,string>", by_symbol_id).assignable_to(&ty("struct{int first;string second}"), &env));
+        assert!(!global("pair<int,string>", by_symbol_id).assignable_to(&ty("struct{string first;int second}"), &env));
+        assert!(global("id", by_symbol_id).compatible_for_eq(&ty("i8"), &env));
+    }
+    assert!(!global("id", false).assignable_to(&ty("int"), &TypeEnv::new()));
+}
+
+#[test]
+fn test_arr_length_overflow() {
+    let t = parse_type_str("arr<int,1000000000000000000>").unwrap();
+    assert!(matches!(t.kind, TypeKind::Arr(1_000_000_000_000_000_000, _)));
+
+    // 数组的大小超出 u64 时不会溢出
+    let t = parse_type_str("arr<int,4000000000000000000>").unwrap();
+    assert_eq!(Type::sizeof(&t.kind), u64::MAX);
+
+    let error = parse_type_str("arr<int,18446744073709551616>").unwrap_err();
+    assert_eq!(error.message, "array length overflows u64");
+    assert_eq!(parse_type_str("arr<int,0>").unwrap_err().message, "array length must be greater than 0");
+
+    // 数字分隔符
+    let t = parse_type_str("arr<int,1_000_000_000_000_000_000>").unwrap();
+    assert!(matches!(t.kind, TypeKind::Arr(1_000_000_000_000_000_000, _)));
+    let error = parse_type_str("arr<int,18_446_744_073_709_551_616>").unwrap_err();
+    assert_eq!(error.message, "array length overflows u64");
+
+    // 长度的上限取决于编译目标而不是 nls 所在的平台
+    let parse_arr = |source: &str, pointer_width: u32| {
+        let options = ParseOptions {
+            config: ParserConfig {
+                pointer_width,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        parse_with_options(source, options).errors
+    };
+    let errors = parse_arr("fn f(arr<int,4_294_967_296> a) {\n}\n", 64);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let errors = parse_arr("fn f(arr<int,4_294_967_296> a) {\n}\n", 32);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].message, "array length exceeds platform maximum");
+    assert!(parse_arr("fn f(arr<int,4_294_967_295> a) {\n}\n", 32).is_empty());
+
+    // 分隔符同样适用于普通的数字字面量
+    let m = module_analyze("fn main() {\n    i32 a = 1_000_000\n    u8 b = 1_000\n    f64 c = 1_000.5\n}\n");
+    let messages: Vec<&str> = m.analyzer_errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages, vec!["integer out of range"]);
+}
+
+#[test]
+fn test_type_render() {
+    let ty = |source: &str| lower_type(parse_type_str(source).unwrap_or_else(|e| panic!("parse '{}' failed: {}", source, e.message)));
+
+    let cases = vec![
+        ("int", "int"),
+        ("map<string,vec<int>>", "map<string, vec<int>>"),
+        ("int|string|null", "int|string?"),
+        ("int?", "int?"),
+        ("fn(int,string):bool", "fn(int, string):bool"),
+        ("fn(int):string!", "fn(int):string!"),
+        ("pair<T,U>", "pair<T, U>"),
+        ("pair<T=int,U=string>", "pair<T=int, U=string>"),
+        ("tup<int,bool>", "(int, bool)"),
+        ("arr<u8,4>", "arr<u8, 4>"),
+        ("ptr<person>", "ptr<person>"),
+        ("struct{int a;bool b}", "struct{int a, bool b}"),
+        ("any", "any"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(render(&ty(source)), expected, "render '{}'", source);
+    }
+
+    // 具名 struct 与 alias 归约后的类型使用名称展示
+    let mut person = ty("struct{string name}");
+    person.origin_ident = Some("person".to_string());
+    assert_eq!(render(&person), "struct person");
+
+    let mut boxed = Type::new(TypeKind::Struct("box".to_string(), 0, Vec::new()));
+    assert_eq!(render(&boxed), "struct box");
+    boxed.origin_ident = Some("box".to_string());
+    boxed.impl_args = vec![Type::new(TypeKind::Int)];
+    assert_eq!(render(&boxed), "struct box<int>");
+
+    let mut list = ty("vec<int>");
+    list.origin_ident = Some("list".to_string());
+    assert_eq!(render(&list), "list");
+
+    // 超过长度时优先折叠深层的实参
+    let nested = ty("map<string,vec<map<int,bool>>>");
+    assert_eq!(render_truncated(&nested, 100), "map<string, vec<map<int, bool>>>");
+    assert_eq!(render_truncated(&nested, 22), "map<string, vec<…>>");
+    assert_eq!(render_truncated(&nested, 10), "map<…>");
+    assert_eq!(render_truncated(&nested, 4), "map…");
+}
+
+#[test]
+fn test_fn_multiple_return_types() {
+    let return_type = |source: &str| -> Type {
+        let stmts = parse(source);
+        let AstNode::FnDef(fndef) = &stmts[0].node else {
+            panic!("expect fndef, got {:?}", stmts[0].node)
+        };
+        let fndef = fndef.read().unwrap();
+        fndef.return_type.clone()
+    };
+
+    let source = "fn minmax(int x, int y): int, int {\n    return (min(x, y), max(x, y))\n}\n";
+    let shorthand = return_type(source);
+    assert_ast_eq!(
+        parse(source),
+        parse("fn minmax(int x, int y): (int, int) {\n    return (min(x, y), max(x, y))\n}\n")
+    );
+    assert_eq!(shorthand.to_string(), "(int,int)");
+    assert_eq!(&source[shorthand.span.range()], "int, int");
+
+    // ! 作用于函数本身
+    let source = "fn split(string s): string, vec<string>! {\n    return (s, [s])\n}\n";
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef) = &stmts[0].node else { unreachable!() };
+    assert!(fndef.read().unwrap().is_errable);
+    assert_eq!(fndef.read().unwrap().return_type.to_string(), "(string,vec<string>)");
+
+    // fn 表达式
+    let source = "var f = fn(int a): int, bool {\n    return (a, true)\n}\n";
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef) = &var_def_right(&stmts[0]).node else {
+        panic!("expect fndef")
+    };
+    assert_eq!(fndef.read().unwrap().return_type.to_string(), "(int,bool)");
+
+    let m = module_analyze("fn pair(int x): int, string {\n    return (x, 'a')\n}\n\nfn main() {\n    var (a, b) = pair(1)\n}\n");
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    // fn 类型中的逗号仍然用于分隔泛型参数
+    assert_eq!(parse_type_str("map<string,fn():int>").unwrap().to_string(), "map<string,fn():int>");
+}
+
+#[test]
+fn test_assign_lvalue() {
+    let valid =
+        "fn main() {\n    a = 1\n    a.b = 1\n    list[0] = 1\n    *p = 1\n    a += 1\n    *p -= 1\n    (a, b.c, list[0], *p) = t\n    ((a, b), c) = t\n}\n";
+    parse(valid);
+
+    let check = |source: String, lvalue: &str, found: &str| {
+        let (_, errors) = parse_with_errors(&source);
+        let start = source.find(lvalue).unwrap();
+        let messages: Vec<(usize, usize, &str)> = errors
+            .iter()
+            .map(|e| (e.span.start as usize, e.span.end as usize, e.message.as_str()))
+            .collect();
+        let expected = format!("cannot assign to {}", found);
+        assert_eq!(messages, vec![(start, start + lvalue.len(), expected.as_str())], "{}", source);
+    };
+
+    // 语句只能以 ident, ( 或者 * 开头, 其余的表达式只会出现在元组解构中
+    let cases = vec![
+        ("foo()", "a function call", true),
+        ("a.b()", "a function call", true),
+        ("a + b", "a binary expression", true),
+        ("x as int", "a type cast", true),
+        ("x is int", "a type test", true),
+        ("*p + 1", "a binary expression", true),
+        ("1", "a literal", false),
+        ("-a", "a unary expression", false),
+        ("!a", "a unary expression", false),
+        ("[1]", "a vec literal", false),
+    ];
+    for (lvalue, found, plain) in cases {
+        if plain {
+            check(format!("fn main() {{\n    {} = 1\n}}\n", lvalue), lvalue, found);
+            check(format!("fn main() {{\n    {} += 1\n}}\n", lvalue), lvalue, found);
+        }
+        check(format!("fn main() {{\n    (a, {}) = t\n}}\n", lvalue), lvalue, found);
+        check(format!("fn main() {{\n    ((a, {}), b) = t\n}}\n", lvalue), lvalue, found);
+    }
+}
+
+#[test]
+fn test_lambda_short() {
+    let source = "fn main() {\n    items.each(=> _ + 1)\n    btn.on_click(=> handle_click())\n}\n";
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef) = &stmts[0].node else { panic!("expect fndef") };
+    let fndef = fndef.read().unwrap();
+    let AstNode::Call(call) = &fndef.body[0].node else { panic!("expect call") };
+    let AstNode::LambdaShort(body) = &call.args[0].node else {
+        panic!("expect lambda short, got {:?}", call.args[0].node)
+    };
+    assert!(matches!(body.node, AstNode::Binary(ExprOp::Add, ..)));
+    assert_eq!(&source[call.args[0].span.range()], "=> _ + 1");
+    let AstNode::Call(call) = &fndef.body[1].node else { panic!("expect call") };
+    assert!(matches!(&call.args[0].node, AstNode::LambdaShort(body) if matches!(body.node, AstNode::Call(..))));
+    assert_eq!(body_to_source(&fndef.body), "items.each(=> _ + 1)\nbtn.on_click(=> handle_click())");
+    let formatted = format_source("fn main() {\n    run(=>handle_click())\n}\n", &FormatConfig::default()).unwrap();
+    assert_eq!(formatted, "fn main() {\n    run(=> handle_click())\n}\n");
+
+    // 参数与返回类型来自形参的 fn 类型
+    let prelude = "fn apply(int x, fn(int):int f):int {\n    return f(x)\n}\n\nfn run(fn():void cb) {\n    cb()\n}\n\nfn handle_click() {\n}\n\n";
+    let m = module_analyze(&format!(
+        "{}fn main() {{\n    var a = apply(1, => _ * 2)\n    run(=> handle_click())\n    var b = apply(2, => apply(_, => _ + 1))\n}}\n",
+        prelude
+    ));
+    assert!(m.analyzer_errors.is_empty(), "{:?}", m.analyzer_errors);
+
+    let messages = |body: &str| -> Vec<String> {
+        let m = module_analyze(&format!("{}fn main() {{\n{}\n}}\n", prelude, body));
+        m.analyzer_errors.iter().map(|e| e.message.clone()).collect()
+    };
+    assert_eq!(
+        messages("    var f = => 1"),
+        vec!["cannot infer the type of '=>' lambda without an expected fn type"]
+    );
+    assert_eq!(
+        messages("    run(=> _ + 1)"),
+        vec!["'=>' lambda uses '_' but expected fn type 'fn():void' has 0 params"]
+    );
+    assert!(!messages("    var a = apply(1, => 'a')").is_empty());
+}
+
+#[test]
+fn test_structurally_eq() {
+    let stmts = parse("fn main() {\n    a += 1\n    var (x, y) = (1, 2)\n}\n");
+    let spaced = parse("\n\nfn  main ( )  {\n  a  +=  1\n\n  var (x,y) = (1,2)\n}");
+    assert_ast_eq!(stmts, spaced);
+    assert!(structurally_eq(&stmts[0], &spaced[0]));
+
+    // 复合赋值展开后与直接编写的赋值结构相同
+    assert_ast_eq!(parse("fn main() {\n    a += 1\n}\n"), parse("fn main() {\n    a = a + 1\n}\n"));
+
+    // 比较会进入 fn/var decl 等 Arc<RwLock<..>> 节点
+    assert!(!body_structurally_eq(&parse("fn f(int a) {\n}\n"), &parse("fn f(int b) {\n}\n")));
+    assert!(!body_structurally_eq(
+        &parse("fn f():int {\n    return 1\n}\n"),
+        &parse("fn f():int {\n    return 2\n}\n")
+    ));
+    assert!(!body_structurally_eq(&parse("var a = 1"), &parse("var a = 1.0")));
+    assert!(!body_structurally_eq(&parse("var a = 1"), &parse("var a = 1\nvar b = 2")));
+
+    // typesys 推导得到的 expr 类型不参与比较
+    let mut inferred = parse("var a = x");
+    let AstNode::VarDef(_, right) = &mut inferred[0].node else {
+        panic!("expect var def")
+    };
+    right.type_ = Type::new(TypeKind::Int);
+    assert_ast_eq!(inferred, parse("var a = x"));
+
+    let result = std::panic::catch_unwind(|| assert_ast_eq!(parse("var a = 1"), parse("var a = 2")));
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert_eq!(*message, "ast not equal\n--- left\nvar a = 1\n--- right\nvar a = 2\n");
+}
+
+#[test]
+fn test_comment_only_source() {
+    let cases = vec![
+        ("", false, true),
+        ("\n  \n", false, true),
+        ("// comment\n// another comment\n", true, false),
+        ("/* block */", true, false),
+        ("/*\n * doc\n */\n", true, false),
+        ("//\n/* */\n", true, true),
+        ("// comment\nvar a = 1\n", false, false),
+        ("var a = 1", false, false),
+        // lexer 没有输出未闭合的注释
+        ("/* unterminated", false, true),
+    ];
+
+    for (source, comment_only, effectively_empty) in cases {
+        assert_eq!(is_comment_only(source), comment_only, "{:?}", source);
+
+        let result = parse_with_options(
+            source,
+            ParseOptions {
+                keep_tokens: true,
+                ..Default::default()
+            },
+        );
+        let tokens = result.tokens.unwrap();
+        assert_eq!(is_effectively_empty(&result.stmts, &tokens), effectively_empty, "{:?}", source);
+    }
+}
+
+#[test]
+fn test_token_cursor() {
+    let source = "fn main() {\n    var m = foo<map<string, [tup<int, bool>]>>(x < y, [1]) // call\n}\n";
+    let (tokens, _, _) = Lexer::new(source.to_string()).scan();
+    let offset = |pattern: &str| source.find(pattern).unwrap();
+
+    // 匹配 offset 处的括号, 返回匹配括号的 start
+    let matching = |offset: usize| {
+        let mut cursor = TokenCursor::new(&tokens);
+        cursor.token_at(offset)?;
+        cursor.matching_bracket().map(|t| t.span.start as usize)
+    };
+
+    let outer_open = offset("<map");
+    let outer_close = offset(">>(") + 1;
+    assert_eq!(matching(outer_open), Some(outer_close));
+    assert_eq!(matching(outer_close), Some(outer_open));
+
+    let inner_open = offset("<string");
+    let inner_close = offset(">>(");
+    assert_eq!(matching(inner_open), Some(inner_close));
+    assert_eq!(matching(inner_close), Some(inner_open));
+
+    assert_eq!(matching(offset("<int")), Some(offset(">]")));
+    assert_eq!(matching(offset("[tup")), Some(offset("]>")));
+    assert_eq!(matching(offset("(x")), Some(offset(") //")));
+    assert_eq!(matching(offset(") //")), Some(offset("(x")));
+    assert_eq!(matching(offset("{")), Some(offset("}")));
+    assert_eq!(matching(offset("}")), Some(offset("{")));
+
+    // 比较运算符
+    assert_eq!(matching(offset("< y")), None);
+    assert_eq!(matching(offset("main")), None);
+
+    // 位于注释内部
+    let mut cursor = TokenCursor::new(&tokens);
+    let comment = cursor.token_at(offset("call")).unwrap();
+    assert_eq!(comment.token_type, TokenType::LineComment);
+    // 隐式的 StmtEof 位于注释之前
+    assert_eq!(cursor.prev_non_trivia().unwrap().token_type, TokenType::StmtEof);
+    assert_eq!(cursor.prev_non_trivia().unwrap().span.start as usize, offset(") //"));
+    assert_eq!(cursor.next_non_trivia().unwrap().token_type, TokenType::StmtEof);
+    assert_eq!(cursor.next_non_trivia().unwrap().token_type, TokenType::RightCurly);
+    assert!(cursor.next_non_trivia().is_none());
+    assert_eq!(cursor.current().unwrap().token_type, TokenType::RightCurly);
+
+    let mut cursor = TokenCursor::new(&tokens);
+    cursor.token_at(offset("call"));
+    assert_eq!(cursor.next_non_trivia().unwrap().token_type, TokenType::RightCurly);
+
+    // 空白处不返回 token, cursor 停在之后的 token
+    let mut cursor = TokenCursor::new(&tokens);
+    assert!(cursor.token_at(offset("main") - 1).is_none());
+    assert_eq!(cursor.current().unwrap().literal, "main");
+    assert_eq!(cursor.prev_non_trivia().unwrap().token_type, TokenType::Fn);
+    assert!(cursor.prev_non_trivia().is_none());
+
+    let mut cursor = TokenCursor::new(&tokens);
+    assert!(cursor.token_at(source.len() + 10).is_none());
+}
+
+#[tokio::test]
+async fn test_check_json_schema() {
+    let root = std::env::temp_dir().join(format!("nls_check_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("lib")).unwrap();
+    std::fs::write(root.join("main.n"), "fn main() {\n    var unused = 1\n}\n").unwrap();
+    std::fs::write(root.join("lib/broken.n"), "fn broken() {\n    var a = )\n}\n").unwrap();
+
+    let args = |args: &[&str]| CheckOptions::parse(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+    let root_str = root.to_string_lossy().to_string();
+    let options = args(&[&root_str, "--format", "json"]).unwrap();
+    assert_eq!(options.format, CheckFormat::Json);
+    assert!(!options.deny_warnings);
+
+    let report = check(&options).await.unwrap();
+    assert_eq!(report.files.len(), 2);
+    assert_eq!(report.exit_code(), 1);
+
+    // 每行一个 json object, key 固定
+    let lines: Vec<serde_json::Value> = report.diagnostics.iter().map(diagnostic_json).collect();
+    for line in &lines {
+        let text = line.to_string();
+        assert!(!text.contains('\n'));
+        let mut keys: Vec<&str> = line.as_object().unwrap().keys().map(|k| k.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["code", "file", "message", "range", "related", "severity", "source"]);
+        assert!(line["related"].is_array());
+    }
+
+    let broken_path = root.join("lib/broken.n").canonicalize().unwrap().to_string_lossy().to_string();
+    let error = lines.iter().find(|line| line["severity"] == "error").unwrap();
+    assert_eq!(error["file"], broken_path);
+    assert_eq!(error["source"], "nature");
+    assert!(error["code"].is_null());
+    assert_eq!(error["range"]["start"]["line"], 2);
+    assert!(error["range"]["start"]["column"].as_u64().unwrap() >= 1);
+
+    // line 与 column 从 1 开始
+    let main_path = root.join("main.n").canonicalize().unwrap().to_string_lossy().to_string();
+    let warning = lines.iter().find(|line| line["severity"] == "warning").unwrap();
+    assert_eq!(warning["file"], main_path);
+    assert_eq!(
+        warning["range"],
+        serde_json::json!({ "start": { "line": 2, "column": 9 }, "end": { "line": 2, "column": 15 } })
+    );
+    assert!(warning["message"].as_str().unwrap().contains("unused"));
+
+    let warning_diagnostic = report.diagnostics.iter().find(|d| d.path == main_path).unwrap();
+    let text = diagnostic_human(warning_diagnostic, &report.sources[&main_path], RenderConfig::default());
+    assert!(text.starts_with("warning: unused variable"));
+    assert!(text.contains(&format!("--> {}:2:9\n", main_path)));
+
+    // 只有 warning 时退出码为 0, --deny-warnings 时 warning 作为 error 上报
+    let main_str = root.join("main.n").to_string_lossy().to_string();
+    let report = check(&args(&[&main_str]).unwrap()).await.unwrap();
+    assert_eq!(report.files, vec![main_path.clone()]);
+    assert_eq!((report.error_count(), report.warning_count(), report.exit_code()), (0, 1, 0));
+
+    let report = check(&args(&[&main_str, "--deny-warnings"]).unwrap()).await.unwrap();
+    assert_eq!((report.error_count(), report.warning_count(), report.exit_code()), (1, 0, 1));
+    assert_eq!(diagnostic_json(&report.diagnostics[0])["severity"], "error");
+
+    assert_eq!(args(&[]).unwrap().path, ".");
+    assert!(args(&["--format", "xml"]).is_err());
+    assert!(args(&["--format"]).is_err());
+    assert!(args(&["a", "b"]).is_err());
+    assert!(args(&["--unknown"]).is_err());
+    assert!(check(&args(&[&root.join("missing").to_string_lossy()]).unwrap()).await.is_err());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_snippet_render() {
+    let plain = RenderConfig::default();
+    let render = |source: &str, snippet: &Snippet| render_snippet("main.n", source, &LineIndex::new(source, PositionEncoding::Utf8), snippet, plain);
+    let snippet = |start: usize, end: usize| Snippet {
+        severity: DiagnosticSeverity::ERROR,
+        code: None,
+        message: "message".to_string(),
+        primary: Label::new(start, end, ""),
+        secondary: Vec::new(),
+        notes: Vec::new(),
+    };
+
+    let source = "fn main() {\n    var b = )\n}\n";
+    let offset = source.rfind(')').unwrap();
+    let error = AnalyzerError::new(Span::new(offset, offset + 1), "<expr> expected, found ')'");
+    let text = render_error("main.n", source, &LineIndex::new(source, PositionEncoding::Utf8), &error, plain);
+    assert_eq!(
+        text,
+        "error: <expr> expected, found ')'\n --> main.n:2:13\n  |\n2 |     var b = )\n  |             ^"
+    );
+
+    // 错误码, secondary 标注与 note, 不相邻的行之间使用 ... 分隔
+    let source = "fn main() {\n    var a = 1\n\n\n    a = \"s\"\n}\n";
+    let decl = source.find("var a").unwrap() + 4;
+    let assign = source.find("\"s\"").unwrap();
+    let mut s = snippet(assign, assign + 3);
+    s.severity = DiagnosticSeverity::WARNING;
+    s.code = Some("E0308".to_string());
+    s.primary.message = "expected int".to_string();
+    s.secondary.push(Label::new(decl, decl + 1, "declared here"));
+    s.notes.push("lib.n:1:1: defined here".to_string());
+    assert_eq!(
+        render(source, &s),
+        [
+            "warning[E0308]: message",
+            " --> main.n:5:9",
+            "  |",
+            "2 |     var a = 1",
+            "  |         - declared here",
+            "...",
+            "5 |     a = \"s\"",
+            "  |         ^^^ expected int",
+            "  = note: lib.n:1:1: defined here",
+        ]
+        .join("\n")
+    );
+
+    // 多行 span 在每一行分别标注, 与 display_with_source 相同保留 tab 使得标注与源码对齐
+    let source = "fn main() {\n\tfoo(1,\n\t\t2)\n}";
+    let start = source.find("foo").unwrap();
+    let end = source.find("2)").unwrap() + 2;
+    let mut s = snippet(start, end);
+    s.primary.message = "call".to_string();
+    assert_eq!(
+        render(source, &s),
+        [
+            "error: message",
+            " --> main.n:2:2",
+            "  |",
+            "2 | \tfoo(1,",
+            "  | \t^^^^^^",
+            "3 | \t\t2)",
+            "  | \t\t^^ call"
+        ]
+        .join("\n")
+    );
+
+    // 同一个错误在两种渲染方式中的源码与标注一致
+    let source = "fn main() {\n\tvar b = )\n}\n";
+    let offset = source.rfind(')').unwrap();
+    let error = AnalyzerError::new(Span::new(offset, offset + 1), "message");
+    let rendered = render_error("main.n", source, &LineIndex::new(source, PositionEncoding::Utf8), &error, plain);
+    let displayed = error.display_with_source(source);
+    assert_eq!(rendered.lines().skip(2).collect::<Vec<_>>(), displayed.lines().skip(2).collect::<Vec<_>>());
+
+    // 超过 4 行的 span 只显示首尾两行
+    let source = "fn main() {\n    var a = 1\n    var b = 2\n    var c = 3\n    var d = 4\n}\n";
+    let s = snippet(0, source.len() - 1);
+    assert_eq!(
+        render(source, &s),
+        [
+            "error: message",
+            " --> main.n:1:1",
+            "  |",
+            "1 | fn main() {",
+            "  | ^^^^^^^^^^^",
+            "...",
+            "6 | }",
+            "  | ^"
+        ]
+        .join("\n")
+    );
+
+    // 指向文件末尾的 span 标注在最后一行的行尾
+    let source = "fn main() {\n    var a = 1\n";
+    let s = snippet(source.len(), source.len());
+    assert_eq!(
+        render(source, &s),
+        ["error: message", " --> main.n:2:14", "  |", "2 |     var a = 1", "  |              ^"].join("\n")
+    );
+
+    let source = "var a = 1";
+    let s = snippet(source.len(), source.len() + 5);
+    assert_eq!(
+        render(source, &s),
+        ["error: message", " --> main.n:1:10", "  |", "1 | var a = 1", "  |          ^"].join("\n")
+    );
+
+    // 空文件只显示位置
+    assert_eq!(render("", &snippet(0, 0)), "error: message\n --> main.n:1:1");
+
+    // 行号宽度
+    let source = "\n".repeat(9) + "var a = 1\n";
+    let text = render(&source, &snippet(9, 12));
+    assert_eq!(text, ["error: message", "  --> main.n:10:1", "   |", "10 | var a = 1", "   | ^^^"].join("\n"));
+
+    let colored = render_snippet(
+        "main.n",
+        "var a",
+        &LineIndex::new("var a", PositionEncoding::Utf8),
+        &snippet(4, 5),
+        RenderConfig { color: true },
+    );
+    assert!(colored.starts_with("\x1b[1;31merror\x1b[0m"));
+    assert!(colored.contains("\x1b[1;31m^\x1b[0m"));
+}
+
+// wasm 接口的逻辑与 target 无关, 浏览器中的测试见 tests/wasm.rs
+#[test]
+fn test_wasm_bindings() {
+    let source = "fn add(int a, int b):int {\n    return a + b\n}\n\nfn main() {\n    var sum = add(1, 2)\n    println(sum)\n}\n";
+    let json = parse_to_json(source);
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value.as_array().unwrap().len(), 2);
+    assert_eq!(value[0]["kind"], "FnDef");
+    assert_eq!(json, ast_to_json(&parse(source)));
+
+    // println 来自内置的 fallback builtin
+    assert_eq!(diagnostics(source), "[]");
+
+    let value: serde_json::Value = serde_json::from_str(&diagnostics("fn main() {\n    var a = 1\n    var b = )\n}\n")).unwrap();
+    let items = value.as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["severity"], "error");
+    assert_eq!(items[0]["file"], "main.n");
+    assert_eq!(items[0]["range"]["start"], serde_json::json!({ "line": 3, "column": 13 }));
+    assert_eq!(items[1]["severity"], "warning");
+
+    let value: serde_json::Value = serde_json::from_str(&diagnostics("import \"lib.n\"\n")).unwrap();
+    assert_eq!(value.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_unified_diagnostic() {
+    // lexer, parser 与 validate 的错误使用同一个类型, 通过 phase 区分来源
+    let result = nls::parse("fn main() {\n    var s = 'abc\n}\n\nfn foo() {\n    var = 1\n    a catch e {\n    }\n}\n");
+    let errors = &result.errors;
+    assert_eq!(errors.first().map(|e| e.phase), Some(DiagnosticPhase::Lexer), "{:?}", errors);
+    assert!(errors.iter().any(|e| e.phase == DiagnosticPhase::Syntax));
+    assert!(errors.iter().all(|e| e.severity == Severity::Error && e.code.is_none()));
+
+    let error: AnalyzerError = SyntaxError(Span::new(4, 7), "unexpected ident".to_string()).into();
+    assert_eq!(error.phase, DiagnosticPhase::Syntax);
+    assert_eq!(error.span, Span::new(4, 7));
+    assert_eq!(error.to_string(), "error: unexpected ident");
+    assert_eq!(SyntaxError(Span::new(4, 7), "unexpected ident".to_string()).to_string(), "unexpected ident");
+
+    let source = "var a = 1\nvar a = 2\n";
+    let error = AnalyzerError::new(Span::new(14, 15), "duplicate declaration 'a'")
+        .with_severity(Severity::Warning)
+        .with_code("E0001")
+        .with_related(Span::new(4, 5), "first declared here");
+    assert_eq!(error.to_string(), "warning[E0001]: duplicate declaration 'a'");
+    let boxed: Box<dyn std::error::Error> = Box::new(error.clone());
+    assert_eq!(boxed.to_string(), "warning[E0001]: duplicate declaration 'a'");
+
+    let m = Module::new(String::new(), source.to_string(), "/project/main.n".to_string(), 0);
+    let diagnostic = to_diagnostic(&m, &error).unwrap();
+    assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+    assert_eq!(diagnostic.code, Some(NumberOrString::String("E0001".to_string())));
+    let related = diagnostic.related_information.unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].message, "first declared here");
+    assert_eq!(related[0].location.uri.path(), "/project/main.n");
+    assert_eq!(related[0].location.range, Range::new(Position::new(0, 4), Position::new(0, 5)));
+
+    let rendered = render_error(
+        "main.n",
+        source,
+        &LineIndex::new(source, PositionEncoding::Utf8),
+        &error,
+        RenderConfig::default(),
+    );
+    assert!(rendered.starts_with("warning[E0001]: duplicate declaration 'a'"), "{}", rendered);
+    assert!(rendered.contains("- first declared here"), "{}", rendered);
+}
+
+#[test]
+fn test_ast_metrics() {
+    let source = "fn add(int a, int b):int {\n    return a + b * (a - b)\n}\n\nfn classify(int n):string {\n    if n < 0 {\n        return \"negative\"\n    } else if n == 0 {\n        return \"zero\"\n    }\n    for int i = 0; i < n; i += 1 {\n        if i > 10 {\n            break\n        }\n    }\n    var f = fn(int x):int {\n        return x * 2\n    }\n    return match n {\n        1 -> \"one\"\n        _ -> \"many\"\n    }\n}\n";
+    let result = nls::parse(source);
+    assert!(result.is_error_free(), "{:?}", result.errors);
+    let m = metrics(&result.stmts);
+
+    // match 的分支被 parser 转换为 break 语句
+    assert_eq!(m.count("FnDef"), 3);
+    assert_eq!(m.count("Return"), 5);
+    assert_eq!(m.count("If"), 3);
+    assert_eq!(m.count("Break"), 3);
+    assert_eq!(m.count("Binary"), 9);
+    assert_eq!(m.count("Select"), 0);
+    assert_eq!(m.stmts, 17);
+    assert_eq!(m.max_expr_depth, 4); // a + b * (a - b)
+
+    // closure 的 body 只计入 closure 自身
+    let fns: Vec<(&str, usize, usize)> = m.fns.iter().map(|f| (f.name.as_str(), f.stmts, f.complexity)).collect();
+    assert_eq!(fns, vec![("add", 1, 1), ("classify", 13, 7), ("lambda0", 1, 1)]);
+    assert_eq!(&source[m.fns[2].start..m.fns[2].start + 2], "fn");
+    assert_eq!(m.covered, source.len() - 3);
+
+    // 出错的 stmt 被丢弃, 之后的 fn 依旧被统计
+    let source = "fn ok() {\n    var a = 1\n}\n\nvar = 2\n\nfn after():int {\n    for true {\n        return 2\n    }\n}\n";
+    let result = nls::parse(source);
+    assert!(!result.is_error_free());
+    let m = metrics(&result.stmts);
+    let fns: Vec<(&str, usize, usize)> = m.fns.iter().map(|f| (f.name.as_str(), f.stmts, f.complexity)).collect();
+    assert_eq!(fns, vec![("ok", 1, 1), ("after", 2, 2)]);
+    let after = source.find("fn after").unwrap();
+    assert_eq!(m.fns[1].start, after);
+    let ok_end = source.find("}\n").unwrap() + 1;
+    assert_eq!(m.covered, ok_end + source.trim_end().len() - after);
+
+    assert_eq!(metrics(&[]), Default::default());
+}
+
+#[test]
+fn test_intern_identifiers() {
+    let foo = intern("foo");
+    assert_eq!(intern(&String::from("foo")).as_u32(), foo.as_u32());
+    assert_ne!(intern("bar"), foo);
+    assert_eq!(resolve(foo), "foo");
+    assert_eq!(foo, "foo");
+    assert_eq!(Atom::from("foo"), foo);
+    assert_eq!(resolve(Atom::default()), "");
+
+    // 与 String 一致的展示与排序, 排序与驻留的顺序无关
+    assert_eq!(format!("{} {:?}", foo, foo), "foo \"foo\"");
+    assert_eq!(serde_json::to_string(&foo).unwrap(), "\"foo\"");
+    let later = intern("intern_test_b");
+    let earlier = intern("intern_test_a");
+    assert!(later.as_u32() < earlier.as_u32() && earlier < later);
+
+    // lexer 驻留标识符, 同一个标识符的声明与引用是同一个 Atom, 字符串字面量不驻留
+    let source = "fn main() {\n    var count = 1\n    count = count + 1\n    var s = 'count'\n}\n";
+    let (tokens, _, _) = Lexer::new(source.to_string()).scan();
+    let counts: Vec<&Token> = tokens.iter().filter(|t| t.literal == "count").collect();
+    assert_eq!(counts.len(), 4);
+    assert!(counts[..3]
+        .iter()
+        .all(|t| matches!(t.literal, TokenText::Atom(atom) if atom == intern("count"))));
+    assert!(matches!(&counts[3].literal, TokenText::Text(text) if text == "count"));
+
+    let stmts = parse(source);
+    let AstNode::FnDef(fndef) = &stmts[0].node else { panic!("expect fn def") };
+    let fndef = fndef.read().unwrap();
+    let AstNode::VarDef(var_decl, _) = &fndef.body[0].node else {
+        panic!("expect var def")
+    };
+    let declared = var_decl.read().unwrap().ident;
+    let AstNode::Assign(left, right) = &fndef.body[1].node else {
+        panic!("expect assign")
+    };
+    let AstNode::Ident(assigned, _) = &left.node else { panic!("expect ident") };
+    let AstNode::Binary(_, operand, _) = &right.node else {
+        panic!("expect binary")
+    };
+    let AstNode::Ident(read, _) = &operand.node else { panic!("expect ident") };
+    assert_eq!(declared, "count");
+    assert!(*assigned == declared && *read == declared);
+}
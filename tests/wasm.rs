@@ -0,0 +1,35 @@
+// wasm-pack test --headless --firefox --no-default-features --features wasm
+#![cfg(target_arch = "wasm32")]
+
+use nls::wasm::{diagnostics, parse_to_json};
+use serde_json::Value;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+const SAMPLE: &str = "fn add(int a, int b):int {\n    return a + b\n}\n\nfn main() {\n    var sum = add(1, 2)\n    println(sum)\n}\n";
+
+#[wasm_bindgen_test]
+fn parse_json_round_trip() {
+    let json = parse_to_json(SAMPLE);
+    let value: Value = serde_json::from_str(&json).unwrap();
+    let stmts = value.as_array().unwrap();
+    assert_eq!(stmts.len(), 2);
+    assert_eq!(stmts[0]["kind"], "FnDef");
+
+    let reparsed: Value = serde_json::from_str(&value.to_string()).unwrap();
+    assert_eq!(reparsed, value);
+    assert_eq!(parse_to_json(SAMPLE), json);
+}
+
+#[wasm_bindgen_test]
+fn diagnostics_json() {
+    let value: Value = serde_json::from_str(&diagnostics(SAMPLE)).unwrap();
+    assert_eq!(value, Value::Array(Vec::new()));
+
+    let value: Value = serde_json::from_str(&diagnostics("fn main() {\n    var a = )\n}\n")).unwrap();
+    let items = value.as_array().unwrap();
+    assert!(!items.is_empty());
+    assert_eq!(items[0]["severity"], "error");
+    assert_eq!(items[0]["range"]["start"]["line"], 2);
+}